@@ -4,6 +4,7 @@
 //! discriminant（判別子）とactive variantのフィールドを取得します。
 
 use anyhow::Result;
+use std::fmt;
 
 /// Generatorのフィールド情報
 #[derive(Debug, Clone)]
@@ -82,7 +83,7 @@ impl<'a> GeneratorAnalyzer<'a> {
 
         while let Some(header) = iter.next()? {
             let unit = self.dwarf.unit(header)?;
-            if let Some(offset) = kokia_dwarf::FunctionFinder::find_at_pc(&unit, pc)? {
+            if let Some(offset) = kokia_dwarf::FunctionFinder::find_at_pc(self.dwarf, &unit, pc)? {
                 return Ok(Some(offset));
             }
         }
@@ -91,6 +92,58 @@ impl<'a> GeneratorAnalyzer<'a> {
     }
 }
 
+/// コンパイラが予約している discriminant（中断点は 3 から順に振られる）
+pub const UNRESUMED_DISCRIMINANT: u64 = 0;
+pub const RETURNED_DISCRIMINANT: u64 = 1;
+pub const PANICKED_DISCRIMINANT: u64 = 2;
+pub const FIRST_SUSPEND_DISCRIMINANT: u64 = 3;
+
+/// discriminant が表す状態機械の状態
+///
+/// DWARF の variant 名（`Unresumed`, `Suspend0` など）と同じ名前で表示します。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratorState {
+    /// まだ一度も poll されていない
+    Unresumed,
+    /// Ready を返して完了した
+    Returned,
+    /// poll の途中でパニックした（もう一度 poll するとパニックする）
+    Panicked,
+    /// N 番目の中断点（.await）で止まっている
+    Suspend(u64),
+}
+
+impl GeneratorState {
+    pub fn from_discriminant(discriminant: u64) -> Self {
+        match discriminant {
+            UNRESUMED_DISCRIMINANT => GeneratorState::Unresumed,
+            RETURNED_DISCRIMINANT => GeneratorState::Returned,
+            PANICKED_DISCRIMINANT => GeneratorState::Panicked,
+            n => GeneratorState::Suspend(n - FIRST_SUSPEND_DISCRIMINANT),
+        }
+    }
+
+    pub fn discriminant(self) -> u64 {
+        match self {
+            GeneratorState::Unresumed => UNRESUMED_DISCRIMINANT,
+            GeneratorState::Returned => RETURNED_DISCRIMINANT,
+            GeneratorState::Panicked => PANICKED_DISCRIMINANT,
+            GeneratorState::Suspend(n) => n + FIRST_SUSPEND_DISCRIMINANT,
+        }
+    }
+}
+
+impl fmt::Display for GeneratorState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeneratorState::Unresumed => write!(f, "Unresumed"),
+            GeneratorState::Returned => write!(f, "Returned"),
+            GeneratorState::Panicked => write!(f, "Panicked"),
+            GeneratorState::Suspend(n) => write!(f, "Suspend{}", n),
+        }
+    }
+}
+
 /// フィールド名を正規化（実装依存の接尾辞を除去）
 ///
 /// `__await_3`, `<local>@5` などの実装依存名を人間可読な形式に変換します。
@@ -120,4 +173,14 @@ mod tests {
         assert_eq!(normalize_field_name("local@5"), "local");
         assert_eq!(normalize_field_name(".0"), ".0");
     }
+
+    #[test]
+    fn test_generator_state_from_discriminant() {
+        assert_eq!(GeneratorState::from_discriminant(0), GeneratorState::Unresumed);
+        assert_eq!(GeneratorState::from_discriminant(2), GeneratorState::Panicked);
+        assert_eq!(GeneratorState::from_discriminant(5), GeneratorState::Suspend(2));
+        assert_eq!(GeneratorState::from_discriminant(5).to_string(), "Suspend2");
+        assert_eq!(GeneratorState::Suspend(2).discriminant(), 5);
+        assert_eq!(GeneratorState::Returned.discriminant(), RETURNED_DISCRIMINANT);
+    }
 }
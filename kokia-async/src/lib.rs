@@ -10,18 +10,29 @@ pub mod logical_stack;
 pub mod task;
 pub mod tracker;
 pub mod detector;
+pub mod stats;
+pub mod lifetimes;
+pub mod retention;
+pub mod tree;
 
 pub use genfuture::GenFutureDetector;
-pub use generator::{GeneratorAnalyzer, GeneratorField, DiscriminantInfo, normalize_field_name};
+pub use generator::{
+    GeneratorAnalyzer, GeneratorField, GeneratorState, DiscriminantInfo, normalize_field_name,
+    UNRESUMED_DISCRIMINANT, RETURNED_DISCRIMINANT, PANICKED_DISCRIMINANT, FIRST_SUSPEND_DISCRIMINANT,
+};
 pub use logical_stack::{LogicalStack, LogicalFrame};
 pub use task::{
-    Tid, TaskId, TaskInfo, TaskTracker,
+    Tid, TaskId, TaskInfo, TaskRef, TaskTracker,
     EdgeId, Edge, EdgeTracker,
-    CallsiteId, Callsite, CallsiteTracker,
+    CallsiteId, Callsite, CallsiteTracker, AwaitSite,
     PollScope, ThreadPollScopeManager,
 };
-pub use tracker::AsyncTracker;
+pub use tracker::{AsyncTracker, ObservedFrame};
 pub use detector::AsyncDetector;
+pub use stats::{AsyncStats, RunningTime, DEFAULT_STALL_THRESHOLD};
+pub use lifetimes::{LifetimeBucket, LifetimeStats, ThroughputWindow, TypeLifetimes};
+pub use retention::{RetentionPolicy, EvictionStats};
+pub use tree::{EdgeFilter, TreeLine};
 
 /// async機能の結果型
 pub type Result<T> = anyhow::Result<T>;
@@ -0,0 +1,223 @@
+//! タスクの寿命の分布と完了のスループット（async lifetimes）
+//!
+//! 寿命は最初に観測してから完了（または破棄）されるまでの時間です。未完了のタスクは
+//! 現在までの経過時間で数えるので、実際の寿命はそれ以上になります。保持ポリシーで捨てた
+//! タスクは含まれません。
+
+use crate::TaskInfo;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// 寿命の区切り（この値未満のバケットに入る。最後のバケットは上限なし）
+const BUCKET_BOUNDS: [Duration; 6] = [
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+];
+
+/// タスクの終わり方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Completed,
+    /// Ready にならずにアドレスが別の Future に再利用された
+    Dropped,
+    Pending,
+}
+
+/// 寿命のバケット1つ
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LifetimeBucket {
+    /// 寿命の上限（None は上限なし）
+    pub upper: Option<Duration>,
+    pub completed: usize,
+    pub dropped: usize,
+    pub pending: usize,
+}
+
+impl LifetimeBucket {
+    pub fn total(&self) -> usize {
+        self.completed + self.dropped + self.pending
+    }
+}
+
+/// Future の型（関数）ごとの集計
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeLifetimes {
+    pub type_name: String,
+    pub completed: usize,
+    pub dropped: usize,
+    pub pending: usize,
+    /// 完了したタスクの寿命の平均
+    pub mean_completed: Option<Duration>,
+    /// 最も長い寿命（未完了のタスクの経過時間を含む）
+    pub max: Duration,
+}
+
+/// 一定時間ごとに始まった・終わったタスクの数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThroughputWindow {
+    /// 最初のタスクを観測してからの区間の始まり
+    pub start: Duration,
+    pub spawned: usize,
+    pub completed: usize,
+}
+
+/// タスクの寿命の統計
+#[derive(Debug, Clone, Default)]
+pub struct LifetimeStats {
+    pub buckets: Vec<LifetimeBucket>,
+    /// タスク数の多い順
+    pub by_type: Vec<TypeLifetimes>,
+    pub throughput: Vec<ThroughputWindow>,
+    /// 最初のタスクを観測してから `now` までの時間
+    pub span: Duration,
+}
+
+impl LifetimeStats {
+    /// タスク一覧から統計を計算する
+    ///
+    /// # Arguments
+    /// * `tasks` - 対象タスク
+    /// * `now` - 基準時刻
+    /// * `windows` - スループットを数える区間の数（観測期間を等分する）
+    pub fn collect<'a>(tasks: impl IntoIterator<Item = &'a TaskInfo>, now: Instant, windows: usize) -> Self {
+        let tasks: Vec<&TaskInfo> = tasks.into_iter().collect();
+        let Some(origin) = tasks.iter().map(|task| task.first_seen).min() else {
+            return Self::default();
+        };
+
+        let mut buckets: Vec<LifetimeBucket> = BUCKET_BOUNDS.iter()
+            .map(|&bound| Some(bound))
+            .chain([None])
+            .map(|upper| LifetimeBucket { upper, ..Default::default() })
+            .collect();
+        let mut by_type: HashMap<&str, (TypeLifetimes, Duration)> = HashMap::new();
+
+        for task in &tasks {
+            let (outcome, end) = match (task.completed, task.superseded) {
+                (true, _) => (Outcome::Completed, task.finished_at.unwrap_or(task.last_seen)),
+                (false, true) => (Outcome::Dropped, task.finished_at.unwrap_or(task.last_seen)),
+                _ => (Outcome::Pending, now),
+            };
+            let lifetime = end.saturating_duration_since(task.first_seen);
+            let bucket = BUCKET_BOUNDS.iter().position(|&bound| lifetime < bound).unwrap_or(BUCKET_BOUNDS.len());
+            count(&mut buckets[bucket], outcome);
+
+            let type_name = task.type_name.as_deref().unwrap_or("<unknown>");
+            let (entry, completed_sum) = by_type.entry(type_name).or_insert_with(|| {
+                (
+                    TypeLifetimes {
+                        type_name: type_name.to_string(),
+                        completed: 0,
+                        dropped: 0,
+                        pending: 0,
+                        mean_completed: None,
+                        max: Duration::ZERO,
+                    },
+                    Duration::ZERO,
+                )
+            });
+            match outcome {
+                Outcome::Completed => {
+                    entry.completed += 1;
+                    *completed_sum += lifetime;
+                }
+                Outcome::Dropped => entry.dropped += 1,
+                Outcome::Pending => entry.pending += 1,
+            }
+            entry.max = entry.max.max(lifetime);
+        }
+
+        let mut by_type: Vec<TypeLifetimes> = by_type.into_values()
+            .map(|(mut entry, completed_sum)| {
+                entry.mean_completed = (entry.completed > 0).then(|| completed_sum / entry.completed as u32);
+                entry
+            })
+            .collect();
+        by_type.sort_by(|a, b| {
+            let total = |t: &TypeLifetimes| t.completed + t.dropped + t.pending;
+            total(b).cmp(&total(a)).then_with(|| a.type_name.cmp(&b.type_name))
+        });
+
+        let span = now.saturating_duration_since(origin);
+        Self { buckets, by_type, throughput: throughput(&tasks, origin, span, windows), span }
+    }
+}
+
+fn count(bucket: &mut LifetimeBucket, outcome: Outcome) {
+    match outcome {
+        Outcome::Completed => bucket.completed += 1,
+        Outcome::Dropped => bucket.dropped += 1,
+        Outcome::Pending => bucket.pending += 1,
+    }
+}
+
+/// 観測期間を `windows` 等分し、区間ごとに観測し始めたタスクと完了したタスクを数える
+fn throughput(tasks: &[&TaskInfo], origin: Instant, span: Duration, windows: usize) -> Vec<ThroughputWindow> {
+    if windows == 0 {
+        return Vec::new();
+    }
+    // 期間が0でも1つの区間に入るように、幅は最低 1ms にする
+    let width = (span / windows as u32).max(Duration::from_millis(1));
+    let mut result: Vec<ThroughputWindow> = (0..windows)
+        .map(|i| ThroughputWindow { start: width * i as u32, spawned: 0, completed: 0 })
+        .collect();
+    let index = |at: Instant| {
+        let offset = at.saturating_duration_since(origin);
+        ((offset.as_nanos() / width.as_nanos()) as usize).min(windows - 1)
+    };
+    for task in tasks {
+        result[index(task.first_seen)].spawned += 1;
+        if task.completed {
+            result[index(task.finished_at.unwrap_or(task.last_seen))].completed += 1;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: u64, type_name: &str, start: Instant, end: Option<Instant>) -> TaskInfo {
+        let mut task = TaskInfo::new(id);
+        task.type_name = Some(type_name.to_string());
+        task.first_seen = start;
+        task.last_seen = start;
+        if let Some(end) = end {
+            task.completed = true;
+            task.finished_at = Some(end);
+        }
+        task
+    }
+
+    #[test]
+    fn test_lifetime_stats() {
+        let now = Instant::now();
+        let at = |ms: u64| now - Duration::from_secs(4) + Duration::from_millis(ms);
+        let quick = task(1, "app::get", at(0), Some(at(5)));
+        let slow = task(2, "app::get", at(1000), Some(at(3500)));
+        let mut dropped = task(3, "app::put", at(2000), None);
+        dropped.superseded = true;
+        dropped.finished_at = Some(at(2050));
+        let pending = task(4, "app::serve", at(0), None);
+
+        let stats = LifetimeStats::collect([&quick, &slow, &dropped, &pending], now, 4);
+        assert_eq!(stats.span, Duration::from_secs(4));
+        // 5ms / 50ms / 2.5s / 4s
+        let totals: Vec<(usize, usize, usize)> = stats.buckets.iter().map(|b| (b.completed, b.dropped, b.pending)).collect();
+        assert_eq!(totals, vec![(0, 0, 0), (1, 0, 0), (0, 1, 0), (0, 0, 0), (1, 0, 1), (0, 0, 0), (0, 0, 0)]);
+        assert_eq!(stats.buckets.last().unwrap().upper, None);
+
+        let get = &stats.by_type[0];
+        assert_eq!((get.type_name.as_str(), get.completed, get.pending), ("app::get", 2, 0));
+        assert_eq!(get.mean_completed, Some(Duration::from_millis(1252) + Duration::from_micros(500)));
+        assert_eq!(get.max, Duration::from_millis(2500));
+
+        let windows: Vec<(usize, usize)> = stats.throughput.iter().map(|w| (w.spawned, w.completed)).collect();
+        assert_eq!(windows, vec![(2, 1), (1, 0), (1, 0), (0, 1)]);
+    }
+}
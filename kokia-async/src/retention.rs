@@ -0,0 +1,44 @@
+//! 終了済みタスクの保持ポリシー
+//!
+//! 長時間動くサービスでは完了したタスクが際限なく溜まるため、件数と完了後の経過時間で
+//! 古いものから捨てます。捨てたタスクは [`EvictionStats`] に件数だけ残します。
+
+use std::time::Duration;
+
+/// 完了・破棄されたタスクをいつまで保持するか
+///
+/// どちらも `None` なら無制限に保持します。未完了のタスクは対象外です。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// 追跡するタスク数の上限（超えた分は終了の古い順に捨てる）
+    pub max_tasks: Option<usize>,
+    /// 終了してからこの時間が経ったタスクを捨てる
+    pub max_age: Option<Duration>,
+}
+
+impl RetentionPolicy {
+    /// 無制限に保持するポリシー
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// 何らかの制限が設定されているか
+    pub fn is_limited(&self) -> bool {
+        self.max_tasks.is_some() || self.max_age.is_some()
+    }
+}
+
+/// 保持ポリシーで捨てたものの累計
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvictionStats {
+    /// 捨てたタスクの数
+    pub tasks: u64,
+    /// そのうち完了していたタスクの数
+    pub completed: u64,
+    /// そのうちアドレスの再利用で破棄されていたタスクの数
+    pub superseded: u64,
+    /// 一緒に捨てたエッジの数
+    pub edges: u64,
+    /// コンパクションを実行した回数
+    pub compactions: u64,
+}
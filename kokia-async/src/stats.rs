@@ -0,0 +1,178 @@
+//! タスク統計と停滞（stall）検出
+
+use crate::TaskInfo;
+use std::time::{Duration, Instant};
+
+/// 停滞とみなすまでのデフォルトの未観測時間
+pub const DEFAULT_STALL_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// タスク統計
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AsyncStats {
+    /// 観測されたタスクの総数
+    pub total: usize,
+    /// 未完了のタスク数
+    pub live: usize,
+    /// 完了したタスク数
+    pub completed: usize,
+    /// 未完了のうち、しきい値以上 poll されていないタスク数
+    pub stalled: usize,
+    /// 未完了のルートタスク数
+    pub roots: usize,
+    /// 保持ポリシーで捨てたタスク数（total には含まない）
+    pub evicted: u64,
+}
+
+impl AsyncStats {
+    /// タスク一覧から統計を計算する
+    ///
+    /// # Arguments
+    /// * `tasks` - 対象タスク
+    /// * `running` - デバッグ対象が実行していた時間の記録
+    /// * `now` - 基準時刻
+    /// * `stall_threshold` - 実行中にこの時間以上 poll されていない未完了タスクを停滞とみなす
+    pub fn collect<'a>(
+        tasks: impl IntoIterator<Item = &'a TaskInfo>,
+        running: &RunningTime,
+        now: Instant,
+        stall_threshold: Duration,
+    ) -> Self {
+        let mut stats = Self::default();
+        for task in tasks {
+            stats.total += 1;
+            if task.completed {
+                stats.completed += 1;
+                continue;
+            }
+            if task.superseded {
+                continue;
+            }
+            stats.live += 1;
+            if task.is_root {
+                stats.roots += 1;
+            }
+            if is_stalled(task, running, now, stall_threshold) {
+                stats.stalled += 1;
+            }
+        }
+        stats
+    }
+}
+
+/// タスクが停滞しているか（未完了かつ、デバッグ対象の実行中にしきい値以上 poll されていない）
+pub fn is_stalled(task: &TaskInfo, running: &RunningTime, now: Instant, stall_threshold: Duration) -> bool {
+    task.is_live() && idle_time(task, running, now) >= stall_threshold
+}
+
+/// 最後に poll されてから `now` までに、デバッグ対象が実行していた時間
+pub fn idle_time(task: &TaskInfo, running: &RunningTime, now: Instant) -> Duration {
+    running.since(task.last_seen, now)
+}
+
+/// デバッグ対象が実行していた時間の記録
+///
+/// プロンプトで止まっている間はどのタスクも poll されないので、停滞の判定には実行していた
+/// 時間だけを数えます。再開と停止の時刻を順に記録しておき、ある時刻から後の実行時間を求めます。
+/// 何も記録していなければ、ずっと実行していたとみなします。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunningTime {
+    /// 再開した時刻、停止した時刻（実行中なら None）、それより前の実行時間の合計
+    runs: Vec<(Instant, Option<Instant>, Duration)>,
+}
+
+impl RunningTime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 再開したことを記録する（実行中なら何もしない）
+    pub fn resume(&mut self, at: Instant) {
+        if self.is_running() {
+            return;
+        }
+        let before = self.runs.last()
+            .map(|&(start, end, before)| before + end.map_or(Duration::ZERO, |end| end.saturating_duration_since(start)))
+            .unwrap_or_default();
+        self.runs.push((at, None, before));
+    }
+
+    /// 停止したことを記録する
+    pub fn stop(&mut self, at: Instant) {
+        if let Some((_, end @ None, _)) = self.runs.last_mut() {
+            *end = Some(at);
+        }
+    }
+
+    /// 実行中か
+    pub fn is_running(&self) -> bool {
+        matches!(self.runs.last(), Some((_, None, _)))
+    }
+
+    /// `since` から `now` までにデバッグ対象が実行していた時間
+    pub fn since(&self, since: Instant, now: Instant) -> Duration {
+        if self.runs.is_empty() {
+            return now.saturating_duration_since(since);
+        }
+        self.total_at(now).saturating_sub(self.total_at(since))
+    }
+
+    /// 最初に再開してから `at` までの実行時間の合計
+    fn total_at(&self, at: Instant) -> Duration {
+        let count = self.runs.partition_point(|(start, _, _)| *start <= at);
+        let Some(&(start, end, before)) = count.checked_sub(1).map(|last| &self.runs[last]) else {
+            return Duration::ZERO;
+        };
+        let until = end.map_or(at, |end| end.min(at));
+        before + until.saturating_duration_since(start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_stats() {
+        let now = Instant::now();
+        let old = now - Duration::from_secs(10);
+
+        let mut root = TaskInfo::new(1);
+        root.is_root = true;
+        root.last_seen = old;
+        let fresh = TaskInfo::new(2);
+        let mut done = TaskInfo::new(3);
+        done.completed = true;
+        done.last_seen = old;
+
+        let stats = AsyncStats::collect([&root, &fresh, &done], &RunningTime::new(), now, DEFAULT_STALL_THRESHOLD);
+        assert_eq!(
+            stats,
+            AsyncStats { total: 3, live: 2, completed: 1, stalled: 1, roots: 1, evicted: 0 }
+        );
+    }
+
+    #[test]
+    fn test_stall_counts_only_running_time() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut task = TaskInfo::new(1);
+        task.last_seen = at(0);
+
+        // 2 秒実行して 8 秒プロンプトで止まり、また実行する
+        let mut running = RunningTime::new();
+        running.resume(at(0));
+        running.stop(at(2));
+        assert!(!is_stalled(&task, &running, at(300), DEFAULT_STALL_THRESHOLD));
+        running.resume(at(10));
+        assert!(running.is_running());
+        assert_eq!(idle_time(&task, &running, at(12)), Duration::from_secs(4));
+        assert!(!is_stalled(&task, &running, at(12), DEFAULT_STALL_THRESHOLD));
+        assert!(is_stalled(&task, &running, at(13), DEFAULT_STALL_THRESHOLD));
+
+        // 止まっている間に観測したタスクは、次に再開してからの時間だけを数える
+        running.stop(at(13));
+        task.last_seen = at(11);
+        assert_eq!(running.since(at(11), at(100)), Duration::from_secs(2));
+        assert_eq!(running.since(at(20), at(100)), Duration::ZERO);
+    }
+}
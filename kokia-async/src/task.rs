@@ -2,15 +2,43 @@
 
 use std::collections::HashMap;
 use std::time::Instant;
-use crate::LogicalStack;
+use crate::{GeneratorState, LogicalStack, RetentionPolicy, UNRESUMED_DISCRIMINANT};
 
 /// スレッドID
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Tid(pub i32);
 
-/// タスクID（Futureのselfポインタ）
+/// タスクID
+///
+/// 最初の世代は Future の self ポインタそのもの。解放された Future のアドレスが別の Future に
+/// 再利用された場合は、上位16ビットに世代番号を入れて別の論理タスクとして扱う
+/// （ユーザー空間のアドレスは47ビットに収まるため衝突しない）。
 pub type TaskId = u64;
 
+/// 世代番号を入れるビット位置
+const GENERATION_SHIFT: u32 = 48;
+
+/// アドレスと世代番号から論理タスクIDを作る
+pub fn logical_task_id(address: u64, generation: u32) -> TaskId {
+    address | ((generation as u64 & 0xffff) << GENERATION_SHIFT)
+}
+
+/// コマンドでのタスクの指定（`#4` のような表示用番号か、タスクID）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TaskRef {
+    Handle(u32),
+    Id(TaskId),
+}
+
+impl std::fmt::Display for TaskRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskRef::Handle(handle) => write!(f, "#{}", handle),
+            TaskRef::Id(id) => write!(f, "0x{:x}", id),
+        }
+    }
+}
+
 /// EdgeID (parent, child, callsite のハッシュ)
 pub type EdgeId = u128;
 
@@ -36,29 +64,64 @@ macro_rules! compute_hash {
 #[derive(Debug, Clone)]
 pub struct TaskInfo {
     pub id: TaskId,
+    /// 表示用の短い番号（登録順に 1 から振る。0 は未登録）
+    pub handle: u32,
+    /// Future の self ポインタ（メモリを読むときはこちらを使う）
+    pub address: u64,
+    /// 同じアドレスを使った何番目の Future か（0 始まり）
+    pub generation: u32,
+    /// 最初に観測した entry の命令ポインタ
+    pub first_rip: Option<u64>,
     pub type_name: Option<String>,
     pub first_seen: Instant,
     pub last_seen: Instant,
     pub current_discriminant: Option<u64>,
     pub last_rip: Option<u64>,
     pub is_root: bool,
+    /// 最後の entry で親とみなしたタスク（root として poll されたら None）
+    pub parent: Option<TaskId>,
     pub completed: bool,
+    /// 計装を始める前から存在していた（アタッチ時のスタックから見つけた）タスクか
+    pub pre_existing: bool,
+    /// アドレスが別の Future に再利用された（このタスクは破棄済み）
+    pub superseded: bool,
+    /// 完了、または破棄された時刻
+    pub finished_at: Option<Instant>,
+    /// 最後に poll したスレッド
+    pub last_tid: Option<Tid>,
+    /// 別のスレッドで poll された回数（work-stealing による移動）
+    pub migrations: u32,
     pub logical_stack: LogicalStack,
 }
 
 impl TaskInfo {
     /// 新しいタスク情報を作成する
     pub fn new(id: TaskId) -> Self {
+        Self::with_generation(id, 0)
+    }
+
+    /// アドレスの `generation` 世代目のタスク情報を作成する
+    pub fn with_generation(address: u64, generation: u32) -> Self {
         let now = Instant::now();
         Self {
-            id,
+            id: logical_task_id(address, generation),
+            handle: 0,
+            address,
+            generation,
+            first_rip: None,
             type_name: None,
             first_seen: now,
             last_seen: now,
             current_discriminant: None,
             last_rip: None,
             is_root: false,
+            parent: None,
             completed: false,
+            pre_existing: false,
+            superseded: false,
+            finished_at: None,
+            last_tid: None,
+            migrations: 0,
             logical_stack: LogicalStack::new(),
         }
     }
@@ -67,6 +130,52 @@ impl TaskInfo {
     pub fn touch(&mut self) {
         self.last_seen = Instant::now();
     }
+
+    /// `#4 (0x7ffd2a10)` のような表示名
+    pub fn label(&self) -> String {
+        format!("#{} (0x{:x})", self.handle, self.id)
+    }
+
+    /// 完了としてマークする
+    pub fn mark_completed(&mut self) {
+        self.completed = true;
+        self.finished_at.get_or_insert_with(Instant::now);
+    }
+
+    /// 完了しておらず、破棄もされていないか
+    pub fn is_live(&self) -> bool {
+        !self.completed && !self.superseded
+    }
+
+    /// 最後に読んだ discriminant が表す状態
+    pub fn state(&self) -> Option<GeneratorState> {
+        self.current_discriminant.map(GeneratorState::from_discriminant)
+    }
+
+    /// poll の途中でパニックした状態（Panicked）で残っているか
+    pub fn is_panicked(&self) -> bool {
+        self.state() == Some(GeneratorState::Panicked)
+    }
+
+    /// 同じアドレスで新たに poll された Future が、このタスクとは別物と疑われるか
+    ///
+    /// - 完了済みのタスクが再び poll された（Ready 後の poll は起こらない）
+    /// - entry の命令ポインタや関数名が違う（別の async 関数の Future）
+    /// - discriminant が Unresumed(0) に戻った（一度再開した Future は 0 に戻らない）
+    pub fn looks_reused(&self, rip: u64, function_name: Option<&str>, discriminant: Option<u64>) -> bool {
+        if self.completed {
+            return true;
+        }
+        if self.first_rip.is_some_and(|first| first != rip) {
+            return true;
+        }
+        if let (Some(known), Some(name)) = (self.type_name.as_deref(), function_name) {
+            if known != name {
+                return true;
+            }
+        }
+        discriminant == Some(UNRESUMED_DISCRIMINANT) && self.current_discriminant.is_some_and(|d| d != UNRESUMED_DISCRIMINANT)
+    }
 }
 
 /// 呼び出しサイト情報
@@ -99,6 +208,17 @@ impl Callsite {
     }
 }
 
+/// 親が子を await している位置（親フレームへの戻りアドレスから求めたもの）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AwaitSite {
+    /// ソースファイル名
+    pub file: Option<String>,
+    /// 行番号（`.await` の行）
+    pub line: Option<u32>,
+    /// その行に対応する親の停止点インデックス（状態機械の discriminant）
+    pub suspend_idx: Option<u32>,
+}
+
 /// エッジ情報（親タスクが子タスクをawaitする関係）
 #[derive(Debug, Clone)]
 pub struct Edge {
@@ -144,6 +264,12 @@ impl Edge {
 /// タスクトラッカー
 pub struct TaskTracker {
     tasks: HashMap<TaskId, TaskInfo>,
+    /// アドレス → そのアドレスを使っている最新世代のタスク
+    current: HashMap<u64, TaskId>,
+    /// 表示用の番号 → タスク
+    handles: HashMap<u32, TaskId>,
+    /// 次に振る表示用の番号（clear しても戻さない）
+    next_handle: u32,
 }
 
 impl TaskTracker {
@@ -151,12 +277,75 @@ impl TaskTracker {
     pub fn new() -> Self {
         Self {
             tasks: HashMap::new(),
+            current: HashMap::new(),
+            handles: HashMap::new(),
+            next_handle: 1,
+        }
+    }
+
+    /// タスクを登録する（初めて見たタスクには表示用の番号を振る）
+    pub fn register(&mut self, mut task: TaskInfo) {
+        let generation = task.generation;
+        let previous = self.current.get(&task.address).and_then(|id| self.tasks.get(id));
+        if previous.is_none_or(|p| p.generation <= generation) {
+            self.current.insert(task.address, task.id);
+        }
+        if !self.tasks.contains_key(&task.id) {
+            task.handle = self.next_handle;
+            self.next_handle += 1;
+            self.handles.insert(task.handle, task.id);
+            self.tasks.insert(task.id, task);
+        }
+    }
+
+    /// 保存しておいたタスクを表示用の番号ごと戻す（状態の読み込み用）
+    ///
+    /// 番号のないタスクには新しい番号を振り、以後に振る番号は戻した番号より大きくします。
+    pub fn restore(&mut self, mut task: TaskInfo) {
+        if task.handle == 0 {
+            task.handle = self.next_handle;
+        }
+        self.next_handle = self.next_handle.max(task.handle + 1);
+        let previous = self.current.get(&task.address).and_then(|id| self.tasks.get(id));
+        if previous.is_none_or(|p| p.generation <= task.generation) {
+            self.current.insert(task.address, task.id);
+        }
+        self.handles.insert(task.handle, task.id);
+        self.tasks.insert(task.id, task);
+    }
+
+    /// 表示用の番号かタスクIDで指定されたタスクを引く
+    pub fn lookup(&self, task: TaskRef) -> Option<TaskId> {
+        match task {
+            TaskRef::Handle(handle) => self.handles.get(&handle).copied(),
+            TaskRef::Id(id) if self.tasks.contains_key(&id) => Some(id),
+            // 世代番号を含まないアドレスで指定された場合は最新世代のタスク
+            TaskRef::Id(address) => self.resolve(address),
         }
     }
 
-    /// タスクを登録する
-    pub fn register(&mut self, task: TaskInfo) {
-        self.tasks.entry(task.id).or_insert(task);
+    /// アドレスを使っている最新世代のタスクIDを引く
+    pub fn resolve(&self, address: u64) -> Option<TaskId> {
+        self.current.get(&address).copied()
+    }
+
+    /// アドレスが再利用されたとみなし、古いタスクを破棄済みにして次の世代のタスクを登録する
+    ///
+    /// # Returns
+    /// 新しいタスクのID
+    pub fn start_new_generation(&mut self, address: u64) -> TaskId {
+        let generation = match self.resolve(address).and_then(|id| self.tasks.get_mut(&id)) {
+            Some(old) => {
+                old.superseded = true;
+                old.finished_at.get_or_insert_with(Instant::now);
+                old.generation + 1
+            }
+            None => 0,
+        };
+        let task = TaskInfo::with_generation(address, generation);
+        let id = task.id;
+        self.register(task);
+        id
     }
 
     /// タスクを取得する
@@ -176,7 +365,54 @@ impl TaskTracker {
 
     /// タスクを削除する
     pub fn remove(&mut self, id: TaskId) -> Option<TaskInfo> {
-        self.tasks.remove(&id)
+        let task = self.tasks.remove(&id)?;
+        if self.current.get(&task.address) == Some(&id) {
+            self.current.remove(&task.address);
+        }
+        self.handles.remove(&task.handle);
+        Some(task)
+    }
+
+    /// 追跡中のタスク数
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// 追跡中のタスクがないか
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// 保持ポリシーに従って終了済みのタスクを取り除く
+    ///
+    /// 未完了のタスクは取り除きません。まず終了から `max_age` 以上経ったものを、
+    /// 次に `max_tasks` を超えた分を終了の古い順に取り除きます。
+    ///
+    /// # Returns
+    /// 取り除いたタスク
+    pub fn compact(&mut self, policy: &RetentionPolicy, now: Instant) -> Vec<TaskInfo> {
+        let mut finished: Vec<(Instant, TaskId)> = self.tasks.values()
+            .filter(|t| !t.is_live())
+            .map(|t| (t.finished_at.unwrap_or(t.last_seen), t.id))
+            .collect();
+        finished.sort();
+
+        let expired = policy.max_age
+            .map(|age| finished.partition_point(|(at, _)| now.saturating_duration_since(*at) >= age))
+            .unwrap_or(0);
+        let over = policy.max_tasks
+            .map(|max| (self.tasks.len() - expired).saturating_sub(max))
+            .unwrap_or(0);
+        let evict = (expired + over).min(finished.len());
+
+        finished[..evict].iter().filter_map(|(_, id)| self.remove(*id)).collect()
+    }
+
+    /// すべてのタスクを破棄する
+    pub fn clear(&mut self) {
+        self.tasks.clear();
+        self.current.clear();
+        self.handles.clear();
     }
 }
 
@@ -211,6 +447,13 @@ impl EdgeTracker {
         edge_id
     }
 
+    /// 保存しておいたエッジを時刻と完了フラグごと戻す
+    pub fn restore(&mut self, edge: Edge) -> EdgeId {
+        let edge_id = edge.compute_id();
+        self.edges.insert(edge_id, edge);
+        edge_id
+    }
+
     /// エッジを取得する
     pub fn get(&self, id: EdgeId) -> Option<&Edge> {
         self.edges.get(&id)
@@ -242,6 +485,21 @@ impl EdgeTracker {
             edge.completed = true;
         }
     }
+
+    /// 条件を満たすエッジだけを残す
+    ///
+    /// # Returns
+    /// 取り除いたエッジの数
+    pub fn retain(&mut self, mut keep: impl FnMut(&Edge) -> bool) -> usize {
+        let before = self.edges.len();
+        self.edges.retain(|_, e| keep(e));
+        before - self.edges.len()
+    }
+
+    /// すべてのエッジを破棄する
+    pub fn clear(&mut self) {
+        self.edges.clear();
+    }
 }
 
 impl Default for EdgeTracker {
@@ -279,6 +537,16 @@ impl CallsiteTracker {
     pub fn all_callsites(&self) -> impl Iterator<Item = &Callsite> {
         self.callsites.values()
     }
+
+    /// 条件を満たす呼び出しサイトだけを残す
+    pub fn retain(&mut self, mut keep: impl FnMut(&Callsite) -> bool) {
+        self.callsites.retain(|_, c| keep(c));
+    }
+
+    /// すべての呼び出しサイトを破棄する
+    pub fn clear(&mut self) {
+        self.callsites.clear();
+    }
 }
 
 impl Default for CallsiteTracker {
@@ -292,6 +560,8 @@ impl Default for CallsiteTracker {
 pub struct PollScope {
     /// ネストしたpoll呼び出しのスタック（子→親の順）
     stack: Vec<TaskId>,
+    /// 各エントリの poll に入ったときのスタックポインタ（分からなければ None）
+    stack_pointers: Vec<Option<u64>>,
 }
 
 impl PollScope {
@@ -299,16 +569,26 @@ impl PollScope {
     pub fn new() -> Self {
         Self {
             stack: Vec::new(),
+            stack_pointers: Vec::new(),
         }
     }
 
     /// タスクをスタックにプッシュする
     pub fn push(&mut self, task_id: TaskId) {
         self.stack.push(task_id);
+        self.stack_pointers.push(None);
+    }
+
+    /// 最上位のエントリに、poll に入ったときのスタックポインタを記録する
+    pub fn set_top_stack_pointer(&mut self, sp: u64) {
+        if let Some(top) = self.stack_pointers.last_mut() {
+            *top = Some(sp);
+        }
     }
 
     /// スタックからタスクをポップする
     pub fn pop(&mut self) -> Option<TaskId> {
+        self.stack_pointers.pop();
         self.stack.pop()
     }
 
@@ -325,6 +605,42 @@ impl PollScope {
     /// スタックをクリアする
     pub fn clear(&mut self) {
         self.stack.clear();
+        self.stack_pointers.clear();
+    }
+
+    /// フレームの CFA が `cfa` より深い（小さい）poll のエントリを取り除く
+    ///
+    /// `cfa` はパニックを受け止めたフレームの CFA です。それより深いフレームはもう消えているので、
+    /// そこで poll 中だったタスクは exit を通らずに抜けています。entry はプロローグ前なので、
+    /// poll のフレームの CFA は記録したスタックポインタ + 8 です。スタックポインタの分からないエントリは、
+    /// それより外側のエントリが取り除かれたときだけ一緒に取り除きます。
+    ///
+    /// # Returns
+    /// 取り除いたタスク（外側から順）
+    pub fn unwind_to(&mut self, cfa: u64) -> Vec<TaskId> {
+        match self.stack_pointers.iter().position(|entry| entry.is_some_and(|entry| entry + 8 < cfa)) {
+            Some(pos) => {
+                self.stack_pointers.truncate(pos);
+                self.stack.split_off(pos)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// タスクとそれより深いエントリを取り除く
+    ///
+    /// # Returns
+    /// 取り除いたエントリの数
+    pub fn truncate_at(&mut self, task_id: TaskId) -> usize {
+        match self.stack.iter().position(|&id| id == task_id) {
+            Some(pos) => {
+                let removed = self.stack.len() - pos;
+                self.stack.truncate(pos);
+                self.stack_pointers.truncate(pos);
+                removed
+            }
+            None => 0,
+        }
     }
 
     /// OS スタックから取得したタスクリストで再同期する
@@ -335,6 +651,9 @@ impl PollScope {
     /// # Arguments
     /// * `actual_stack` - OS スタックから取得した実際のタスクリスト（子→親の順）
     pub fn resync(&mut self, actual_stack: Vec<TaskId>) {
+        // 内部のスタックは push 順（親→子）なので、向きを揃える
+        let actual_stack: Vec<TaskId> = actual_stack.into_iter().rev().collect();
+
         // 最長共通接頭辞を見つける
         let mut common_len = 0;
         for (i, (&expected, &actual)) in self.stack.iter().zip(actual_stack.iter()).enumerate() {
@@ -347,10 +666,11 @@ impl PollScope {
 
         // 共通部分以降を削除
         self.stack.truncate(common_len);
+        self.stack_pointers.truncate(common_len);
 
         // actual_stack の残りを追加
         for &task_id in &actual_stack[common_len..] {
-            self.stack.push(task_id);
+            self.push(task_id);
         }
     }
 }
@@ -376,7 +696,7 @@ impl ThreadPollScopeManager {
 
     /// 指定スレッドのpollスコープを取得する（なければ作成）
     pub fn get_or_create(&mut self, tid: Tid) -> &mut PollScope {
-        self.scopes.entry(tid).or_insert_with(PollScope::new)
+        self.scopes.entry(tid).or_default()
     }
 
     /// 指定スレッドのpollスコープを取得する
@@ -384,9 +704,19 @@ impl ThreadPollScopeManager {
         self.scopes.get(&tid)
     }
 
+    /// 指定スレッドのpollスコープを可変参照で取得する
+    pub fn get_mut(&mut self, tid: Tid) -> Option<&mut PollScope> {
+        self.scopes.get_mut(&tid)
+    }
+
     /// 指定スレッドのpollスコープを削除する
-    pub fn remove(&mut self, tid: Tid) {
-        self.scopes.remove(&tid);
+    pub fn remove(&mut self, tid: Tid) -> Option<PollScope> {
+        self.scopes.remove(&tid)
+    }
+
+    /// スコープを持っているスレッド
+    pub fn threads(&self) -> impl Iterator<Item = Tid> + '_ {
+        self.scopes.keys().copied()
     }
 
     /// すべてのスコープをクリアする
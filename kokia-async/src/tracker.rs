@@ -5,11 +5,28 @@
 use crate::{
     TaskId, TaskInfo, TaskTracker,
     EdgeTracker, Edge,
-    CallsiteTracker, Callsite, CallsiteId,
+    CallsiteTracker, Callsite, CallsiteId, AwaitSite,
     ThreadPollScopeManager, Tid,
-    GenFutureDetector,
+    GenFutureDetector, AsyncStats, LifetimeStats, RunningTime,
+    RetentionPolicy, EvictionStats,
 };
 use crate::Result;
+use crate::generator::{PANICKED_DISCRIMINANT, RETURNED_DISCRIMINANT, UNRESUMED_DISCRIMINANT};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// スタック上で見つけた poll 中の async 関数フレーム
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObservedFrame {
+    /// タスクID（フレームが保持する self ポインタ）
+    pub task: TaskId,
+    /// 関数名（デマングル済み）
+    pub function_name: Option<String>,
+    /// タスクの discriminant（停止点インデックス）
+    pub discriminant: Option<u64>,
+    /// フレームの PC に対応するソースコード位置（親フレームなら await している箇所）
+    pub source_location: Option<(String, u32)>,
+}
 
 /// Async タスクトラッカー
 pub struct AsyncTracker {
@@ -23,8 +40,20 @@ pub struct AsyncTracker {
     scope_manager: ThreadPollScopeManager,
     /// GenFuture検出器
     detector: GenFutureDetector,
+    /// 終了済みタスクの保持ポリシー
+    retention: RetentionPolicy,
+    /// 保持ポリシーで捨てたものの累計
+    evicted: EvictionStats,
+    /// 前回のコンパクション以降に終了したタスクの数
+    finished_since_compaction: usize,
 }
 
+/// 保持ポリシーの適用間隔（終了したタスクの数）
+///
+/// 毎回全タスクを走査しないよう間引くので、`max_tasks` は最大でこの数
+/// （`max_tasks` の方が小さければその数）だけ超えることがある。
+const COMPACTION_INTERVAL: usize = 256;
+
 impl AsyncTracker {
     /// 新しいAsyncTrackerを作成する
     pub fn new() -> Result<Self> {
@@ -34,6 +63,9 @@ impl AsyncTracker {
             callsite_tracker: CallsiteTracker::new(),
             scope_manager: ThreadPollScopeManager::new(),
             detector: GenFutureDetector::new()?,
+            retention: RetentionPolicy::unlimited(),
+            evicted: EvictionStats::default(),
+            finished_since_compaction: 0,
         })
     }
 
@@ -46,7 +78,8 @@ impl AsyncTracker {
     /// * `parent_task` - フレームスキャンで検出された親タスク（Option）
     /// * `discriminant` - 子タスクの discriminant（停止点インデックス）
     /// * `function_name` - タスクの関数名（デマングル済み）
-    /// * `source_location` - ソースコード位置 (file, line)
+    /// * `await_site` - 親が子を await している位置（不明なら None）
+    #[allow(clippy::too_many_arguments)]
     pub fn on_poll_entry(
         &mut self,
         tid: Tid,
@@ -55,17 +88,28 @@ impl AsyncTracker {
         parent_task: Option<u64>,
         discriminant: Option<u64>,
         function_name: Option<String>,
-        source_location: Option<(String, u32)>,
+        await_site: Option<AwaitSite>,
     ) -> Result<()> {
-        let child = child_self;
+        // 0) self ポインタを論理タスクに対応付ける（アドレス再利用なら新しい世代を作る）
+        let child = match self.task_tracker.resolve(child_self) {
+            Some(id) if self.task_tracker.get(id)
+                .is_some_and(|t| t.looks_reused(rip, function_name.as_deref(), discriminant)) =>
+            {
+                self.note_finished();
+                self.task_tracker.start_new_generation(child_self)
+            }
+            Some(id) => id,
+            None => child_self,
+        };
 
         // 1) 親探索（優先: フレームスキャン → スコープスタック）
-        let parent = parent_task.or_else(|| {
-            self.scope_manager.get(tid).and_then(|scope| scope.top())
-        });
+        let parent = parent_task
+            .map(|ptr| self.task_tracker.resolve(ptr).unwrap_or(ptr))
+            .or_else(|| self.scope_manager.get(tid).and_then(|scope| scope.top()));
 
         // 2) タスク登録・属性更新
         if let Some(t) = self.task_tracker.get_mut(child) {
+            t.first_rip.get_or_insert(rip);
             t.touch();
             t.last_rip = Some(rip);
             if let Some(d) = discriminant {
@@ -77,6 +121,7 @@ impl AsyncTracker {
             }
         } else {
             let mut task = TaskInfo::new(child);
+            task.first_rip = Some(rip);
             task.last_rip = Some(rip);
             task.type_name = function_name;
             if let Some(d) = discriminant {
@@ -84,23 +129,19 @@ impl AsyncTracker {
             }
             self.task_tracker.register(task);
         }
-
-        // ソースコード位置を取得（addr2line）
-        let (file, line) = source_location
-            .map(|(f, l)| (Some(f), Some(l)))
-            .unwrap_or((None, None));
+        // 別スレッドから移ってきたタスクは、前のスレッドのスコープに残った古いエントリを捨てる
+        self.note_poll_thread(tid, child);
 
         // 3) エッジ登録（callsite 同定）
         if let Some(parent_id) = parent {
-            let parent_discriminant = self.task_tracker.get(parent_id)
-                .and_then(|t| t.current_discriminant);
-
-            let callsite = Callsite {
-                parent: parent_id,
-                suspend_idx: parent_discriminant.map(|d| d as u32),
-                file,
-                line,
-            };
+            // 停止点は await の行から求めたものを優先し、なければ親の最後の discriminant を使う
+            let AwaitSite { file, line, suspend_idx } = await_site.unwrap_or_default();
+            let suspend_idx = suspend_idx.or_else(|| {
+                self.task_tracker.get(parent_id)
+                    .and_then(|t| t.current_discriminant)
+                    .map(|d| d as u32)
+            });
+            let callsite = Callsite { parent: parent_id, suspend_idx, file, line };
 
             let callsite_id = self.callsite_tracker.register(callsite);
             self.edge_tracker.register_or_update(parent_id, child, callsite_id);
@@ -110,6 +151,9 @@ impl AsyncTracker {
                 task.is_root = true;
             }
         }
+        if let Some(task) = self.task_tracker.get_mut(child) {
+            task.parent = parent;
+        }
 
         // 4) 動的スコープ push
         let scope = self.scope_manager.get_or_create(tid);
@@ -121,6 +165,35 @@ impl AsyncTracker {
         Ok(())
     }
 
+    /// フレームスキャンをせずに、スコープスタックの先頭をこの entry の親とみなしてよいか
+    ///
+    /// 前回の poll で分かった親と discriminant から、スタックが実際の呼び出しと食い違っている
+    /// 疑いのあるときに false を返します。
+    /// - 先頭のタスクが終わっている（完了済み、または Returned / Panicked）か、子自身
+    /// - 再開された（discriminant が Unresumed 以外の）既知の子の前回の親が、先頭と違う
+    pub fn scope_parent_agrees(&self, tid: Tid, child_self: u64, discriminant: Option<u64>) -> bool {
+        let top = self.scope_manager.get(tid).and_then(|scope| scope.top());
+        let child = self.task_tracker.resolve(child_self);
+        if let Some(top) = top {
+            if Some(top) == child {
+                return false;
+            }
+            let finished = self.task_tracker.get(top).is_some_and(|task| {
+                task.completed || matches!(task.current_discriminant, Some(RETURNED_DISCRIMINANT | PANICKED_DISCRIMINANT))
+            });
+            if finished {
+                return false;
+            }
+        }
+        if discriminant == Some(UNRESUMED_DISCRIMINANT) {
+            return true;
+        }
+        match child.and_then(|id| self.task_tracker.get(id)) {
+            Some(task) if task.is_live() => task.parent == top,
+            _ => true,
+        }
+    }
+
     /// GenFuture::poll exit イベントを処理する
     ///
     /// # Arguments
@@ -153,8 +226,9 @@ impl AsyncTracker {
 
                 // タスクを完了としてマーク
                 if let Some(task) = self.task_tracker.get_mut(child_id) {
-                    task.completed = true;
+                    task.mark_completed();
                 }
+                self.note_finished();
             }
         } else {
             // スタックが空の場合は再同期が必要
@@ -164,6 +238,209 @@ impl AsyncTracker {
         Ok(())
     }
 
+    /// 計装を始める前から poll 中だったタスクを、スレッドのスタックから登録する
+    ///
+    /// 隣り合うフレームを親子としてエッジを張り、最も外側のタスクを root にします。
+    /// スコープスタックもスタックに合わせるので、以後の exit イベントは正しく対応付けられます。
+    ///
+    /// # Arguments
+    /// * `tid` - スレッド ID
+    /// * `frames` - スタック上の async 関数フレーム（子→親の順、task は self ポインタ）
+    ///
+    /// # Returns
+    /// 新たに登録したタスクの数
+    pub fn seed_from_stack(&mut self, tid: Tid, frames: &[ObservedFrame]) -> usize {
+        let mut registered = 0;
+        let frames: Vec<ObservedFrame> = frames
+            .iter()
+            .map(|f| ObservedFrame { task: self.task_tracker.resolve(f.task).unwrap_or(f.task), ..f.clone() })
+            .collect();
+        for frame in &frames {
+            if let Some(task) = self.task_tracker.get_mut(frame.task) {
+                if frame.discriminant.is_some() {
+                    task.current_discriminant = frame.discriminant;
+                }
+                continue;
+            }
+            let mut task = TaskInfo::new(frame.task);
+            task.type_name = frame.function_name.clone();
+            task.current_discriminant = frame.discriminant;
+            task.pre_existing = true;
+            self.task_tracker.register(task);
+            registered += 1;
+        }
+
+        for pair in frames.windows(2) {
+            let (child, parent) = (&pair[0], &pair[1]);
+            let (file, line) = parent.source_location.clone()
+                .map(|(f, l)| (Some(f), Some(l)))
+                .unwrap_or((None, None));
+            let callsite_id = self.callsite_tracker.register(Callsite {
+                parent: parent.task,
+                suspend_idx: parent.discriminant.map(|d| d as u32),
+                file,
+                line,
+            });
+            self.edge_tracker.register_or_update(parent.task, child.task, callsite_id);
+        }
+
+        if let Some(outermost) = frames.last() {
+            let has_parent = self.edge_tracker.edges_by_child(outermost.task).next().is_some();
+            if let Some(task) = self.task_tracker.get_mut(outermost.task) {
+                task.is_root = !has_parent;
+            }
+        }
+
+        self.resync_from_stack(tid, frames.iter().map(|f| f.task).collect());
+        registered
+    }
+
+    /// タスクを poll したスレッドを記録し、スレッド間の移動を検出する
+    ///
+    /// 前のスレッドのスコープにタスクが残っていれば（exit を取りこぼしている）、
+    /// タスクとそれより深いエントリを取り除きます。
+    fn note_poll_thread(&mut self, tid: Tid, task_id: TaskId) {
+        let Some(task) = self.task_tracker.get_mut(task_id) else {
+            return;
+        };
+        let previous = task.last_tid.replace(tid);
+        if let Some(prev) = previous.filter(|&prev| prev != tid) {
+            task.migrations += 1;
+            if let Some(scope) = self.scope_manager.get_mut(prev) {
+                scope.truncate_at(task_id);
+            }
+        }
+    }
+
+    /// 直前の entry で poll に入ったときのスタックポインタを記録する（巻き戻しの検出に使う）
+    pub fn note_poll_stack_pointer(&mut self, tid: Tid, sp: u64) {
+        if let Some(scope) = self.scope_manager.get_mut(tid) {
+            scope.set_top_stack_pointer(sp);
+        }
+    }
+
+    /// パニックの巻き戻しで exit を通らずに抜けた poll をスコープから取り除く
+    ///
+    /// # Arguments
+    /// * `tid` - スレッド ID
+    /// * `cfa` - パニックを受け止めたフレームの CFA（これより深いフレームは巻き戻し済み）
+    ///
+    /// # Returns
+    /// 取り除いたタスク（外側から順）
+    pub fn on_unwind(&mut self, tid: Tid, cfa: u64) -> Vec<TaskId> {
+        self.scope_manager.get_mut(tid).map_or_else(Vec::new, |scope| scope.unwind_to(cfa))
+    }
+
+    /// poll の出入り以外で読み直したタスクの discriminant を記録する
+    pub fn record_discriminant(&mut self, task: TaskId, discriminant: u64) {
+        if let Some(task) = self.task_tracker.get_mut(task) {
+            task.current_discriminant = Some(discriminant);
+        }
+    }
+
+    /// スレッドの終了を処理する（そのスレッドのスコープスタックを捨てる）
+    ///
+    /// # Returns
+    /// 捨てたスコープのエントリ数
+    pub fn on_thread_exit(&mut self, tid: Tid) -> usize {
+        self.scope_manager.remove(tid).map_or(0, |scope| scope.stack().len())
+    }
+
+    /// 生きているスレッド以外のスコープスタックを捨てる
+    ///
+    /// # Returns
+    /// スコープを捨てたスレッドの数
+    pub fn retain_threads(&mut self, live: &[Tid]) -> usize {
+        let dead: Vec<Tid> = self.scope_manager.threads().filter(|tid| !live.contains(tid)).collect();
+        for &tid in &dead {
+            self.on_thread_exit(tid);
+        }
+        dead.len()
+    }
+
+    /// 保持ポリシーを設定し、すぐに適用する
+    ///
+    /// # Returns
+    /// 捨てたタスクの数
+    pub fn set_retention(&mut self, policy: RetentionPolicy) -> usize {
+        self.retention = policy;
+        self.compact()
+    }
+
+    /// 現在の保持ポリシー
+    pub fn retention(&self) -> RetentionPolicy {
+        self.retention
+    }
+
+    /// 保持ポリシーで捨てたものの累計
+    pub fn eviction_stats(&self) -> EvictionStats {
+        self.evicted
+    }
+
+    /// 保持ポリシーに従って終了済みのタスクと、それに関わるエッジ・呼び出しサイトを捨てる
+    ///
+    /// # Returns
+    /// 捨てたタスクの数
+    pub fn compact(&mut self) -> usize {
+        self.finished_since_compaction = 0;
+        if !self.retention.is_limited() {
+            return 0;
+        }
+
+        let removed = self.task_tracker.compact(&self.retention, Instant::now());
+        self.evicted.compactions += 1;
+        if removed.is_empty() {
+            return 0;
+        }
+
+        let ids: HashSet<TaskId> = removed.iter().map(|t| t.id).collect();
+        let edges = self.edge_tracker.retain(|e| !ids.contains(&e.parent) && !ids.contains(&e.child));
+        self.callsite_tracker.retain(|c| !ids.contains(&c.parent));
+
+        self.evicted.tasks += removed.len() as u64;
+        self.evicted.completed += removed.iter().filter(|t| t.completed).count() as u64;
+        self.evicted.superseded += removed.iter().filter(|t| t.superseded && !t.completed).count() as u64;
+        self.evicted.edges += edges as u64;
+        removed.len()
+    }
+
+    /// タスクの終了を数え、間隔に達したら保持ポリシーを適用する
+    fn note_finished(&mut self) {
+        self.finished_since_compaction += 1;
+        let interval = self.retention.max_tasks.map_or(COMPACTION_INTERVAL, |max| max.clamp(1, COMPACTION_INTERVAL));
+        if self.finished_since_compaction >= interval {
+            self.compact();
+        }
+    }
+
+    /// 追跡状態（タスク・エッジ・呼び出しサイト・スコープ・捨てた件数）をすべて破棄する
+    ///
+    /// 保持ポリシーはそのまま残します。
+    pub fn clear(&mut self) {
+        self.task_tracker.clear();
+        self.edge_tracker.clear();
+        self.callsite_tracker.clear();
+        self.scope_manager.clear();
+        self.evicted = EvictionStats::default();
+        self.finished_since_compaction = 0;
+    }
+
+    /// 書き出しておいたタスク・エッジ・呼び出しサイトで追跡状態を置き換える
+    ///
+    /// 実行中のスコープは戻さないので、戻したタスクはどのスレッドでも poll 中になりません。
+    pub fn restore(&mut self, tasks: Vec<TaskInfo>, edges: Vec<Edge>, callsites: Vec<Callsite>) {
+        self.clear();
+        for task in tasks {
+            self.task_tracker.restore(task);
+        }
+        for callsite in callsites {
+            self.callsite_tracker.register(callsite);
+        }
+        for edge in edges {
+            self.edge_tracker.restore(edge);
+        }
+    }
+
     /// OS スタックからスコープスタックを再同期する
     ///
     /// 実際の OS スタックから取得したタスクリストで、内部のスコープスタックを同期します。
@@ -173,6 +450,10 @@ impl AsyncTracker {
     /// * `tid` - スレッド ID
     /// * `actual_tasks` - OS スタックから取得した実際のタスクリスト（子→親の順）
     pub fn resync_from_stack(&mut self, tid: Tid, actual_tasks: Vec<u64>) {
+        let actual_tasks = actual_tasks
+            .into_iter()
+            .map(|ptr| self.task_tracker.resolve(ptr).unwrap_or(ptr))
+            .collect();
         let scope = self.scope_manager.get_or_create(tid);
         scope.resync(actual_tasks);
     }
@@ -228,9 +509,73 @@ impl AsyncTracker {
             .unwrap_or_default()
     }
 
-    /// すべてのタスクを取得する
+    /// 未完了のルートタスクを取得する（ID 昇順）
+    pub fn root_tasks(&self) -> Vec<&TaskInfo> {
+        let mut roots: Vec<_> = self.task_tracker.all_tasks()
+            .filter(|t| t.is_root && t.is_live())
+            .collect();
+        roots.sort_by_key(|t| t.id);
+        roots
+    }
+
+    /// ルートタスクから await チェーンを再構成する（子→親の順）
+    ///
+    /// 現在 poll 中でないタスクでも、未完了のエッジを辿ることで
+    /// 中断中の論理スタックを復元する。子が複数ある場合は最後に観測された
+    /// エッジを優先する。循環は検出した時点で打ち切る。
+    pub fn await_chain(&self, root: TaskId) -> Vec<TaskId> {
+        let mut chain = vec![root];
+        let mut current = root;
+
+        loop {
+            let next = self.edge_tracker.edges_by_parent(current)
+                .filter(|e| !e.completed)
+                .filter(|e| self.task_tracker.get(e.child).is_none_or(|t| t.is_live()))
+                .max_by_key(|e| e.last_seen)
+                .map(|e| e.child);
+
+            match next {
+                Some(child) if !chain.contains(&child) => {
+                    chain.push(child);
+                    current = child;
+                }
+                _ => break,
+            }
+        }
+
+        chain.reverse();
+        chain
+    }
+
+    /// 指定スレッドで現在 poll 中の最も深いタスクを取得する
+    pub fn current_task(&self, tid: Tid) -> Option<&TaskInfo> {
+        self.scope_manager.get(tid)
+            .and_then(|scope| scope.top())
+            .and_then(|id| self.task_tracker.get(id))
+    }
+
+    /// タスク統計を計算する
+    ///
+    /// # Arguments
+    /// * `running` - デバッグ対象が実行していた時間の記録
+    /// * `stall_threshold` - 実行中にこの時間以上 poll されていない未完了タスクを停滞とみなす
+    pub fn stats(&self, running: &RunningTime, stall_threshold: Duration) -> AsyncStats {
+        AsyncStats {
+            evicted: self.evicted.tasks,
+            ..AsyncStats::collect(self.task_tracker.all_tasks(), running, Instant::now(), stall_threshold)
+        }
+    }
+
+    /// タスクの寿命の分布を計算する（`windows` はスループットを数える区間の数）
+    pub fn lifetimes(&self, windows: usize) -> LifetimeStats {
+        LifetimeStats::collect(self.task_tracker.all_tasks(), Instant::now(), windows)
+    }
+
+    /// すべてのタスクを表示用の番号順に取得する
     pub fn all_tasks(&self) -> Vec<&TaskInfo> {
-        self.task_tracker.all_tasks().collect()
+        let mut tasks: Vec<&TaskInfo> = self.task_tracker.all_tasks().collect();
+        tasks.sort_by_key(|t| t.handle);
+        tasks
     }
 
     /// すべてのエッジを取得する
@@ -259,3 +604,231 @@ impl Default for AsyncTracker {
         Self::new().expect("Failed to create AsyncTracker")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_await_chain_for_suspended_root() {
+        let mut tracker = AsyncTracker::new().unwrap();
+        let tid = Tid(1);
+
+        // root(0x1000) -> child(0x2000) -> grandchild(0x3000) と poll し、全て Pending で戻る
+        tracker.on_poll_entry(tid, 0x1000, 0x10, None, Some(3), None, None).unwrap();
+        tracker.on_poll_entry(tid, 0x2000, 0x20, None, Some(3), None, None).unwrap();
+        tracker.on_poll_entry(tid, 0x3000, 0x30, None, Some(3), None, None).unwrap();
+        tracker.on_poll_exit(tid, 0x30, false).unwrap();
+        tracker.on_poll_exit(tid, 0x20, false).unwrap();
+        tracker.on_poll_exit(tid, 0x10, false).unwrap();
+
+        assert!(tracker.async_backtrace(tid).is_empty());
+
+        let roots: Vec<_> = tracker.root_tasks().iter().map(|t| t.id).collect();
+        assert_eq!(roots, vec![0x1000]);
+        assert_eq!(tracker.await_chain(0x1000), vec![0x3000, 0x2000, 0x1000]);
+    }
+
+    #[test]
+    fn test_scope_parent_agrees_with_previous_poll() {
+        let mut tracker = AsyncTracker::new().unwrap();
+        let tid = Tid(1);
+
+        // root(0x1000) -> child(0x2000) と poll し、どちらも Pending で戻る
+        assert!(tracker.scope_parent_agrees(tid, 0x1000, Some(0)));
+        tracker.on_poll_entry(tid, 0x1000, 0x10, None, Some(0), None, None).unwrap();
+        assert!(tracker.scope_parent_agrees(tid, 0x2000, Some(0)));
+        tracker.on_poll_entry(tid, 0x2000, 0x20, None, Some(0), None, None).unwrap();
+        tracker.on_poll_exit(tid, 0x20, false).unwrap();
+        tracker.on_poll_exit(tid, 0x10, false).unwrap();
+
+        // 再開も同じ親の下なら一致する
+        assert!(tracker.scope_parent_agrees(tid, 0x1000, Some(3)));
+        tracker.on_poll_entry(tid, 0x1000, 0x10, None, Some(3), None, None).unwrap();
+        assert!(tracker.scope_parent_agrees(tid, 0x2000, Some(3)));
+        // 前回の親が分からない子は先頭を信じる
+        assert!(tracker.scope_parent_agrees(tid, 0x3000, Some(3)));
+        tracker.on_poll_exit(tid, 0x10, false).unwrap();
+
+        // 0x1000 の下で poll された子がスタックの空のときに再開されたら、スタックが欠けている
+        assert!(!tracker.scope_parent_agrees(tid, 0x2000, Some(3)));
+        // root が別のタスクの上で再開されるのも食い違い
+        tracker.on_poll_entry(tid, 0x2000, 0x20, None, Some(3), None, None).unwrap();
+        assert!(!tracker.scope_parent_agrees(tid, 0x1000, Some(3)));
+        // 子が自分自身の上で poll されることはない
+        assert!(!tracker.scope_parent_agrees(tid, 0x2000, Some(3)));
+    }
+
+    #[test]
+    fn test_reused_address_gets_new_generation() {
+        let mut tracker = AsyncTracker::new().unwrap();
+        let tid = Tid(1);
+        let name = || Some("app::handler::{{closure}}".to_string());
+
+        // 0x1000 の Future が Ready で完了し、解放後に同じアドレスへ別の Future が置かれる
+        tracker.on_poll_entry(tid, 0x1000, 0x10, None, Some(0), name(), None).unwrap();
+        tracker.on_poll_exit(tid, 0x10, true).unwrap();
+        tracker.on_poll_entry(tid, 0x1000, 0x10, None, Some(0), name(), None).unwrap();
+
+        let current = tracker.async_backtrace(tid)[0];
+        assert_ne!(current, 0x1000);
+        assert_eq!(tracker.get_task(current).unwrap().address, 0x1000);
+        assert_eq!(tracker.get_task(current).unwrap().generation, 1);
+        assert!(tracker.get_task(0x1000).unwrap().completed);
+        tracker.on_poll_exit(tid, 0x10, false).unwrap();
+
+        // Pending で戻った後の再 poll（discriminant が進んでいる）は同じタスク
+        tracker.on_poll_entry(tid, 0x1000, 0x10, None, Some(3), name(), None).unwrap();
+        assert_eq!(tracker.async_backtrace(tid), vec![current]);
+        tracker.on_poll_exit(tid, 0x10, false).unwrap();
+
+        // 完了前に破棄され、Unresumed(0) の Future が置かれたら別タスク
+        tracker.on_poll_entry(tid, 0x1000, 0x10, None, Some(0), name(), None).unwrap();
+        let third = tracker.async_backtrace(tid)[0];
+        assert_eq!(tracker.get_task(third).unwrap().generation, 2);
+        assert!(tracker.get_task(current).unwrap().superseded);
+        assert_eq!(tracker.stats(&RunningTime::new(), std::time::Duration::from_secs(5)).live, 1);
+    }
+
+    #[test]
+    fn test_seed_from_stack() {
+        let mut tracker = AsyncTracker::new().unwrap();
+        let tid = Tid(1);
+        let frame = |task, name: &str| ObservedFrame {
+            task,
+            function_name: Some(name.to_string()),
+            discriminant: Some(3),
+            source_location: Some(("main.rs".to_string(), 10)),
+        };
+
+        // アタッチ時点で main(0x1000) -> worker(0x2000) を poll 中だった
+        let frames = [frame(0x2000, "app::worker::{{closure}}"), frame(0x1000, "app::main::{{closure}}")];
+        assert_eq!(tracker.seed_from_stack(tid, &frames), 2);
+        assert!(tracker.get_task(0x2000).unwrap().pre_existing);
+        assert_eq!(tracker.root_tasks().iter().map(|t| t.id).collect::<Vec<_>>(), vec![0x1000]);
+
+        // 以後の exit はシード済みのスコープから取り出される
+        tracker.on_poll_exit(tid, 0x20, true).unwrap();
+        assert!(tracker.get_task(0x2000).unwrap().completed);
+        assert_eq!(tracker.async_backtrace(tid), vec![0x1000]);
+        assert_eq!(tracker.seed_from_stack(tid, &frames), 0);
+    }
+
+    #[test]
+    fn test_resync_from_stack_keeps_parent_at_bottom() {
+        let mut tracker = AsyncTracker::new().unwrap();
+        let tid = Tid(1);
+
+        // スタックから読んだ並びは子→親、スコープスタックは push 順（親→子）
+        for (task, sp) in [(0x1000, 0x7f00), (0x2000, 0x7e00)] {
+            tracker.on_poll_entry(tid, task, 0x10, None, Some(3), None, None).unwrap();
+            tracker.note_poll_stack_pointer(tid, sp);
+        }
+        // 0x2000 の exit を取りこぼし、main が次に 0x3000 を poll している
+        tracker.resync_from_stack(tid, vec![0x3000, 0x1000]);
+        assert_eq!(tracker.async_backtrace(tid), vec![0x1000, 0x3000]);
+        // 空のスコープも親から積む
+        tracker.resync_from_stack(Tid(2), vec![0x3000, 0x1000]);
+        assert_eq!(tracker.async_backtrace(Tid(2)), vec![0x1000, 0x3000]);
+    }
+
+    #[test]
+    fn test_unwind_pops_scopes_below_catch() {
+        let mut tracker = AsyncTracker::new().unwrap();
+        let tid = Tid(1);
+
+        // main(sp 0x7f00) -> outer(0x7e00) -> inner(0x7d00) を poll 中にパニックし、
+        // main の poll から呼んだ catch_unwind（CFA 0x7e80）で受け止めた。
+        // __rust_panic_cleanup に来た時には do_catch・cleanup のフレームが outer の跡（sp 0x7d80 あたり）まで
+        // 積まれているが、比べるのは受け止めたフレームの CFA
+        for (task, sp) in [(0x1000, 0x7f00), (0x2000, 0x7e00), (0x3000, 0x7d00)] {
+            tracker.on_poll_entry(tid, task, 0x10, None, Some(3), None, None).unwrap();
+            tracker.note_poll_stack_pointer(tid, sp);
+        }
+        assert_eq!(tracker.on_unwind(tid, 0x7e80), vec![0x2000, 0x3000]);
+        assert_eq!(tracker.async_backtrace(tid), vec![0x1000]);
+        assert!(!tracker.get_task(0x3000).unwrap().completed);
+        assert!(tracker.on_unwind(tid, 0x7e80).is_empty());
+        assert!(tracker.on_unwind(Tid(2), 0x7e80).is_empty());
+        // poll の関数そのものが受け止めたフレーム（CFA = entry の sp + 8）なら、その poll は残る
+        assert!(tracker.on_unwind(tid, 0x7f08).is_empty());
+        assert_eq!(tracker.async_backtrace(tid), vec![0x1000]);
+    }
+
+    #[test]
+    fn test_retention_evicts_oldest_finished() {
+        let mut tracker = AsyncTracker::new().unwrap();
+        let tid = Tid(1);
+
+        // root(0x1000) の下で5つの子が順に完了する
+        tracker.on_poll_entry(tid, 0x1000, 0x10, None, Some(3), None, None).unwrap();
+        for child in 0..5 {
+            tracker.on_poll_entry(tid, 0x2000 + child * 0x100, 0x20, None, Some(0), None, None).unwrap();
+            tracker.on_poll_exit(tid, 0x20, true).unwrap();
+        }
+        assert_eq!(tracker.all_tasks().len(), 6);
+
+        // 上限3件: 未完了の root は残し、古い子から3つ捨てる
+        let policy = RetentionPolicy { max_tasks: Some(3), max_age: None };
+        assert_eq!(tracker.set_retention(policy), 3);
+        let mut kept: Vec<_> = tracker.all_tasks().iter().map(|t| t.id).collect();
+        kept.sort();
+        assert_eq!(kept, vec![0x1000, 0x2300, 0x2400]);
+        assert_eq!(tracker.all_edges().len(), 2);
+        let evicted = tracker.eviction_stats();
+        assert_eq!((evicted.tasks, evicted.completed, evicted.edges), (3, 3, 3));
+        assert_eq!(tracker.stats(&RunningTime::new(), Duration::from_secs(5)).evicted, 3);
+
+        // 経過時間 0 なら終了済みはすべて捨てる
+        let policy = RetentionPolicy { max_tasks: None, max_age: Some(Duration::ZERO) };
+        assert_eq!(tracker.set_retention(policy), 2);
+
+        tracker.clear();
+        assert!(tracker.all_tasks().is_empty());
+        assert_eq!(tracker.eviction_stats(), EvictionStats::default());
+        assert_eq!(tracker.retention(), policy);
+    }
+
+    #[test]
+    fn test_callsite_from_await_site() {
+        let mut tracker = AsyncTracker::new().unwrap();
+        let tid = Tid(1);
+        let site = |line, suspend_idx| Some(AwaitSite { file: Some("main.rs".into()), line: Some(line), suspend_idx });
+
+        // 親(0x1000) が2つの await で別々の子を poll する
+        tracker.on_poll_entry(tid, 0x1000, 0x10, None, Some(0), None, None).unwrap();
+        tracker.on_poll_entry(tid, 0x2000, 0x20, None, Some(0), None, site(30, Some(3))).unwrap();
+        tracker.on_poll_exit(tid, 0x20, true).unwrap();
+        tracker.on_poll_entry(tid, 0x3000, 0x20, None, Some(0), None, site(33, None)).unwrap();
+
+        let mut callsites: Vec<_> = tracker.edges_by_parent(0x1000).iter()
+            .map(|e| tracker.get_callsite(e.callsite).unwrap())
+            .map(|c| (c.line, c.suspend_idx))
+            .collect();
+        callsites.sort();
+        // 停止点が分からなければ親の discriminant で補う
+        assert_eq!(callsites, vec![(Some(30), Some(3)), (Some(33), Some(0))]);
+    }
+
+    #[test]
+    fn test_task_migration_and_thread_exit() {
+        let mut tracker = AsyncTracker::new().unwrap();
+        let (a, b) = (Tid(1), Tid(2));
+
+        // スレッド A で poll された root(0x1000) -> child(0x2000) の exit を取りこぼした
+        tracker.on_poll_entry(a, 0x1000, 0x10, None, Some(3), None, None).unwrap();
+        tracker.on_poll_entry(a, 0x2000, 0x20, None, Some(3), None, None).unwrap();
+
+        // work-stealing で root がスレッド B に移ると、A の古いエントリは消える
+        tracker.on_poll_entry(b, 0x1000, 0x10, None, Some(3), None, None).unwrap();
+        assert!(tracker.async_backtrace(a).is_empty());
+        assert_eq!(tracker.async_backtrace(b), vec![0x1000]);
+        let root = tracker.get_task(0x1000).unwrap();
+        assert_eq!((root.last_tid, root.migrations), (Some(b), 1));
+
+        // スレッド B が終了したらスコープを捨てる
+        assert_eq!(tracker.retain_threads(&[a]), 1);
+        assert!(tracker.async_backtrace(b).is_empty());
+        assert_eq!(tracker.on_thread_exit(a), 0);
+    }
+}
@@ -0,0 +1,220 @@
+//! タスクグラフの絞り込みと木表示
+//!
+//! 長時間トラッキングするとエッジが膨大になるため、未完了のものだけ・特定のルート以下だけ・
+//! 最近観測したものだけに絞り込めるようにします。木表示では、すべて完了した部分木を
+//! 「N completed children」の1行にまとめます。
+
+use crate::{AsyncTracker, Edge, TaskId, TaskInfo, TaskRef};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// エッジとタスク木の絞り込み条件
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EdgeFilter {
+    /// 完了したエッジや、終了したタスクへのエッジを含めない
+    pub active_only: bool,
+    /// このタスクから辿れる部分だけを対象にする
+    pub root: Option<TaskRef>,
+    /// 直近この時間内に観測したエッジだけを対象にする
+    pub since: Option<Duration>,
+}
+
+impl EdgeFilter {
+    /// ルート指定以外の条件にタスクが合うか
+    fn accepts_task(&self, task: &TaskInfo, now: Instant) -> bool {
+        if self.active_only && !task.is_live() {
+            return false;
+        }
+        self.since.is_none_or(|since| now.saturating_duration_since(task.last_seen) <= since)
+    }
+}
+
+/// タスク木の1行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeLine {
+    /// タスク
+    Task { depth: usize, id: TaskId },
+    /// 1つにまとめた、すべて完了した部分木の数
+    FoldedCompleted { depth: usize, count: usize },
+}
+
+impl AsyncTracker {
+    /// ルート指定以外の条件にエッジが合うか
+    ///
+    /// exit を取りこぼすとエッジに完了が付かないので、`active_only` では両端のタスクの状態も見る。
+    fn edge_accepted(&self, filter: &EdgeFilter, edge: &Edge, now: Instant) -> bool {
+        let live = |id| self.get_task(id).is_some_and(|t: &TaskInfo| t.is_live());
+        if filter.active_only && (edge.completed || !live(edge.parent) || !live(edge.child)) {
+            return false;
+        }
+        filter.since.is_none_or(|since| now.saturating_duration_since(edge.last_seen) <= since)
+    }
+
+    /// 絞り込みのルートのタスクID（番号で指定されて、その番号のタスクがなければ 0）
+    fn filter_root(&self, filter: &EdgeFilter) -> Option<TaskId> {
+        filter.root.map(|root| match root {
+            TaskRef::Id(id) => self.task_tracker().lookup(root).unwrap_or(id),
+            TaskRef::Handle(_) => self.task_tracker().lookup(root).unwrap_or(0),
+        })
+    }
+
+    /// 条件に合うエッジを観測順に取得する
+    pub fn filtered_edges(&self, filter: &EdgeFilter) -> Vec<&Edge> {
+        let now = Instant::now();
+        let mut edges: Vec<&Edge> = match self.filter_root(filter) {
+            Some(root) => {
+                let mut reached = Vec::new();
+                let mut visited = HashSet::from([root]);
+                let mut pending = vec![root];
+                while let Some(parent) = pending.pop() {
+                    for edge in self.edge_tracker().edges_by_parent(parent).filter(|e| self.edge_accepted(filter, e, now)) {
+                        reached.push(edge);
+                        if visited.insert(edge.child) {
+                            pending.push(edge.child);
+                        }
+                    }
+                }
+                reached
+            }
+            None => self.edge_tracker().all_edges().filter(|e| self.edge_accepted(filter, e, now)).collect(),
+        };
+        edges.sort_by_key(|e| (e.first_seen, e.parent, e.child));
+        edges
+    }
+
+    /// タスク木を行のリストとして組み立てる
+    ///
+    /// ルートは `filter.root`（完了していても展開する。未知のタスクなら空）、なければ root と
+    /// みなしたタスクすべてです。子は最初に観測した順に並べ、タスク自身と子孫がすべて終了している
+    /// 部分木は、兄弟ごとに1行にまとめます。循環や合流で既に表示したタスクは再び展開しません。
+    pub fn task_tree(&self, filter: &EdgeFilter) -> Vec<TreeLine> {
+        let now = Instant::now();
+        let mut lines = Vec::new();
+        let mut visited = HashSet::new();
+
+        match self.filter_root(filter) {
+            Some(root) if self.get_task(root).is_some() => {
+                visited.insert(root);
+                lines.push(TreeLine::Task { depth: 0, id: root });
+                let children = self.tree_children(root, filter, now);
+                self.push_siblings(&children, 1, filter, now, &mut visited, &mut lines);
+            }
+            Some(_) => {}
+            None => {
+                let mut roots: Vec<&TaskInfo> = self.task_tracker().all_tasks()
+                    .filter(|t| t.is_root && filter.accepts_task(t, now))
+                    .collect();
+                roots.sort_by_key(|t| (t.first_seen, t.id));
+                let roots: Vec<TaskId> = roots.into_iter().map(|t| t.id).collect();
+                self.push_siblings(&roots, 0, filter, now, &mut visited, &mut lines);
+            }
+        }
+        lines
+    }
+
+    /// 兄弟タスクを順に展開し、完了した部分木は数えてまとめる
+    fn push_siblings(
+        &self,
+        siblings: &[TaskId],
+        depth: usize,
+        filter: &EdgeFilter,
+        now: Instant,
+        visited: &mut HashSet<TaskId>,
+        lines: &mut Vec<TreeLine>,
+    ) {
+        let mut folded = 0;
+        for &id in siblings {
+            if self.subtree_finished(id, filter, now, &mut HashSet::new()) {
+                folded += 1;
+                continue;
+            }
+            if !visited.insert(id) {
+                continue;
+            }
+            lines.push(TreeLine::Task { depth, id });
+            let children = self.tree_children(id, filter, now);
+            self.push_siblings(&children, depth + 1, filter, now, visited, lines);
+        }
+        if folded > 0 {
+            lines.push(TreeLine::FoldedCompleted { depth, count: folded });
+        }
+    }
+
+    /// 条件に合う子タスクを最初に観測した順に取得する（重複する子は最初のエッジだけ）
+    fn tree_children(&self, parent: TaskId, filter: &EdgeFilter, now: Instant) -> Vec<TaskId> {
+        let mut edges: Vec<&Edge> = self.edge_tracker().edges_by_parent(parent)
+            .filter(|e| self.edge_accepted(filter, e, now))
+            .collect();
+        edges.sort_by_key(|e| (e.first_seen, e.child));
+
+        let mut seen = HashSet::new();
+        edges.into_iter()
+            .map(|e| e.child)
+            .filter(|child| seen.insert(*child))
+            .collect()
+    }
+
+    /// タスクと、その子孫がすべて終了しているか（捨てられたタスクは終了扱い）
+    fn subtree_finished(&self, id: TaskId, filter: &EdgeFilter, now: Instant, visited: &mut HashSet<TaskId>) -> bool {
+        if !visited.insert(id) {
+            return true;
+        }
+        if self.get_task(id).is_some_and(|t| t.is_live()) {
+            return false;
+        }
+        self.tree_children(id, filter, now)
+            .into_iter()
+            .all(|child| self.subtree_finished(child, filter, now, visited))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Tid;
+
+    #[test]
+    fn test_task_tree_folds_completed_children() {
+        let mut tracker = AsyncTracker::new().unwrap();
+        let tid = Tid(1);
+
+        // root(0x1000) の下で3つの子が完了し、4つ目(0x4000)が孫(0x5000)を await 中
+        tracker.on_poll_entry(tid, 0x1000, 0x10, None, Some(3), None, None).unwrap();
+        for child in [0x2000, 0x3000, 0x3100] {
+            tracker.on_poll_entry(tid, child, 0x20, None, Some(0), None, None).unwrap();
+            tracker.on_poll_exit(tid, 0x20, true).unwrap();
+        }
+        tracker.on_poll_entry(tid, 0x4000, 0x20, None, Some(3), None, None).unwrap();
+        tracker.on_poll_entry(tid, 0x5000, 0x30, None, Some(3), None, None).unwrap();
+
+        let lines = tracker.task_tree(&EdgeFilter::default());
+        let shape: Vec<(usize, Option<TaskId>)> = lines.iter()
+            .map(|l| match l {
+                TreeLine::Task { depth, id } => (*depth, Some(*id)),
+                TreeLine::FoldedCompleted { depth, count } => {
+                    assert_eq!(*count, 3);
+                    (*depth, None)
+                }
+            })
+            .collect();
+        assert_eq!(shape, vec![(0, Some(0x1000)), (1, Some(0x4000)), (2, Some(0x5000)), (1, None)]);
+
+        // --active は完了したエッジを除き、--root は部分木だけに絞る
+        let active = EdgeFilter { active_only: true, ..Default::default() };
+        assert_eq!(tracker.filtered_edges(&active).len(), 2);
+        let sub = EdgeFilter { root: Some(TaskRef::Id(0x4000)), ..Default::default() };
+        let edges: Vec<_> = tracker.filtered_edges(&sub).iter().map(|e| (e.parent, e.child)).collect();
+        assert_eq!(edges, vec![(0x4000, 0x5000)]);
+        // 表示用の番号は登録順に振られ、--root #5 でも同じ部分木になる
+        assert_eq!(tracker.get_task(0x4000).unwrap().label(), "#5 (0x4000)");
+        let by_handle = EdgeFilter { root: Some(TaskRef::Handle(5)), ..Default::default() };
+        assert_eq!(tracker.filtered_edges(&by_handle).len(), 1);
+        assert!(tracker.filtered_edges(&EdgeFilter { root: Some(TaskRef::Handle(99)), ..Default::default() }).is_empty());
+        // 明示したルートは完了していても展開する
+        let done = EdgeFilter { root: Some(TaskRef::Id(0x2000)), ..Default::default() };
+        assert_eq!(tracker.task_tree(&done), vec![TreeLine::Task { depth: 0, id: 0x2000 }]);
+        assert!(tracker.task_tree(&EdgeFilter { root: Some(TaskRef::Id(0x1)), ..Default::default() }).is_empty());
+        let recent = EdgeFilter { since: Some(Duration::from_secs(60)), ..Default::default() };
+        assert_eq!(tracker.filtered_edges(&recent).len(), 5);
+    }
+}
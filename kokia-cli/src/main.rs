@@ -4,17 +4,55 @@
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
-use kokia_core::{Command, Debugger, StopReason};
+use kokia_core::{AfterStop, BreakContext, BreakpointId, BuildIdCheck, Command, Debugger, EdgeFilter, HelpCategory, InterruptGuard, RetentionPolicy, SpawnOptions, StepFilter, StopAction, StopPlan, StopReason, Supervision, SymbolPattern, TaskRef, TreeLine, Trigger, Value};
+use kokia_dwarf::VariableKind;
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
 use tracing_subscriber::EnvFilter;
 
+mod session_log;
+
+// 画面への出力をすべて `set logging` の記録にも流すため、このファイルでは標準のマクロを置き換える
+macro_rules! println {
+    () => { println!("") };
+    ($($arg:tt)*) => {{
+        let text = format!($($arg)*);
+        std::println!("{}", text);
+        $crate::session_log::output($crate::session_log::Stream::Stdout, &format!("{}\n", text));
+    }};
+}
+
+macro_rules! print {
+    ($($arg:tt)*) => {{
+        let text = format!($($arg)*);
+        std::print!("{}", text);
+        $crate::session_log::output($crate::session_log::Stream::Stdout, &text);
+    }};
+}
+
+macro_rules! eprintln {
+    () => { eprintln!("") };
+    ($($arg:tt)*) => {{
+        let text = format!($($arg)*);
+        std::eprintln!("{}", text);
+        $crate::session_log::output($crate::session_log::Stream::Stderr, &format!("{}\n", text));
+    }};
+}
+
 /// Kokia - Rust Async Debugger
 #[derive(Parser)]
 #[command(name = "kokia")]
 #[command(version = "0.1.0")]
 #[command(about = "Runtime-independent debugger for Rust async functions", long_about = None)]
 struct Cli {
+    /// Run the --ex commands without starting the REPL and exit with the debuggee's exit code
+    #[arg(long)]
+    batch: bool,
+
+    /// Command to execute after startup (can be repeated)
+    #[arg(short = 'x', long = "ex", value_name = "CMD")]
+    ex: Vec<String>,
+
     #[command(subcommand)]
     command: DebugCommand,
 }
@@ -26,6 +64,19 @@ enum DebugCommand {
         /// Path to the executable binary
         binary: String,
 
+        /// Place the program in its own process group (terminal Ctrl-C won't reach it)
+        #[arg(long)]
+        new_pgrp: bool,
+
+        /// Keep continuing until the program crashes (fatal signal or caught panic), then run
+        /// these comma-separated actions: report, core[=path], source=<file>, then hold or exit
+        #[arg(long, value_name = "ACTIONS", value_parser = StopPlan::parse)]
+        on_crash: Option<StopPlan>,
+
+        /// Like --on-crash, for stops at user breakpoints, watchpoints and `catch err`
+        #[arg(long, value_name = "ACTIONS", value_parser = StopPlan::parse)]
+        on_break: Option<StopPlan>,
+
         /// Arguments to pass to the program
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         args: Vec<String>,
@@ -37,8 +88,46 @@ enum DebugCommand {
         binary: String,
 
         /// Process ID to attach to
-        #[arg(short, long)]
-        pid: i32,
+        #[arg(short, long, required_unless_present = "wait", conflicts_with = "wait")]
+        pid: Option<i32>,
+
+        /// Poll until a process with this name (or PID) appears, then attach to it
+        #[arg(long, value_name = "NAME|PID")]
+        wait: Option<String>,
+
+        /// Debug a process in the container that host PID <PID> belongs to;
+        /// the binary path and --pid are then interpreted inside that container
+        #[arg(long, value_name = "PID")]
+        target_ns: Option<i32>,
+
+        /// Serve Prometheus metrics (tasks alive/stalled, polls, poll duration, breakpoint
+        /// overhead) on http://<ADDR>/metrics while attached, e.g. 127.0.0.1:9900
+        #[arg(long, value_name = "ADDR")]
+        metrics: Option<std::net::SocketAddr>,
+    },
+
+    /// Print function and source line of raw addresses (from a panic backtrace, perf, ...);
+    /// addresses are read from stdin if none are given
+    Symbolize {
+        /// Path to the executable binary
+        binary: String,
+
+        /// Addresses to symbolize (hex with 0x, or decimal)
+        addresses: Vec<String>,
+
+        /// Load address of a PIE binary; subtracted from runtime addresses (see /proc/<pid>/maps)
+        #[arg(long, value_name = "ADDR")]
+        base: Option<String>,
+
+        /// Print exactly like `addr2line -f -C -i -e <binary>`: one address per stdin line (hex, 0x optional), function and file:line for each inline frame
+        #[arg(long)]
+        addr2line: bool,
+    },
+
+    /// Check that ptrace works and that the binary can be debugged, with advice for each problem
+    Doctor {
+        /// Path to the executable binary to check (only the environment is checked if omitted)
+        binary: Option<String>,
     },
 }
 
@@ -56,23 +145,268 @@ fn main() -> Result<()> {
         .with_thread_ids(false)
         .init();
 
+    let cli = Cli::parse();
+
+    // symbolize は出力をパイプでつなぐ道具なので、バナーを出さない
+    if let DebugCommand::Symbolize { binary, addresses, base, addr2line } = &cli.command {
+        return run_symbolize(binary, addresses, base.as_deref(), *addr2line);
+    }
+
     println!("Kokia - Rust Async Debugger");
     println!("Version 0.1.0");
     println!();
 
-    let cli = Cli::parse();
-    let mut debugger = init_debugger(cli.command)?;
+    // kokia が panic/SIGTERM で落ちた場合に起動したプロセスを道連れにする
+    kokia_core::install_cleanup_handlers();
+
+    let command = match cli.command {
+        DebugCommand::Doctor { binary } => {
+            let ok = run_doctor(binary.as_deref());
+            std::process::exit(if ok { 0 } else { 1 });
+        }
+        command => command,
+    };
+    let supervision = match &command {
+        DebugCommand::Run { on_crash, on_break, .. } => Supervision { on_crash: on_crash.clone(), on_break: on_break.clone() },
+        _ => Supervision::default(),
+    };
+    let mut debugger = init_debugger(command)?;
+
+    // 起動時コマンドを順に実行する（quit で打ち切り）
+    for line in &cli.ex {
+        if Command::parse(line) == Some(Command::Quit) {
+            break;
+        }
+        println!("(kokia) {}", line);
+        if let Err(e) = handle_command(&mut debugger, line) {
+            eprintln!("Error: {}", e);
+        }
+    }
+
+    if supervision.is_enabled() {
+        if let Some(code) = supervise(&mut debugger, &supervision)? {
+            debugger.stop_event_export();
+            session_log::stop();
+            kokia_core::kill_registered_inferiors();
+            std::process::exit(code);
+        }
+        // hold: --batch でも対話用に止まったままにする
+        run_repl(&mut debugger)?;
+        print_exit_summary(&debugger);
+        session_log::stop();
+        return Ok(());
+    }
+
+    if cli.batch {
+        // 非対話モード: デバッグ対象の終了ステータスをそのまま返す（シグナルで終わったら 128+番号）
+        print_exit_summary(&debugger);
+        let code = debugger.exit_status().unwrap_or(0);
+        session_log::stop();
+        drop(debugger);
+        std::process::exit(code);
+    }
+
     run_repl(&mut debugger)?;
+    print_exit_summary(&debugger);
+    session_log::stop();
+
+    Ok(())
+}
+
+/// セッション終了時にデバッグ対象の状態を表示する
+fn print_exit_summary(debugger: &Debugger) {
+    match (debugger.exit_code(), debugger.exit_signal(), debugger.pid()) {
+        (Some(code), _, _) => println!("Debuggee exited with code {}", code),
+        (None, Some(signal), _) => println!("Debuggee was killed by {:?}", signal),
+        (None, None, Some(pid)) => println!("Debuggee (pid {}) is still running; detaching", pid),
+        (None, None, None) => {}
+    }
+}
+
+/// `run --on-crash` / `--on-break` の監視: 指定の停止が起きるか終了するまで continue を繰り返す
+///
+/// # Returns
+/// kokia の終了コード（hold なら None）
+fn supervise(debugger: &mut Debugger, supervision: &Supervision) -> Result<Option<i32>> {
+    // クラッシュレポートは report を指定したときだけ出す
+    let crash_report = debugger.crash_report_enabled();
+    debugger.set_crash_report(false);
+    let outcome = supervise_until_triggered(debugger, supervision);
+    debugger.set_crash_report(crash_report);
+    outcome
+}
+
+fn supervise_until_triggered(debugger: &mut Debugger, supervision: &Supervision) -> Result<Option<i32>> {
+    loop {
+        handle_continue(debugger)?;
+        let event = debugger.last_stop().cloned();
+        if debugger.detect_lost_inferior().is_some() || !debugger.is_alive() {
+            let code = match (debugger.exit_status(), event.map(|e| e.reason)) {
+                (Some(code), _) => code,
+                (None, Some(StopReason::Signal(signal))) => 128 + signal as i32,
+                (None, _) => 1,
+            };
+            println!("Supervisor: the program ended without a trigger; exiting with code {}", code);
+            return Ok(Some(code));
+        }
+        let Some(event) = event else { continue };
+        let Some(trigger) = Trigger::classify(&event) else { continue };
+        let Some(plan) = supervision.plan_for(trigger) else {
+            // --on-crash がなければシグナルを届けてプロセスを終わらせ、上で 128+シグナル番号を返して抜ける
+            if trigger == Trigger::Crash {
+                println!("Supervisor: crash without --on-crash; letting the program terminate");
+            }
+            continue;
+        };
+
+        println!();
+        println!("Supervisor: {} triggered, running {}", if trigger == Trigger::Crash { "--on-crash" } else { "--on-break" }, plan);
+        for action in &plan.actions {
+            run_stop_action(debugger, trigger, action);
+        }
+        return Ok(match plan.then {
+            AfterStop::Hold => {
+                println!("Supervisor: holding for interactive use");
+                None
+            }
+            AfterStop::Exit => {
+                let code = trigger.exit_code(&event);
+                println!("Supervisor: exiting with code {}", code);
+                Some(code)
+            }
+        });
+    }
+}
+
+/// 監視で止まったときの動作を1つ行う（失敗しても残りの動作は続ける）
+fn run_stop_action(debugger: &mut Debugger, trigger: Trigger, action: &StopAction) {
+    let result = match action {
+        StopAction::Report if trigger == Trigger::Crash => {
+            print_crash_report(debugger);
+            Ok(())
+        }
+        StopAction::Report => {
+            println!();
+            let result = handle_backtrace(debugger, false);
+            if !debugger.async_tracker().task_tracker().is_empty() {
+                println!();
+                println!("Async tasks:");
+                handle_async_tree(debugger, &EdgeFilter::default());
+            }
+            result
+        }
+        StopAction::Core(path) => handle_generate_core(debugger, path.as_ref().and_then(|p| p.to_str())),
+        StopAction::Source(path) => std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Cannot read {}: {}", path.display(), e))
+            .map(|script| {
+                for line in script.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+                    println!("(kokia) {}", line);
+                    if let Err(e) = handle_command(debugger, line) {
+                        eprintln!("Error: {}", e);
+                    }
+                }
+            }),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+    }
+}
+
+/// `kokia symbolize` を実行する
+///
+/// アドレスが引数になければ標準入力の各行から `0x` で始まる語を拾い、1行に1アドレスずつ出力します。
+fn run_symbolize(binary: &str, addresses: &[String], base: Option<&str>, addr2line: bool) -> Result<()> {
+    use kokia_core::parse::parse_address;
+    use std::io::BufRead;
+
+    let mut symbolizer = kokia_core::Symbolizer::open(binary)?;
+    if let Some(base) = base {
+        symbolizer = symbolizer.with_load_bias(parse_address(base)?);
+    }
+    if addr2line {
+        return run_addr2line(&symbolizer, addresses);
+    }
+
+    if !addresses.is_empty() {
+        for address in addresses {
+            println!("{}", symbolizer.symbolize(parse_address(address)?)?);
+        }
+        return Ok(());
+    }
+
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        let words = line.split(|c: char| !c.is_ascii_alphanumeric());
+        for address in words.filter(|word| word.starts_with("0x")).filter_map(|word| parse_address(word).ok()) {
+            println!("{}", symbolizer.symbolize(address)?);
+        }
+    }
+    Ok(())
+}
 
+/// `addr2line -f -C -i` と同じ形式で出力する
+///
+/// addr2line と同じく、アドレスは `0x` がなくても16進数として読み、読めないものは `??` になります。
+/// パイプの先で1件ずつ読めるように、標準入力の1行ごとに出力をフラッシュします。
+fn run_addr2line(symbolizer: &kokia_core::Symbolizer, addresses: &[String]) -> Result<()> {
+    use std::io::{BufRead, Write};
+
+    let lookup = |word: &str| -> Result<String> {
+        let digits = word.trim_start_matches("0x").trim_start_matches("0X");
+        Ok(match u64::from_str_radix(digits, 16) {
+            Ok(address) => kokia_core::format_addr2line(&symbolizer.inline_frames(address)?),
+            Err(_) => kokia_core::format_addr2line(&[]),
+        })
+    };
+
+    if !addresses.is_empty() {
+        for address in addresses {
+            print!("{}", lookup(address)?);
+        }
+        return Ok(());
+    }
+    for line in std::io::stdin().lock().lines() {
+        for word in line?.split_whitespace() {
+            print!("{}", lookup(word)?);
+        }
+        std::io::stdout().flush()?;
+    }
     Ok(())
 }
 
+/// `kokia doctor` の診断結果を表示する。デバッグできない問題がなければ true
+fn run_doctor(binary: Option<&str>) -> bool {
+    use kokia_core::{CheckStatus, DoctorReport};
+
+    let report = DoctorReport::run(binary.map(std::path::Path::new));
+    for check in &report.checks {
+        println!("{:<6} {:<14} {}", format!("[{}]", check.status), check.name, check.summary);
+        for advice in &check.advice {
+            println!("       {:<14} -> {}", "", advice);
+        }
+    }
+    println!();
+    match report.worst() {
+        CheckStatus::Ok => println!("No problems found"),
+        CheckStatus::Warning => println!("Debugging works, but some features are limited (see warnings above)"),
+        CheckStatus::Error => println!("kokia cannot debug in this setup until the failures above are fixed"),
+    }
+    report.worst() != CheckStatus::Error
+}
+
 /// デバッガを初期化してプロセスにアタッチまたは起動する
 fn init_debugger(command: DebugCommand) -> Result<Debugger> {
     let mut debugger = Debugger::new();
+    debugger.set_progress_callback(progress_reporter());
+    if std::io::IsTerminal::is_terminal(&std::io::stdout()) {
+        let names = kokia_core::FutureNames { highlight: true, ..debugger.future_names().clone() };
+        debugger.set_future_names(names);
+    }
+    debugger.set_logpoint_callback(Some(std::rc::Rc::new(|id, line: &str| println!("[logpoint {}] {}", id, line))));
+    debugger.set_warning_callback(Some(std::rc::Rc::new(|message: &str| eprintln!("Warning: {}", message))));
 
     match command {
-        DebugCommand::Run { binary, args } => {
+        DebugCommand::Run { binary, new_pgrp, args, .. } => {
             println!("Loading binary: {}", binary);
             println!();
 
@@ -81,24 +415,52 @@ fn init_debugger(command: DebugCommand) -> Result<Debugger> {
             println!("Loaded DWARF information from {}", binary);
 
             // プロセスを起動
-            debugger.spawn(&binary, &args)?;
+            let options = SpawnOptions { new_process_group: new_pgrp };
+            debugger.spawn_with_options(&binary, &args, &options)?;
+            warn_build_id_mismatch(&debugger);
             println!("Process spawned and stopped at first instruction");
             println!("Memory mappings are now initialized");
             println!("Set breakpoints and use 'continue' to continue execution");
             println!();
         }
-        DebugCommand::Attach { binary, pid } => {
+        DebugCommand::Doctor { .. } | DebugCommand::Symbolize { .. } => unreachable!("handled before starting a debugger"),
+        DebugCommand::Attach { binary, pid, wait, target_ns, metrics } => {
+            if let Some(ns_pid) = target_ns {
+                debugger.set_target_namespace(ns_pid)?;
+                println!("Target namespace: {}", ns_pid);
+            }
+            let pid = match pid {
+                Some(pid) => {
+                    let host_pid = debugger.host_pid(pid)?;
+                    if host_pid != pid {
+                        println!("Pid {} in container is host pid {}", pid, host_pid);
+                    }
+                    Some(host_pid)
+                }
+                None => None,
+            };
             println!("Loading binary: {}", binary);
-            println!("Attaching to process: {}", pid);
-            println!();
 
             // バイナリからDWARF情報を読み込む
             debugger.load_binary(&binary)?;
             println!("Loaded DWARF information from {}", binary);
 
             // プロセスにアタッチ
-            debugger.attach(pid)?;
+            let pid = match (pid, wait) {
+                (Some(pid), _) => {
+                    println!("Attaching to process: {}", pid);
+                    debugger.attach(pid)?;
+                    pid
+                }
+                (None, Some(target)) => wait_and_attach(&mut debugger, &target)?,
+                (None, None) => unreachable!("clap requires --pid or --wait"),
+            };
             println!("Attached to process {}", pid);
+            warn_build_id_mismatch(&debugger);
+            if let Some(address) = metrics {
+                let address = debugger.start_metrics(address)?;
+                println!("Serving metrics on http://{}/metrics (updated by 'async enable' tracking)", address);
+            }
             println!();
         }
     }
@@ -106,25 +468,124 @@ fn init_debugger(command: DebugCommand) -> Result<Debugger> {
     Ok(debugger)
 }
 
+/// 端末の stderr にスピナーと百分率で進捗を表示するコールバックを作る
+///
+/// stderr が端末でない場合（バッチ実行のログなど）は表示しない。
+fn progress_reporter() -> Option<kokia_dwarf::ProgressCallback> {
+    use kokia_dwarf::ProgressEvent;
+    use std::cell::RefCell;
+    use std::io::{IsTerminal, Write};
+
+    if !std::io::stderr().is_terminal() {
+        return None;
+    }
+
+    const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+    // (表示中のタイトル, スピナーの位置)
+    let state = RefCell::new((String::new(), 0usize));
+    Some(std::rc::Rc::new(move |event: &ProgressEvent| {
+        let mut state = state.borrow_mut();
+        let mut stderr = std::io::stderr();
+        match event {
+            ProgressEvent::Start { title, .. } => {
+                *state = (title.clone(), 0);
+                let _ = write!(stderr, "\r{} {}...", SPINNER[0], title);
+            }
+            ProgressEvent::Update { done, total } => {
+                state.1 = (state.1 + 1) % SPINNER.len();
+                let _ = match total {
+                    Some(total) if *total > 0 => write!(
+                        stderr,
+                        "\r{} {}... {:3}% ({}/{})",
+                        SPINNER[state.1],
+                        state.0,
+                        done * 100 / total,
+                        done,
+                        total
+                    ),
+                    _ => write!(stderr, "\r{} {}... {}", SPINNER[state.1], state.0, done),
+                };
+            }
+            ProgressEvent::End => {
+                // 行を消して元の出力に戻す
+                let _ = write!(stderr, "\r\x1b[2K");
+            }
+        }
+        let _ = stderr.flush();
+    }))
+}
+
+/// プロセスが現れるまで待ってアタッチする（Ctrl-C で待つのをやめる）
+fn wait_and_attach(debugger: &mut Debugger, target: &str) -> Result<i32> {
+    println!("Waiting for process '{}' (Ctrl-C to stop waiting)...", target);
+    let interrupt = InterruptGuard::install();
+    let result = debugger.attach_wait(target, ATTACH_POLL_INTERVAL);
+    drop(interrupt);
+    result
+}
+
+/// `attach --wait` でプロセス一覧を見直す間隔
+const ATTACH_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// 読み込んだバイナリが実行中のものと異なる場合に警告する
+fn warn_build_id_mismatch(debugger: &Debugger) {
+    match debugger.check_build_id() {
+        Ok(BuildIdCheck::Mismatch { file, target }) => {
+            eprintln!("**********************************************************************");
+            eprintln!("WARNING: the binary on disk does not match the running process!");
+            eprintln!("  build-id of loaded file:   {}", file);
+            eprintln!("  build-id of running image: {}", target);
+            eprintln!("  Symbols, line numbers and type layouts may be wrong.");
+            eprintln!("  Use 'symbol-file-from-memory' to take symbols from the running image.");
+            eprintln!("**********************************************************************");
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("Warning: could not verify build-id: {}", e),
+    }
+}
+
 /// REPLループを実行する
 fn run_repl(debugger: &mut Debugger) -> Result<()> {
     println!("Type 'help' for available commands, 'quit' to exit.");
     println!();
 
     let mut rl = DefaultEditor::new()?;
+    // `!prefix` の展開に使う履歴（古い順）と、空行で繰り返す直前の実行制御コマンド
+    let mut history: Vec<String> = Vec::new();
+    let mut repeat: Option<String> = None;
 
     loop {
         let readline = rl.readline("(kokia) ");
         match readline {
             Ok(line) => {
-                let line = line.trim();
+                let mut line = line.trim().to_string();
                 if line.is_empty() {
-                    continue;
+                    match &repeat {
+                        Some(last) => line = last.clone(),
+                        None => continue,
+                    }
+                } else {
+                    if let Some(expanded) = kokia_core::command::expand_history(&line, &history) {
+                        match expanded {
+                            Ok(expanded) => {
+                                println!("{}", expanded);
+                                line = expanded;
+                            }
+                            Err(e) => {
+                                eprintln!("{}", e);
+                                continue;
+                            }
+                        }
+                    }
+                    rl.add_history_entry(line.as_str())?;
+                    history.push(line.clone());
                 }
 
-                rl.add_history_entry(line)?;
+                repeat = Command::parse(&line)
+                    .filter(Command::repeats_on_empty_line)
+                    .map(|_| line.clone());
 
-                if let Err(e) = handle_command(debugger, line) {
+                if let Err(e) = handle_command(debugger, &line) {
                     eprintln!("Error: {}", e);
                 }
             }
@@ -155,6 +616,11 @@ fn demangle_name(name: &str) -> String {
     }
 }
 
+/// async の表示で使う Future の型名（デマングルして `set async names` の設定で短縮する）
+fn future_name(debugger: &Debugger, name: &str) -> String {
+    debugger.future_names().display(&demangle_name(name))
+}
+
 /// シンボルリストを表示するヘルパー関数
 fn print_symbol_list(title: &str, symbols: &[kokia_core::Symbol], limit: Option<usize>) {
     if symbols.is_empty() {
@@ -178,28 +644,277 @@ fn print_symbol_list(title: &str, symbols: &[kokia_core::Symbol], limit: Option<
     }
 }
 
-#[allow(unreachable_patterns)]
+/// コマンドを実行し、その間にデバッグ対象が消えていたら「プロセスなし」の状態に移す
 fn handle_command(debugger: &mut Debugger, line: &str) -> Result<()> {
+    let result = dispatch_command(debugger, line);
+    if let Some(pid) = debugger.detect_lost_inferior() {
+        println!();
+        println!("Process {} is gone; 'attach [--wait] <pid|name>' to debug it again", pid);
+    }
+    result
+}
+
+#[allow(unreachable_patterns)]
+fn dispatch_command(debugger: &mut Debugger, line: &str) -> Result<()> {
+    session_log::command(line);
     let parsed_command = Command::parse(line);
 
     match parsed_command {
-        Some(Command::Help) => print_help(),
-        Some(Command::Quit) => handle_quit(),
-        Some(Command::Break(loc)) => handle_break(debugger, &loc)?,
+        Some(Command::Help(topic)) => print_help(topic.as_deref()),
+        Some(Command::Quit) => handle_quit(debugger),
+        Some(Command::Break(loc, context)) => {
+            if let Some(id) = handle_break(debugger, &loc)?.filter(|_| context != BreakContext::default()) {
+                restrict_breakpoint(debugger, id, context)?;
+            }
+        }
+        Some(Command::Logpoint(loc, format)) => handle_logpoint(debugger, &loc, &format)?,
+        Some(Command::BpGroupCreate(name)) => {
+            debugger.create_breakpoint_group(&name)?;
+            println!("Created breakpoint group '{}' (disabled; add members with 'bpgroup add {} break <loc>')", name, name);
+        }
+        Some(Command::BpGroupAdd(name, member)) => handle_bpgroup_add(debugger, &name, member)?,
+        Some(Command::BpGroupEnable(name)) => handle_bpgroup_enable(debugger, &name)?,
+        Some(Command::BpGroupDisable(name)) => {
+            let removed = debugger.disable_breakpoint_group(&name)?;
+            println!("Disabled breakpoint group '{}' ({})", name, describe_group_activation(&removed));
+        }
+        Some(Command::BpGroupDelete(name)) => {
+            debugger.delete_breakpoint_group(&name)?;
+            println!("Deleted breakpoint group '{}'", name);
+        }
+        Some(Command::BpGroupList) => handle_bpgroup_list(debugger),
+        Some(Command::BpGroupSave(path)) => {
+            debugger.breakpoint_groups().save(std::path::Path::new(&path))?;
+            println!("Saved breakpoint groups to {}", path);
+        }
+        Some(Command::BpGroupLoad(path)) => {
+            let names = debugger.load_breakpoint_groups(std::path::Path::new(&path))?;
+            println!("Loaded {} breakpoint group(s) from {}: {}", names.len(), path, names.join(", "));
+        }
         Some(Command::Continue) => handle_continue(debugger)?,
         Some(Command::Step) => handle_step(debugger)?,
+        Some(Command::ReverseStepi(count)) => handle_reverse_stepi(debugger, count)?,
+        Some(Command::TraceStart(mode, capacity)) => handle_trace_start(debugger, mode, capacity),
+        Some(Command::TraceStop) => handle_trace_stop(debugger),
+        Some(Command::TraceShow(count)) => handle_trace_show(debugger, count)?,
+        Some(Command::TraceExport(path)) => handle_trace_export(debugger, &path)?,
+        Some(Command::TraceHwStart) => handle_trace_hw_start(debugger)?,
+        Some(Command::TraceHwStop) => handle_trace_hw_stop(debugger)?,
+        Some(Command::Ftrace(pattern)) => handle_ftrace(debugger, &pattern)?,
+        Some(Command::FtraceList) => handle_ftrace_list(debugger),
+        Some(Command::FtraceLog(count)) => print_ftrace_log(debugger, count),
+        Some(Command::FtraceClear) => {
+            debugger.clear_ftrace_log();
+            println!("Call log cleared");
+        }
+        Some(Command::FtraceStop(pattern)) => handle_ftrace_stop(debugger, pattern.as_deref())?,
+        Some(Command::ProfileStart(frequency)) => handle_profile_start(debugger, frequency)?,
+        Some(Command::ProfileStop) => handle_profile_stop(debugger)?,
+        Some(Command::ProfileReport) => handle_profile_report(debugger)?,
         Some(Command::Next) => handle_next(debugger)?,
         Some(Command::Finish) => handle_finish(debugger)?,
-        Some(Command::Backtrace) => handle_backtrace(debugger)?,
+        Some(Command::Backtrace) => handle_backtrace(debugger, false)?,
+        Some(Command::BacktraceFull) => handle_backtrace(debugger, true)?,
         Some(Command::Locals) => handle_locals(debugger)?,
+        Some(Command::InfoArgs) => handle_info_args(debugger)?,
         Some(Command::Print(expr)) => handle_print(debugger, &expr)?,
+        Some(Command::SetVariable(name, expr)) => handle_set_variable(debugger, &name, &expr)?,
+        Some(Command::PrintType(target)) => handle_ptype(debugger, &target)?,
+        Some(Command::WhatIs(target)) => handle_whatis(debugger, &target)?,
+        Some(Command::Examine(count, expr)) => handle_examine(debugger, count, &expr)?,
+        Some(Command::Display(expr)) => handle_display(debugger, expr.as_deref())?,
+        Some(Command::Undisplay(id)) => handle_undisplay(debugger, id)?,
+        Some(Command::InfoProc) => handle_info_proc(debugger)?,
+        Some(Command::MemProtect(addr, len, perms)) => handle_mem_protect(debugger, &addr, len, &perms)?,
+        Some(Command::Patch(addr, payload)) => handle_patch(debugger, &addr, &payload)?,
+        Some(Command::Unpatch(id)) => handle_unpatch(debugger, id)?,
+        Some(Command::InfoPatches) => handle_info_patches(debugger),
+        Some(Command::Watch(addr, len, kind)) => handle_watch(debugger, &addr, len, kind)?,
+        Some(Command::Unwatch(id)) => handle_unwatch(debugger, id)?,
+        Some(Command::InfoWatchpoints) => handle_info_watchpoints(debugger),
+        Some(Command::InfoAsyncRuntime) => handle_info_async_runtime(debugger)?,
+        Some(Command::InfoBinary) => handle_info_binary(debugger)?,
+        Some(Command::InfoVariants(type_name)) => handle_info_variants(debugger, &type_name)?,
+        Some(Command::SetQueryTimeout(secs)) => {
+            debugger.set_query_timeout(secs.map(std::time::Duration::from_secs));
+            match secs {
+                Some(secs) => println!("DWARF queries time out after {} s", secs),
+                None => println!("DWARF queries run without a time limit"),
+            }
+        }
+        Some(Command::SymbolFileFromMemory) => {
+            let count = debugger.load_symbols_from_memory()?;
+            println!("Loaded {} dynamic symbols from the running image", count);
+        }
+        Some(Command::SetAsyncSummary(enabled)) => {
+            debugger.set_async_summary(enabled);
+            println!("Async summary after each stop: {}", if enabled { "on" } else { "off" });
+        }
+        Some(Command::SetAsyncTrace(enabled)) => {
+            debugger.set_async_trace(enabled)?;
+            if enabled {
+                println!("Async trace mode: on (async breakpoints record events and keep running)");
+            } else {
+                println!("Async trace mode: off");
+            }
+        }
+        Some(Command::SetAsyncFastEntry(enabled)) => {
+            debugger.set_fast_async_entry(enabled);
+            if enabled {
+                println!("Async fast entry: on (parents come from the poll scope stack; backtraces only on mismatch)");
+            } else {
+                println!("Async fast entry: off");
+            }
+        }
+        Some(Command::SetAsyncNames(style)) => {
+            let names = kokia_core::FutureNames { style, ..debugger.future_names().clone() };
+            debugger.set_future_names(names);
+            println!("Future type names in async displays: {}", style);
+        }
+        Some(Command::SetAsyncNameGenerics(collapse)) => {
+            let names = kokia_core::FutureNames { collapse_generics: collapse, ..debugger.future_names().clone() };
+            debugger.set_future_names(names);
+            println!("Collapse generic arguments of future type names: {}", if collapse { "on" } else { "off" });
+        }
+        Some(Command::AsyncRawNames(cmd)) => {
+            let saved = debugger.future_names().clone();
+            debugger.set_future_names(kokia_core::FutureNames { style: kokia_core::NameStyle::Raw, ..saved.clone() });
+            let result = dispatch_command(debugger, &cmd);
+            debugger.set_future_names(saved);
+            result?
+        }
+        Some(Command::SetAsyncRetentionMaxTasks(max_tasks)) => {
+            let policy = RetentionPolicy { max_tasks, ..debugger.async_tracker().retention() };
+            set_async_retention(debugger, policy);
+        }
+        Some(Command::SetAsyncRetentionMaxAge(secs)) => {
+            let max_age = secs.map(std::time::Duration::from_secs);
+            let policy = RetentionPolicy { max_age, ..debugger.async_tracker().retention() };
+            set_async_retention(debugger, policy);
+        }
+        Some(Command::AsyncCatchPoison) => {
+            debugger.catch_poison()?;
+            println!("Catchpoint (poison): stop when a panic during poll leaves a task in the Panicked state");
+        }
+        Some(Command::AsyncExportStart(config)) => {
+            debugger.start_event_export(config.clone())?;
+            println!("Exporting async events: {}", config);
+        }
+        Some(Command::AsyncExportStop) => match debugger.stop_event_export() {
+            Some(stats) => println!("Stopped exporting async events ({})", format_export_stats(&stats)),
+            None => println!("Async events are not being exported"),
+        },
+        Some(Command::AsyncExportStatus) => match debugger.event_export() {
+            Some((config, stats)) => {
+                println!("Exporting async events: {}", config);
+                println!("  {}", format_export_stats(&stats));
+                if let Some(error) = &stats.last_error {
+                    println!("  Last error: {}", error);
+                }
+            }
+            None => println!("Async events are not being exported (use 'async export webhook|otlp <url>')"),
+        },
+        Some(Command::AsyncStats) => handle_async_stats(debugger),
+        Some(Command::AsyncClear) => {
+            let count = debugger.async_tracker().all_tasks().len();
+            debugger.clear_async_tracking();
+            println!("Cleared {} tracked async task(s)", count);
+        }
         Some(Command::AsyncBacktrace) => handle_async_backtrace(debugger)?,
+        Some(Command::AsyncBacktraceAll) => handle_async_backtrace_all(debugger)?,
         Some(Command::AsyncTasks) => handle_async_tasks(debugger)?,
-        Some(Command::AsyncEdges) => handle_async_edges(debugger)?,
-        Some(Command::AsyncEnable) => handle_async_enable(debugger)?,
-        Some(Command::AsyncLocals) => {
-            handle_async_locals(debugger)?;
+        Some(Command::AsyncMemsize) => handle_async_memsize(debugger)?,
+        Some(Command::AsyncLifetimes) => handle_async_lifetimes(debugger),
+        Some(Command::AsyncFind(query)) => handle_async_find(debugger, &query)?,
+        Some(Command::AsyncAssert(assertion)) => handle_async_assert(debugger, assertion),
+        Some(Command::AsyncEdges(filter)) => handle_async_edges(debugger, &filter)?,
+        Some(Command::AsyncTree(filter)) => handle_async_tree(debugger, &filter),
+        Some(Command::AsyncEnable(filter)) => handle_async_enable(debugger, filter.as_deref())?,
+        Some(Command::AsyncDisable(pattern)) => handle_async_disable(debugger, &pattern)?,
+        Some(Command::AsyncDisableAll(keep_data)) => handle_async_disable_all(debugger, keep_data)?,
+        Some(Command::AsyncSample(setting)) => handle_async_sample(debugger, setting)?,
+        Some(Command::AsyncUprobeStart(filter)) => {
+            let filter = filter.as_deref().map(SymbolPattern::parse).transpose()?;
+            let count = debugger.start_uprobe_tracking(filter.as_ref())?;
+            println!("Installed uprobes on {} async function(s) (entry + return)", count);
+            println!("Use 'async uprobe collect <secs>' to run the program and gather events.");
+        }
+        Some(Command::AsyncUprobeCollect(secs)) => handle_async_uprobe_collect(debugger, secs)?,
+        Some(Command::AsyncUprobeStop) => {
+            if debugger.stop_uprobe_tracking() {
+                println!("Removed uprobes");
+            } else {
+                println!("uprobe tracking is not active");
+            }
+        }
+        Some(Command::GenerateCore(path)) => handle_generate_core(debugger, path.as_deref())?,
+        Some(Command::Attach { target, wait }) => handle_attach(debugger, &target, wait)?,
+        Some(Command::ExportState(path)) => {
+            let state = debugger.export_state()?;
+            state.save(std::path::Path::new(&path))?;
+            println!(
+                "Saved state to {} ({} breakpoint(s), {} task(s), {} module(s))",
+                path, state.breakpoints.len(), state.tasks.len(), state.modules.len()
+            );
+        }
+        Some(Command::ImportState(path)) => {
+            let state = kokia_core::DebuggerState::load(std::path::Path::new(&path))?;
+            println!("{}", debugger.import_state(&state)?);
+        }
+        Some(Command::CatchErr(function)) => {
+            let catchpoint = debugger.catch_err(&function)?;
+            println!(
+                "Catchpoint (err) on {}: {} return site(s), returns {}",
+                catchpoint.function, catchpoint.breakpoints.len(), catchpoint.layout.name
+            );
+        }
+        Some(Command::SetLogging(Some(path))) => {
+            let format = session_log::start(&path)?;
+            let format = match format {
+                session_log::LogFormat::Text => "text",
+                session_log::LogFormat::Jsonl => "JSONL",
+            };
+            println!("Logging commands, stops and output to {} ({})", path, format);
+        }
+        Some(Command::SetLogging(None)) => match session_log::stop() {
+            Some(path) => println!("Stopped logging to {}", path.display()),
+            None => println!("Logging is not active"),
+        },
+        Some(Command::SetStepFilter(prefixes)) => {
+            let filter = prefixes.map(StepFilter::with_prefixes).unwrap_or_default();
+            if filter.is_empty() {
+                println!("Step filter: off");
+            } else {
+                println!("Step filter: {}", filter.prefixes().join(" "));
+            }
+            debugger.set_step_filter(filter);
+        }
+        Some(Command::CatchPanic) => {
+            let id = debugger.catch_panic()?;
+            println!("Catchpoint {} (panic)", id);
+        }
+        Some(Command::SetCrashReport(enabled)) => {
+            debugger.set_crash_report(enabled);
+            println!("Crash report on fatal signals: {}", if enabled { "on" } else { "off" });
         }
+        Some(Command::ThreadApplyAll(cmd)) => handle_thread_apply(debugger, ThreadSelection::All, &cmd)?,
+        Some(Command::ThreadApply(n, cmd)) => handle_thread_apply(debugger, ThreadSelection::Number(n), &cmd)?,
+        Some(Command::ThreadApplyMatching(pattern, cmd)) => {
+            let pattern = SymbolPattern::parse(&pattern)?;
+            handle_thread_apply(debugger, ThreadSelection::Matching(pattern), &cmd)?
+        }
+        Some(Command::InfoThreads(pattern)) => {
+            let pattern = pattern.as_deref().map(SymbolPattern::parse).transpose()?;
+            handle_info_threads(debugger, pattern.as_ref())?
+        }
+        Some(Command::AddInferior { binary, args }) => handle_add_inferior(debugger, binary.as_deref(), &args)?,
+        Some(Command::Inferior(id)) => {
+            debugger.select_inferior(id)?;
+            println!("[Switching to {}]", inferior_label(debugger, id));
+        }
+        Some(Command::InferiorApplyAll(cmd)) => handle_inferior_apply_all(debugger, &cmd),
+        Some(Command::InfoInferiors) => handle_info_inferiors(debugger),
+        Some(Command::AsyncLocals(task)) => handle_async_locals(debugger, task)?,
         None => handle_custom_command(debugger, line)?,
         _ => println!("Command not yet implemented: {}", line),
     }
@@ -208,13 +923,35 @@ fn handle_command(debugger: &mut Debugger, line: &str) -> Result<()> {
 }
 
 /// Quitコマンドを処理する
-fn handle_quit() {
+fn handle_quit(debugger: &mut Debugger) {
+    print_exit_summary(debugger);
+    // exit ではデストラクタが走らないので、送り残した async イベントはここで送る
+    debugger.stop_event_export();
     println!("Goodbye!");
+    session_log::stop();
     std::process::exit(0);
 }
 
-/// Breakコマンドを処理する
-fn handle_break(debugger: &mut Debugger, loc: &str) -> Result<()> {
+/// attach コマンドを処理する（バイナリ未読み込みなら、アタッチしたプロセスの実行ファイルを読む）
+fn handle_attach(debugger: &mut Debugger, target: &str, wait: bool) -> Result<()> {
+    let pid = if wait {
+        wait_and_attach(debugger, target)?
+    } else {
+        debugger.attach_target(target)?
+    };
+    println!("Attached to process {}", pid);
+
+    if !debugger.has_binary() {
+        let exe = std::fs::read_link(format!("/proc/{}/exe", pid))?;
+        debugger.load_binary(&exe)?;
+        println!("Loaded DWARF information from {}", exe.display());
+    }
+    warn_build_id_mismatch(debugger);
+    Ok(())
+}
+
+/// Breakコマンドを処理する（設定したブレークポイントの ID を返す）
+fn handle_break(debugger: &mut Debugger, loc: &str) -> Result<Option<BreakpointId>> {
     use kokia_core::parse::parse_address;
 
     // まずアドレスとして解釈を試みる
@@ -230,7 +967,7 @@ fn handle_break(debugger: &mut Debugger, loc: &str) -> Result<()> {
             }
         }
 
-        return Ok(());
+        return Ok(Some(bp_id));
     }
 
     // ファイル名:行番号の形式かチェック（例: "main.rs:30"）
@@ -257,16 +994,27 @@ fn handle_break(debugger: &mut Debugger, loc: &str) -> Result<()> {
                             println!("  ({}:{})", full_file, actual_line);
                         }
                     }
-                    return Ok(());
+                    return Ok(Some(bp_id));
                 }
                 Err(e) => {
                     println!("Error: {}", e);
-                    return Ok(());
+                    return Ok(None);
                 }
             }
         }
     }
 
+    // アドレス式として解釈（`*$rsp`, `$pc+0x12`, `symbol+0x40` など）
+    if let Some(expression) = parse_address_expression(loc) {
+        let addr = kokia_core::ExpressionEvaluator::new(debugger).evaluate_address(&expression)?;
+        let bp_id = debugger.set_breakpoint(addr)?;
+        println!("Breakpoint {} set at 0x{:x}", bp_id, addr);
+        if let Some(symbol) = debugger.reverse_resolve(addr) {
+            println!("  at {}", symbol.demangled_name);
+        }
+        return Ok(Some(bp_id));
+    }
+
     // シンボル名として解釈（PIEの場合のみベースアドレスを加算）
     // まずシンボルを検索してデマングル名を取得
     let symbols = debugger.find_symbols(loc);
@@ -292,71 +1040,885 @@ fn handle_break(debugger: &mut Debugger, loc: &str) -> Result<()> {
                 println!(" at symbol '{}'", loc);
             }
 
-            Ok(())
+            Ok(Some(bp_id))
         }
         Err(e) => {
             println!("Error: {}", e);
-            Ok(())
+            Ok(None)
         }
     }
 }
 
-/// Continueコマンドを処理する
-fn handle_continue(debugger: &mut Debugger) -> Result<()> {
-    println!("Continuing execution...");
-
-    let stop_reason = debugger.continue_and_wait()?;
-
-    match stop_reason {
-        StopReason::Breakpoint => {
-            println!();
-            println!("Breakpoint hit!");
-
-            // PCを取得
-            let pc = debugger.get_pc()?;
-            println!("Stopped at 0x{:x}", pc);
+/// グループで置いたものの数の表示（`3 breakpoint(s), 2 async function(s)`）
+fn describe_group_activation(placed: &kokia_core::GroupActivation) -> String {
+    let mut parts = vec![format!("{} breakpoint(s)", placed.breakpoints.len())];
+    if !placed.async_entries.is_empty() {
+        parts.push(format!("{} async function(s)", placed.async_entries.len()));
+    }
+    parts.join(", ")
+}
 
-            // シンボルを逆引き（デマングル済み）
-            if let Some(symbol) = debugger.reverse_resolve(pc) {
-                println!("In function: {}", symbol.demangled_name);
-                if symbol.size > 0 {
-                    println!("Function address: 0x{:x}, size: {}", symbol.address, symbol.size);
-                }
+/// bpgroup add を処理する
+fn handle_bpgroup_add(debugger: &mut Debugger, name: &str, member: kokia_core::GroupMember) -> Result<()> {
+    let description = member.to_string();
+    let placed = debugger.add_to_breakpoint_group(name, member)?;
+    if debugger.breakpoint_groups().get(name)?.is_enabled() {
+        println!("Added '{}' to breakpoint group '{}' ({} set)", description, name, describe_group_activation(&placed));
+    } else {
+        println!("Added '{}' to breakpoint group '{}' (set when the group is enabled)", description, name);
+    }
+    Ok(())
+}
 
-                // ソースファイルと行番号を表示
-                if let Some((file, line)) = debugger.get_line_info(pc) {
-                    println!("  at {}:{}", file, line);
-                }
-            }
-        }
-        StopReason::Step => {
-            println!();
-            println!("Stepped (unexpected during continue)");
+/// bpgroup enable を処理する
+fn handle_bpgroup_enable(debugger: &mut Debugger, name: &str) -> Result<()> {
+    let placed = debugger.enable_breakpoint_group(name)?.clone();
+    println!("Enabled breakpoint group '{}' ({} set)", name, describe_group_activation(&placed));
+    for &id in placed.breakpoints.iter().take(10) {
+        let Some(bp) = debugger.breakpoints().find(|bp| bp.id == id) else {
+            continue;
+        };
+        match debugger.reverse_resolve(bp.address) {
+            Some(symbol) => println!("  Breakpoint {} at 0x{:x} in {}", id, bp.address, symbol.demangled_name),
+            None => println!("  Breakpoint {} at 0x{:x}", id, bp.address),
         }
-        StopReason::Signal(signal) => {
-            println!();
-            println!("Received signal: {:?}", signal);
+    }
+    if placed.breakpoints.len() > 10 {
+        println!("  ... and {} more", placed.breakpoints.len() - 10);
+    }
+    Ok(())
+}
+
+/// bpgroup list を処理する
+fn handle_bpgroup_list(debugger: &Debugger) {
+    let groups = debugger.breakpoint_groups();
+    if groups.is_empty() {
+        println!("No breakpoint groups (create one with 'bpgroup create <name>')");
+        return;
+    }
+    for (name, group) in groups.iter() {
+        match &group.active {
+            Some(placed) => println!("{} [enabled: {}]", name, describe_group_activation(placed)),
+            None => println!("{} [disabled]", name),
         }
-        StopReason::Exited(code) => {
-            println!();
-            println!("Process exited with code {}", code);
+        if group.members.is_empty() {
+            println!("  (empty)");
         }
-        StopReason::Other => {
-            println!();
-            println!("Process stopped (unknown reason)");
+        for member in &group.members {
+            println!("  {}", member);
         }
     }
+}
 
+/// logpoint コマンドを処理する（ブレークポイントを置いてからログポイントにする）
+fn handle_logpoint(debugger: &mut Debugger, loc: &str, format: &str) -> Result<()> {
+    let template = kokia_core::LogTemplate::parse(format)?;
+    let Some(id) = handle_break(debugger, loc)? else {
+        return Ok(());
+    };
+    if let Err(e) = debugger.set_logpoint(id, template) {
+        debugger.remove_breakpoint(id)?;
+        return Err(e);
+    }
+    println!("  logs \"{}\" and continues", format);
     Ok(())
 }
 
-/// Stepコマンドを処理する
-fn handle_step(debugger: &mut Debugger) -> Result<()> {
+/// `break ... thread N task #M` の限定をブレークポイントに付ける
+fn restrict_breakpoint(debugger: &mut Debugger, id: usize, context: BreakContext) -> Result<()> {
+    let thread = match context.thread {
+        Some(n) => {
+            let threads = numbered_threads(debugger, None)?;
+            let tid = threads.iter().find(|(number, _)| *number == n).map(|(_, tid)| *tid)
+                .ok_or_else(|| anyhow::anyhow!("No thread {} (see 'info threads')", n))?;
+            Some(tid)
+        }
+        None => None,
+    };
+    if let Err(e) = debugger.restrict_breakpoint(id, thread, context.task) {
+        // 限定できないブレークポイントは、意図しない場所で止まらないように残さない
+        debugger.remove_breakpoint(id)?;
+        return Err(e);
+    }
+    if let Some(tid) = thread {
+        println!("  only in thread {}", thread_label(debugger, tid));
+    }
+    if let Some(task) = context.task {
+        println!("  only while task {} is being polled", task);
+    }
+    Ok(())
+}
+
+/// 文字列がアドレス計算式（`*`/`$`/加減算を含む）であればパースして返す
+///
+/// 単純なシンボル名や数値は None を返し、従来の解釈に任せる。
+fn parse_address_expression(input: &str) -> Option<kokia_core::Expression> {
+    use kokia_core::Expression;
+
+    match kokia_core::parse_expression(input).ok()? {
+        e @ (Expression::Deref(_) | Expression::Register(_) | Expression::Binary { .. }) => Some(e),
+        _ => None,
+    }
+}
+
+/// アドレスをシンボル+オフセット形式で表示用に整形する（例: `<main+16>`）
+fn format_symbol_offset(debugger: &Debugger, addr: u64) -> String {
+    let Some(symbol) = debugger.reverse_resolve(addr) else {
+        return String::new();
+    };
+    let Ok(start) = debugger.symbol_address(&symbol.name) else {
+        return String::new();
+    };
+
+    // サイズ不明のシンボルは先頭アドレスと一致する場合のみ表示する
+    match addr.checked_sub(start) {
+        Some(0) => format!(" <{}>", symbol.demangled_name),
+        Some(offset) if offset < symbol.size => format!(" <{}+{}>", symbol.demangled_name, offset),
+        _ => String::new(),
+    }
+}
+
+/// Examineコマンドを処理する（8バイト単位でメモリを表示）
+fn handle_examine(debugger: &mut Debugger, count: usize, expr: &str) -> Result<()> {
+    use kokia_core::{parse_expression, ExpressionEvaluator};
+
+    let expression = parse_expression(expr)?;
+    let addr = ExpressionEvaluator::new(debugger).evaluate_address(&expression)?;
+    let memory = debugger.memory()
+        .ok_or_else(|| anyhow::anyhow!("Cannot read memory: process not running"))?;
+
+    for i in 0..count as u64 {
+        let a = addr.wrapping_add(i * 8);
+        match memory.read_u64(a as usize) {
+            Ok(value) => println!("0x{:016x}{}:\t0x{:016x}", a, format_symbol_offset(debugger, a), value),
+            Err(e) => {
+                println!("0x{:016x}: <error: {}>", a, e);
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Continueコマンドを処理する
+fn handle_continue(debugger: &mut Debugger) -> Result<()> {
+    println!("Continuing execution...");
+
+    // 命令トレース中は1命令ずつ進むので、Ctrl-C で kokia ごと終わらずに止められるようにする
+    let traced_before = debugger.instruction_trace().map(|trace| trace.executed());
+    let calls_before = debugger.function_trace().recorded();
+    let interrupt = debugger.is_tracing_instructions().then(InterruptGuard::install);
+    let stop_reason = debugger.continue_and_wait();
+    drop(interrupt);
+    let stop_reason = stop_reason?;
+
+    if debugger.async_trace_enabled() {
+        println!("Traced {} async event(s)", debugger.traced_async_events());
+    }
+    if let (Some(before), Some(trace)) = (traced_before, debugger.instruction_trace().filter(|_| debugger.is_tracing_instructions())) {
+        println!("Traced {} instruction(s)", trace.executed() - before);
+    }
+    let calls = debugger.function_trace().recorded() - calls_before;
+    if calls > 0 {
+        print_ftrace_log(debugger, (calls as usize).min(FTRACE_PRINT_LIMIT));
+    }
+
+    let event = debugger.last_stop().cloned();
+    match stop_reason {
+        StopReason::Breakpoint => {
+            println!();
+            if let Some(panic) = event.as_ref().and_then(|e| e.panic.as_ref()) {
+                print_caught_panic(debugger, event.as_ref().and_then(|e| e.breakpoint_id()), panic);
+                run_stop_hooks(debugger);
+                return Ok(());
+            }
+            if let Some(poisoned) = event.as_ref().and_then(|e| e.poisoned.as_ref()) {
+                print_poisoned_task(debugger, poisoned);
+                run_stop_hooks(debugger);
+                return Ok(());
+            }
+            if let Some(err) = event.as_ref().and_then(|e| e.err_return.as_ref()) {
+                print_err_return(debugger, err);
+            } else {
+                match event.as_ref().and_then(|e| e.breakpoint) {
+                    Some((id, bp_type)) => println!("Breakpoint {} hit! ({:?})", id, bp_type),
+                    None => println!("Breakpoint hit!"),
+                }
+            }
+
+            // PCを取得
+            let pc = debugger.get_pc()?;
+            match event.as_ref().and_then(|e| e.tid) {
+                Some(tid) => println!("Stopped at 0x{:x} (thread {})", pc, thread_label(debugger, tid)),
+                None => println!("Stopped at 0x{:x}", pc),
+            }
+            if let Some(task) = event.as_ref().and_then(|e| e.task) {
+                println!("Async task: {}", task_label(debugger, task));
+            }
+            if let Some(value) = event.as_ref().and_then(|e| e.return_value.as_ref()) {
+                println!("Poll returned: {}", value);
+            }
+
+            // シンボルを逆引き（デマングル済み）
+            if let Some(symbol) = debugger.reverse_resolve(pc) {
+                println!("In function: {}", symbol.demangled_name);
+                if symbol.size > 0 {
+                    println!("Function address: 0x{:x}, size: {}", symbol.address, symbol.size);
+                }
+
+                // ソースファイルと行番号を表示
+                if let Some((file, line)) = debugger.get_line_info(pc) {
+                    println!("  at {}:{}", file, line);
+                }
+                // 関数の入口で止まったなら引数も表示する
+                if let Ok(Some(args)) = debugger.entry_arguments() {
+                    if !args.is_empty() {
+                        let args: Vec<String> = args.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+                        println!("Arguments: {}", args.join(", "));
+                    }
+                }
+            }
+        }
+        StopReason::Watchpoint => {
+            println!();
+            print_watchpoint_hit(debugger);
+
+            let pc = debugger.get_pc()?;
+            println!("Stopped at 0x{:x}", pc);
+            if let Some(symbol) = debugger.reverse_resolve(pc) {
+                println!("In function: {}", symbol.demangled_name);
+                if let Some((file, line)) = debugger.get_line_info(pc) {
+                    println!("  at {}:{}", file, line);
+                }
+            }
+        }
+        StopReason::Step => {
+            println!();
+            println!("Stepped (unexpected during continue)");
+        }
+        StopReason::Signal(signal) => {
+            println!();
+            println!("Received signal: {:?}", signal);
+            print_signal_detail(event.as_ref());
+            if event.as_ref().is_some_and(|e| e.is_crash()) && debugger.crash_report_enabled() {
+                print_crash_report(debugger);
+            } else if let Some(pc) = event.as_ref().and_then(|e| e.pc) {
+                println!("Stopped at 0x{:x}", pc);
+            }
+        }
+        StopReason::Exited(code) => {
+            println!();
+            println!("Process exited with code {}", code);
+        }
+        StopReason::Killed(signal) => {
+            println!();
+            println!("Process killed by {:?}", signal);
+        }
+        StopReason::Other => {
+            println!();
+            println!("Process stopped (unknown reason)");
+        }
+    }
+
+    run_stop_hooks(debugger);
+
+    Ok(())
+}
+
+/// `catch err` のキャッチポイントで止まったときに、返そうとしている Err の中身を表示する
+fn print_err_return(debugger: &Debugger, err: &kokia_core::ErrReturn) {
+    use kokia_dwarf::ValueFormatter;
+
+    println!("Catchpoint (err): {} is returning Err", err.function);
+    let value = match (err.payload_address, debugger.memory()) {
+        (Some(address), Some(memory)) => ValueFormatter::new(memory)
+            .format_by_type(address, &err.payload_type)
+            .unwrap_or_else(|_| format!("<at 0x{:x}>", address)),
+        // レジスタで返された値は整数として表示する
+        _ if err.payload.len() <= 8 => {
+            let mut raw = [0u8; 8];
+            raw[..err.payload.len()].copy_from_slice(&err.payload);
+            let value = u64::from_le_bytes(raw);
+            format!("{} (0x{:x})", value, value)
+        }
+        _ => err.payload.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" "),
+    };
+    println!("  Err({}) = {}", err.payload_type, value);
+}
+
+/// パニックのキャッチポイントで止まったときに、メッセージと OS / async 両方のバックトレースを表示する
+fn print_caught_panic(debugger: &mut Debugger, id: Option<kokia_core::BreakpointId>, panic: &kokia_core::PanicReport) {
+    match id {
+        Some(id) => println!("Catchpoint {} (panic)", id),
+        None => println!("Caught panic"),
+    }
+    match &panic.message {
+        Some(message) => println!("  panicked: {}", message),
+        None => println!("  panicked: <non-string payload>"),
+    }
+    if let Some(location) = &panic.location {
+        println!("  at {}", location);
+    }
+    if let Some(tid) = debugger.current_thread() {
+        println!("  thread {}", thread_label(debugger, tid));
+    }
+
+    println!();
+    if let Err(e) = handle_backtrace(debugger, false) {
+        println!("Backtrace: <error: {}>", e);
+    }
+    println!();
+    if let Err(e) = handle_async_backtrace(debugger) {
+        println!("Async backtrace: <error: {}>", e);
+    }
+}
+
+/// `async catch poison` で止まったときに、Panicked になったタスクと巻き戻った await チェーンを表示する
+fn print_poisoned_task(debugger: &Debugger, poisoned: &kokia_core::PoisonedTask) {
+    let poisoned_labels: Vec<String> = poisoned.poisoned.iter().map(|task| task_label(debugger, *task)).collect();
+    println!("Catchpoint (poison): task {} panicked during poll and is now Panicked", task_label(debugger, poisoned.task));
+    if let Some(location) = &poisoned.location {
+        println!("  panicked at {}", location);
+    }
+    if poisoned.poisoned.len() > 1 {
+        println!("  poisoned: {}", poisoned_labels.join(", "));
+    }
+    if let Some(tid) = debugger.current_thread() {
+        println!("  caught on thread {}", thread_label(debugger, tid));
+    }
+
+    println!();
+    println!("Unwound await chain (root first):");
+    for (i, task_id) in poisoned.chain.iter().enumerate() {
+        match debugger.async_tracker().get_task(*task_id) {
+            Some(task) => {
+                print!("  #{:<3} ", i);
+                format_task_info(debugger, task, "     ", false);
+            }
+            None => println!("  #{} Task 0x{:x}", i, task_id),
+        }
+    }
+}
+
+/// 致命的なシグナルで止まったときのクラッシュレポートを表示する
+///
+/// 各項目は取れた分だけ表示し、1つが失敗しても残りは続けます。
+fn print_crash_report(debugger: &mut Debugger) {
+    println!();
+    println!("=== Crash report ===");
+
+    match debugger.general_registers() {
+        Ok(regs) => {
+            println!("Registers:");
+            let values = [
+                ("rax", regs.rax), ("rbx", regs.rbx), ("rcx", regs.rcx), ("rdx", regs.rdx),
+                ("rsi", regs.rsi), ("rdi", regs.rdi), ("rbp", regs.rbp), ("rsp", regs.rsp),
+                ("r8", regs.r8), ("r9", regs.r9), ("r10", regs.r10), ("r11", regs.r11),
+                ("r12", regs.r12), ("r13", regs.r13), ("r14", regs.r14), ("r15", regs.r15),
+                ("rip", regs.rip), ("eflags", regs.eflags), ("fs_base", regs.fs_base), ("gs_base", regs.gs_base),
+            ];
+            for row in values.chunks(4) {
+                let cells: Vec<String> = row.iter().map(|(name, value)| format!("{:>7} 0x{:016x}", name, value)).collect();
+                println!("  {}", cells.join("  "));
+            }
+        }
+        Err(e) => println!("Registers: <error: {}>", e),
+    }
+
+    println!();
+    if let Ok(pc) = debugger.get_pc() {
+        match debugger.disassemble_around(pc, 5, 4) {
+            Ok(lines) => {
+                println!("Disassembly around 0x{:x}{}:", pc, format_symbol_offset(debugger, pc));
+                for line in lines {
+                    let marker = if line.address == pc { "=>" } else { "  " };
+                    println!("  {} 0x{:x}:  {}", marker, line.address, line.text);
+                }
+            }
+            Err(e) => println!("Disassembly: <error: {}>", e),
+        }
+    }
+
+    println!();
+    if let Err(e) = handle_backtrace(debugger, false) {
+        println!("Backtrace: <error: {}>", e);
+    }
+
+    if !debugger.async_tracker().task_tracker().is_empty() {
+        println!();
+        println!("Async tasks:");
+        handle_async_tree(debugger, &EdgeFilter::default());
+    }
+
+    println!();
+    println!("Use 'gcore [path]' to write a core file, or 'set crash-report off' to skip this report.");
+}
+
+/// gcore コマンドを処理する
+fn handle_generate_core(debugger: &mut Debugger, path: Option<&str>) -> Result<()> {
+    let path = match path {
+        Some(path) => std::path::PathBuf::from(path),
+        None => {
+            let pid = debugger.pid().ok_or_else(|| anyhow::anyhow!("No process"))?;
+            std::path::PathBuf::from(format!("core.{}", pid))
+        }
+    };
+    let summary = debugger.write_core(&path)?;
+    print!(
+        "Saved core file {} ({} thread(s), {} segment(s), {} bytes)",
+        path.display(), summary.threads, summary.segments, summary.bytes
+    );
+    if summary.unreadable > 0 {
+        print!("; {} unreadable byte(s) written as zeros", summary.unreadable);
+    }
+    println!();
+    Ok(())
+}
+
+/// 停止イベントに記録されたシグナルの詳細を表示する
+fn print_signal_detail(event: Option<&kokia_core::StopEvent>) {
+    let Some(info) = event.and_then(|e| e.signal) else {
+        return;
+    };
+    print!("  si_code={}", info.code);
+    if let Some(addr) = info.fault_addr {
+        print!(", fault address 0x{:x}", addr);
+    }
+    if let Some(pid) = info.sender_pid {
+        print!(", sent by pid {}", pid);
+    }
+    println!();
+}
+
+/// Displayコマンドを処理する
+fn handle_display(debugger: &mut Debugger, expr: Option<&str>) -> Result<()> {
+    if let Some(expr) = expr {
+        let id = debugger.add_display(expr);
+        // 登録時に一度評価して表示する
+        if debugger.is_alive() {
+            print!("{}: ", id);
+            handle_print(debugger, expr)?;
+        }
+    } else {
+        show_displays(debugger);
+    }
+    Ok(())
+}
+
+/// Undisplayコマンドを処理する
+fn handle_undisplay(debugger: &mut Debugger, id: Option<usize>) -> Result<()> {
+    match id {
+        Some(id) => debugger.remove_display(id)?,
+        None => debugger.clear_displays(),
+    }
+    Ok(())
+}
+
+/// 保持ポリシーを設定して内容を表示する
+fn set_async_retention(debugger: &mut Debugger, policy: RetentionPolicy) {
+    let evicted = debugger.set_async_retention(policy);
+    let max_tasks = policy.max_tasks.map_or("unlimited".to_string(), |n| n.to_string());
+    let max_age = policy.max_age.map_or("unlimited".to_string(), |age| format!("{}s", age.as_secs()));
+    println!("Async retention: max-tasks {}, max-age {}", max_tasks, max_age);
+    if evicted > 0 {
+        println!("Evicted {} finished task(s)", evicted);
+    }
+}
+
+/// 停止時に実行する表示処理（display 式、async サマリー）
+fn run_stop_hooks(debugger: &mut Debugger) {
+    if let Some(event) = debugger.last_stop() {
+        session_log::stop_event(event);
+    }
+    show_displays(debugger);
+    if debugger.async_summary_enabled() {
+        print_async_summary(debugger);
+    }
+}
+
+/// async トラッカーの状態を1行で表示する
+fn print_async_summary(debugger: &Debugger) {
+    use kokia_core::{Tid, DEFAULT_STALL_THRESHOLD};
+
+    let tracker = debugger.async_tracker();
+    let stats = tracker.stats(debugger.running_time(), DEFAULT_STALL_THRESHOLD);
+
+    let current = debugger.current_thread()
+        .and_then(|tid| tracker.current_task(Tid(tid)))
+        .map(|task| {
+            let name = task.type_name.as_deref()
+                .map(|name| future_name(debugger, name))
+                .unwrap_or_else(|| format!("0x{:x}", task.id));
+            match task.state() {
+                Some(state) => format!("#{} {} state={}", task.handle, name, state),
+                None => format!("#{} {}", task.handle, name),
+            }
+        })
+        .unwrap_or_else(|| "none".to_string());
+
+    println!(
+        "[async] {} tasks, {} stalled >{}s, current: {}",
+        stats.live,
+        stats.stalled,
+        DEFAULT_STALL_THRESHOLD.as_secs(),
+        current
+    );
+}
+
+/// 登録済みの自動表示式を評価して表示する
+fn show_displays(debugger: &mut Debugger) {
+    if !debugger.is_alive() {
+        return;
+    }
+    let entries = debugger.displays().to_vec();
+    for entry in entries {
+        print!("{}: ", entry.id);
+        if let Err(e) = handle_print(debugger, &entry.expr) {
+            println!("{} = <error: {}>", entry.expr, e);
+        }
+    }
+}
+
+/// mem protect コマンドを処理する
+fn handle_mem_protect(debugger: &mut Debugger, addr: &str, len: u64, perms: &str) -> Result<()> {
+    use kokia_core::{parse_expression, ExpressionEvaluator, Protection};
+
+    let prot = Protection::parse(perms)?;
+    let addr = ExpressionEvaluator::new(debugger).evaluate_address(&parse_expression(addr)?)?;
+    let (start, len) = debugger.set_memory_protection(addr, len, prot)?;
+    println!("Changed protection of 0x{:x}-0x{:x} to {}", start, start + len, prot);
+    Ok(())
+}
+
+/// ptype コマンドを処理する
+///
+/// まず型名として検索し、見つからなければ式として評価してその型を表示する。
+fn handle_ptype(debugger: &mut Debugger, target: &str) -> Result<()> {
+    use kokia_core::{parse_expression, ExpressionEvaluator};
+    use kokia_dwarf::type_index::DEFAULT_EXPAND_DEPTH;
+
+    if let Some(layout) = debugger.type_layout(target, DEFAULT_EXPAND_DEPTH)? {
+        println!("type = {}", layout);
+        return Ok(());
+    }
+
+    let type_name = match parse_expression(target)
+        .and_then(|expr| ExpressionEvaluator::new(debugger).evaluate(&expr))
+    {
+        Ok(result) => result.type_name,
+        Err(e) => {
+            println!("No type or variable named '{}': {}", target, e);
+            return Ok(());
+        }
+    };
+    match debugger.type_layout(&type_name, DEFAULT_EXPAND_DEPTH)? {
+        Some(layout) => println!("type = {}", layout),
+        None => println!("type = {}", type_name),
+    }
+    Ok(())
+}
+
+/// whatis コマンドを処理する
+///
+/// 式として評価できればその宣言型を、できなければ型名として解決した名前を表示する。
+/// 構造は展開しないため、型索引は式の評価に失敗したときだけ使われる。
+fn handle_whatis(debugger: &mut Debugger, target: &str) -> Result<()> {
+    use kokia_core::{parse_expression, ExpressionEvaluator};
+
+    let evaluated = parse_expression(target)
+        .and_then(|expr| ExpressionEvaluator::new(debugger).evaluate(&expr));
+    match evaluated {
+        Ok(result) => println!("type = {}", result.type_name),
+        Err(e) => match debugger.resolve_type_name(target)? {
+            Some(name) => println!("type = {}", name),
+            None => println!("No type or variable named '{}': {}", target, e),
+        },
+    }
+    Ok(())
+}
+
+/// info variants コマンドを処理する
+///
+/// メモリダンプ中の生の discriminant 値を読み解けるよう、値・variant 名・suspend point の行を並べる。
+fn handle_info_variants(debugger: &mut Debugger, type_name: &str) -> Result<()> {
+    let Some(layout) = debugger.type_layout(type_name, 0)? else {
+        println!("No type named '{}'", type_name);
+        return Ok(());
+    };
+    if layout.variants.is_empty() {
+        println!("{} has no variants", layout.name);
+        return Ok(());
+    }
+
+    match &layout.discriminant {
+        Some(discr) => println!(
+            "{}: discriminant {} ({}) at offset 0x{:x}",
+            layout.name,
+            discr.name,
+            discr.type_name,
+            discr.offset.unwrap_or(0)
+        ),
+        None => println!("{}:", layout.name),
+    }
+    for variant in &layout.variants {
+        let discr = variant.discriminant.map_or("_".to_string(), |d| d.to_string());
+        let location = match (&variant.decl_file, variant.decl_line) {
+            (Some(file), Some(line)) => format!("{}:{}", file, line),
+            (None, Some(line)) => format!("line {}", line),
+            _ => String::new(),
+        };
+        println!("  {:>4}  {:<12} {}", discr, variant.name, location);
+    }
+    Ok(())
+}
+
+/// patch コマンドを処理する
+fn handle_patch(debugger: &mut Debugger, addr: &str, payload: &str) -> Result<()> {
+    use kokia_core::{parse_expression, ExpressionEvaluator};
+    use kokia_core::patch::{assemble, parse_hex_bytes};
+
+    let addr = ExpressionEvaluator::new(debugger).evaluate_address(&parse_expression(addr)?)?;
+    let bytes = match payload.strip_prefix("asm ") {
+        Some(source) => assemble(source, addr, |target| {
+            ExpressionEvaluator::new(debugger).evaluate_address(&parse_expression(target)?)
+        })?,
+        None => parse_hex_bytes(payload)?,
+    };
+
+    let id = debugger.patch(addr, &bytes)?;
+    println!("Patch {} at 0x{:x}{}: {} byte(s) written", id, addr, format_symbol_offset(debugger, addr), bytes.len());
+    Ok(())
+}
+
+/// unpatch コマンドを処理する
+fn handle_unpatch(debugger: &mut Debugger, id: Option<usize>) -> Result<()> {
+    let ids: Vec<usize> = match id {
+        Some(id) => vec![id],
+        // 新しいものから順に戻す
+        None => debugger.patches().iter().rev().map(|p| p.id).collect(),
+    };
+    if ids.is_empty() {
+        println!("No patches applied.");
+    }
+    for id in ids {
+        let patch = debugger.unpatch(id)?;
+        println!("Patch {} at 0x{:x} reverted ({} byte(s) restored)", patch.id, patch.address, patch.original.len());
+    }
+    Ok(())
+}
+
+/// info patches コマンドを処理する
+fn handle_info_patches(debugger: &Debugger) {
+    let patches = debugger.patches();
+    if patches.is_empty() {
+        println!("No patches applied.");
+        return;
+    }
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+    for patch in patches {
+        println!("#{} 0x{:x}{}", patch.id, patch.address, format_symbol_offset(debugger, patch.address));
+        println!("    original: {}", hex(&patch.original));
+        println!("    patched:  {}", hex(&patch.patched));
+    }
+}
+
+/// watch / awatch コマンドを処理する
+fn handle_watch(debugger: &mut Debugger, addr: &str, len: usize, kind: kokia_core::WatchKind) -> Result<()> {
+    use kokia_core::{parse_expression, ExpressionEvaluator};
+
+    let addr = ExpressionEvaluator::new(debugger).evaluate_address(&parse_expression(addr)?)?;
+    let id = debugger.watch(addr, len, kind)?;
+    println!("Hardware watchpoint {} ({:?}): 0x{:x}{}, {} byte(s)", id, kind, addr, format_symbol_offset(debugger, addr), len);
+    Ok(())
+}
+
+/// unwatch コマンドを処理する
+fn handle_unwatch(debugger: &mut Debugger, id: Option<usize>) -> Result<()> {
+    let ids: Vec<usize> = match id {
+        Some(id) => vec![id],
+        None => debugger.watchpoints().map(|w| w.id).collect(),
+    };
+    if ids.is_empty() {
+        println!("No watchpoints.");
+    }
+    for id in ids {
+        let watchpoint = debugger.unwatch(id)?;
+        println!("Watchpoint {} at 0x{:x} deleted", watchpoint.id, watchpoint.address);
+    }
+    Ok(())
+}
+
+/// info watchpoints コマンドを処理する
+fn handle_info_watchpoints(debugger: &Debugger) {
+    let mut any = false;
+    for watchpoint in debugger.watchpoints() {
+        any = true;
+        println!(
+            "#{} {:?} 0x{:x}{} len {} (DR{}), hit {} time(s), value = {}",
+            watchpoint.id,
+            watchpoint.kind,
+            watchpoint.address,
+            format_symbol_offset(debugger, watchpoint.address),
+            watchpoint.len,
+            watchpoint.slot,
+            watchpoint.hits,
+            format_watched_value(&watchpoint.value)
+        );
+    }
+    if !any {
+        println!("No watchpoints.");
+    }
+}
+
+/// info async-runtime コマンドを処理する
+fn handle_info_async_runtime(debugger: &Debugger) -> Result<()> {
+    let runtimes = debugger.async_runtimes()?;
+    if runtimes.is_empty() {
+        println!("No async runtime found in the binary's symbols.");
+        return Ok(());
+    }
+    for runtime in runtimes {
+        match &runtime.version {
+            Some(version) => println!("Runtime: {} {}", runtime.kind, version),
+            None => println!("Runtime: {}", runtime.kind),
+        }
+        if !runtime.compiled_schedulers.is_empty() {
+            println!("  Schedulers linked: {}", runtime.compiled_schedulers.join(", "));
+        }
+        match runtime.worker_threads {
+            Some(n) => println!("  Worker threads: {}", n),
+            None => println!("  Worker threads: unknown (no process)"),
+        }
+        if let Some(scheduler) = &runtime.scheduler {
+            println!("  Scheduler: {}", scheduler);
+        }
+    }
+    Ok(())
+}
+
+/// info binary コマンドを処理する
+fn handle_info_binary(debugger: &Debugger) -> Result<()> {
+    let report = debugger.binary_report()?;
+    let build = &report.build;
+
+    println!("Binary: {}", report.path.display());
+    println!("  Build-id:        {}", report.build_id.as_deref().unwrap_or("none"));
+    println!("  PIE:             {}", if report.is_pie { "yes" } else { "no" });
+    println!("  rustc:           {}", report.rustc_version().as_deref().unwrap_or("unknown"));
+    for entry in report.comment.iter().filter(|entry| !entry.starts_with("rustc version ")) {
+        println!("  .comment:        {}", entry);
+    }
+    println!("  Debug info:      {}", build.debuginfo_level());
+    println!("  Split debuginfo: {}", report.split_debuginfo);
+    if report.split_debuginfo != kokia_core::SplitDebugInfo::Off {
+        println!("                   (kokia reads only the executable's own DWARF, not the split files)");
+    }
+    println!("  Optimized:       {}", if build.looks_optimized() { "looks like it (opt-level >= 1)" } else { "no" });
+    println!("  Symbol table:    {}", if report.has_symtab { "present" } else { "stripped" });
+    println!("  Compile units:   {} ({} Rust outside std)", build.units, build.rust_units);
+    println!("  Functions:       {} in DWARF, {} symbols", build.functions, report.symbols);
+    println!("  Async closures:  {}", report.async_closures);
+    if let Some(reason) = report.missing_async_reason() {
+        println!("  Note: no async fn closures: {}", reason);
+    }
+    Ok(())
+}
+
+/// 監視中のメモリの値をリトルエンディアンの整数として表示する
+fn format_watched_value(bytes: &[u8]) -> String {
+    let mut buf = [0u8; 8];
+    let len = bytes.len().min(8);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    let value = u64::from_le_bytes(buf);
+    format!("0x{:x} ({})", value, value)
+}
+
+/// ウォッチポイントのヒットを表示する
+fn print_watchpoint_hit(debugger: &Debugger) {
+    let Some(hit) = debugger.last_stop().and_then(|e| e.watchpoint.as_ref()) else {
+        return;
+    };
+    println!("Hardware watchpoint {} ({:?}): 0x{:x}", hit.id, hit.kind, hit.address);
+    if hit.changed() {
+        println!("Old value = {}", format_watched_value(&hit.old));
+        println!("New value = {}", format_watched_value(&hit.new));
+    } else {
+        println!("Value = {}", format_watched_value(&hit.new));
+    }
+}
+
+/// info proc コマンドを処理する
+fn handle_info_proc(debugger: &mut Debugger) -> Result<()> {
+    let info = debugger.process_info()?;
+
+    println!("process {}", info.pid);
+    if let Some(exe) = &info.exe {
+        println!("exe     = {}", exe.display());
+    }
+    if let Some(cwd) = &info.cwd {
+        println!("cwd     = {}", cwd.display());
+    }
+    println!("cmdline = {}", info.cmdline.join(" "));
+    if let Some(state) = &info.state {
+        println!("state   = {}", state);
+    }
+    if let Some(threads) = info.threads {
+        println!("threads = {}", threads);
+    }
+    if let (Some(size), Some(rss)) = (info.vm_size_kb, info.vm_rss_kb) {
+        println!("memory  = {} kB virtual, {} kB resident", size, rss);
+    }
+
+    // マッピングの概要（件数・合計サイズと、ファイルごとの内訳）
+    let mappings = debugger.memory_mappings()?;
+    let total: usize = mappings.iter().map(|m| m.end - m.start).sum();
+    let executable = mappings.iter().filter(|m| m.executable).count();
+    let writable = mappings.iter().filter(|m| m.writable).count();
+    println!(
+        "mappings = {} regions, {} kB total ({} executable, {} writable)",
+        mappings.len(),
+        total / 1024,
+        executable,
+        writable
+    );
+
+    let mut files: Vec<(&str, usize)> = Vec::new();
+    for mapping in &mappings {
+        let Some(path) = mapping.path.as_deref() else {
+            continue;
+        };
+        match files.iter_mut().find(|(p, _)| *p == path) {
+            Some((_, size)) => *size += mapping.end - mapping.start,
+            None => files.push((path, mapping.end - mapping.start)),
+        }
+    }
+    for (path, size) in files {
+        println!("  {:>10} kB  {}", size / 1024, path);
+    }
+
+    Ok(())
+}
+
+/// Stepコマンドを処理する
+fn handle_step(debugger: &mut Debugger) -> Result<()> {
     let stop_reason = debugger.step()?;
 
+    if let StopReason::Exited(code) = stop_reason {
+        println!("Process exited with code {}", code);
+        return Ok(());
+    }
+    if let StopReason::Killed(signal) = stop_reason {
+        println!("Process killed by {:?}", signal);
+        return Ok(());
+    }
+
     // PCを取得
     let pc = debugger.get_pc()?;
-    println!("Stepped to 0x{:x}", pc);
+    match debugger.filtered_calls() {
+        0 => println!("Stepped to 0x{:x}", pc),
+        n => println!("Stepped to 0x{:x} (ran through {} filtered function(s))", pc, n),
+    }
 
     // シンボルを逆引き（デマングル済み）
     if let Some(symbol) = debugger.reverse_resolve(pc) {
@@ -375,15 +1937,48 @@ fn handle_step(debugger: &mut Debugger) -> Result<()> {
         StopReason::Breakpoint => {
             println!("(at breakpoint)");
         }
+        StopReason::Watchpoint => print_watchpoint_hit(debugger),
         StopReason::Signal(signal) => {
             println!("Received signal: {:?}", signal);
+            print_signal_detail(debugger.last_stop());
         }
-        StopReason::Exited(code) => {
-            println!("Process exited with code {}", code);
+        StopReason::Exited(_) | StopReason::Killed(_) | StopReason::Other => {}
+    }
+
+    run_stop_hooks(debugger);
+
+    Ok(())
+}
+
+/// reverse-stepi コマンドを処理する
+fn handle_reverse_stepi(debugger: &mut Debugger, count: usize) -> Result<()> {
+    let mut unrecorded = 0;
+    for i in 0..count {
+        match debugger.reverse_stepi() {
+            Ok(entry) => unrecorded += entry.unrecorded_writes,
+            // 途中で記録が尽きたら、そこまで戻したところで止める
+            Err(e) if i > 0 => {
+                println!("Stopped after {} instruction(s): {}", i, e);
+                break;
+            }
+            Err(e) => return Err(e),
         }
-        StopReason::Other => {}
     }
 
+    let pc = debugger.get_pc()?;
+    println!("Reversed to 0x{:x} ({} more instruction(s) can be undone)", pc, debugger.reversible_steps());
+    if let Some(symbol) = debugger.reverse_resolve(pc) {
+        println!("In function: {}", symbol.demangled_name);
+        if let Some((file, line)) = debugger.get_line_info(pc) {
+            println!("  at {}:{}", file, line);
+        }
+    }
+    if unrecorded > 0 {
+        println!("Warning: {} memory write(s) could not be recorded and were not undone", unrecorded);
+    }
+
+    run_stop_hooks(debugger);
+
     Ok(())
 }
 
@@ -391,6 +1986,15 @@ fn handle_step(debugger: &mut Debugger) -> Result<()> {
 fn handle_next(debugger: &mut Debugger) -> Result<()> {
     let stop_reason = debugger.step_over()?;
 
+    if let StopReason::Exited(code) = stop_reason {
+        println!("Process exited with code {}", code);
+        return Ok(());
+    }
+    if let StopReason::Killed(signal) = stop_reason {
+        println!("Process killed by {:?}", signal);
+        return Ok(());
+    }
+
     // PCを取得
     let pc = debugger.get_pc()?;
     println!("Stepped to next line at 0x{:x}", pc);
@@ -409,15 +2013,16 @@ fn handle_next(debugger: &mut Debugger) -> Result<()> {
         StopReason::Step | StopReason::Breakpoint => {
             // 通常の完了（テンポラリBPヒットまたは単純ステップ）
         }
+        StopReason::Watchpoint => print_watchpoint_hit(debugger),
         StopReason::Signal(signal) => {
             println!("Received signal: {:?}", signal);
+            print_signal_detail(debugger.last_stop());
         }
-        StopReason::Exited(code) => {
-            println!("Process exited with code {}", code);
-        }
-        StopReason::Other => {}
+        StopReason::Exited(_) | StopReason::Killed(_) | StopReason::Other => {}
     }
 
+    run_stop_hooks(debugger);
+
     Ok(())
 }
 
@@ -425,9 +2030,21 @@ fn handle_next(debugger: &mut Debugger) -> Result<()> {
 fn handle_finish(debugger: &mut Debugger) -> Result<()> {
     let stop_reason = debugger.step_out()?;
 
+    if let StopReason::Exited(code) = stop_reason {
+        println!("Process exited with code {}", code);
+        return Ok(());
+    }
+    if let StopReason::Killed(signal) = stop_reason {
+        println!("Process killed by {:?}", signal);
+        return Ok(());
+    }
+
     // PCを取得
     let pc = debugger.get_pc()?;
     println!("Returned to caller at 0x{:x}", pc);
+    if let Some(value) = debugger.last_stop().and_then(|e| e.return_value.as_ref()) {
+        println!("Value returned: {}", value);
+    }
 
     // シンボルを逆引き（デマングル済み）
     if let Some(symbol) = debugger.reverse_resolve(pc) {
@@ -443,20 +2060,23 @@ fn handle_finish(debugger: &mut Debugger) -> Result<()> {
         StopReason::Step | StopReason::Breakpoint => {
             // 通常の完了（テンポラリBPヒットまたは単純ステップ）
         }
+        StopReason::Watchpoint => print_watchpoint_hit(debugger),
         StopReason::Signal(signal) => {
             println!("Received signal: {:?}", signal);
+            print_signal_detail(debugger.last_stop());
         }
-        StopReason::Exited(code) => {
-            println!("Process exited with code {}", code);
-        }
-        StopReason::Other => {}
+        StopReason::Exited(_) | StopReason::Killed(_) | StopReason::Other => {}
     }
 
+    run_stop_hooks(debugger);
+
     Ok(())
 }
 
 /// Backtraceコマンドを処理する
-fn handle_backtrace(debugger: &mut Debugger) -> Result<()> {
+///
+/// `full` のときは各フレームの引数とローカル変数も表示する
+fn handle_backtrace(debugger: &mut Debugger, full: bool) -> Result<()> {
     let frames = debugger.backtrace()?;
 
     if frames.is_empty() {
@@ -482,115 +2102,373 @@ fn handle_backtrace(debugger: &mut Debugger) -> Result<()> {
 
         // アドレス
         println!("\n        (0x{:x})", frame.pc);
+
+        if full {
+            print_frame_variables(debugger, frame);
+        }
     }
 
     Ok(())
 }
 
-/// Localsコマンドを処理する
-fn handle_locals(debugger: &mut Debugger) -> Result<()> {
-    use kokia_dwarf::{VariableLocation, ValueFormatter};
+/// trace start コマンドを処理する
+fn handle_trace_start(debugger: &mut Debugger, mode: kokia_core::TraceMode, capacity: usize) {
+    debugger.start_instruction_trace(mode, capacity);
+    let what = match mode {
+        kokia_core::TraceMode::Instructions => "every instruction",
+        kokia_core::TraceMode::Branches => "taken branches",
+    };
+    println!("Tracing {} (keeping the last {} records)", what, capacity);
+    println!("'continue' and 'step' now single-step; 'trace stop' to finish");
+}
 
-    match debugger.get_local_variables() {
-        Ok(variables) => {
-            if variables.is_empty() {
-                println!("No local variables found");
-                println!("Note: Variables may be optimized out. Try compiling with -C opt-level=0");
-                return Ok(());
+/// trace stop コマンドを処理する（分岐を関数の組ごとに集計して表示）
+fn handle_trace_stop(debugger: &mut Debugger) {
+    let Some(trace) = debugger.stop_instruction_trace().cloned() else {
+        println!("No instruction trace (use 'trace start')");
+        return;
+    };
+    println!("Traced {} instruction(s), {} taken branch(es)", trace.executed(), trace.branches());
+    if trace.dropped() > 0 {
+        println!("{} older record(s) were dropped; raise 'trace start --limit' to keep them", trace.dropped());
+    }
+
+    let summary = trace.branch_summary(|addr| debugger.reverse_resolve(addr).map(|symbol| symbol.demangled_name));
+    if summary.is_empty() {
+        return;
+    }
+    println!();
+    println!("Taken branches by function:");
+    for branch in summary.iter().take(20) {
+        if branch.from == branch.to {
+            println!("{:>8}  {} (within)", branch.count, branch.from);
+        } else {
+            println!("{:>8}  {} -> {}", branch.count, branch.from, branch.to);
+        }
+    }
+    if summary.len() > 20 {
+        println!("... {} more (see 'trace export')", summary.len() - 20);
+    }
+}
+
+/// 命令トレースの1件を表示用に整形する
+fn format_trace_record(debugger: &Debugger, record: &kokia_core::TraceRecord) -> String {
+    let mut line = format!("0x{:x}{}", record.pc, format_symbol_offset(debugger, record.pc));
+    if let Some(target) = record.target {
+        line.push_str(&format!(" -> 0x{:x}{}", target, format_symbol_offset(debugger, target.get())));
+    }
+    line
+}
+
+/// trace show コマンドを処理する
+fn handle_trace_show(debugger: &Debugger, count: usize) -> Result<()> {
+    let trace = debugger.instruction_trace()
+        .ok_or_else(|| anyhow::anyhow!("No instruction trace (use 'trace start')"))?;
+    let skip = trace.records().len().saturating_sub(count);
+    for record in trace.records().skip(skip) {
+        println!("{}", format_trace_record(debugger, record));
+    }
+    Ok(())
+}
+
+/// trace export コマンドを処理する（1行に1命令）
+fn handle_trace_export(debugger: &Debugger, path: &str) -> Result<()> {
+    use std::io::Write;
+
+    let trace = debugger.instruction_trace()
+        .ok_or_else(|| anyhow::anyhow!("No instruction trace (use 'trace start')"))?;
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    for record in trace.records() {
+        writeln!(file, "{}", format_trace_record(debugger, record))?;
+    }
+    file.flush()?;
+    println!("Wrote {} record(s) to {}", trace.records().len(), path);
+    Ok(())
+}
+
+/// trace hw start コマンドを処理する
+fn handle_trace_hw_start(debugger: &mut Debugger) -> Result<()> {
+    let threads = debugger.start_hw_trace()?;
+    println!("Intel PT tracing started on {} thread(s); 'continue' runs at full speed", threads);
+    println!("Use 'trace hw stop' at the next stop to see what ran in between");
+    Ok(())
+}
+
+/// trace hw stop コマンドを処理する（スレッドごとに分岐先を関数ごとに集計して表示）
+fn handle_trace_hw_stop(debugger: &mut Debugger) -> Result<()> {
+    use kokia_core::PtEvent;
+
+    let traces = debugger.stop_hw_trace()?;
+    let function_of = |addr: u64| debugger.reverse_resolve(addr).map(|symbol| symbol.demangled_name);
+    for (trace, decoded) in &traces {
+        println!("Thread {}: {} bytes of trace{}", trace.tid, trace.data.len(), if trace.truncated { " (buffer full, truncated)" } else { "" });
+        if decoded.events.is_empty() && decoded.taken + decoded.not_taken == 0 {
+            println!("  (no user-space branches)");
+            continue;
+        }
+        let targets: Vec<u64> = decoded.branch_targets().collect();
+        let overflows = decoded.events.iter().filter(|event| **event == PtEvent::Overflow).count();
+        println!(
+            "  {} indirect branch/return target(s), conditional branches: {} taken, {} not taken",
+            targets.len(), decoded.taken, decoded.not_taken
+        );
+        if overflows > 0 || decoded.desyncs > 0 {
+            println!("  warning: {} overflow(s), {} undecodable section(s) skipped", overflows, decoded.desyncs);
+        }
+        for (function, count) in kokia_core::trace::count_by_function(targets.iter().copied(), function_of).iter().take(10) {
+            println!("  {:>8}  {}", count, function);
+        }
+        let last: Vec<&u64> = targets.iter().rev().take(5).collect();
+        if !last.is_empty() {
+            println!("  last targets (newest first):");
+            for target in last {
+                println!("    0x{:x}{}", target, format_symbol_offset(debugger, *target));
             }
+        }
+    }
+    Ok(())
+}
 
-            // ValueFormatterを作成（メモリアクセス用）
-            let memory = debugger.memory();
-            let registers = debugger.registers();
+/// continue のたびに表示する呼び出しログの件数の上限（それより前は ftrace log で見る）
+const FTRACE_PRINT_LIMIT: usize = 40;
 
-            println!("Local variables:");
-            for var in &variables {
-                print!("  {} : {}", var.name, var.type_name);
+/// ftrace コマンドを処理する
+fn handle_ftrace(debugger: &mut Debugger, pattern: &str) -> Result<()> {
+    let pattern = SymbolPattern::parse(pattern)?;
+    let traced = debugger.ftrace(&pattern)?;
+    if traced.is_empty() {
+        println!("No new functions to trace for '{}'", pattern.as_str());
+        return Ok(());
+    }
+    println!("Tracing {} function(s):", traced.len());
+    for name in &traced {
+        println!("  - {}", name);
+    }
+    println!("'continue' logs each call without stopping; 'ftrace log' shows the call log");
+    Ok(())
+}
 
-                // 型名と変数のアドレスから値をフォーマット
-                let formatted_value = if let (Some(mem), Some(regs)) = (memory, registers) {
-                    match &var.location {
-                        VariableLocation::Address(addr) => {
-                            let formatter = ValueFormatter::new(mem);
-                            formatter.format_by_type(*addr, &var.type_name)
-                                .unwrap_or_else(|_| format!("<error reading value>"))
-                        }
-                        VariableLocation::FrameOffset(offset) => {
-                            // RBPからのオフセットを計算してアドレスを取得
-                            if let Ok(rbp) = regs.get_rbp() {
-                                let addr = if *offset < 0 {
-                                    rbp.wrapping_sub(offset.unsigned_abs())
-                                } else {
-                                    rbp.wrapping_add(*offset as u64)
-                                };
-
-                                let formatter = ValueFormatter::new(mem);
-                                formatter.format_by_type(addr, &var.type_name)
-                                    .unwrap_or_else(|_| {
-                                        // フォーマット失敗時は元の値を表示
-                                        if let Some(ref value) = var.value {
-                                            format!("{}", value)
-                                        } else {
-                                            format!("<unavailable>")
-                                        }
-                                    })
-                            } else {
-                                format!("<rbp unavailable>")
-                            }
-                        }
-                        _ => {
-                            // その他の場合は元の値を表示
-                            if let Some(ref value) = var.value {
-                                format!("{}", value)
-                            } else {
-                                format!("<unavailable>")
-                            }
-                        }
-                    }
-                } else {
-                    // メモリアクセスできない場合は元の値を表示
-                    if let Some(ref value) = var.value {
-                        format!("{}", value)
-                    } else {
-                        format!("<unavailable>")
-                    }
-                };
+/// ftrace を引数なしで実行したとき（トレース中の関数を表示）
+fn handle_ftrace_list(debugger: &Debugger) {
+    let trace = debugger.function_trace();
+    if trace.functions().is_empty() {
+        println!("No functions are traced (use 'ftrace <pattern>')");
+    } else {
+        println!("Traced functions ({}):", trace.functions().len());
+        for function in trace.functions() {
+            println!("  {} (entry bp {}, {} return bp(s))", function.name, function.entry, function.exits.len());
+        }
+    }
+    println!("{} call event(s) recorded", trace.recorded());
+}
 
-                print!(" = {}", formatted_value);
+/// 関数トレースの呼び出しログの最後の `count` 件を表示する
+fn print_ftrace_log(debugger: &Debugger, count: usize) {
+    let trace = debugger.function_trace();
+    if trace.records().len() == 0 {
+        println!("No calls recorded");
+        return;
+    }
+    let skip = trace.records().len().saturating_sub(count);
+    if skip + trace.dropped() > 0 {
+        println!("({} earlier event(s) not shown)", skip + trace.dropped());
+    }
+    for record in trace.records().skip(skip) {
+        println!("{}", record);
+    }
+}
 
-                // ロケーション情報（デバッグ用）
-                match &var.location {
-                    VariableLocation::FrameOffset(offset) => {
-                        println!("  (rbp{:+})", offset);
-                    }
-                    VariableLocation::Address(addr) => {
-                        println!("  (@0x{:x})", addr);
-                    }
-                    VariableLocation::Register(reg) => {
-                        println!("  (reg{})", reg);
-                    }
-                    VariableLocation::OptimizedOut => {
-                        println!("  (optimized out)");
-                    }
-                    VariableLocation::Unknown => {
-                        println!();
-                    }
-                }
+/// ftrace stop コマンドを処理する
+fn handle_ftrace_stop(debugger: &mut Debugger, pattern: Option<&str>) -> Result<()> {
+    let pattern = pattern.map(SymbolPattern::parse).transpose()?;
+    let removed = debugger.ftrace_stop(pattern.as_ref())?;
+    println!("Stopped tracing {} function(s); the call log is kept", removed.len());
+    Ok(())
+}
+
+/// profile start コマンドを処理する
+fn handle_profile_start(debugger: &mut Debugger, frequency: u64) -> Result<()> {
+    let threads = debugger.start_profile(frequency)?;
+    println!("Sampling {} thread(s) at {} Hz while the target runs", threads, frequency);
+    println!("Use 'profile report' at the next stop to see where the time went");
+    Ok(())
+}
+
+/// profile stop コマンドを処理する
+fn handle_profile_stop(debugger: &mut Debugger) -> Result<()> {
+    let lost = debugger.profile_lost_samples();
+    let samples = debugger.stop_profile()?.samples();
+    println!("Profiling stopped: {} sample(s){}", samples, format_lost_samples(lost));
+    println!("Use 'profile report' to see the result");
+    Ok(())
+}
+
+fn format_lost_samples(lost: u64) -> String {
+    if lost == 0 { String::new() } else { format!(", {} lost", lost) }
+}
+
+/// profile report コマンドを処理する（flat、呼び出し木、async fn ごとの内訳）
+fn handle_profile_report(debugger: &mut Debugger) -> Result<()> {
+    /// 呼び出し木に出す部分木の下限（全サンプルに対する割合）
+    const TREE_MIN_FRACTION: f64 = 0.02;
+
+    let lost = debugger.profile_lost_samples();
+    let running = debugger.is_profiling();
+    let Some(profile) = debugger.profile() else {
+        return Err(anyhow::anyhow!("No profile recorded (use 'profile start' and continue)"));
+    };
+    let total = profile.samples();
+    println!("{} sample(s){}{}", total, format_lost_samples(lost), if running { " (still sampling)" } else { "" });
+    if total == 0 {
+        println!("The target has not run on the CPU since 'profile start'");
+        return Ok(());
+    }
+    let percent = |n: u64| n as f64 * 100.0 / total as f64;
+
+    println!();
+    println!("{:>7} {:>7}  function", "self", "total");
+    for function in profile.flat().iter().take(20) {
+        println!("{:>6.1}% {:>6.1}%  {}", percent(function.self_samples), percent(function.total_samples), function.name);
+    }
+
+    println!();
+    println!("Call tree (callers first, subtrees under {:.0}% hidden):", TREE_MIN_FRACTION * 100.0);
+    for line in profile.tree(TREE_MIN_FRACTION) {
+        println!("{:>6.1}%  {}{}", percent(line.total_samples), "  ".repeat(line.depth), line.name);
+    }
+
+    println!();
+    println!("By async fn (innermost on the stack):");
+    for (async_fn, count) in profile.by_async_fn() {
+        println!("{:>6.1}%  {}", percent(count), async_fn.as_deref().unwrap_or("(outside async fns)"));
+    }
+    Ok(())
+}
+
+/// Localsコマンドを処理する
+fn handle_locals(debugger: &mut Debugger) -> Result<()> {
+    let Some(variables) = current_frame_variables(debugger, VariableKind::Local) else {
+        return Ok(());
+    };
+    if variables.is_empty() {
+        println!("No local variables found");
+        println!("Note: Variables may be optimized out. Try compiling with -C opt-level=0");
+        return Ok(());
+    }
+
+    let rbp = debugger.registers().and_then(|regs| regs.get_rbp().ok());
+    println!("Local variables:");
+    for var in &variables {
+        print_variable(debugger, var, rbp, "  ");
+    }
+    Ok(())
+}
+
+/// info args コマンドを処理する
+fn handle_info_args(debugger: &mut Debugger) -> Result<()> {
+    let Some(variables) = current_frame_variables(debugger, VariableKind::Argument) else {
+        return Ok(());
+    };
+    if variables.is_empty() {
+        println!("No arguments");
+        return Ok(());
+    }
+
+    let rbp = debugger.registers().and_then(|regs| regs.get_rbp().ok());
+    println!("Arguments:");
+    for var in &variables {
+        print_variable(debugger, var, rbp, "  ");
+    }
+    Ok(())
+}
+
+/// フレームの引数とローカル変数を表示する（bt full）
+fn print_frame_variables(debugger: &Debugger, frame: &kokia_core::StackFrame) {
+    let interrupt = InterruptGuard::install();
+    let variables = debugger.frame_variables(frame);
+    drop(interrupt);
+
+    match variables {
+        Ok(variables) if variables.is_empty() => println!("        No locals."),
+        Ok(mut variables) => {
+            // 引数を先に並べる
+            variables.sort_by_key(|var| var.kind != VariableKind::Argument);
+            for var in &variables {
+                print_variable(debugger, var, Some(frame.rbp), "        ");
             }
         }
+        Err(e) => println!("        <variables unavailable: {}>", e),
+    }
+}
+
+/// 現在のフレームの変数のうち `kind` のものを取得する（失敗したら理由を表示して None）
+fn current_frame_variables(debugger: &Debugger, kind: VariableKind) -> Option<Vec<kokia_dwarf::Variable>> {
+    // 検索中の Ctrl-C は kokia を終了させず、検索だけを打ち切る
+    let interrupt = InterruptGuard::install();
+    let variables = debugger.get_local_variables();
+    drop(interrupt);
+
+    match variables {
+        Ok(variables) => Some(variables.into_iter().filter(|var| var.kind == kind).collect()),
+        Err(e) if kokia_dwarf::cancel::is_cancelled(&e) => {
+            println!("{}; use 'set query-timeout' to change the limit", e);
+            None
+        }
         Err(e) => {
             println!("Failed to get local variables: {}", e);
             println!("Ensure the binary was compiled with debug info (-C debuginfo=2)");
+            None
         }
     }
+}
 
-    Ok(())
+/// 変数を1行で表示する（`rbp` はそのフレームのベースポインタ）
+fn print_variable(debugger: &Debugger, var: &kokia_dwarf::Variable, rbp: Option<u64>, indent: &str) {
+    use kokia_dwarf::{VariableLocation, ValueFormatter};
+
+    // 元の値（型によるフォーマットができないとき）
+    let raw_value = || match &var.value {
+        Some(value) => format!("{}", value),
+        None => "<unavailable>".to_string(),
+    };
+
+    // 型名と変数のアドレスから値をフォーマット
+    let formatted_value = match (debugger.memory(), &var.location) {
+        (Some(mem), VariableLocation::Address(addr)) => ValueFormatter::new(mem)
+            .format_by_type(*addr, &var.type_name)
+            .unwrap_or_else(|_| "<error reading value>".to_string()),
+        (Some(mem), VariableLocation::FrameOffset(offset)) => match rbp {
+            // RBPからのオフセットを計算してアドレスを取得
+            Some(rbp) => {
+                let addr = if *offset < 0 {
+                    rbp.wrapping_sub(offset.unsigned_abs())
+                } else {
+                    rbp.wrapping_add(*offset as u64)
+                };
+                ValueFormatter::new(mem)
+                    .format_by_type(addr, &var.type_name)
+                    .unwrap_or_else(|_| raw_value())
+            }
+            None => "<rbp unavailable>".to_string(),
+        },
+        _ => raw_value(),
+    };
+
+    // ロケーション情報（デバッグ用）
+    let location = match &var.location {
+        VariableLocation::FrameOffset(offset) => format!("  (rbp{:+})", offset),
+        VariableLocation::Address(addr) => format!("  (@0x{:x})", addr),
+        VariableLocation::Register(reg) => format!("  (reg{})", reg),
+        VariableLocation::Computed => "  (computed)".to_string(),
+        VariableLocation::OptimizedOut => "  (optimized out)".to_string(),
+        VariableLocation::Unknown => String::new(),
+    };
+    println!("{}{} : {} = {}{}", indent, var.name, var.type_name, formatted_value, location);
 }
 
 /// Printコマンドを処理する
 fn handle_print(debugger: &mut Debugger, expr: &str) -> Result<()> {
     use kokia_core::{parse_expression, ExpressionEvaluator};
-    use kokia_dwarf::ValueFormatter;
 
     // 式をパース
     let expression = match parse_expression(expr) {
@@ -603,40 +2481,45 @@ fn handle_print(debugger: &mut Debugger, expr: &str) -> Result<()> {
 
     // 式を評価
     let evaluator = ExpressionEvaluator::new(debugger);
-    let result = match evaluator.evaluate(&expression) {
-        Ok(r) => r,
+    let (value, formatted) = match evaluator.evaluate_to_value(&expression)
+        .and_then(|value| evaluator.format_value(&value).map(|formatted| (value, formatted)))
+    {
+        Ok(evaluated) => evaluated,
         Err(e) => {
             println!("Failed to evaluate expression '{}': {}", expr, e);
             return Ok(());
         }
     };
 
-    // 値をフォーマットして表示
-    if let Some(memory) = debugger.memory() {
-        let formatter = ValueFormatter::new(memory);
+    // `$pc+0x12` のような数値式はシンボル+オフセットも添える
+    let symbol = match value {
+        Value::Address(address) => format_symbol_offset(debugger, address),
+        _ => String::new(),
+    };
+    let number = debugger.record_value(value);
+    println!("${} = {}{}", number, formatted, symbol);
 
-        // 型情報がある場合は詳細フォーマット、ない場合は型名ベースフォーマット
-        let formatted = if let Some(type_info) = result.type_info {
-            use kokia_dwarf::FormatOptions;
-            let options = FormatOptions::default();
-            formatter.format_with_type_info(result.address, &type_info, options)
-                .unwrap_or_else(|_| {
-                    // 型情報ベースで失敗した場合は型名ベースを試す
-                    formatter.format_by_type(result.address, &result.type_name)
-                        .unwrap_or_else(|_| format!("<error reading value>"))
-                })
-        } else {
-            // 型情報がない場合は型名ベースでフォーマット
-            formatter.format_by_type(result.address, &result.type_name)
-                .unwrap_or_else(|_| format!("<error reading value>"))
-        };
+    Ok(())
+}
+
+/// `set $name = <expr>` を処理する（値は履歴に残さない）
+fn handle_set_variable(debugger: &mut Debugger, name: &str, expr: &str) -> Result<()> {
+    use kokia_core::{parse_expression, ExpressionEvaluator};
+
+    let evaluator = ExpressionEvaluator::new(debugger);
+    let value = parse_expression(expr).and_then(|expression| evaluator.evaluate_to_value(&expression))?;
+    let formatted = evaluator.format_value(&value)?;
+    debugger.set_convenience_variable(name, value)?;
+    println!("${} = {}", name, formatted);
+    Ok(())
+}
 
-        println!("{} = {}", expr, formatted);
-    } else {
-        println!("Cannot read memory: process not running");
+/// タスクを `#4 (0x7ffd2a10)` の形で表す（追跡していないタスクはアドレスのみ）
+fn task_label(debugger: &Debugger, id: u64) -> String {
+    match debugger.async_tracker().get_task(id) {
+        Some(task) => task.label(),
+        None => format!("0x{:x}", id),
     }
-
-    Ok(())
 }
 
 /// タスク情報を整形して表示するヘルパー関数
@@ -645,15 +2528,22 @@ fn handle_print(debugger: &mut Debugger, expr: &str) -> Result<()> {
 /// * `task` - タスク情報
 /// * `prefix` - 各行の接頭辞（インデント用）
 /// * `verbose` - 詳細モード（true: 複数行、false: 1行）
-fn format_task_info(task: &kokia_core::TaskInfo, prefix: &str, verbose: bool) {
+fn format_task_info(debugger: &Debugger, task: &kokia_core::TaskInfo, prefix: &str, verbose: bool) {
     if verbose {
         // 詳細モード：複数行で表示
-        print!("{}Task 0x{:x}", prefix, task.id);
+        print!("{}Task {}", prefix, task.label());
         if let Some(ref type_name) = task.type_name {
-            // デマングルして表示
-            print!("\n{}   Type: {}", prefix, demangle_name(type_name));
+            print!("\n{}   Type: {}", prefix, future_name(debugger, type_name));
+        }
+        if let (Some(state), Some(discriminant)) = (task.state(), task.current_discriminant) {
+            print!("\n{}   State: {} (discriminant {})", prefix, state, discriminant);
+            if task.is_panicked() {
+                print!(" -- an earlier poll panicked; polling it again panics");
+            }
         }
 
+        let generation = format!("gen {}", task.generation);
+        let migrated = format!("migrated {}x", task.migrations);
         let mut flags = Vec::new();
         if task.is_root {
             flags.push("root task");
@@ -661,6 +2551,18 @@ fn format_task_info(task: &kokia_core::TaskInfo, prefix: &str, verbose: bool) {
         if task.completed {
             flags.push("completed");
         }
+        if task.pre_existing {
+            flags.push("pre-existing");
+        }
+        if task.superseded {
+            flags.push("superseded");
+        }
+        if task.generation > 0 {
+            flags.push(&generation);
+        }
+        if task.migrations > 0 {
+            flags.push(&migrated);
+        }
 
         if !flags.is_empty() {
             print!("\n{}   [{}]", prefix, flags.join(", "));
@@ -668,20 +2570,37 @@ fn format_task_info(task: &kokia_core::TaskInfo, prefix: &str, verbose: bool) {
         println!();
     } else {
         // 簡潔モード：1行で表示
-        print!("{}Task 0x{:x}", prefix, task.id);
+        print!("{}Task {}", prefix, task.label());
 
         if let Some(ref type_name) = task.type_name {
-            // デマングルして表示
-            print!(" ({})", demangle_name(type_name));
+            print!(" ({})", future_name(debugger, type_name));
         }
 
+        let generation = format!("gen {}", task.generation);
+        let migrated = format!("migrated {}x", task.migrations);
         let mut flags = Vec::new();
+        let state = task.state().map(|state| state.to_string());
         if task.is_root {
             flags.push("root");
         }
+        if let Some(state) = &state {
+            flags.push(if task.is_panicked() { "PANICKED" } else { state });
+        }
         if task.completed {
             flags.push("completed");
         }
+        if task.pre_existing {
+            flags.push("pre-existing");
+        }
+        if task.superseded {
+            flags.push("superseded");
+        }
+        if task.generation > 0 {
+            flags.push(&generation);
+        }
+        if task.migrations > 0 {
+            flags.push(&migrated);
+        }
 
         if !flags.is_empty() {
             print!(" [{}]", flags.join(", "));
@@ -694,9 +2613,9 @@ fn format_task_info(task: &kokia_core::TaskInfo, prefix: &str, verbose: bool) {
 fn handle_async_backtrace(debugger: &mut Debugger) -> Result<()> {
     use kokia_core::Tid;
 
-    // TODO: 現在のスレッドIDを取得する（現時点ではプロセスIDを使用）
-    let pid = debugger.pid().ok_or_else(|| anyhow::anyhow!("No process attached"))?;
-    let tid = Tid(pid);
+    let tid = debugger.current_thread()
+        .map(Tid)
+        .ok_or_else(|| anyhow::anyhow!("No process attached"))?;
 
     let backtrace = debugger.async_tracker().async_backtrace(tid);
 
@@ -706,11 +2625,11 @@ fn handle_async_backtrace(debugger: &mut Debugger) -> Result<()> {
         return Ok(());
     }
 
-    println!("Async backtrace (logical stack):");
+    println!("Async backtrace (logical stack) of thread {}:", thread_label(debugger, tid.0));
     for (i, task_id) in backtrace.iter().enumerate() {
         if let Some(task) = debugger.async_tracker().get_task(*task_id) {
             print!("  #{:<3} ", i);
-            format_task_info(task, "     ", true);
+            format_task_info(debugger, task, "     ", true);
         } else {
             println!("  #{} Task 0x{:x}", i, task_id);
         }
@@ -719,6 +2638,38 @@ fn handle_async_backtrace(debugger: &mut Debugger) -> Result<()> {
     Ok(())
 }
 
+/// AsyncBacktraceAllコマンドを処理する
+///
+/// 全ルートタスクについて、中断中のものも含めて await チェーンを表示する
+fn handle_async_backtrace_all(debugger: &mut Debugger) -> Result<()> {
+    let tracker = debugger.async_tracker();
+    let roots = tracker.root_tasks();
+
+    if roots.is_empty() {
+        println!("No live root tasks tracked");
+        println!("Note: Root tasks are discovered by observing GenFuture::poll calls");
+        return Ok(());
+    }
+
+    for (n, root) in roots.iter().enumerate() {
+        if n > 0 {
+            println!();
+        }
+        println!("Root task {}:", root.label());
+
+        for (i, task_id) in tracker.await_chain(root.id).iter().enumerate() {
+            if let Some(task) = tracker.get_task(*task_id) {
+                print!("  #{:<3} ", i);
+                format_task_info(debugger, task, "     ", true);
+            } else {
+                println!("  #{} Task 0x{:x}", i, task_id);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// AsyncTasksコマンドを処理する
 fn handle_async_tasks(debugger: &mut Debugger) -> Result<()> {
     let tasks = debugger.async_tracker().all_tasks();
@@ -729,19 +2680,287 @@ fn handle_async_tasks(debugger: &mut Debugger) -> Result<()> {
         return Ok(());
     }
 
-    println!("Async tasks ({} total):", tasks.len());
-    for task in tasks {
-        format_task_info(task, "  ", false);
+    let evicted = debugger.async_tracker().eviction_stats();
+    if evicted.tasks > 0 {
+        println!(
+            "Async tasks ({} total, {} evicted: {} completed, {} superseded):",
+            tasks.len(),
+            evicted.tasks,
+            evicted.completed,
+            evicted.superseded
+        );
+    } else {
+        println!("Async tasks ({} total):", tasks.len());
+    }
+    if debugger.is_async_read_only() {
+        println!("  (read-only snapshot: async tracking is disabled)");
+    }
+    for task in &tasks {
+        format_task_info(debugger, task, "  ", false);
+    }
+    warn_panicked_tasks(tasks);
+
+    Ok(())
+}
+
+/// Panicked の状態で残っているタスクを、中断中のタスクと区別して警告する
+fn warn_panicked_tasks<'a>(tasks: impl IntoIterator<Item = &'a kokia_core::TaskInfo>) {
+    let panicked: Vec<String> = tasks.into_iter()
+        .filter(|task| task.is_live() && task.is_panicked())
+        .map(|task| format!("#{}", task.handle))
+        .collect();
+    if !panicked.is_empty() {
+        println!(
+            "Warning: {} task(s) panicked during a poll and are poisoned: {} (polling them again panics)",
+            panicked.len(),
+            panicked.join(", ")
+        );
+    }
+}
+
+/// async memsize コマンドを処理する（大きい状態機械、ルートタスクごと、関数ごと）
+fn handle_async_memsize(debugger: &mut Debugger) -> Result<()> {
+    let report = debugger.async_memsize()?;
+    if report.tasks().is_empty() {
+        println!("No live async tasks tracked");
+        return Ok(());
+    }
+    let unknown = report.unknown();
+    let roots = report.by_root();
+    let total: u64 = roots.iter().map(|root| root.total).sum();
+    print!("{} live task(s) in {} root task(s), {} byte(s) of task state", report.tasks().len(), roots.len(), total);
+    if unknown > 0 {
+        print!(" ({} task(s) of unknown size not counted)", unknown);
+    }
+    println!();
+
+    let name = |function: &Option<String>| {
+        function.as_deref().map_or_else(|| "<unknown>".to_string(), |function| future_name(debugger, function))
+    };
+    println!();
+    println!("Largest state machines:");
+    for task in report.largest(10) {
+        println!("  {:>8}  #{:<4} {}", task.size.unwrap_or(0), task.handle, name(&task.function));
+    }
+
+    println!();
+    println!("By root task (bytes, tasks):");
+    for root in &roots {
+        println!("  {:>8}  {:>5}  #{} (0x{:x}) {}", root.total, root.tasks, root.root.handle, root.root.id, name(&root.root.function));
+    }
+
+    println!();
+    println!("By async fn (bytes each x count):");
+    for function in report.by_function() {
+        println!(
+            "  {:>8}  {:>6} x {:<5} {}",
+            function.size * function.count as u64, function.size, function.count, future_name(debugger, &function.function)
+        );
+    }
+    Ok(())
+}
+
+/// タスクの寿命などの短い表示（1.5s, 12.0ms, 800us）
+fn format_lifetime(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs_f64();
+    if secs >= 1.0 {
+        format!("{:.1}s", secs)
+    } else if secs >= 0.001 {
+        format!("{:.1}ms", secs * 1e3)
+    } else {
+        format!("{}us", duration.as_micros())
+    }
+}
+
+/// async lifetimes コマンドを処理する（寿命の分布、型ごとの集計、区間ごとのスループット）
+fn handle_async_lifetimes(debugger: &mut Debugger) {
+    /// スループットを数える区間の数
+    const WINDOWS: usize = 10;
+
+    let tracker = debugger.async_tracker();
+    let stats = tracker.lifetimes(WINDOWS);
+    let total: usize = stats.buckets.iter().map(|bucket| bucket.total()).sum();
+    if total == 0 {
+        println!("No async tasks tracked");
+        return;
+    }
+    let evicted = tracker.eviction_stats().tasks;
+    let (completed, dropped, pending) = stats.buckets.iter()
+        .fold((0, 0, 0), |(c, d, p), b| (c + b.completed, d + b.dropped, p + b.pending));
+    let percent = |n: usize| n as f64 * 100.0 / total as f64;
+    println!(
+        "{} task(s) over {}: {} completed ({:.0}%), {} dropped ({:.0}%), {} pending ({:.0}%)",
+        total, format_lifetime(stats.span), completed, percent(completed), dropped, percent(dropped), pending, percent(pending)
+    );
+    if evicted > 0 {
+        println!("  ({} evicted task(s) are not included)", evicted);
+    }
+
+    println!();
+    println!("Lifetime (pending tasks count their age so far):");
+    let widest = stats.buckets.iter().map(|bucket| bucket.total()).max().unwrap_or(1).max(1);
+    let mut lower = None;
+    for bucket in &stats.buckets {
+        // 区切りはミリ秒か秒のきりのよい値
+        let bound = |d: std::time::Duration| {
+            if d.as_millis() >= 1000 { format!("{}s", d.as_secs()) } else { format!("{}ms", d.as_millis()) }
+        };
+        let label = match (lower, bucket.upper) {
+            (None, Some(upper)) => format!("< {}", bound(upper)),
+            (Some(lower), Some(upper)) => format!("{} - {}", bound(lower), bound(upper)),
+            (Some(lower), None) => format!(">= {}", bound(lower)),
+            (None, None) => "any".to_string(),
+        };
+        lower = bucket.upper;
+        let bar = "#".repeat((bucket.total() * 40).div_ceil(widest));
+        println!(
+            "  {:>14}  {:>6}  {:<40}  {} done, {} dropped, {} pending",
+            label, bucket.total(), bar, bucket.completed, bucket.dropped, bucket.pending
+        );
+    }
+
+    println!();
+    println!("By future type:");
+    println!("  {:>6} {:>6} {:>7} {:>9} {:>9}  type", "done", "drop", "pending", "mean", "max");
+    for entry in stats.by_type.iter().take(20) {
+        println!(
+            "  {:>6} {:>6} {:>7} {:>9} {:>9}  {}",
+            entry.completed,
+            entry.dropped,
+            entry.pending,
+            entry.mean_completed.map(format_lifetime).unwrap_or_else(|| "-".to_string()),
+            format_lifetime(entry.max),
+            future_name(debugger, &entry.type_name)
+        );
+    }
+
+    println!();
+    println!("Throughput (since first task):");
+    for window in &stats.throughput {
+        println!("  +{:>8}  {:>6} started  {:>6} completed", format_lifetime(window.start), window.spawned, window.completed);
+    }
+}
+
+/// async assert コマンドを処理する（違反していれば kokia を終了する）
+/// 送出の件数を `3 sent, 0 failed, 0 dropped` の形にする
+fn format_export_stats(stats: &kokia_core::ExportStats) -> String {
+    format!("{} sent, {} failed, {} dropped", stats.sent, stats.failed, stats.dropped)
+}
+
+/// 計装で止まっていた時間の割合がこれを超えたら、計装を絞るよう勧める
+const OVERHEAD_ADVICE_FRACTION: f64 = 0.1;
+
+/// async stats コマンドを処理する
+fn handle_async_stats(debugger: &Debugger) {
+    use kokia_core::{OverheadKind, DEFAULT_STALL_THRESHOLD};
+
+    let stats = debugger.async_tracker().stats(debugger.running_time(), DEFAULT_STALL_THRESHOLD);
+    println!(
+        "Tasks: {} seen, {} live ({} stalled >{}s), {} completed, {} evicted",
+        stats.total,
+        stats.live,
+        stats.stalled,
+        DEFAULT_STALL_THRESHOLD.as_secs(),
+        stats.completed,
+        stats.evicted
+    );
+    let polls = debugger.poll_timer();
+    match polls.mean() {
+        Some(mean) => println!("Polls: {} ({} timed, mean {:.3}ms)", polls.polls(), polls.timed().0, mean.as_secs_f64() * 1000.0),
+        None => println!("Polls: {}", polls.polls()),
+    }
+
+    if let Some(scans) = debugger.fast_async_entry() {
+        println!("Fast entry: on ({} entries fell back to a backtrace)", scans);
+    }
+
+    let overhead = debugger.breakpoint_overhead();
+    let total = overhead.total();
+    let Some(mean) = total.mean() else {
+        println!("Instrumentation overhead: none (no stops at kokia's own breakpoints)");
+        return;
+    };
+    let share = overhead.fraction()
+        .map(|fraction| format!(", {:.1}% of {:.3}s running", fraction * 100.0, overhead.running.as_secs_f64()))
+        .unwrap_or_default();
+    println!(
+        "Instrumentation overhead: {:.3}s stopped over {} stops (mean {:.3}ms{})",
+        total.stopped.as_secs_f64(),
+        total.stops,
+        mean.as_secs_f64() * 1000.0,
+        share
+    );
+    for kind in OverheadKind::ALL {
+        let time = overhead.get(kind);
+        if let Some(mean) = time.mean() {
+            println!(
+                "  {:<12} {:>8} stops  {:>9.3}s  mean {:.3}ms",
+                kind.name(),
+                time.stops,
+                time.stopped.as_secs_f64(),
+                mean.as_secs_f64() * 1000.0
+            );
+        }
+    }
+    if overhead.fraction().is_some_and(|fraction| fraction > OVERHEAD_ADVICE_FRACTION) {
+        println!("The target spends much of its time stopped by kokia; timings are skewed. Narrow the async breakpoints with 'async enable --filter <pat>' or 'async sample <pat> <n>', or use 'async uprobe'.");
+        if debugger.fast_async_entry().is_none() {
+            println!("'set async fast-entry on' avoids a backtrace on every async fn entry.");
+        }
+    }
+}
+
+fn handle_async_assert(debugger: &mut Debugger, assertion: kokia_core::AsyncAssertion) {
+    let tasks = debugger.async_tracker().all_tasks();
+    match assertion.check(tasks, debugger.running_time(), std::time::Instant::now()) {
+        None => println!("Assertion passed: async assert {}", assertion),
+        Some(failure) => {
+            println!("Assertion failed: async assert {}: {}", assertion, failure);
+            print_exit_summary(debugger);
+            debugger.stop_event_export();
+            session_log::stop();
+            std::process::exit(kokia_core::ASSERTION_FAILED_EXIT_CODE);
+        }
+    }
+}
+
+/// AsyncFindコマンドを処理する
+///
+/// 各行の先頭に、最初に観測してからの経過時間を出す
+fn handle_async_find(debugger: &mut Debugger, query: &kokia_core::TaskQuery) -> Result<()> {
+    let tracker = debugger.async_tracker();
+    let now = std::time::Instant::now();
+    let tasks = tracker.all_tasks();
+    if tasks.is_empty() {
+        println!("No async tasks tracked");
+        return Ok(());
+    }
+
+    let total = tasks.len();
+    let found = query.run(tasks, now)?;
+    if found.is_empty() {
+        println!("No async tasks match");
+        return Ok(());
+    }
+
+    println!("{} of {} async tasks match:", found.len(), total);
+    for task in found {
+        let age = now.saturating_duration_since(task.first_seen).as_secs_f64();
+        format_task_info(debugger, task, &format!("  {:>7} ", format!("{:.1}s", age)), false);
     }
 
     Ok(())
 }
 
 /// AsyncEdgesコマンドを処理する
-fn handle_async_edges(debugger: &mut Debugger) -> Result<()> {
-    let edges = debugger.async_tracker().all_edges();
+fn handle_async_edges(debugger: &mut Debugger, filter: &EdgeFilter) -> Result<()> {
+    let edges = debugger.async_tracker().filtered_edges(filter);
 
     if edges.is_empty() {
+        if *filter != EdgeFilter::default() {
+            println!("No async edges match the filter");
+            return Ok(());
+        }
         println!("No async edges tracked");
         println!("Note: Edges (parent-child relationships) are built by observing GenFuture::poll calls");
         return Ok(());
@@ -749,7 +2968,7 @@ fn handle_async_edges(debugger: &mut Debugger) -> Result<()> {
 
     println!("Async edges (parent awaits child):");
     for edge in edges {
-        print!("  0x{:x} -> 0x{:x}", edge.parent, edge.child);
+        print!("  {} -> {}", task_label(debugger, edge.parent), task_label(debugger, edge.child));
 
         if let Some(callsite) = debugger.async_tracker().get_callsite(edge.callsite) {
             if let (Some(ref file), Some(line)) = (&callsite.file, callsite.line) {
@@ -769,12 +2988,45 @@ fn handle_async_edges(debugger: &mut Debugger) -> Result<()> {
     Ok(())
 }
 
+/// AsyncTreeコマンドを処理する
+fn handle_async_tree(debugger: &Debugger, filter: &EdgeFilter) {
+    let tracker = debugger.async_tracker();
+    let lines = tracker.task_tree(filter);
+
+    if lines.is_empty() {
+        println!("No async tasks match");
+        return;
+    }
+
+    for line in lines {
+        match line {
+            TreeLine::Task { depth, id } => {
+                let indent = "  ".repeat(depth + 1);
+                match tracker.get_task(id) {
+                    Some(task) => format_task_info(debugger, task, &indent, false),
+                    None => println!("{}Task 0x{:x} [evicted]", indent, id),
+                }
+            }
+            TreeLine::FoldedCompleted { depth, count } => {
+                let what = if depth == 0 { "root task" } else { "child" };
+                let plural = if count == 1 { "" } else if depth == 0 { "s" } else { "ren" };
+                println!("{}... {} completed {}{}", "  ".repeat(depth + 1), count, what, plural);
+            }
+        }
+    }
+    warn_panicked_tasks(tracker.all_tasks());
+}
+
 /// AsyncLocalsコマンドを処理する
-fn handle_async_locals(debugger: &mut Debugger) -> Result<()> {
+fn handle_async_locals(debugger: &mut Debugger, task: Option<TaskRef>) -> Result<()> {
     use kokia_dwarf::VariableLocation;
 
-    // 現在のフレームのローカル変数を取得
-    match debugger.get_async_locals() {
+    // 指定したタスク（なければ poll 中のタスク）のローカル変数を取得（Ctrl-C で検索を打ち切れる）
+    let interrupt = InterruptGuard::install();
+    let locals = debugger.get_async_locals(task);
+    drop(interrupt);
+
+    match locals {
         Ok(variables) => {
             if variables.is_empty() {
                 println!("No local variables found at current frame");
@@ -782,7 +3034,15 @@ fn handle_async_locals(debugger: &mut Debugger) -> Result<()> {
                 return Ok(());
             }
 
-            println!("Local variables at current frame:");
+            match task {
+                Some(task) => {
+                    let label = debugger.async_tracker().task_tracker().lookup(task)
+                        .map(|id| task_label(debugger, id))
+                        .unwrap_or_else(|| task.to_string());
+                    println!("Local variables of task {}:", label);
+                }
+                None => println!("Local variables at current frame:"),
+            }
             for var in &variables {
                 print!("  {} : {}", var.name, var.type_name);
 
@@ -803,6 +3063,9 @@ fn handle_async_locals(debugger: &mut Debugger) -> Result<()> {
                     VariableLocation::Address(addr) => {
                         println!("  (@{:#x})", addr);
                     }
+                    VariableLocation::Computed => {
+                        println!("  (computed)");
+                    }
                     VariableLocation::OptimizedOut => {
                         println!("  (optimized out)");
                     }
@@ -812,6 +3075,9 @@ fn handle_async_locals(debugger: &mut Debugger) -> Result<()> {
                 }
             }
         }
+        Err(e) if kokia_dwarf::cancel::is_cancelled(&e) => {
+            println!("{}; use 'set query-timeout' to change the limit", e);
+        }
         Err(e) => {
             println!("Failed to get async local variables: {}", e);
             println!("Ensure the binary was compiled with debug info (-C debuginfo=2)");
@@ -822,19 +3088,31 @@ fn handle_async_locals(debugger: &mut Debugger) -> Result<()> {
 }
 
 /// AsyncEnableコマンドを処理する
-fn handle_async_enable(debugger: &mut Debugger) -> Result<()> {
+///
+/// # Arguments
+/// * `filter` - 計装対象を絞り込むパターン（None なら全 async 関数）
+fn handle_async_enable(debugger: &mut Debugger, filter: Option<&str>) -> Result<()> {
+    let filter = filter.map(SymbolPattern::parse).transpose()?;
+
     println!("Enabling async task tracking (runtime-independent mode)...");
     println!("Searching for async function closures...");
 
-    let symbols = debugger.find_genfuture_poll_symbols();
+    let mut symbols = debugger.find_genfuture_poll_symbols();
+    if let Some(filter) = &filter {
+        let total = symbols.len();
+        symbols.retain(|sym| filter.matches(&sym.demangled_name));
+        println!("Filter '{}' matched {} of {} async function closure(s)", filter.as_str(), symbols.len(), total);
+        if symbols.is_empty() {
+            return Ok(());
+        }
+    }
 
     if symbols.is_empty() {
         println!("Warning: No async function closures found");
-        println!("Make sure the binary was compiled with debug symbols.");
-        println!();
-        println!("Possible causes:");
-        println!("  - The binary was compiled without debug info");
-        println!("  - All async functions were inlined by the optimizer");
+        if let Some(reason) = debugger.binary_report().ok().and_then(|report| report.missing_async_reason()) {
+            println!("Likely cause: {}", reason);
+        }
+        println!("Run 'info binary' to see how the binary was built.");
         println!();
         println!("Workaround:");
         println!("  Set breakpoints manually on your async functions:");
@@ -859,9 +3137,17 @@ fn handle_async_enable(debugger: &mut Debugger) -> Result<()> {
     println!();
     println!("Setting breakpoints on async function entry points...");
 
-    let breakpoint_ids = debugger.set_genfuture_poll_breakpoints()?;
+    let breakpoint_ids = debugger.enable_async_instrumentation(filter.as_ref())?;
 
     println!("Successfully set {} breakpoint(s) for async tracking", breakpoint_ids.len());
+    let (seeded, threads) = debugger.seed_async_tasks_from_threads()?;
+    if seeded > 0 {
+        println!("Found {} already-running task(s) on the stacks of {} thread(s)", seeded, threads);
+    }
+    let already = symbols.len().saturating_sub(breakpoint_ids.len());
+    if already > 0 {
+        println!("({} function(s) were already instrumented or could not be armed)", already);
+    }
     println!();
     println!("Note: In modern Rust, Future::poll is inlined, so we track async function");
     println!("      entry points instead. This provides basic async task tracking.");
@@ -875,18 +3161,299 @@ fn handle_async_enable(debugger: &mut Debugger) -> Result<()> {
     Ok(())
 }
 
+/// async sample コマンドを処理する
+///
+/// # Arguments
+/// * `setting` - (パターン, 間隔)。None なら計装済み関数のヒット数と間引き設定を表示
+fn handle_async_sample(debugger: &mut Debugger, setting: Option<(String, u64)>) -> Result<()> {
+    if let Some((pattern, every)) = setting {
+        let pattern = SymbolPattern::parse(&pattern)?;
+        let count = debugger.set_async_sampling(&pattern, every);
+        if count == 0 {
+            println!("No instrumented async function matches '{}'", pattern.as_str());
+        } else if every == 1 {
+            println!("Recording every entry of {} function(s)", count);
+        } else {
+            println!("Recording 1 of every {} entries of {} function(s)", every, count);
+        }
+        return Ok(());
+    }
+
+    let plan = debugger.async_instrumentation();
+    if plan.is_empty() {
+        println!("No async functions instrumented (use 'async enable')");
+        return Ok(());
+    }
+    println!("{:>8} {:>8} {:>6}  Function", "Hits", "Recorded", "Every");
+    for (_, function) in plan.armed() {
+        println!("{:>8} {:>8} {:>6}  {}", function.hits, function.recorded, function.sample_every, function.name);
+    }
+    Ok(())
+}
+
+/// async uprobe collect コマンドを処理する
+fn handle_async_uprobe_collect(debugger: &mut Debugger, secs: u64) -> Result<()> {
+    println!("Collecting uprobe events for {}s...", secs);
+    let collection = debugger.collect_uprobe_events(std::time::Duration::from_secs(secs))?;
+
+    print!("Collected {} async event(s)", collection.events);
+    if collection.lost > 0 {
+        print!(" ({} lost to ring buffer overflow)", collection.lost);
+    }
+    println!();
+
+    match collection.stop_reason {
+        StopReason::Signal(signal) => println!("Process stopped ({:?})", signal),
+        StopReason::Exited(code) => println!("Process exited with code {}", code),
+        StopReason::Killed(signal) => println!("Process killed by {:?}", signal),
+        StopReason::Breakpoint => {
+            let pc = debugger.get_pc()?;
+            println!("Breakpoint hit at 0x{:x}", pc);
+            if let Some(symbol) = debugger.reverse_resolve(pc) {
+                println!("In function: {}", symbol.demangled_name);
+            }
+        }
+        other => println!("Process stopped: {:?}", other),
+    }
+    println!("Use 'async tasks' / 'async edges' to inspect the tracked tasks.");
+
+    Ok(())
+}
+
+/// AsyncDisableコマンドを処理する
+fn handle_async_disable(debugger: &mut Debugger, pattern: &str) -> Result<()> {
+    let pattern = SymbolPattern::parse(pattern)?;
+    let removed = debugger.disable_async_instrumentation(&pattern)?;
+
+    if removed.is_empty() {
+        println!("No instrumented async function matches '{}'", pattern.as_str());
+        return Ok(());
+    }
+
+    let breakpoints: usize = removed.iter().map(|f| f.breakpoints().count()).sum();
+    println!("Disabled async tracking for {} function(s) ({} breakpoint(s) removed):", removed.len(), breakpoints);
+    for function in &removed {
+        println!("  - {}", function.name);
+    }
+    println!("{} function(s) remain instrumented", debugger.async_instrumentation().len());
+
+    Ok(())
+}
+
+/// async disable（パターンなし）を処理する
+fn handle_async_disable_all(debugger: &mut Debugger, keep_data: bool) -> Result<()> {
+    let teardown = debugger.disable_all_async_instrumentation(keep_data)?;
+    println!(
+        "Async tracking disabled: {} function(s) uninstrumented, {} breakpoint(s) removed{}",
+        teardown.functions,
+        teardown.breakpoints,
+        if teardown.uprobes_stopped { ", uprobes stopped" } else { "" }
+    );
+    if teardown.data_kept {
+        println!("Collected tasks are kept read-only; 'async enable' resumes tracking, 'async clear' discards them");
+    } else {
+        println!("Collected tasks were discarded (use 'async disable --keep' to keep them)");
+    }
+    Ok(())
+}
+
+/// thread apply の対象
+enum ThreadSelection {
+    All,
+    /// 1始まりのスレッド番号
+    Number(usize),
+    /// スレッド名（/proc/<tid>/comm）に対するパターン
+    Matching(SymbolPattern),
+}
+
+/// スレッドの表示用ラベル（`1234 "tokio-runtime-w"`、名前が読めなければ tid だけ）
+fn thread_label(debugger: &Debugger, tid: i32) -> String {
+    match debugger.thread_name(tid) {
+        Some(name) => format!("{} \"{}\"", tid, name),
+        None => tid.to_string(),
+    }
+}
+
+/// 名前がパターンに一致するスレッドを、1始まりの番号と一緒に返す（パターンなしなら全スレッド）
+fn numbered_threads(debugger: &Debugger, pattern: Option<&SymbolPattern>) -> Result<Vec<(usize, i32)>> {
+    let threads = debugger.threads()?;
+    Ok(threads.into_iter()
+        .enumerate()
+        .map(|(i, tid)| (i + 1, tid))
+        .filter(|(_, tid)| match pattern {
+            Some(pattern) => debugger.thread_name(*tid).is_some_and(|name| pattern.matches(&name)),
+            None => true,
+        })
+        .collect())
+}
+
+/// info threads コマンドを処理する
+fn handle_info_threads(debugger: &Debugger, pattern: Option<&SymbolPattern>) -> Result<()> {
+    use kokia_core::Tid;
+
+    let threads = numbered_threads(debugger, pattern)?;
+    if threads.is_empty() {
+        match pattern {
+            Some(pattern) => println!("No threads named like '{}'.", pattern.as_str()),
+            None => println!("No threads."),
+        }
+        return Ok(());
+    }
+
+    let current = debugger.current_thread();
+    println!("  {:<4} {:<8} {:<18} Frame", "Id", "LWP", "Name");
+    for (n, tid) in threads {
+        let marker = if current == Some(tid) { '*' } else { ' ' };
+        let name = debugger.thread_name(tid).unwrap_or_else(|| "?".to_string());
+        let frame = match debugger.thread_pc(tid) {
+            Ok(pc) => match debugger.reverse_resolve(pc) {
+                Some(symbol) => format!("0x{:x} in {}", pc, symbol.demangled_name),
+                None => format!("0x{:x}", pc),
+            },
+            Err(_) => "<running>".to_string(),
+        };
+        println!("{} {:<4} {:<8} {:<18} {}", marker, n, tid, format!("\"{}\"", name), frame);
+
+        // スレッドで poll 中の async タスクと、その論理スタックの深さ
+        let tracker = debugger.async_tracker();
+        let backtrace = tracker.async_backtrace(Tid(tid));
+        if let Some(task) = tracker.current_task(Tid(tid)) {
+            let function = task.type_name.as_deref().unwrap_or("<unknown>");
+            println!("         async: task {} {} (scope depth {})", task.label(), function, backtrace.len());
+        }
+    }
+    Ok(())
+}
+
+/// thread apply コマンドを処理する
+///
+/// # Arguments
+/// * `selection` - 対象スレッド（全スレッド、番号、スレッド名のパターン）
+/// * `cmd` - 各スレッドのコンテキストで実行するコマンド
+fn handle_thread_apply(debugger: &mut Debugger, selection: ThreadSelection, cmd: &str) -> Result<()> {
+    let original = debugger.current_thread();
+
+    let targets = match &selection {
+        ThreadSelection::Number(n) => {
+            let threads = debugger.threads()?;
+            let tid = n.checked_sub(1)
+                .and_then(|i| threads.get(i))
+                .ok_or_else(|| anyhow::anyhow!("Invalid thread number: {} ({} threads)", n, threads.len()))?;
+            vec![(*n, *tid)]
+        }
+        ThreadSelection::All => numbered_threads(debugger, None)?,
+        ThreadSelection::Matching(pattern) => {
+            let targets = numbered_threads(debugger, Some(pattern))?;
+            if targets.is_empty() {
+                anyhow::bail!("No threads named like '{}'", pattern.as_str());
+            }
+            targets
+        }
+    };
+
+    for (n, tid) in targets {
+        println!();
+        println!("Thread {} (LWP {}):", n, thread_label(debugger, tid));
+        let result = debugger.select_thread(tid)
+            .and_then(|_| handle_command(debugger, cmd));
+        if let Err(e) = result {
+            println!("Error: {}", e);
+        }
+    }
+
+    // 元のスレッドに戻す（スレッドが終了していた場合は無視）
+    if let Some(tid) = original {
+        let _ = debugger.select_thread(tid);
+    }
+
+    Ok(())
+}
+
+/// `inferior 2 [process 1234] (./server)` のような inferior の表示名
+fn inferior_label(debugger: &Debugger, id: kokia_core::InferiorId) -> String {
+    let Some(inferior) = debugger.inferiors().into_iter().find(|inferior| inferior.id == id) else {
+        return format!("inferior {}", id);
+    };
+    let mut label = format!("inferior {}", id);
+    match (inferior.pid, inferior.exit_code) {
+        (Some(pid), _) => label.push_str(&format!(" [process {}]", pid)),
+        (None, Some(code)) => label.push_str(&format!(" [exited with code {}]", code)),
+        (None, None) => label.push_str(" [no process]"),
+    }
+    if let Some(binary) = &inferior.binary {
+        label.push_str(&format!(" ({})", binary.display()));
+    }
+    label
+}
+
+/// add-inferior コマンドを処理する（追加した inferior を選択し、バイナリがあれば起動する）
+fn handle_add_inferior(debugger: &mut Debugger, binary: Option<&str>, args: &[String]) -> Result<()> {
+    let id = debugger.add_inferior();
+    debugger.select_inferior(id)?;
+    if let Some(binary) = binary {
+        debugger.load_binary(binary)?;
+        debugger.spawn_with_options(binary, args, &SpawnOptions::default())?;
+        warn_build_id_mismatch(debugger);
+    }
+    println!("Added {}; it is now selected", inferior_label(debugger, id));
+    if binary.is_none() {
+        println!("Use 'attach [--wait] <pid|name>' to give it a process");
+    }
+    Ok(())
+}
+
+/// 全 inferior でコマンドを実行し、元の inferior に戻す
+fn handle_inferior_apply_all(debugger: &mut Debugger, cmd: &str) {
+    let original = debugger.current_inferior();
+    let ids: Vec<kokia_core::InferiorId> = debugger.inferiors().iter().map(|inferior| inferior.id).collect();
+    for id in ids {
+        println!();
+        let result = debugger.select_inferior(id).and_then(|_| {
+            println!("{}:", inferior_label(debugger, id));
+            handle_command(debugger, cmd)
+        });
+        if let Err(e) = result {
+            println!("Error: {}", e);
+        }
+    }
+    let _ = debugger.select_inferior(original);
+}
+
+/// info inferiors コマンドを処理する
+fn handle_info_inferiors(debugger: &Debugger) {
+    println!("  Num  Process          Async tasks  Binary");
+    for inferior in debugger.inferiors() {
+        let process = match (inferior.pid, inferior.exit_code) {
+            (Some(pid), _) => format!("process {}", pid),
+            (None, Some(code)) => format!("exited ({})", code),
+            (None, None) => "<none>".to_string(),
+        };
+        let binary = inferior.binary.as_ref().map(|b| b.display().to_string()).unwrap_or_else(|| "<none>".to_string());
+        println!(
+            "{} {:<4} {:<16} {:<12} {}",
+            if inferior.current { "*" } else { " " },
+            inferior.id,
+            process,
+            inferior.tasks,
+            binary
+        );
+    }
+}
+
 /// カスタムコマンドを処理する
 fn handle_custom_command(debugger: &mut Debugger, line: &str) -> Result<()> {
-    if line.starts_with("find ") {
-        let pattern = &line[5..];
+    if let Some(pattern) = line.strip_prefix("find ") {
         let symbols = debugger.find_symbols(pattern);
         let title = format!("Symbols matching '{}'", pattern);
         print_symbol_list(&title, &symbols, Some(10));
-    } else if line.starts_with("async ") {
+    } else if let Some(cmd) = line.strip_prefix("async ") {
         // async関連のコマンド
-        handle_async_command(debugger, &line[6..])?;
+        handle_async_command(debugger, cmd)?;
+    } else if let Some(help) = kokia_core::help::find_by_input(line) {
+        println!("Usage: {}", help.synopsis());
     } else {
         println!("Unknown command: {}", line);
+        print_suggestion(line);
         println!("Type 'help' for available commands.");
     }
     Ok(())
@@ -896,42 +3463,43 @@ fn handle_async_command(debugger: &mut Debugger, cmd: &str) -> Result<()> {
     if cmd == "list" || cmd == "ls" {
         let async_symbols = debugger.find_async_symbols();
         print_symbol_list("Async-related symbols", &async_symbols, None);
-    } else if cmd.starts_with("locals") {
-        handle_async_locals(debugger)?;
+    } else if let Some(help) = kokia_core::help::find_by_input(&format!("async {}", cmd)) {
+        println!("Usage: {}", help.synopsis());
     } else {
         println!("Unknown async command: {}", cmd);
+        print_suggestion(&format!("async {}", cmd));
     }
     Ok(())
 }
 
-fn print_help() {
-    println!("Available commands:");
-    println!();
-    println!("  help           - Show this help message");
-    println!("  quit/exit/q    - Exit the debugger");
-    println!();
-    println!("Debug commands:");
-    println!("  break <loc>    - Set breakpoint at symbol or address");
-    println!("  continue (c)   - Continue execution");
-    println!("  step (s)       - Execute one instruction (step into)");
-    println!("  next (n)       - Execute to next source line (step over)");
-    println!("  finish (f)     - Execute until current function returns (step out)");
-    println!("  backtrace (bt) - Show stack backtrace");
-    println!("  locals (l)     - Show local variables");
-    println!("  print <expr>   - Evaluate and print expression (variable, field, array index)");
-    println!("  find <pattern> - Find symbols matching pattern");
-    println!();
-    println!("Async commands:");
-    println!("  async enable   - Enable async tracking (set GenFuture::poll breakpoints)");
-    println!("  async list     - List all async-related symbols");
-    println!("  async bt       - Show async backtrace (logical stack)");
-    println!("  async tasks    - Show all tracked async tasks");
-    println!("  async edges    - Show async task parent-child relationships");
-    println!("  async locals   - Show local variables at current async frame");
+fn print_help(topic: Option<&str>) {
+    if let Some(topic) = topic {
+        print_command_help(topic);
+        return;
+    }
+
+    for category in HelpCategory::ALL {
+        println!("{}:", category.title());
+        if category == HelpCategory::General {
+            println!();
+        }
+        for help in kokia_core::help::COMMANDS.iter().filter(|help| help.category == category) {
+            println!("  {:<14} - {}", help.synopsis(), help.summary);
+        }
+        if category == HelpCategory::General {
+            println!("  {:<14} - Repeat the last continue/step/next", "<Enter>");
+            println!("  {:<14} - Re-run the last command / the last command starting with prefix", "!! / !<prefix>");
+        }
+        println!();
+    }
+    println!("Type 'help <command>' for details (e.g. help async tree).");
     println!();
     println!("Examples:");
     println!("  break main");
     println!("  break 0x1234");
+    println!("  break *$rsp");
+    println!("  break main+0x40");
+    println!("  x/4 $rsp");
     println!("  step");
     println!("  next");
     println!("  finish");
@@ -942,3 +3510,34 @@ fn print_help() {
     println!("  find double");
     println!("  async tasks");
 }
+
+/// `help <command>` の詳細を表示する
+fn print_command_help(topic: &str) {
+    let found = kokia_core::help::lookup(topic);
+    match found.as_slice() {
+        [] => {
+            println!("No help for '{}'.", topic);
+            print_suggestion(topic);
+        }
+        [help] => {
+            println!("{}", help.synopsis());
+            println!("  {}", help.summary);
+            if !help.details.is_empty() {
+                println!();
+                println!("  {}", help.details);
+            }
+        }
+        helps => {
+            for help in helps {
+                println!("  {:<14} - {}", help.synopsis(), help.summary);
+            }
+        }
+    }
+}
+
+/// 入力に近いコマンドがあれば候補を表示する
+fn print_suggestion(input: &str) {
+    if let Some(name) = kokia_core::help::suggest(input) {
+        println!("Did you mean '{}'?", name);
+    }
+}
@@ -0,0 +1,295 @@
+//! セッションの記録（set logging）
+//!
+//! 実行したコマンド・停止イベント・画面に出した出力を時刻付きでファイルに書き出します。
+//! デバッグの様子を共有したり、kokia 自体の不具合報告に再現手順として添付したりするためのものです。
+//! 出力は main.rs で置き換えた `println!` などのマクロから [`output`] に流れてきます。
+
+use anyhow::{Context, Result};
+use kokia_core::StopEvent;
+use serde_json::json;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 記録中のログ（`set logging off` までの間だけ Some）
+static LOG: Mutex<Option<SessionLog>> = Mutex::new(None);
+
+/// ログファイルの形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// 1行1レコードのテキスト
+    Text,
+    /// 1行1つの JSON オブジェクト
+    Jsonl,
+}
+
+impl LogFormat {
+    /// 拡張子が `.jsonl` なら JSONL、それ以外はテキスト
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl") => LogFormat::Jsonl,
+            _ => LogFormat::Text,
+        }
+    }
+}
+
+/// 出力先
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn name(self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+}
+
+/// ログの1レコード
+enum Entry<'a> {
+    Start,
+    End,
+    Command(&'a str),
+    Stop(&'a StopEvent),
+    Output(Stream, &'a str),
+}
+
+struct SessionLog {
+    file: File,
+    path: PathBuf,
+    format: LogFormat,
+    /// 改行がまだ来ていない出力（stdout, stderr の順）
+    pending: [String; 2],
+}
+
+impl SessionLog {
+    fn write(&mut self, entry: &Entry) -> std::io::Result<()> {
+        let line = format_entry(self.format, SystemTime::now(), entry);
+        self.file.write_all(line.as_bytes())
+    }
+
+    /// 途中までの出力行を書き出す（コマンドや停止の記録より前に出た分の順序を保つ）
+    fn flush_pending(&mut self) -> std::io::Result<()> {
+        for stream in [Stream::Stdout, Stream::Stderr] {
+            let text = std::mem::take(&mut self.pending[stream as usize]);
+            if !text.is_empty() {
+                self.write(&Entry::Output(stream, &text))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `path` への記録を始める（記録中なら前のファイルを閉じて切り替える）
+pub fn start(path: &str) -> Result<LogFormat> {
+    let file = File::create(path).with_context(|| format!("Cannot create log file '{}'", path))?;
+    let path = PathBuf::from(path);
+    let format = LogFormat::from_path(&path);
+    stop();
+
+    let mut log = SessionLog { file, path, format, pending: Default::default() };
+    log.write(&Entry::Start).with_context(|| format!("Cannot write log file '{}'", log.path.display()))?;
+    *lock() = Some(log);
+    Ok(format)
+}
+
+/// 記録を終える。記録していたファイルを返す
+pub fn stop() -> Option<PathBuf> {
+    let mut log = lock().take()?;
+    let _ = log.flush_pending().and_then(|_| log.write(&Entry::End));
+    Some(log.path)
+}
+
+/// 実行したコマンドを記録する
+pub fn command(line: &str) {
+    record(|log| {
+        log.flush_pending()?;
+        log.write(&Entry::Command(line))
+    });
+}
+
+/// 停止イベントを記録する
+pub fn stop_event(event: &StopEvent) {
+    record(|log| {
+        log.flush_pending()?;
+        log.write(&Entry::Stop(event))
+    });
+}
+
+/// 画面に出した文字列を記録する（改行ごとに1レコード）
+pub fn output(stream: Stream, text: &str) {
+    record(|log| {
+        let pending = &mut log.pending[stream as usize];
+        pending.push_str(text);
+        if !pending.contains('\n') {
+            return Ok(());
+        }
+        let buffered = std::mem::take(pending);
+        let (lines, rest) = buffered.rsplit_once('\n').unwrap_or_default();
+        log.pending[stream as usize] = rest.to_string();
+        for line in lines.split('\n') {
+            log.write(&Entry::Output(stream, line))?;
+        }
+        Ok(())
+    });
+}
+
+/// 記録中なら `f` を実行する。書き込みに失敗したら記録をやめる
+fn record(f: impl FnOnce(&mut SessionLog) -> std::io::Result<()>) {
+    let mut guard = lock();
+    let Some(log) = guard.as_mut() else {
+        return;
+    };
+    if let Err(e) = f(log) {
+        // ここでは置き換え後の eprintln! を使わない（ロック中に再入してしまう）
+        std::eprintln!("Warning: stopped logging to {}: {}", log.path.display(), e);
+        *guard = None;
+    }
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Option<SessionLog>> {
+    LOG.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// 1レコードを改行付きの文字列にする
+fn format_entry(format: LogFormat, time: SystemTime, entry: &Entry) -> String {
+    let time = utc_timestamp(time);
+    match format {
+        LogFormat::Text => {
+            let body = match entry {
+                Entry::Start => format!("# kokia {} logging started", env!("CARGO_PKG_VERSION")),
+                Entry::End => "# logging stopped".to_string(),
+                Entry::Command(line) => format!("> {}", line),
+                Entry::Stop(event) => {
+                    let fields: Vec<String> = stop_fields(event).into_iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                    format!("* stop {}", fields.join(" "))
+                }
+                Entry::Output(Stream::Stdout, text) => format!("| {}", text),
+                Entry::Output(Stream::Stderr, text) => format!("! {}", text),
+            };
+            format!("{} {}\n", time, body)
+        }
+        LogFormat::Jsonl => {
+            let mut record = json!({ "time": time });
+            match entry {
+                Entry::Start => {
+                    record["kind"] = json!("start");
+                    record["version"] = json!(env!("CARGO_PKG_VERSION"));
+                }
+                Entry::End => record["kind"] = json!("end"),
+                Entry::Command(line) => {
+                    record["kind"] = json!("command");
+                    record["text"] = json!(line);
+                }
+                Entry::Stop(event) => {
+                    record["kind"] = json!("stop");
+                    for (key, value) in stop_fields(event) {
+                        record[key] = json!(value);
+                    }
+                }
+                Entry::Output(stream, text) => {
+                    record["kind"] = json!("output");
+                    record["stream"] = json!(stream.name());
+                    record["text"] = json!(text);
+                }
+            }
+            format!("{}\n", record)
+        }
+    }
+}
+
+/// 停止イベントのうち記録する項目（詳細は出力側に残るので、検索に使う分だけ）
+fn stop_fields(event: &StopEvent) -> Vec<(&'static str, String)> {
+    let mut fields = vec![("reason", format!("{:?}", event.reason))];
+    if let Some(tid) = event.tid {
+        fields.push(("thread", tid.to_string()));
+    }
+    if let Some(pc) = event.pc {
+        fields.push(("pc", format!("0x{:x}", pc)));
+    }
+    if let Some((id, bp_type)) = event.breakpoint {
+        fields.push(("breakpoint", format!("{}:{:?}", id, bp_type)));
+    }
+    if let Some(task) = event.task {
+        fields.push(("task", format!("0x{:x}", task)));
+    }
+    fields
+}
+
+/// `2026-10-14T08:30:12.345Z` 形式の UTC 時刻
+fn utc_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let rem = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// 1970-01-01 からの日数を (年, 月, 日) にする（グレゴリオ暦）
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kokia_core::{BreakpointType, StopReason};
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_entry() {
+        // 2024-02-29T23:59:58.250Z（閏日）
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_251_198_250);
+        assert_eq!(utc_timestamp(time), "2024-02-29T23:59:58.250Z");
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+
+        assert_eq!(
+            format_entry(LogFormat::Text, time, &Entry::Command("break main")),
+            "2024-02-29T23:59:58.250Z > break main\n"
+        );
+        let mut event = StopEvent::new(StopReason::Breakpoint);
+        event.tid = Some(42);
+        event.pc = Some(0x1000);
+        event.breakpoint = Some((3, BreakpointType::AsyncEntry));
+        assert_eq!(
+            format_entry(LogFormat::Text, time, &Entry::Stop(&event)),
+            "2024-02-29T23:59:58.250Z * stop reason=Breakpoint thread=42 pc=0x1000 breakpoint=3:AsyncEntry\n"
+        );
+        assert_eq!(
+            format_entry(LogFormat::Jsonl, time, &Entry::Output(Stream::Stderr, "say \"hi\"\t\u{1b}")),
+            "{\"kind\":\"output\",\"stream\":\"stderr\",\"text\":\"say \\\"hi\\\"\\t\\u001b\",\"time\":\"2024-02-29T23:59:58.250Z\"}\n"
+        );
+        // 制御文字や BMP の外の文字もそのまま読み戻せる
+        let text = "nul \u{0} del \u{7f} crab \u{1f980} \\";
+        let line = format_entry(LogFormat::Jsonl, time, &Entry::Command(text));
+        let record: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(record["text"], text);
+        assert_eq!(record["kind"], "command");
+        assert_eq!(LogFormat::from_path(Path::new("bug.jsonl")), LogFormat::Jsonl);
+        assert_eq!(LogFormat::from_path(Path::new("kokia.log")), LogFormat::Text);
+    }
+}
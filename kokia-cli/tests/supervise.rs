@@ -0,0 +1,19 @@
+//! `kokia run --on-crash` / `--on-break` の監視を、kokia のバイナリを起動して確かめる
+
+use kokia_testsupport::{fixture_binary, OptLevel};
+use std::process::{Command, Stdio};
+
+/// `--on-break` だけのときのクラッシュは止めずにシグナルを届け、128+シグナル番号で終わる
+#[test]
+fn test_on_break_only_lets_crash_terminate() {
+    let binary = fixture_binary("crash", OptLevel::O0).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_kokia"))
+        .args(["run", "--on-break", "report"])
+        .arg(&binary)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(128 + 11), "{}", stdout);
+    assert!(stdout.contains("ended without a trigger"), "{}", stdout);
+}
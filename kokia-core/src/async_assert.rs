@@ -0,0 +1,142 @@
+//! async タスクの異常の検査（async assert）
+//!
+//! CI で結合テストに kokia をアタッチし、停滞したタスクやタスク数の増えすぎを見つけたら
+//! kokia を失敗の終了コードで終わらせるための検査です。`--batch -x` と組み合わせて使います。
+
+use kokia_async::stats::{idle_time, is_stalled};
+use kokia_async::{RunningTime, TaskInfo};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// 検査に失敗したときの kokia の終了コード（デバッグ対象の終了コードと区別しやすい値）
+pub const ASSERTION_FAILED_EXIT_CODE: i32 = 3;
+
+/// 失敗の理由に挙げるタスクの数
+const MAX_REPORTED_TASKS: usize = 5;
+
+/// async タスクについての検査
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsyncAssertion {
+    /// 実行中にこの時間以上 poll されていない未完了のタスクがない（no-stalled [--timeout 5s]）
+    NoStalled(Duration),
+    /// 未完了のタスクがこの数以下（max-tasks <n>）
+    MaxTasks(usize),
+    /// 完了せずに破棄されたタスクがない（no-dropped）
+    NoDropped,
+}
+
+impl AsyncAssertion {
+    /// `async assert` の引数をパースする
+    pub fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            ["no-stalled"] => Some(AsyncAssertion::NoStalled(kokia_async::DEFAULT_STALL_THRESHOLD)),
+            ["no-stalled", "--timeout", timeout] => parse_duration(timeout).map(AsyncAssertion::NoStalled),
+            ["max-tasks", max] => max.parse().ok().map(AsyncAssertion::MaxTasks),
+            ["no-dropped"] => Some(AsyncAssertion::NoDropped),
+            _ => None,
+        }
+    }
+
+    /// タスクを検査する（違反していればその説明、満たしていれば None）
+    ///
+    /// 停滞は `running` に記録されたデバッグ対象の実行時間だけで数えます。
+    pub fn check<'a>(&self, tasks: impl IntoIterator<Item = &'a TaskInfo>, running: &RunningTime, now: Instant) -> Option<String> {
+        let tasks: Vec<&TaskInfo> = tasks.into_iter().collect();
+        match *self {
+            AsyncAssertion::NoStalled(timeout) => {
+                let stalled: Vec<&TaskInfo> = tasks.iter().copied().filter(|task| is_stalled(task, running, now, timeout)).collect();
+                (!stalled.is_empty()).then(|| {
+                    format!("{} task(s) not polled for {:?}: {}", stalled.len(), timeout, list(&stalled, running, now))
+                })
+            }
+            AsyncAssertion::MaxTasks(max) => {
+                let live: Vec<&TaskInfo> = tasks.iter().copied().filter(|task| task.is_live()).collect();
+                (live.len() > max).then(|| format!("{} live task(s), limit is {}", live.len(), max))
+            }
+            AsyncAssertion::NoDropped => {
+                let dropped: Vec<&TaskInfo> = tasks.iter().copied().filter(|task| task.superseded && !task.completed).collect();
+                (!dropped.is_empty()).then(|| {
+                    format!("{} task(s) dropped before completing: {}", dropped.len(), list(&dropped, running, now))
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for AsyncAssertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsyncAssertion::NoStalled(timeout) => write!(f, "no-stalled --timeout {:?}", timeout),
+            AsyncAssertion::MaxTasks(max) => write!(f, "max-tasks {}", max),
+            AsyncAssertion::NoDropped => write!(f, "no-dropped"),
+        }
+    }
+}
+
+/// 違反したタスクを先頭から数件だけ並べる
+fn list(tasks: &[&TaskInfo], running: &RunningTime, now: Instant) -> String {
+    let mut items: Vec<String> = tasks.iter()
+        .take(MAX_REPORTED_TASKS)
+        .map(|task| {
+            let idle = idle_time(task, running, now).as_secs_f64();
+            format!("#{} {} (idle {:.1}s)", task.handle, task.type_name.as_deref().unwrap_or("<unknown>"), idle)
+        })
+        .collect();
+    if tasks.len() > MAX_REPORTED_TASKS {
+        items.push(format!("and {} more", tasks.len() - MAX_REPORTED_TASKS));
+    }
+    items.join(", ")
+}
+
+/// `5s`, `500ms`, `2m` 形式の時間をパースする（単位がなければ秒）
+pub(crate) fn parse_duration(input: &str) -> Option<Duration> {
+    let split = input.find(|c: char| !c.is_ascii_digit()).unwrap_or(input.len());
+    let (digits, unit) = input.split_at(split);
+    let value: u64 = digits.parse().ok()?;
+    match unit {
+        "" | "s" => Some(Duration::from_secs(value)),
+        "ms" => Some(Duration::from_millis(value)),
+        "m" => Some(Duration::from_secs(value.checked_mul(60)?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_async_assertions() {
+        assert_eq!(AsyncAssertion::parse(&["no-stalled", "--timeout", "500ms"]), Some(AsyncAssertion::NoStalled(Duration::from_millis(500))));
+        assert_eq!(AsyncAssertion::parse(&["no-stalled"]), Some(AsyncAssertion::NoStalled(Duration::from_secs(5))));
+        assert_eq!(AsyncAssertion::parse(&["max-tasks", "1000"]), Some(AsyncAssertion::MaxTasks(1000)));
+        assert_eq!(AsyncAssertion::parse(&["no-stalled", "--timeout", "5h"]), None);
+        assert_eq!(AsyncAssertion::parse(&["max-tasks"]), None);
+
+        let now = Instant::now();
+        let mut stalled = TaskInfo::new(0x1000);
+        stalled.handle = 1;
+        stalled.type_name = Some("app::serve".to_string());
+        stalled.last_seen = now - Duration::from_secs(10);
+        let mut fresh = TaskInfo::new(0x2000);
+        fresh.last_seen = now;
+        let mut done = TaskInfo::new(0x3000);
+        done.completed = true;
+        done.last_seen = now - Duration::from_secs(10);
+        let tasks = [stalled, fresh, done];
+
+        let running = RunningTime::new();
+        let failure = AsyncAssertion::NoStalled(Duration::from_secs(5)).check(&tasks, &running, now).unwrap();
+        assert!(failure.starts_with("1 task(s) not polled for 5s: #1 app::serve (idle 10.0s)"), "{}", failure);
+        assert!(AsyncAssertion::NoStalled(Duration::from_secs(30)).check(&tasks, &running, now).is_none());
+        assert!(AsyncAssertion::MaxTasks(2).check(&tasks, &running, now).is_none());
+        assert_eq!(AsyncAssertion::MaxTasks(1).check(&tasks, &running, now).unwrap(), "2 live task(s), limit is 1");
+        assert!(AsyncAssertion::NoDropped.check(&tasks, &running, now).is_none());
+
+        // 10 秒のうち 8 秒はプロンプトで止まっていた
+        let mut running = RunningTime::new();
+        running.resume(now - Duration::from_secs(10));
+        running.stop(now - Duration::from_secs(8));
+        assert!(AsyncAssertion::NoStalled(Duration::from_secs(5)).check(&tasks, &running, now).is_none());
+    }
+}
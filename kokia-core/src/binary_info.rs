@@ -0,0 +1,131 @@
+//! 読み込んだバイナリのメタデータ（info binary）
+//!
+//! ビルドID・PIE かどうか・コンパイラ・デバッグ情報のレベルと置き場所・シンボルテーブルの有無と、
+//! 関数や async fn の数をまとめます。`async enable` で何も見つからなかったときの原因の推定にも使います。
+
+use kokia_dwarf::BuildInfo;
+use std::fmt;
+use std::path::PathBuf;
+
+/// デバッグ情報の置き場所（-C split-debuginfo）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SplitDebugInfo {
+    /// 実行ファイルに埋め込まれている
+    Off,
+    /// オブジェクトごとの .dwo ファイル
+    Unpacked,
+    /// 1つにまとめた .dwp ファイル
+    Packed(PathBuf),
+    /// `objcopy --only-keep-debug` などで分けたファイル（.gnu_debuglink の名前）
+    DebugLink(String),
+}
+
+impl fmt::Display for SplitDebugInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitDebugInfo::Off => write!(f, "off (embedded)"),
+            SplitDebugInfo::Unpacked => write!(f, "unpacked (.dwo files)"),
+            SplitDebugInfo::Packed(path) => write!(f, "packed ({})", path.display()),
+            SplitDebugInfo::DebugLink(name) => write!(f, "separate file via .gnu_debuglink ({})", name),
+        }
+    }
+}
+
+/// バイナリのメタデータ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryReport {
+    pub path: PathBuf,
+    /// `.note.gnu.build-id` のビルドID（16進数）
+    pub build_id: Option<String>,
+    pub is_pie: bool,
+    /// `.symtab` が残っているか（strip されていれば false）
+    pub has_symtab: bool,
+    /// `.comment` の文字列（コンパイラとリンカのバージョン）
+    pub comment: Vec<String>,
+    pub build: BuildInfo,
+    pub split_debuginfo: SplitDebugInfo,
+    /// シンボルテーブルにある関数の数
+    pub symbols: usize,
+    /// ランタイムや std 以外の async fn の closure の数
+    pub async_closures: usize,
+}
+
+impl BinaryReport {
+    /// rustc のバージョン（DW_AT_producer になければ .comment から）
+    pub fn rustc_version(&self) -> Option<String> {
+        if !self.build.rustc_versions.is_empty() {
+            let versions: Vec<&str> = self.build.rustc_versions.keys().map(String::as_str).collect();
+            return Some(versions.join(", "));
+        }
+        self.comment.iter()
+            .find_map(|entry| entry.strip_prefix("rustc version "))
+            .map(str::to_string)
+    }
+
+    /// async fn の closure が見つからない理由の推定（見つかっていれば None）
+    pub fn missing_async_reason(&self) -> Option<&'static str> {
+        if self.async_closures > 0 {
+            return None;
+        }
+        Some(if !self.has_symtab && self.build.units == 0 {
+            "the binary is stripped (no symbol table and no debug info)"
+        } else if self.build.units == 0 {
+            "the binary has no debug info"
+        } else if self.build.looks_optimized() {
+            "the binary looks optimized; async fns were probably inlined"
+        } else {
+            "the program may not define any async fn outside its dependencies"
+        })
+    }
+}
+
+/// `.comment` セクションの NUL 区切りの文字列を取り出す（重複は除く）
+pub fn parse_comment(data: &[u8]) -> Vec<String> {
+    let mut entries: Vec<String> = Vec::new();
+    for entry in data.split(|&b| b == 0) {
+        let entry = String::from_utf8_lossy(entry).trim().to_string();
+        if !entry.is_empty() && !entries.contains(&entry) {
+            entries.push(entry);
+        }
+    }
+    entries
+}
+
+/// `.gnu_debuglink` の先頭にあるファイル名を取り出す（後ろは CRC32）
+pub fn parse_debuglink(data: &[u8]) -> Option<String> {
+    let name = data.split(|&b| b == 0).next()?;
+    (!name.is_empty()).then(|| String::from_utf8_lossy(name).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_metadata() {
+        let comment = b"\0rustc version 1.95.0 (59807616e 2026-04-14)\0GCC: (Ubuntu 13.2.0) 13.2.0\0Linker: LLD 19.1\0GCC: (Ubuntu 13.2.0) 13.2.0\0";
+        let comment = parse_comment(comment);
+        assert_eq!(comment.len(), 3);
+        assert_eq!(parse_debuglink(b"server.debug\0\0\0\0\x12\x34\x56\x78"), Some("server.debug".to_string()));
+        assert_eq!(parse_debuglink(b"\0\0\0\0"), None);
+
+        let mut report = BinaryReport {
+            path: PathBuf::from("/app/server"),
+            build_id: None,
+            is_pie: true,
+            has_symtab: false,
+            comment,
+            build: BuildInfo::default(),
+            split_debuginfo: SplitDebugInfo::Off,
+            symbols: 0,
+            async_closures: 0,
+        };
+        assert_eq!(report.rustc_version().as_deref(), Some("1.95.0 (59807616e 2026-04-14)"));
+        assert_eq!(report.missing_async_reason(), Some("the binary is stripped (no symbol table and no debug info)"));
+        report.build.units = 4;
+        report.build.call_sites = 10;
+        assert_eq!(report.missing_async_reason(), Some("the binary looks optimized; async fns were probably inlined"));
+        report.async_closures = 2;
+        assert_eq!(report.missing_async_reason(), None);
+    }
+}
@@ -0,0 +1,220 @@
+//! ブレークポイントのグループ（bpgroup）
+//!
+//! よく使う計装の組に名前を付けて定義し、まとめて有効化・無効化します。グループが覚えるのは
+//! ブレークポイントの ID ではなく置き方（`break` の場所や async 計装のパターン）なので、
+//! プロセスを起動し直しても有効化し直せ、ファイルに保存して別のセッションで読み込めます。
+
+use crate::{BreakpointId, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// 保存形式のバージョン（互換性のない変更をしたら上げる）
+pub const GROUP_FORMAT_VERSION: u32 = 1;
+
+/// グループの要素
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupMember {
+    /// `break <loc>`（`*` `?` を含むか `/.../` ならシンボルのパターンとして全一致に置く）
+    Break(String),
+    /// `async enable --filter <pattern>`
+    Async(String),
+}
+
+impl GroupMember {
+    /// `break <loc>` / `b <loc>` / `async enable --filter <pattern>` をパースする
+    pub fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            ["break" | "b", loc @ ..] if !loc.is_empty() => Some(GroupMember::Break(loc.join(" "))),
+            ["async", "enable", "--filter", pattern @ ..] if !pattern.is_empty() => {
+                Some(GroupMember::Async(pattern.join(" ")))
+            }
+            _ => None,
+        }
+    }
+
+    /// `break` の場所がシンボルのパターンか
+    pub fn is_pattern(loc: &str) -> bool {
+        loc.contains(['*', '?']) || (loc.len() > 1 && loc.starts_with('/') && loc.ends_with('/'))
+    }
+}
+
+impl fmt::Display for GroupMember {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupMember::Break(loc) => write!(f, "break {}", loc),
+            GroupMember::Async(pattern) => write!(f, "async enable --filter {}", pattern),
+        }
+    }
+}
+
+/// グループを有効化したときに置いたもの
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupActivation {
+    /// `break` の要素で置いたブレークポイント
+    pub breakpoints: Vec<BreakpointId>,
+    /// async 計装した関数の entry ブレークポイント（計装プランの関数を指す）
+    pub async_entries: Vec<BreakpointId>,
+}
+
+impl GroupActivation {
+    pub fn extend(&mut self, other: GroupActivation) {
+        self.breakpoints.extend(other.breakpoints);
+        self.async_entries.extend(other.async_entries);
+    }
+}
+
+/// 名前付きのグループ
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BreakpointGroup {
+    pub members: Vec<GroupMember>,
+    /// 有効なら置いたもの
+    pub active: Option<GroupActivation>,
+}
+
+impl BreakpointGroup {
+    pub fn is_enabled(&self) -> bool {
+        self.active.is_some()
+    }
+}
+
+/// ファイルに保存するグループの定義
+#[derive(Debug, Serialize, Deserialize)]
+struct GroupFile {
+    version: u32,
+    groups: BTreeMap<String, Vec<GroupMember>>,
+}
+
+/// グループの一覧（名前順）
+#[derive(Debug, Clone, Default)]
+pub struct BreakpointGroups {
+    groups: BTreeMap<String, BreakpointGroup>,
+}
+
+impl BreakpointGroups {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 空のグループを作る（無効の状態で始まる）
+    pub fn create(&mut self, name: &str) -> Result<()> {
+        if self.groups.contains_key(name) {
+            return Err(anyhow::anyhow!("Breakpoint group '{}' already exists", name));
+        }
+        self.groups.insert(name.to_string(), BreakpointGroup::default());
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<&BreakpointGroup> {
+        self.groups.get(name).ok_or_else(|| anyhow::anyhow!("No breakpoint group '{}'", name))
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Result<&mut BreakpointGroup> {
+        self.groups.get_mut(name).ok_or_else(|| anyhow::anyhow!("No breakpoint group '{}'", name))
+    }
+
+    /// グループを取り除く（置いたものの削除は呼び出し側で行う）
+    pub fn remove(&mut self, name: &str) -> Result<BreakpointGroup> {
+        self.groups.remove(name).ok_or_else(|| anyhow::anyhow!("No breakpoint group '{}'", name))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &BreakpointGroup)> {
+        self.groups.iter().map(|(name, group)| (name.as_str(), group))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// 置いたものを忘れてすべて無効に戻す（プロセスが終わってブレークポイントが消えたとき）
+    pub fn deactivate_all(&mut self) {
+        for group in self.groups.values_mut() {
+            group.active = None;
+        }
+    }
+
+    /// 定義を JSON にする（有効かどうかは保存しない）
+    pub fn to_json(&self) -> Result<String> {
+        let file = GroupFile {
+            version: GROUP_FORMAT_VERSION,
+            groups: self.groups.iter().map(|(name, group)| (name.clone(), group.members.clone())).collect(),
+        };
+        Ok(serde_json::to_string_pretty(&file)?)
+    }
+
+    /// JSON の定義を読み込み、同じ名前のグループの要素を置き換える
+    ///
+    /// 有効なグループは置き換えられないので、先に無効化してから読み込みます。
+    ///
+    /// # Returns
+    /// 読み込んだグループの名前
+    pub fn merge_json(&mut self, json: &str) -> Result<Vec<String>> {
+        let file: GroupFile = serde_json::from_str(json)?;
+        if file.version != GROUP_FORMAT_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported breakpoint group format version {} (this kokia reads version {})",
+                file.version, GROUP_FORMAT_VERSION
+            ));
+        }
+        if let Some(name) = file.groups.keys().find(|name| self.groups.get(*name).is_some_and(BreakpointGroup::is_enabled)) {
+            return Err(anyhow::anyhow!("Breakpoint group '{}' is enabled; disable it before loading", name));
+        }
+        let names = file.groups.keys().cloned().collect();
+        for (name, members) in file.groups {
+            self.groups.insert(name, BreakpointGroup { members, active: None });
+        }
+        Ok(names)
+    }
+
+    /// ファイルに書き出す
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_json()? + "\n")
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// ファイルから読み込む
+    pub fn load(&mut self, path: &Path) -> Result<Vec<String>> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        self.merge_json(&json).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_groups_round_trip_definitions() {
+        let mut groups = BreakpointGroups::new();
+        groups.create("io").unwrap();
+        assert!(groups.create("io").is_err());
+
+        let member = GroupMember::parse(&["break", "tokio::net::*"]).unwrap();
+        assert!(matches!(&member, GroupMember::Break(loc) if GroupMember::is_pattern(loc)));
+        assert!(!GroupMember::is_pattern("main.rs:10"));
+        assert!(GroupMember::is_pattern("/db::.*query/"));
+        groups.get_mut("io").unwrap().members.push(member);
+        let member = GroupMember::parse(&["async", "enable", "--filter", "app::io::*"]).unwrap();
+        assert_eq!(member.to_string(), "async enable --filter app::io::*");
+        groups.get_mut("io").unwrap().members.push(member);
+        assert_eq!(GroupMember::parse(&["async", "enable"]), None);
+        assert_eq!(GroupMember::parse(&["watch", "0x10"]), None);
+
+        let json = groups.to_json().unwrap();
+        let mut loaded = BreakpointGroups::new();
+        assert_eq!(loaded.merge_json(&json).unwrap(), vec!["io".to_string()]);
+        assert_eq!(loaded.get("io").unwrap().members, groups.get("io").unwrap().members);
+
+        // 有効なグループは読み込みで上書きしない
+        loaded.get_mut("io").unwrap().active = Some(GroupActivation::default());
+        assert!(loaded.merge_json(&json).is_err());
+        loaded.deactivate_all();
+        assert!(loaded.merge_json(&json).is_ok());
+        assert!(loaded.merge_json(&json.replace("\"version\": 1", "\"version\": 9")).is_err());
+        assert!(loaded.remove("io").is_ok());
+        assert!(loaded.get("io").is_err());
+    }
+}
@@ -1,7 +1,8 @@
 //! ブレークポイント管理
 
 use crate::Result;
-use kokia_target::{Memory, SoftwareBreakpoint};
+use kokia_async::TaskId;
+use kokia_target::{SoftwareBreakpoint, TargetBackend, TargetMemory};
 use std::collections::HashMap;
 
 /// ブレークポイントID
@@ -18,6 +19,20 @@ pub enum BreakpointType {
     AsyncExit,
     /// テンポラリブレークポイント（next/finishコマンド用）
     Temporary,
+    /// パニックハンドラの内部ブレークポイント（パニック位置を控えて実行を続ける）
+    PanicHandler,
+    /// パニックのキャッチポイント（catch panic）
+    PanicCatch,
+    /// catch_unwind がパニックを受け止めた所の内部ブレークポイント（async catch poison）
+    PanicCleanup,
+    /// Err を返す関数の ret 命令のキャッチポイント（catch err）
+    ErrReturn,
+    /// 式の値を出力して止まらずに続けるログポイント（logpoint）
+    Logpoint,
+    /// 関数トレースの入口（ftrace）
+    TraceEntry,
+    /// 関数トレースの ret 命令（ftrace）
+    TraceExit,
 }
 
 /// ブレークポイント
@@ -27,6 +42,10 @@ pub struct Breakpoint {
     pub address: u64,
     pub enabled: bool,
     pub bp_type: BreakpointType,
+    /// このスレッドで当たったときだけ止まる（`break ... thread N`）
+    pub thread: Option<i32>,
+    /// この async タスクを poll している間だけ止まる（`break ... task #N`）
+    pub task: Option<TaskId>,
 }
 
 /// ブレークポイントマネージャ
@@ -48,7 +67,7 @@ impl BreakpointManager {
     }
 
     /// ブレークポイントを追加し、有効化する
-    pub fn add_and_enable(&mut self, address: u64, memory: &Memory) -> Result<BreakpointId> {
+    pub fn add_and_enable(&mut self, address: u64, memory: &dyn TargetMemory) -> Result<BreakpointId> {
         self.add_and_enable_with_type(address, memory, BreakpointType::User)
     }
 
@@ -56,7 +75,7 @@ impl BreakpointManager {
     pub fn add_and_enable_with_type(
         &mut self,
         address: u64,
-        memory: &Memory,
+        memory: &dyn TargetMemory,
         bp_type: BreakpointType,
     ) -> Result<BreakpointId> {
         let id = self.next_id;
@@ -67,6 +86,8 @@ impl BreakpointManager {
             address,
             enabled: true,
             bp_type,
+            thread: None,
+            task: None,
         };
 
         let mut sw_bp = SoftwareBreakpoint::new(address);
@@ -77,7 +98,7 @@ impl BreakpointManager {
     }
 
     /// ブレークポイントを削除し、無効化する
-    pub fn remove_and_disable(&mut self, id: BreakpointId, memory: &Memory) -> Result<()> {
+    pub fn remove_and_disable(&mut self, id: BreakpointId, memory: &dyn TargetMemory) -> Result<()> {
         if let Some((_bp, mut sw_bp)) = self.breakpoints.remove(&id) {
             sw_bp.disable(memory)?;
         }
@@ -89,6 +110,29 @@ impl BreakpointManager {
         self.breakpoints.get(&id).map(|(bp, _)| bp)
     }
 
+    /// ブレークポイントのタイプを変える（User をログポイントにするなど）
+    pub fn set_type(&mut self, id: BreakpointId, bp_type: BreakpointType) -> bool {
+        match self.breakpoints.get_mut(&id) {
+            Some((bp, _)) => {
+                bp.bp_type = bp_type;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 止まるスレッド・タスクを限定する（None は限定しない）
+    pub fn restrict(&mut self, id: BreakpointId, thread: Option<i32>, task: Option<TaskId>) -> bool {
+        match self.breakpoints.get_mut(&id) {
+            Some((bp, _)) => {
+                bp.thread = thread;
+                bp.task = task;
+                true
+            }
+            None => false,
+        }
+    }
+
     /// 全てのブレークポイントを取得する
     pub fn all(&self) -> impl Iterator<Item = &Breakpoint> {
         self.breakpoints.values().map(|(bp, _)| bp)
@@ -107,8 +151,51 @@ impl BreakpointManager {
             .map(|(bp, _)| bp.id)
     }
 
+    /// 指定範囲 `[start, end)` に含まれるブレークポイントを取得する（無効化中のものも含む）
+    pub fn find_in_range(&self, start: u64, end: u64) -> impl Iterator<Item = &Breakpoint> {
+        self.breakpoints
+            .values()
+            .map(|(bp, _)| bp)
+            .filter(move |bp| start <= bp.address && bp.address < end)
+    }
+
+    /// 指定したタイプのブレークポイントを取得する
+    pub fn by_type(&self, bp_type: BreakpointType) -> impl Iterator<Item = &Breakpoint> {
+        self.all().filter(move |bp| bp.bp_type == bp_type)
+    }
+
+    /// 指定したタイプのブレークポイントをすべて削除し、無効化する
+    ///
+    /// # Returns
+    /// 削除したブレークポイントの数
+    pub fn remove_by_type(&mut self, bp_type: BreakpointType, memory: &dyn TargetMemory) -> Result<usize> {
+        let ids: Vec<BreakpointId> = self.by_type(bp_type).map(|bp| bp.id).collect();
+        for &id in &ids {
+            self.remove_and_disable(id, memory)?;
+        }
+        Ok(ids.len())
+    }
+
+    /// 指定範囲 `[start, end)` のブレークポイントを、メモリに触れずに記録から外す
+    ///
+    /// モジュールがアンロードされて元のバイトを書き戻せなくなったときに使います。
+    pub fn forget_in_range(&mut self, start: u64, end: u64) -> Vec<Breakpoint> {
+        let ids: Vec<BreakpointId> = self.find_in_range(start, end).map(|bp| bp.id).collect();
+        ids.iter().filter_map(|id| self.breakpoints.remove(id)).map(|(bp, _)| bp).collect()
+    }
+
+    /// メモリから読んだバイト列のうち、有効なブレークポイントの INT3 を元のバイトに戻す
+    pub fn restore_original_bytes(&self, address: u64, bytes: &mut [u8]) {
+        let end = address + bytes.len() as u64;
+        for (bp, sw_bp) in self.breakpoints.values() {
+            if bp.enabled && sw_bp.is_enabled() && address <= bp.address && bp.address < end {
+                bytes[(bp.address - address) as usize] = sw_bp.original_byte();
+            }
+        }
+    }
+
     /// ブレークポイントを一時的に無効化する
-    pub fn disable_temporarily(&mut self, id: BreakpointId, memory: &Memory) -> Result<()> {
+    pub fn disable_temporarily(&mut self, id: BreakpointId, memory: &dyn TargetMemory) -> Result<()> {
         if let Some((bp, sw_bp)) = self.breakpoints.get_mut(&id) {
             if bp.enabled {
                 sw_bp.disable(memory)?;
@@ -118,8 +205,22 @@ impl BreakpointManager {
         Ok(())
     }
 
+    /// PC にある有効なブレークポイントを一時的に外して1命令だけ進め、張り直す
+    ///
+    /// # Returns
+    /// ブレークポイントをまたいだか（PC にブレークポイントがなければ何もしない）
+    pub fn step_over(&mut self, target: &dyn TargetBackend) -> Result<bool> {
+        let Some(id) = self.find_by_address(target.pc()?) else {
+            return Ok(false);
+        };
+        self.disable_temporarily(id, target)?;
+        target.step()?;
+        self.reenable(id, target)?;
+        Ok(true)
+    }
+
     /// ブレークポイントを再有効化する
-    pub fn reenable(&mut self, id: BreakpointId, memory: &Memory) -> Result<()> {
+    pub fn reenable(&mut self, id: BreakpointId, memory: &dyn TargetMemory) -> Result<()> {
         if let Some((bp, sw_bp)) = self.breakpoints.get_mut(&id) {
             if !bp.enabled {
                 sw_bp.enable(memory)?;
@@ -135,3 +236,42 @@ impl Default for BreakpointManager {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kokia_target::{FakeTarget, StopReason};
+
+    #[test]
+    fn test_breakpoint_bookkeeping() {
+        let target = FakeTarget::new();
+        target.map(0x4000, vec![0x55, 0x48, 0x89, 0xe5, 0xc3]);
+        let mut manager = BreakpointManager::new();
+
+        let id = manager.add_and_enable(0x4001, &target).unwrap();
+        assert_eq!(target.read_memory(0x4000, 3).unwrap(), vec![0x55, 0xcc, 0x89]);
+        assert!(manager.add_and_enable(0x9000, &target).is_err());
+        let mut bytes = target.read_memory(0x4000, 5).unwrap();
+        manager.restore_original_bytes(0x4000, &mut bytes);
+        assert_eq!(bytes, vec![0x55, 0x48, 0x89, 0xe5, 0xc3]);
+
+        // 当たったら PC を INT3 の位置に戻してからまたぐ
+        target.will_execute(0x4001);
+        assert_eq!(target.resume().unwrap(), StopReason::Breakpoint);
+        target.set_pc(target.pc().unwrap() - 1).unwrap();
+        assert!(manager.step_over(&target).unwrap());
+        assert_eq!((target.steps(), target.pc().unwrap()), (1, 0x4002));
+        assert_eq!(target.read_memory(0x4001, 1).unwrap(), vec![0xcc]);
+        assert!(!manager.step_over(&target).unwrap());
+
+        // 一時的に外している間は検索に出てこない
+        manager.disable_temporarily(id, &target).unwrap();
+        assert_eq!(manager.find_by_address(0x4001), None);
+        manager.reenable(id, &target).unwrap();
+        assert_eq!(manager.find_by_address(0x4001), Some(id));
+
+        manager.remove_and_disable(id, &target).unwrap();
+        assert_eq!(target.read_memory(0x4001, 1).unwrap(), vec![0x48]);
+        assert_eq!(manager.count(), 0);
+    }
+}
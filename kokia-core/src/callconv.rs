@@ -0,0 +1,945 @@
+//! 関数の入口での引数と、戻るときの戻り値の読み出し（x86-64 System V の呼び出し規約）
+//!
+//! DWARF の仮引数の型のレイアウトから、各引数を 8 バイト（eightbyte）ごとに INTEGER か SSE に分類し、
+//! rdi, rsi, rdx, rcx, r8, r9 と xmm0〜7 に宣言順に割り当てます。レジスタが足りない引数と
+//! 16 バイトを超える引数はスタック（入口の rsp + 8 から）に置かれます。
+//!
+//! Rust ABI の関数は少し違います。スカラー1つか2つからなる値（`&str`、`Option<u64>`、`Poll<u32>` など）は
+//! スカラーごとに別のレジスタを使い、そのほかの 16 バイト以下の値は整数レジスタに詰めます。
+//! 16 バイトを超える値は呼び出し側のコピーへのポインタで渡すので、その分はポインタ1つとして数えます。
+//! 戻り値も同じ分け方で RAX・RDX と xmm0・xmm1 に入り、大きな戻り値は返却先のポインタが最初の整数レジスタを使います。
+
+use iced_x86::{Decoder, DecoderOptions, InstructionInfoFactory, Mnemonic, OpAccess, OpKind, Register};
+use kokia_dwarf::{DecodeConfig, DisplayValue, MemberLayout, TypeKind, TypeLayout, ValueDecoder};
+use kokia_target::UserRegs;
+use std::collections::HashMap;
+
+/// 引数に使う整数レジスタの数（rdi, rsi, rdx, rcx, r8, r9）
+pub const INTEGER_ARGUMENT_REGISTERS: usize = 6;
+
+/// 引数に使う SSE レジスタの数（xmm0〜7）
+pub const SSE_ARGUMENT_REGISTERS: usize = 8;
+
+/// レジスタで渡せる値の大きさの上限
+const MAX_REGISTER_VALUE_SIZE: u64 = 16;
+
+/// 文字列の引数を表示するときに読む長さの上限
+const MAX_STR_BYTES: usize = 64;
+
+/// レジスタの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgClass {
+    Integer,
+    Sse,
+}
+
+/// 値のうちレジスタ1つに載る部分
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterPiece {
+    pub class: ArgClass,
+    /// その種類のレジスタの中の番号（classify の結果では 0）
+    pub index: usize,
+    /// 値の中のオフセットと大きさ（レジスタの下位バイトに入る）
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// 引数の置き場所
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgLocation {
+    Registers(Vec<RegisterPiece>),
+    /// 入口の rsp からのオフセット
+    Stack(u64),
+    /// 値へのポインタが整数レジスタにある（Rust ABI の大きな値）
+    IndirectRegister(usize),
+    /// 値へのポインタがスタックにある
+    IndirectStack(u64),
+}
+
+/// 値の中のスカラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Leaf {
+    offset: usize,
+    size: Option<usize>,
+    is_float: bool,
+}
+
+/// 値を、レジスタ1つずつに載る部分に分ける（レジスタで渡せない大きさなら None）
+pub fn classify(layout: &TypeLayout, rust_abi: bool) -> Option<Vec<RegisterPiece>> {
+    let size = layout.size?;
+    if size > MAX_REGISTER_VALUE_SIZE {
+        return None;
+    }
+    let size = size as usize;
+    let mut leaves = Vec::new();
+    collect_leaves(layout, 0, &mut leaves);
+    leaves.sort_by_key(|leaf| leaf.offset);
+    leaves.dedup();
+    let piece = |class, offset, size| RegisterPiece { class, index: 0, offset, size };
+    let class_of = |is_float| if is_float { ArgClass::Sse } else { ArgClass::Integer };
+
+    if rust_abi {
+        // スカラー1つか2つ（enum ならタグと、どの variant でも同じ位置のスカラー1つ）ならスカラーごと
+        let scalars: Option<Vec<RegisterPiece>> = leaves.iter()
+            .map(|leaf| Some(piece(class_of(leaf.is_float), leaf.offset, leaf.size?)))
+            .collect();
+        if let Some(scalars) = scalars.filter(|scalars| (1..=2).contains(&scalars.len()) && is_disjoint(scalars)) {
+            return Some(scalars);
+        }
+        let eightbytes = (0..size.div_ceil(8)).map(|i| piece(ArgClass::Integer, i * 8, (size - i * 8).min(8)));
+        return Some(eightbytes.collect());
+    }
+
+    let eightbytes = (0..size.div_ceil(8))
+        .map(|i| {
+            let range = i * 8..(i + 1) * 8;
+            let mut overlapping = leaves.iter().filter(|leaf| range.contains(&leaf.offset)).peekable();
+            // 浮動小数点数だけからなる eightbyte が SSE
+            let is_float = overlapping.peek().is_some() && overlapping.all(|leaf| leaf.is_float);
+            piece(class_of(is_float), i * 8, (size - i * 8).min(8))
+        });
+    Some(eightbytes.collect())
+}
+
+fn is_disjoint(pieces: &[RegisterPiece]) -> bool {
+    pieces.windows(2).all(|pair| pair[0].offset + pair[0].size <= pair[1].offset)
+}
+
+/// 値の中のスカラーを集める
+fn collect_leaves(layout: &TypeLayout, base: usize, leaves: &mut Vec<Leaf>) {
+    // enum は discriminant とすべての variant のフィールドを重ねる
+    let members: Vec<&MemberLayout> = layout.members.iter()
+        .chain(&layout.discriminant)
+        .chain(layout.variants.iter().flat_map(|variant| &variant.members))
+        .collect();
+    if members.is_empty() {
+        if layout.size != Some(0) {
+            leaves.push(Leaf { offset: base, size: layout.size.map(|size| size as usize), is_float: is_float(&layout.name) });
+        }
+        return;
+    }
+    for member in members {
+        let offset = base + member.offset.unwrap_or(0) as usize;
+        match &member.layout {
+            Some(inner) => collect_leaves(inner, offset, leaves),
+            None if member_size(member) == Some(0) => {}
+            None => leaves.push(Leaf {
+                offset,
+                size: member_size(member).map(|size| size as usize),
+                is_float: is_float(&member.type_name),
+            }),
+        }
+    }
+}
+
+fn is_float(type_name: &str) -> bool {
+    matches!(type_name, "f32" | "f64")
+}
+
+/// フィールドの大きさ（ポインタのフィールドは大きさも展開したレイアウトも持たないことがある）
+fn member_size(member: &MemberLayout) -> Option<u64> {
+    member.size
+        .or_else(|| member.layout.as_ref().and_then(|layout| layout.size))
+        .or_else(|| (member.type_name.ends_with('*') || member.type_name.starts_with('&')).then_some(8))
+}
+
+/// 戻り値が返却先のポインタ経由で返されるか
+pub fn returns_in_memory(layout: &TypeLayout) -> bool {
+    layout.size.is_some_and(|size| size > MAX_REGISTER_VALUE_SIZE)
+}
+
+/// Rust ABI の関数か（マングルされた名前で見分ける）
+pub fn is_rust_abi(mangled_name: &str) -> bool {
+    mangled_name.starts_with("_ZN") || mangled_name.starts_with("_R")
+}
+
+/// 引数を宣言順にレジスタとスタックに割り当てる
+#[derive(Debug, Clone)]
+pub struct ArgumentAssigner {
+    rust_abi: bool,
+    integer: usize,
+    sse: usize,
+    stack: u64,
+}
+
+impl ArgumentAssigner {
+    /// `rust_abi` は Rust ABI の関数か、`indirect_return` は戻り値をメモリで返すか
+    pub fn new(rust_abi: bool, indirect_return: bool) -> Self {
+        // スタック上の引数は戻りアドレスの上から
+        Self { rust_abi, integer: usize::from(indirect_return), sse: 0, stack: 8 }
+    }
+
+    /// 次の引数の置き場所（大きさ 0 の値は何も使わないので None）
+    pub fn assign(&mut self, layout: &TypeLayout) -> Option<ArgLocation> {
+        let size = layout.size?;
+        if size == 0 {
+            return None;
+        }
+        match classify(layout, self.rust_abi) {
+            Some(pieces) => {
+                let integers = pieces.iter().filter(|piece| piece.class == ArgClass::Integer).count();
+                let sses = pieces.len() - integers;
+                if self.integer + integers > INTEGER_ARGUMENT_REGISTERS || self.sse + sses > SSE_ARGUMENT_REGISTERS {
+                    return Some(ArgLocation::Stack(self.take_stack(size)));
+                }
+                let pieces = pieces.into_iter()
+                    .map(|piece| {
+                        let counter = match piece.class {
+                            ArgClass::Integer => &mut self.integer,
+                            ArgClass::Sse => &mut self.sse,
+                        };
+                        *counter += 1;
+                        RegisterPiece { index: *counter - 1, ..piece }
+                    })
+                    .collect();
+                Some(ArgLocation::Registers(pieces))
+            }
+            None if self.rust_abi && self.integer < INTEGER_ARGUMENT_REGISTERS => {
+                self.integer += 1;
+                Some(ArgLocation::IndirectRegister(self.integer - 1))
+            }
+            None if self.rust_abi => Some(ArgLocation::IndirectStack(self.take_stack(8))),
+            None => Some(ArgLocation::Stack(self.take_stack(size))),
+        }
+    }
+
+    fn take_stack(&mut self, size: u64) -> u64 {
+        let offset = self.stack;
+        self.stack += size.div_ceil(8) * 8;
+        offset
+    }
+}
+
+/// レジスタの下位バイトから値を組み立てる
+fn assemble(
+    pieces: &[RegisterPiece],
+    size: usize,
+    mut register: impl FnMut(ArgClass, usize) -> Option<[u8; 16]>,
+) -> Option<Vec<u8>> {
+    let mut bytes = vec![0u8; size];
+    for piece in pieces {
+        let value = register(piece.class, piece.index)?;
+        let end = (piece.offset + piece.size).min(size);
+        bytes.get_mut(piece.offset..end)?.copy_from_slice(&value[..end - piece.offset]);
+    }
+    Some(bytes)
+}
+
+fn widen(value: u64) -> [u8; 16] {
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&value.to_le_bytes());
+    bytes
+}
+
+/// 戻り値の各部分が入るレジスタ（メモリで返す値なら None）
+fn return_registers(layout: &TypeLayout, rust_abi: bool) -> Option<Vec<RegisterPiece>> {
+    let mut pieces = classify(layout, rust_abi)?;
+    let (mut integers, mut sses) = (0, 0);
+    for piece in &mut pieces {
+        let counter = if piece.class == ArgClass::Integer { &mut integers } else { &mut sses };
+        piece.index = *counter;
+        *counter += 1;
+    }
+    Some(pieces)
+}
+
+/// 戻り値レジスタ（RAX・RDX と xmm0・xmm1）の値
+fn return_register(regs: &UserRegs, xmm: &[[u8; 16]], class: ArgClass, index: usize) -> Option<[u8; 16]> {
+    match class {
+        ArgClass::Integer => [regs.rax, regs.rdx].get(index).copied().map(widen),
+        ArgClass::Sse => xmm.get(index).copied(),
+    }
+}
+
+/// 関数から戻るときの戻り値のバイト列を読む（ret 命令の上か、戻った直後のレジスタで）
+///
+/// メモリで返す値は RAX が返却先を指します。
+pub fn read_return_value(
+    layout: &TypeLayout,
+    rust_abi: bool,
+    regs: &UserRegs,
+    xmm: &[[u8; 16]],
+    read_memory: &mut dyn FnMut(u64, usize) -> Option<Vec<u8>>,
+) -> Option<Vec<u8>> {
+    let size = layout.size? as usize;
+    match return_registers(layout, rust_abi) {
+        Some(pieces) => assemble(&pieces, size, |class, index| return_register(regs, xmm, class, index)),
+        None => read_memory(regs.rax, size),
+    }
+}
+
+/// `Poll<T>` の discriminant が戻り値のどこにあるか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscriminantLocation {
+    /// 戻り値レジスタの中（レジスタの中のバイトオフセット）
+    Register { class: ArgClass, index: usize, offset: usize },
+    /// 返却先（RAX が指すメモリ）からのオフセット
+    Memory(usize),
+}
+
+/// poll 関数の戻り値 `Poll<T>` から Ready かどうかを読むための情報
+///
+/// `T` によって Poll のタグは専用のフィールドだったり、`T` の使っていない値（niche）だったりし、
+/// 戻り値も RAX だけ・RAX と RDX・メモリ経由と変わるので、関数ごとに DWARF から一度だけ求めます。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PollDiscriminant {
+    pub location: DiscriminantLocation,
+    pub size: usize,
+    /// Ready・Pending の discriminant 値（niche の場合、値を持つ Ready は None）
+    pub ready: Option<u64>,
+    pub pending: Option<u64>,
+}
+
+impl PollDiscriminant {
+    /// 戻り値の型のレイアウトから求める（`Poll<T>` でなければ None）
+    pub fn analyze(layout: &TypeLayout, rust_abi: bool) -> Option<Self> {
+        if !short_type_name(&layout.name).starts_with("Poll<") {
+            return None;
+        }
+        let variant = |name: &str| layout.variants.iter().find(|variant| variant.name == name);
+        let (ready, pending) = (variant("Ready")?.discriminant, variant("Pending")?.discriminant);
+        let discriminant = layout.discriminant.as_ref()?;
+        let (offset, size) = (discriminant.offset? as usize, discriminant.size? as usize);
+        if size > 8 || (ready.is_none() && pending.is_none()) {
+            return None;
+        }
+        let location = match return_registers(layout, rust_abi) {
+            Some(pieces) => {
+                let piece = pieces.iter()
+                    .find(|piece| piece.offset <= offset && offset + size <= piece.offset + piece.size)?;
+                DiscriminantLocation::Register { class: piece.class, index: piece.index, offset: offset - piece.offset }
+            }
+            None => DiscriminantLocation::Memory(offset),
+        };
+        Some(Self { location, size, ready, pending })
+    }
+
+    /// 戻り値から Ready かどうかを読む
+    pub fn read(
+        &self,
+        regs: &UserRegs,
+        xmm: &[[u8; 16]],
+        read_memory: &mut dyn FnMut(u64, usize) -> Option<Vec<u8>>,
+    ) -> Option<bool> {
+        let value = match self.location {
+            DiscriminantLocation::Register { class, index, offset } => {
+                little_endian(return_register(regs, xmm, class, index)?.get(offset..offset + self.size)?)
+            }
+            DiscriminantLocation::Memory(offset) => little_endian(&read_memory(regs.rax + offset as u64, self.size)?),
+        };
+        Some(self.is_ready(value))
+    }
+
+    /// RAX だけから Ready かどうかを読む（uprobe の戻りイベントのように他のレジスタがないとき）
+    pub fn read_rax(&self, rax: u64) -> Option<bool> {
+        match self.location {
+            DiscriminantLocation::Register { class: ArgClass::Integer, index: 0, offset } => {
+                Some(self.is_ready(little_endian(widen(rax).get(offset..offset + self.size)?)))
+            }
+            _ => None,
+        }
+    }
+
+    fn is_ready(&self, value: u64) -> bool {
+        match (self.ready, self.pending) {
+            (Some(ready), _) => value == ready,
+            // niche: Pending の値でなければ Ready
+            (None, Some(pending)) => value != pending,
+            (None, None) => false,
+        }
+    }
+}
+
+/// 関数の入口での引数レジスタとスタックポインタ（値が分からなくなったレジスタは None）
+#[derive(Debug, Clone, Default)]
+pub struct EntryRegisters {
+    /// rdi, rsi, rdx, rcx, r8, r9
+    pub integer: [Option<u64>; INTEGER_ARGUMENT_REGISTERS],
+    /// xmm0〜7
+    pub sse: [Option<[u8; 16]>; SSE_ARGUMENT_REGISTERS],
+    /// 入口での rsp（[rsp] が戻りアドレス）
+    pub rsp: u64,
+}
+
+impl EntryRegisters {
+    /// 置き場所から `size` バイトの値を読む
+    pub fn read(
+        &self,
+        location: &ArgLocation,
+        size: usize,
+        read_memory: &mut dyn FnMut(u64, usize) -> Option<Vec<u8>>,
+    ) -> Option<Vec<u8>> {
+        match location {
+            ArgLocation::Registers(pieces) => assemble(pieces, size, |class, index| match class {
+                ArgClass::Integer => self.integer.get(index).copied().flatten().map(widen),
+                ArgClass::Sse => self.sse.get(index).copied().flatten(),
+            }),
+            ArgLocation::Stack(offset) => read_memory(self.rsp + offset, size),
+            ArgLocation::IndirectRegister(index) => read_memory(self.integer.get(*index).copied().flatten()?, size),
+            ArgLocation::IndirectStack(offset) => {
+                let pointer = read_memory(self.rsp + offset, 8)?;
+                read_memory(u64::from_le_bytes(pointer.try_into().ok()?), size)
+            }
+        }
+    }
+}
+
+const INTEGER_REGISTERS: [Register; INTEGER_ARGUMENT_REGISTERS] =
+    [Register::RDI, Register::RSI, Register::RDX, Register::RCX, Register::R8, Register::R9];
+
+/// xmm0〜7（iced では部分レジスタの full_register が zmm になる）
+const SSE_REGISTERS: [Register; SSE_ARGUMENT_REGISTERS] = [
+    Register::ZMM0, Register::ZMM1, Register::ZMM2, Register::ZMM3,
+    Register::ZMM4, Register::ZMM5, Register::ZMM6, Register::ZMM7,
+];
+
+/// 入口での引数レジスタの値の、プロローグの後の置き場所（有効な下位バイト数つき）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgumentHome {
+    Register(Register, usize),
+    /// 入口の rsp からの距離
+    Frame(i64, usize),
+}
+
+/// プロローグを調べた結果
+#[derive(Debug, Clone)]
+pub struct Prologue {
+    /// 入口の rsp が今の rsp からいくつ上か
+    pub stack_adjustment: u64,
+    /// 引数のレジスタごとの入口の値の置き場所（分からなくなったものは含まない）
+    pub homes: HashMap<Register, ArgumentHome>,
+}
+
+impl Prologue {
+    /// 今のレジスタと xmm0〜15 の値から、入口での引数レジスタを復元する
+    pub fn entry_registers(
+        &self,
+        regs: &UserRegs,
+        xmm: &[[u8; 16]],
+        read_memory: &mut dyn FnMut(u64, usize) -> Option<Vec<u8>>,
+    ) -> EntryRegisters {
+        let rsp = regs.rsp + self.stack_adjustment;
+        let mut value = |register: Register| -> Option<[u8; 16]> {
+            let (mut bytes, width) = match *self.homes.get(&register)? {
+                ArgumentHome::Register(home, width) if home.is_zmm() => (*xmm.get(home.number())?, width),
+                ArgumentHome::Register(home, width) => {
+                    let mut bytes = [0u8; 16];
+                    bytes[..8].copy_from_slice(&general_register(regs, home)?.to_le_bytes());
+                    (bytes, width)
+                }
+                ArgumentHome::Frame(offset, width) => {
+                    let mut bytes = [0u8; 16];
+                    bytes[..width].copy_from_slice(&read_memory(rsp.wrapping_add_signed(offset), width)?);
+                    (bytes, width)
+                }
+            };
+            bytes[width..].fill(0);
+            Some(bytes)
+        };
+        EntryRegisters {
+            integer: INTEGER_REGISTERS.map(|register| value(register).map(|bytes| little_endian(&bytes[..8]))),
+            sse: SSE_REGISTERS.map(&mut value),
+            rsp,
+        }
+    }
+}
+
+fn general_register(regs: &UserRegs, register: Register) -> Option<u64> {
+    Some(match register {
+        Register::RAX => regs.rax,
+        Register::RBX => regs.rbx,
+        Register::RCX => regs.rcx,
+        Register::RDX => regs.rdx,
+        Register::RSI => regs.rsi,
+        Register::RDI => regs.rdi,
+        Register::R8 => regs.r8,
+        Register::R9 => regs.r9,
+        Register::R10 => regs.r10,
+        Register::R11 => regs.r11,
+        Register::R12 => regs.r12,
+        Register::R13 => regs.r13,
+        Register::R14 => regs.r14,
+        Register::R15 => regs.r15,
+        _ => return None,
+    })
+}
+
+/// 関数の先頭からプロローグの後（`code` の終わり）までの命令を追い、引数のレジスタの入口の値がどこへ移ったかを求める
+///
+/// レジスタ間の mov とフレームへの退避・読み戻しだけを追い、それ以外で書き換えられたレジスタの値は
+/// 分からなくなったものとします。push と `sub rsp, imm` 以外で rsp を動かす命令があれば None です。
+pub fn trace_prologue(code: &[u8], ip: u64) -> Option<Prologue> {
+    let mut decoder = Decoder::with_ip(64, code, ip, DecoderOptions::NONE);
+    let mut factory = InstructionInfoFactory::new();
+    // 今のレジスタ → (入口の値が入っている引数レジスタ, 有効なバイト数)
+    let mut registers: HashMap<Register, (Register, usize)> = INTEGER_REGISTERS.iter()
+        .map(|register| (*register, (*register, 8)))
+        .chain(SSE_REGISTERS.iter().map(|register| (*register, (*register, 16))))
+        .collect();
+    // 入口の rsp からの距離 → 退避された値
+    let mut frame: HashMap<i64, (Register, usize)> = HashMap::new();
+    let mut adjustment = 0u64;
+    // rbp から入口の rsp までの距離（mov rbp, rsp の後だけ分かる）
+    let mut rbp_offset: Option<i64> = None;
+
+    while decoder.can_decode() {
+        let instruction = decoder.decode();
+        if instruction.is_invalid() {
+            return None;
+        }
+        let slot = frame_slot(&instruction, adjustment, rbp_offset);
+        match (instruction.mnemonic(), instruction.op0_kind(), instruction.op1_kind()) {
+            (Mnemonic::Push, _, _) => {
+                adjustment += 8;
+                continue;
+            }
+            (Mnemonic::Sub, OpKind::Register, OpKind::Immediate8to64 | OpKind::Immediate32to64)
+                if instruction.op0_register() == Register::RSP =>
+            {
+                adjustment += instruction.immediate(1);
+                continue;
+            }
+            (Mnemonic::Mov, OpKind::Register, OpKind::Register)
+                if instruction.op0_register() == Register::RBP && instruction.op1_register() == Register::RSP =>
+            {
+                rbp_offset = Some(-(adjustment as i64));
+                registers.remove(&Register::RBP);
+                continue;
+            }
+            (mnemonic, OpKind::Register, OpKind::Register) if is_move(mnemonic) && !is_partial_write(&instruction) => {
+                let width = move_width(mnemonic, instruction.op0_register().size());
+                let source = registers.get(&instruction.op1_register().full_register()).map(|(home, w)| (*home, width.min(*w)));
+                set(&mut registers, instruction.op0_register().full_register(), source);
+                continue;
+            }
+            (mnemonic, OpKind::Memory, OpKind::Register) if is_move(mnemonic) && slot.is_some() => {
+                let width = instruction.memory_size().size();
+                let source = registers.get(&instruction.op1_register().full_register()).map(|(home, w)| (*home, width.min(*w)));
+                set(&mut frame, slot.unwrap_or_default(), source);
+                continue;
+            }
+            (mnemonic, OpKind::Register, OpKind::Memory) if is_move(mnemonic) && slot.is_some() && !is_partial_write(&instruction) => {
+                let width = instruction.memory_size().size();
+                let source = frame.get(&slot.unwrap_or_default()).map(|(home, w)| (*home, width.min(*w)));
+                set(&mut registers, instruction.op0_register().full_register(), source);
+                continue;
+            }
+            _ => {}
+        }
+        if let Some(slot) = slot {
+            frame.remove(&slot);
+        }
+        for used in factory.info(&instruction).used_registers() {
+            let writes = matches!(
+                used.access(),
+                OpAccess::Write | OpAccess::CondWrite | OpAccess::ReadWrite | OpAccess::ReadCondWrite
+            );
+            if !writes {
+                continue;
+            }
+            let register = used.register().full_register();
+            if register == Register::RSP {
+                return None;
+            }
+            registers.remove(&register);
+        }
+    }
+
+    // 自分自身に残っていればそれを、なければ退避先（メモリは後で書き換えられにくい）を、最後に別のレジスタを使う
+    let mut homes = HashMap::new();
+    for argument in INTEGER_REGISTERS.iter().chain(SSE_REGISTERS.iter()) {
+        let in_itself = registers.get(argument).filter(|(home, _)| home == argument)
+            .map(|(_, width)| ArgumentHome::Register(*argument, *width));
+        let in_frame = || frame.iter().filter(|(_, (home, _))| home == argument)
+            .max_by_key(|(offset, (_, width))| (*width, -**offset))
+            .map(|(offset, (_, width))| ArgumentHome::Frame(*offset, *width));
+        let elsewhere = || registers.iter().filter(|(_, (home, _))| home == argument)
+            .max_by_key(|(register, (_, width))| (*width, std::cmp::Reverse(register.number())))
+            .map(|(register, (_, width))| ArgumentHome::Register(*register, *width));
+        if let Some(home) = in_itself.or_else(in_frame).or_else(elsewhere) {
+            homes.insert(*argument, home);
+        }
+    }
+    Some(Prologue { stack_adjustment: adjustment, homes })
+}
+
+fn set<K: std::hash::Hash + Eq>(map: &mut HashMap<K, (Register, usize)>, key: K, value: Option<(Register, usize)>) {
+    match value {
+        Some(value) => map.insert(key, value),
+        None => map.remove(&key),
+    };
+}
+
+/// rsp か rbp からの距離で指したフレーム上の場所（入口の rsp からの距離）
+fn frame_slot(instruction: &iced_x86::Instruction, adjustment: u64, rbp_offset: Option<i64>) -> Option<i64> {
+    let has_memory = (0..instruction.op_count()).any(|i| instruction.op_kind(i) == OpKind::Memory);
+    if !has_memory || instruction.memory_index() != Register::None {
+        return None;
+    }
+    let displacement = instruction.memory_displacement64() as i64;
+    match instruction.memory_base() {
+        Register::RSP => Some(displacement - adjustment as i64),
+        Register::RBP => rbp_offset.map(|offset| offset + displacement),
+        _ => None,
+    }
+}
+
+fn is_move(mnemonic: Mnemonic) -> bool {
+    matches!(
+        mnemonic,
+        Mnemonic::Mov | Mnemonic::Movq | Mnemonic::Movd | Mnemonic::Movss | Mnemonic::Movsd
+            | Mnemonic::Movaps | Mnemonic::Movups | Mnemonic::Movapd | Mnemonic::Movupd
+            | Mnemonic::Movdqa | Mnemonic::Movdqu
+    )
+}
+
+/// 8・16 ビットのレジスタへの書き込みは上位を残すので、値の移動として追わない
+fn is_partial_write(instruction: &iced_x86::Instruction) -> bool {
+    instruction.op0_register().is_gpr8() || instruction.op0_register().is_gpr16()
+}
+
+/// 移動で運ばれるバイト数
+fn move_width(mnemonic: Mnemonic, register_size: usize) -> usize {
+    match mnemonic {
+        Mnemonic::Movss | Mnemonic::Movd => 4,
+        Mnemonic::Movsd | Mnemonic::Movq => 8,
+        _ => register_size,
+    }
+}
+
+/// 値のバイト列をレイアウトに従って表示する
+///
+/// 文字列スライスは中身を読み、構造体と enum はフィールドを並べます。読めない型は 16 進数にします。
+pub fn format_value(bytes: &[u8], layout: &TypeLayout, read_memory: &mut dyn FnMut(u64, usize) -> Option<Vec<u8>>) -> String {
+    let decoder = ValueDecoder::new(DecodeConfig::default());
+    let member_bytes = |member: &MemberLayout| {
+        let start = member.offset.unwrap_or(0) as usize;
+        bytes.get(start..start + member_size(member).unwrap_or(0) as usize)
+    };
+
+    if layout.name == "&str" || layout.name == "&mut str" {
+        let field = |name: &str| layout.members.iter().find(|m| m.name == name)
+            .and_then(member_bytes)
+            .and_then(|b| Some(u64::from_le_bytes(b.try_into().ok()?)));
+        if let (Some(pointer), Some(length)) = (field("data_ptr"), field("length")) {
+            let shown = (length as usize).min(MAX_STR_BYTES);
+            return match read_memory(pointer, shown) {
+                Some(text) => {
+                    let ellipsis = if shown < length as usize { "..." } else { "" };
+                    format!("\"{}{}\"", String::from_utf8_lossy(&text).escape_debug(), ellipsis)
+                }
+                None => format!("<str at 0x{:x}, len {}>", pointer, length),
+            };
+        }
+    }
+
+    match layout.kind {
+        TypeKind::Base => match decoder.decode_primitive(bytes, &layout.name) {
+            DisplayValue::Unavailable => hex(bytes),
+            value => value.to_string(),
+        },
+        TypeKind::Pointer => hex(bytes),
+        TypeKind::Array => format_array(bytes, &layout.name, &decoder).unwrap_or_else(|| hex(bytes)),
+        TypeKind::Enum if !layout.enumerators.is_empty() => {
+            let value = little_endian(bytes) as i64;
+            match layout.enumerators.iter().find(|(_, v)| *v == value) {
+                Some((name, _)) => name.clone(),
+                None => value.to_string(),
+            }
+        }
+        _ if !layout.variants.is_empty() => {
+            let Some(variant) = layout.active_variant(bytes) else {
+                return format!("{} {}", layout.name, hex(bytes));
+            };
+            let fields: Vec<String> = variant.members.iter()
+                .map(|member| format_member(member_bytes(member), member, read_memory))
+                .collect();
+            let name = short_type_name(&layout.name);
+            let name = &name[..name.find('<').unwrap_or(name.len())];
+            if fields.is_empty() {
+                format!("{}::{}", name, variant.name)
+            } else {
+                format!("{}::{}({})", name, variant.name, fields.join(", "))
+            }
+        }
+        _ if !layout.members.is_empty() => {
+            let fields: Vec<String> = layout.members.iter()
+                .map(|member| {
+                    let value = format_member(member_bytes(member), member, read_memory);
+                    if member.name.starts_with("__") {
+                        value
+                    } else {
+                        format!("{}: {}", member.name, value)
+                    }
+                })
+                .collect();
+            format!("{} {{ {} }}", short_type_name(&layout.name), fields.join(", "))
+        }
+        _ if layout.size == Some(0) => short_type_name(&layout.name).to_string(),
+        _ => hex(bytes),
+    }
+}
+
+fn format_member(
+    bytes: Option<&[u8]>,
+    member: &MemberLayout,
+    read_memory: &mut dyn FnMut(u64, usize) -> Option<Vec<u8>>,
+) -> String {
+    let Some(bytes) = bytes else {
+        return "?".to_string();
+    };
+    match &member.layout {
+        Some(layout) => format_value(bytes, layout, read_memory),
+        // `()` などの大きさ 0 の値
+        None if bytes.is_empty() => short_type_name(&member.type_name).to_string(),
+        None => {
+            let decoder = ValueDecoder::new(DecodeConfig::default());
+            match decoder.decode_primitive(bytes, &member.type_name) {
+                DisplayValue::Unavailable => format_array(bytes, &member.type_name, &decoder).unwrap_or_else(|| hex(bytes)),
+                value => value.to_string(),
+            }
+        }
+    }
+}
+
+/// `[T; N]` の要素が基本型なら要素ごとに表示する
+fn format_array(bytes: &[u8], type_name: &str, decoder: &ValueDecoder) -> Option<String> {
+    let (element, length) = type_name.strip_prefix('[')?.strip_suffix(']')?.rsplit_once("; ")?;
+    let length: usize = length.parse().ok()?;
+    if length == 0 {
+        return Some("[]".to_string());
+    }
+    let element_size = bytes.len() / length;
+    let elements: Option<Vec<String>> = bytes.chunks_exact(element_size.max(1))
+        .take(length)
+        .map(|chunk| match decoder.decode_primitive(chunk, element) {
+            DisplayValue::Unavailable => None,
+            value => Some(value.to_string()),
+        })
+        .collect();
+    Some(format!("[{}]", elements?.join(", ")))
+}
+
+/// 型名のパスの最後の要素（ジェネリクス引数は残す）
+fn short_type_name(name: &str) -> &str {
+    let path_end = name.find('<').unwrap_or(name.len());
+    match name[..path_end].rfind("::") {
+        Some(pos) => &name[pos + 2..],
+        None => name,
+    }
+}
+
+fn little_endian(bytes: &[u8]) -> u64 {
+    let mut raw = [0u8; 8];
+    let len = bytes.len().min(8);
+    raw[..len].copy_from_slice(&bytes[..len]);
+    u64::from_le_bytes(raw)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    if bytes.len() <= 8 {
+        format!("0x{:x}", little_endian(bytes))
+    } else {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kokia_dwarf::{MemberLayout, VariantLayout};
+
+    fn base(name: &str, size: u64) -> TypeLayout {
+        TypeLayout {
+            name: name.to_string(),
+            kind: TypeKind::Base,
+            size: Some(size),
+            members: Vec::new(),
+            discriminant: None,
+            variants: Vec::new(),
+            enumerators: Vec::new(),
+        }
+    }
+
+    fn structure(name: &str, fields: &[(&str, u64, TypeLayout)]) -> TypeLayout {
+        let members: Vec<MemberLayout> = fields.iter()
+            .map(|(field, offset, layout)| MemberLayout {
+                name: field.to_string(),
+                offset: Some(*offset),
+                size: layout.size,
+                type_name: layout.name.clone(),
+                layout: Some(Box::new(layout.clone())),
+            })
+            .collect();
+        let size = fields.iter().map(|(_, offset, layout)| offset + layout.size.unwrap()).max().unwrap_or(0);
+        TypeLayout { name: name.to_string(), kind: TypeKind::Struct, size: Some(size), members, ..base(name, 0) }
+    }
+
+    #[test]
+    fn test_assign_and_read_arguments() {
+        let str_slice = structure("&str", &[("data_ptr", 0, base("*const u8", 8)), ("length", 8, base("usize", 8))]);
+        let point = structure("app::Point", &[("x", 0, base("f64", 8)), ("y", 8, base("f64", 8))]);
+        let big = structure("app::Big", &[("a", 0, base("u64", 8)), ("b", 8, base("u64", 8)), ("c", 16, base("u64", 8))]);
+        let pair = structure("app::Pair", &[("a", 0, base("u32", 4)), ("b", 4, base("u32", 4))]);
+        let piece = |class, index, offset, size| RegisterPiece { class, index, offset, size };
+        use ArgClass::{Integer, Sse};
+        assert_eq!(classify(&point, false), Some(vec![piece(Sse, 0, 0, 8), piece(Sse, 0, 8, 8)]));
+        assert_eq!(classify(&big, false), None);
+        // C ABI は eightbyte ごと、Rust ABI はスカラーごとにレジスタを使う
+        assert_eq!(classify(&pair, false), Some(vec![piece(Integer, 0, 0, 8)]));
+        assert_eq!(classify(&pair, true), Some(vec![piece(Integer, 0, 0, 4), piece(Integer, 0, 4, 4)]));
+
+        // fn f(name: &str, p: Point, big: Big, n: i32) （Rust ABI）
+        let mut assigner = ArgumentAssigner::new(true, false);
+        let locations: Vec<ArgLocation> = [&str_slice, &point, &big, &base("i32", 4)].iter()
+            .map(|layout| assigner.assign(layout).unwrap())
+            .collect();
+        assert_eq!(locations, vec![
+            ArgLocation::Registers(vec![piece(Integer, 0, 0, 8), piece(Integer, 1, 8, 8)]),
+            ArgLocation::Registers(vec![piece(Sse, 0, 0, 8), piece(Sse, 1, 8, 8)]),
+            ArgLocation::IndirectRegister(2),
+            ArgLocation::Registers(vec![piece(Integer, 3, 0, 4)]),
+        ]);
+        // C ABI では大きな値はスタックに置かれ、戻り値の返却先が rdi を使う
+        let mut assigner = ArgumentAssigner::new(false, true);
+        assert_eq!(assigner.assign(&big), Some(ArgLocation::Stack(8)));
+        assert_eq!(assigner.assign(&base("u8", 1)), Some(ArgLocation::Registers(vec![piece(Integer, 1, 0, 1)])));
+
+        let mut regs = EntryRegisters { rsp: 0x7000, ..EntryRegisters::default() };
+        regs.integer[0] = Some(0x5000);
+        regs.integer[1] = Some(3);
+        regs.integer[3] = Some((-5i32) as u32 as u64);
+        let xmm = |value: f64| {
+            let mut bytes = [0u8; 16];
+            bytes[..8].copy_from_slice(&value.to_le_bytes());
+            Some(bytes)
+        };
+        regs.sse[0] = xmm(1.5);
+        regs.sse[1] = xmm(-2.0);
+        let mut memory = |address: u64, len: usize| (address == 0x5000).then(|| b"bob"[..len].to_vec());
+
+        let bytes = regs.read(&locations[0], 16, &mut memory).unwrap();
+        assert_eq!(format_value(&bytes, &str_slice, &mut memory), "\"bob\"");
+        let bytes = regs.read(&locations[1], 16, &mut memory).unwrap();
+        assert_eq!(format_value(&bytes, &point, &mut memory), "Point { x: 1.5, y: -2 }");
+        let bytes = regs.read(&locations[3], 4, &mut memory).unwrap();
+        assert_eq!(format_value(&bytes, &base("i32", 4), &mut memory), "-5");
+    }
+
+    #[test]
+    fn test_read_poll_return_value() {
+        let tag = MemberLayout { name: "tag".to_string(), offset: Some(0), size: Some(4), type_name: "u32".to_string(), layout: None };
+        let variant = |name: &str, discriminant: u64, members: Vec<MemberLayout>| VariantLayout {
+            discriminant: Some(discriminant),
+            name: name.to_string(),
+            members,
+            decl_file: None,
+            decl_line: None,
+        };
+        let value = MemberLayout { name: "__0".to_string(), offset: Some(4), size: Some(4), type_name: "i32".to_string(), layout: None };
+        let poll = TypeLayout {
+            name: "core::task::poll::Poll<i32>".to_string(),
+            kind: TypeKind::Struct,
+            size: Some(8),
+            discriminant: Some(tag),
+            variants: vec![variant("Ready", 0, vec![value]), variant("Pending", 1, Vec::new())],
+            ..base("", 0)
+        };
+        let mut regs: UserRegs = unsafe { std::mem::zeroed() };
+        let mut memory = |_: u64, _: usize| None;
+
+        // Rust ABI ではタグが RAX、値が RDX に入る
+        regs.rdx = 7;
+        let bytes = read_return_value(&poll, true, &regs, &[], &mut memory).unwrap();
+        assert_eq!(format_value(&bytes, &poll, &mut memory), "Poll::Ready(7)");
+        regs.rax = 1;
+        let bytes = read_return_value(&poll, true, &regs, &[], &mut memory).unwrap();
+        assert_eq!(format_value(&bytes, &poll, &mut memory), "Poll::Pending");
+        regs.rax = 7 << 32;
+        let bytes = read_return_value(&poll, false, &regs, &[], &mut memory).unwrap();
+        assert_eq!(format_value(&bytes, &poll, &mut memory), "Poll::Ready(7)");
+
+        // 16 バイトを超える値は RAX が指すメモリから読む
+        let big = structure("app::Big", &[("a", 0, base("u64", 8)), ("b", 8, base("u64", 8)), ("c", 16, base("u64", 8))]);
+        regs.rax = 0x5000;
+        let mut memory = |address: u64, len: usize| (address == 0x5000).then(|| vec![1; len]);
+        assert_eq!(read_return_value(&big, true, &regs, &[], &mut memory), Some(vec![1; 24]));
+    }
+
+    #[test]
+    fn test_poll_discriminant_locations() {
+        let member = |name: &str, offset: u64, size: u64, type_name: &str| MemberLayout {
+            name: name.to_string(),
+            offset: Some(offset),
+            size: Some(size),
+            type_name: type_name.to_string(),
+            layout: None,
+        };
+        let variant = |name: &str, discriminant: Option<u64>, members: Vec<MemberLayout>| VariantLayout {
+            discriminant,
+            name: name.to_string(),
+            members,
+            decl_file: None,
+            decl_line: None,
+        };
+        let poll = |name: &str, size: u64, discriminant: MemberLayout, ready: Option<u64>, pending: Option<u64>, value: MemberLayout| TypeLayout {
+            name: name.to_string(),
+            kind: TypeKind::Struct,
+            size: Some(size),
+            discriminant: Some(discriminant),
+            variants: vec![variant("Ready", ready, vec![value]), variant("Pending", pending, Vec::new())],
+            ..base("", 0)
+        };
+        let mut regs: UserRegs = unsafe { std::mem::zeroed() };
+        let mut memory = |address: u64, len: usize| (address == 0x5000 + 24).then(|| vec![1; len]);
+
+        // Poll<u64>: タグは RAX、値は RDX
+        let tagged = poll("Poll<u64>", 16, member("tag", 0, 8, "u64"), Some(0), Some(1), member("__0", 8, 8, "u64"));
+        let found = PollDiscriminant::analyze(&tagged, true).unwrap();
+        assert_eq!(found.location, DiscriminantLocation::Register { class: ArgClass::Integer, index: 0, offset: 0 });
+        regs.rdx = 1;
+        assert_eq!(found.read(&regs, &[], &mut memory), Some(true));
+        assert_eq!(found.read_rax(1), Some(false));
+
+        // Poll<bool>: Pending は bool の使っていない値 2（niche）
+        let niche = poll("Poll<bool>", 1, member("tag", 0, 1, "u8"), None, Some(2), member("__0", 0, 1, "bool"));
+        let found = PollDiscriminant::analyze(&niche, true).unwrap();
+        assert_eq!(found.read_rax(0x1), Some(true));
+        assert_eq!(found.read_rax(0x2), Some(false));
+        // 戻り値レジスタの上位に残ったごみは見ない
+        assert_eq!(found.read_rax(0xff00), Some(true));
+
+        // Poll<[u64; 3]> はメモリで返り、タグは値の後ろにある
+        let big = poll("Poll<[u64; 3]>", 32, member("tag", 24, 8, "u64"), Some(0), Some(1), member("__0", 0, 24, "[u64; 3]"));
+        let found = PollDiscriminant::analyze(&big, true).unwrap();
+        assert_eq!(found.location, DiscriminantLocation::Memory(24));
+        regs.rax = 0x5000;
+        assert_eq!(found.read(&regs, &[], &mut memory), Some(false));
+        assert_eq!(found.read_rax(0x5000), None);
+
+        assert!(PollDiscriminant::analyze(&base("u32", 4), true).is_none());
+    }
+
+    #[test]
+    fn test_trace_prologue_follows_spilled_arguments() {
+        // push rbp; mov rbp, rsp; sub rsp, 0x50; mov [rbp-0x4c], edx; mov rax, rsi; mov esi, [rbp-0x4c]
+        // mov [rbp-0x48], rdi; mov [rbp-0x40], rax; mov [rbp-0xc], esi
+        let code = [
+            0x55, 0x48, 0x89, 0xe5, 0x48, 0x83, 0xec, 0x50, 0x89, 0x55, 0xb4, 0x48, 0x89, 0xf0, 0x8b, 0x75, 0xb4,
+            0x48, 0x89, 0x7d, 0xb8, 0x48, 0x89, 0x45, 0xc0, 0x89, 0x75, 0xf4,
+        ];
+        let prologue = trace_prologue(&code, 0x1000).unwrap();
+        assert_eq!(prologue.stack_adjustment, 0x58);
+        assert_eq!(prologue.homes[&Register::RDI], ArgumentHome::Register(Register::RDI, 8));
+        // rsi は rax を経てフレームに退避され、rsi 自体は rdx の値で上書きされた
+        assert_eq!(prologue.homes[&Register::RSI], ArgumentHome::Frame(-0x48, 8));
+        assert_eq!(prologue.homes[&Register::RDX], ArgumentHome::Register(Register::RDX, 8));
+        assert_eq!(prologue.homes[&Register::ZMM0], ArgumentHome::Register(Register::ZMM0, 16));
+
+        // xor edi, edi で rdi の入口の値は失われる
+        let prologue = trace_prologue(&[0x55, 0x31, 0xff], 0x1000).unwrap();
+        assert!(!prologue.homes.contains_key(&Register::RDI));
+        // call は rsp を動かす
+        assert!(trace_prologue(&[0xe8, 0, 0, 0, 0], 0x1000).is_none());
+    }
+}
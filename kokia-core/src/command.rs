@@ -1,61 +1,349 @@
 //! デバッガコマンド
 
+use crate::async_assert::AsyncAssertion;
+use crate::bpgroup::GroupMember;
+use crate::future_name::NameStyle;
+use crate::profile::DEFAULT_PROFILE_FREQUENCY;
+use crate::telemetry::ExportConfig;
+use crate::task_query::{TaskQuery, TaskSort, TaskState};
+use crate::trace::{TraceMode, DEFAULT_TRACE_CAPACITY};
+use kokia_async::{EdgeFilter, TaskRef};
+use kokia_target::WatchKind;
+use std::time::Duration;
+
+/// ブレークポイントで止まるスレッド・タスクの限定（`break <loc> thread N task #M`）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BreakContext {
+    /// `info threads` の1始まりのスレッド番号
+    pub thread: Option<usize>,
+    pub task: Option<TaskRef>,
+}
+
 /// デバッガコマンド
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Command {
     /// ブレークポイントを設定
-    Break(String),
+    Break(String, BreakContext),
+    /// 当たると式を埋めた書式を出力して続けるログポイントを設定（場所, 書式）
+    Logpoint(String, String),
+    /// ブレークポイントのグループを作る（bpgroup create <name>）
+    BpGroupCreate(String),
+    /// グループに要素を加える（bpgroup add <name> break <loc> | async enable --filter <pattern>）
+    BpGroupAdd(String, GroupMember),
+    /// グループの要素をまとめて置く
+    BpGroupEnable(String),
+    /// グループで置いたものをまとめて外す
+    BpGroupDisable(String),
+    /// グループを削除する
+    BpGroupDelete(String),
+    /// グループの一覧を表示（bpgroup list）
+    BpGroupList,
+    /// グループの定義をファイルに保存する
+    BpGroupSave(String),
+    /// ファイルからグループの定義を読み込む
+    BpGroupLoad(String),
+    /// ウォッチポイントを設定（アドレス式, 長さ, 監視するアクセス）
+    Watch(String, usize, WatchKind),
+    /// ウォッチポイントを解除（引数なしなら全解除）
+    Unwatch(Option<usize>),
     /// 実行継続
     Continue,
     /// ステップ実行
     Step,
+    /// 直前にステップ実行した命令を N 個巻き戻す
+    ReverseStepi(usize),
+    /// 命令トレースを開始（記録するもの, バッファの件数）
+    TraceStart(TraceMode, usize),
+    /// 命令トレースを止めて分岐の集計を表示
+    TraceStop,
+    /// 命令トレースの最後の N 件を表示
+    TraceShow(usize),
+    /// 命令トレースをファイルに書き出す
+    TraceExport(String),
+    /// Intel PT による分岐の記録を開始
+    TraceHwStart,
+    /// Intel PT の記録を止めて集計を表示
+    TraceHwStop,
+    /// パターンにマッチする関数の出入りをトレースする
+    Ftrace(String),
+    /// トレース中の関数を表示
+    FtraceList,
+    /// 関数トレースの呼び出しログの最後の N 件を表示
+    FtraceLog(usize),
+    /// 関数トレースの呼び出しログを空にする
+    FtraceClear,
+    /// 関数トレースをやめる（パターン省略時はすべて）
+    FtraceStop(Option<String>),
+    /// continue 中の PC のサンプリングを開始（周波数 Hz）
+    ProfileStart(u64),
+    /// サンプリングを止めて集計を表示
+    ProfileStop,
+    /// サンプリングの集計を表示
+    ProfileReport,
     /// 次の行へ
     Next,
     /// 現在の関数から抜けるまで実行
     Finish,
     /// バックトレース表示
     Backtrace,
-    /// ローカル変数表示
+    /// 各フレームの引数とローカル変数を付けたバックトレース表示（bt full）
+    BacktraceFull,
+    /// ローカル変数表示（引数は含まない）
     Locals,
-    /// 式を評価して値を表示
+    /// 現在のフレームの引数表示（info args）
+    InfoArgs,
+    /// 式を評価して値を表示（結果は履歴 `$n` に残る）
     Print(String),
+    /// コンビニエンス変数に式の値を入れる（set $name = <expr>）
+    SetVariable(String, String),
+    /// 型、または式の型のレイアウトを表示（ptype）
+    PrintType(String),
+    /// 式の宣言型のみを表示（whatis）
+    WhatIs(String),
+    /// メモリを8バイト単位で表示（個数, アドレス式）
+    Examine(usize, String),
+    /// 停止のたびに式を表示（引数なしなら登録済みの式を今すぐ表示）
+    Display(Option<String>),
+    /// 自動表示を解除（引数なしなら全解除）
+    Undisplay(Option<usize>),
     /// 論理スタック（awaitチェーン）表示
     AsyncBacktrace,
-    /// async関数のローカル変数表示
-    AsyncLocals,
+    /// 全ルートタスクの論理スタック表示
+    AsyncBacktraceAll,
+    /// asyncタスクのローカル変数表示（async locals [task]、None は選択中のタスク）
+    AsyncLocals(Option<TaskRef>),
     /// asyncタスク一覧表示
     AsyncTasks,
-    /// asyncエッジ（親子関係）表示
-    AsyncEdges,
-    /// asyncトラッキングを有効化（GenFuture::pollにブレークポイント設定）
-    AsyncEnable,
-    /// ヘルプ表示
-    Help,
+    /// 追跡中のタスクの状態機械のサイズを集計
+    AsyncMemsize,
+    /// タスクの寿命の分布と完了の統計
+    AsyncLifetimes,
+    /// asyncタスクを関数名・状態・経過時間で絞り込んで表示
+    AsyncFind(TaskQuery),
+    /// タスクの異常を検査し、違反していれば kokia を失敗の終了コードで終える（async assert）
+    AsyncAssert(AsyncAssertion),
+    /// asyncエッジ（親子関係）表示（--active / --root / --since で絞り込み）
+    AsyncEdges(EdgeFilter),
+    /// asyncタスクを親子関係の木で表示（完了した部分木はまとめる）
+    AsyncTree(EdgeFilter),
+    /// asyncトラッキングを有効化（GenFuture::pollにブレークポイント設定、フィルタ指定可）
+    AsyncEnable(Option<String>),
+    /// パターンにマッチするasync関数の計装を外す
+    AsyncDisable(String),
+    /// async トラッキングをすべて止める（true なら集めたデータを読み取り専用で残す）
+    AsyncDisableAll(bool),
+    /// async 関数の entry を N 回に1回だけ記録（パターン, N）。引数なしなら現在の設定を表示
+    AsyncSample(Option<(String, u64)>),
+    /// uprobe による async イベント収集を開始（フィルタ指定可）
+    AsyncUprobeStart(Option<String>),
+    /// 指定秒数だけ実行して uprobe のイベントを集める
+    AsyncUprobeCollect(u64),
+    /// uprobe を外して収集を終了
+    AsyncUprobeStop,
+    /// 追跡中の async タスク・エッジをすべて破棄
+    AsyncClear,
+    /// poll 中のパニックでタスクが Panicked になったら止まる（async catch poison）
+    AsyncCatchPoison,
+    /// async イベントの外部への送出を始める（async export webhook|otlp <url>）
+    AsyncExportStart(ExportConfig),
+    /// async イベントの送出を止める（async export stop）
+    AsyncExportStop,
+    /// 送出の設定と送信の状況を表示（async export）
+    AsyncExportStatus,
+    /// タスク・poll の集計と計装で止まっていた時間を表示（async stats）
+    AsyncStats,
+    /// 型名を短縮せずに async の表示コマンドを実行（`--raw` を外したコマンド）
+    AsyncRawNames(String),
+    /// 全スレッドでコマンドを実行
+    ThreadApplyAll(String),
+    /// 指定番号（1始まり）のスレッドでコマンドを実行
+    ThreadApply(usize, String),
+    /// 名前がパターンに一致するスレッドでコマンドを実行（thread apply <pattern> <cmd>）
+    ThreadApplyMatching(String, String),
+    /// スレッド一覧を表示（info threads [pattern]）
+    InfoThreads(Option<String>),
+    /// inferior を追加して選択し、バイナリがあれば起動する（add-inferior [<binary> [args...]]）
+    AddInferior { binary: Option<String>, args: Vec<String> },
+    /// inferior を選択（inferior <n>）
+    Inferior(usize),
+    /// 全 inferior でコマンドを実行（inferior apply all <cmd>）
+    InferiorApplyAll(String),
+    /// inferior 一覧を表示（info inferiors）
+    InfoInferiors,
+    /// デバッグ対象プロセスの情報を表示（info proc）
+    InfoProc,
+    /// メモリ保護属性を変更（アドレス式, 長さ, 属性 `rwx`）
+    MemProtect(String, u64, String),
+    /// アドレスにバイト列を書き込む（アドレス式, `90 90` 形式のバイト列または `asm <命令列>`）
+    Patch(String, String),
+    /// パッチを元に戻す（引数なしなら全て）
+    Unpatch(Option<usize>),
+    /// 状態機械の型の variant と discriminant 値の一覧を表示（info variants）
+    InfoVariants(String),
+    /// 適用中のパッチ一覧を表示（info patches）
+    InfoPatches,
+    /// 設定中のウォッチポイント一覧を表示（info watchpoints）
+    InfoWatchpoints,
+    /// リンクされている async ランタイムとスケジューラを表示（info async-runtime）
+    InfoAsyncRuntime,
+    /// 読み込んだバイナリのビルドID・コンパイラ・デバッグ情報などを表示（info binary）
+    InfoBinary,
+    /// シンボルテーブルを実行中のイメージのメモリから読み直す（symbol-file-from-memory）
+    SymbolFileFromMemory,
+    /// 停止時の async サマリー表示を切り替え（set async summary on|off）
+    SetAsyncSummary(bool),
+    /// async トレースモードを切り替え（set async trace on|off）
+    SetAsyncTrace(bool),
+    /// async fn の entry の親をスコープスタックから推定するか（set async fast-entry on|off）
+    SetAsyncFastEntry(bool),
+    /// async の表示での型名の短縮を切り替え（set async names short|raw）
+    SetAsyncNames(NameStyle),
+    /// 型名のジェネリクス引数をまとめるか（set async names generics on|off）
+    SetAsyncNameGenerics(bool),
+    /// 追跡するタスク数の上限を設定（set async retention max-tasks <n>|off）
+    SetAsyncRetentionMaxTasks(Option<usize>),
+    /// 完了したタスクを保持する秒数を設定（set async retention max-age <秒>|off）
+    SetAsyncRetentionMaxAge(Option<u64>),
+    /// DWARF 検索の制限時間を設定（set query-timeout <秒>|off）
+    SetQueryTimeout(Option<u64>),
+    /// 停止中のプロセスのコアファイルを書き出す（gcore [path]）
+    GenerateCore(Option<String>),
+    /// PID かプロセス名でアタッチし直す（attach [--wait] <pid|name>）
+    Attach { target: String, wait: bool },
+    /// ブレークポイント・設定・async タスクを JSON に書き出す（export-state <path>）
+    ExportState(String),
+    /// export-state で書き出した状態を読み込む（import-state <path>）
+    ImportState(String),
+    /// 致命的なシグナルでの停止時のクラッシュレポートを切り替え（set crash-report on|off）
+    SetCrashReport(bool),
+    /// Rust のパニックで止まるキャッチポイントを設定（catch panic）
+    CatchPanic,
+    /// 関数が Err を返すときに止まるキャッチポイントを設定（catch err <function>）
+    CatchErr(String),
+    /// step でスキップする関数の接頭辞を設定（set step-filter <prefix>...|off|default、None は既定に戻す）
+    SetStepFilter(Option<Vec<String>>),
+    /// コマンド・停止イベント・出力をファイルに記録（set logging on [file]|off、None は停止）
+    SetLogging(Option<String>),
+    /// ヘルプ表示（help [command]）
+    Help(Option<String>),
     /// 終了
     Quit,
 }
 
 impl Command {
+    /// 空行で繰り返す実行制御コマンドか（gdb と同じく Enter で続けて step できる）
+    pub fn repeats_on_empty_line(&self) -> bool {
+        matches!(self, Command::Continue | Command::Step | Command::Next)
+    }
+
+    /// タスクの型名を表示する async のコマンドか（`--raw` を付けられる）
+    pub fn shows_future_names(&self) -> bool {
+        matches!(
+            self,
+            Command::AsyncBacktrace
+                | Command::AsyncBacktraceAll
+                | Command::AsyncTasks
+                | Command::AsyncMemsize
+                | Command::AsyncLifetimes
+                | Command::AsyncFind(_)
+                | Command::AsyncTree(_)
+        )
+    }
+
     /// コマンド文字列をパースする
     pub fn parse(input: &str) -> Option<Self> {
-        let parts: Vec<&str> = input.trim().split_whitespace().collect();
+        let parts: Vec<&str> = input.split_whitespace().collect();
         if parts.is_empty() {
             return None;
         }
 
         match parts[0] {
             "break" | "b" => {
+                // 末尾の `thread N` / `task <task>` を限定として取り出す
+                let mut location = &parts[1..];
+                let mut context = BreakContext::default();
+                while let [rest @ .., keyword, value] = location {
+                    match *keyword {
+                        "thread" if context.thread.is_none() => context.thread = Some(value.parse().ok()?),
+                        "task" if context.task.is_none() => context.task = Some(parse_task_ref(value)?),
+                        _ => break,
+                    }
+                    location = rest;
+                }
+                if location.is_empty() {
+                    None
+                } else {
+                    Some(Command::Break(location.join(" "), context))
+                }
+            }
+            "logpoint" => parse_logpoint(input),
+            "bpgroup" => match parts.get(1..) {
+                Some([] | ["list"]) => Some(Command::BpGroupList),
+                Some(["create", name]) => Some(Command::BpGroupCreate(name.to_string())),
+                Some(["add", name, member @ ..]) => {
+                    GroupMember::parse(member).map(|member| Command::BpGroupAdd(name.to_string(), member))
+                }
+                Some(["enable", name]) => Some(Command::BpGroupEnable(name.to_string())),
+                Some(["disable", name]) => Some(Command::BpGroupDisable(name.to_string())),
+                Some(["delete", name]) => Some(Command::BpGroupDelete(name.to_string())),
+                Some(["save", path]) => Some(Command::BpGroupSave(path.to_string())),
+                Some(["load", path]) => Some(Command::BpGroupLoad(path.to_string())),
+                _ => None,
+            },
+            "watch" | "awatch" => {
+                let kind = if parts[0] == "watch" { WatchKind::Write } else { WatchKind::ReadWrite };
+                match parts.get(1..) {
+                    Some([addr]) => Some(Command::Watch(addr.to_string(), 8, kind)),
+                    Some([addr, len]) => len.parse().ok().map(|len| Command::Watch(addr.to_string(), len, kind)),
+                    _ => None,
+                }
+            }
+            "unwatch" => {
                 if parts.len() > 1 {
-                    Some(Command::Break(parts[1..].join(" ")))
+                    parts[1].parse().ok().map(|n| Command::Unwatch(Some(n)))
                 } else {
-                    None
+                    Some(Command::Unwatch(None))
                 }
             }
             "continue" | "c" => Some(Command::Continue),
             "step" | "s" => Some(Command::Step),
+            "reverse-stepi" | "rsi" => match parts.get(1) {
+                Some(n) => n.parse().ok().filter(|n| *n > 0).map(Command::ReverseStepi),
+                None => Some(Command::ReverseStepi(1)),
+            },
+            "trace" => match parts.get(1..) {
+                Some(["start", options @ ..]) => parse_trace_start(options),
+                Some(["stop"]) => Some(Command::TraceStop),
+                Some(["show"]) => Some(Command::TraceShow(20)),
+                Some(["show", n]) => n.parse().ok().filter(|n| *n > 0).map(Command::TraceShow),
+                Some(["export", path]) => Some(Command::TraceExport(path.to_string())),
+                Some(["hw", "start"]) => Some(Command::TraceHwStart),
+                Some(["hw", "stop"]) => Some(Command::TraceHwStop),
+                _ => None,
+            },
+            "ftrace" => match parts.get(1..) {
+                Some([]) => Some(Command::FtraceList),
+                Some(["log"]) => Some(Command::FtraceLog(50)),
+                Some(["log", n]) => n.parse().ok().filter(|n| *n > 0).map(Command::FtraceLog),
+                Some(["clear"]) => Some(Command::FtraceClear),
+                Some(["stop"]) => Some(Command::FtraceStop(None)),
+                Some(["stop", pattern @ ..]) => Some(Command::FtraceStop(Some(pattern.join(" ")))),
+                Some(pattern) => Some(Command::Ftrace(pattern.join(" "))),
+                None => None,
+            },
+            "profile" => match parts.get(1..) {
+                Some(["start"]) => Some(Command::ProfileStart(DEFAULT_PROFILE_FREQUENCY)),
+                Some(["start", hz]) => hz.parse().ok().filter(|hz| *hz > 0).map(Command::ProfileStart),
+                Some(["stop"]) => Some(Command::ProfileStop),
+                Some(["report"]) => Some(Command::ProfileReport),
+                _ => None,
+            },
             "next" | "n" => Some(Command::Next),
             "finish" | "f" => Some(Command::Finish),
-            "backtrace" | "bt" => Some(Command::Backtrace),
+            "backtrace" | "bt" => match parts.get(1) {
+                Some(&"full") => Some(Command::BacktraceFull),
+                _ => Some(Command::Backtrace),
+            },
             "locals" | "l" => Some(Command::Locals),
             "print" | "p" => {
                 if parts.len() > 1 {
@@ -64,37 +352,598 @@ impl Command {
                     None
                 }
             }
+            "ptype" => {
+                if parts.len() > 1 {
+                    Some(Command::PrintType(parts[1..].join(" ")))
+                } else {
+                    None
+                }
+            }
+            "whatis" => {
+                if parts.len() > 1 {
+                    Some(Command::WhatIs(parts[1..].join(" ")))
+                } else {
+                    None
+                }
+            }
+            "display" => {
+                if parts.len() > 1 {
+                    Some(Command::Display(Some(parts[1..].join(" "))))
+                } else {
+                    Some(Command::Display(None))
+                }
+            }
+            "undisplay" => {
+                if parts.len() > 1 {
+                    parts[1].parse().ok().map(|n| Command::Undisplay(Some(n)))
+                } else {
+                    Some(Command::Undisplay(None))
+                }
+            }
+            cmd if cmd == "x" || cmd.starts_with("x/") => {
+                if parts.len() < 2 {
+                    return None;
+                }
+                // `x/4` や `x/4x` の個数部分を取り出す（書式文字は無視）
+                let count = match cmd.strip_prefix("x/") {
+                    Some(fmt) => {
+                        let digits: String = fmt.chars().take_while(|c| c.is_ascii_digit()).collect();
+                        if digits.is_empty() { 1 } else { digits.parse().ok()? }
+                    }
+                    None => 1,
+                };
+                Some(Command::Examine(count, parts[1..].join(" ")))
+            }
+            "async" if parts[1..].contains(&"--raw") => {
+                let rest: Vec<&str> = parts.iter().copied().filter(|part| *part != "--raw").collect();
+                let rest = rest.join(" ");
+                Command::parse(&rest)
+                    .filter(Command::shows_future_names)
+                    .map(|_| Command::AsyncRawNames(rest))
+            }
             "async" => {
                 if parts.len() > 1 {
                     match parts[1] {
-                        "bt" | "backtrace" => Some(Command::AsyncBacktrace),
-                        "locals" | "l" => Some(Command::AsyncLocals),
+                        "bt" | "backtrace" => match parts.get(2) {
+                            Some(&"--all") | Some(&"-a") => Some(Command::AsyncBacktraceAll),
+                            _ => Some(Command::AsyncBacktrace),
+                        },
+                        "locals" | "l" => match parts.get(2..) {
+                            Some([]) => Some(Command::AsyncLocals(None)),
+                            Some([task]) => parse_task_ref(task).map(|t| Command::AsyncLocals(Some(t))),
+                            _ => None,
+                        },
                         "tasks" => Some(Command::AsyncTasks),
-                        "edges" => Some(Command::AsyncEdges),
-                        "enable" => Some(Command::AsyncEnable),
+                        "memsize" => Some(Command::AsyncMemsize),
+                        "lifetimes" => Some(Command::AsyncLifetimes),
+                        "find" => parse_task_query(&parts[2..]).map(Command::AsyncFind),
+                        "assert" => AsyncAssertion::parse(&parts[2..]).map(Command::AsyncAssert),
+                        "edges" => parse_edge_filter(&parts[2..]).map(Command::AsyncEdges),
+                        "tree" => parse_edge_filter(&parts[2..]).map(Command::AsyncTree),
+                        "clear" => Some(Command::AsyncClear),
+                        "stats" => Some(Command::AsyncStats),
+                        "export" => match &parts[2..] {
+                            [] => Some(Command::AsyncExportStatus),
+                            ["stop"] => Some(Command::AsyncExportStop),
+                            args => ExportConfig::parse(args).map(Command::AsyncExportStart),
+                        },
+                        "catch" => match parts.get(2..) {
+                            Some(["poison"]) => Some(Command::AsyncCatchPoison),
+                            _ => None,
+                        },
+                        "enable" => match parts.get(2..) {
+                            Some(["--filter", pattern @ ..]) if !pattern.is_empty() => {
+                                Some(Command::AsyncEnable(Some(pattern.join(" "))))
+                            }
+                            Some([]) => Some(Command::AsyncEnable(None)),
+                            _ => None,
+                        },
+                        "disable" => match &parts[2..] {
+                            [] => Some(Command::AsyncDisableAll(false)),
+                            ["--keep"] => Some(Command::AsyncDisableAll(true)),
+                            pattern => Some(Command::AsyncDisable(pattern.join(" "))),
+                        },
+                        "sample" => match parts.get(2..) {
+                            Some([]) => Some(Command::AsyncSample(None)),
+                            Some([pattern @ .., every]) if !pattern.is_empty() => every
+                                .parse()
+                                .ok()
+                                .filter(|n| *n > 0)
+                                .map(|n| Command::AsyncSample(Some((pattern.join(" "), n)))),
+                            _ => None,
+                        },
+                        "uprobe" => match parts.get(2..) {
+                            Some(["start"]) => Some(Command::AsyncUprobeStart(None)),
+                            Some(["start", "--filter", pattern @ ..]) if !pattern.is_empty() => {
+                                Some(Command::AsyncUprobeStart(Some(pattern.join(" "))))
+                            }
+                            Some(["collect", secs]) => secs.parse().ok().map(Command::AsyncUprobeCollect),
+                            Some(["stop"]) => Some(Command::AsyncUprobeStop),
+                            _ => None,
+                        },
                         _ => None,
                     }
                 } else {
                     None
                 }
             }
-            "help" | "h" | "?" => Some(Command::Help),
+            "thread" => {
+                if parts.len() > 3 && parts[1] == "apply" {
+                    let cmd = parts[3..].join(" ");
+                    if parts[2] == "all" {
+                        Some(Command::ThreadApplyAll(cmd))
+                    } else if parts[2].starts_with(|c: char| c.is_ascii_digit()) {
+                        parts[2].parse().ok().map(|n| Command::ThreadApply(n, cmd))
+                    } else {
+                        Some(Command::ThreadApplyMatching(parts[2].to_string(), cmd))
+                    }
+                } else {
+                    None
+                }
+            }
+            "add-inferior" => Some(Command::AddInferior {
+                binary: parts.get(1).map(|binary| binary.to_string()),
+                args: parts.iter().skip(2).map(|arg| arg.to_string()).collect(),
+            }),
+            "inferior" => match parts.get(1..) {
+                Some([n]) => n.parse().ok().map(Command::Inferior),
+                Some(["apply", "all", cmd @ ..]) if !cmd.is_empty() => Some(Command::InferiorApplyAll(cmd.join(" "))),
+                _ => None,
+            },
+            "info" | "i" => match parts.get(1..) {
+                Some(["proc"]) => Some(Command::InfoProc),
+                Some(["inferiors"]) => Some(Command::InfoInferiors),
+                Some(["patches"]) => Some(Command::InfoPatches),
+                Some(["watchpoints"] | ["watch"]) => Some(Command::InfoWatchpoints),
+                Some(["async-runtime"]) => Some(Command::InfoAsyncRuntime),
+                Some(["binary"]) => Some(Command::InfoBinary),
+                Some(["args"]) => Some(Command::InfoArgs),
+                Some(["threads"]) => Some(Command::InfoThreads(None)),
+                Some(["threads", pattern]) => Some(Command::InfoThreads(Some(pattern.to_string()))),
+                Some(["variants", rest @ ..]) if !rest.is_empty() => Some(Command::InfoVariants(rest.join(" "))),
+                _ => None,
+            },
+            "mem" => match parts.get(1..) {
+                Some(["protect", addr, len, perms]) => crate::parse::parse_address(len)
+                    .ok()
+                    .map(|len| Command::MemProtect(addr.to_string(), len, perms.to_string())),
+                _ => None,
+            },
+            "patch" => {
+                if parts.len() > 2 {
+                    Some(Command::Patch(parts[1].to_string(), parts[2..].join(" ")))
+                } else {
+                    None
+                }
+            }
+            "unpatch" => {
+                if parts.len() > 1 {
+                    parts[1].parse().ok().map(|n| Command::Unpatch(Some(n)))
+                } else {
+                    Some(Command::Unpatch(None))
+                }
+            }
+            "symbol-file-from-memory" => Some(Command::SymbolFileFromMemory),
+            "gcore" | "generate-core-file" => match parts.get(1..) {
+                Some([]) => Some(Command::GenerateCore(None)),
+                Some([path]) => Some(Command::GenerateCore(Some(path.to_string()))),
+                _ => None,
+            },
+            "attach" => match parts.get(1..) {
+                Some(["--wait", target]) => Some(Command::Attach { target: target.to_string(), wait: true }),
+                Some([target]) if *target != "--wait" => Some(Command::Attach { target: target.to_string(), wait: false }),
+                _ => None,
+            },
+            "export-state" => match parts.get(1..) {
+                Some([path]) => Some(Command::ExportState(path.to_string())),
+                _ => None,
+            },
+            "import-state" => match parts.get(1..) {
+                Some([path]) => Some(Command::ImportState(path.to_string())),
+                _ => None,
+            },
+            "catch" => match parts.get(1..) {
+                Some(["panic"]) => Some(Command::CatchPanic),
+                Some(["err", function]) => Some(Command::CatchErr(function.to_string())),
+                _ => None,
+            },
+            "set" if parts.get(1).is_some_and(|part| part.starts_with('$')) => parse_set_variable(input),
+            "set" => match parts.get(1..) {
+                Some(["async", "summary", value]) => parse_on_off(value).map(Command::SetAsyncSummary),
+                Some(["async", "trace", value]) => parse_on_off(value).map(Command::SetAsyncTrace),
+                Some(["async", "fast-entry", value]) => parse_on_off(value).map(Command::SetAsyncFastEntry),
+                Some(["async", "names", "short"]) => Some(Command::SetAsyncNames(NameStyle::Short)),
+                Some(["async", "names", "raw"]) => Some(Command::SetAsyncNames(NameStyle::Raw)),
+                Some(["async", "names", "generics", value]) => parse_on_off(value).map(Command::SetAsyncNameGenerics),
+                Some(["async", "retention", "max-tasks", "off"]) => Some(Command::SetAsyncRetentionMaxTasks(None)),
+                Some(["async", "retention", "max-tasks", n]) => {
+                    n.parse().ok().map(|n| Command::SetAsyncRetentionMaxTasks(Some(n)))
+                }
+                Some(["async", "retention", "max-age", "off"]) => Some(Command::SetAsyncRetentionMaxAge(None)),
+                Some(["async", "retention", "max-age", secs]) => {
+                    secs.parse().ok().map(|s| Command::SetAsyncRetentionMaxAge(Some(s)))
+                }
+                Some(["crash-report", value]) => parse_on_off(value).map(Command::SetCrashReport),
+                Some(["step-filter", "off"]) => Some(Command::SetStepFilter(Some(Vec::new()))),
+                Some(["step-filter", "default"]) => Some(Command::SetStepFilter(None)),
+                Some(["step-filter", prefixes @ ..]) if !prefixes.is_empty() => {
+                    Some(Command::SetStepFilter(Some(prefixes.iter().map(|p| p.to_string()).collect())))
+                }
+                Some(["query-timeout", "off"]) => Some(Command::SetQueryTimeout(None)),
+                Some(["query-timeout", secs]) => secs.parse().ok().map(|s| Command::SetQueryTimeout(Some(s))),
+                Some(["logging", "on"]) => Some(Command::SetLogging(Some("kokia.log".to_string()))),
+                Some(["logging", "on", path]) => Some(Command::SetLogging(Some(path.to_string()))),
+                Some(["logging", "off"]) => Some(Command::SetLogging(None)),
+                _ => None,
+            },
+            "help" | "h" | "?" => match parts.get(1..) {
+                Some([]) => Some(Command::Help(None)),
+                Some(topic) => Some(Command::Help(Some(topic.join(" ")))),
+                None => None,
+            },
             "quit" | "q" | "exit" => Some(Command::Quit),
             _ => None,
         }
     }
 }
 
+/// `async edges` / `async tree` の絞り込みオプションをパースする
+fn parse_edge_filter(args: &[&str]) -> Option<EdgeFilter> {
+    let mut filter = EdgeFilter::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--active" => filter.active_only = true,
+            "--root" => filter.root = Some(parse_task_ref(args.next()?)?),
+            "--since" => filter.since = Some(Duration::from_secs(args.next()?.parse().ok()?)),
+            _ => return None,
+        }
+    }
+    Some(filter)
+}
+
+/// `async find` の引数をパースする（パターンは1つまで）
+fn parse_task_query(args: &[&str]) -> Option<TaskQuery> {
+    let mut query = TaskQuery::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--state" => query.state = Some(TaskState::parse(args.next()?)?),
+            "--older-than" => query.older_than = Some(Duration::from_secs(args.next()?.parse().ok()?)),
+            "--newer-than" => query.newer_than = Some(Duration::from_secs(args.next()?.parse().ok()?)),
+            "--sort" => query.sort = TaskSort::parse(args.next()?)?,
+            flag if flag.starts_with("--") => return None,
+            pattern if query.pattern.is_none() => query.pattern = Some(pattern.to_string()),
+            _ => return None,
+        }
+    }
+    Some(query)
+}
+
+/// `trace start` のオプション（`--branches`, `--limit <n>`）をパースする
+fn parse_trace_start(args: &[&str]) -> Option<Command> {
+    let mut mode = TraceMode::Instructions;
+    let mut capacity = DEFAULT_TRACE_CAPACITY;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--branches" => mode = TraceMode::Branches,
+            "--limit" => capacity = args.next()?.parse().ok().filter(|n| *n > 0)?,
+            _ => return None,
+        }
+    }
+    Some(Command::TraceStart(mode, capacity))
+}
+
+/// `!!` と `!prefix` を履歴から展開する
+///
+/// `history` は古い順で、最も新しい一致を使います。`!` で始まらない行は `None`、一致する履歴が
+/// なければエラーメッセージを返します。
+pub fn expand_history(input: &str, history: &[String]) -> Option<Result<String, String>> {
+    let event = input.trim().strip_prefix('!')?;
+    let found = match event {
+        "" => return Some(Err("usage: !! or !<prefix>".to_string())),
+        "!" => history.last(),
+        prefix => history.iter().rev().find(|entry| entry.starts_with(prefix)),
+    };
+    Some(found.cloned().ok_or_else(|| format!("!{}: event not found", event)))
+}
+
+/// `#4` のような表示用の番号か、タスクID（アドレス）をパースする
+fn parse_task_ref(value: &str) -> Option<TaskRef> {
+    match value.strip_prefix('#') {
+        Some(handle) => handle.parse().ok().map(TaskRef::Handle),
+        None => crate::parse::parse_address(value).ok().map(TaskRef::Id),
+    }
+}
+
+/// `logpoint <loc> "<format>"` をパースする（書式の中の空白はそのまま残す）
+fn parse_logpoint(input: &str) -> Option<Command> {
+    let rest = input.trim().strip_prefix("logpoint")?;
+    let (open, close) = (rest.find('"')?, rest.rfind('"')?);
+    let location = rest[..open].trim();
+    if close == open || location.is_empty() || !rest[close + 1..].trim().is_empty() {
+        return None;
+    }
+    Some(Command::Logpoint(location.to_string(), rest[open + 1..close].to_string()))
+}
+
+/// `set $name = <expr>` をパースする（`=` の前後の空白は省略できる）
+fn parse_set_variable(input: &str) -> Option<Command> {
+    let rest = input.trim().strip_prefix("set")?;
+    let (name, expr) = rest.split_once('=')?;
+    let name = name.trim().strip_prefix('$')?;
+    let expr = expr.trim();
+    if name.is_empty() || expr.is_empty() {
+        return None;
+    }
+    Some(Command::SetVariable(name.to_string(), expr.to_string()))
+}
+
+/// `on`/`off` をパースする
+fn parse_on_off(value: &str) -> Option<bool> {
+    match value {
+        "on" | "1" | "true" => Some(true),
+        "off" | "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_parse_commands() {
+        assert_eq!(
+            Command::parse(r#"logpoint main.rs:42 "x={x}  state={self.__state}""#),
+            Some(Command::Logpoint("main.rs:42".to_string(), "x={x}  state={self.__state}".to_string()))
+        );
+        assert_eq!(Command::parse("logpoint main.rs:42"), None);
+        assert_eq!(Command::parse(r#"logpoint "x={x}""#), None);
+        assert_eq!(Command::parse("break main"), Some(Command::Break("main".to_string(), BreakContext::default())));
+        assert_eq!(
+            Command::parse("break app::handler::{{closure}} thread 3 task #7"),
+            Some(Command::Break(
+                "app::handler::{{closure}}".to_string(),
+                BreakContext { thread: Some(3), task: Some(TaskRef::Handle(7)) }
+            ))
+        );
+        assert_eq!(Command::parse("break thread 3"), None);
+        assert_eq!(
+            Command::parse("bpgroup add io break tokio::net::*"),
+            Some(Command::BpGroupAdd("io".to_string(), GroupMember::Break("tokio::net::*".to_string())))
+        );
+        assert_eq!(
+            Command::parse("bpgroup add io async enable --filter app::io::*"),
+            Some(Command::BpGroupAdd("io".to_string(), GroupMember::Async("app::io::*".to_string())))
+        );
+        assert_eq!(Command::parse("bpgroup add io"), None);
+        assert_eq!(Command::parse("bpgroup enable io"), Some(Command::BpGroupEnable("io".to_string())));
+        assert_eq!(Command::parse("bpgroup"), Some(Command::BpGroupList));
+        assert_eq!(Command::parse("bpgroup enable"), None);
+        assert_eq!(Command::parse("break main thread x"), None);
         assert_eq!(Command::parse("continue"), Some(Command::Continue));
         assert_eq!(Command::parse("c"), Some(Command::Continue));
         assert_eq!(Command::parse("step"), Some(Command::Step));
         assert_eq!(Command::parse("async bt"), Some(Command::AsyncBacktrace));
+        assert_eq!(Command::parse("async bt --all"), Some(Command::AsyncBacktraceAll));
+        assert_eq!(Command::parse("async enable"), Some(Command::AsyncEnable(None)));
+        assert_eq!(
+            Command::parse("async enable --filter my_crate::api::*"),
+            Some(Command::AsyncEnable(Some("my_crate::api::*".to_string())))
+        );
+        assert_eq!(Command::parse("async enable --filter"), None);
+        assert_eq!(Command::parse("async disable /db/"), Some(Command::AsyncDisable("/db/".to_string())));
+        assert_eq!(Command::parse("async disable"), Some(Command::AsyncDisableAll(false)));
+        assert_eq!(Command::parse("async disable --keep"), Some(Command::AsyncDisableAll(true)));
+        assert_eq!(
+            Command::parse("async uprobe start --filter app::*"),
+            Some(Command::AsyncUprobeStart(Some("app::*".to_string())))
+        );
+        assert_eq!(Command::parse("async uprobe collect 5"), Some(Command::AsyncUprobeCollect(5)));
+        assert_eq!(Command::parse("async sample"), Some(Command::AsyncSample(None)));
+        assert_eq!(
+            Command::parse("async sample tokio::time::* 100"),
+            Some(Command::AsyncSample(Some(("tokio::time::*".to_string(), 100))))
+        );
+        assert_eq!(Command::parse("async sample timer 0"), None);
+        assert_eq!(Command::parse("async uprobe collect soon"), None);
         assert_eq!(Command::parse("quit"), Some(Command::Quit));
+        assert_eq!(Command::parse("help"), Some(Command::Help(None)));
+        assert_eq!(Command::parse("help async  tasks"), Some(Command::Help(Some("async tasks".to_string()))));
+        assert_eq!(
+            Command::parse("thread apply all bt"),
+            Some(Command::ThreadApplyAll("bt".to_string()))
+        );
+        assert_eq!(
+            Command::parse("thread apply 2 print x"),
+            Some(Command::ThreadApply(2, "print x".to_string()))
+        );
+        assert_eq!(
+            Command::parse("thread apply tokio-runtime-w* bt"),
+            Some(Command::ThreadApplyMatching("tokio-runtime-w*".to_string(), "bt".to_string()))
+        );
+        assert_eq!(Command::parse("thread apply 2x bt"), None);
+        assert_eq!(Command::parse("info threads"), Some(Command::InfoThreads(None)));
+        assert_eq!(Command::parse("add-inferior"), Some(Command::AddInferior { binary: None, args: vec![] }));
+        assert_eq!(
+            Command::parse("add-inferior ./server --port 8080"),
+            Some(Command::AddInferior { binary: Some("./server".into()), args: vec!["--port".into(), "8080".into()] })
+        );
+        assert_eq!(Command::parse("inferior 2"), Some(Command::Inferior(2)));
+        assert_eq!(Command::parse("inferior apply all async tree"), Some(Command::InferiorApplyAll("async tree".into())));
+        assert_eq!(Command::parse("inferior apply all"), None);
+        assert_eq!(Command::parse("info inferiors"), Some(Command::InfoInferiors));
+        assert_eq!(Command::parse("info threads tokio*"), Some(Command::InfoThreads(Some("tokio*".to_string()))));
+        assert_eq!(Command::parse("x/4x $rsp"), Some(Command::Examine(4, "$rsp".to_string())));
+        assert_eq!(Command::parse("x $pc+8"), Some(Command::Examine(1, "$pc+8".to_string())));
+        assert_eq!(Command::parse("display counter"), Some(Command::Display(Some("counter".to_string()))));
+        assert_eq!(Command::parse("undisplay 2"), Some(Command::Undisplay(Some(2))));
+        assert_eq!(Command::parse("undisplay"), Some(Command::Undisplay(None)));
+        assert_eq!(Command::parse("set async summary on"), Some(Command::SetAsyncSummary(true)));
+        assert_eq!(Command::parse("set async trace off"), Some(Command::SetAsyncTrace(false)));
+        assert_eq!(Command::parse("set async fast-entry on"), Some(Command::SetAsyncFastEntry(true)));
+        assert_eq!(
+            Command::parse("set async retention max-tasks 10000"),
+            Some(Command::SetAsyncRetentionMaxTasks(Some(10000)))
+        );
+        assert_eq!(Command::parse("set async retention max-age off"), Some(Command::SetAsyncRetentionMaxAge(None)));
+        assert_eq!(Command::parse("async clear"), Some(Command::AsyncClear));
+        assert_eq!(Command::parse("async tasks --raw"), Some(Command::AsyncRawNames("async tasks".into())));
+        assert_eq!(
+            Command::parse("async tree --raw --active"),
+            Some(Command::AsyncRawNames("async tree --active".into()))
+        );
+        assert_eq!(Command::parse("async clear --raw"), None);
+        assert_eq!(Command::parse("async catch poison"), Some(Command::AsyncCatchPoison));
+        assert_eq!(Command::parse("async catch"), None);
+        assert_eq!(Command::parse("async export"), Some(Command::AsyncExportStatus));
+        assert_eq!(Command::parse("async export stop"), Some(Command::AsyncExportStop));
+        assert!(matches!(Command::parse("async export otlp http://127.0.0.1:4318"), Some(Command::AsyncExportStart(_))));
+        assert_eq!(Command::parse("async export otlp"), None);
+        assert_eq!(Command::parse("async stats"), Some(Command::AsyncStats));
+        assert_eq!(Command::parse("set async names raw"), Some(Command::SetAsyncNames(NameStyle::Raw)));
+        assert_eq!(Command::parse("set async names generics off"), Some(Command::SetAsyncNameGenerics(false)));
+        assert_eq!(Command::parse("async memsize"), Some(Command::AsyncMemsize));
+        assert_eq!(Command::parse("async lifetimes"), Some(Command::AsyncLifetimes));
+        assert_eq!(
+            Command::parse("async assert no-stalled --timeout 5s"),
+            Some(Command::AsyncAssert(AsyncAssertion::NoStalled(Duration::from_secs(5))))
+        );
+        assert_eq!(Command::parse("async assert max-tasks 1000"), Some(Command::AsyncAssert(AsyncAssertion::MaxTasks(1000))));
+        assert_eq!(Command::parse("async assert"), None);
+        assert_eq!(Command::parse("async locals"), Some(Command::AsyncLocals(None)));
+        assert_eq!(Command::parse("async locals 0x7ffd1000"), Some(Command::AsyncLocals(Some(TaskRef::Id(0x7ffd1000)))));
+        assert_eq!(Command::parse("async locals #4"), Some(Command::AsyncLocals(Some(TaskRef::Handle(4)))));
+        assert_eq!(
+            Command::parse("async find fetch --state pending --older-than 5 --sort age"),
+            Some(Command::AsyncFind(TaskQuery {
+                pattern: Some("fetch".to_string()),
+                state: Some(TaskState::Pending),
+                older_than: Some(Duration::from_secs(5)),
+                newer_than: None,
+                sort: TaskSort::Age,
+            }))
+        );
+        assert_eq!(Command::parse("async find a b"), None);
+        assert_eq!(Command::parse("async find --state stuck"), None);
+        assert_eq!(Command::parse("async locals #x"), None);
+        assert_eq!(Command::parse("async locals soon"), None);
+        assert_eq!(Command::parse("async edges"), Some(Command::AsyncEdges(EdgeFilter::default())));
+        assert_eq!(
+            Command::parse("async tree --active --root 0x1000 --since 30"),
+            Some(Command::AsyncTree(EdgeFilter {
+                active_only: true,
+                root: Some(TaskRef::Id(0x1000)),
+                since: Some(Duration::from_secs(30)),
+            }))
+        );
+        assert_eq!(Command::parse("async tree --root"), None);
+        assert_eq!(Command::parse("set query-timeout 5"), Some(Command::SetQueryTimeout(Some(5))));
+        assert_eq!(Command::parse("set query-timeout off"), Some(Command::SetQueryTimeout(None)));
+        assert_eq!(Command::parse("set logging on"), Some(Command::SetLogging(Some("kokia.log".into()))));
+        assert_eq!(Command::parse("set logging on bug.jsonl"), Some(Command::SetLogging(Some("bug.jsonl".into()))));
+        assert_eq!(Command::parse("set logging off"), Some(Command::SetLogging(None)));
+        assert_eq!(
+            Command::parse("set $base = 0x7f00 + 8"),
+            Some(Command::SetVariable("base".into(), "0x7f00 + 8".into()))
+        );
+        assert_eq!(Command::parse("set $s=state"), Some(Command::SetVariable("s".into(), "state".into())));
+        assert_eq!(Command::parse("set $base ="), None);
+        assert_eq!(Command::parse("set $ = 1"), None);
+        assert_eq!(Command::parse("set query-timeout soon"), None);
+        assert_eq!(Command::parse("set async summary maybe"), None);
+        assert_eq!(Command::parse("info proc"), Some(Command::InfoProc));
+        assert_eq!(
+            Command::parse("mem protect $pc 0x1000 rwx"),
+            Some(Command::MemProtect("$pc".to_string(), 0x1000, "rwx".to_string()))
+        );
+        assert_eq!(
+            Command::parse("patch foo+4 asm nop; ret"),
+            Some(Command::Patch("foo+4".to_string(), "asm nop; ret".to_string()))
+        );
+        assert_eq!(Command::parse("patch 0x1000"), None);
+        assert_eq!(Command::parse("unpatch 1"), Some(Command::Unpatch(Some(1))));
+        assert_eq!(Command::parse("info patches"), Some(Command::InfoPatches));
+        assert_eq!(
+            Command::parse("watch &COUNTER 4"),
+            Some(Command::Watch("&COUNTER".into(), 4, WatchKind::Write))
+        );
+        assert_eq!(Command::parse("awatch 0x1000"), Some(Command::Watch("0x1000".into(), 8, WatchKind::ReadWrite)));
+        assert_eq!(Command::parse("watch 0x1000 four"), None);
+        assert_eq!(Command::parse("unwatch"), Some(Command::Unwatch(None)));
+        assert_eq!(Command::parse("reverse-stepi"), Some(Command::ReverseStepi(1)));
+        assert_eq!(Command::parse("rsi 3"), Some(Command::ReverseStepi(3)));
+        assert_eq!(Command::parse("rsi 0"), None);
+        assert_eq!(Command::parse("trace start"), Some(Command::TraceStart(TraceMode::Instructions, DEFAULT_TRACE_CAPACITY)));
+        assert_eq!(
+            Command::parse("trace start --branches --limit 500"),
+            Some(Command::TraceStart(TraceMode::Branches, 500))
+        );
+        assert_eq!(Command::parse("trace start --limit 0"), None);
+        assert_eq!(Command::parse("trace stop"), Some(Command::TraceStop));
+        assert_eq!(Command::parse("trace hw start"), Some(Command::TraceHwStart));
+        assert_eq!(Command::parse("trace hw"), None);
+        assert_eq!(Command::parse("profile start"), Some(Command::ProfileStart(DEFAULT_PROFILE_FREQUENCY)));
+        assert_eq!(Command::parse("profile start 499"), Some(Command::ProfileStart(499)));
+        assert_eq!(Command::parse("profile report"), Some(Command::ProfileReport));
+        assert_eq!(Command::parse("trace show 5"), Some(Command::TraceShow(5)));
+        assert_eq!(Command::parse("trace export /tmp/poll.trace"), Some(Command::TraceExport("/tmp/poll.trace".into())));
+        assert_eq!(Command::parse("ftrace app::db::*"), Some(Command::Ftrace("app::db::*".into())));
+        assert_eq!(Command::parse("ftrace"), Some(Command::FtraceList));
+        assert_eq!(Command::parse("ftrace log"), Some(Command::FtraceLog(50)));
+        assert_eq!(Command::parse("ftrace log 0"), None);
+        assert_eq!(Command::parse("ftrace stop /fib/"), Some(Command::FtraceStop(Some("/fib/".into()))));
+        assert_eq!(Command::parse("gcore /tmp/core.1"), Some(Command::GenerateCore(Some("/tmp/core.1".into()))));
+        assert_eq!(Command::parse("attach 4242"), Some(Command::Attach { target: "4242".into(), wait: false }));
+        assert_eq!(Command::parse("attach --wait my-server"), Some(Command::Attach { target: "my-server".into(), wait: true }));
+        assert_eq!(Command::parse("attach --wait"), None);
+        assert_eq!(Command::parse("export-state bug.json"), Some(Command::ExportState("bug.json".into())));
+        assert_eq!(Command::parse("import-state bug.json"), Some(Command::ImportState("bug.json".into())));
+        assert_eq!(Command::parse("import-state"), None);
+        assert_eq!(Command::parse("set crash-report off"), Some(Command::SetCrashReport(false)));
+        assert_eq!(Command::parse("catch panic"), Some(Command::CatchPanic));
+        assert_eq!(Command::parse("catch err app::load_config"), Some(Command::CatchErr("app::load_config".into())));
+        assert_eq!(Command::parse("catch err"), None);
+        assert_eq!(Command::parse("catch throw"), None);
+        assert_eq!(
+            Command::parse("set step-filter tokio:: core::"),
+            Some(Command::SetStepFilter(Some(vec!["tokio::".into(), "core::".into()])))
+        );
+        assert_eq!(Command::parse("set step-filter off"), Some(Command::SetStepFilter(Some(Vec::new()))));
+        assert_eq!(Command::parse("set step-filter default"), Some(Command::SetStepFilter(None)));
+        assert_eq!(Command::parse("info watchpoints"), Some(Command::InfoWatchpoints));
+        assert_eq!(Command::parse("info async-runtime"), Some(Command::InfoAsyncRuntime));
+        assert_eq!(Command::parse("info binary"), Some(Command::InfoBinary));
+        assert_eq!(Command::parse("info args"), Some(Command::InfoArgs));
+        assert_eq!(Command::parse("bt"), Some(Command::Backtrace));
+        assert_eq!(Command::parse("backtrace full"), Some(Command::BacktraceFull));
+        assert_eq!(Command::parse("symbol-file-from-memory"), Some(Command::SymbolFileFromMemory));
+        assert_eq!(
+            Command::parse("info variants simple_async::compute"),
+            Some(Command::InfoVariants("simple_async::compute".to_string()))
+        );
+        assert_eq!(Command::parse("info variants"), None);
+        assert_eq!(
+            Command::parse("ptype simple_async::compute"),
+            Some(Command::PrintType("simple_async::compute".to_string()))
+        );
+        assert_eq!(Command::parse("whatis task.state"), Some(Command::WhatIs("task.state".to_string())));
+    }
+
+    #[test]
+    fn test_history_expansion() {
+        let history: Vec<String> = ["break main", "print x", "bt", "print y"].map(String::from).to_vec();
+        assert_eq!(expand_history("!!", &history), Some(Ok("print y".to_string())));
+        assert_eq!(expand_history("!pr", &history), Some(Ok("print y".to_string())));
+        assert_eq!(expand_history("!b", &history), Some(Ok("bt".to_string())));
+        assert_eq!(expand_history("!break", &history), Some(Ok("break main".to_string())));
+        assert!(matches!(expand_history("!watch", &history), Some(Err(_))));
+        assert!(matches!(expand_history("!!", &[]), Some(Err(_))));
+        assert_eq!(expand_history("print !x", &history), None);
+
+        assert!(Command::parse("n").unwrap().repeats_on_empty_line());
+        assert!(!Command::parse("bt").unwrap().repeats_on_empty_line());
     }
 }
@@ -0,0 +1,116 @@
+//! 値の履歴とコンビニエンス変数（`$1`, `$`, `$$2`, `$name`）
+//!
+//! `print` の結果を番号付きで履歴に残し、後の式から `$n`（n 番目）、`$`（最新）、`$$`（1つ前）、
+//! `$$n`（n 個前）で参照できるようにします。`set $name = <expr>` で名前を付けた変数も置けます。
+//! メモリ上の値は場所と型に加えて記録した時点のその場所のバイト列を保存し、後から参照しても
+//! 記録した時点の値を見せます（ポインタの指す先はその時点のメモリを読みます）。
+
+use crate::{EvaluationResult, Result};
+use std::collections::HashMap;
+
+/// 保存した値
+#[derive(Debug, Clone)]
+pub enum Value {
+    /// アドレス計算式などの数値（16進数で表示する）
+    Address(u64),
+    /// 整数型へのキャストの結果
+    Integer { value: u64, type_name: String },
+    /// メモリ上の場所と型、記録した時点のその場所のバイト列
+    Location { result: EvaluationResult, bytes: Vec<u8> },
+}
+
+impl Value {
+    /// 数値として使うときの値（場所はそのアドレス）
+    pub fn address(&self) -> u64 {
+        match self {
+            Value::Address(value) | Value::Integer { value, .. } => *value,
+            Value::Location { result, .. } => result.address,
+        }
+    }
+}
+
+/// 履歴の参照
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HistoryRef {
+    /// `$n`: n 番目（1 から）
+    Absolute(usize),
+    /// `$` は 0、`$$` は 1、`$$n` は n: 最新からいくつ前か
+    Relative(usize),
+}
+
+/// 値の履歴と名前付きの変数
+#[derive(Debug, Clone, Default)]
+pub struct ConvenienceVariables {
+    history: Vec<Value>,
+    variables: HashMap<String, Value>,
+}
+
+impl ConvenienceVariables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 値を履歴に加え、その番号を返す
+    pub fn record(&mut self, value: Value) -> usize {
+        self.history.push(value);
+        self.history.len()
+    }
+
+    /// 履歴の値を取得する
+    pub fn history(&self, reference: HistoryRef) -> Result<&Value> {
+        let index = match reference {
+            HistoryRef::Absolute(n) => n.checked_sub(1),
+            HistoryRef::Relative(back) => self.history.len().checked_sub(back + 1),
+        };
+        index.and_then(|i| self.history.get(i)).ok_or_else(|| match reference {
+            _ if self.history.is_empty() => anyhow::anyhow!("History is empty"),
+            HistoryRef::Absolute(n) => anyhow::anyhow!("History has no value ${}", n),
+            HistoryRef::Relative(back) => anyhow::anyhow!("History has no value $${}", back),
+        })
+    }
+
+    /// 名前付きの変数を設定する
+    pub fn set(&mut self, name: &str, value: Value) -> Result<()> {
+        let mut chars = name.chars();
+        let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(anyhow::anyhow!("Invalid variable name: ${}", name));
+        }
+        self.variables.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// 名前付きの変数を取得する
+    pub fn variable(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convenience_variables() {
+        let mut vars = ConvenienceVariables::new();
+        assert!(vars.history(HistoryRef::Relative(0)).unwrap_err().to_string().contains("empty"));
+
+        assert_eq!(vars.record(Value::Address(0x10)), 1);
+        assert_eq!(vars.record(Value::Integer { value: 7, type_name: "u8".to_string() }), 2);
+        assert_eq!(vars.record(Value::Address(0x30)), 3);
+        assert_eq!(vars.history(HistoryRef::Relative(0)).unwrap().address(), 0x30);
+        assert_eq!(vars.history(HistoryRef::Relative(1)).unwrap().address(), 7);
+        assert_eq!(vars.history(HistoryRef::Absolute(1)).unwrap().address(), 0x10);
+        assert!(vars.history(HistoryRef::Absolute(0)).is_err());
+        assert!(vars.history(HistoryRef::Absolute(4)).is_err());
+        assert!(vars.history(HistoryRef::Relative(3)).is_err());
+
+        vars.set("base", Value::Address(0x7f00)).unwrap();
+        vars.set("_x1", Value::Address(1)).unwrap();
+        assert!(vars.set("1x", Value::Address(1)).is_err());
+        assert!(vars.set("a-b", Value::Address(1)).is_err());
+        assert_eq!(vars.variable("base").unwrap().address(), 0x7f00);
+        assert!(vars.variable("_x1").is_some());
+    }
+}
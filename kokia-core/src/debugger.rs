@@ -1,12 +1,46 @@
 //! デバッガのメインロジック
 
 use crate::{breakpoint::BreakpointManager, errors, Breakpoint, BreakpointId, Result};
-use kokia_async::AsyncTracker;
-use kokia_dwarf::{DwarfLoader, LineInfoProvider, Symbol, SymbolResolver};
-use kokia_target::{Memory, Process, Registers, StopReason};
-use std::path::Path;
-use std::collections::HashSet;
-use tracing::{debug, warn};
+use crate::callconv::PollDiscriminant;
+use crate::convenience::{ConvenienceVariables, Value};
+use crate::display::{DisplayEntry, DisplayId, DisplayList};
+use crate::err_catch::{ErrCatchpoint, ErrReturn};
+use crate::ftrace::{FunctionTrace, TracedFunction};
+use crate::future_name::FutureNames;
+use crate::eval_cache::{EvalCache, FrameKey, StopEpoch};
+use crate::symbol_memo::SymbolMemo;
+use crate::instrument::{ArmedFunction, InstrumentationPlan, SymbolPattern};
+use crate::panic::{PanicLocation, PanicReport};
+use crate::poison::{PoisonCatch, PoisonedTask, PANIC_CATCH_FRAMES, PANIC_CLEANUP_SYMBOLS};
+use crate::patch::{Patch, PatchId, PatchManager};
+use crate::reverse::{UndoEntry, UndoLog, MAX_INSTRUCTION_LEN};
+use crate::binary_info::BinaryReport;
+use crate::bpgroup::{BreakpointGroups, GroupActivation, GroupMember};
+use crate::unwind::{scan_stack_for_self_ptr, walk_frame_pointers};
+use crate::runtime::RuntimeReport;
+use crate::state::{BinarySnapshot, BreakpointSnapshot, ConfigSnapshot, DebuggerState, ImportReport, ModuleSnapshot, STATE_FORMAT_VERSION};
+use crate::step_filter::StepFilter;
+use crate::telemetry::{EventExporter, ExportConfig, ExportStats};
+use crate::stop::StopEvent;
+use crate::logpoint::{LogTemplate, LogpointCallback};
+use crate::metrics::{BreakpointOverhead, MetricsServer, MetricsSnapshot, OverheadKind, PollTimer};
+use crate::profile::Profile;
+use crate::trace::{instruction_len, InstructionTrace, TraceMode};
+use crate::watchpoint::{Watchpoint, WatchpointHit, WatchpointId, WatchpointManager};
+use kokia_async::{AsyncTracker, AwaitSite, RetentionPolicy, RunningTime, TaskId, PANICKED_DISCRIMINANT, RETURNED_DISCRIMINANT};
+use kokia_dwarf::{
+    BuildInfo, CallFrameTable, CallSiteIndex, CancelToken, DwarfLoader, LineInfoProvider, Progress, ProgressCallback, StaticVariable, Symbol, SymbolResolver, TypeIndex, TypeInfo, TypeLayout,
+};
+use kokia_target::{
+    CoreSummary, DebugStatus, PerfSampler, PtDecoded, PtSession, PtThreadTrace, MappedImage, Memory, MemoryMapping, Process, ProcessInfo, Protection, PtraceBackend, Registers, Signal, SpawnOptions, StopReason, TargetBackend,
+    TargetNamespace, ProbeKind, ProbeSpec, UprobeSession, UserRegs, WatchKind,
+};
+use std::cell::{OnceCell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
 
 /// スタックフレーム情報
 #[derive(Debug, Clone)]
@@ -27,28 +61,287 @@ pub struct StackFrame {
     pub saved_rdi: Option<u64>,
 }
 
+/// ディスク上のバイナリと実行中のイメージのビルドID比較結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildIdCheck {
+    /// 一致した
+    Match,
+    /// 一致しない（ディスク上のバイナリが差し替えられている）
+    Mismatch {
+        /// 読み込んだファイルのビルドID（16進）
+        file: String,
+        /// 実行中のイメージのビルドID（16進）
+        target: String,
+    },
+    /// どちらかにビルドIDがなく比較できない
+    Unknown,
+}
+
+/// uprobe によるイベント収集1回分の結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UprobeCollection {
+    /// AsyncTracker に渡したイベント数
+    pub events: usize,
+    /// リングバッファ溢れで失われたイベントの累計
+    pub lost: u64,
+    /// 収集を終えたときの停止理由（期間満了なら SIGSTOP）
+    pub stop_reason: StopReason,
+}
+
+/// async トラッキングを止めた結果（async disable）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AsyncTeardown {
+    /// 計装を外した関数の数
+    pub functions: usize,
+    /// 削除した AsyncEntry/AsyncExit ブレークポイントの数
+    pub breakpoints: usize,
+    /// uprobe による収集も止めたか
+    pub uprobes_stopped: bool,
+    /// 集めたタスクを読み取り専用で残したか
+    pub data_kept: bool,
+}
+
+/// 処理は続けられるが利用者に知らせたい問題（計装の一部の失敗など）を受け取るコールバック
+pub type WarningCallback = Rc<dyn Fn(&str)>;
+
+/// uprobe を設置した async 関数
+struct UprobeTarget {
+    name: String,
+    /// 関数先頭の実行時アドレス
+    entry: u64,
+    /// 戻り値の Poll の discriminant の場所
+    poll: Option<PollDiscriminant>,
+}
+
+/// inferior（デバッグ対象1つ分の状態のまとまり）の番号（1 から）
+pub type InferiorId = usize;
+
+/// `info inferiors` 用の inferior の概要
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InferiorSummary {
+    pub id: InferiorId,
+    /// 選択中か
+    pub current: bool,
+    pub pid: Option<i32>,
+    pub binary: Option<PathBuf>,
+    pub exit_code: Option<i32>,
+    /// 追跡している async タスクの数
+    pub tasks: usize,
+}
+
+/// [`Debugger`] のフィールドのうち、inferior ごとに持つもの
+///
+/// 選択中の inferior の状態は [`Debugger`] のフィールドにそのまま置き、選択していないものは
+/// [`Inferior`] に退避しておきます。`inferior <n>` で両者を入れ替えるので、
+/// 他のメソッドは選択中の inferior だけを相手にすればよくなります。
+macro_rules! per_inferior_state {
+    ($($field:ident: $ty:ty,)*) => {
+        /// 選択していない inferior の状態
+        struct Inferior {
+            $($field: $ty,)*
+        }
+
+        impl Debugger {
+            /// 選択中の inferior の状態と `parked` を入れ替える
+            fn swap_inferior(&mut self, parked: &mut Inferior) {
+                $(std::mem::swap(&mut self.$field, &mut parked.$field);)*
+            }
+
+            /// 何も読み込んでいない inferior の状態
+            fn blank_inferior() -> Inferior {
+                let fresh = Debugger::new();
+                Inferior { $($field: fresh.$field,)* }
+            }
+        }
+    };
+}
+
+per_inferior_state! {
+    process: Option<Rc<Process>>,
+    target: Option<Box<dyn TargetBackend>>,
+    pid: Option<i32>,
+    memory: Option<Memory>,
+    registers: Option<Registers>,
+    current_tid: Option<i32>,
+    binary_path: Option<PathBuf>,
+    dwarf_loader: Option<DwarfLoader>,
+    symbol_resolver: Option<SymbolResolver>,
+    type_index: OnceCell<TypeIndex>,
+    call_sites: OnceCell<CallSiteIndex>,
+    call_frames: OnceCell<Option<CallFrameTable>>,
+    suspend_points: HashMap<String, Vec<(String, u64, u64)>>,
+    poll_discriminants: HashMap<u64, Option<PollDiscriminant>>,
+    async_tracker: AsyncTracker,
+    breakpoint_manager: BreakpointManager,
+    watchpoint_manager: WatchpointManager,
+    instrumentation: InstrumentationPlan,
+    exit_code: Option<i32>,
+    exit_signal: Option<Signal>,
+    lost_pid: Option<i32>,
+    patch_manager: PatchManager,
+    async_trace: bool,
+    fast_async_entry: bool,
+    async_entry_scans: u64,
+    traced_async_events: u64,
+    poll_timer: PollTimer,
+    breakpoint_overhead: BreakpointOverhead,
+    running_time: RunningTime,
+    last_stop: Option<StopEvent>,
+    undo_log: UndoLog,
+    instruction_trace: Option<InstructionTrace>,
+    tracing_instructions: bool,
+    hw_trace: Option<PtSession>,
+    sampler: Option<PerfSampler>,
+    profile: Option<Profile>,
+    profile_load_base: u64,
+    pending_panic_location: Option<PanicLocation>,
+    filtered_calls: u64,
+    err_catchpoints: Vec<ErrCatchpoint>,
+    poison_catch: Option<PoisonCatch>,
+    uprobe_session: Option<UprobeSession>,
+    async_read_only: bool,
+    logpoints: HashMap<BreakpointId, LogTemplate>,
+    ftrace: FunctionTrace,
+    eval_cache: RefCell<EvalCache>,
+    symbol_memo: RefCell<SymbolMemo>,
+    uprobe_targets: Vec<UprobeTarget>,
+    uprobe_load_base: u64,
+    target_namespace: Option<TargetNamespace>,
+    breakpoint_groups: BreakpointGroups,
+}
+
 /// デバッガ
 pub struct Debugger {
-    /// デバッグ対象プロセス
-    process: Option<Process>,
+    /// デバッグ対象プロセス（`target` と共有する）
+    process: Option<Rc<Process>>,
+    /// メモリ・汎用レジスタの読み書きと実行の再開（ptrace なら `process` の [`PtraceBackend`]）
+    target: Option<Box<dyn TargetBackend>>,
     /// プロセスID
     pid: Option<i32>,
     /// メモリアクセス
     memory: Option<Memory>,
-    /// レジスタアクセス
+    /// レジスタアクセス（選択中のスレッドに紐づく）
     registers: Option<Registers>,
+    /// 選択中のスレッドID
+    current_tid: Option<i32>,
+    /// 読み込んだバイナリのパス
+    binary_path: Option<PathBuf>,
     /// DWARF情報ローダー
     dwarf_loader: Option<DwarfLoader>,
     /// シンボル解決器
     symbol_resolver: Option<SymbolResolver>,
+    /// 型名の索引（最初の型検索時に構築）
+    type_index: OnceCell<TypeIndex>,
+    /// 戻りアドレス -> 呼び出し位置（DW_TAG_call_site）の索引（最初の利用時に構築）
+    call_sites: OnceCell<CallSiteIndex>,
+    /// `.eh_frame` の表（最初の利用時に読む。セクションがなければ None）
+    call_frames: OnceCell<Option<CallFrameTable>>,
+    /// async 関数名 → 状態機械の停止点 (ファイル, 行, discriminant)（await 位置の解決用）
+    suspend_points: HashMap<String, Vec<(String, u64, u64)>>,
+    /// poll 関数の先頭（ファイルオフセット）→ 戻り値 `Poll<T>` の discriminant の場所（Ready 判定用）
+    poll_discriminants: HashMap<u64, Option<PollDiscriminant>>,
     /// 行番号情報プロバイダー（DwarfLoaderへの参照が必要）
     // LineInfoProviderはライフタイム付きなので、毎回DwarfLoaderから作成
     /// Asyncタスクトラッカー
     async_tracker: AsyncTracker,
     /// ブレークポイント管理
     breakpoint_manager: BreakpointManager,
-    /// exit BP配置済みの関数アドレス（関数開始アドレスで管理）
-    async_exit_bps_installed: HashSet<u64>,
+    /// ウォッチポイント管理（デバッグレジスタ）
+    watchpoint_manager: WatchpointManager,
+    /// async 計装済みの関数と、そのために置いたブレークポイント
+    instrumentation: InstrumentationPlan,
+    /// デバッグ対象プロセスの終了コード（終了済みの場合のみ）
+    exit_code: Option<i32>,
+    /// デバッグ対象プロセスを終わらせたシグナル（シグナルで終了した場合のみ）
+    exit_signal: Option<Signal>,
+    /// 終了コードを受け取る前に消えたプロセスの PID（再アタッチを促すため）
+    lost_pid: Option<i32>,
+    /// 停止のたびに表示する式
+    display_list: DisplayList,
+    /// コードパッチ管理
+    patch_manager: PatchManager,
+    /// 停止のたびに async サマリーを表示するか
+    async_summary: bool,
+    /// トレースモード（async BP で止まらず記録だけして自動継続する）
+    async_trace: bool,
+    /// async fn の entry で親をスコープスタックから推定し、食い違うときだけフレームスキャンするか
+    fast_async_entry: bool,
+    /// fast モードでスコープスタックと食い違い、フレームスキャンに戻った entry の数
+    async_entry_scans: u64,
+    /// 致命的なシグナルで止まったときにクラッシュレポートを表示するか
+    crash_report: bool,
+    /// 直近の continue 中にトレースモードで記録した async イベント数
+    traced_async_events: u64,
+    /// 計装した async fn の poll の回数と時間
+    poll_timer: PollTimer,
+    /// 計装のブレークポイントで止まっていた時間（観測による影響の見積もり）
+    breakpoint_overhead: BreakpointOverhead,
+    /// デバッグ対象が実行していた時間（停止中の時間を停滞に数えないため）
+    running_time: RunningTime,
+    /// 直近の停止イベント
+    last_stop: Option<StopEvent>,
+    /// reverse-stepi 用の、直近のステップ実行の記録
+    undo_log: UndoLog,
+    /// trace start で始めた命令トレース（trace stop の後も表示・書き出し用に残す）
+    instruction_trace: Option<InstructionTrace>,
+    /// 命令トレースを記録中か
+    tracing_instructions: bool,
+    /// trace hw start で始めた Intel PT の記録
+    hw_trace: Option<PtSession>,
+    /// profile start で始めたサンプリング（profile stop で止めても集計は残す）
+    sampler: Option<PerfSampler>,
+    profile: Option<Profile>,
+    /// サンプリング開始時のロードアドレス（終了後に届いたサンプルも関数名にできるように）
+    profile_load_base: u64,
+    /// パニックハンドラで控えた、キャッチポイントで報告するパニック位置
+    pending_panic_location: Option<PanicLocation>,
+    /// step でスキップするランタイム内部の関数
+    step_filter: StepFilter,
+    /// 直近の step で呼び出し元に戻るまで実行した、フィルタに当たる関数の数
+    filtered_calls: u64,
+    /// async の表示での Future の型名の短縮
+    future_names: FutureNames,
+    /// `catch err` で設定したキャッチポイント
+    err_catchpoints: Vec<ErrCatchpoint>,
+    /// `async catch poison` の、巻き戻し中のパニックの記録（None なら無効）
+    poison_catch: Option<PoisonCatch>,
+    /// `async export` の送出先（None なら送らない）
+    event_exporter: Option<EventExporter>,
+    /// `attach --metrics` の公開先
+    metrics_server: Option<MetricsServer>,
+    /// uprobe によるイベント収集（プローブ i は uprobe_targets[i / 2] の entry/return）
+    uprobe_session: Option<UprobeSession>,
+    /// async disable --keep で止めたあと、集めたタスクを読み取り専用で残しているか
+    async_read_only: bool,
+    /// ログポイントの書式（ブレークポイント ID ごと）
+    logpoints: HashMap<BreakpointId, LogTemplate>,
+    logpoint_callback: Option<LogpointCallback>,
+    /// 関数トレース（ftrace）の対象と呼び出しログ
+    ftrace: FunctionTrace,
+    /// 警告の通知先（None なら tracing の warn に出す）
+    warning_callback: Option<WarningCallback>,
+    /// print の履歴と `set $name` の変数
+    convenience: ConvenienceVariables,
+    /// 停止中に評価した式の結果（display やログポイントの繰り返しの評価用）
+    eval_cache: RefCell<EvalCache>,
+    /// 停止中に引いたシンボルと行番号（再開するまで使い回す）
+    symbol_memo: RefCell<SymbolMemo>,
+    uprobe_targets: Vec<UprobeTarget>,
+    /// uprobe 設置時のロードベース（終了後に届いたイベントのアドレス変換用）
+    uprobe_load_base: u64,
+    /// デバッグ対象が属するコンテナの名前空間（ホストと同じなら None）
+    target_namespace: Option<TargetNamespace>,
+    /// 名前付きのブレークポイントのグループ（bpgroup）
+    breakpoint_groups: BreakpointGroups,
+    /// ローカル変数などの DWARF 検索の制限時間（None なら無制限）
+    query_timeout: Option<Duration>,
+    /// 長時間処理の進捗通知先
+    progress: Option<ProgressCallback>,
+    /// 選択中の inferior の番号
+    current_inferior: InferiorId,
+    /// 選択していない inferior
+    parked_inferiors: BTreeMap<InferiorId, Inferior>,
+    next_inferior: InferiorId,
 }
 
 impl Debugger {
@@ -56,30 +349,224 @@ impl Debugger {
     pub fn new() -> Self {
         Self {
             process: None,
+            target: None,
             pid: None,
             memory: None,
             registers: None,
+            current_tid: None,
+            binary_path: None,
             dwarf_loader: None,
             symbol_resolver: None,
+            type_index: OnceCell::new(),
+            call_sites: OnceCell::new(),
+            call_frames: OnceCell::new(),
+            suspend_points: HashMap::new(),
+            poll_discriminants: HashMap::new(),
             async_tracker: AsyncTracker::new()
                 .expect("Failed to create AsyncTracker"),
             breakpoint_manager: BreakpointManager::new(),
-            async_exit_bps_installed: HashSet::new(),
+            watchpoint_manager: WatchpointManager::new(),
+            instrumentation: InstrumentationPlan::new(),
+            exit_code: None,
+            exit_signal: None,
+            lost_pid: None,
+            display_list: DisplayList::new(),
+            patch_manager: PatchManager::new(),
+            async_summary: false,
+            async_trace: false,
+            fast_async_entry: false,
+            async_entry_scans: 0,
+            crash_report: true,
+            traced_async_events: 0,
+            poll_timer: PollTimer::new(),
+            breakpoint_overhead: BreakpointOverhead::default(),
+            running_time: RunningTime::new(),
+            last_stop: None,
+            undo_log: UndoLog::default(),
+            instruction_trace: None,
+            tracing_instructions: false,
+            hw_trace: None,
+            sampler: None,
+            profile: None,
+            profile_load_base: 0,
+            pending_panic_location: None,
+            step_filter: StepFilter::new(),
+            filtered_calls: 0,
+            future_names: FutureNames::new(),
+            err_catchpoints: Vec::new(),
+            poison_catch: None,
+            event_exporter: None,
+            metrics_server: None,
+            uprobe_session: None,
+            async_read_only: false,
+            logpoints: HashMap::new(),
+            logpoint_callback: None,
+            ftrace: FunctionTrace::new(),
+            warning_callback: None,
+            convenience: ConvenienceVariables::new(),
+            eval_cache: RefCell::new(EvalCache::new()),
+            symbol_memo: RefCell::new(SymbolMemo::new()),
+            uprobe_targets: Vec::new(),
+            uprobe_load_base: 0,
+            target_namespace: None,
+            breakpoint_groups: BreakpointGroups::new(),
+            query_timeout: None,
+            progress: None,
+            current_inferior: 1,
+            parked_inferiors: BTreeMap::new(),
+            next_inferior: 2,
+        }
+    }
+
+    /// 空の inferior を追加する（選択はしない）
+    pub fn add_inferior(&mut self) -> InferiorId {
+        let id = self.next_inferior;
+        self.next_inferior += 1;
+        self.parked_inferiors.insert(id, Self::blank_inferior());
+        id
+    }
+
+    /// inferior を選択する
+    ///
+    /// 選択していない inferior のプロセスは止まったまま待ちます（continue などは選択中のものだけを動かす）。
+    pub fn select_inferior(&mut self, id: InferiorId) -> Result<()> {
+        if id == self.current_inferior {
+            return Ok(());
+        }
+        let mut selected = self.parked_inferiors.remove(&id)
+            .ok_or_else(|| anyhow::anyhow!("No inferior {}", id))?;
+        self.swap_inferior(&mut selected);
+        self.parked_inferiors.insert(self.current_inferior, selected);
+        self.current_inferior = id;
+        Ok(())
+    }
+
+    /// 選択中の inferior の番号
+    pub fn current_inferior(&self) -> InferiorId {
+        self.current_inferior
+    }
+
+    /// すべての inferior の概要（番号順）
+    pub fn inferiors(&self) -> Vec<InferiorSummary> {
+        let current = InferiorSummary {
+            id: self.current_inferior,
+            current: true,
+            pid: self.pid,
+            binary: self.binary_path.clone(),
+            exit_code: self.exit_code,
+            tasks: self.async_tracker.task_tracker().len(),
+        };
+        let parked = self.parked_inferiors.iter().map(|(&id, inferior)| InferiorSummary {
+            id,
+            current: false,
+            pid: inferior.pid,
+            binary: inferior.binary_path.clone(),
+            exit_code: inferior.exit_code,
+            tasks: inferior.async_tracker.task_tracker().len(),
+        });
+        let mut all: Vec<InferiorSummary> = parked.chain(std::iter::once(current)).collect();
+        all.sort_by_key(|inferior| inferior.id);
+        all
+    }
+
+    /// プロセスが存在しない場合のエラーを生成する
+    ///
+    /// 終了済みのプロセスに対する操作では、終了コードを含むメッセージを返します。
+    fn no_process_error(&self) -> anyhow::Error {
+        match (self.exit_code, self.lost_pid) {
+            (Some(code), _) => anyhow::anyhow!("{} (exit code {})", errors::ERR_PROCESS_EXITED, code),
+            (None, _) if self.exit_signal.is_some() => {
+                anyhow::anyhow!("{} (killed by {:?})", errors::ERR_PROCESS_EXITED, self.exit_signal.unwrap())
+            }
+            (None, Some(pid)) => anyhow::anyhow!("{} (pid {}; use 'attach' to debug it again)", errors::ERR_PROCESS_LOST, pid),
+            (None, None) => anyhow::anyhow!(errors::ERR_NOT_ATTACHED),
+        }
+    }
+
+    /// プロセス終了を記録し、プロセスに紐づく状態を破棄する
+    ///
+    /// 以降のコマンドは「プロセスなし」エラーになります。
+    /// ブレークポイントは終了したプロセスのメモリを参照しているため、併せて破棄します。
+    fn handle_process_exit(&mut self, code: i32) {
+        debug!("Process exited with code {}", code);
+        self.exit_code = Some(code);
+        self.exit_signal = None;
+        self.clear_inferior();
+    }
+
+    /// シグナルによるプロセス終了を記録し、プロセスに紐づく状態を破棄する
+    fn handle_process_killed(&mut self, signal: Signal) {
+        debug!("Process killed by {:?}", signal);
+        self.exit_code = None;
+        self.exit_signal = Some(signal);
+        self.clear_inferior();
+    }
+
+    /// プロセスの終了（[`StopReason::is_terminated`] な停止）を記録し、プロセスに紐づく状態を破棄する
+    fn handle_termination(&mut self, reason: &StopReason) {
+        match *reason {
+            StopReason::Exited(code) => self.handle_process_exit(code),
+            StopReason::Killed(signal) => self.handle_process_killed(signal),
+            _ => {}
         }
     }
 
+    /// デバッグ対象が知らないうちに消えていたら（SIGKILL や外部からの kill など）、
+    /// 「プロセスなし」の状態に移って再アタッチできるようにする
+    ///
+    /// # Returns
+    /// 消えていたプロセスの PID
+    pub fn detect_lost_inferior(&mut self) -> Option<i32> {
+        let pid = self.process.as_ref().filter(|process| process.is_gone())?.pid();
+        debug!("Process {} is gone", pid);
+        self.lost_pid = Some(pid);
+        self.clear_inferior();
+        Some(pid)
+    }
+
+    /// プロセスに紐づく状態を破棄する（バイナリ・設定・async トラッカーの記録は残す）
+    fn clear_inferior(&mut self) {
+        self.process = None;
+        self.target = None;
+        self.pid = None;
+        self.memory = None;
+        self.registers = None;
+        self.current_tid = None;
+        self.breakpoint_manager = BreakpointManager::new();
+        // ブレークポイントの番号は 1 からに戻るので、書式も一緒に捨てる
+        self.logpoints.clear();
+        self.patch_manager.clear();
+        self.watchpoint_manager.clear();
+        self.undo_log.clear();
+        self.pending_panic_location = None;
+        self.err_catchpoints.clear();
+        self.poison_catch = None;
+        // 呼び出しログは終了後も見られるように残す
+        self.ftrace.remove_where(|_| true);
+        self.instrumentation.clear();
+        self.breakpoint_groups.deactivate_all();
+        self.stop_uprobe_tracking();
+    }
+
     /// プロセスにアタッチされているか確認し、Registersへの参照を取得
     fn require_registers(&self) -> Result<&Registers> {
         self.registers
             .as_ref()
-            .ok_or_else(|| anyhow::anyhow!(errors::ERR_NOT_ATTACHED))
+            .ok_or_else(|| self.no_process_error())
+    }
+
+    /// デバッグ対象があるか確認し、メモリ・汎用レジスタ・実行制御への参照を取得
+    fn require_target(&self) -> Result<&dyn TargetBackend> {
+        self.target
+            .as_deref()
+            .ok_or_else(|| self.no_process_error())
     }
 
     /// プロセスにアタッチされているか確認し、Memoryへの参照を取得
     fn require_memory(&self) -> Result<&Memory> {
         self.memory
             .as_ref()
-            .ok_or_else(|| anyhow::anyhow!(errors::ERR_NOT_ATTACHED))
+            .ok_or_else(|| self.no_process_error())
     }
 
     /// 実行時アドレスをファイルオフセットに変換する（PIE対応）
@@ -175,387 +662,2672 @@ impl Debugger {
     /// プロセスは最初の命令で停止状態で開始されます。
     /// メモリマッピングが完全に初期化されているため、ブレークポイントを安全に設定できます。
     pub fn spawn<P: AsRef<Path>>(&mut self, program: P, args: &[String]) -> Result<()> {
-        let process = Process::spawn(program, args)?;
+        self.spawn_with_options(program, args, &SpawnOptions::default())
+    }
+
+    /// オプションを指定して実行可能ファイルを起動する
+    pub fn spawn_with_options<P: AsRef<Path>>(
+        &mut self,
+        program: P,
+        args: &[String],
+        options: &SpawnOptions,
+    ) -> Result<()> {
+        let process = Process::spawn_with_options(program, args, options)?;
         let pid = process.pid();
+        self.exit_code = None;
+        self.exit_signal = None;
         self.pid = Some(pid);
-        self.memory = Some(Memory::new(pid));
+        self.memory = Some(self.new_memory(pid));
         self.registers = Some(Registers::new(pid));
-        self.process = Some(process);
+        self.current_tid = Some(pid);
+        self.set_process(process);
         Ok(())
     }
 
+    /// ptrace で制御するプロセスをデバッグ対象にする
+    fn set_process(&mut self, process: Process) {
+        let process = Rc::new(process);
+        self.target = Some(Box::new(PtraceBackend::new(Rc::clone(&process))));
+        self.process = Some(process);
+    }
+
+    /// ptrace 以外のデバッグ対象（[`kokia_target::FakeTarget`] など）をシンボル一覧とともにデバッグ対象にする
+    ///
+    /// DWARF 情報は読み込まないので、シンボルとメモリ・汎用レジスタ・実行制御だけで動く機能
+    /// （ブレークポイントや async の entry/exit の追跡）が使えます。
+    pub fn attach_backend(&mut self, target: Box<dyn TargetBackend>, symbols: Vec<Symbol>) {
+        let tid = target.thread_id();
+        self.exit_code = None;
+        self.exit_signal = None;
+        self.pid = Some(tid);
+        self.current_tid = Some(tid);
+        self.target = Some(target);
+        self.symbol_resolver = Some(SymbolResolver::from_symbols(symbols, false));
+        self.symbol_memo.get_mut().clear();
+    }
+
     /// 既存のプロセスにアタッチする
+    ///
+    /// 前のプロセスが終了した・消えた後なら、kokia を起動し直さずにアタッチし直せます。
     pub fn attach(&mut self, pid: i32) -> Result<()> {
+        if let Some(process) = &self.process {
+            anyhow::bail!("Already debugging process {}", process.pid());
+        }
         let process = Process::attach(pid)?;
+        self.exit_code = None;
+        self.exit_signal = None;
+        self.lost_pid = None;
         self.pid = Some(pid);
-        self.memory = Some(Memory::new(pid));
+        self.memory = Some(self.new_memory(pid));
         self.registers = Some(Registers::new(pid));
-        self.process = Some(process);
+        self.current_tid = Some(pid);
+        self.set_process(process);
         Ok(())
     }
 
-    /// ELFバイナリからDWARF情報を読み込む
-    pub fn load_binary<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        let loader = DwarfLoader::load(path)?;
-        let resolver = SymbolResolver::new(&loader)?;
-        self.dwarf_loader = Some(loader);
-        self.symbol_resolver = Some(resolver);
+    /// `target`（PID またはプロセス名）のプロセスにアタッチする
+    ///
+    /// 名前に当てはまるプロセスが複数あれば最も新しく起動したものを選びます。
+    /// PID は名前空間が設定されていればその中の PID として扱います。
+    ///
+    /// # Returns
+    /// アタッチしたプロセスの（ホスト側の）PID
+    pub fn attach_target(&mut self, target: &str) -> Result<i32> {
+        self.try_attach_target(target)?
+            .ok_or_else(|| anyhow::anyhow!("No running process matches '{}'", target))
+    }
+
+    /// [`Self::attach_target`] と同じだが、プロセスが現れるまで `poll_interval` ごとに探し直す
+    ///
+    /// systemd などに再起動されるサービスを待ち構えるためのものです。
+    /// [`kokia_target::interrupt::InterruptGuard`] 設置中の Ctrl-C で待つのをやめます。
+    pub fn attach_wait(&mut self, target: &str, poll_interval: Duration) -> Result<i32> {
+        loop {
+            if let Some(pid) = self.try_attach_target(target)? {
+                return Ok(pid);
+            }
+            if kokia_target::interrupt::interrupted() {
+                anyhow::bail!("Interrupted while waiting for '{}'", target);
+            }
+            std::thread::sleep(poll_interval);
+        }
+    }
+
+    fn try_attach_target(&mut self, target: &str) -> Result<Option<i32>> {
+        let target = match target.parse::<i32>() {
+            Ok(pid) => self.host_pid(pid).unwrap_or(pid).to_string(),
+            Err(_) => target.to_string(),
+        };
+        let found = kokia_target::find_processes(&target);
+        let Some(&pid) = found.first() else {
+            return Ok(None);
+        };
+        if found.len() > 1 {
+            self.emit_warning(format!(
+                "{} processes match '{}'; attaching to the newest one (pid {})",
+                found.len(), target, pid
+            ));
+        }
+        self.attach(pid)?;
+        Ok(Some(pid))
+    }
+
+    /// コンテナ内のプロセスをデバッグするため、名前空間の基準プロセスを設定する
+    ///
+    /// 以降のバイナリ読み込みやメモリマッピングのパスは、`ns_pid`（ホスト側 PID）の
+    /// マウント名前空間内のパスとして解釈されます。
+    pub fn set_target_namespace(&mut self, ns_pid: i32) -> Result<()> {
+        self.target_namespace = Some(TargetNamespace::new(ns_pid)?);
         Ok(())
     }
 
-    /// シンボル名からアドレスを解決する
-    pub fn resolve_symbol(&self, name: &str) -> Option<u64> {
-        self.symbol_resolver.as_ref()?.resolve(name)
+    /// 設定中の名前空間を取得する
+    pub fn target_namespace(&self) -> Option<&TargetNamespace> {
+        self.target_namespace.as_ref()
     }
 
-    /// アドレスからシンボルを解決する
-    pub fn reverse_resolve(&self, addr: u64) -> Option<Symbol> {
-        let resolver = self.symbol_resolver.as_ref()?;
-        let lookup_addr = self.runtime_addr_to_offset(addr).ok()?;
-        resolver.reverse_resolve(lookup_addr)
+    /// デバッグ対象から見たパス（バイナリや DWARF のソースファイル名）をホスト側のパスに変換する
+    pub fn target_path(&self, path: impl AsRef<Path>) -> PathBuf {
+        match &self.target_namespace {
+            Some(ns) => ns.host_path(path),
+            None => path.as_ref().to_path_buf(),
+        }
     }
 
-    /// アドレスから行番号情報を取得する
-    pub fn get_line_info(&self, addr: u64) -> Option<(String, u32)> {
-        let loader = self.dwarf_loader.as_ref()?;
-        let lookup_addr = self.runtime_addr_to_offset(addr).ok()?;
-        let line_provider = LineInfoProvider::new(loader);
-        let line_info = line_provider.lookup(lookup_addr).ok()??;
-        Some((line_info.file?, line_info.line? as u32))
+    /// 名前空間内の PID をホスト側の PID に変換する（名前空間が未設定ならそのまま）
+    pub fn host_pid(&self, pid: i32) -> Result<i32> {
+        match &self.target_namespace {
+            Some(ns) => ns.host_pid(pid),
+            None => Ok(pid),
+        }
     }
 
-    /// パターンにマッチするシンボルを検索する
-    pub fn find_symbols(&self, pattern: &str) -> Vec<Symbol> {
-        self.symbol_resolver
-            .as_ref()
-            .map(|r| r.find_symbols(pattern))
-            .unwrap_or_default()
+    fn new_memory(&self, pid: i32) -> Memory {
+        match &self.target_namespace {
+            Some(ns) => Memory::with_namespace(pid, ns.clone()),
+            None => Memory::new(pid),
+        }
     }
 
-    /// async関連のシンボルをすべて検索する
-    pub fn find_async_symbols(&self) -> Vec<Symbol> {
-        let resolver = match &self.symbol_resolver {
-            Some(r) => r,
-            None => return Vec::new(),
-        };
+    /// デバッグ対象プロセスのスレッドID一覧を取得する
+    pub fn threads(&self) -> Result<Vec<i32>> {
+        let process = self.process.as_ref()
+            .ok_or_else(|| self.no_process_error())?;
+        Ok(process.threads()?.iter().map(|t| t.tid()).collect())
+    }
 
-        resolver
-            .all_symbols()
-            .filter(|sym| self.async_tracker.detector().is_async_related(&sym.name))
-            .cloned()
-            .collect()
+    /// スレッド名を取得する
+    pub fn thread_name(&self, tid: i32) -> Option<String> {
+        kokia_target::thread_name(self.process.as_ref()?.pid(), tid)
     }
 
-    /// async関数のclosureかどうか判定する（ランタイム非依存）
-    ///
-    /// 検出条件：
-    /// - シンボル名が `::{{closure}}` を含む
-    /// - ランタイム内部（tokio::, async_std::, futures::）は除外
-    /// - 標準ライブラリ（std::, core::, alloc::）は除外
-    /// - 依存ライブラリ（parking_lot, hashbrown等）は除外
-    fn is_user_async_closure(name: &str) -> bool {
-        let detector = kokia_async::AsyncDetector::new();
-        detector.is_user_async_closure(name)
+    /// 指定スレッドの PC を取得する（選択中のスレッドは切り替えない）
+    pub fn thread_pc(&self, tid: i32) -> Result<u64> {
+        if self.process.is_none() {
+            return Err(self.no_process_error());
+        }
+        Registers::new(tid).get_pc()
     }
 
-    /// async関数のclosureを検出する（ランタイム非依存）
+    /// リンクされている async ランタイムを検出する（info async-runtime）
     ///
-    /// 最新のRustでは`GenFuture::poll`が存在しないため、async関数のclosureを
-    /// 直接検出してブレークポイントを設定します。
-    pub fn find_async_function_closures(&self) -> Vec<Symbol> {
-        let resolver = match &self.symbol_resolver {
-            Some(r) => r,
-            None => return Vec::new(),
+    /// プロセスが動いていれば、スレッド名からワーカースレッド数とスケジューラも推定します。
+    pub fn async_runtimes(&self) -> Result<Vec<RuntimeReport>> {
+        use crate::runtime;
+
+        let resolver = self.symbol_resolver.as_ref()
+            .ok_or_else(|| anyhow::anyhow!(errors::ERR_DWARF_NOT_LOADED))?;
+        let detected = runtime::detect_runtimes(resolver.all_symbols().map(|s| s.demangled_name.as_str()));
+
+        // ソースパスは行番号テーブルの文字列と、パニック位置の文字列として埋め込まれている
+        let sections: Vec<&[u8]> = self.dwarf_loader.iter()
+            .flat_map(|loader| [".debug_line_str", ".debug_line", ".debug_str", ".rodata"].map(|name| loader.section_data(name)))
+            .flatten()
+            .collect();
+        let thread_names: Option<Vec<String>> = self.threads().ok()
+            .map(|tids| tids.into_iter().filter_map(|tid| self.thread_name(tid)).collect());
+
+        Ok(detected.into_iter().map(|(kind, compiled_schedulers)| {
+            let version = kind.crate_name().and_then(|name| {
+                sections.iter().find_map(|data| runtime::crate_version(data, name))
+            });
+            let worker_threads = thread_names.as_ref()
+                .map(|names| runtime::count_worker_threads(kind, names.iter().map(String::as_str)));
+            let scheduler = runtime::infer_scheduler(kind, &compiled_schedulers, worker_threads);
+            RuntimeReport { kind, version, compiled_schedulers, worker_threads, scheduler }
+        }).collect())
+    }
+
+    /// 読み込んだバイナリのメタデータをまとめる（info binary）
+    pub fn binary_report(&self) -> Result<BinaryReport> {
+        use crate::binary_info::{parse_comment, parse_debuglink, SplitDebugInfo};
+
+        let (Some(loader), Some(resolver), Some(path)) = (&self.dwarf_loader, &self.symbol_resolver, &self.binary_path) else {
+            return Err(anyhow::anyhow!(errors::ERR_DWARF_NOT_LOADED));
+        };
+        let build = BuildInfo::collect(loader)?;
+
+        // rustc は <binary>.dwp にまとめる（-C split-debuginfo=packed）
+        let dwp = PathBuf::from(format!("{}.dwp", path.display()));
+        let debuglink = loader.section_data(".gnu_debuglink").and_then(parse_debuglink);
+        let split_debuginfo = match debuglink {
+            _ if build.split_units > 0 && dwp.exists() => SplitDebugInfo::Packed(dwp),
+            _ if build.split_units > 0 => SplitDebugInfo::Unpacked,
+            Some(name) => SplitDebugInfo::DebugLink(name),
+            None => SplitDebugInfo::Off,
         };
 
-        resolver
-            .all_symbols()
-            .filter(|sym| Self::is_user_async_closure(&sym.demangled_name))
-            .cloned()
-            .collect()
+        Ok(BinaryReport {
+            path: path.clone(),
+            build_id: loader.build_id().map(kokia_target::elf_image::format_build_id),
+            is_pie: loader.is_pie(),
+            has_symtab: loader.section_data(".symtab").is_some(),
+            comment: loader.section_data(".comment").map(parse_comment).unwrap_or_default(),
+            build,
+            split_debuginfo,
+            symbols: resolver.all_symbols().count(),
+            async_closures: self.find_async_function_closures().len(),
+        })
     }
 
-    /// async関数のFuture::poll 実装を検索する（ランタイム非依存）
-    ///
-    /// 検出戦略：
-    /// 1. GenFuture::poll (古いRustバージョン)
-    /// 2. async関数のclosure (最新のRust)
+    /// デバッグ対象プロセスのメタデータを /proc から取得する
+    pub fn process_info(&self) -> Result<ProcessInfo> {
+        let pid = self.pid.ok_or_else(|| self.no_process_error())?;
+        ProcessInfo::read(pid)
+    }
+
+    /// デバッグ対象プロセスのメモリマッピング一覧を取得する
+    pub fn memory_mappings(&self) -> Result<Vec<MemoryMapping>> {
+        self.require_memory()?.get_mappings()
+    }
+
+    /// メモリ領域の保護属性を変更する（mprotect の注入）
     ///
-    /// 注意：最新のRustではFuture::pollがインライン化されているため、
-    /// async関数のclosureエントリーポイントを代わりに使用します。
-    pub fn find_genfuture_poll_symbols(&self) -> Vec<Symbol> {
-        let resolver = match &self.symbol_resolver {
-            Some(r) => r,
-            None => return Vec::new(),
-        };
+    /// # Returns
+    /// ページ境界に揃えた実際の変更範囲（開始アドレス, 長さ）
+    pub fn set_memory_protection(&self, addr: u64, len: u64, prot: Protection) -> Result<(u64, u64)> {
+        let memory = self.require_memory()?;
+        let (start, len) = memory.set_protection(addr as usize, len as usize, prot)?;
+        Ok((start as u64, len as u64))
+    }
 
-        let mut symbols = Vec::new();
+    /// 選択中のスレッドIDを取得する
+    pub fn current_thread(&self) -> Option<i32> {
+        self.current_tid
+    }
 
-        // 1. GenFuture::poll を検出（古いRustコンパイラ用）
-        for sym in resolver.all_symbols() {
-            if (sym.name.contains("GenFuture") && sym.name.contains("poll"))
-                || (sym.name.contains("core..future..from_generator") && sym.name.contains("poll")) {
-                symbols.push(sym.clone());
-            }
+    /// レジスタアクセスの対象スレッドを切り替える
+    ///
+    /// バックトレースやローカル変数の表示は選択中のスレッドのレジスタを使用します。
+    /// continue はすべてのスレッドを再開し、step は選択中のスレッドだけを進めます。
+    pub fn select_thread(&mut self, tid: i32) -> Result<()> {
+        if !self.threads()?.contains(&tid) {
+            return Err(anyhow::anyhow!("Thread not found: {}", tid));
+        }
+        if let Some(process) = &self.process {
+            process.select_thread(tid)?;
         }
+        self.registers = Some(Registers::new(tid));
+        self.current_tid = Some(tid);
+        Ok(())
+    }
 
-        // GenFuture::pollが見つかった場合はそれを返す
-        if !symbols.is_empty() {
-            return symbols;
+    /// 停止を報告したスレッドを選択中のスレッドにする
+    ///
+    /// どのスレッドがブレークポイントを踏んでも、PC の巻き戻しやスコープの更新はそのスレッドの
+    /// レジスタで行います。
+    fn select_stopped_thread(&mut self) {
+        if self.process.as_ref().is_some_and(|process| process.traced_threads().is_empty()) {
+            return;
+        }
+        let Some(tid) = self.target.as_deref().map(|target| target.thread_id()) else {
+            return;
+        };
+        if self.current_tid != Some(tid) {
+            if self.process.is_some() {
+                self.registers = Some(Registers::new(tid));
+            }
+            self.current_tid = Some(tid);
         }
+    }
 
-        // 2. 最新のRust: async関数のclosureを検出
-        self.find_async_function_closures()
+    /// 実行中のイメージのビルドIDを、読み込んだバイナリのものと比較する
+    pub fn check_build_id(&self) -> Result<BuildIdCheck> {
+        let loader = self.dwarf_loader.as_ref()
+            .ok_or_else(|| anyhow::anyhow!(errors::ERR_DWARF_NOT_LOADED))?;
+        let (memory, image) = self.mapped_image()?;
+
+        let check = match (loader.build_id(), image.build_id(memory)?) {
+            (Some(file), Some(target)) if file == target.as_slice() => BuildIdCheck::Match,
+            (Some(file), Some(target)) => BuildIdCheck::Mismatch {
+                file: kokia_target::elf_image::format_build_id(file),
+                target: kokia_target::elf_image::format_build_id(&target),
+            },
+            _ => BuildIdCheck::Unknown,
+        };
+        Ok(check)
     }
 
-    /// async関数に自動的にブレークポイントを設定する
+    /// シンボルテーブルを実行中のイメージの動的シンボル（`.dynsym`）に置き換える
     ///
-    /// async タスクトラッキングのために、async関数のエントリポイントに
-    /// ブレークポイントを設定します。
-    ///
-    /// 検出戦略：
-    /// - 古いRust: GenFuture::poll を検出
-    /// - 最新のRust: async関数のclosureを検出（Future::pollはインライン化されるため）
+    /// ディスク上のバイナリが差し替えられていてシンボルアドレスが信用できない場合に使います。
+    /// DWARF 情報（行番号・型）は読み込んだファイルのまま残ります。
     ///
-    /// 注意：最新のRustでは完全なpoll entry/exitの監視ではなく、
-    /// async関数が最初に呼ばれたときにトラッキングします。
-    pub fn set_genfuture_poll_breakpoints(&mut self) -> Result<Vec<BreakpointId>> {
-        let symbols = self.find_genfuture_poll_symbols();
-
+    /// # Returns
+    /// 読み込んだシンボル数
+    pub fn load_symbols_from_memory(&mut self) -> Result<usize> {
+        let (memory, image) = self.mapped_image()?;
+        let symbols: Vec<Symbol> = image
+            .dynamic_symbols(memory)?
+            .into_iter()
+            .map(|sym| Symbol::new(sym.name, sym.address, sym.size))
+            .collect();
         if symbols.is_empty() {
-            return Ok(Vec::new());
+            return Err(anyhow::anyhow!("The running image exports no dynamic symbols"));
         }
+        let count = symbols.len();
+        self.symbol_resolver = Some(SymbolResolver::from_symbols(symbols, image.is_pie()));
+        self.symbol_memo.get_mut().clear();
+        Ok(count)
+    }
 
-        let mut breakpoint_ids = Vec::new();
+    /// 実行ファイルのマップ済みイメージを読み取る
+    fn mapped_image(&self) -> Result<(&Memory, MappedImage)> {
+        let memory = self.require_memory()?;
+        let base = memory.get_base_address()? as u64;
+        Ok((memory, MappedImage::read(memory, base)?))
+    }
 
-        for symbol in symbols {
-            match self.set_async_breakpoint_by_symbol(&symbol.name) {
-                Ok(bp_id) => {
-                    breakpoint_ids.push(bp_id);
-                }
-                Err(e) => {
-                    warn!("Failed to set breakpoint on {}: {}", symbol.name, e);
-                }
+    /// ELFバイナリからDWARF情報を読み込む
+    ///
+    /// 名前空間が設定されている場合、パスはデバッグ対象のマウント名前空間内のものとして扱います。
+    pub fn load_binary<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = self.target_path(path);
+        let reading = Progress::start(self.progress.as_ref(), format!("Reading {}", path.display()), None);
+        let loader = DwarfLoader::load(&path)?;
+        reading.finish();
+        let resolver = SymbolResolver::new_with_progress(&loader, self.progress.as_ref())?;
+        self.dwarf_loader = Some(loader);
+        self.symbol_resolver = Some(resolver);
+        self.binary_path = Some(path);
+        self.type_index = OnceCell::new();
+        self.call_sites = OnceCell::new();
+        self.call_frames = OnceCell::new();
+        self.suspend_points.clear();
+        self.poll_discriminants.clear();
+        self.symbol_memo.get_mut().clear();
+        Ok(())
+    }
+
+    /// 型名からレイアウトを取得する（見つからなければ None）
+    ///
+    /// 型名は完全修飾名・修飾なしの名前・async fn のパス（状態機械の型）のいずれでもよい。
+    pub fn type_layout(&self, name: &str, expand_depth: usize) -> Result<Option<TypeLayout>> {
+        let (loader, index) = self.type_index()?;
+        match index.lookup(name) {
+            Some(found) => {
+                let mut layout = index.layout(loader, found.type_ref, expand_depth)?;
+                layout.name = found.qualified_name.clone();
+                Ok(Some(layout))
             }
+            None => Ok(None),
         }
-
-        Ok(breakpoint_ids)
     }
 
-    /// Async tracking用のブレークポイントをシンボル名で設定する
-    ///
-    /// entry（関数先頭）とexit（各ret命令）の両方にブレークポイントを設定します。
-    fn set_async_breakpoint_by_symbol(&mut self, symbol_name: &str) -> Result<BreakpointId> {
-        use crate::breakpoint::BreakpointType;
+    /// 型名から値の読み取りに使う型情報を取得する（見つからなければ None）
+    pub fn type_info(&self, name: &str) -> Result<Option<TypeInfo>> {
+        let (loader, index) = self.type_index()?;
+        index.lookup(name).map(|found| index.type_info(loader, found.type_ref)).transpose()
+    }
 
-        let symbol = self.find_best_symbol(symbol_name)?;
-        let entry_address = self.offset_to_runtime_addr(symbol.address)?;
+    /// 型名を完全修飾名に解決する（レイアウトは組み立てない）
+    pub fn resolve_type_name(&self, name: &str) -> Result<Option<String>> {
+        let (_, index) = self.type_index()?;
+        Ok(index.lookup(name).map(|found| found.qualified_name.clone()))
+    }
 
-        // メモリを取得（借用問題を避けるため後で使う）
-        let memory = self.memory.as_ref()
-            .ok_or_else(|| anyhow::anyhow!(errors::ERR_NOT_ATTACHED))?;
+    /// 型索引を取得する（未構築なら構築する）
+    fn type_index(&self) -> Result<(&DwarfLoader, &TypeIndex)> {
+        let loader = self.dwarf_loader.as_ref()
+            .ok_or_else(|| anyhow::anyhow!(errors::ERR_DWARF_NOT_LOADED))?;
+        let index = match self.type_index.get() {
+            Some(index) => index,
+            None => {
+                let index = TypeIndex::build_with_progress(loader, self.progress.as_ref())?;
+                self.type_index.get_or_init(|| index)
+            }
+        };
+        Ok((loader, index))
+    }
+
+    /// 呼び出し位置の索引を取得する（未構築なら構築する）
+    fn call_site_index(&self) -> Result<&CallSiteIndex> {
+        let loader = self.dwarf_loader.as_ref()
+            .ok_or_else(|| anyhow::anyhow!(errors::ERR_DWARF_NOT_LOADED))?;
+        match self.call_sites.get() {
+            Some(index) => Ok(index),
+            None => {
+                let index = CallSiteIndex::build_with_progress(loader, self.progress.as_ref())?;
+                Ok(self.call_sites.get_or_init(|| index))
+            }
+        }
+    }
+
+    /// 呼び出し元のフレームのリターンアドレスから、呼び出しの行を取得する
+    ///
+    /// 呼び出し位置の情報があれば call 命令の位置で、なければリターンアドレスの1つ前で引きます。
+    /// リターンアドレスそのものは、呼び出しの次の行（`.await` の後など）に属していることがあります。
+    pub fn get_call_line_info(&self, return_address: u64) -> Option<(String, u32)> {
+        let offset = self.runtime_addr_to_offset(return_address).ok()?;
+        let call_pc = self.call_site_index().ok()
+            .and_then(|index| index.find_by_return_pc(offset))
+            .map(|site| site.line_lookup_pc())
+            .unwrap_or(offset.saturating_sub(1));
+        let loader = self.dwarf_loader.as_ref()?;
+        let line_info = LineInfoProvider::new(loader).lookup(call_pc).ok()??;
+        Some((line_info.file?, line_info.line? as u32))
+    }
+
+    /// 現在の関数の入口での引数レジスタの値を、呼び出し元の call site から復元する
+    ///
+    /// リターンアドレスは、プロローグの前なら rsp、後なら rbp+8 にあります。呼び出し位置の
+    /// 呼び出し先が現在の関数と一致するものだけを使います。呼び出し元のレジスタは rbp と rsp しか
+    /// 分からないので、それ以外に依存する引数は復元しません。
+    fn caller_entry_values(&self, pc: u64, regs: &UserRegs) -> HashMap<u16, u64> {
+        let (Some(memory), Some(symbol), Ok(index)) = (self.target.as_deref(), self.reverse_resolve(pc), self.call_site_index()) else {
+            return HashMap::new();
+        };
+
+        let saved_rbp = memory.read_word(regs.rbp).ok();
+        let candidates = [(regs.rsp, Some(regs.rbp)), (regs.rbp.wrapping_add(8), saved_rbp)];
+        for (slot, caller_rbp) in candidates {
+            let Ok(return_address) = memory.read_word(slot) else {
+                continue;
+            };
+            let Ok(offset) = self.runtime_addr_to_offset(return_address) else {
+                continue;
+            };
+            let Some(site) = index.find_by_return_pc(offset) else {
+                continue;
+            };
+            if site.target.as_deref().is_some_and(|t| t != symbol.name && t != symbol.demangled_name) {
+                continue;
+            }
+
+            let get_reg = |reg: u16| match (reg, caller_rbp) {
+                (6, Some(rbp)) => Ok(rbp),
+                // call 命令を実行する前の rsp
+                (7, _) => Ok(slot.wrapping_add(8)),
+                _ => Err(anyhow::anyhow!("Register {} of the caller is unknown", reg)),
+            };
+            let read_mem = |addr: u64, size: usize| memory.read_memory(addr, size);
+            return site.entry_values(get_reg, read_mem);
+        }
+        HashMap::new()
+    }
+
+    /// シンボル名からアドレスを解決する
+    pub fn resolve_symbol(&self, name: &str) -> Option<u64> {
+        self.symbol_resolver.as_ref()?.resolve(name)
+    }
+
+    /// シンボル名から実行時アドレス（関数先頭）を取得する（PIE対応）
+    pub fn symbol_address(&self, name: &str) -> Result<u64> {
+        let symbol = self.find_best_symbol(name)?;
+        self.offset_to_runtime_addr(symbol.address)
+    }
+
+    /// 選択中のスレッドの汎用レジスタ一式を取得する
+    pub fn general_registers(&self) -> Result<UserRegs> {
+        self.require_target()?.registers()
+    }
+
+    /// PC の前後の命令を逆アセンブルする
+    ///
+    /// x86 は可変長命令なので、PC を含む関数の先頭から読んで PC より前の `before` 命令と、
+    /// PC 以降の `after` 命令を返します。関数がわからなければ PC から読みます。
+    /// ブレークポイントの INT3 は元の命令に戻して表示します。
+    pub fn disassemble_around(&self, pc: u64, before: usize, after: usize) -> Result<Vec<crate::disasm::DisasmLine>> {
+        // 関数先頭から PC までが長すぎる場合は、PC 手前だけを読む意味がないので PC から読む
+        const MAX_LEAD: u64 = 4096;
+
+        let start = self.reverse_resolve(pc)
+            .and_then(|sym| self.offset_to_runtime_addr(sym.address).ok())
+            .filter(|&start| start <= pc && pc - start <= MAX_LEAD)
+            .unwrap_or(pc);
+        let len = (pc - start) as usize + after * MAX_INSTRUCTION_LEN;
+        let memory = self.require_memory()?;
+        let mut code = memory.read_partial(start as usize, len)?.prefix().to_vec();
+        self.breakpoint_manager.restore_original_bytes(start, &mut code);
+
+        let lines = crate::disasm::disassemble(&code, start)?;
+        let at = lines.iter().position(|l| l.address >= pc).unwrap_or(lines.len());
+        let from = at.saturating_sub(before);
+        Ok(lines.into_iter().skip(from).take(at - from + after).collect())
+    }
+
+    /// 停止中のプロセスのコアファイルを書き出す
+    ///
+    /// 選択中のスレッドを先頭に、ptrace で止めているスレッドのレジスタを書き出します。
+    /// 直前の停止がシグナルなら、そのシグナルを受けたものとして記録します。
+    pub fn write_core(&self, path: &Path) -> Result<CoreSummary> {
+        let pid = self.pid.ok_or_else(|| self.no_process_error())?;
+        let current = self.current_tid.unwrap_or(pid);
+        let mut threads = vec![current];
+        threads.extend(self.threads()?.into_iter().filter(|&tid| tid != current));
+
+        let signal = self.last_stop.as_ref().and_then(|e| e.signal).map(|info| info.signo);
+        kokia_target::write_core(path, pid, self.require_memory()?, &threads, signal)
+    }
+
+    /// Rust のパニックで止まるキャッチポイントを設定する（catch panic）
+    ///
+    /// `rust_panic` に止まるためのブレークポイントと、パニック位置を控えるためのパニックハンドラの
+    /// 内部ブレークポイントを設定し、前者のIDを返します。
+    pub fn catch_panic(&mut self) -> Result<BreakpointId> {
+        use crate::breakpoint::BreakpointType;
+
+        if let Some(bp) = self.breakpoint_manager.all().find(|bp| bp.bp_type == BreakpointType::PanicCatch) {
+            return Ok(bp.id);
+        }
+
+        let catch_address = self.panic_symbol_address(crate::panic::PANIC_SYMBOLS)
+            .ok_or_else(|| anyhow::anyhow!("No panic runtime symbol (rust_panic) found; is this a Rust binary?"))?;
+        match self.panic_symbol_address(crate::panic::PANIC_HANDLER_SYMBOLS) {
+            Some(handler) => {
+                self.set_breakpoint_with_type(handler, BreakpointType::PanicHandler)?;
+            }
+            None => self.emit_warning("Panic handler not found; panic locations will not be reported".to_string()),
+        }
+        self.set_breakpoint_with_type(catch_address, BreakpointType::PanicCatch)
+    }
+
+    /// poll 中のパニックで Panicked になったタスクで止まるようにする（async catch poison）
+    ///
+    /// パニックハンドラで poll 中のタスクを控え、catch_unwind がパニックを受け止めた所で
+    /// それらの状態を読み直します。poll 中のタスクは async 計装で分かるので、先に async enable が必要です。
+    pub fn catch_poison(&mut self) -> Result<()> {
+        use crate::breakpoint::BreakpointType;
+
+        if self.poison_catch.is_some() {
+            return Ok(());
+        }
+        if self.instrumentation.is_empty() {
+            return Err(anyhow::anyhow!("Async tracking is not enabled (use 'async enable' first)"));
+        }
+        let handler = self.panic_symbol_address(crate::panic::PANIC_HANDLER_SYMBOLS)
+            .ok_or_else(|| anyhow::anyhow!("No panic handler symbol (rust_begin_unwind) found; is this a Rust binary?"))?;
+        if !self.set_panic_cleanup_breakpoint()? {
+            return Err(anyhow::anyhow!("No __rust_panic_cleanup symbol found; is the binary built with panic=abort?"));
+        }
+        if !self.breakpoint_manager.all().any(|bp| bp.bp_type == BreakpointType::PanicHandler) {
+            self.set_breakpoint_with_type(handler, BreakpointType::PanicHandler)?;
+        }
+        self.poison_catch = Some(PoisonCatch::new());
+        Ok(())
+    }
+
+    /// catch_unwind がパニックを受け止める所（`__rust_panic_cleanup`）に内部ブレークポイントを置く
+    ///
+    /// async 計装の巻き戻しの検出と `async catch poison` が共有します。
+    ///
+    /// # Returns
+    /// 置いた（置いてあった）なら true、シンボルがない（panic=abort）なら false
+    fn set_panic_cleanup_breakpoint(&mut self) -> Result<bool> {
+        use crate::breakpoint::BreakpointType;
+
+        if self.breakpoint_manager.all().any(|bp| bp.bp_type == BreakpointType::PanicCleanup) {
+            return Ok(true);
+        }
+        let Some(cleanup) = self.panic_symbol_address(PANIC_CLEANUP_SYMBOLS) else {
+            return Ok(false);
+        };
+        self.set_breakpoint_with_type(cleanup, BreakpointType::PanicCleanup)?;
+        Ok(true)
+    }
+
+    /// パニックを受け止めた所で、巻き戻しで exit を通らずに抜けた poll をスコープから外す
+    ///
+    /// `__rust_panic_cleanup` では、受け止めたフレームの下に do_catch・cleanup のフレームが積まれていて、
+    /// 巻き戻された poll のフレームがあった所を再び使っています。そのため停止時の RSP ではなく、
+    /// それらを抜けた受け止めたフレームの CFA と比べます。CFA が分からなければ cleanup の CFA で代えます。
+    fn unwind_async_scopes(&mut self) {
+        let (Some(tid), Ok(regs)) = (self.stopped_tid(), self.general_registers()) else {
+            return;
+        };
+        let cfa = self.panic_catch_cfa(&regs).unwrap_or(regs.rsp + 8);
+        let unwound = self.async_tracker.on_unwind(tid, cfa);
+        if !unwound.is_empty() {
+            debug!("Panic unwound {} poll scope(s): {:?}", unwound.len(), unwound);
+        }
+    }
+
+    /// `__rust_panic_cleanup` の入口から、パニックの受け止めの関数（[`PANIC_CATCH_FRAMES`]）の
+    /// フレームを `.eh_frame` で抜け、最初のそれ以外のフレーム（受け止めたフレーム）の CFA を返す
+    fn panic_catch_cfa(&self, regs: &UserRegs) -> Option<u64> {
+        const MAX_FRAMES: usize = 16;
+
+        let table = self.call_frames.get_or_init(|| self.dwarf_loader.as_ref().and_then(CallFrameTable::new)).as_ref()?;
+        let memory = self.target.as_deref()?;
+        // 停止位置は関数の先頭なのでそのまま引き、呼び出し元は call 命令の中（戻りアドレス - 1）で引く
+        let (mut lookup_pc, mut rsp, mut rbp) = (regs.rip, regs.rsp, regs.rbp);
+        for _ in 0..MAX_FRAMES {
+            let rule = table.rule_at(self.runtime_addr_to_offset(lookup_pc).ok()?).ok()??;
+            let cfa = rule.cfa(rsp, rbp)?;
+            let name = self.reverse_resolve(lookup_pc)?.demangled_name;
+            if !PANIC_CATCH_FRAMES.iter().any(|frame| name.contains(frame)) {
+                return Some(cfa);
+            }
+            if let Some(offset) = rule.saved_rbp {
+                rbp = memory.read_word(cfa.wrapping_add_signed(offset)).ok()?;
+            }
+            lookup_pc = memory.read_word(cfa.checked_sub(8)?).ok()?.checked_sub(1)?;
+            rsp = cfa;
+        }
+        None
+    }
+
+    /// `async catch poison` が有効か
+    pub fn is_catching_poison(&self) -> bool {
+        self.poison_catch.is_some()
+    }
+
+    /// catch_unwind がパニックを受け止めた所で、パニックの時に poll 中だったタスクの状態を読み直す
+    fn check_poisoned_tasks(&mut self) -> Option<PoisonedTask> {
+        let tid = kokia_async::Tid(self.current_tid.or(self.pid)?);
+        let mut catch = self.poison_catch.take()?;
+        let poisoned = catch.on_cleanup(tid, |task| {
+            let task = self.async_tracker.get_task(task)?;
+            self.read_discriminant(task.address, task.type_name.as_deref())
+        });
+        self.poison_catch = Some(catch);
+        let poisoned = poisoned?;
+        for &task in &poisoned.poisoned {
+            self.async_tracker.record_discriminant(task, kokia_async::PANICKED_DISCRIMINANT);
+        }
+        Some(poisoned)
+    }
+
+    /// 候補の名前のうち最初に見つかったパニック関連シンボルの実行時アドレス
+    fn panic_symbol_address(&self, names: &[&str]) -> Option<u64> {
+        names.iter().find_map(|name| {
+            let symbol = self.find_symbols(name).into_iter()
+                .find(|s| crate::panic::is_panic_symbol(&s.demangled_name, name))?;
+            self.offset_to_runtime_addr(symbol.address).ok()
+        })
+    }
+
+    /// パニックハンドラの引数（`&PanicInfo`）からパニック位置を読む
+    fn read_panic_location(&self) -> Option<PanicLocation> {
+        let regs = self.general_registers().ok()?;
+        let memory = self.target.as_deref()?;
+        crate::panic::read_panic_info_location(&mut |address, len| memory.read_memory(address, len), regs.rdi)
+    }
+
+    /// `rust_panic` の引数（`&mut dyn PanicPayload`）からメッセージを読み、控えた位置と合わせる
+    fn take_panic_report(&mut self) -> PanicReport {
+        let location = self.pending_panic_location.take();
+        let message = self.general_registers().ok().and_then(|regs| {
+            let memory = self.target.as_deref()?;
+            crate::panic::read_payload_message(&mut |address, len| memory.read_memory(address, len), regs.rdi, regs.rsi)
+        });
+        PanicReport { message, location }
+    }
+
+    /// 関数が Err を返すときに止まるキャッチポイントを設定する（catch err）
+    ///
+    /// 戻り値の型は DWARF から取り、関数内の各 ret 命令にブレークポイントを置きます。
+    pub fn catch_err(&mut self, function: &str) -> Result<ErrCatchpoint> {
+        use crate::breakpoint::BreakpointType;
+
+        let symbol = self.find_best_symbol(function)?;
+        let rets = self.find_function_rets(&symbol)?;
+        let layout = {
+            let (loader, index) = self.type_index()?;
+            let type_ref = kokia_dwarf::function_return_type(loader, symbol.address)?
+                .ok_or_else(|| anyhow::anyhow!("{} does not return a value", symbol.demangled_name))?;
+            index.layout(loader, type_ref, 0)?
+        };
+        let mut catchpoint = ErrCatchpoint::new(symbol.demangled_name.clone(), layout)?;
+
+        for ret in rets {
+            let address = self.offset_to_runtime_addr(ret)?;
+            catchpoint.breakpoints.push(self.set_breakpoint_with_type(address, BreakpointType::ErrReturn)?);
+        }
+        if catchpoint.breakpoints.is_empty() {
+            anyhow::bail!("No return instruction found in {}", symbol.demangled_name);
+        }
+        self.err_catchpoints.push(catchpoint.clone());
+        Ok(catchpoint)
+    }
+
+    /// 関数内の ret 命令のアドレス（DWARF 上のアドレス）を探す
+    ///
+    /// コールドな部分に分割された関数は、その部分の ret 命令も含めます。
+    fn find_function_rets(&self, symbol: &Symbol) -> Result<Vec<u64>> {
+        let ranges = self.symbol_resolver.as_ref()
+            .map(|resolver| resolver.function_ranges(symbol))
+            .unwrap_or_default();
+        if ranges.is_empty() {
+            anyhow::bail!("Size of {} is unknown; cannot find its return instructions", symbol.demangled_name);
+        }
+
+        let memory = self.require_target()?;
+        let mut rets = Vec::new();
+        for (start, end) in ranges {
+            let runtime_start = self.offset_to_runtime_addr(start)?;
+            let mut code = memory.read_memory(runtime_start, (end - start) as usize)?;
+            self.breakpoint_manager.restore_original_bytes(runtime_start, &mut code);
+            rets.extend(crate::disasm::find_ret_instructions(&code, start)?);
+        }
+        Ok(rets)
+    }
+
+    /// ret 命令のキャッチポイントで、戻り値が Err なら取り出す
+    fn check_err_return(&self, pc: u64) -> Option<ErrReturn> {
+        let id = self.breakpoint_manager.find_by_address(pc)?;
+        let catchpoint = self.err_catchpoints.iter().find(|c| c.breakpoints.contains(&id))?;
+        let regs = self.general_registers().ok()?;
+        if catchpoint.returned_in_memory() {
+            let bytes = self.target.as_deref()?
+                .read_memory(regs.rax, catchpoint.return_size() as usize)
+                .inspect_err(|e| debug!("Failed to read return value of {}: {}", catchpoint.function, e))
+                .ok()?;
+            catchpoint.check(&bytes, Some(regs.rax))
+        } else {
+            catchpoint.check(&catchpoint.register_image(regs.rax, regs.rdx), None)
+        }
+    }
+
+    /// 致命的なシグナルでの停止時にクラッシュレポートを表示するかを設定する
+    pub fn set_crash_report(&mut self, enabled: bool) {
+        self.crash_report = enabled;
+    }
+
+    /// 致命的なシグナルでの停止時にクラッシュレポートを表示するか
+    pub fn crash_report_enabled(&self) -> bool {
+        self.crash_report
+    }
+
+    /// レジスタ名（`pc`, `rsp` など）から現在の値を取得する
+    ///
+    /// `pc`/`sp`/`fp` はそれぞれ `rip`/`rsp`/`rbp` の別名として扱います。
+    pub fn read_register(&self, name: &str) -> Result<u64> {
+        let regs = self.general_registers()?;
+        let value = match name {
+            "pc" | "rip" => regs.rip,
+            "sp" | "rsp" => regs.rsp,
+            "fp" | "rbp" => regs.rbp,
+            "rax" => regs.rax,
+            "rbx" => regs.rbx,
+            "rcx" => regs.rcx,
+            "rdx" => regs.rdx,
+            "rsi" => regs.rsi,
+            "rdi" => regs.rdi,
+            "r8" => regs.r8,
+            "r9" => regs.r9,
+            "r10" => regs.r10,
+            "r11" => regs.r11,
+            "r12" => regs.r12,
+            "r13" => regs.r13,
+            "r14" => regs.r14,
+            "r15" => regs.r15,
+            "eflags" => regs.eflags,
+            "fs_base" => regs.fs_base,
+            "gs_base" => regs.gs_base,
+            _ => return Err(anyhow::anyhow!("Unknown register: ${}", name)),
+        };
+        Ok(value)
+    }
+
+    /// 値の履歴とコンビニエンス変数
+    pub fn convenience(&self) -> &ConvenienceVariables {
+        &self.convenience
+    }
+
+    /// 値を履歴に加え、その番号（`$n` の n）を返す
+    pub fn record_value(&mut self, value: Value) -> usize {
+        self.convenience.record(value)
+    }
+
+    /// コンビニエンス変数 `$name` を設定する（`$pc` などのレジスタ名は使えない）
+    pub fn set_convenience_variable(&mut self, name: &str, value: Value) -> Result<()> {
+        if REGISTER_NAMES.contains(&name) {
+            return Err(anyhow::anyhow!("${} is a register", name));
+        }
+        self.convenience.set(name, value)
+    }
+
+    /// 今の停止・フレームで評価済みの式の結果
+    pub(crate) fn cached_evaluation(&self, expr: &crate::Expression) -> Option<crate::EvaluationResult> {
+        let (epoch, frame) = self.eval_cache_key()?;
+        self.eval_cache.borrow_mut().get(epoch, expr, frame)
+    }
+
+    /// 式の評価結果を、今の停止・フレームの間だけ覚える
+    pub(crate) fn cache_evaluation(&self, expr: &crate::Expression, result: &crate::EvaluationResult) {
+        if let Some((epoch, frame)) = self.eval_cache_key() {
+            self.eval_cache.borrow_mut().insert(epoch, expr, frame, result.clone());
+        }
+    }
+
+    fn eval_cache_key(&self) -> Option<(StopEpoch, FrameKey)> {
+        let regs = self.general_registers().ok()?;
+        Some((self.stop_epoch()?, (regs.rip, regs.rbp)))
+    }
+
+    /// 今の停止（プロセスがなければ None）
+    fn stop_epoch(&self) -> Option<StopEpoch> {
+        self.process.as_ref().map(|process| (process.pid(), process.resume_count()))
+    }
+
+    /// アドレスからシンボルを解決する
+    ///
+    /// 結果は再開するまで覚えておきます。
+    pub fn reverse_resolve(&self, addr: u64) -> Option<Symbol> {
+        let epoch = self.stop_epoch();
+        if let Some(symbol) = epoch.and_then(|epoch| self.symbol_memo.borrow_mut().symbol(epoch, addr)) {
+            return symbol;
+        }
+        let symbol = self.symbol_resolver.as_ref()
+            .zip(self.runtime_addr_to_offset(addr).ok())
+            .and_then(|(resolver, lookup_addr)| resolver.reverse_resolve(lookup_addr));
+        if let Some(epoch) = epoch {
+            self.symbol_memo.borrow_mut().insert_symbol(epoch, addr, symbol.clone());
+        }
+        symbol
+    }
+
+    /// アドレスから行番号情報を取得する（[`Debugger::reverse_resolve`] と同じく再開するまで覚えておく）
+    pub fn get_line_info(&self, addr: u64) -> Option<(String, u32)> {
+        let epoch = self.stop_epoch();
+        if let Some(line) = epoch.and_then(|epoch| self.symbol_memo.borrow_mut().line(epoch, addr)) {
+            return line;
+        }
+        let line = self.lookup_line_info(addr);
+        if let Some(epoch) = epoch {
+            self.symbol_memo.borrow_mut().insert_line(epoch, addr, line.clone());
+        }
+        line
+    }
+
+    fn lookup_line_info(&self, addr: u64) -> Option<(String, u32)> {
+        let loader = self.dwarf_loader.as_ref()?;
+        let lookup_addr = self.runtime_addr_to_offset(addr).ok()?;
+        let line_provider = LineInfoProvider::new(loader);
+        let line_info = line_provider.lookup(lookup_addr).ok()??;
+        Some((line_info.file?, line_info.line? as u32))
+    }
+
+    /// パターンにマッチするシンボルを検索する
+    pub fn find_symbols(&self, pattern: &str) -> Vec<Symbol> {
+        self.symbol_resolver
+            .as_ref()
+            .map(|r| r.find_symbols(pattern))
+            .unwrap_or_default()
+    }
+
+    /// async関連のシンボルをすべて検索する
+    pub fn find_async_symbols(&self) -> Vec<Symbol> {
+        let resolver = match &self.symbol_resolver {
+            Some(r) => r,
+            None => return Vec::new(),
+        };
+
+        resolver
+            .all_symbols()
+            .filter(|sym| self.async_tracker.detector().is_async_related(&sym.name))
+            .cloned()
+            .collect()
+    }
+
+    /// async関数のclosureかどうか判定する（ランタイム非依存）
+    ///
+    /// 検出条件：
+    /// - シンボル名が `::{{closure}}` を含む
+    /// - ランタイム内部（tokio::, async_std::, futures::）は除外
+    /// - 標準ライブラリ（std::, core::, alloc::）は除外
+    /// - 依存ライブラリ（parking_lot, hashbrown等）は除外
+    fn is_user_async_closure(name: &str) -> bool {
+        let detector = kokia_async::AsyncDetector::new();
+        detector.is_user_async_closure(name)
+    }
+
+    /// async関数のclosureを検出する（ランタイム非依存）
+    ///
+    /// 最新のRustでは`GenFuture::poll`が存在しないため、async関数のclosureを
+    /// 直接検出してブレークポイントを設定します。
+    pub fn find_async_function_closures(&self) -> Vec<Symbol> {
+        let resolver = match &self.symbol_resolver {
+            Some(r) => r,
+            None => return Vec::new(),
+        };
+
+        resolver
+            .all_symbols()
+            .filter(|sym| Self::is_user_async_closure(&sym.demangled_name))
+            .cloned()
+            .collect()
+    }
+
+    /// async関数のFuture::poll 実装を検索する（ランタイム非依存）
+    ///
+    /// 検出戦略：
+    /// 1. GenFuture::poll (古いRustバージョン)
+    /// 2. async関数のclosure (最新のRust)
+    ///
+    /// 注意：最新のRustではFuture::pollがインライン化されているため、
+    /// async関数のclosureエントリーポイントを代わりに使用します。
+    pub fn find_genfuture_poll_symbols(&self) -> Vec<Symbol> {
+        let resolver = match &self.symbol_resolver {
+            Some(r) => r,
+            None => return Vec::new(),
+        };
+
+        let mut symbols = Vec::new();
+
+        // 1. GenFuture::poll を検出（古いRustコンパイラ用）
+        for sym in resolver.all_symbols() {
+            if (sym.name.contains("GenFuture") && sym.name.contains("poll"))
+                || (sym.name.contains("core..future..from_generator") && sym.name.contains("poll")) {
+                symbols.push(sym.clone());
+            }
+        }
+
+        // GenFuture::pollが見つかった場合はそれを返す
+        if !symbols.is_empty() {
+            return symbols;
+        }
+
+        // 2. 最新のRust: async関数のclosureを検出
+        self.find_async_function_closures()
+    }
+
+    /// async関数に自動的にブレークポイントを設定する
+    ///
+    /// async タスクトラッキングのために、async関数のエントリポイントに
+    /// ブレークポイントを設定します。
+    ///
+    /// 検出戦略：
+    /// - 古いRust: GenFuture::poll を検出
+    /// - 最新のRust: async関数のclosureを検出（Future::pollはインライン化されるため）
+    ///
+    /// 注意：最新のRustでは完全なpoll entry/exitの監視ではなく、
+    /// async関数が最初に呼ばれたときにトラッキングします。
+    pub fn set_genfuture_poll_breakpoints(&mut self) -> Result<Vec<BreakpointId>> {
+        self.enable_async_instrumentation(None)
+    }
+
+    /// フィルタにマッチする async 関数だけに計装用ブレークポイントを設定する
+    ///
+    /// すでに計装済みの関数はスキップします。
+    ///
+    /// # Returns
+    /// 新たに設定した entry ブレークポイントのID
+    pub fn enable_async_instrumentation(&mut self, filter: Option<&SymbolPattern>) -> Result<Vec<BreakpointId>> {
+        self.async_read_only = false;
+        let symbols = self.instrumentation.select(self.find_genfuture_poll_symbols(), filter);
+
+        if symbols.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut breakpoint_ids = Vec::new();
+        let mut progress = Progress::start(
+            self.progress.as_ref(),
+            "Setting async breakpoints",
+            Some(symbols.len() as u64),
+        );
+
+        for symbol in symbols {
+            progress.advance(1);
+            match self.set_async_breakpoint_by_symbol(&symbol.name) {
+                Ok((entry, exits)) => {
+                    // サイズが分かれば exit BP も配置済み
+                    self.instrumentation.arm(&symbol, entry, exits, symbol.size > 0);
+                    breakpoint_ids.push(entry);
+                }
+                Err(e) => {
+                    self.emit_warning(format!("Failed to set breakpoint on {}: {}", symbol.name, e));
+                }
+            }
+        }
+
+        // パニックで exit を通らずに抜けた poll をスコープから外すため
+        if let Err(e) = self.set_panic_cleanup_breakpoint() {
+            self.emit_warning(format!("Failed to set the panic cleanup breakpoint: {}", e));
+        }
+
+        Ok(breakpoint_ids)
+    }
+
+    /// パターンにマッチする async 関数の計装を外す
+    ///
+    /// # Returns
+    /// 計装を外した関数
+    pub fn disable_async_instrumentation(&mut self, pattern: &SymbolPattern) -> Result<Vec<ArmedFunction>> {
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        let removed = self.instrumentation.disarm(pattern);
+        for function in &removed {
+            for bp_id in function.breakpoints() {
+                self.breakpoint_manager.remove_and_disable(bp_id, memory)?;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// async トラッキングを止め、ターゲットを計装前の速さに戻す
+    ///
+    /// すべての AsyncEntry/AsyncExit ブレークポイント（計装プランに載っていない、配置の途中で
+    /// 失敗したものも含む）を削除し、uprobe による収集も止めます。`keep_data` なら集めたタスク・
+    /// エッジを読み取り専用のスナップショットとして残し、そうでなければ破棄します。
+    pub fn disable_all_async_instrumentation(&mut self, keep_data: bool) -> Result<AsyncTeardown> {
+        use crate::breakpoint::BreakpointType;
+
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        let functions = self.instrumentation.len();
+        let mut breakpoints = 0;
+        for bp_type in [BreakpointType::AsyncEntry, BreakpointType::AsyncExit] {
+            breakpoints += self.breakpoint_manager.remove_by_type(bp_type, memory)?;
+        }
+        if self.poison_catch.is_none() {
+            self.breakpoint_manager.remove_by_type(BreakpointType::PanicCleanup, memory)?;
+        }
+        // exit ブレークポイントの配置済みフラグも計装プランと一緒に消える
+        self.instrumentation.clear();
+        let uprobes_stopped = self.stop_uprobe_tracking();
+        if keep_data {
+            self.async_read_only = true;
+        } else {
+            self.clear_async_tracking();
+        }
+        Ok(AsyncTeardown { functions, breakpoints, uprobes_stopped, data_kept: keep_data })
+    }
+
+    /// async トラッキングを止めたあと、集めたデータを読み取り専用で残しているか
+    ///
+    /// 読み取り専用の間は、終了したスレッドのスコープも捨てずにそのまま残します。
+    pub fn is_async_read_only(&self) -> bool {
+        self.async_read_only
+    }
+
+    /// パターンにマッチする計装済み async 関数の entry を `every` 回に1回だけ記録するようにする
+    ///
+    /// 間引いたヒットはカウンタを進めるだけで、REPL に戻らず実行を再開します。
+    ///
+    /// # Returns
+    /// 設定した関数の数
+    pub fn set_async_sampling(&mut self, pattern: &SymbolPattern, every: u64) -> usize {
+        self.instrumentation.set_sampling(pattern, every)
+    }
+
+    /// async 計装プランを取得する
+    pub fn async_instrumentation(&self) -> &InstrumentationPlan {
+        &self.instrumentation
+    }
+
+    /// async 関数の entry/return に uprobe を設置してイベント収集を始める
+    ///
+    /// INT3 で計装済みの関数は対象外です。
+    ///
+    /// # Returns
+    /// uprobe を設置した関数の数
+    pub fn start_uprobe_tracking(&mut self, filter: Option<&SymbolPattern>) -> Result<usize> {
+        if self.uprobe_session.is_some() {
+            return Err(anyhow::anyhow!("uprobe tracking is already active"));
+        }
+        let pid = self.pid.ok_or_else(|| self.no_process_error())?;
+        self.async_read_only = false;
+        let symbols = self.instrumentation.select(self.find_genfuture_poll_symbols(), filter);
+        if symbols.is_empty() {
+            return Err(anyhow::anyhow!("No async function closures to probe"));
+        }
+
+        let mappings = self.require_memory()?.get_mappings()?;
+        let mut probes = Vec::new();
+        let mut targets = Vec::new();
+        for symbol in symbols {
+            let entry = self.offset_to_runtime_addr(symbol.address)?;
+            let Some((path, file_offset)) = file_location(&mappings, entry) else {
+                self.emit_warning(format!("No file mapping for {} at 0x{:x}", symbol.demangled_name, entry));
+                continue;
+            };
+            for kind in [ProbeKind::Entry, ProbeKind::Return] {
+                probes.push(ProbeSpec { path: path.clone(), file_offset, kind });
+            }
+            targets.push(UprobeTarget {
+                name: symbol.demangled_name.clone(),
+                entry,
+                poll: self.poll_discriminant(&symbol),
+            });
+        }
+
+        self.uprobe_session = Some(UprobeSession::open(pid, probes)?);
+        self.uprobe_targets = targets;
+        self.uprobe_load_base = self.offset_to_runtime_addr(0)?;
+        Ok(self.uprobe_targets.len())
+    }
+
+    /// uprobe を外して収集を終える
+    ///
+    /// # Returns
+    /// 収集中だったか
+    pub fn stop_uprobe_tracking(&mut self) -> bool {
+        self.uprobe_targets.clear();
+        self.uprobe_session.take().is_some()
+    }
+
+    /// uprobe による収集中か
+    pub fn uprobe_tracking_active(&self) -> bool {
+        self.uprobe_session.is_some()
+    }
+
+    /// プロセスを `duration` の間だけ走らせ、uprobe のイベントを AsyncTracker に渡す
+    ///
+    /// 期間が過ぎると SIGSTOP で止めて REPL に戻ります。途中でブレークポイントに当たったり
+    /// 終了したりした場合はその時点で打ち切ります。
+    pub fn collect_uprobe_events(&mut self, duration: Duration) -> Result<UprobeCollection> {
+        if self.uprobe_session.is_none() {
+            return Err(anyhow::anyhow!("uprobe tracking is not active (use 'async uprobe start')"));
+        }
+        // ブレークポイント上で止まっている場合は先に踏み越える
+        self.step_over_breakpoint()?;
+        let process = self.process.as_ref()
+            .ok_or_else(|| self.no_process_error())?;
+        process.continue_execution()?;
+        let deadline = std::time::Instant::now() + duration;
+        let mut events = 0;
+        let stop_reason = loop {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            let batch = self.uprobe_session.as_mut()
+                .map(|session| session.poll(remaining.min(Duration::from_millis(100))))
+                .transpose()?
+                .unwrap_or_default();
+            events += self.feed_uprobe_events(&batch);
+            self.feed_uprobe_thread_exits();
+
+            let process = self.process.as_ref()
+                .ok_or_else(|| self.no_process_error())?;
+            if let Some(reason) = process.try_wait()? {
+                break reason;
+            }
+            if remaining.is_zero() {
+                break process.interrupt_and_wait()?;
+            }
+        };
+
+        self.select_stopped_thread();
+        let rest = self.uprobe_session.as_mut().map(|session| session.drain()).unwrap_or_default();
+        events += self.feed_uprobe_events(&rest);
+        self.feed_uprobe_thread_exits();
+        let lost = self.uprobe_session.as_ref().map_or(0, |session| session.lost());
+
+        match stop_reason {
+            StopReason::Exited(code) => self.handle_process_exit(code),
+            StopReason::Killed(signal) => self.handle_process_killed(signal),
+            StopReason::Breakpoint => {
+                // INT3 の分だけ PC を戻す
+                let target = self.require_target()?;
+                target.set_pc(target.pc()? - 1)?;
+            }
+            _ => {}
+        }
+
+        Ok(UprobeCollection { events, lost, stop_reason })
+    }
+
+    /// uprobe のイベントを AsyncTracker に渡す
+    ///
+    /// 親タスクはスレッドごとのスコープスタックから推定し、discriminant は読みません
+    /// （イベントを処理する時点でターゲットはもう先へ進んでいるため）。
+    fn feed_uprobe_events(&mut self, events: &[kokia_target::UprobeEvent]) -> usize {
+        use kokia_async::Tid;
+
+        let Some(session) = self.uprobe_session.as_ref() else {
+            return 0;
+        };
+        let kinds: Vec<ProbeKind> = events.iter().map(|e| session.probe(e.probe).kind).collect();
+        // イベントの時刻はカーネルのタイムスタンプの差から、いま届いた最後のイベントを基準に戻す
+        let received = std::time::Instant::now();
+        let latest = events.iter().map(|e| e.time).max().unwrap_or_default();
+        for (event, kind) in events.iter().zip(kinds) {
+            let tid = Tid(event.tid);
+            let at = received.checked_sub(Duration::from_nanos(latest - event.time)).unwrap_or(received);
+            let polled = self.async_tracker.current_task(tid).map(|task| task.id);
+            let result = match kind {
+                ProbeKind::Entry => {
+                    let await_site = match event.return_address {
+                        0 => None,
+                        ret => self.await_site_at(ret.wrapping_sub(self.uprobe_load_base)),
+                    };
+                    let target = &self.uprobe_targets[event.probe / 2];
+                    self.async_tracker.on_poll_entry(
+                        tid,
+                        event.rdi,
+                        target.entry,
+                        None,
+                        None,
+                        Some(target.name.clone()),
+                        await_site,
+                    )
+                }
+                ProbeKind::Return => {
+                    // 戻りイベントには RAX しかないので、RAX で読めない Poll は推定に頼る
+                    let ready = self.uprobe_targets[event.probe / 2].poll
+                        .and_then(|poll| poll.read_rax(event.rax))
+                        .unwrap_or_else(|| poll_is_ready(event.rax));
+                    self.async_tracker.on_poll_exit(tid, event.ip, ready)
+                }
+            };
+            if let Err(e) = result {
+                self.emit_warning(format!("Failed to track uprobe event: {}", e));
+            }
+            match kind {
+                ProbeKind::Entry => self.observe_poll_entry(tid, at),
+                ProbeKind::Return => self.observe_poll_exit(tid, polled, at),
+            }
+        }
+        events.len()
+    }
+
+    /// uprobe セッションが観測したスレッドの終了を AsyncTracker に渡す
+    fn feed_uprobe_thread_exits(&mut self) {
+        let exited = self.uprobe_session.as_mut().map(|s| s.take_exited_threads()).unwrap_or_default();
+        for tid in exited {
+            self.async_tracker.on_thread_exit(kokia_async::Tid(tid));
+        }
+    }
+
+    /// Async tracking用のブレークポイントをシンボル名で設定する
+    ///
+    /// entry（関数先頭）とexit（各ret命令）の両方にブレークポイントを設定します。
+    ///
+    /// # Returns
+    /// (entry ブレークポイントのID, exit ブレークポイントのID一覧)
+    fn set_async_breakpoint_by_symbol(&mut self, symbol_name: &str) -> Result<(BreakpointId, Vec<BreakpointId>)> {
+        use crate::breakpoint::BreakpointType;
+
+        let symbol = self.find_best_symbol(symbol_name)?;
+        let entry_address = self.offset_to_runtime_addr(symbol.address)?;
+        let rets = if symbol.size > 0 { Some(self.find_function_rets(&symbol)) } else { None };
+
+        // メモリを取得（借用問題を避けるため後で使う）
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
 
         // 1. Entry用のブレークポイントを設定
         let entry_bp_id = self.breakpoint_manager
             .add_and_enable_with_type(entry_address, memory, BreakpointType::AsyncEntry)?;
 
-        // 2. Exit用のブレークポイントを設定（ret命令を検出）
-        if symbol.size > 0 {
-            // 関数のバイト列を読み取る
-            let code = memory.read(entry_address as usize, symbol.size as usize)?;
+        // 2. Exit用のブレークポイントを設定（ret命令を検出）
+        let mut exit_bp_ids = Vec::new();
+        if let Some(rets) = rets {
+            // ret命令を検出（コールドな部分も含む）
+            match rets {
+                Ok(ret_addrs) => {
+                    for ret_addr in ret_addrs {
+                        let actual_ret_addr = self.offset_to_runtime_addr(ret_addr)?;
+
+                        // 各ret命令にExitブレークポイントを設定
+                        match self.breakpoint_manager.add_and_enable_with_type(
+                            actual_ret_addr,
+                            memory,
+                            BreakpointType::AsyncExit,
+                        ) {
+                            Ok(bp_id) => exit_bp_ids.push(bp_id),
+                            Err(e) => self.emit_warning(format!("Failed to set exit breakpoint at 0x{:x}: {}", actual_ret_addr, e)),
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.emit_warning(format!("Failed to disassemble function {}: {}", symbol_name, e));
+                }
+            }
+        }
+
+        Ok((entry_bp_id, exit_bp_ids))
+    }
+
+    /// ブレークポイントを設定する（アドレス指定）
+    pub fn set_breakpoint(&mut self, address: u64) -> Result<BreakpointId> {
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        self.breakpoint_manager.add_and_enable(address, memory)
+    }
+
+    /// 型指定付きでブレークポイントを設定する
+    fn set_breakpoint_with_type(&mut self, address: u64, bp_type: crate::breakpoint::BreakpointType) -> Result<BreakpointId> {
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        self.breakpoint_manager.add_and_enable_with_type(address, memory, bp_type)
+    }
+
+    /// シンボル名からブレークポイントを設定する
+    ///
+    /// DWARF行番号情報を使って、関数の最初の有効なソース行にブレークポイントを設定します。
+    /// PIEの場合、実行時ベースアドレスを自動的に加算します。
+    /// 非PIEの場合、シンボルアドレスは既に絶対アドレスなので加算しません。
+    pub fn set_breakpoint_by_symbol(&mut self, symbol_name: &str) -> Result<BreakpointId> {
+        let symbol = self.find_best_symbol(symbol_name)?;
+        let actual_address = self.symbol_breakpoint_address(&symbol)?;
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        self.breakpoint_manager.add_and_enable(actual_address, memory)
+    }
+
+    /// シンボルにブレークポイントを置く実行時アドレス（関数の最初の有効な行）
+    fn symbol_breakpoint_address(&self, symbol: &Symbol) -> Result<u64> {
+        // DWARF行番号情報を使って最初の有効な行のアドレスを取得
+        let mut breakpoint_address = symbol.address;
+        if let Some(loader) = &self.dwarf_loader {
+            let line_provider = LineInfoProvider::new(loader);
+
+            // シンボルのアドレス範囲で最初の有効な行を検索
+            let end_addr = if symbol.size > 0 {
+                symbol.address + symbol.size
+            } else {
+                symbol.address + 0x1000 // サイズ不明の場合は適当な範囲
+            };
+
+            match line_provider.find_first_line_in_range(symbol.address, end_addr) {
+                Ok(Some(first_line_addr)) => {
+                    breakpoint_address = first_line_addr;
+                }
+                Ok(None) | Err(_) => {
+                    // 行番号情報が見つからない場合、シンボルアドレスを使用
+                }
+            }
+        }
+
+        self.offset_to_runtime_addr(breakpoint_address)
+    }
+
+    /// ファイル名と行番号からブレークポイントを設定する
+    ///
+    /// DWARF行番号情報を使って、指定されたファイルの行番号にブレークポイントを設定します。
+    /// ファイル名は部分一致で検索されます（例: "main.rs" で "examples/simple_async/src/main.rs" にマッチ）。
+    pub fn set_breakpoint_by_file_line(&mut self, file_pattern: &str, line: u32) -> Result<BreakpointId> {
+        let runtime_address = self.file_line_address(file_pattern, line)?;
+
+        // ブレークポイントを設定
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        self.breakpoint_manager.add_and_enable(runtime_address, memory)
+    }
+
+    /// ファイル名と行番号の実行時アドレス
+    fn file_line_address(&self, file_pattern: &str, line: u32) -> Result<u64> {
+        let loader = self.dwarf_loader.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("DWARF information not loaded"))?;
+
+        let line_provider = kokia_dwarf::LineInfoProvider::new(loader);
+
+        // ファイル名と行番号からアドレスを検索
+        let address = line_provider.find_address_by_file_line(file_pattern, line)?
+            .ok_or_else(|| anyhow::anyhow!("No matching line found for '{}:{}'", file_pattern, line))?;
+
+        // オフセットアドレスを実行時アドレスに変換
+        self.offset_to_runtime_addr(address)
+    }
+
+    /// ブレークポイントを削除する
+    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> Result<()> {
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        self.logpoints.remove(&id);
+        self.breakpoint_manager.remove_and_disable(id, memory)
+    }
+
+    /// ブレークポイントのグループ（bpgroup）
+    pub fn breakpoint_groups(&self) -> &BreakpointGroups {
+        &self.breakpoint_groups
+    }
+
+    /// 空のグループを作る
+    pub fn create_breakpoint_group(&mut self, name: &str) -> Result<()> {
+        self.breakpoint_groups.create(name)
+    }
+
+    /// グループに要素を加える
+    ///
+    /// グループが有効ならその場で置き、置けなければ加えません。
+    ///
+    /// # Returns
+    /// 置いたもの（無効なグループなら空）
+    pub fn add_to_breakpoint_group(&mut self, name: &str, member: GroupMember) -> Result<GroupActivation> {
+        let placed = if self.breakpoint_groups.get(name)?.is_enabled() {
+            self.place_group_members(std::slice::from_ref(&member))?
+        } else {
+            GroupActivation::default()
+        };
+        let group = self.breakpoint_groups.get_mut(name)?;
+        group.members.push(member);
+        if let Some(active) = &mut group.active {
+            active.extend(placed.clone());
+        }
+        Ok(placed)
+    }
+
+    /// グループの要素をすべて置く
+    ///
+    /// 1つでも置けなければ、それまでに置いたものを外してエラーにします（一部だけ有効にはしない）。
+    pub fn enable_breakpoint_group(&mut self, name: &str) -> Result<&GroupActivation> {
+        let group = self.breakpoint_groups.get(name)?;
+        if group.is_enabled() {
+            return Err(anyhow::anyhow!("Breakpoint group '{}' is already enabled", name));
+        }
+        let members = group.members.clone();
+        let placed = self.place_group_members(&members)?;
+        Ok(self.breakpoint_groups.get_mut(name)?.active.insert(placed))
+    }
+
+    /// グループで置いたものをすべて外す
+    pub fn disable_breakpoint_group(&mut self, name: &str) -> Result<GroupActivation> {
+        let placed = self.breakpoint_groups.get_mut(name)?.active.take()
+            .ok_or_else(|| anyhow::anyhow!("Breakpoint group '{}' is not enabled", name))?;
+        self.remove_group_placements(&placed)?;
+        Ok(placed)
+    }
+
+    /// グループを削除する（有効なら先に外す）
+    pub fn delete_breakpoint_group(&mut self, name: &str) -> Result<()> {
+        if self.breakpoint_groups.get(name)?.is_enabled() {
+            self.disable_breakpoint_group(name)?;
+        }
+        self.breakpoint_groups.remove(name).map(drop)
+    }
+
+    /// ファイルからグループの定義を読み込む（読み込んだグループは無効の状態）
+    pub fn load_breakpoint_groups(&mut self, path: &Path) -> Result<Vec<String>> {
+        self.breakpoint_groups.load(path)
+    }
+
+    /// グループの要素を順に置く（失敗したら置いた分を外す）
+    fn place_group_members(&mut self, members: &[GroupMember]) -> Result<GroupActivation> {
+        self.require_memory()?;
+        let mut placed = GroupActivation::default();
+        for member in members {
+            let result = match member {
+                GroupMember::Break(loc) => self.place_group_breakpoints(loc)
+                    .map(|ids| placed.breakpoints.extend(ids)),
+                GroupMember::Async(pattern) => SymbolPattern::parse(pattern)
+                    .and_then(|pattern| self.enable_async_instrumentation(Some(&pattern)))
+                    .map(|entries| placed.async_entries.extend(entries)),
+            };
+            if let Err(e) = result {
+                if let Err(undo) = self.remove_group_placements(&placed) {
+                    self.emit_warning(format!("Failed to roll back breakpoint group: {}", undo));
+                }
+                return Err(anyhow::anyhow!("{}: {}", member, e));
+            }
+        }
+        Ok(placed)
+    }
+
+    /// グループの `break` の要素を置く
+    ///
+    /// パターンならマッチする関数すべてに置きます。すでにブレークポイントがあるアドレスは
+    /// グループの外で置いたものなので、重ねて置かずに飛ばします。
+    fn place_group_breakpoints(&mut self, loc: &str) -> Result<Vec<BreakpointId>> {
+        use crate::parse::parse_address;
+
+        let mut addresses = if GroupMember::is_pattern(loc) {
+            let pattern = SymbolPattern::parse(loc)?;
+            let resolver = self.symbol_resolver.as_ref()
+                .ok_or_else(|| anyhow::anyhow!(errors::ERR_DWARF_NOT_LOADED))?;
+            let symbols: Vec<Symbol> = resolver.all_symbols()
+                .filter(|sym| pattern.matches(&sym.demangled_name))
+                .cloned()
+                .collect();
+            if symbols.is_empty() {
+                return Err(anyhow::anyhow!("No functions match '{}'", loc));
+            }
+            symbols.iter().map(|sym| self.symbol_breakpoint_address(sym)).collect::<Result<Vec<_>>>()?
+        } else if let Ok(address) = parse_address(loc) {
+            vec![address]
+        } else if let Some((file, line)) = loc.rsplit_once(':').and_then(|(file, line)| Some((file, line.parse().ok()?))) {
+            vec![self.file_line_address(file, line)?]
+        } else {
+            vec![self.symbol_breakpoint_address(&self.find_best_symbol(loc)?)?]
+        };
+        addresses.sort_unstable();
+        addresses.dedup();
+
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        let mut ids = Vec::new();
+        for address in addresses {
+            if self.breakpoint_manager.find_by_address(address).is_some() {
+                continue;
+            }
+            match self.breakpoint_manager.add_and_enable(address, memory) {
+                Ok(id) => ids.push(id),
+                Err(e) => {
+                    for id in ids {
+                        let _ = self.breakpoint_manager.remove_and_disable(id, memory);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// グループで置いたものを外す（グループの外で先に消されたものは飛ばす）
+    fn remove_group_placements(&mut self, placed: &GroupActivation) -> Result<()> {
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        for &id in &placed.breakpoints {
+            if self.breakpoint_manager.get(id).is_some() {
+                self.logpoints.remove(&id);
+                self.breakpoint_manager.remove_and_disable(id, memory)?;
+            }
+        }
+        for &entry in &placed.async_entries {
+            if let Some(function) = self.instrumentation.disarm_entry(entry) {
+                for bp_id in function.breakpoints() {
+                    self.breakpoint_manager.remove_and_disable(bp_id, memory)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// ユーザーのブレークポイントをログポイントにする
+    ///
+    /// 以後そこに当たると書式の式を評価して1行出力し、止まらずに実行を続けます。
+    /// 出力は [`Debugger::set_logpoint_callback`] で受け取ります（未設定ならログに出す）。
+    pub fn set_logpoint(&mut self, id: BreakpointId, template: LogTemplate) -> Result<()> {
+        use crate::breakpoint::BreakpointType;
+
+        match self.breakpoint_manager.get(id).map(|bp| bp.bp_type) {
+            Some(BreakpointType::User | BreakpointType::Logpoint) => {}
+            Some(_) => return Err(anyhow::anyhow!("Breakpoint {} is internal and cannot log", id)),
+            None => return Err(anyhow::anyhow!("Breakpoint {} not found", id)),
+        }
+        self.breakpoint_manager.set_type(id, BreakpointType::Logpoint);
+        self.logpoints.insert(id, template);
+        Ok(())
+    }
+
+    /// ログポイントの書式
+    pub fn logpoint(&self, id: BreakpointId) -> Option<&LogTemplate> {
+        self.logpoints.get(&id)
+    }
+
+    /// ログポイントの出力を受け取るコールバックを設定する
+    pub fn set_logpoint_callback(&mut self, callback: Option<LogpointCallback>) {
+        self.logpoint_callback = callback;
+    }
+
+    /// 警告を受け取るコールバックを設定する
+    pub fn set_warning_callback(&mut self, callback: Option<WarningCallback>) {
+        self.warning_callback = callback;
+    }
+
+    /// 警告をコールバックに渡す（未設定ならログに出す）
+    fn emit_warning(&self, message: String) {
+        match &self.warning_callback {
+            Some(callback) => callback(&message),
+            None => warn!("{}", message),
+        }
+    }
+
+    /// `address` のログポイントの書式を評価して出力する
+    fn emit_logpoint(&self, address: u64) {
+        let Some(id) = self.breakpoint_manager.find_by_address(address) else {
+            return;
+        };
+        let Some(template) = self.logpoints.get(&id) else {
+            return;
+        };
+        let evaluator = crate::ExpressionEvaluator::new(self);
+        let line = template.render(|source| {
+            crate::parse_expression(source)
+                .and_then(|expression| evaluator.evaluate_to_string(&expression))
+                .unwrap_or_else(|e| format!("<error: {}>", e))
+        });
+        match &self.logpoint_callback {
+            Some(callback) => callback(id, &line),
+            None => info!("logpoint {}: {}", id, line),
+        }
+    }
+
+    /// パターンにマッチする関数の出入りをトレースする（ftrace）
+    ///
+    /// 入口（プロローグの後）と ret 命令に内部ブレークポイントを置きます。ret 命令の分からない関数や、
+    /// 入口にすでにブレークポイントのある関数は飛ばします。
+    ///
+    /// # Returns
+    /// 新たにトレースを始めた関数の名前
+    pub fn ftrace(&mut self, pattern: &SymbolPattern) -> Result<Vec<String>> {
+        use crate::breakpoint::BreakpointType;
+
+        let resolver = self.symbol_resolver.as_ref()
+            .ok_or_else(|| anyhow::anyhow!(errors::ERR_DWARF_NOT_LOADED))?;
+        let mut symbols: Vec<Symbol> = resolver.all_symbols()
+            .filter(|sym| sym.size > 0 && pattern.matches(&sym.demangled_name))
+            .cloned()
+            .collect();
+        symbols.sort_by(|a, b| a.demangled_name.cmp(&b.demangled_name));
+        symbols.dedup_by(|a, b| a.demangled_name == b.demangled_name);
+        if symbols.is_empty() {
+            return Err(anyhow::anyhow!("No functions match '{}'", pattern.as_str()));
+        }
+
+        let mut traced = Vec::new();
+        for symbol in symbols {
+            if self.ftrace.is_tracing(&symbol.demangled_name) {
+                continue;
+            }
+            // 引数のレジスタがまだ書き換えられていない関数の先頭で止める
+            let entry = self.offset_to_runtime_addr(symbol.address)?;
+            if self.breakpoint_manager.find_by_address(entry).is_some() {
+                self.emit_warning(format!("{} already has a breakpoint at its entry; not tracing it", symbol.demangled_name));
+                continue;
+            }
+            let rets = match self.find_function_rets(&symbol) {
+                Ok(rets) if !rets.is_empty() => rets,
+                Ok(_) => {
+                    self.emit_warning(format!("No return instruction found in {}; not tracing it", symbol.demangled_name));
+                    continue;
+                }
+                Err(e) => {
+                    self.emit_warning(format!("Cannot trace {}: {}", symbol.demangled_name, e));
+                    continue;
+                }
+            };
+            let returns_value = self.dwarf_loader.as_ref()
+                .and_then(|loader| kokia_dwarf::function_return_type(loader, symbol.address).ok().flatten())
+                .is_some();
+
+            let entry = self.set_breakpoint_with_type(entry, BreakpointType::TraceEntry)?;
+            let mut exits = Vec::new();
+            for ret in rets {
+                let address = self.offset_to_runtime_addr(ret)?;
+                exits.push(self.set_breakpoint_with_type(address, BreakpointType::TraceExit)?);
+            }
+            traced.push(symbol.demangled_name.clone());
+            self.ftrace.add(TracedFunction { name: symbol.demangled_name, entry, exits, returns_value });
+        }
+        Ok(traced)
+    }
+
+    /// 関数トレースをやめる（パターン省略時はすべて）
+    ///
+    /// 呼び出しログは残ります。
+    pub fn ftrace_stop(&mut self, pattern: Option<&SymbolPattern>) -> Result<Vec<TracedFunction>> {
+        let removed = self.ftrace.remove_where(|function| pattern.is_none_or(|pattern| pattern.matches(&function.name)));
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        for function in &removed {
+            for id in function.breakpoints() {
+                self.breakpoint_manager.remove_and_disable(id, memory)?;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// 関数トレースの対象と呼び出しログ
+    pub fn function_trace(&self) -> &FunctionTrace {
+        &self.ftrace
+    }
+
+    /// 関数トレースの呼び出しログを空にする
+    pub fn clear_ftrace_log(&mut self) {
+        self.ftrace.clear_log();
+    }
+
+    /// トレース中の関数の入口で、引数を読んで記録する
+    fn record_ftrace_entry(&mut self, address: u64) {
+        let Some(function) = self.breakpoint_manager.find_by_address(address)
+            .and_then(|id| self.ftrace.entry_of(id))
+            .map(|function| function.name.clone()) else {
+            return;
+        };
+        let args = self.entry_arguments().unwrap_or_else(|e| {
+            debug!("Failed to decode arguments of {}: {}", function, e);
+            None
+        }).unwrap_or_default();
+        let tid = self.current_tid.or(self.pid).unwrap_or_default();
+        self.ftrace.on_enter(tid, &function, args);
+    }
+
+    /// トレース中の関数の ret 命令で、戻り値（RAX）を記録する
+    fn record_ftrace_exit(&mut self, address: u64) {
+        let Some((function, returns_value)) = self.breakpoint_manager.find_by_address(address)
+            .and_then(|id| self.ftrace.exit_of(id))
+            .map(|function| (function.name.clone(), function.returns_value)) else {
+            return;
+        };
+        let value = returns_value
+            .then(|| self.reverse_resolve(address))
+            .flatten()
+            .and_then(|symbol| self.decode_return_value(&symbol));
+        let tid = self.current_tid.or(self.pid).unwrap_or_default();
+        self.ftrace.on_exit(tid, &function, value);
+    }
+
+    /// すべてのブレークポイントを取得する
+    pub fn breakpoints(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.breakpoint_manager.all()
+    }
+
+    /// メモリへのアクセスを監視するウォッチポイントを設定する
+    ///
+    /// ptrace しているすべてのスレッドのデバッグレジスタに設定し、後から生まれたスレッドにも
+    /// 走り出す前に設定します。
+    pub fn watch(&mut self, address: u64, len: usize, kind: WatchKind) -> Result<WatchpointId> {
+        let process = self.process.as_ref()
+            .ok_or_else(|| self.no_process_error())?;
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        let id = self.watchpoint_manager.add(address, len, kind, &process.traced_threads(), memory)?;
+        process.set_inherited_debug_regs(self.watchpoint_manager.debug_registers());
+        Ok(id)
+    }
+
+    /// ウォッチポイントを解除する
+    pub fn unwatch(&mut self, id: WatchpointId) -> Result<Watchpoint> {
+        let process = self.process.as_ref()
+            .ok_or_else(|| self.no_process_error())?;
+        let watchpoint = self.watchpoint_manager.remove(id, &process.traced_threads())?;
+        process.set_inherited_debug_regs(self.watchpoint_manager.debug_registers());
+        Ok(watchpoint)
+    }
+
+    /// 設定中のウォッチポイントを取得する
+    pub fn watchpoints(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.watchpoint_manager.all()
+    }
+
+    /// 指定アドレスにバイト列を書き込む（元のバイト列は `unpatch` で復元できる）
+    ///
+    /// 書き換え範囲にブレークポイントがある場合、INT3 と保存済みの元バイトの整合が
+    /// 取れなくなるためエラーにします。
+    pub fn patch(&mut self, address: u64, bytes: &[u8]) -> Result<PatchId> {
+        self.check_patch_range(address, address + bytes.len() as u64)?;
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        let patched = self.patch_manager.apply(address, bytes, memory);
+        // ポインタをたどった評価結果は書き換えたメモリに依存する
+        self.eval_cache.get_mut().clear();
+        patched
+    }
+
+    /// パッチを元に戻す
+    pub fn unpatch(&mut self, id: PatchId) -> Result<Patch> {
+        let patch = self.patch_manager.get(id)
+            .ok_or_else(|| anyhow::anyhow!("No patch number {}", id))?;
+        self.check_patch_range(patch.address, patch.end())?;
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        let reverted = self.patch_manager.revert(id, memory);
+        self.eval_cache.get_mut().clear();
+        reverted
+    }
+
+    /// 適用中のパッチを取得する
+    pub fn patches(&self) -> &[Patch] {
+        self.patch_manager.all()
+    }
+
+    /// パッチ範囲にブレークポイントが無いことを確認する
+    fn check_patch_range(&self, start: u64, end: u64) -> Result<()> {
+        match self.breakpoint_manager.find_in_range(start, end).next() {
+            Some(bp) => Err(anyhow::anyhow!(
+                "Breakpoint {} at 0x{:x} lies within 0x{:x}-0x{:x}; delete it first",
+                bp.id, bp.address, start, end
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// 自動表示式を追加する
+    pub fn add_display(&mut self, expr: &str) -> DisplayId {
+        self.display_list.add(expr)
+    }
+
+    /// 自動表示式を削除する
+    pub fn remove_display(&mut self, id: DisplayId) -> Result<()> {
+        self.display_list.remove(id)
+            .map(|_| ())
+            .ok_or_else(|| anyhow::anyhow!("No display number {}", id))
+    }
+
+    /// すべての自動表示式を削除する
+    pub fn clear_displays(&mut self) {
+        self.display_list.clear();
+    }
+
+    /// 登録済みの自動表示式を取得する
+    pub fn displays(&self) -> &[DisplayEntry] {
+        self.display_list.all()
+    }
+
+    /// 停止時の async サマリー表示を設定する
+    pub fn set_async_summary(&mut self, enabled: bool) {
+        self.async_summary = enabled;
+    }
+
+    /// 停止時の async サマリー表示が有効か
+    pub fn async_summary_enabled(&self) -> bool {
+        self.async_summary
+    }
+
+    /// async トレースモードを設定する
+    ///
+    /// 有効にすると AsyncEntry/AsyncExit ブレークポイントはイベントを記録したあと、
+    /// REPL に戻らずそのまま実行を継続します。無効にすると片道で外していた
+    /// ブレークポイントをすべて張り直します。
+    pub fn set_async_trace(&mut self, enabled: bool) -> Result<()> {
+        if self.async_trace && !enabled {
+            if let Some(memory) = self.memory.as_ref() {
+                for (_, function) in self.instrumentation.armed() {
+                    for bp_id in function.breakpoints() {
+                        self.breakpoint_manager.reenable(bp_id, memory)?;
+                    }
+                }
+            }
+        }
+        self.async_trace = enabled;
+        Ok(())
+    }
+
+    /// async トレースモードが有効か
+    pub fn async_trace_enabled(&self) -> bool {
+        self.async_trace
+    }
+
+    /// async fn の entry の親の求め方を設定する
+    ///
+    /// 有効にすると、entry のたびにバックトレースを取る代わりにスコープスタックの先頭を親とみなします。
+    /// 前回の poll で分かった親や discriminant と食い違うときだけフレームスキャンします。
+    pub fn set_fast_async_entry(&mut self, enabled: bool) {
+        self.fast_async_entry = enabled;
+        self.async_entry_scans = 0;
+    }
+
+    /// fast モードなら、フレームスキャンに戻った entry の数
+    pub fn fast_async_entry(&self) -> Option<u64> {
+        self.fast_async_entry.then_some(self.async_entry_scans)
+    }
+
+    /// 直近の continue 中にトレースモードで記録した async イベント数
+    pub fn traced_async_events(&self) -> u64 {
+        self.traced_async_events
+    }
+
+    /// 終了済み async タスクの保持ポリシーを設定する
+    ///
+    /// # Returns
+    /// 新しいポリシーの適用で捨てたタスクの数
+    pub fn set_async_retention(&mut self, policy: RetentionPolicy) -> usize {
+        self.async_tracker.set_retention(policy)
+    }
+
+    /// async イベントの外部への送出を始める（送出中なら設定を置き換える）
+    ///
+    /// イベントは async 計装（ブレークポイントか uprobe）が見た poll から作るので、
+    /// 送られるのは `async enable` などで計装している間だけです。
+    pub fn start_event_export(&mut self, config: ExportConfig) -> Result<()> {
+        self.stop_event_export();
+        self.event_exporter = Some(EventExporter::start(config, self.pid)?);
+        Ok(())
+    }
+
+    /// 残りのイベントを送ってから送出を止める（送出していなければ None）
+    pub fn stop_event_export(&mut self) -> Option<ExportStats> {
+        self.event_exporter.take().map(EventExporter::stop)
+    }
+
+    /// 送出の設定と送信の状況
+    pub fn event_export(&self) -> Option<(&ExportConfig, ExportStats)> {
+        self.event_exporter.as_ref().map(|exporter| (exporter.config(), exporter.stats()))
+    }
+
+    /// poll の entry を poll の計時・送出するイベント・メトリクスに反映する（on_poll_entry の後に呼ぶ）
+    fn observe_poll_entry(&mut self, tid: kokia_async::Tid, at: std::time::Instant) {
+        let Some(task) = self.async_tracker.current_task(tid) else {
+            return;
+        };
+        self.poll_timer.on_entry(tid.0, task.id, at);
+        if let Some(exporter) = self.event_exporter.as_mut() {
+            exporter.on_poll_entry(task, tid.0, at);
+            exporter.check_stalls(self.async_tracker.task_tracker().all_tasks(), &self.running_time, at);
+        }
+        self.publish_metrics(at);
+    }
+
+    /// poll の exit を反映する（`polled` は on_poll_exit の前に poll 中だったタスク）
+    fn observe_poll_exit(&mut self, tid: kokia_async::Tid, polled: Option<kokia_async::TaskId>, at: std::time::Instant) {
+        let Some(task) = polled.and_then(|id| self.async_tracker.get_task(id)) else {
+            return;
+        };
+        self.poll_timer.on_exit(tid.0, task.id, at);
+        if let Some(exporter) = self.event_exporter.as_mut() {
+            exporter.on_poll_exit(task, tid.0, at);
+        }
+        self.publish_metrics(at);
+    }
+
+    /// メトリクスを公開する（`attach --metrics`、ポート 0 なら空いているポート）
+    ///
+    /// # Returns
+    /// 待ち受けているアドレス
+    pub fn start_metrics(&mut self, address: std::net::SocketAddr) -> Result<std::net::SocketAddr> {
+        self.metrics_server = None;
+        let server = MetricsServer::start(address, kokia_async::DEFAULT_STALL_THRESHOLD)?;
+        let address = server.address();
+        self.metrics_server = Some(server);
+        self.publish_metrics(std::time::Instant::now());
+        Ok(address)
+    }
+
+    /// 公開中のメトリクスの値を更新する（前回から間がなければ何もしない）
+    fn publish_metrics(&mut self, now: std::time::Instant) {
+        let Some(server) = self.metrics_server.as_mut() else {
+            return;
+        };
+        let (timed_polls, poll_time) = self.poll_timer.timed();
+        server.publish_if_due(now, |stall_threshold| MetricsSnapshot {
+            live_last_seen: self.async_tracker.task_tracker().all_tasks()
+                .filter(|task| task.is_live())
+                .map(|task| task.last_seen)
+                .collect(),
+            running: self.running_time.clone(),
+            stall_threshold,
+            polls: self.poll_timer.polls(),
+            timed_polls,
+            poll_time,
+            overhead: self.breakpoint_overhead,
+        });
+    }
+
+    /// async トラッキングで集めたタスク・エッジをすべて破棄する
+    ///
+    /// 計装（ブレークポイントや uprobe）はそのまま残るので、以後のイベントは新たに追跡されます。
+    /// 実行中のタスクは次の poll で改めて登録されます。
+    pub fn clear_async_tracking(&mut self) {
+        self.async_tracker.clear();
+        self.traced_async_events = 0;
+        self.poll_timer.clear();
+        self.breakpoint_overhead = BreakpointOverhead::default();
+        self.async_entry_scans = 0;
+    }
+
+    /// poll の回数と時間
+    pub fn poll_timer(&self) -> &PollTimer {
+        &self.poll_timer
+    }
+
+    /// 計装のブレークポイントで止まっていた回数と時間
+    pub fn breakpoint_overhead(&self) -> &BreakpointOverhead {
+        &self.breakpoint_overhead
+    }
+
+    /// デバッグ対象が実行していた時間の記録（停滞の判定に使う）
+    pub fn running_time(&self) -> &RunningTime {
+        &self.running_time
+    }
+
+    /// バイナリ読み込み・型索引構築・async 計装の進捗通知先を設定する
+    pub fn set_progress_callback(&mut self, callback: Option<ProgressCallback>) {
+        self.progress = callback;
+    }
+
+    /// DWARF 検索の制限時間を設定する（None なら無制限）
+    pub fn set_query_timeout(&mut self, timeout: Option<Duration>) {
+        self.query_timeout = timeout;
+    }
+
+    /// DWARF 検索の制限時間
+    pub fn query_timeout(&self) -> Option<Duration> {
+        self.query_timeout
+    }
+
+    /// ブレークポイント・設定・async トラッカーの中身・モジュール一覧を書き出す（export-state）
+    pub fn export_state(&self) -> Result<DebuggerState> {
+        use crate::breakpoint::BreakpointType;
+
+        let mut breakpoints = Vec::new();
+        for bp in self.breakpoint_manager.all() {
+            if !matches!(bp.bp_type, BreakpointType::User | BreakpointType::Logpoint) {
+                continue;
+            }
+            breakpoints.push(BreakpointSnapshot {
+                id: bp.id,
+                offset: self.runtime_addr_to_offset(bp.address)?,
+                thread: bp.thread,
+                task: bp.task,
+                logpoint: (bp.bp_type == BreakpointType::Logpoint)
+                    .then(|| self.logpoints.get(&bp.id))
+                    .flatten()
+                    .map(|template| template.as_str().to_string()),
+            });
+        }
+        breakpoints.sort_by_key(|bp| bp.id);
+
+        let retention = self.async_tracker.retention();
+        let mut state = DebuggerState {
+            version: STATE_FORMAT_VERSION,
+            kokia_version: env!("CARGO_PKG_VERSION").to_string(),
+            binary: self.binary_path.as_ref().map(|path| BinarySnapshot {
+                path: path.clone(),
+                build_id: self.dwarf_loader.as_ref()
+                    .and_then(|loader| loader.build_id())
+                    .map(kokia_target::elf_image::format_build_id),
+            }),
+            config: ConfigSnapshot {
+                async_summary: self.async_summary,
+                async_trace: self.async_trace,
+                crash_report: self.crash_report,
+                query_timeout_ms: self.query_timeout.map(|timeout| timeout.as_millis() as u64),
+                step_filter: self.step_filter.prefixes().to_vec(),
+                retention_max_tasks: retention.max_tasks,
+                retention_max_age_ms: retention.max_age.map(|age| age.as_millis() as u64),
+            },
+            breakpoints,
+            displays: self.display_list.all().iter().map(|entry| entry.expr.clone()).collect(),
+            tasks: Vec::new(),
+            edges: Vec::new(),
+            callsites: Vec::new(),
+            modules: match self.memory {
+                Some(_) if self.is_alive() => ModuleSnapshot::from_mappings(&self.memory_mappings()?),
+                _ => Vec::new(),
+            },
+        };
+        let tracker = &self.async_tracker;
+        state.capture_async(
+            tracker.all_tasks(),
+            tracker.all_edges(),
+            tracker.callsite_tracker().all_callsites(),
+            std::time::Instant::now(),
+        );
+        Ok(state)
+    }
+
+    /// [`Debugger::export_state`] で書き出した状態を読み込む（import-state）
+    ///
+    /// 設定・display・async トラッカーの中身を置き換えます。ブレークポイントはプロセスがあれば
+    /// 書き出したときと同じファイル上のアドレスに設定し直し、なければ未設定として報告します。
+    /// スレッド ID はプロセスごとに変わるので、スレッドの限定は戻しません。
+    pub fn import_state(&mut self, state: &DebuggerState) -> Result<ImportReport> {
+        state.check_version()?;
+        let mut report = ImportReport::default();
+
+        let loaded = self.dwarf_loader.as_ref()
+            .and_then(|loader| loader.build_id())
+            .map(kokia_target::elf_image::format_build_id);
+        if let (Some(saved), Some(loaded)) = (state.binary.as_ref().and_then(|b| b.build_id.as_ref()), &loaded) {
+            if saved != loaded {
+                report.warnings.push(format!(
+                    "State was exported from build-id {}, the loaded binary is {}; breakpoint addresses may be wrong",
+                    saved, loaded
+                ));
+            }
+        }
 
-            // ret命令を検出
-            match crate::disasm::find_ret_instructions(&code, symbol.address) {
-                Ok(ret_addrs) => {
-                    for ret_addr in ret_addrs {
-                        let actual_ret_addr = self.offset_to_runtime_addr(ret_addr)?;
+        let config = &state.config;
+        self.async_summary = config.async_summary;
+        self.set_async_trace(config.async_trace)?;
+        self.crash_report = config.crash_report;
+        self.query_timeout = config.query_timeout_ms.map(Duration::from_millis);
+        self.step_filter = StepFilter::with_prefixes(config.step_filter.clone());
 
-                        // 各ret命令にExitブレークポイントを設定
-                        if let Err(e) = self.breakpoint_manager.add_and_enable_with_type(
-                            actual_ret_addr,
-                            memory,
-                            BreakpointType::AsyncExit,
-                        ) {
-                            warn!("Failed to set exit breakpoint at 0x{:x}: {}", actual_ret_addr, e);
-                        }
-                    }
-                }
-                Err(e) => {
-                    warn!("Failed to disassemble function {}: {}", symbol_name, e);
+        self.display_list.clear();
+        for expr in &state.displays {
+            self.display_list.add(expr);
+        }
+        report.displays = state.displays.len();
+
+        let (tasks, edges, callsites) = state.restore_async(std::time::Instant::now())?;
+        report.tasks = tasks.len();
+        report.edges = edges.len();
+        self.async_tracker.restore(tasks, edges, callsites);
+        self.async_tracker.set_retention(config.retention());
+        self.traced_async_events = 0;
+
+        for snapshot in &state.breakpoints {
+            if self.memory.is_none() {
+                report.pending_breakpoints.push(snapshot.clone());
+                continue;
+            }
+            let address = self.offset_to_runtime_addr(snapshot.offset)?;
+            let id = self.set_breakpoint(address)?;
+            if let Some(source) = &snapshot.logpoint {
+                self.set_logpoint(id, LogTemplate::parse(source)?)?;
+            }
+            if let Some(task) = snapshot.task {
+                if let Err(e) = self.restrict_breakpoint(id, None, Some(kokia_async::TaskRef::Id(task))) {
+                    report.warnings.push(format!("Breakpoint {}: {}", id, e));
                 }
             }
+            if let Some(thread) = snapshot.thread {
+                report.warnings.push(format!("Breakpoint {} was limited to thread {}; not restored", id, thread));
+            }
+            report.breakpoints.push((snapshot.id, id));
         }
+        Ok(report)
+    }
 
-        // Entryブレークポイントのidを返す
-        Ok(entry_bp_id)
+    /// 検索1回分の中断トークンを作成する
+    ///
+    /// 制限時間に加え、[`kokia_target::interrupt::InterruptGuard`] 設置中の Ctrl-C でも中断します。
+    fn query_cancel_token(&self) -> CancelToken {
+        let token = CancelToken::new().with_interrupt_check(kokia_target::interrupt::interrupted);
+        match self.query_timeout {
+            Some(timeout) => token.with_timeout(timeout),
+            None => token,
+        }
     }
 
-    /// ブレークポイントを設定する（アドレス指定）
-    pub fn set_breakpoint(&mut self, address: u64) -> Result<BreakpointId> {
-        let memory = self.memory.as_ref()
-            .ok_or_else(|| anyhow::anyhow!(errors::ERR_NOT_ATTACHED))?;
-        self.breakpoint_manager.add_and_enable(address, memory)
+    /// プロセスを実行継続する（停止イベントを待たない）
+    pub fn continue_execution(&self) -> Result<()> {
+        if let Some(process) = &self.process {
+            process.continue_execution()?;
+        }
+        Ok(())
     }
 
-    /// 型指定付きでブレークポイントを設定する
-    fn set_breakpoint_with_type(&mut self, address: u64, bp_type: crate::breakpoint::BreakpointType) -> Result<BreakpointId> {
-        let memory = self.memory.as_ref()
-            .ok_or_else(|| anyhow::anyhow!(errors::ERR_NOT_ATTACHED))?;
-        self.breakpoint_manager.add_and_enable_with_type(address, memory, bp_type)
+    /// プロセスを実行継続して停止イベントを待機する
+    ///
+    /// プロセスを実行継続し、次の停止イベント（ブレークポイント、シグナル、終了など）まで待機します。
+    /// ブレークポイントヒット時は、PCを自動的に1バイト戻します（INT3命令の分）。
+    /// トレースモードでは async ブレークポイントでは戻らず、それ以外の停止まで走り続けます。
+    /// 停止の詳細は [`Debugger::last_stop`] で取得できます。
+    pub fn continue_and_wait(&mut self) -> Result<StopReason> {
+        self.traced_async_events = 0;
+        self.prune_exited_async_threads();
+        // 記録していない実行を挟むので、ステップ実行の履歴はもう巻き戻せない
+        self.undo_log.clear();
+
+        let started = std::time::Instant::now();
+        self.running_time.resume(started);
+        if let Some(server) = self.metrics_server.as_ref() {
+            server.set_running_time(&self.running_time);
+        }
+        let event = if self.tracing_instructions { self.run_traced() } else { self.run_until_stop() };
+        self.running_time.stop(std::time::Instant::now());
+        let event = event?;
+        self.breakpoint_overhead.running += started.elapsed();
+        let stop_reason = event.reason.clone();
+        self.record_stop(event);
+        self.collect_profile_samples();
+        self.publish_metrics(std::time::Instant::now());
+        Ok(stop_reason)
     }
 
-    /// シンボル名からブレークポイントを設定する
+    /// プロセスの全スレッドを `frequency` Hz でサンプリングし始める（前の集計は捨てる）
     ///
-    /// DWARF行番号情報を使って、関数の最初の有効なソース行にブレークポイントを設定します。
-    /// PIEの場合、実行時ベースアドレスを自動的に加算します。
-    /// 非PIEの場合、シンボルアドレスは既に絶対アドレスなので加算しません。
-    pub fn set_breakpoint_by_symbol(&mut self, symbol_name: &str) -> Result<BreakpointId> {
-        let symbol = self.find_best_symbol(symbol_name)?;
+    /// サンプルは `continue` で止まるたびに読み出して集計します。返すのはサンプリングするスレッド数です。
+    pub fn start_profile(&mut self, frequency: u64) -> Result<usize> {
+        let threads = self.threads()?;
+        let sampler = PerfSampler::start(&threads, frequency)?;
+        let count = sampler.thread_count();
+        self.profile_load_base = self.offset_to_runtime_addr(0)?;
+        self.sampler = Some(sampler);
+        self.profile = Some(Profile::new());
+        Ok(count)
+    }
 
-        // DWARF行番号情報を使って最初の有効な行のアドレスを取得
-        let mut breakpoint_address = symbol.address;
-        if let Some(loader) = &self.dwarf_loader {
-            let line_provider = LineInfoProvider::new(loader);
+    /// サンプリングを止める（集計は [`Debugger::profile`] で引き続き参照できる）
+    pub fn stop_profile(&mut self) -> Result<&Profile> {
+        if self.sampler.is_none() {
+            return Err(anyhow::anyhow!("Profiling is not running (use 'profile start')"));
+        }
+        self.collect_profile_samples();
+        self.sampler = None;
+        Ok(self.profile.as_ref().expect("profile is created with the sampler"))
+    }
 
-            // シンボルのアドレス範囲で最初の有効な行を検索
-            let end_addr = if symbol.size > 0 {
-                symbol.address + symbol.size
-            } else {
-                symbol.address + 0x1000 // サイズ不明の場合は適当な範囲
-            };
+    /// 直近のプロファイル（サンプリング中なら溜まっている分も集計してから返す）
+    pub fn profile(&mut self) -> Option<&Profile> {
+        self.collect_profile_samples();
+        self.profile.as_ref()
+    }
 
-            match line_provider.find_first_line_in_range(symbol.address, end_addr) {
-                Ok(Some(first_line_addr)) => {
-                    breakpoint_address = first_line_addr;
-                }
-                Ok(None) | Err(_) => {
-                    // 行番号情報が見つからない場合、シンボルアドレスを使用
-                }
+    /// サンプリング中か
+    pub fn is_profiling(&self) -> bool {
+        self.sampler.is_some()
+    }
+
+    /// バッファ溢れで失われたサンプル数
+    pub fn profile_lost_samples(&self) -> u64 {
+        self.sampler.as_ref().map_or(0, PerfSampler::lost)
+    }
+
+    /// 溜まっているサンプルを関数名にして集計し、その間に生まれたスレッドもサンプリングに加える
+    fn collect_profile_samples(&mut self) {
+        let Some(sampler) = self.sampler.as_mut() else {
+            return;
+        };
+        let samples = sampler.drain();
+        if let Ok(threads) = self.threads() {
+            if let Some(Err(e)) = self.sampler.as_mut().map(|sampler| sampler.add_threads(&threads)) {
+                debug!("Failed to sample new threads: {}", e);
             }
         }
 
-        let actual_address = self.offset_to_runtime_addr(breakpoint_address)?;
-        let memory = self.memory.as_ref()
-            .ok_or_else(|| anyhow::anyhow!(errors::ERR_NOT_ATTACHED))?;
-        self.breakpoint_manager.add_and_enable(actual_address, memory)
+        let mut names: HashMap<u64, String> = HashMap::new();
+        let mut frames = Vec::new();
+        for sample in samples {
+            frames.clear();
+            // 呼び出し元は戻りアドレスなので、call 命令の中を指すように1引いて引く
+            let addresses = std::iter::once(sample.ip).chain(sample.callers.iter().map(|addr| addr.saturating_sub(1)));
+            for address in addresses {
+                let name = names.entry(address).or_insert_with(|| {
+                    self.symbol_resolver.as_ref()
+                        .and_then(|resolver| resolver.reverse_resolve(address.wrapping_sub(self.profile_load_base)))
+                        .map_or_else(|| "??".to_string(), |symbol| symbol.demangled_name)
+                });
+                frames.push(name.clone());
+            }
+            let async_fn = frames.iter().find(|name| Self::is_user_async_closure(name)).cloned();
+            if let Some(profile) = self.profile.as_mut() {
+                profile.add(&frames, async_fn.as_deref());
+            }
+        }
     }
 
-    /// ファイル名と行番号からブレークポイントを設定する
+    /// 命令トレースを始める（前の記録は捨てる）
     ///
-    /// DWARF行番号情報を使って、指定されたファイルの行番号にブレークポイントを設定します。
-    /// ファイル名は部分一致で検索されます（例: "main.rs" で "examples/simple_async/src/main.rs" にマッチ）。
-    pub fn set_breakpoint_by_file_line(&mut self, file_pattern: &str, line: u32) -> Result<BreakpointId> {
-        let loader = self.dwarf_loader.as_ref()
-            .ok_or_else(|| anyhow::anyhow!("DWARF information not loaded"))?;
+    /// 止めるまでの `continue` はシングルステップで進み、ユーザーのブレークポイントと
+    /// ウォッチポイント、シグナルでだけ止まります。async の計装やキャッチポイントは素通りします。
+    pub fn start_instruction_trace(&mut self, mode: TraceMode, capacity: usize) {
+        self.instruction_trace = Some(InstructionTrace::new(mode, capacity));
+        self.tracing_instructions = true;
+    }
 
-        let line_provider = kokia_dwarf::LineInfoProvider::new(loader);
+    /// 命令トレースを止める（記録は [`Debugger::instruction_trace`] で引き続き参照できる）
+    pub fn stop_instruction_trace(&mut self) -> Option<&InstructionTrace> {
+        self.tracing_instructions = false;
+        self.instruction_trace.as_ref()
+    }
 
-        // ファイル名と行番号からアドレスを検索
-        let address = line_provider.find_address_by_file_line(file_pattern, line)?
-            .ok_or_else(|| anyhow::anyhow!("No matching line found for '{}:{}'", file_pattern, line))?;
+    /// 直近の命令トレースの記録
+    pub fn instruction_trace(&self) -> Option<&InstructionTrace> {
+        self.instruction_trace.as_ref()
+    }
 
-        // オフセットアドレスを実行時アドレスに変換
-        let runtime_address = self.offset_to_runtime_addr(address)?;
+    /// 命令トレースを記録中か
+    pub fn is_tracing_instructions(&self) -> bool {
+        self.tracing_instructions
+    }
 
-        // ブレークポイントを設定
-        let memory = self.memory.as_ref()
-            .ok_or_else(|| anyhow::anyhow!(errors::ERR_NOT_ATTACHED))?;
-        self.breakpoint_manager.add_and_enable(runtime_address, memory)
+    /// プロセスの全スレッドで Intel PT による分岐の記録を始める。記録するスレッド数を返す
+    ///
+    /// 記録はそのまま `continue` で走らせた間も続き、[`Debugger::stop_hw_trace`] で取り出します。
+    pub fn start_hw_trace(&mut self) -> Result<usize> {
+        if self.hw_trace.is_some() {
+            return Err(anyhow::anyhow!("Hardware tracing is already running (use 'trace hw stop')"));
+        }
+        let threads = self.threads()?;
+        let session = PtSession::start(&threads)?;
+        let count = session.thread_count();
+        self.hw_trace = Some(session);
+        Ok(count)
     }
 
-    /// ブレークポイントを削除する
-    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> Result<()> {
-        let memory = self.memory.as_ref()
-            .ok_or_else(|| anyhow::anyhow!(errors::ERR_NOT_ATTACHED))?;
-        self.breakpoint_manager.remove_and_disable(id, memory)
+    /// Intel PT の記録を止め、スレッドごとにデコードして返す
+    pub fn stop_hw_trace(&mut self) -> Result<Vec<(PtThreadTrace, PtDecoded)>> {
+        let session = self.hw_trace.take()
+            .ok_or_else(|| anyhow::anyhow!("Hardware tracing is not running (use 'trace hw start')"))?;
+        Ok(session.stop()?
+            .into_iter()
+            .map(|trace| {
+                let decoded = kokia_target::intel_pt::decode(&trace.data);
+                (trace, decoded)
+            })
+            .collect())
     }
 
-    /// すべてのブレークポイントを取得する
-    pub fn breakpoints(&self) -> impl Iterator<Item = &Breakpoint> {
-        self.breakpoint_manager.all()
+    /// トレースしながら、ユーザーのブレークポイントかウォッチポイントに当たるまで1命令ずつ進める
+    ///
+    /// [`kokia_target::interrupt::InterruptGuard`] 設置中の Ctrl-C では SIGINT の停止として戻ります。
+    fn run_traced(&mut self) -> Result<StopEvent> {
+        loop {
+            if kokia_target::interrupt::interrupted() {
+                return Ok(StopEvent::new(StopReason::Signal(kokia_target::Signal::SIGINT)));
+            }
+            let stop_reason = self.step_traced()?;
+            if stop_reason != StopReason::Step {
+                return Ok(StopEvent::new(stop_reason));
+            }
+            if let Some(hit) = self.take_watchpoint_hit()? {
+                return Ok(StopEvent { watchpoint: Some(hit), ..StopEvent::new(StopReason::Watchpoint) });
+            }
+            let pc = self.get_pc()?;
+            let bp_type = self.breakpoint_manager.find_by_address(pc)
+                .and_then(|id| self.breakpoint_manager.get(id))
+                .filter(|bp| bp.enabled)
+                .map(|bp| bp.bp_type);
+            match bp_type {
+                Some(crate::breakpoint::BreakpointType::User) if self.breakpoint_context_matches(pc) => {
+                    return Ok(StopEvent::new(StopReason::Breakpoint));
+                }
+                Some(crate::breakpoint::BreakpointType::Logpoint) if self.breakpoint_context_matches(pc) => {
+                    self.emit_logpoint(pc);
+                }
+                Some(crate::breakpoint::BreakpointType::TraceEntry) => self.record_ftrace_entry(pc),
+                Some(crate::breakpoint::BreakpointType::TraceExit) => self.record_ftrace_exit(pc),
+                _ => {}
+            }
+        }
     }
 
-    /// プロセスを実行継続する（停止イベントを待たない）
-    pub fn continue_execution(&self) -> Result<()> {
-        if let Some(process) = &self.process {
-            process.continue_execution()?;
+    /// 1命令だけ実行して命令トレースに記録する
+    ///
+    /// PC のブレークポイントは一時的に外して実行するので、INT3 を踏まずに元の命令を実行します。
+    fn step_traced(&mut self) -> Result<StopReason> {
+        let pc = self.get_pc()?;
+        let len = self.instruction_len_at(pc);
+
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        let bp_at_pc = self.breakpoint_manager.find_by_address(pc)
+            .filter(|id| self.breakpoint_manager.get(*id).is_some_and(|bp| bp.enabled));
+        if let Some(id) = bp_at_pc {
+            self.breakpoint_manager.disable_temporarily(id, memory)?;
         }
-        Ok(())
+        let stop_reason = memory.step()?;
+        if stop_reason.is_terminated() {
+            self.handle_termination(&stop_reason);
+            return Ok(stop_reason);
+        }
+        if let Some(id) = bp_at_pc {
+            self.breakpoint_manager.reenable(id, memory)?;
+        }
+        self.select_stopped_thread();
+
+        let next_pc = self.get_pc()?;
+        if let Some(trace) = self.instruction_trace.as_mut() {
+            trace.record(pc, len, next_pc);
+        }
+        Ok(stop_reason)
     }
 
-    /// プロセスを実行継続して停止イベントを待機する
+    /// PC にある命令の長さ（INT3 は元の命令として読む。読めなければ 0）
+    fn instruction_len_at(&self, pc: u64) -> usize {
+        let Some(memory) = self.memory.as_ref() else {
+            return 0;
+        };
+        let Ok(read) = memory.read_partial(pc as usize, MAX_INSTRUCTION_LEN) else {
+            return 0;
+        };
+        let mut code = read.data;
+        self.breakpoint_manager.restore_original_bytes(pc, &mut code);
+        instruction_len(&code, pc)
+    }
+
+    /// 止まるべき停止まで実行を続ける
     ///
-    /// プロセスを実行継続し、次の停止イベント（ブレークポイント、シグナル、終了など）まで待機します。
-    /// ブレークポイントヒット時は、PCを自動的に1バイト戻します（INT3命令の分）。
-    pub fn continue_and_wait(&mut self) -> Result<StopReason> {
-        let process = self.process.as_ref()
-            .ok_or_else(|| anyhow::anyhow!(errors::ERR_NOT_ATTACHED))?;
-        let memory = self.memory.as_ref()
-            .ok_or_else(|| anyhow::anyhow!(errors::ERR_NOT_ATTACHED))?;
+    /// 返すイベントには、停止の種類と async ブレークポイントで対象になったタスク、ヒットした
+    /// ウォッチポイントだけが入っています。
+    fn run_until_stop(&mut self) -> Result<StopEvent> {
+        // 計装のブレークポイントで止まった時刻と種類（止まらずに再開するときにかかった時間を数える）
+        let mut stopped: Option<(std::time::Instant, OverheadKind)> = None;
+        loop {
+            self.step_over_breakpoint()?;
+            if let Some((stopped_at, kind)) = stopped.take() {
+                self.breakpoint_overhead.record(kind, stopped_at.elapsed());
+            }
 
-        // 現在のPCを取得
-        let registers = self.require_registers()?;
-        let current_pc = registers.get_pc()?;
+            let stop_reason = self.require_target()?.resume()?;
+            let stopped_at = std::time::Instant::now();
+            self.select_stopped_thread();
 
-        // 現在のPCにブレークポイントがあるかチェック
-        let bp_at_current_pc = self.breakpoint_manager.find_by_address(current_pc);
+            if stop_reason.is_terminated() {
+                self.handle_termination(&stop_reason);
+                return Ok(StopEvent::new(stop_reason));
+            }
 
-        // ブレークポイント上にいる場合、一時的に無効化してステップ実行してから再有効化
-        if let Some(bp_id) = bp_at_current_pc {
-            self.breakpoint_manager.disable_temporarily(bp_id, memory)?;
-            let _ = process.step()?;
-            self.breakpoint_manager.reenable(bp_id, memory)?;
-        }
+            if stop_reason != StopReason::Breakpoint {
+                return Ok(StopEvent::new(stop_reason));
+            }
 
-        let stop_reason = process.continue_and_wait()?;
+            // デバッグレジスタによるトラップは命令の実行後に起きるので、PC は戻さない
+            if self.is_hardware_trap() {
+                if let Some(hit) = self.take_watchpoint_hit()? {
+                    return Ok(StopEvent { watchpoint: Some(hit), ..StopEvent::new(StopReason::Watchpoint) });
+                }
+            }
 
-        // ブレークポイントヒット時はPCを1バイト戻す（INT3命令の分）
-        if stop_reason == StopReason::Breakpoint {
-            let registers = self.require_registers()?;
-            let pc = registers.get_pc()?;
-            registers.set_pc(pc - 1)?;
+            // ブレークポイントヒット時はPCを1バイト戻す（INT3命令の分）
+            let target = self.require_target()?;
+            let pc = target.pc()?;
+            target.set_pc(pc - 1)?;
 
             // PCを戻した後、Async用のブレークポイントかチェック
             let adjusted_pc = pc - 1;
-            if let Some(bp_id) = self.breakpoint_manager.find_by_address(adjusted_pc) {
-                if let Some(bp) = self.breakpoint_manager.get(bp_id) {
-                    match bp.bp_type {
-                        crate::breakpoint::BreakpointType::AsyncEntry => {
-                            // Entry: on_poll_entryを呼び出す
-                            self.handle_async_entry(adjusted_pc)?;
-                        }
-                        crate::breakpoint::BreakpointType::AsyncExit => {
-                            // Exit: on_poll_exitを呼び出す
-                            self.handle_async_exit(adjusted_pc)?;
+            let bp_type = self.breakpoint_manager.find_by_address(adjusted_pc)
+                .and_then(|bp_id| self.breakpoint_manager.get(bp_id))
+                .map(|bp| bp.bp_type);
+            stopped = Some((stopped_at, OverheadKind::of(bp_type)));
+            let (task, return_value) = match bp_type {
+                Some(crate::breakpoint::BreakpointType::AsyncEntry) => {
+                    // 間引き対象のヒットは数えるだけで再開する
+                    let func_start = self.reverse_resolve(adjusted_pc).map(|sym| sym.address);
+                    let (tid, sp) = self.stopped_thread_and_sp()?;
+                    if !func_start.is_none_or(|f| self.instrumentation.on_entry_hit(f, tid, sp)) {
+                        continue;
+                    }
+                    // Entry: on_poll_entryを呼び出す
+                    self.handle_async_entry(adjusted_pc)?;
+                    (self.current_async_task(), None)
+                }
+                Some(crate::breakpoint::BreakpointType::AsyncExit) => {
+                    let func_start = self.reverse_resolve(adjusted_pc).map(|sym| sym.address);
+                    let (tid, sp) = self.stopped_thread_and_sp()?;
+                    if !func_start.is_none_or(|f| self.instrumentation.on_exit_hit(f, tid, sp)) {
+                        continue;
+                    }
+                    // Exit: on_poll_exitを呼び出す（exit 後はスコープから外れるので先に控える）
+                    let task = self.current_async_task();
+                    self.handle_async_exit(adjusted_pc)?;
+                    let return_value = if self.async_trace {
+                        None
+                    } else {
+                        self.reverse_resolve(adjusted_pc).and_then(|symbol| self.decode_return_value(&symbol))
+                    };
+                    (task, return_value)
+                }
+                Some(crate::breakpoint::BreakpointType::PanicHandler) => {
+                    self.pending_panic_location = self.read_panic_location();
+                    if let (Some(catch), Some(tid)) = (self.poison_catch.as_mut(), self.current_tid.or(self.pid)) {
+                        let tid = kokia_async::Tid(tid);
+                        catch.on_panic_start(tid, self.async_tracker.async_backtrace(tid), self.pending_panic_location.clone());
+                    }
+                    continue;
+                }
+                Some(crate::breakpoint::BreakpointType::PanicCleanup) => {
+                    // 状態は巻き戻し前の await チェーンで読むので、スコープを外すのはその後
+                    let poisoned = self.check_poisoned_tasks();
+                    self.unwind_async_scopes();
+                    match poisoned {
+                        Some(poisoned) => {
+                            let task = Some(poisoned.task);
+                            return Ok(StopEvent { task, poisoned: Some(poisoned), ..StopEvent::new(stop_reason) });
                         }
-                        _ => {}
+                        None => continue,
+                    }
+                }
+                Some(crate::breakpoint::BreakpointType::PanicCatch) => {
+                    let panic = self.take_panic_report();
+                    return Ok(StopEvent { panic: Some(panic), ..StopEvent::new(stop_reason) });
+                }
+                Some(crate::breakpoint::BreakpointType::ErrReturn) => match self.check_err_return(adjusted_pc) {
+                    Some(err) => return Ok(StopEvent { err_return: Some(err), ..StopEvent::new(stop_reason) }),
+                    // Ok を返す場合は止まらない
+                    None => continue,
+                },
+                Some(crate::breakpoint::BreakpointType::User) if !self.breakpoint_context_matches(adjusted_pc) => continue,
+                Some(crate::breakpoint::BreakpointType::Logpoint) => {
+                    if self.breakpoint_context_matches(adjusted_pc) {
+                        self.emit_logpoint(adjusted_pc);
                     }
+                    continue;
+                }
+                Some(crate::breakpoint::BreakpointType::TraceEntry) => {
+                    self.record_ftrace_entry(adjusted_pc);
+                    continue;
                 }
+                Some(crate::breakpoint::BreakpointType::TraceExit) => {
+                    self.record_ftrace_exit(adjusted_pc);
+                    continue;
+                }
+                _ => return Ok(StopEvent::new(stop_reason)),
+            };
+
+            if !self.async_trace {
+                return Ok(StopEvent { task, return_value, ..StopEvent::new(stop_reason) });
             }
+            self.traced_async_events += 1;
+            self.flip_trace_breakpoints(adjusted_pc)?;
         }
+    }
 
-        Ok(stop_reason)
+    /// `address` のブレークポイントのスレッド・タスクの限定に、いまの停止が合うか
+    ///
+    /// スレッドは停止を報告したスレッドと比べます。タスクの限定は、そのスレッドの
+    /// async スコープ（poll 中のタスクとその呼び出し元のタスク）にそのタスクがあれば合うとみなします。
+    fn breakpoint_context_matches(&self, address: u64) -> bool {
+        let Some(bp) = self.breakpoint_manager.find_by_address(address).and_then(|id| self.breakpoint_manager.get(id)) else {
+            return true;
+        };
+        let tid = self.stopped_tid();
+        if bp.thread.is_some_and(|thread| Some(kokia_async::Tid(thread)) != tid) {
+            return false;
+        }
+        match (bp.task, tid) {
+            (Some(task), Some(tid)) => self.async_tracker.async_backtrace(tid).contains(&task),
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
     }
 
-    /// Async関数のエントリー処理
-    fn handle_async_entry(&mut self, pc: u64) -> Result<()> {
+    /// ブレークポイントで止まるスレッド・タスクを限定する（None は限定しない）
+    pub fn restrict_breakpoint(&mut self, id: BreakpointId, thread: Option<i32>, task: Option<kokia_async::TaskRef>) -> Result<()> {
+        if let Some(thread) = thread {
+            if !self.threads()?.contains(&thread) {
+                return Err(anyhow::anyhow!("Thread not found: {}", thread));
+            }
+        }
+        let task = task
+            .map(|task| self.async_tracker.task_tracker().lookup(task).ok_or_else(|| {
+                anyhow::anyhow!("Task not found: {} (tasks are known once 'async enable' has seen them polled)", task)
+            }))
+            .transpose()?;
+        if !self.breakpoint_manager.restrict(id, thread, task) {
+            return Err(anyhow::anyhow!("Breakpoint {} not found", id));
+        }
+        Ok(())
+    }
+
+    /// 選択中のスレッドで poll 中のタスク
+    fn current_async_task(&self) -> Option<TaskId> {
+        self.async_tracker.current_task(self.stopped_tid()?).map(|task| task.id)
+    }
+
+    /// 選択中のスレッド（停止直後なら停止を報告したスレッド）の async トラッカーでの ID
+    fn stopped_tid(&self) -> Option<kokia_async::Tid> {
+        self.current_tid.or(self.pid).map(kokia_async::Tid)
+    }
+
+    /// 停止を報告したスレッドとその RSP（entry と exit の突き合わせに使う）
+    fn stopped_thread_and_sp(&self) -> Result<(kokia_async::Tid, u64)> {
+        let tid = self.stopped_tid().ok_or_else(|| self.no_process_error())?;
+        Ok((tid, self.general_registers()?.rsp))
+    }
+
+    /// 停止がデバッグレジスタによるトラップか（ウォッチポイントがなければ調べない）
+    fn is_hardware_trap(&self) -> bool {
+        if self.watchpoint_manager.is_empty() {
+            return false;
+        }
+        self.process.as_ref()
+            .and_then(|process| process.signal_info().ok())
+            .is_some_and(|info| info.is_hardware_trap())
+    }
+
+    /// DR6 を読んで発火したウォッチポイントを特定し、DR6 をクリアする
+    fn take_watchpoint_hit(&mut self) -> Result<Option<WatchpointHit>> {
+        if self.watchpoint_manager.is_empty() {
+            return Ok(None);
+        }
+        let registers = self.require_registers()?;
+        let status = DebugStatus(registers.read_debug_reg(6)?);
+        registers.write_debug_reg(6, 0)?;
+
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        Ok(status.triggered().find_map(|slot| self.watchpoint_manager.record_hit(slot, memory)))
+    }
+
+    /// 停止イベントの残りを埋めて記録する
+    ///
+    /// ブレークポイントは PC を戻した後のアドレスで引き、シグナル停止なら siginfo も読みます。
+    fn record_stop(&mut self, mut event: StopEvent) {
+        let reason = &event.reason;
+        if self.target.is_some() {
+            event.tid = self.current_tid.or(self.pid);
+            event.pc = self.get_pc().ok();
+        }
+        if *reason == StopReason::Breakpoint {
+            event.breakpoint = event.pc
+                .and_then(|pc| self.breakpoint_manager.find_by_address(pc))
+                .and_then(|id| self.breakpoint_manager.get(id))
+                .map(|bp| (bp.id, bp.bp_type));
+        }
+        if let (StopReason::Signal(_), Some(process)) = (reason, &self.process) {
+            event.signal = process.signal_info()
+                .inspect_err(|e| debug!("Failed to read siginfo: {}", e))
+                .ok();
+        }
+        self.last_stop = Some(event);
+    }
+
+    /// 直近の停止イベント（continue / step / next / finish の後に更新される）
+    pub fn last_stop(&self) -> Option<&StopEvent> {
+        self.last_stop.as_ref()
+    }
+
+    /// 既に終了したスレッドのスコープスタックを async トラッカーから捨てる
+    fn prune_exited_async_threads(&mut self) {
         use kokia_async::Tid;
 
+        if self.async_read_only {
+            return;
+        }
+        if let Ok(threads) = self.threads() {
+            let live: Vec<Tid> = threads.into_iter().map(Tid).collect();
+            let pruned = self.async_tracker.retain_threads(&live);
+            if pruned > 0 {
+                debug!("Dropped async scopes of {} exited thread(s)", pruned);
+            }
+        }
+    }
+
+    /// 現在のPCに有効なブレークポイントがあれば、一時的に外して1命令だけ進める
+    fn step_over_breakpoint(&mut self) -> Result<()> {
+        let target = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        self.breakpoint_manager.step_over(target)?;
+        Ok(())
+    }
+
+    /// トレースモードで、ヒットした async 関数のブレークポイントを片道で切り替える
+    ///
+    /// entry でヒットしたら entry を外して exit を張り、exit でヒットしたら exit を外して
+    /// entry を張り直します。現在の PC のブレークポイントは外れているので、
+    /// 再開時に一時無効化＋シングルステップを挟まずにそのまま continue できます。
+    /// 代わりに、同じ関数の poll が入れ子になった（再帰）場合、内側の entry/exit は記録されません。
+    ///
+    /// 複数のスレッドがあるときは、別のスレッドが同じ関数を同時に poll するかもしれないので
+    /// 切り替えずにすべて張っておき、再開時に踏み越えます。
+    fn flip_trace_breakpoints(&mut self, pc: u64) -> Result<()> {
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        let concurrent = self.process.as_ref().is_some_and(|process| process.traced_threads().len() > 1);
+        let Some(func_start) = self.reverse_resolve(pc).map(|sym| sym.address) else {
+            return Ok(());
+        };
+        let Some(function) = self.instrumentation.get(func_start) else {
+            return Ok(());
+        };
+        let at_entry = self.breakpoint_manager.find_by_address(pc) == Some(function.entry);
+
+        let (disarm, arm): (Vec<BreakpointId>, Vec<BreakpointId>) = if concurrent {
+            (Vec::new(), function.breakpoints().collect())
+        } else if at_entry {
+            (vec![function.entry], function.exits.clone())
+        } else {
+            (function.exits.clone(), vec![function.entry])
+        };
+        for bp_id in disarm {
+            self.breakpoint_manager.disable_temporarily(bp_id, memory)?;
+        }
+        for bp_id in arm {
+            self.breakpoint_manager.reenable(bp_id, memory)?;
+        }
+        Ok(())
+    }
+
+    /// Async関数のエントリー処理
+    fn handle_async_entry(&mut self, pc: u64) -> Result<()> {
         // 初回ヒット時: GenFuture::poll のret命令にexit BPを自動配置
         self.ensure_async_exit_breakpoints(pc)?;
 
-        // 現在のスレッドIDを取得（簡易版：PIDを使用）
-        let pid = self.pid().ok_or_else(|| anyhow::anyhow!("No process attached"))?;
-        let tid = Tid(pid);
+        // 停止を報告したスレッドのスコープスタックを更新する
+        let tid = self.stopped_tid().ok_or_else(|| self.no_process_error())?;
 
         // レジスタから第1引数（self ポインタ）を取得
         // x86_64 System V ABI: 第1引数は RDI
-        let registers = self.require_registers()?;
-        let child_self = registers.get_rdi()?;
-
-        // 親タスクをフレームスキャンで検出
-        // バックトレースを取得し、フレーム1以降から最初の async 関数（{{closure}}）を探す
-        let parent_task = self.scan_parent_async_function()?;
+        let registers = self.general_registers()?;
+        let child_self = registers.rdi;
+        let entry_sp = registers.rsp;
 
         // PCから関数名を解決（デマングル済み）
         let function_name = self.reverse_resolve(pc)
@@ -564,8 +3336,24 @@ impl Debugger {
         // 子タスクの discriminant を読み取る（関数名を使ってDWARFから正確な位置を取得）
         let discriminant = self.read_discriminant(child_self, function_name.as_deref());
 
-        // ソースコード位置を取得（addr2line）
-        let source_location = self.get_line_info(pc);
+        // 親タスクをフレームスキャンで検出
+        // バックトレースを取得し、フレーム1以降から最初の async 関数（{{closure}}）を探す。
+        // fast モードでスコープスタックが信用できるなら、None を渡して先頭を親にさせる
+        let parent_task = if self.fast_async_entry && self.async_tracker.scope_parent_agrees(tid, child_self, discriminant) {
+            None
+        } else {
+            if self.fast_async_entry {
+                self.async_entry_scans += 1;
+            }
+            self.scan_parent_async_function()?
+        };
+
+        // entry ブレークポイントはプロローグ前なので、[rsp] が親への戻りアドレス
+        let return_address = self.require_target()
+            .and_then(|memory| memory.read_word(entry_sp));
+        let await_site = return_address.ok()
+            .and_then(|ret| self.runtime_addr_to_offset(ret).ok())
+            .and_then(|ret| self.await_site_at(ret));
 
         // AsyncTrackerのon_poll_entryを呼び出す
         if let Err(e) = self.async_tracker.on_poll_entry(
@@ -575,14 +3363,68 @@ impl Debugger {
             parent_task,
             discriminant,
             function_name,
-            source_location,
+            await_site,
         ) {
-            warn!("Failed to track async entry: {}", e);
+            self.emit_warning(format!("Failed to track async entry: {}", e));
+        }
+        self.async_tracker.note_poll_stack_pointer(tid, entry_sp);
+        self.observe_poll_entry(tid, std::time::Instant::now());
+
+        // 前の poll がパニックした Future をもう一度 poll している（このまま続けるとパニックする）
+        if discriminant == Some(PANICKED_DISCRIMINANT) {
+            if let Some(task) = self.async_tracker.current_task(tid) {
+                let warning = format!(
+                    "Task {} {} is polled in the Panicked state: an earlier poll panicked, so this one panics with \"`async fn` resumed after panicking\"",
+                    task.label(),
+                    task.type_name.as_deref().unwrap_or("<unknown>")
+                );
+                self.emit_warning(warning);
+            }
         }
 
         Ok(())
     }
 
+    /// 親フレームへの戻りアドレス（ファイル上のアドレス）から、親が子を await している位置を求める
+    ///
+    /// 戻りアドレスは call の次の命令を指すので、1 つ手前の行を引きます。停止点は親の状態機械の
+    /// variant のうち、宣言行が await の行と一致するものです。親が async 関数でない
+    /// （ランタイムから直接 poll された）場合は None。
+    fn await_site_at(&mut self, return_address: u64) -> Option<AwaitSite> {
+        let parent = self.symbol_resolver.as_ref()?.reverse_resolve(return_address)?.demangled_name;
+        if !parent.contains("{{closure}}") {
+            return None;
+        }
+        let line_info = LineInfoProvider::new(self.dwarf_loader.as_ref()?)
+            .lookup(return_address.checked_sub(1)?)
+            .ok()??;
+        let (file, line) = (line_info.file?, line_info.line? as u32);
+        let suspend_idx = self.suspend_index_at(&parent, &file, line);
+        Some(AwaitSite { file: Some(file), line: Some(line), suspend_idx })
+    }
+
+    /// async 関数の await の行に対応する停止点の discriminant を引く
+    fn suspend_index_at(&mut self, function_name: &str, file: &str, line: u32) -> Option<u32> {
+        if !self.suspend_points.contains_key(function_name) {
+            // 状態機械の型は async fn のパス（`::{{closure}}` を除いた名前）で引ける
+            let type_name = function_name.strip_suffix("::{{closure}}").unwrap_or(function_name);
+            let points = match self.type_layout(type_name, 0) {
+                Ok(Some(layout)) => layout.variants.iter()
+                    .filter(|v| v.name.starts_with("Suspend"))
+                    .filter_map(|v| Some((v.decl_file.clone()?, v.decl_line?, v.discriminant?)))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            self.suspend_points.insert(function_name.to_string(), points);
+        }
+
+        self.suspend_points[function_name].iter()
+            .find(|(decl_file, decl_line, _)| {
+                *decl_line == line as u64 && (decl_file.ends_with(file) || file.ends_with(decl_file.as_str()))
+            })
+            .map(|(_, _, discriminant)| *discriminant as u32)
+    }
+
     /// 親の async 関数をフレームスキャンで検出する
     ///
     /// バックトレースからフレーム1以降の最初の GenFuture::poll を探し、
@@ -616,18 +3458,8 @@ impl Debugger {
 
     /// Async関数のイグジット処理
     fn handle_async_exit(&mut self, _pc: u64) -> Result<()> {
-        use kokia_async::Tid;
-
-        // 現在のスレッドIDを取得
-        let pid = self.pid().ok_or_else(|| anyhow::anyhow!("No process attached"))?;
-        let tid = Tid(pid);
-
-        // 戻り値（RAX）から Poll::Ready/Pending を判定
-        // Poll<T> の discriminant は通常、最初のバイトに格納される
-        // Pending = 0, Ready = 1
-        let registers = self.require_registers()?;
-        let rax = registers.get_rax()?;
-        let is_ready = (rax & 0xFF) == 1;
+        // 停止を報告したスレッドのスコープスタックを更新する
+        let tid = self.stopped_tid().ok_or_else(|| self.no_process_error())?;
 
         // スコープスタックが空かチェック（再同期が必要な可能性）
         let scope_stack = self.async_tracker.async_backtrace(tid);
@@ -638,12 +3470,38 @@ impl Debugger {
             if let Ok(actual_tasks) = self.extract_async_tasks_from_stack() {
                 self.async_tracker.resync_from_stack(tid, actual_tasks);
             }
-        }
+        }
+
+        // Poll::Ready/Pending を判定する。関数ごとに求めた Poll の discriminant の場所（レジスタか
+        // 返却先のメモリ）から読み、読めなければ poll 中のタスクの状態が Returned になっているかを見る。
+        // どちらもだめなら RAX のタグで推定する
+        let poll = self.reverse_resolve(_pc).and_then(|symbol| self.poll_discriminant(&symbol));
+        let ready = match poll {
+            Some(poll) => {
+                let memory = self.require_target()?;
+                let mut read_memory = |address: u64, len: usize| memory.read_memory(address, len).ok();
+                poll.read(&self.general_registers()?, &self.xmm_registers()?, &mut read_memory)
+            }
+            None => None,
+        };
+        let is_ready = match ready {
+            Some(ready) => ready,
+            None => {
+                let state = self.async_tracker.current_task(tid)
+                    .and_then(|task| self.read_discriminant(task.address, task.type_name.as_deref()));
+                match state {
+                    Some(discriminant) => discriminant == RETURNED_DISCRIMINANT,
+                    None => poll_is_ready(self.general_registers()?.rax),
+                }
+            }
+        };
 
         // AsyncTrackerのon_poll_exitを呼び出す
+        let polled = self.async_tracker.current_task(tid).map(|task| task.id);
         if let Err(e) = self.async_tracker.on_poll_exit(tid, _pc, is_ready) {
-            warn!("Failed to track async exit: {}", e);
+            self.emit_warning(format!("Failed to track async exit: {}", e));
         }
+        self.observe_poll_exit(tid, polled, std::time::Instant::now());
 
         Ok(())
     }
@@ -665,6 +3523,7 @@ impl Debugger {
 
         // フレーム1（呼び出し元）のPCがリターンアドレス
         let return_address = frames[1].pc;
+        let function = self.reverse_resolve(frames[0].pc);
 
         // リターンアドレスにテンポラリブレークポイントを設定
         let temp_bp_id = self.set_breakpoint_with_type(return_address, BreakpointType::Temporary)?;
@@ -673,10 +3532,18 @@ impl Debugger {
         let stop_reason = self.continue_and_wait()?;
 
         // テンポラリブレークポイントを削除（失敗しても無視）
-        if let Some(memory) = self.memory.as_ref() {
+        if let Some(memory) = self.target.as_deref() {
             let _ = self.breakpoint_manager.remove_and_disable(temp_bp_id, memory);
         }
 
+        // 戻った直後なら戻り値のレジスタはまだそのまま
+        if stop_reason == StopReason::Breakpoint && self.get_pc().ok() == Some(return_address) {
+            let value = function.and_then(|function| self.decode_return_value(&function));
+            if let Some(event) = self.last_stop.as_mut() {
+                event.return_value = value;
+            }
+        }
+
         Ok(stop_reason)
     }
 
@@ -689,8 +3556,7 @@ impl Debugger {
         use crate::breakpoint::BreakpointType;
 
         // 現在のPCを取得
-        let registers = self.require_registers()?;
-        let current_pc = registers.get_pc()?;
+        let current_pc = self.get_pc()?;
 
         // PIE対応のアドレス変換
         let current_pc_offset = self.runtime_addr_to_offset(current_pc)?;
@@ -718,7 +3584,7 @@ impl Debugger {
         let stop_reason = self.continue_and_wait()?;
 
         // テンポラリブレークポイントを削除（失敗しても無視）
-        if let Some(memory) = self.memory.as_ref() {
+        if let Some(memory) = self.target.as_deref() {
             let _ = self.breakpoint_manager.remove_and_disable(temp_bp_id, memory);
         }
 
@@ -728,35 +3594,202 @@ impl Debugger {
     /// 1命令だけ実行する（ステップ実行）
     ///
     /// プロセスの1命令だけを実行し、次の停止イベントまで待機します。
-    /// 関数呼び出しの中にも入ります（ステップイン）。
+    /// 関数呼び出しの中にも入ります（ステップイン）。ただしユーザーのコードからステップフィルタに
+    /// 当たる関数に入った場合は、フィルタの外の呼び出し元に戻るまで実行を続けます。
     /// ブレークポイントヒット時は、PCを自動的に1バイト戻します（INT3命令の分）。
+    /// 実行前の状態を undo ログに残すので、直後なら [`Debugger::reverse_stepi`] で戻せます。
     pub fn step(&mut self) -> Result<StopReason> {
-        let process = self.process.as_ref()
-            .ok_or_else(|| anyhow::anyhow!(errors::ERR_NOT_ATTACHED))?;
-        let memory = self.memory.as_ref()
-            .ok_or_else(|| anyhow::anyhow!(errors::ERR_NOT_ATTACHED))?;
+        self.filtered_calls = 0;
+        let from_user_code = !self.in_filtered_code();
+        let mut stop_reason = self.step_once()?;
+        while from_user_code && stop_reason == StopReason::Step && self.in_filtered_code() {
+            stop_reason = self.run_to_caller()?;
+            self.filtered_calls += 1;
+        }
+        Ok(stop_reason)
+    }
+
+    /// 今の関数から呼び出し元に戻るまで実行する（戻ったら `StopReason::Step` を返す）
+    ///
+    /// 戻りアドレスにテンポラリブレークポイントを置いて再開します。別のスレッドや、再帰した
+    /// 内側の呼び出し（スタックポインタがまだ深い）が先に踏んだ場合はそのまま続けます。
+    fn run_to_caller(&mut self) -> Result<StopReason> {
+        use crate::breakpoint::BreakpointType;
+
+        let (tid, pc, sp) = (self.current_thread(), self.get_pc()?, self.general_registers()?.rsp);
+        // call の直後（関数の先頭）ではまだフレームを積んでいないので、戻りアドレスは [rsp] にある
+        let entry = self.reverse_resolve(pc).map(|symbol| self.offset_to_runtime_addr(symbol.address)).transpose()?;
+        let return_address = if entry == Some(pc) {
+            self.require_target()?.read_uint(sp, 8)?
+        } else {
+            self.backtrace()?.get(1)
+                .ok_or_else(|| anyhow::anyhow!("Cannot find the caller of the filtered function"))?
+                .pc
+        };
+        let temp_bp_id = self.set_breakpoint_with_type(return_address, BreakpointType::Temporary)?;
+
+        let stop_reason = loop {
+            match self.continue_and_wait() {
+                Ok(StopReason::Breakpoint)
+                    if self.get_pc().ok() == Some(return_address)
+                        && (self.current_thread() != tid || self.general_registers().is_ok_and(|regs| regs.rsp <= sp)) => {}
+                other => break other,
+            }
+        };
+
+        // テンポラリブレークポイントを削除（失敗しても無視）
+        if let Some(memory) = self.target.as_deref() {
+            let _ = self.breakpoint_manager.remove_and_disable(temp_bp_id, memory);
+        }
+
+        let stop_reason = stop_reason?;
+        if stop_reason == StopReason::Breakpoint && self.get_pc().ok() == Some(return_address) {
+            if let Some(event) = self.last_stop.as_mut() {
+                event.reason = StopReason::Step;
+            }
+            return Ok(StopReason::Step);
+        }
+        Ok(stop_reason)
+    }
+
+    /// 現在の PC がステップフィルタに当たる関数の中か
+    fn in_filtered_code(&self) -> bool {
+        if self.step_filter.is_empty() {
+            return false;
+        }
+        self.get_pc().ok()
+            .and_then(|pc| self.reverse_resolve(pc))
+            .is_some_and(|symbol| self.step_filter.matches(&symbol.demangled_name))
+    }
+
+    /// ステップフィルタを設定する
+    pub fn set_step_filter(&mut self, filter: StepFilter) {
+        self.step_filter = filter;
+    }
+
+    /// 現在のステップフィルタ
+    pub fn step_filter(&self) -> &StepFilter {
+        &self.step_filter
+    }
+
+    /// async の表示での型名の短縮を設定する
+    pub fn set_future_names(&mut self, names: FutureNames) {
+        self.future_names = names;
+    }
+
+    /// async の表示での型名の短縮の設定
+    pub fn future_names(&self) -> &FutureNames {
+        &self.future_names
+    }
+
+    /// 直近の step で呼び出し元に戻るまで実行した、フィルタに当たる関数の数
+    pub fn filtered_calls(&self) -> u64 {
+        self.filtered_calls
+    }
+
+    /// フィルタを考えずに1命令だけ実行する
+    fn step_once(&mut self) -> Result<StopReason> {
+        let undo = self.capture_undo_entry()
+            .inspect_err(|e| debug!("Failed to record undo entry: {}", e))
+            .ok();
+        let traced_pc = undo.as_ref().map(UndoEntry::pc).filter(|_| self.tracing_instructions);
+        let traced_len = traced_pc.map(|pc| self.instruction_len_at(pc));
+        let mut event = StopEvent::new(self.step_instruction()?);
+        if let Some(entry) = undo.filter(|_| self.process.is_some()) {
+            self.undo_log.push(entry);
+        }
+        if let (Some(pc), Some(len), Ok(next_pc)) = (traced_pc, traced_len, self.get_pc()) {
+            if let Some(trace) = self.instruction_trace.as_mut() {
+                trace.record(pc, len, next_pc);
+            }
+        }
+        // ステップ実行の SIGTRAP は si_code が TRAP_TRACE になるので、DR6 だけで判定する
+        if event.reason == StopReason::Step {
+            if let Some(hit) = self.take_watchpoint_hit()? {
+                event = StopEvent { watchpoint: Some(hit), ..StopEvent::new(StopReason::Watchpoint) };
+            }
+        }
+        let stop_reason = event.reason.clone();
+        self.record_stop(event);
+        Ok(stop_reason)
+    }
+
+    /// PC の命令を実行する前のレジスタと、命令が書き込むメモリの内容を記録する
+    fn capture_undo_entry(&self) -> Result<UndoEntry> {
+        let tid = self.current_tid.or(self.pid).ok_or_else(|| self.no_process_error())?;
+        let regs = self.require_registers()?.read()?;
+        let memory = self.require_memory()?;
+
+        let mut code = memory.read_partial(regs.rip as usize, MAX_INSTRUCTION_LEN)?.data;
+        self.breakpoint_manager.restore_original_bytes(regs.rip, &mut code);
+        Ok(UndoEntry::capture(tid, regs, &code, |addr, len| memory.read(addr as usize, len)))
+    }
+
+    /// 直前にステップ実行した命令を1つ巻き戻す
+    ///
+    /// 命令が書き込んだメモリを元に戻し、レジスタを実行前の値に戻します。記録は `step` ごとに
+    /// 残り、continue やプロセス終了で破棄されます。
+    ///
+    /// # Returns
+    /// 巻き戻した命令の記録（`unrecorded_writes` が 0 でなければメモリは完全には戻っていない）
+    pub fn reverse_stepi(&mut self) -> Result<UndoEntry> {
+        let tid = self.current_tid.or(self.pid).ok_or_else(|| self.no_process_error())?;
+        match self.undo_log.last() {
+            None => return Err(anyhow::anyhow!(
+                "No recorded instruction to reverse (only instructions run with 'step' since the last continue can be undone)"
+            )),
+            Some(entry) if entry.tid != tid => return Err(anyhow::anyhow!(
+                "The last recorded step ran on thread {}; switch to it first", entry.tid
+            )),
+            Some(_) => {}
+        }
+        let entry = self.undo_log.pop().expect("checked above");
+
+        let memory = self.require_memory()?;
+        for (address, bytes) in entry.memory.iter().rev() {
+            memory.write(*address as usize, bytes)?;
+        }
+        self.require_registers()?.write(entry.regs)?;
+        self.eval_cache.get_mut().clear();
+        self.record_stop(StopEvent::new(StopReason::Step));
+        Ok(entry)
+    }
+
+    /// 巻き戻せる命令の数
+    pub fn reversible_steps(&self) -> usize {
+        self.undo_log.len()
+    }
+
+    /// 1命令だけ実行し、ステップ先のブレークポイントを判定する
+    fn step_instruction(&mut self) -> Result<StopReason> {
+        let target = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
 
         // 現在のPCを取得
-        let registers = self.require_registers()?;
-        let current_pc = registers.get_pc()?;
+        let current_pc = target.pc()?;
 
         // 現在のPCにブレークポイントがあるかチェック
         let bp_at_current_pc = self.breakpoint_manager.find_by_address(current_pc);
 
         // ブレークポイント上にいる場合、一時的に無効化してから実行
         if let Some(bp_id) = bp_at_current_pc {
-            self.breakpoint_manager.disable_temporarily(bp_id, memory)?;
-            let stop_reason = process.step()?;
-            self.breakpoint_manager.reenable(bp_id, memory)?;
+            self.breakpoint_manager.disable_temporarily(bp_id, target)?;
+            let stop_reason = target.step()?;
+            if stop_reason.is_terminated() {
+                self.handle_termination(&stop_reason);
+                return Ok(stop_reason);
+            }
+            self.breakpoint_manager.reenable(bp_id, target)?;
+            self.select_stopped_thread();
 
             // ステップ実行後、新しいPCを取得
-            let registers = self.require_registers()?;
-            let new_pc = registers.get_pc()?;
+            let target = self.require_target()?;
+            let new_pc = target.pc()?;
 
             // ステップ先（PC-1）にブレークポイントがあるかチェック
-            if let Some(_) = self.breakpoint_manager.find_by_address(new_pc - 1) {
+            if self.breakpoint_manager.find_by_address(new_pc - 1).is_some() {
                 // ブレークポイントにヒットした
-                registers.set_pc(new_pc - 1)?;
+                target.set_pc(new_pc - 1)?;
                 return Ok(StopReason::Breakpoint);
             }
 
@@ -764,16 +3797,22 @@ impl Debugger {
         }
 
         // ブレークポイント上にいない場合は通常のステップ実行
-        let stop_reason = process.step()?;
+        let stop_reason = target.step()?;
+
+        if stop_reason.is_terminated() {
+            self.handle_termination(&stop_reason);
+            return Ok(stop_reason);
+        }
+        self.select_stopped_thread();
 
         // ステップ実行後、新しいPCを取得
-        let registers = self.require_registers()?;
-        let new_pc = registers.get_pc()?;
+        let target = self.require_target()?;
+        let new_pc = target.pc()?;
 
         // ステップ先（PC-1）にブレークポイントがあるかチェック
-        if let Some(_) = self.breakpoint_manager.find_by_address(new_pc - 1) {
+        if self.breakpoint_manager.find_by_address(new_pc - 1).is_some() {
             // ブレークポイントにヒットした
-            registers.set_pc(new_pc - 1)?;
+            target.set_pc(new_pc - 1)?;
             return Ok(StopReason::Breakpoint);
         }
 
@@ -782,8 +3821,32 @@ impl Debugger {
 
     /// プログラムカウンタを取得する
     pub fn get_pc(&self) -> Result<u64> {
-        let registers = self.require_registers()?;
-        registers.get_pc()
+        self.require_target()?.pc()
+    }
+
+    /// デバッグ対象プロセスが生存しているか
+    pub fn is_alive(&self) -> bool {
+        self.target.is_some()
+    }
+
+    /// バイナリ（DWARF 情報）を読み込み済みか
+    pub fn has_binary(&self) -> bool {
+        self.symbol_resolver.is_some()
+    }
+
+    /// デバッグ対象プロセスの終了コードを取得する（未終了なら None）
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// デバッグ対象プロセスを終わらせたシグナルを取得する（シグナルで終了していなければ None）
+    pub fn exit_signal(&self) -> Option<Signal> {
+        self.exit_signal
+    }
+
+    /// シェルと同じ形の終了ステータス（終了コード、シグナルで終了したら 128+シグナル番号。未終了なら None）
+    pub fn exit_status(&self) -> Option<i32> {
+        self.exit_code.or(self.exit_signal.map(|signal| 128 + signal as i32))
     }
 
     /// Asyncトラッカーを取得する
@@ -816,15 +3879,15 @@ impl Debugger {
     /// フレームポインタ（RBP）をチェーンして呼び出しスタックを辿ります。
     /// 各フレームでリターンアドレスからシンボルを解決します。
     pub fn backtrace(&self) -> Result<Vec<StackFrame>> {
-        let registers = self.require_registers()?;
-        let memory = self.memory.as_ref()
-            .ok_or_else(|| anyhow::anyhow!(errors::ERR_NOT_ATTACHED))?;
+        let memory = self.target.as_deref()
+            .ok_or_else(|| self.no_process_error())?;
+        let registers = memory.registers()?;
 
         let mut frames = Vec::new();
 
         // 現在のフレーム（フレーム0）
-        let current_pc = registers.get_pc()?;
-        let current_rbp = registers.get_rbp()?;
+        let current_pc = registers.rip;
+        let current_rbp = registers.rbp;
 
         let function_name = self.reverse_resolve(current_pc)
             .map(|sym| sym.demangled_name.clone());
@@ -834,7 +3897,7 @@ impl Debugger {
             .unwrap_or((None, None));
 
         // フレーム0の RDI は直接レジスタから取得
-        let saved_rdi = registers.get_rdi().ok();
+        let saved_rdi = Some(registers.rdi);
 
         frames.push(StackFrame {
             frame_number: 0,
@@ -847,117 +3910,34 @@ impl Debugger {
         });
 
         // フレームポインタをチェーンして辿る
-        let mut rbp = current_rbp;
-        let mut frame_number = 1;
         const MAX_FRAMES: usize = 100; // 無限ループ防止
-
-        while frame_number < MAX_FRAMES {
-            // RBP が 0 または小さすぎる場合は終了
-            if rbp == 0 || rbp < 0x1000 {
-                break;
-            }
-
-            // RBP が有効なメモリ範囲にあるかチェック
-            if !memory.is_mapped(rbp as usize)? {
-                break;
-            }
-
-            // RBP が指すメモリから前のRBPを読み取る
-            let prev_rbp = match memory.read_u64(rbp as usize) {
-                Ok(val) => val,
-                Err(_) => break,
-            };
-
-            // RBP+8 からリターンアドレスを読み取る
-            let return_address = match memory.read_u64((rbp + 8) as usize) {
-                Ok(val) => val,
-                Err(_) => break,
-            };
-
-            // リターンアドレスが無効な場合は終了
-            if return_address == 0 || return_address < 0x1000 {
-                break;
-            }
-
+        for (caller, frame_number) in walk_frame_pointers(memory, current_rbp, MAX_FRAMES - 1)?.into_iter().zip(1..) {
             // リターンアドレスからシンボルを解決
-            let function_name = self.reverse_resolve(return_address)
+            let function_name = self.reverse_resolve(caller.return_address)
                 .map(|sym| sym.demangled_name.clone());
 
-            let (file, line) = self.get_line_info(return_address)
+            let (file, line) = self.get_call_line_info(caller.return_address)
                 .map(|(f, l)| (Some(f), Some(l)))
                 .unwrap_or((None, None));
 
             // スタックフレームから self ポインタを探索
             // async 関数の場合、RDI（self）がスタックに保存されている
-            let saved_rdi = self.scan_stack_for_self_ptr(rbp, memory);
+            let saved_rdi = scan_stack_for_self_ptr(memory, caller.frame_base);
 
             frames.push(StackFrame {
                 frame_number,
-                pc: return_address,
-                rbp: prev_rbp,
+                pc: caller.return_address,
+                rbp: caller.caller_rbp,
                 function_name,
                 file,
                 line,
                 saved_rdi,
             });
-
-            // 次のフレームへ
-            rbp = prev_rbp;
-            frame_number += 1;
-
-            // 前のRBPが現在のRBP以下の場合（スタックが逆方向）は終了
-            if prev_rbp <= current_rbp {
-                break;
-            }
         }
 
         Ok(frames)
     }
 
-    /// スタックフレームから self ポインタ（RDI の保存値）を探索する
-    ///
-    /// async 関数のスタックフレーム内から妥当なポインタ値を探索します。
-    /// スタックフレームの範囲（RBP-256 ～ RBP）を8バイトずつスキャンし、
-    /// ヒープ領域を指す可能性のあるポインタ値を返します。
-    ///
-    /// # Arguments
-    /// * `rbp` - フレームのベースポインタ
-    /// * `memory` - メモリアクセス
-    ///
-    /// # Returns
-    /// 最初に見つかった妥当なポインタ値、または None
-    fn scan_stack_for_self_ptr(&self, rbp: u64, memory: &Memory) -> Option<u64> {
-        // スタックフレームのサイズを制限（256バイト程度）
-        const FRAME_SCAN_SIZE: u64 = 256;
-
-        // RBP から下方向にスキャン（ローカル変数領域）
-        // RBP-8, RBP-16, ... とスキャン
-        for offset in (8..=FRAME_SCAN_SIZE).step_by(8) {
-            if rbp < offset {
-                break;
-            }
-
-            let addr = rbp - offset;
-
-            // メモリが読み取り可能かチェック
-            if let Ok(value) = memory.read_u64(addr as usize) {
-                // ポインタ値として妥当かチェック
-                // - NULL ではない
-                // - 小さすぎない（0x1000 以上）
-                // - マップされた領域を指している
-                if value >= 0x1000 && value < 0x7fff_ffff_ffff {
-                    // ヒープ領域やスタック領域を指している可能性が高い
-                    // 最初に見つかったものを返す（簡易実装）
-                    if memory.is_mapped(value as usize).unwrap_or(false) {
-                        return Some(value);
-                    }
-                }
-            }
-        }
-
-        None
-    }
-
     /// GenFuture::pollのret命令にexit BPを自動配置する
     ///
     /// 初回のentry BPヒット時に、関数内のすべてのret命令を検出し、
@@ -974,57 +3954,45 @@ impl Debugger {
 
         let func_start = symbol.address;
 
-        // すでに配置済みかチェック
-        if self.async_exit_bps_installed.contains(&func_start) {
+        // 計装済みでない、またはすでに配置済みならスキップ
+        if self.instrumentation.get(func_start).is_none_or(|f| f.exits_installed) {
             return Ok(());
         }
 
-        // 関数のサイズを取得
-        let func_size = symbol.size;
-        if func_size == 0 {
-            // サイズ不明の場合はスキップ（将来的にはDWARFから取得）
+        // 関数のサイズを確認
+        if symbol.size == 0 {
+            // シンボルテーブルにも DWARF にも範囲がなかった場合はスキップ
             return Ok(());
         }
 
-        // 関数のバイト列を読み取り
-        let func_bytes = {
-            let memory = self.require_memory()?;
-            match memory.read(func_start as usize, func_size as usize) {
-                Ok(bytes) => bytes,
-                Err(e) => {
-                    warn!("Failed to read function bytes at 0x{:x}: {}", func_start, e);
-                    return Ok(());
-                }
-            }
-        };
-
-        // ret命令を検出
-        let ret_addresses = match crate::disasm::find_ret_instructions(&func_bytes, func_start) {
+        // ret命令を検出（コールドな部分も含む）
+        let ret_addresses = match self.find_function_rets(&symbol) {
             Ok(addrs) => addrs,
             Err(e) => {
-                warn!("Failed to disassemble function at 0x{:x}: {}", func_start, e);
+                self.emit_warning(format!("Failed to disassemble function at 0x{:x}: {}", func_start, e));
                 return Ok(());
             }
         };
 
         // 各ret命令にBPを配置（AsyncExitとして設定）
+        let mut exit_bp_ids = Vec::new();
         for ret_addr in ret_addresses {
-            match self.set_breakpoint_with_type(ret_addr, crate::breakpoint::BreakpointType::AsyncExit) {
-                Ok(_bp_id) => {
-                    // BPが配置されたことをログ出力
-                    if let Some(sym) = self.reverse_resolve(ret_addr) {
-                        debug!("Async exit breakpoint set at {}+{:#x} (0x{:x})",
-                                 sym.demangled_name, ret_addr - sym.address, ret_addr);
-                    }
+            let runtime_addr = self.offset_to_runtime_addr(ret_addr)?;
+            match self.set_breakpoint_with_type(runtime_addr, crate::breakpoint::BreakpointType::AsyncExit) {
+                Ok(bp_id) => {
+                    exit_bp_ids.push(bp_id);
+                    debug!("Async exit breakpoint set at {}+{:#x} (0x{:x})",
+                             symbol.demangled_name, ret_addr - func_start, runtime_addr);
                 }
                 Err(e) => {
-                    warn!("Failed to set exit breakpoint at 0x{:x}: {}", ret_addr, e);
+                    self.emit_warning(format!("Failed to set exit breakpoint at 0x{:x}: {}", ret_addr, e));
                 }
             }
         }
 
-        // 配置完了を記録
-        self.async_exit_bps_installed.insert(func_start);
+        // 配置完了を記録（戻り値の Poll の読み方もここで求めておく）
+        self.instrumentation.add_exits(func_start, exit_bp_ids);
+        self.poll_discriminant(&symbol);
 
         Ok(())
     }
@@ -1041,42 +4009,55 @@ impl Debugger {
     /// # Returns
     /// discriminant 値（u32）、または読み取り失敗時は None
     pub fn read_discriminant(&self, task_ptr: u64, function_name: Option<&str>) -> Option<u64> {
-        let memory = self.memory.as_ref()?;
+        let memory = self.target.as_deref()?;
 
         debug!("read_discriminant for task_ptr=0x{:x}, function_name={:?}", task_ptr, function_name);
 
+        // 型の索引に状態機械の型があれば、その discriminant のメンバーを読む
+        let member = function_name
+            .and_then(|function| self.state_machine_layout(function).ok().flatten())
+            .and_then(|layout| layout.discriminant);
+        if let Some((offset, size)) = member.and_then(|member| Some((member.offset?, member.size?))) {
+            let value = memory.read_uint(task_ptr + offset, size as usize);
+            if let Ok(value) = value {
+                debug!("Read discriminant at offset {} ({} bytes) from the type index: {}", offset, size, value);
+                return Some(value);
+            }
+        }
+
         // 関数名が提供された場合、DWARFからdiscriminantレイアウトを取得
         if let Some(func_name) = function_name {
             if let Some(dwarf_loader) = &self.dwarf_loader {
                 use kokia_dwarf::GeneratorLayoutAnalyzer;
 
-                let analyzer = GeneratorLayoutAnalyzer::new(dwarf_loader.dwarf());
+                let analyzer = GeneratorLayoutAnalyzer::new(dwarf_loader.dwarf())
+                    .with_cancel(self.query_cancel_token());
                 if let Ok(Some(layout)) = analyzer.get_discriminant_layout(func_name) {
                     debug!("Found discriminant layout: offset={}, size={}", layout.offset, layout.size);
                     // レイアウト情報に基づいて読み取り
-                    let addr = (task_ptr + layout.offset) as usize;
+                    let addr = task_ptr + layout.offset;
 
                     match layout.size {
                         1 => {
-                            if let Ok(val) = memory.read_u8(addr) {
+                            if let Ok(val) = memory.read_uint(addr, 1) {
                                 debug!("Read discriminant u8: {}", val);
-                                return Some(val as u64);
+                                return Some(val);
                             }
                         }
                         2 => {
-                            if let Ok(val) = memory.read_u16(addr) {
+                            if let Ok(val) = memory.read_uint(addr, 2) {
                                 debug!("Read discriminant u16: {}", val);
-                                return Some(val as u64);
+                                return Some(val);
                             }
                         }
                         4 => {
-                            if let Ok(val) = memory.read_u32(addr) {
+                            if let Ok(val) = memory.read_uint(addr, 4) {
                                 debug!("Read discriminant u32: {}", val);
-                                return Some(val as u64);
+                                return Some(val);
                             }
                         }
                         8 => {
-                            if let Ok(val) = memory.read_u64(addr) {
+                            if let Ok(val) = memory.read_uint(addr, 8) {
                                 debug!("Read discriminant u64: {}", val);
                                 return Some(val);
                             }
@@ -1084,9 +4065,9 @@ impl Debugger {
                         _ => {
                             debug!("Unsupported discriminant size: {}, trying to read first byte", layout.size);
                             // 大きなサイズの場合、最初の1バイトまたは4バイトを読む
-                            if let Ok(val) = memory.read_u32(addr) {
+                            if let Ok(val) = memory.read_uint(addr, 4) {
                                 debug!("Read discriminant (first 4 bytes of {}-byte field): {}", layout.size, val);
-                                return Some(val as u64);
+                                return Some(val);
                             }
                         }
                     }
@@ -1099,9 +4080,9 @@ impl Debugger {
         // フォールバック: デフォルトの動作（offset 0, size 4）
         // 生成器の discriminant は通常、構造体の先頭に u32 として配置される
         debug!("Falling back to default discriminant read (offset=0, size=4)");
-        if let Ok(discr) = memory.read_u32(task_ptr as usize) {
+        if let Ok(discr) = memory.read_uint(task_ptr, 4) {
             debug!("Read discriminant (fallback) u32: {}", discr);
-            return Some(discr as u64);
+            return Some(discr);
         }
 
         debug!("Failed to read discriminant");
@@ -1110,8 +4091,145 @@ impl Debugger {
 
     /// ローカル変数を取得する
     ///
-    /// 現在のPCでのローカル変数を取得し、値を読み取ります。
+    /// 現在のPCでのローカル変数と引数を取得し、値を読み取ります（種類は `Variable::kind`）。
     pub fn get_local_variables(&self) -> Result<Vec<kokia_dwarf::Variable>> {
+        let registers = self.require_registers()?;
+        self.variables_at(registers.get_pc()?, registers.get_rbp()?)
+    }
+
+    /// バックトレースのフレームのローカル変数と引数を取得する
+    ///
+    /// 呼び出し元のフレームの PC は call 命令の次（関数の末尾なら別の関数）を指すことがあるため、
+    /// 1 戻して call 命令の中で探します。
+    pub fn frame_variables(&self, frame: &StackFrame) -> Result<Vec<kokia_dwarf::Variable>> {
+        let pc = if frame.frame_number == 0 { frame.pc } else { frame.pc.saturating_sub(1) };
+        self.variables_at(pc, frame.rbp)
+    }
+
+    /// xmm0〜15 の値（読めなければ 0）
+    fn xmm_registers(&self) -> Result<[[u8; 16]; 16]> {
+        let mut xmm = [[0u8; 16]; 16];
+        if let Ok(fpregs) = self.require_registers()?.read_fpregs() {
+            for (xmm, words) in xmm.iter_mut().zip(fpregs.xmm_space.chunks(4)) {
+                for (bytes, word) in xmm.chunks_mut(4).zip(words) {
+                    bytes.copy_from_slice(&word.to_le_bytes());
+                }
+            }
+        }
+        Ok(xmm)
+    }
+
+    /// 関数の戻り値を DWARF の戻り値の型に従って読み、整形する（ret 命令の上か、戻った直後で呼ぶ）
+    ///
+    /// 戻り値の型がない関数や読めない場合は None です。
+    fn decode_return_value(&self, function: &Symbol) -> Option<String> {
+        let decoded = (|| -> Result<Option<String>> {
+            let (loader, index) = self.type_index()?;
+            let Some(type_ref) = kokia_dwarf::function_return_type(loader, function.address)? else {
+                return Ok(None);
+            };
+            let layout = index.layout(loader, type_ref, 2)?;
+            let memory = self.require_memory()?;
+            let mut read_memory = |address: u64, len: usize| memory.read(address as usize, len).ok();
+            let rust_abi = crate::callconv::is_rust_abi(&function.name);
+            let bytes = crate::callconv::read_return_value(&layout, rust_abi, &self.general_registers()?, &self.xmm_registers()?, &mut read_memory)
+                .ok_or_else(|| anyhow::anyhow!("the return value is not readable"))?;
+            Ok(Some(crate::callconv::format_value(&bytes, &layout, &mut read_memory)))
+        })();
+        decoded.unwrap_or_else(|e| {
+            debug!("Failed to decode the return value of {}: {}", function.demangled_name, e);
+            None
+        })
+    }
+
+    /// poll 関数の戻り値 `Poll<T>` の discriminant の場所を引く（関数ごとに一度だけ DWARF から求める）
+    ///
+    /// 戻り値が `Poll<T>` でない関数や型が分からない関数は None です。
+    fn poll_discriminant(&mut self, function: &Symbol) -> Option<PollDiscriminant> {
+        if let Some(poll) = self.poll_discriminants.get(&function.address) {
+            return *poll;
+        }
+        let analyzed = (|| -> Result<Option<PollDiscriminant>> {
+            let (loader, index) = self.type_index()?;
+            let Some(type_ref) = kokia_dwarf::function_return_type(loader, function.address)? else {
+                return Ok(None);
+            };
+            let layout = index.layout(loader, type_ref, 2)?;
+            Ok(PollDiscriminant::analyze(&layout, crate::callconv::is_rust_abi(&function.name)))
+        })();
+        let poll = analyzed.unwrap_or_else(|e| {
+            debug!("Failed to analyze the Poll of {}: {}", function.demangled_name, e);
+            None
+        });
+        debug!("Poll discriminant of {}: {:?}", function.demangled_name, poll);
+        self.poll_discriminants.insert(function.address, poll);
+        poll
+    }
+
+    /// 関数の入口で止まっているとき、仮引数を呼び出し規約に従って引数のレジスタとスタックから読む
+    ///
+    /// 関数の先頭か、引数のレジスタを書き換えないプロローグの直後（シンボルのブレークポイントの位置）で
+    /// だけ読めます。それ以外の場所では None です。
+    pub fn entry_arguments(&self) -> Result<Option<Vec<(String, String)>>> {
+        use crate::callconv::{self, ArgumentAssigner};
+
+        let registers = self.require_registers()?;
+        let memory = self.require_memory()?;
+        let regs = registers.read()?;
+        let Some(symbol) = self.reverse_resolve(regs.rip) else {
+            return Ok(None);
+        };
+        let start = self.offset_to_runtime_addr(symbol.address)?;
+        let prologue = if regs.rip == start {
+            callconv::trace_prologue(&[], start)
+        } else if regs.rip > start && regs.rip == self.symbol_breakpoint_address(&symbol)? {
+            let mut code = memory.read(start as usize, (regs.rip - start) as usize)?;
+            self.breakpoint_manager.restore_original_bytes(start, &mut code);
+            callconv::trace_prologue(&code, start)
+        } else {
+            None
+        };
+        let Some(prologue) = prologue else {
+            return Ok(None);
+        };
+
+        let (loader, index) = self.type_index()?;
+        let parameters = kokia_dwarf::function_parameters(loader, symbol.address)?;
+        let indirect_return = kokia_dwarf::function_return_type(loader, symbol.address)?
+            .and_then(|type_ref| index.layout(loader, type_ref, 0).ok())
+            .is_some_and(|layout| callconv::returns_in_memory(&layout));
+        let mut assigner = ArgumentAssigner::new(callconv::is_rust_abi(&symbol.name), indirect_return);
+
+        let xmm = self.xmm_registers()?;
+        let mut read_memory = |address: u64, len: usize| memory.read(address as usize, len).ok();
+        let entry = prologue.entry_registers(&regs, &xmm, &mut read_memory);
+
+        let mut arguments = Vec::new();
+        let mut lost = false;
+        for (name, type_ref) in parameters {
+            // 型が分からない引数があると、それ以降の置き場所も分からない
+            let layout = if lost { None } else { index.layout(loader, type_ref, 2).ok() };
+            let location = layout.as_ref().filter(|layout| layout.size.is_some()).map(|layout| (layout, assigner.assign(layout)));
+            let value = match location {
+                Some((layout, None)) => callconv::format_value(&[], layout, &mut read_memory),
+                Some((layout, Some(location))) => {
+                    let size = layout.size.unwrap_or(0) as usize;
+                    match entry.read(&location, size, &mut read_memory) {
+                        Some(bytes) => callconv::format_value(&bytes, layout, &mut read_memory),
+                        None => "<unavailable>".to_string(),
+                    }
+                }
+                None => {
+                    lost = true;
+                    "<unavailable>".to_string()
+                }
+            };
+            arguments.push((name, value));
+        }
+        Ok(Some(arguments))
+    }
+
+    fn variables_at(&self, pc: u64, rbp: u64) -> Result<Vec<kokia_dwarf::Variable>> {
         use kokia_dwarf::VariableLocator;
 
         let loader = self.dwarf_loader.as_ref()
@@ -1119,14 +4237,12 @@ impl Debugger {
 
         let registers = self.require_registers()?;
         let memory = self.require_memory()?;
-        let pc = registers.get_pc()?;
-        let rbp = registers.get_rbp()?;
 
         // PIE対応のアドレス変換
         let pc_offset = self.runtime_addr_to_offset(pc)?;
 
         // DWARFから変数情報を取得
-        let locator = VariableLocator::new(loader);
+        let locator = VariableLocator::new(loader).with_cancel(self.query_cancel_token());
         let mut variables = locator.get_locals(pc_offset)?;
 
         // 各変数の値を読み取る
@@ -1137,6 +4253,57 @@ impl Debugger {
         Ok(variables)
     }
 
+    /// 関数の外の static か `thread_local!` を名前で探し、実行時アドレスと変数を返す（なければ None）
+    ///
+    /// `thread_local!` は選択中のスレッドの実体を指します。FS レジスタの TCB から DTV をたどり、
+    /// `__tls_get_addr` と同じ計算で実行ファイルの TLS ブロックを求めます。
+    pub fn find_static_variable(&self, name: &str) -> Result<Option<(u64, StaticVariable)>> {
+        use kokia_dwarf::{StaticLocation, VariableLocator};
+        use kokia_target::{tls_block_address, EXECUTABLE_TLS_MODULE};
+
+        let loader = self.dwarf_loader.as_ref()
+            .ok_or_else(|| anyhow::anyhow!(errors::ERR_DWARF_NOT_LOADED))?;
+        let mut candidates = VariableLocator::new(loader).with_cancel(self.query_cancel_token()).find_statics(name)?;
+        // 完全な名前で一致するものがあれば、末尾だけ一致するものは捨てる
+        if candidates.iter().any(|var| var.path == name) {
+            candidates.retain(|var| var.path == name);
+        }
+        // 同じ static が複数のコンパイルユニットに現れることがある
+        let mut unique: Vec<StaticVariable> = Vec::new();
+        for var in candidates {
+            if !unique.iter().any(|seen| seen.path == var.path && seen.location == var.location) {
+                unique.push(var);
+            }
+        }
+        let var = match unique.len() {
+            0 => return Ok(None),
+            1 => unique.remove(0),
+            _ => {
+                let paths: Vec<&str> = unique.iter().map(|var| var.path.as_str()).collect();
+                return Err(anyhow::anyhow!("'{}' is ambiguous: {}", name, paths.join(", ")));
+            }
+        };
+
+        let address = match var.location {
+            StaticLocation::Address(addr) => self.offset_to_runtime_addr(addr)?,
+            StaticLocation::ThreadLocal(offset) => {
+                let fs_base = self.require_registers()?.read()?.fs_base;
+                tls_block_address(self.require_memory()?, fs_base, EXECUTABLE_TLS_MODULE)? + offset
+            }
+        };
+        Ok(Some((address, var)))
+    }
+
+    /// [`Debugger::find_static_variable`] で見つけた変数の型情報
+    pub fn static_type_info(&self, var: &StaticVariable) -> Result<Option<TypeInfo>> {
+        let (Some(loader), Some(type_ref)) = (self.dwarf_loader.as_ref(), var.type_ref) else {
+            return Ok(None);
+        };
+        let dwarf = loader.dwarf();
+        let unit = dwarf.unit(dwarf.debug_info.header_from_offset(type_ref.unit)?)?;
+        kokia_dwarf::TypeInfoExtractor::new(dwarf).extract_type_info(&unit, type_ref.die).map(Some)
+    }
+
     /// 現在のPCがasync関数（generator）かを判定し、selfポインタと型名を返す
     ///
     /// # Arguments
@@ -1170,16 +4337,21 @@ impl Debugger {
         Ok(None)
     }
 
-    /// 現在のフレームのローカル変数を取得する（async関数対応）
+    /// async タスクのローカル変数を取得する
     ///
-    /// 現在停止しているasync関数のローカル変数を取得します。
+    /// 実行中のフレームのタスクなら次の2つを合わせて返します。
     /// - DWARF location evaluation: スタック/レジスタ上の変数
     /// - Generator state machine: Future構造体内の変数（.awaitを跨ぐ変数）
     ///
+    /// 中断中のタスクはスタックフレームを持たないので、Future 構造体に保存された変数だけを返します。
+    ///
+    /// # Arguments
+    /// * `task` - 対象のタスク（`#4` の番号かタスクID。`None` なら現在のフレーム、つまり選択中のスレッドで poll 中のタスク）
+    ///
     /// # Returns
     /// ローカル変数のリスト
-    pub fn get_async_locals(&self) -> Result<Vec<kokia_dwarf::Variable>> {
-        use kokia_dwarf::{GeneratorLayoutAnalyzer, Variable, VariableLocation, VariableValue, VariableLocator};
+    pub fn get_async_locals(&self, task: Option<kokia_async::TaskRef>) -> Result<Vec<kokia_dwarf::Variable>> {
+        use kokia_dwarf::{GeneratorLayoutAnalyzer, Variable, VariableKind, VariableLocation, VariableValue, VariableLocator};
 
         let loader = self.dwarf_loader.as_ref()
             .ok_or_else(|| anyhow::anyhow!(errors::ERR_DWARF_NOT_LOADED))?;
@@ -1196,6 +4368,19 @@ impl Debugger {
         // async関数の場合、第一引数（RDI）がgenerator selfポインタ
         let generator_self = self.detect_generator_self(pc, registers)?;
 
+        if let Some(task) = task {
+            let info = self.async_tracker.task_tracker().lookup(task)
+                .and_then(|id| self.async_tracker.task_tracker().get(id))
+                .ok_or_else(|| anyhow::anyhow!("Task not found: {}", task))?;
+            let id = info.id;
+            // 現在のフレームが poll しているタスクでなければ、スタック上の変数はない
+            if generator_self.as_ref().map(|(self_ptr, _)| *self_ptr) != Some(info.address) {
+                let function = info.type_name.as_deref()
+                    .ok_or_else(|| anyhow::anyhow!("Function of task {:#x} is not known yet", id))?;
+                return self.suspended_task_locals(info.address, function);
+            }
+        }
+
         // レジスタ状態を取得（DWARF location evaluationで使用）
         let regs = registers.read()?;
 
@@ -1216,7 +4401,14 @@ impl Debugger {
             }
         };
 
-        let locator = VariableLocator::new(loader);
+        let cancel = self.query_cancel_token();
+        // 最適化で上書きされた引数は、呼び出し元の call site の値（DW_OP_entry_value）で復元する
+        let locator = VariableLocator::new(loader)
+            .with_cancel(cancel.clone())
+            .with_entry_values(self.caller_entry_values(pc, &regs));
+
+        // XMM レジスタ（浮動小数点変数が置かれることが多い）
+        let fpregs = registers.read_fpregs().ok();
 
         // レジスタ値取得コールバック（現在のレジスタ状態を使用）
         // x86_64 DWARF register mapping
@@ -1239,6 +4431,13 @@ impl Debugger {
                 14 => regs.r14,
                 15 => regs.r15,
                 16 => regs.rip,
+                // xmm0〜xmm15 の下位 64 ビット（f32/f64 の値を含む）
+                17..=32 => {
+                    let fpregs = fpregs.as_ref()
+                        .ok_or_else(|| anyhow::anyhow!("FP registers not available"))?;
+                    let base = (reg as usize - 17) * 4;
+                    (fpregs.xmm_space[base] as u64) | ((fpregs.xmm_space[base + 1] as u64) << 32)
+                }
                 _ => return Err(anyhow::anyhow!("Unsupported register number: {}", reg)),
             };
             Ok(val)
@@ -1259,8 +4458,9 @@ impl Debugger {
                 debug!("DWARF found {} variables", vars.len());
                 result_variables = vars;
             }
+            Err(e) if kokia_dwarf::cancel::is_cancelled(&e) => return Err(e),
             Err(e) => {
-                warn!("DWARF variable extraction failed: {}", e);
+                self.emit_warning(format!("DWARF variable extraction failed: {}", e));
             }
         }
 
@@ -1272,7 +4472,7 @@ impl Debugger {
             let discriminant = self.read_discriminant(self_ptr, Some(&type_name)).unwrap_or(0);
             debug!("Generator discriminant = {}", discriminant);
 
-            let analyzer = GeneratorLayoutAnalyzer::new(loader.dwarf());
+            let analyzer = GeneratorLayoutAnalyzer::new(loader.dwarf()).with_cancel(cancel);
 
             debug!("Looking for generator variant with func_name='{}' and discriminant={}", type_name, discriminant);
 
@@ -1292,9 +4492,9 @@ impl Debugger {
                             4 => memory.read_u32(addr as usize).ok()
                                 .map(|v| VariableValue::UnsignedInteger(v as u64)),
                             8 => memory.read_u64(addr as usize).ok()
-                                .map(|v| VariableValue::UnsignedInteger(v)),
+                                .map(VariableValue::UnsignedInteger),
                             _ => memory.read(addr as usize, field.size as usize).ok()
-                                .map(|bytes| VariableValue::Bytes(bytes)),
+                                .map(VariableValue::Bytes),
                         };
 
                         generator_variables.push(Variable {
@@ -1302,12 +4502,14 @@ impl Debugger {
                             type_name: field.type_name.unwrap_or_else(|| format!("{} bytes", field.size)),
                             value,
                             location: VariableLocation::Address(addr),
+                            kind: VariableKind::Local,
                         });
                     }
                 }
                 Ok(None) => {
                     debug!("No variant info found for discriminant {}", discriminant);
                 }
+                Err(e) if kokia_dwarf::cancel::is_cancelled(&e) => return Err(e),
                 Err(e) => {
                     debug!("Generator layout analysis failed: {}", e);
                 }
@@ -1385,7 +4587,7 @@ impl Debugger {
                 // レジスタの値（今後実装）
                 Ok(VariableValue::Unavailable)
             }
-            VariableLocation::OptimizedOut => {
+            VariableLocation::Computed | VariableLocation::OptimizedOut => {
                 Ok(VariableValue::Unavailable)
             }
             VariableLocation::Unknown => {
@@ -1394,73 +4596,160 @@ impl Debugger {
         }
     }
 
-    /// Async関数（generator）のローカル変数を取得する
-    ///
-    /// # Arguments
-    /// * `task_id` - 対象のTaskID（generatorのselfポインタ）
+    /// 中断中のタスクの Future 構造体から、現在の状態の variant に保存された変数を読む
     ///
-    /// # Returns
-    /// 変数情報のベクタ
-    pub fn get_async_local_variables(&self, task_id: kokia_async::TaskId) -> Result<Vec<kokia_dwarf::Variable>> {
-        use kokia_async::GeneratorAnalyzer;
-        use kokia_dwarf::{Variable, VariableValue, VariableLocation};
-
-        let loader = self.dwarf_loader.as_ref()
-            .ok_or_else(|| anyhow::anyhow!(errors::ERR_DWARF_NOT_LOADED))?;
-
-        let memory = self.require_memory()?;
-
-        // TaskInfoを取得してPCとdiscriminantを取得
-        let task_info = self.async_tracker
-            .task_tracker()
-            .get(task_id)
-            .ok_or_else(|| anyhow::anyhow!("Task not found: {:#x}", task_id))?;
-
-        let pc = task_info.last_rip
-            .ok_or_else(|| anyhow::anyhow!("Task PC not available"))?;
-        let discriminant = task_info.current_discriminant;
-
-        // PIE対応のアドレス変換
-        let pc_offset = self.runtime_addr_to_offset(pc)?;
-
-        // GeneratorAnalyzerを使ってdiscriminant情報を取得
-        let analyzer = GeneratorAnalyzer::new(loader.dwarf());
+    /// 状態機械の型は `ptype` と同じく async fn のパスで引き、discriminant で variant を選びます。
+    fn suspended_task_locals(&self, address: u64, function: &str) -> Result<Vec<kokia_dwarf::Variable>> {
+        use kokia_dwarf::{Variable, VariableKind, VariableLocation, VariableValue};
+
+        let type_name = function.strip_suffix("::{{closure}}").unwrap_or(function);
+        let layout = self.type_layout(type_name, 0)?
+            .ok_or_else(|| anyhow::anyhow!("State machine type of {} not found", type_name))?;
+        let size = layout.size.ok_or_else(|| anyhow::anyhow!("Size of {} is unknown", layout.name))?;
+        let bytes = self.require_memory()?.read(address as usize, size as usize)?;
+        let variant = layout.active_variant(&bytes)
+            .ok_or_else(|| anyhow::anyhow!("Task state at {:#x} matches no variant of {}", address, layout.name))?;
+
+        let mut variables = vec![Variable {
+            name: "__state".to_string(),
+            type_name: variant.name.clone(),
+            value: variant.discriminant.map(VariableValue::UnsignedInteger),
+            location: VariableLocation::Unknown,
+            kind: VariableKind::Local,
+        }];
+        for member in &variant.members {
+            let (Some(offset), Some(len)) = (member.offset, member.size) else { continue };
+            let field = bytes.get(offset as usize..(offset + len) as usize);
+            let value = match field {
+                Some(raw) if (1..=8).contains(&raw.len()) => {
+                    let mut word = [0u8; 8];
+                    word[..raw.len()].copy_from_slice(raw);
+                    VariableValue::UnsignedInteger(u64::from_le_bytes(word))
+                }
+                Some(raw) => VariableValue::Bytes(raw.to_vec()),
+                None => VariableValue::Unavailable,
+            };
+            variables.push(Variable {
+                name: kokia_async::normalize_field_name(&member.name),
+                type_name: member.type_name.clone(),
+                value: Some(value),
+                location: VariableLocation::Address(address + offset),
+                kind: VariableKind::Local,
+            });
+        }
+        Ok(variables)
+    }
 
-        let mut variables = Vec::new();
+    /// 追跡中の（完了していない）タスクの状態機械のサイズを集計する
+    ///
+    /// サイズは各タスクの関数の状態機械の型から求め、親は最後に観測した未完了のエッジから選びます。
+    pub fn async_memsize(&self) -> Result<crate::memsize::MemsizeReport> {
+        use crate::memsize::{MemsizeReport, TaskMemory};
 
-        // まず、discriminantフィールド自体を追加
-        if let Some(discr_val) = discriminant {
-            variables.push(Variable {
-                name: "__state".to_string(),
-                type_name: "u32".to_string(),
-                value: Some(VariableValue::UnsignedInteger(discr_val)),
-                location: VariableLocation::Unknown,
+        let mut sizes: HashMap<&str, Option<u64>> = HashMap::new();
+        let mut tasks = Vec::new();
+        for task in self.async_tracker.all_tasks().into_iter().filter(|task| task.is_live()) {
+            let size = match task.type_name.as_deref() {
+                Some(function) => match sizes.get(function) {
+                    Some(&size) => size,
+                    None => {
+                        let size = self.state_machine_size(function)?;
+                        *sizes.entry(function).or_insert(size)
+                    }
+                },
+                None => None,
+            };
+            let parent = self.async_tracker.edge_tracker().edges_by_child(task.id)
+                .filter(|edge| !edge.completed)
+                .max_by_key(|edge| edge.last_seen)
+                .map(|edge| edge.parent);
+            tasks.push(TaskMemory {
+                id: task.id,
+                handle: task.handle,
+                address: task.address,
+                function: task.type_name.clone(),
+                size,
+                parent,
             });
         }
+        Ok(MemsizeReport::new(tasks))
+    }
 
-        // Active variantのフィールドを取得
-        if let Some(discr_val) = discriminant {
-            let fields = analyzer.get_variant_fields(pc_offset, discr_val)?;
+    /// タスクの関数名（`path::{{closure}}`）から状態機械の型のサイズを求める
+    ///
+    /// async fn でなければ、その関数の中の async ブロックが1つだけのときに限りその型を使います。
+    fn state_machine_size(&self, function: &str) -> Result<Option<u64>> {
+        Ok(self.state_machine_layout(function)?.and_then(|layout| layout.size))
+    }
 
-            for field in fields {
-                // generatorのselfポインタ + フィールドオフセットから値を読み取る
-                let field_addr = task_id + field.offset;
+    /// タスクの関数名から状態機械の型のレイアウトを求める（[`Debugger::state_machine_size`] と同じ規則）
+    fn state_machine_layout(&self, function: &str) -> Result<Option<TypeLayout>> {
+        let path = function.strip_suffix("::{{closure}}").unwrap_or(function);
+        let mut layout = self.type_layout(path, 0)?;
+        if layout.is_none() && self.resolve_type_name(&format!("{}::{{async_block_env#1}}", path))?.is_none() {
+            layout = self.type_layout(&format!("{}::{{async_block_env#0}}", path), 0)?;
+        }
+        Ok(layout)
+    }
 
-                let value = match memory.read_u64(field_addr as usize) {
-                    Ok(val) => Some(VariableValue::UnsignedInteger(val)),
-                    Err(_) => Some(VariableValue::Unavailable),
-                };
+    /// 全スレッドのスタックから poll 中の async タスクを見つけ、AsyncTracker に登録する
+    ///
+    /// アタッチ直後に `async enable` すると、それ以降に poll されたタスクしか分からないため、
+    /// 計装の時点ですでに走っていたタスクをスタックから拾って「既存タスク」として登録します。
+    /// ptrace で止まっていないスレッドは読めないので飛ばします。スレッドを切り替えて読むので、
+    /// 最後に選択中のスレッドを元に戻します。
+    ///
+    /// # Returns
+    /// (新たに登録したタスク数, スタックを読めたスレッド数)
+    pub fn seed_async_tasks_from_threads(&mut self) -> Result<(usize, usize)> {
+        use kokia_async::Tid;
 
-                variables.push(Variable {
-                    name: kokia_async::normalize_field_name(&field.name),
-                    type_name: field.type_name.unwrap_or_else(|| "<unknown>".to_string()),
-                    value,
-                    location: VariableLocation::Address(field_addr),
-                });
+        let threads = self.threads()?;
+        let selected = self.process.as_ref().map(|process| process.current_thread());
+        let observed: Vec<_> = threads.into_iter()
+            .filter_map(|tid| match self.observe_async_frames(tid) {
+                Ok(chain) => Some((tid, chain)),
+                Err(e) => {
+                    debug!("Skipping thread {} while seeding async tasks: {}", tid, e);
+                    None
+                }
+            })
+            .collect();
+        if let (Some(process), Some(tid)) = (self.process.as_ref(), selected) {
+            if let Err(e) = process.select_thread(tid) {
+                debug!("Failed to reselect thread {} after seeding async tasks: {}", tid, e);
             }
         }
 
-        Ok(variables)
+        let threads = observed.len();
+        let seeded = observed
+            .into_iter()
+            .map(|(tid, chain)| self.async_tracker.seed_from_stack(Tid(tid), &chain))
+            .sum();
+        Ok((seeded, threads))
+    }
+
+    /// `tid` のスレッドを選択して、スタック上の async 関数フレームを子→親の順に集める
+    fn observe_async_frames(&self, tid: i32) -> Result<Vec<kokia_async::ObservedFrame>> {
+        self.process.as_ref()
+            .ok_or_else(|| self.no_process_error())?
+            .select_thread(tid)?;
+        let frames = self.backtrace()?;
+        Ok(frames
+            .iter()
+            .filter(|frame| frame.function_name.as_deref().is_some_and(|n| n.contains("{{closure}}")))
+            .filter_map(|frame| {
+                let task = frame.saved_rdi?;
+                // 外側のフレームの pc は戻りアドレスなので、1 つ手前が await している call
+                let call_pc = if frame.frame_number > 0 { frame.pc - 1 } else { frame.pc };
+                Some(kokia_async::ObservedFrame {
+                    task,
+                    function_name: frame.function_name.clone(),
+                    discriminant: self.read_discriminant(task, frame.function_name.as_deref()),
+                    source_location: self.get_line_info(call_pc),
+                })
+            })
+            .collect())
     }
 
     /// OS スタックから async 関数のタスクリストを抽出する
@@ -1495,3 +4784,97 @@ impl Default for Debugger {
         Self::new()
     }
 }
+
+/// [`Debugger::read_register`] が読めるレジスタ名（別名を含む）
+const REGISTER_NAMES: &[&str] = &[
+    "pc", "rip", "sp", "rsp", "fp", "rbp", "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "r8", "r9", "r10", "r11",
+    "r12", "r13", "r14", "r15", "eflags", "fs_base", "gs_base",
+];
+
+/// poll の戻り値レジスタから Poll::Ready かを推定する（DWARF の戻り値の型が使えないとき用）
+///
+/// `Poll<T>` は Ready が先頭のバリアント（タグ 0）、Pending がタグ 1 で、
+/// 小さな `T` ならタグは RAX の最下位バイトに入る。大きな戻り値はメモリ経由で返るため当てにならない。
+fn poll_is_ready(rax: u64) -> bool {
+    rax & 0xFF == 0
+}
+
+/// 実行時アドレスを、それを含むファイルマッピングのパスとファイル内オフセットに変換する
+fn file_location(mappings: &[MemoryMapping], addr: u64) -> Option<(PathBuf, u64)> {
+    let addr = addr as usize;
+    let mapping = mappings.iter().find(|m| m.start <= addr && addr < m.end)?;
+    let path = mapping.path.as_deref().filter(|p| p.starts_with('/'))?;
+    Some((PathBuf::from(path), (addr - mapping.start + mapping.offset) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kokia_async::Tid;
+    use kokia_target::FakeTarget;
+
+    const CODE: u64 = 0x1000;
+    const TASK: u64 = 0x5000;
+    const STACK: u64 = 0x7000;
+
+    #[test]
+    fn test_poll_entry_and_exit_on_fake_target() {
+        // push rbp; mov rbp, rsp; pop rbp; ret
+        let target = FakeTarget::new();
+        target.map(CODE, vec![0x55, 0x48, 0x89, 0xe5, 0x5d, 0xc3]);
+        target.map_zeroed(TASK, 0x40);
+        target.map_zeroed(STACK, 0x100);
+        target.with_regs(|regs| {
+            regs.rip = CODE;
+            regs.rsp = STACK + 0x80;
+            regs.rdi = TASK;
+        });
+        target.will_execute(CODE);
+        target.will_execute(CODE + 5);
+
+        let mut debugger = Debugger::new();
+        let symbols = vec![Symbol::new("app::work::{{closure}}".to_string(), CODE, 6)];
+        debugger.attach_backend(Box::new(target), symbols);
+        assert!(debugger.is_alive());
+        assert_eq!(debugger.enable_async_instrumentation(None).unwrap().len(), 1);
+
+        // entry で止まると、RDI の Future が poll 中のタスクになる
+        assert_eq!(debugger.continue_and_wait().unwrap(), StopReason::Breakpoint);
+        assert_eq!(debugger.get_pc().unwrap(), CODE);
+        let task = debugger.async_tracker().current_task(Tid(1)).unwrap();
+        assert_eq!(task.address, TASK);
+        assert_eq!(task.current_discriminant, Some(0));
+
+        // ret で止まるとスコープから外れる（状態は Unresumed のままなので Pending）
+        assert_eq!(debugger.continue_and_wait().unwrap(), StopReason::Breakpoint);
+        assert_eq!(debugger.get_pc().unwrap(), CODE + 5);
+        assert!(debugger.async_tracker().async_backtrace(Tid(1)).is_empty());
+        assert!(debugger.async_tracker().task_tracker().all_tasks().all(|task| !task.completed));
+
+        assert_eq!(debugger.continue_and_wait().unwrap(), StopReason::Exited(0));
+        assert!(!debugger.is_alive());
+    }
+
+    #[test]
+    fn test_logpoints_do_not_outlive_the_inferior() {
+        let launch = |debugger: &mut Debugger| {
+            let target = FakeTarget::new();
+            target.map(CODE, vec![0x90, 0xc3]);
+            let symbols = vec![Symbol::new("app::work".to_string(), CODE, 2)];
+            debugger.attach_backend(Box::new(target), symbols);
+        };
+
+        let mut debugger = Debugger::new();
+        launch(&mut debugger);
+        let id = debugger.set_breakpoint(CODE).unwrap();
+        debugger.set_logpoint(id, LogTemplate::parse("tick").unwrap()).unwrap();
+        assert_eq!(debugger.export_state().unwrap().breakpoints[0].logpoint.as_deref(), Some("tick"));
+        assert_eq!(debugger.continue_and_wait().unwrap(), StopReason::Exited(0));
+
+        // 次のプロセスのブレークポイントは同じ番号から始まるが、ログポイントではない
+        launch(&mut debugger);
+        assert_eq!(debugger.set_breakpoint(CODE).unwrap(), id);
+        assert!(debugger.logpoint(id).is_none());
+        assert_eq!(debugger.export_state().unwrap().breakpoints[0].logpoint, None);
+    }
+}
@@ -1,6 +1,7 @@
 //! 逆アセンブル機能
 //!
 //! 関数のバイト列を逆アセンブルしてret命令のアドレスを検出します。
+//! クラッシュ時の報告用に、命令列をそのまま表示用のテキストにすることもできます。
 
 use crate::Result;
 use capstone::prelude::*;
@@ -40,6 +41,42 @@ pub fn find_ret_instructions(code: &[u8], base_addr: u64) -> Result<Vec<u64>> {
     Ok(ret_addresses)
 }
 
+/// 逆アセンブルした1命令
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisasmLine {
+    pub address: u64,
+    pub bytes: Vec<u8>,
+    /// `mov rax, 1` 形式（Intel 構文）
+    pub text: String,
+}
+
+/// バイト列を先頭から逆アセンブルする（デコードできない位置で止まる）
+pub fn disassemble(code: &[u8], base_addr: u64) -> Result<Vec<DisasmLine>> {
+    let cs = Capstone::new()
+        .x86()
+        .mode(arch::x86::ArchMode::Mode64)
+        .syntax(arch::x86::ArchSyntax::Intel)
+        .build()
+        .map_err(|e| anyhow::anyhow!("Failed to create Capstone: {}", e))?;
+
+    let insns = cs
+        .disasm_all(code, base_addr)
+        .map_err(|e| anyhow::anyhow!("Failed to disassemble: {}", e))?;
+
+    Ok(insns
+        .as_ref()
+        .iter()
+        .map(|insn| {
+            let mnemonic = insn.mnemonic().unwrap_or("");
+            let text = match insn.op_str() {
+                Some(ops) if !ops.is_empty() => format!("{} {}", mnemonic, ops),
+                _ => mnemonic.to_string(),
+            };
+            DisasmLine { address: insn.address(), bytes: insn.bytes().to_vec(), text }
+        })
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -54,5 +91,9 @@ mod tests {
         let rets = find_ret_instructions(&code, 0x1000).unwrap();
         assert_eq!(rets.len(), 1);
         assert_eq!(rets[0], 0x1007);
+
+        let lines = disassemble(&code, 0x1000).unwrap();
+        let texts: Vec<(u64, &str)> = lines.iter().map(|l| (l.address, l.text.as_str())).collect();
+        assert_eq!(texts, vec![(0x1000, "mov rax, 1"), (0x1007, "ret")]);
     }
 }
@@ -0,0 +1,84 @@
+//! 自動表示式（display）の管理
+
+/// 自動表示式ID
+pub type DisplayId = usize;
+
+/// 停止のたびに評価・表示される式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayEntry {
+    pub id: DisplayId,
+    pub expr: String,
+}
+
+/// 自動表示式のリスト
+///
+/// 登録順に表示され、IDは削除後も再利用されません。
+#[derive(Debug)]
+pub struct DisplayList {
+    entries: Vec<DisplayEntry>,
+    next_id: DisplayId,
+}
+
+impl DisplayList {
+    /// 新しい自動表示式リストを作成する
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// 式を追加する
+    pub fn add(&mut self, expr: &str) -> DisplayId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.push(DisplayEntry {
+            id,
+            expr: expr.trim().to_string(),
+        });
+        id
+    }
+
+    /// 式を削除する（存在しなければ None）
+    pub fn remove(&mut self, id: DisplayId) -> Option<DisplayEntry> {
+        let pos = self.entries.iter().position(|e| e.id == id)?;
+        Some(self.entries.remove(pos))
+    }
+
+    /// すべての式を削除する
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// 登録済みの式を登録順に取得する
+    pub fn all(&self) -> &[DisplayEntry] {
+        &self.entries
+    }
+}
+
+impl Default for DisplayList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_remove_display() {
+        let mut list = DisplayList::new();
+        let a = list.add("counter");
+        let b = list.add(" task.state ");
+        assert_eq!(list.all().len(), 2);
+        assert_eq!(list.all()[1].expr, "task.state");
+
+        assert!(list.remove(a).is_some());
+        assert!(list.remove(a).is_none());
+        assert_eq!(list.all()[0].id, b);
+
+        // 削除後もIDは再利用しない
+        assert_eq!(list.add("x"), b + 1);
+    }
+}
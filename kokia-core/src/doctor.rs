@@ -0,0 +1,234 @@
+//! 実行環境とバイナリの自己診断（kokia doctor）
+//!
+//! デバッグを始めてから「ブレークポイントが効かない」「変数が見えない」と気づく前に、
+//! ptrace が使えるか、対象のバイナリにデバッグ情報や async fn のシンボルが残っているか、
+//! 最適化でそれらが消えていないかを調べ、直し方を添えて報告します。
+
+use crate::Result;
+use kokia_async::AsyncDetector;
+use kokia_dwarf::{BuildInfo, DwarfLoader, SymbolResolver};
+use kokia_target::diagnostics::PtraceScope;
+use kokia_target::PtraceEnvironment;
+use std::fmt;
+use std::path::Path;
+
+/// 診断結果の重さ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckStatus {
+    Ok,
+    /// 使えるが一部の機能が働かない
+    Warning,
+    /// このままではデバッグできない
+    Error,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Warning => "warn",
+            CheckStatus::Error => "FAIL",
+        })
+    }
+}
+
+/// 1項目の診断結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub summary: String,
+    /// 直し方（問題がなければ空）
+    pub advice: Vec<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, summary: impl Into<String>) -> Self {
+        Self { name, status: CheckStatus::Ok, summary: summary.into(), advice: Vec::new() }
+    }
+
+    fn warning(name: &'static str, summary: impl Into<String>, advice: &[&str]) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Warning,
+            summary: summary.into(),
+            advice: advice.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    fn error(name: &'static str, summary: impl Into<String>, advice: &[&str]) -> Self {
+        Self { status: CheckStatus::Error, ..Self::warning(name, summary, advice) }
+    }
+}
+
+/// 診断結果の一覧
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DoctorReport {
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    /// 実行環境と（指定されていれば）バイナリを調べる
+    pub fn run(binary: Option<&Path>) -> Self {
+        let mut checks = vec![ptrace_check(&PtraceEnvironment::detect(), kokia_target::probe_ptrace())];
+        if let Some(binary) = binary {
+            match binary_checks(binary) {
+                Ok(found) => checks.extend(found),
+                Err(e) => checks.push(Check::error(
+                    "binary",
+                    format!("cannot read {}: {}", binary.display(), e),
+                    &["check the path, and that the file is an ELF executable"],
+                )),
+            }
+        }
+        Self { checks }
+    }
+
+    /// 最も重い診断結果
+    pub fn worst(&self) -> CheckStatus {
+        self.checks.iter().map(|check| check.status).max().unwrap_or(CheckStatus::Ok)
+    }
+}
+
+/// ptrace が使えるか、他のプロセスにアタッチできるか
+fn ptrace_check(env: &PtraceEnvironment, probe: Result<()>) -> Check {
+    if let Err(e) = probe {
+        return Check::error(
+            "ptrace",
+            format!("{} ({})", e, env),
+            &[
+                "in Docker, run the container with --cap-add=SYS_PTRACE --security-opt seccomp=unconfined",
+                "under systemd or other sandboxes, make sure ptrace is not filtered by a seccomp policy",
+            ],
+        );
+    }
+
+    let privileged = env.is_root || env.has_cap_sys_ptrace == Some(true);
+    let attach_limited = match env.scope {
+        Some(PtraceScope::NoAttach) => true,
+        Some(PtraceScope::Restricted | PtraceScope::AdminOnly) => !privileged,
+        _ => false,
+    };
+    if attach_limited {
+        let hints = env.attach_hints();
+        let advice: Vec<&str> = hints.iter().map(String::as_str).collect();
+        return Check::warning("ptrace", format!("'kokia run' works, 'kokia attach' is restricted ({})", env), &advice);
+    }
+    Check::ok("ptrace", format!("run and attach both work ({})", env))
+}
+
+fn binary_checks(binary: &Path) -> Result<Vec<Check>> {
+    let loader = DwarfLoader::load(binary)?;
+    let info = BuildInfo::collect(&loader)?;
+    let detector = AsyncDetector::new();
+    let async_closures = SymbolResolver::new(&loader)?
+        .all_symbols()
+        .filter(|symbol| detector.is_user_async_closure(&symbol.demangled_name))
+        .count();
+    Ok(build_checks(&info, loader.is_pie(), async_closures))
+}
+
+/// DWARF から推定したビルド設定を診断する
+fn build_checks(info: &BuildInfo, is_pie: bool, async_closures: usize) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    if info.units == 0 {
+        checks.push(Check::error(
+            "debug info",
+            "no DWARF debug info",
+            &[
+                "build with debug info: debug = true in the Cargo profile (or RUSTFLAGS=\"-C debuginfo=2\")",
+                "make sure the binary is not stripped: strip = false in the profile, and no 'strip' after the build",
+            ],
+        ));
+        return checks;
+    }
+    if info.debuginfo_level() == "line-tables-only" {
+        checks.push(Check::warning(
+            "debug info",
+            "line tables only: locals, types and async task state cannot be shown",
+            &["use full debug info: debug = 2 (\"full\") in the Cargo profile"],
+        ));
+    } else {
+        checks.push(Check::ok("debug info", format!("{} compile units, {} variables outside std", info.units, info.variables)));
+    }
+
+    checks.push(Check::ok(
+        "PIE",
+        if is_pie { "position independent: addresses are relocated at load time" } else { "not PIE: addresses are absolute" },
+    ));
+
+    let versions: Vec<&str> = info.rustc_versions.keys().map(String::as_str).collect();
+    checks.push(match versions.as_slice() {
+        [] => Check::warning("rustc", "no rustc producer found (not a Rust binary?)", &[]),
+        [version] => Check::ok("rustc", format!("rustc {}", version)),
+        _ => Check::warning(
+            "rustc",
+            format!("built by several compilers: {}", versions.join(", ")),
+            &["rebuild everything with one toolchain (cargo clean) so that layouts and std agree"],
+        ),
+    });
+
+    if async_closures == 0 {
+        checks.push(Check::warning(
+            "async symbols",
+            "no async fn state machines ({{closure}} symbols) outside the runtime",
+            &[
+                "if the program has async fns, they were inlined away: build with opt-level = 0",
+                "async tracking ('async enable') will find nothing to instrument",
+            ],
+        ));
+    } else {
+        checks.push(Check::ok("async symbols", format!("{} async fn closures", async_closures)));
+    }
+
+    if info.looks_optimized() {
+        checks.push(Check::warning(
+            "optimization",
+            format!(
+                "looks optimized (opt-level >= 1): {} of {} variables use location lists, {} call sites",
+                info.location_list_variables, info.variables, info.call_sites
+            ),
+            &[
+                "variables may show as <optimized out> and async fns may be inlined",
+                "debug an unoptimized build: [profile.dev] opt-level = 0, or a custom profile with inherits = \"release\", opt-level = 0, debug = true",
+            ],
+        ));
+    } else {
+        checks.push(Check::ok("optimization", "not optimized (opt-level = 0)"));
+    }
+
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_checks() {
+        let mut info = BuildInfo { units: 10, rust_units: 4, variables: 50, ..Default::default() };
+        info.rustc_versions.insert("1.95.0 (59807616e 2026-04-14)".to_string(), 10);
+        let report = DoctorReport { checks: build_checks(&info, true, 3) };
+        assert_eq!(report.worst(), CheckStatus::Ok);
+
+        info.call_sites = 12;
+        info.rustc_versions.insert("1.80.0 (051478957 2024-07-21)".to_string(), 1);
+        let report = DoctorReport { checks: build_checks(&info, true, 0) };
+        let warned: Vec<&str> = report.checks.iter()
+            .filter(|check| check.status == CheckStatus::Warning)
+            .map(|check| check.name)
+            .collect();
+        assert_eq!(warned, vec!["rustc", "async symbols", "optimization"]);
+
+        let stripped = build_checks(&BuildInfo::default(), false, 0);
+        assert_eq!(stripped.len(), 1);
+        assert_eq!(stripped[0].status, CheckStatus::Error);
+
+        let env = PtraceEnvironment { scope: Some(PtraceScope::Restricted), has_cap_sys_ptrace: Some(false), is_root: false };
+        assert_eq!(ptrace_check(&env, Ok(())).status, CheckStatus::Warning);
+        assert_eq!(ptrace_check(&env, Err(anyhow::anyhow!("blocked"))).status, CheckStatus::Error);
+        let root = PtraceEnvironment { is_root: true, ..env };
+        assert_eq!(ptrace_check(&root, Ok(())).status, CheckStatus::Ok);
+    }
+}
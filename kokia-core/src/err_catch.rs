@@ -0,0 +1,181 @@
+//! Err を返す関数のキャッチポイント（catch err）
+//!
+//! 関数の各 ret 命令にブレークポイントを置き、戻り値が `Err` のときだけ止まります。どの variant が
+//! 返ったかは DWARF の戻り値型のレイアウトから discriminant を読んで判定します。16 バイト以下の
+//! 戻り値はレジスタから組み立て、それより大きい戻り値は rax が指す返却先のメモリから読みます。
+
+use crate::{BreakpointId, Result};
+use kokia_dwarf::{TypeLayout, VariantLayout};
+
+/// レジスタ（rax, rdx）で返される戻り値の大きさの上限
+pub const MAX_REGISTER_RETURN: u64 = 16;
+
+/// `catch err` で設定したキャッチポイント
+#[derive(Debug, Clone)]
+pub struct ErrCatchpoint {
+    /// 対象の関数（デマングル名）
+    pub function: String,
+    /// 戻り値の型のレイアウト
+    pub layout: TypeLayout,
+    /// 各 ret 命令に置いたブレークポイント
+    pub breakpoints: Vec<BreakpointId>,
+}
+
+/// `Err` を返そうとしている関数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrReturn {
+    pub function: String,
+    /// 戻り値の型名
+    pub return_type: String,
+    /// `Err` の中身の型名
+    pub payload_type: String,
+    /// 中身のアドレス（戻り値がメモリで返される場合のみ）
+    pub payload_address: Option<u64>,
+    /// 中身のバイト列
+    pub payload: Vec<u8>,
+}
+
+impl ErrCatchpoint {
+    /// 戻り値の型が `Err` variant を持つか確かめてキャッチポイントを作る
+    pub fn new(function: String, layout: TypeLayout) -> Result<Self> {
+        if !layout.variants.iter().any(|v| v.name == "Err") {
+            anyhow::bail!("{} does not return a Result (return type: {})", function, layout.name);
+        }
+        Ok(Self { function, layout, breakpoints: Vec::new() })
+    }
+
+    /// 戻り値の大きさ
+    pub fn return_size(&self) -> u64 {
+        self.layout.size.unwrap_or(0)
+    }
+
+    /// 戻り値が rax の指すメモリで返されるか
+    pub fn returned_in_memory(&self) -> bool {
+        self.return_size() > MAX_REGISTER_RETURN
+    }
+
+    /// rax・rdx で返された戻り値を、メモリ上と同じ並びのバイト列に組み立てる
+    ///
+    /// discriminant と1つのスカラーだけからなる enum（`Result<u32, u32>` など）はスカラーの組として
+    /// 1つ目を rax、2つ目を rdx で返します。それ以外はメモリ上の並びのまま rax・rdx に詰められます。
+    pub fn register_image(&self, rax: u64, rdx: u64) -> Vec<u8> {
+        let size = self.return_size() as usize;
+        let mut image: Vec<u8> = rax.to_le_bytes().into_iter().chain(rdx.to_le_bytes()).collect();
+        if let Some((discr, payload)) = self.scalar_pair() {
+            image = vec![0; size.max(payload.end)];
+            image[discr.clone()].copy_from_slice(&rax.to_le_bytes()[..discr.len()]);
+            image[payload.clone()].copy_from_slice(&rdx.to_le_bytes()[..payload.len()]);
+        }
+        image.truncate(size);
+        image
+    }
+
+    /// スカラーの組として返される場合の、discriminant と中身の範囲
+    fn scalar_pair(&self) -> Option<(std::ops::Range<usize>, std::ops::Range<usize>)> {
+        let discr = self.layout.discriminant.as_ref()?;
+        let discr_start = discr.offset? as usize;
+        let discr = discr_start..discr_start + discr.size? as usize;
+        let mut payload = None;
+        for variant in &self.layout.variants {
+            match variant.members.as_slice() {
+                [] => {}
+                // 中身がすべて同じ位置にある 8 バイト以下の値で、discriminant の後ろにある
+                [member] if member.size.is_some_and(|s| (1..=8).contains(&s)) => {
+                    let (_, range) = payload_range(variant);
+                    if discr.start != 0 || range.start < discr.end || payload.as_ref().is_some_and(|p| *p != range) {
+                        return None;
+                    }
+                    payload = Some(range);
+                }
+                _ => return None,
+            }
+        }
+        Some((discr, payload?))
+    }
+
+    /// 戻り値のバイト列が `Err` なら、その中身を取り出す
+    ///
+    /// `address` は戻り値がメモリで返される場合の返却先です。
+    pub fn check(&self, bytes: &[u8], address: Option<u64>) -> Option<ErrReturn> {
+        let variant = self.layout.active_variant(bytes).filter(|v| v.name == "Err")?;
+        let (payload_type, range) = payload_range(variant);
+        Some(ErrReturn {
+            function: self.function.clone(),
+            return_type: self.layout.name.clone(),
+            payload_type,
+            payload_address: address.map(|a| a + range.start as u64),
+            payload: bytes.get(range).map(<[u8]>::to_vec).unwrap_or_default(),
+        })
+    }
+}
+
+/// `Err(e)` の `e` の型名と、戻り値の中での範囲
+fn payload_range(variant: &VariantLayout) -> (String, std::ops::Range<usize>) {
+    match variant.members.first() {
+        Some(member) => {
+            let start = member.offset.unwrap_or(0) as usize;
+            let len = member.size.unwrap_or(0) as usize;
+            (member.type_name.clone(), start..start + len)
+        }
+        None => ("()".to_string(), 0..0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kokia_dwarf::{MemberLayout, TypeKind};
+
+    fn member(name: &str, offset: u64, size: u64, type_name: &str) -> MemberLayout {
+        MemberLayout {
+            name: name.to_string(),
+            offset: Some(offset),
+            size: Some(size),
+            type_name: type_name.to_string(),
+            layout: None,
+        }
+    }
+
+    fn variant(discriminant: u64, name: &str, payload: MemberLayout) -> VariantLayout {
+        VariantLayout {
+            discriminant: Some(discriminant),
+            name: name.to_string(),
+            members: vec![payload],
+            decl_file: None,
+            decl_line: None,
+        }
+    }
+
+    #[test]
+    fn test_err_catchpoint_check() {
+        // Result<u32, u32>: u32 の discriminant の後ろに値
+        let layout = TypeLayout {
+            name: "core::result::Result<u32, u32>".to_string(),
+            kind: TypeKind::Struct,
+            size: Some(8),
+            members: Vec::new(),
+            discriminant: Some(member("__0", 0, 4, "u32")),
+            variants: vec![
+                variant(0, "Ok", member("__0", 4, 4, "u32")),
+                variant(1, "Err", member("__0", 4, 4, "u32")),
+            ],
+            enumerators: Vec::new(),
+        };
+        let catchpoint = ErrCatchpoint::new("errs::small".to_string(), layout.clone()).unwrap();
+        assert!(!catchpoint.returned_in_memory());
+
+        // スカラーの組なので eax = discriminant、edx = 値
+        let bytes = catchpoint.register_image(0, 7);
+        assert_eq!(bytes.len(), 8);
+        assert!(catchpoint.check(&bytes, None).is_none());
+
+        let bytes = catchpoint.register_image(1, 7);
+        let hit = catchpoint.check(&bytes, None).unwrap();
+        assert_eq!(hit.payload_type, "u32");
+        assert_eq!(hit.payload, 7u32.to_le_bytes());
+        assert_eq!(catchpoint.check(&bytes, Some(0x1000)).unwrap().payload_address, Some(0x1004));
+
+        let not_result = TypeLayout { variants: Vec::new(), discriminant: None, ..layout };
+        assert!(ErrCatchpoint::new("errs::plain".to_string(), not_result).is_err());
+    }
+}
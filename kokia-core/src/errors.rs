@@ -3,6 +3,12 @@
 /// プロセスに接続されていない場合のエラーメッセージ
 pub const ERR_NOT_ATTACHED: &str = "Not attached to a process";
 
+/// デバッグ対象プロセスが終了済みの場合のエラーメッセージ
+pub const ERR_PROCESS_EXITED: &str = "No process: the program has exited";
+
+/// デバッグ対象プロセスが終了コードを残さずに消えた場合のエラーメッセージ
+pub const ERR_PROCESS_LOST: &str = "No process: the target is gone";
+
 /// DWARF情報がロードされていない場合のエラーメッセージ
 pub const ERR_DWARF_NOT_LOADED: &str = "DWARF information not loaded";
 
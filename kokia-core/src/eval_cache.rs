@@ -0,0 +1,94 @@
+//! 停止中の式の評価結果のキャッシュ
+//!
+//! `display` やログポイントは停止のたびに同じ式を評価し、そのたびに DWARF から変数を探し直します。
+//! 同じ停止・同じフレームの間は変数の場所と型が変わらないので、評価結果（アドレスと型）を
+//! (式, フレーム) ごとに覚えておきます。プロセスを再開したら（別のプロセスになったら）すべて捨てます。
+//! 値そのものは覚えないので、表示するときはその時点のメモリを読みます。ただしポインタを
+//! たどった式（`*p` や `v[0]`）の場所はメモリの中身で決まるので、メモリを書き換えたら捨てます。
+
+use crate::{EvaluationResult, Expression};
+use std::collections::HashMap;
+
+/// キャッシュが有効な停止（プロセス ID と再開した回数）
+pub type StopEpoch = (i32, u64);
+
+/// 評価したフレーム（PC と RBP）
+pub type FrameKey = (u64, u64);
+
+/// 式の評価結果のキャッシュ
+#[derive(Debug, Default)]
+pub struct EvalCache {
+    epoch: Option<StopEpoch>,
+    entries: HashMap<(Expression, FrameKey), EvaluationResult>,
+    hits: u64,
+    misses: u64,
+}
+
+impl EvalCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// キャッシュした評価結果を取得する（`epoch` が変わっていたら先に捨てる）
+    pub fn get(&mut self, epoch: StopEpoch, expr: &Expression, frame: FrameKey) -> Option<EvaluationResult> {
+        self.sync(epoch);
+        let result = self.entries.get(&(expr.clone(), frame)).cloned();
+        match result {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        result
+    }
+
+    /// 評価結果を覚える
+    pub fn insert(&mut self, epoch: StopEpoch, expr: &Expression, frame: FrameKey, result: EvaluationResult) {
+        self.sync(epoch);
+        self.entries.insert((expr.clone(), frame), result);
+    }
+
+    /// すべて捨てる（メモリやレジスタを書き換えたとき）
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// キャッシュに当たった回数と外れた回数
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+
+    fn sync(&mut self, epoch: StopEpoch) {
+        if self.epoch != Some(epoch) {
+            self.entries.clear();
+            self.epoch = Some(epoch);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(address: u64) -> EvaluationResult {
+        EvaluationResult { address, type_info: None, type_name: "u64".to_string() }
+    }
+
+    #[test]
+    fn test_eval_cache_invalidated_on_resume() {
+        let mut cache = EvalCache::new();
+        let expr = Expression::Variable("counter".to_string());
+        let frame = (0x1000, 0x7ff0);
+
+        assert!(cache.get((1, 0), &expr, frame).is_none());
+        cache.insert((1, 0), &expr, frame, result(0x7fe8));
+        assert_eq!(cache.get((1, 0), &expr, frame).unwrap().address, 0x7fe8);
+        // 別のフレームでは使わない
+        assert!(cache.get((1, 0), &expr, (0x2000, 0x7f00)).is_none());
+
+        // 再開したら捨てる
+        assert!(cache.get((1, 1), &expr, frame).is_none());
+        cache.insert((1, 1), &expr, frame, result(0x7fe0));
+        // 別のプロセスでも捨てる
+        assert!(cache.get((2, 1), &expr, frame).is_none());
+        assert_eq!(cache.stats(), (1, 4));
+    }
+}
@@ -2,11 +2,15 @@
 //!
 //! デバッガで使用する式を評価します（printコマンド等）
 
+use crate::convenience::{HistoryRef, Value};
 use crate::{Debugger, Result};
-use kokia_dwarf::{TypeInfo, VariableLocation, Variable};
+use kokia_dwarf::{MemoryReader, TypeInfo, VariableLocation, Variable};
+use kokia_target::Memory;
+use std::cell::{Ref, RefCell};
+use std::fmt;
 
 /// 式の抽象構文木
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Expression {
     /// 変数名: `x`
     Variable(String),
@@ -20,6 +24,82 @@ pub enum Expression {
         base: Box<Expression>,
         index: usize,
     },
+    /// 範囲を指定した部分スライス: `buf[0..16]`, `v[2..]`（終わりを省くと長さまで）
+    SliceAccess {
+        base: Box<Expression>,
+        start: usize,
+        end: Option<usize>,
+    },
+    /// 整数リテラル: `0x40`
+    Integer(u64),
+    /// レジスタかコンビニエンス変数: `$pc`, `$rsp`, `$base`
+    Register(String),
+    /// 値の履歴: `$1`, `$`, `$$`, `$$2`
+    History(HistoryRef),
+    /// メモリ参照（8バイト読み取り）: `*$rsp`
+    Deref(Box<Expression>),
+    /// 加減算: `$pc+0x12`, `symbol+0x40`
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expression>,
+        rhs: Box<Expression>,
+    },
+    /// 型キャスト: `x as *const Foo`, `(u32)x`
+    Cast {
+        expr: Box<Expression>,
+        target: TypeExpr,
+    },
+}
+
+/// キャスト先の型
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TypeExpr {
+    /// 名前で指定した型: `u32`, `tokio::runtime::task::core::Header`
+    Named(String),
+    /// ポインタ: `*const T`, `*mut T`, `&T`（どれも指す先のアドレスとして扱う）
+    Pointer(Box<TypeExpr>),
+}
+
+impl fmt::Display for TypeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeExpr::Named(name) => write!(f, "{}", name),
+            TypeExpr::Pointer(pointee) => write!(f, "*const {}", pointee),
+        }
+    }
+}
+
+/// 二項演算子
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+}
+
+impl Expression {
+    /// 停止中は結果が変わらない式か（`set $name` で変わる履歴・コンビニエンス変数を含まない）
+    pub fn is_cacheable(&self) -> bool {
+        match self {
+            Expression::Variable(_) | Expression::Integer(_) => true,
+            Expression::Register(_) | Expression::History(_) => false,
+            Expression::FieldAccess { base, .. }
+            | Expression::IndexAccess { base, .. }
+            | Expression::SliceAccess { base, .. } => base.is_cacheable(),
+            Expression::Deref(inner) | Expression::Cast { expr: inner, .. } => inner.is_cacheable(),
+            Expression::Binary { lhs, rhs, .. } => lhs.is_cacheable() && rhs.is_cacheable(),
+        }
+    }
+
+    /// アドレス計算式か（メモリ上の場所ではなく数値として評価される式か）
+    pub fn is_address_arithmetic(&self) -> bool {
+        matches!(
+            self,
+            Expression::Integer(_)
+                | Expression::Register(_)
+                | Expression::Binary { .. }
+                | Expression::Cast { target: TypeExpr::Pointer(_), .. }
+        )
+    }
 }
 
 /// 式の評価結果
@@ -36,42 +116,332 @@ pub struct EvaluationResult {
 /// 式評価器
 pub struct ExpressionEvaluator<'a> {
     debugger: &'a Debugger,
+    /// 参照した履歴の値の、記録した時点の場所とバイト列
+    recorded: RefCell<Vec<(u64, Vec<u8>)>>,
 }
 
 impl<'a> ExpressionEvaluator<'a> {
     /// 新しい式評価器を作成する
     pub fn new(debugger: &'a Debugger) -> Self {
-        Self { debugger }
+        Self { debugger, recorded: RefCell::new(Vec::new()) }
+    }
+
+    /// 値を読むメモリ（参照した履歴の値の場所は、記録した時点のバイト列を読む）
+    fn memory(&self) -> Option<RecordedMemory<'_>> {
+        Some(RecordedMemory { live: self.debugger.memory()?, recorded: self.recorded.borrow() })
     }
 
     /// 式を評価する
+    ///
+    /// 同じ停止・同じフレームで評価済みの式は、DWARF を探し直さずに前の結果を使います。
     pub fn evaluate(&self, expr: &Expression) -> Result<EvaluationResult> {
+        if !expr.is_cacheable() {
+            return self.evaluate_uncached(expr);
+        }
+        if let Some(result) = self.debugger.cached_evaluation(expr) {
+            return Ok(result);
+        }
+        let result = self.evaluate_uncached(expr)?;
+        self.debugger.cache_evaluation(expr, &result);
+        Ok(result)
+    }
+
+    fn evaluate_uncached(&self, expr: &Expression) -> Result<EvaluationResult> {
         match expr {
             Expression::Variable(name) => self.eval_variable(name),
             Expression::FieldAccess { base, field } => self.eval_field_access(base, field),
             Expression::IndexAccess { base, index } => self.eval_index_access(base, *index),
+            Expression::SliceAccess { base, start, end } => self.eval_slice_access(base, *start, *end),
+            Expression::Deref(inner) => {
+                if let Some(pointee) = self.eval_pointee(inner)? {
+                    return Ok(pointee);
+                }
+                // 型情報がないので 8 バイトの整数として扱う
+                Ok(EvaluationResult {
+                    address: self.evaluate_address(inner)?,
+                    type_info: Some(TypeInfo::Primitive { name: "u64".to_string(), size: 8 }),
+                    type_name: "u64".to_string(),
+                })
+            }
+            Expression::Cast { expr, target } => self.eval_cast(expr, target),
+            Expression::Register(_) | Expression::History(_) => match self.stored_value(expr)? {
+                Some(Value::Location { result, .. }) => Ok(result),
+                _ => Err(anyhow::anyhow!("Expression is a value, not a memory location")),
+            },
+            Expression::Integer(_) | Expression::Binary { .. } => {
+                Err(anyhow::anyhow!("Expression is a value, not a memory location"))
+            }
+        }
+    }
+
+    /// 評価結果の値をメモリから読んで表示用の文字列にする
+    ///
+    /// 型情報があればそれに従って整形し、なければ（または失敗したら）型名から整形します。
+    pub fn format_result(&self, result: &EvaluationResult) -> Result<String> {
+        use kokia_dwarf::{ContainerLimits, FormatOptions, ValueFormatter};
+
+        let memory = self.memory()
+            .ok_or_else(|| anyhow::anyhow!("Cannot read memory: process not running"))?;
+        // スライスは指定された範囲の要素をすべて表示する
+        let mut limits = ContainerLimits::default();
+        if let Some(TypeInfo::Array { length: Some(length), .. }) = &result.type_info {
+            limits.max_elements = limits.max_elements.max(*length as usize);
+        }
+        let formatter = ValueFormatter::with_limits(&memory, limits);
+        let formatted = result.type_info.as_ref()
+            .and_then(|type_info| formatter.format_with_type_info(result.address, type_info, FormatOptions::default()).ok())
+            .or_else(|| formatter.format_by_type(result.address, &result.type_name).ok())
+            .unwrap_or_else(|| "<error reading value>".to_string());
+        Ok(formatted)
+    }
+
+    /// 式を評価して表示用の文字列にする（`$pc+0x12` のような数値式は16進数）
+    pub fn evaluate_to_string(&self, expr: &Expression) -> Result<String> {
+        self.format_value(&self.evaluate_to_value(expr)?)
+    }
+
+    /// 式を評価して、履歴に残せる値にする
+    pub fn evaluate_to_value(&self, expr: &Expression) -> Result<Value> {
+        // 前の式で参照した履歴の値の場所は、この式ではいまのメモリを読む
+        self.recorded.borrow_mut().clear();
+        if let Some(value) = self.stored_value(expr)? {
+            return Ok(value);
+        }
+        if expr.is_address_arithmetic() {
+            return Ok(Value::Address(self.evaluate_address(expr)?));
+        }
+        // 整数型へのキャストは値を変換する
+        if let Expression::Cast { target: TypeExpr::Named(name), .. } = expr {
+            if integer_type(name).is_some() {
+                return Ok(Value::Integer { value: self.evaluate_value(expr)?, type_name: name.clone() });
+            }
+        }
+        let result = self.evaluate(expr)?;
+        let bytes = self.record_bytes(&result);
+        Ok(Value::Location { result, bytes })
+    }
+
+    /// 履歴に残す値の、いまのバイト列（大きすぎる・読めない部分は残さない）
+    fn record_bytes(&self, result: &EvaluationResult) -> Vec<u8> {
+        let size = match &result.type_info {
+            Some(type_info) => self.get_type_size(type_info),
+            None => scalar_size(&result.type_name).unwrap_or(0),
+        };
+        match self.memory() {
+            Some(memory) => memory.read_prefix(result.address as usize, size.min(MAX_RECORDED_BYTES) as usize),
+            None => Vec::new(),
+        }
+    }
+
+    /// 値を表示用の文字列にする（整数は10進数、場所は記録した時点のバイト列を整形する）
+    pub fn format_value(&self, value: &Value) -> Result<String> {
+        match value {
+            Value::Address(address) => Ok(format!("0x{:x}", address)),
+            Value::Integer { value, type_name } => Ok(match integer_type(type_name) {
+                Some((_, true)) => (*value as i64).to_string(),
+                _ => value.to_string(),
+            }),
+            Value::Location { result, bytes } => {
+                self.recorded.borrow_mut().push((result.address, bytes.clone()));
+                self.format_result(result)
+            }
+        }
+    }
+
+    /// 履歴・コンビニエンス変数に保存した値（`$name` が変数でなければ None）
+    fn stored_value(&self, expr: &Expression) -> Result<Option<Value>> {
+        let convenience = self.debugger.convenience();
+        let value = match expr {
+            Expression::History(reference) => Some(convenience.history(*reference)?.clone()),
+            Expression::Register(name) => convenience.variable(name).cloned(),
+            _ => None,
+        };
+        if let Some(Value::Location { result, bytes }) = &value {
+            self.recorded.borrow_mut().push((result.address, bytes.clone()));
+        }
+        Ok(value)
+    }
+
+    /// 式をアドレス（数値）として評価する
+    ///
+    /// `break`/`x` などアドレスを受け取る箇所で使用する。
+    /// 変数名はシンボル名として解決し、見つからなければローカル変数のアドレスとする。
+    pub fn evaluate_address(&self, expr: &Expression) -> Result<u64> {
+        match expr {
+            Expression::Integer(value) => Ok(*value),
+            Expression::Register(name) => match self.debugger.convenience().variable(name) {
+                Some(value) => Ok(value.address()),
+                None => self.debugger.read_register(name),
+            },
+            Expression::History(reference) => Ok(self.debugger.convenience().history(*reference)?.address()),
+            Expression::Deref(inner) => {
+                let addr = self.evaluate_address(inner)?;
+                let memory = self.memory()
+                    .ok_or_else(|| anyhow::anyhow!(crate::errors::ERR_NOT_ATTACHED))?;
+                memory.read_u64(addr as usize)
+            }
+            Expression::Binary { op, lhs, rhs } => {
+                let lhs = self.evaluate_address(lhs)?;
+                let rhs = self.evaluate_address(rhs)?;
+                Ok(match op {
+                    BinaryOp::Add => lhs.wrapping_add(rhs),
+                    BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                })
+            }
+            Expression::Variable(name) => self.debugger.symbol_address(name)
+                .or_else(|_| self.eval_variable(name).map(|r| r.address)),
+            Expression::FieldAccess { .. } | Expression::IndexAccess { .. } | Expression::SliceAccess { .. } => {
+                Ok(self.evaluate(expr)?.address)
+            }
+            // 構造体などへの読み替えは場所、整数やポインタへのキャストは値
+            Expression::Cast { target: TypeExpr::Named(name), .. } if primitive_size(name).is_none() => {
+                Ok(self.evaluate(expr)?.address)
+            }
+            Expression::Cast { .. } => self.evaluate_value(expr),
+        }
+    }
+
+    /// 式を数値として評価する
+    ///
+    /// アドレス計算式はそのまま、変数などの場所はそこにある整数・ポインタの値を読みます。
+    fn evaluate_value(&self, expr: &Expression) -> Result<u64> {
+        match expr {
+            Expression::Cast { expr: inner, target } => {
+                let value = self.evaluate_value(inner)?;
+                match target {
+                    TypeExpr::Pointer(_) => Ok(value),
+                    TypeExpr::Named(name) => cast_integer(value, name)
+                        .ok_or_else(|| anyhow::anyhow!("Cannot convert a value to non-integer type {}", name)),
+                }
+            }
+            Expression::Register(_) | Expression::History(_) => match self.stored_value(expr)? {
+                Some(Value::Location { result, .. }) => self.read_scalar(&result),
+                Some(value) => Ok(value.address()),
+                None => self.evaluate_address(expr),
+            },
+            e if e.is_address_arithmetic() => self.evaluate_address(e),
+            _ => {
+                let result = self.evaluate(expr)?;
+                self.read_scalar(&result)
+            }
+        }
+    }
+
+    /// 場所にある整数・ポインタの値を読む（符号付き整数は符号拡張する）
+    fn read_scalar(&self, result: &EvaluationResult) -> Result<u64> {
+        let size = match &result.type_info {
+            Some(type_info) => self.get_type_size(type_info),
+            None => scalar_size(&result.type_name).unwrap_or(0),
+        };
+        if !(1..=8).contains(&size) {
+            return Err(anyhow::anyhow!("Cannot use a value of type {} as an integer", result.type_name));
+        }
+        let memory = self.memory()
+            .ok_or_else(|| anyhow::anyhow!(crate::errors::ERR_NOT_ATTACHED))?;
+        let bytes = memory.read(result.address as usize, size as usize)?;
+        let raw = bytes.iter().rev().fold(0u64, |value, &byte| (value << 8) | byte as u64);
+        Ok(cast_integer(raw, &result.type_name).unwrap_or(raw))
+    }
+
+    /// キャストを評価する（値の場所はそのままで、型だけを読み替える）
+    fn eval_cast(&self, expr: &Expression, target: &TypeExpr) -> Result<EvaluationResult> {
+        if matches!(target, TypeExpr::Pointer(_)) || expr.is_address_arithmetic() {
+            return Err(anyhow::anyhow!(
+                "Expression is a value, not a memory location (dereference a pointer instead: *(<expr> as *const {}))",
+                target
+            ));
+        }
+        let base = self.evaluate(expr)?;
+        Ok(EvaluationResult {
+            address: base.address,
+            type_info: Some(self.resolve_type(target)?),
+            type_name: target.to_string(),
+        })
+    }
+
+    /// ポインタへのキャストが指す先を評価する
+    fn eval_pointer_cast(&self, expr: &Expression, pointee: &TypeExpr) -> Result<EvaluationResult> {
+        Ok(EvaluationResult {
+            address: self.evaluate_value(expr)?,
+            type_info: Some(self.resolve_type(pointee)?),
+            type_name: pointee.to_string(),
+        })
+    }
+
+    /// ポインタの式が指す先を評価する（ポインタへのキャストでもポインタ型の値でもなければ None）
+    fn eval_pointee(&self, expr: &Expression) -> Result<Option<EvaluationResult>> {
+        if let Expression::Cast { target: TypeExpr::Pointer(pointee), .. } = expr {
+            return self.eval_pointer_cast(expr, pointee).map(Some);
+        }
+        if expr.is_address_arithmetic() {
+            return Ok(None);
+        }
+        match self.evaluate(expr) {
+            Ok(result) => self.follow_pointer(&result),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// ポインタ・参照型の値を1段たどる（ポインタ型でなければ None）
+    fn follow_pointer(&self, result: &EvaluationResult) -> Result<Option<EvaluationResult>> {
+        let pointee = match &result.type_info {
+            Some(TypeInfo::Pointer { pointee_type: Some(pointee), .. })
+            | Some(TypeInfo::Reference { referent_type: Some(pointee), .. }) => pointee,
+            _ => return Ok(None),
+        };
+        let memory = self.memory()
+            .ok_or_else(|| anyhow::anyhow!(crate::errors::ERR_NOT_ATTACHED))?;
+        Ok(Some(EvaluationResult {
+            address: memory.read_u64(result.address as usize)?,
+            type_info: Some((**pointee).clone()),
+            type_name: self.type_name(pointee),
+        }))
+    }
+
+    /// キャスト先の型を型情報にする（基本型は DWARF を引かずに作る）
+    fn resolve_type(&self, target: &TypeExpr) -> Result<TypeInfo> {
+        match target {
+            TypeExpr::Pointer(pointee) => Ok(TypeInfo::Pointer {
+                pointee_type: Some(Box::new(self.resolve_type(pointee)?)),
+                size: 8,
+            }),
+            TypeExpr::Named(name) => match primitive_size(name) {
+                Some(size) => Ok(TypeInfo::Primitive { name: name.clone(), size }),
+                None => self.debugger.type_info(name)?
+                    .ok_or_else(|| anyhow::anyhow!("No type named '{}'", name)),
+            },
         }
     }
 
     /// 変数を評価する
     fn eval_variable(&self, name: &str) -> Result<EvaluationResult> {
         // ローカル変数を取得
-        let variables = self.debugger.get_local_variables()?;
+        let variables = self.debugger.get_local_variables();
 
         // 変数名で検索
-        let var = variables
-            .iter()
-            .find(|v| v.name == name)
-            .ok_or_else(|| anyhow::anyhow!("Variable '{}' not found", name))?;
+        if let Some(var) = variables.as_ref().ok().and_then(|vars| vars.iter().find(|v| v.name == name)) {
+            // 変数のアドレスを計算
+            let address = self.get_variable_address(var)?;
 
-        // 変数のアドレスを計算
-        let address = self.get_variable_address(var)?;
+            return Ok(EvaluationResult {
+                address,
+                type_info: None, // TODO: TypeInfoを取得する完全な実装
+                type_name: var.type_name.clone(),
+            });
+        }
 
-        Ok(EvaluationResult {
-            address,
-            type_info: None, // TODO: TypeInfoを取得する完全な実装
-            type_name: var.type_name.clone(),
-        })
+        // ローカル変数になければ static と thread_local! を探す（TLS は選択中のスレッドの値）
+        match self.debugger.find_static_variable(name)? {
+            Some((address, var)) => Ok(EvaluationResult {
+                address,
+                type_info: self.debugger.static_type_info(&var)?,
+                type_name: var.type_name,
+            }),
+            None => {
+                variables?;
+                Err(anyhow::anyhow!("Variable '{}' not found", name))
+            }
+        }
     }
 
     /// 変数のアドレスを取得する
@@ -95,6 +465,9 @@ impl<'a> ExpressionEvaluator<'a> {
             VariableLocation::Register(reg) => {
                 Err(anyhow::anyhow!("Cannot get address of register variable (reg{})", reg))
             }
+            VariableLocation::Computed => {
+                Err(anyhow::anyhow!("Variable '{}' is a computed value and has no address", var.name))
+            }
             VariableLocation::OptimizedOut => {
                 Err(anyhow::anyhow!("Variable '{}' is optimized out", var.name))
             }
@@ -105,10 +478,23 @@ impl<'a> ExpressionEvaluator<'a> {
     }
 
     /// フィールドアクセスを評価する
+    ///
+    /// ポインタ型の値は指す先のフィールドを引き、`a.b.c` のように続くフィールドも順にたどる。
     fn eval_field_access(&self, base: &Expression, field: &str) -> Result<EvaluationResult> {
         // まずベースの式を評価
-        let base_result = self.evaluate(base)?;
+        let mut result = match base {
+            Expression::Cast { target: TypeExpr::Pointer(pointee), .. } => self.eval_pointer_cast(base, pointee)?,
+            _ => self.evaluate(base)?,
+        };
+        for name in field.split('.') {
+            result = self.follow_pointer(&result)?.unwrap_or(result);
+            result = self.field_of(result, name)?;
+        }
+        Ok(result)
+    }
 
+    /// 構造体の値からフィールドを1つ取り出す
+    fn field_of(&self, base_result: EvaluationResult, field: &str) -> Result<EvaluationResult> {
         // TypeInfoがない場合はエラー
         let type_info = base_result
             .type_info
@@ -147,6 +533,16 @@ impl<'a> ExpressionEvaluator<'a> {
 
     /// 配列インデックスアクセスを評価する
     fn eval_index_access(&self, base: &Expression, index: usize) -> Result<EvaluationResult> {
+        // ポインタへのキャストは C と同じく、指す先から並ぶ要素として添字を付ける
+        if let Expression::Cast { target: TypeExpr::Pointer(pointee), .. } = base {
+            let element = self.eval_pointer_cast(base, pointee)?;
+            let size = element.type_info.as_ref().map(|t| self.get_type_size(t)).unwrap_or(0);
+            if size == 0 {
+                return Err(anyhow::anyhow!("Unknown size of {}", pointee));
+            }
+            return Ok(EvaluationResult { address: element.address + index as u64 * size, ..element });
+        }
+
         // まずベースの式を評価
         let base_result = self.evaluate(base)?;
 
@@ -194,6 +590,73 @@ impl<'a> ExpressionEvaluator<'a> {
         }
     }
 
+    /// 部分スライスを評価する（要素の型で `[T; n]` の配列として扱う）
+    fn eval_slice_access(&self, base: &Expression, start: usize, end: Option<usize>) -> Result<EvaluationResult> {
+        let (address, element, length) = self.slice_source(base)?;
+        let end = match (end, length) {
+            (Some(end), _) => end,
+            (None, Some(length)) => length as usize,
+            (None, None) => return Err(anyhow::anyhow!("Length is unknown; give the end of the range, e.g. [{}..{}]", start, start + 16)),
+        };
+        if let Some(length) = length {
+            if start.max(end) as u64 > length {
+                return Err(anyhow::anyhow!("Range {}..{} out of bounds (length: {})", start, end, length));
+            }
+        }
+        if start > end {
+            return Err(anyhow::anyhow!("Invalid range {}..{}", start, end));
+        }
+        let count = end - start;
+        if count > MAX_SLICE_ELEMENTS {
+            return Err(anyhow::anyhow!("Range {}..{} is too long (at most {} elements)", start, end, MAX_SLICE_ELEMENTS));
+        }
+        let element_size = self.get_type_size(&element);
+        if element_size == 0 {
+            return Err(anyhow::anyhow!("Unknown element size of {}", self.type_name(&element)));
+        }
+
+        Ok(EvaluationResult {
+            address: address + start as u64 * element_size,
+            type_name: format!("[{}; {}]", self.type_name(&element), count),
+            type_info: Some(TypeInfo::Array { element_type: Some(Box::new(element)), length: Some(count as u64) }),
+        })
+    }
+
+    /// スライスする元の先頭アドレス・要素の型・長さ（分かれば）
+    ///
+    /// ポインタへのキャストとポインタ型の値は指す先から、配列はその場から、Vec と String は
+    /// ヒープ上のバッファから要素を並べます。
+    fn slice_source(&self, base: &Expression) -> Result<(u64, TypeInfo, Option<u64>)> {
+        if let Expression::Cast { target: TypeExpr::Pointer(pointee), .. } = base {
+            return Ok((self.evaluate_value(base)?, self.resolve_type(pointee)?, None));
+        }
+
+        let result = self.evaluate(base)?;
+        if let Some(TypeInfo::Array { element_type: Some(element), length }) = &result.type_info {
+            return Ok((result.address, (**element).clone(), *length));
+        }
+        if let Some(pointee) = self.follow_pointer(&result)? {
+            let type_info = pointee.type_info.unwrap_or(TypeInfo::Unknown);
+            return Ok((pointee.address, type_info, None));
+        }
+
+        let type_name = match &result.type_info {
+            Some(TypeInfo::Struct { name, .. }) => name.as_str(),
+            _ => result.type_name.as_str(),
+        };
+        let element = match vec_element_type(type_name) {
+            Some(element) => self.resolve_type(&parse_type(element)?)?,
+            None if type_name == "String" || type_name.ends_with("::string::String") => {
+                TypeInfo::Primitive { name: "u8".to_string(), size: 1 }
+            }
+            None => return Err(anyhow::anyhow!("Cannot slice a value of type {}", result.type_name)),
+        };
+        let memory = self.memory()
+            .ok_or_else(|| anyhow::anyhow!(crate::errors::ERR_NOT_ATTACHED))?;
+        let (ptr, len, _) = kokia_dwarf::ValueFormatter::new(&memory).read_raw_vec(result.address)?;
+        Ok((ptr, element, Some(len)))
+    }
+
     /// 型名を取得する
     fn type_name(&self, type_info: &TypeInfo) -> String {
         match type_info {
@@ -229,12 +692,286 @@ impl<'a> ExpressionEvaluator<'a> {
     }
 }
 
+/// 一度にスライスできる要素数の上限
+const MAX_SLICE_ELEMENTS: usize = 4096;
+
+/// 履歴に残す値のバイト列の上限（超えた分はその時点のメモリを読む）
+const MAX_RECORDED_BYTES: u64 = 1 << 20;
+
+/// 参照した履歴の値の場所を、記録した時点のバイト列で見せるメモリ
+struct RecordedMemory<'a> {
+    live: &'a Memory,
+    recorded: Ref<'a, Vec<(u64, Vec<u8>)>>,
+}
+
+impl RecordedMemory<'_> {
+    /// `addr` から読んだ `bytes` のうち、記録した値と重なる部分を記録した時点のバイト列にする
+    fn overlay(&self, addr: u64, bytes: &mut [u8]) {
+        let end = addr + bytes.len() as u64;
+        for (start, recorded) in self.recorded.iter() {
+            let (from, to) = (addr.max(*start), end.min(start + recorded.len() as u64));
+            if from < to {
+                bytes[(from - addr) as usize..(to - addr) as usize]
+                    .copy_from_slice(&recorded[(from - start) as usize..(to - start) as usize]);
+            }
+        }
+    }
+
+    fn read_array<const N: usize>(&self, addr: usize) -> Result<[u8; N]> {
+        self.read(addr, N)?.try_into().map_err(|_| anyhow::anyhow!("Short read of {} bytes at 0x{:x}", N, addr))
+    }
+}
+
+impl MemoryReader for RecordedMemory<'_> {
+    fn read_u8(&self, addr: usize) -> Result<u8> {
+        Ok(self.read_array::<1>(addr)?[0])
+    }
+
+    fn read_u16(&self, addr: usize) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.read_array(addr)?))
+    }
+
+    fn read_u32(&self, addr: usize) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array(addr)?))
+    }
+
+    fn read_u64(&self, addr: usize) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.read_array(addr)?))
+    }
+
+    fn read(&self, addr: usize, size: usize) -> Result<Vec<u8>> {
+        let (start, end) = (addr as u64, (addr + size) as u64);
+        let mut bytes = match self.live.read(addr, size) {
+            Ok(bytes) => bytes,
+            // 記録した値だけで埋まるなら、いまは読めない場所（抜けたフレームなど）でもよい
+            Err(_) if self.recorded.iter().any(|(at, recorded)| *at <= start && end <= at + recorded.len() as u64) => vec![0; size],
+            Err(e) => return Err(e),
+        };
+        self.overlay(start, &mut bytes);
+        Ok(bytes)
+    }
+
+    fn is_mapped(&self, addr: usize) -> bool {
+        MemoryReader::is_mapped(self.live, addr)
+    }
+
+    fn read_prefix(&self, addr: usize, size: usize) -> Vec<u8> {
+        let mut bytes = MemoryReader::read_prefix(self.live, addr, size);
+        self.overlay(addr as u64, &mut bytes);
+        bytes
+    }
+}
+
+/// `Vec<T, A>` の型名から要素の型 `T` を取り出す
+fn vec_element_type(type_name: &str) -> Option<&str> {
+    let start = type_name.find("Vec<")?;
+    if start != 0 && !type_name[..start].ends_with("::vec::") {
+        return None;
+    }
+    let arguments = type_name[start + 4..].strip_suffix('>')?;
+    let end = top_level_bytes(arguments).find(|&(_, c)| c == b',').map_or(arguments.len(), |(i, _)| i);
+    Some(arguments[..end].trim())
+}
+
+/// `[..]` の中の `start..end` をパースする（どちらも省略でき、`..=end` も受け付ける）
+fn parse_range(input: &str) -> Result<Option<(usize, Option<usize>)>> {
+    let Some((start, end)) = input.split_once("..") else {
+        return Ok(None);
+    };
+    let number = |s: &str| s.trim().parse::<usize>().map_err(|_| anyhow::anyhow!("Invalid range bound: {}", s));
+    let start = if start.trim().is_empty() { 0 } else { number(start)? };
+    let end = match end.strip_prefix('=') {
+        Some(end) => Some(number(end)?.saturating_add(1)),
+        None if end.trim().is_empty() => None,
+        None => Some(number(end)?),
+    };
+    Ok(Some((start, end)))
+}
+
+/// 基本型の整数型のサイズと符号の有無
+fn integer_type(name: &str) -> Option<(u64, bool)> {
+    Some(match name {
+        "u8" => (1, false),
+        "u16" => (2, false),
+        "u32" => (4, false),
+        "u64" | "usize" => (8, false),
+        "i8" => (1, true),
+        "i16" => (2, true),
+        "i32" => (4, true),
+        "i64" | "isize" => (8, true),
+        _ => return None,
+    })
+}
+
+/// DWARF を引かずに扱える基本型のサイズ
+fn primitive_size(name: &str) -> Option<u64> {
+    match name {
+        "bool" => Some(1),
+        "char" | "f32" => Some(4),
+        "f64" => Some(8),
+        _ => integer_type(name).map(|(size, _)| size),
+    }
+}
+
+/// 型名から整数・ポインタとして読むサイズを求める
+fn scalar_size(type_name: &str) -> Option<u64> {
+    if type_name.starts_with(['*', '&']) {
+        return Some(8);
+    }
+    primitive_size(type_name)
+}
+
+/// 値を整数型に変換する（切り詰めて、符号付きなら符号拡張する。整数型でなければ None）
+fn cast_integer(value: u64, name: &str) -> Option<u64> {
+    let (size, signed) = integer_type(name)?;
+    let bits = size * 8;
+    if bits == 64 {
+        return Some(value);
+    }
+    let mask = (1u64 << bits) - 1;
+    let value = value & mask;
+    Some(if signed && value >> (bits - 1) == 1 { value | !mask } else { value })
+}
+
+/// トップレベル（括弧・山括弧の外）にある文字を位置とともに列挙する
+///
+/// 開き括弧はトップレベルにあれば含め、`->` の `>` は閉じ括弧とみなさない。
+fn top_level_bytes(input: &str) -> impl Iterator<Item = (usize, u8)> + '_ {
+    let bytes = input.as_bytes();
+    let mut depth = 0i32;
+    bytes.iter().enumerate().filter_map(move |(i, &c)| match c {
+        b'(' | b'[' | b'<' => {
+            depth += 1;
+            (depth == 1).then_some((i, c))
+        }
+        b')' | b']' => {
+            depth -= 1;
+            None
+        }
+        b'>' if i == 0 || bytes[i - 1] != b'-' => {
+            depth -= 1;
+            None
+        }
+        _ => (depth == 0).then_some((i, c)),
+    })
+}
+
+/// トップレベル（括弧・山括弧の外）にある最後の `+`/`-` の位置を探す
+///
+/// 先頭の符号や `->`、シンボル名中の `<...>` 内は無視する。
+fn find_binary_operator(input: &str) -> Option<(usize, BinaryOp)> {
+    let bytes = input.as_bytes();
+    top_level_bytes(input)
+        .filter(|&(i, c)| match c {
+            b'+' => i > 0,
+            b'-' => i > 0 && bytes.get(i + 1) != Some(&b'>'),
+            _ => false,
+        })
+        .last()
+        .map(|(i, c)| (i, if c == b'+' { BinaryOp::Add } else { BinaryOp::Sub }))
+}
+
+/// トップレベルにある最後の ` as ` の位置を探す
+fn find_cast_keyword(input: &str) -> Option<usize> {
+    let bytes = input.as_bytes();
+    top_level_bytes(input)
+        .map(|(i, _)| i)
+        .filter(|&i| bytes[i..].starts_with(b" as "))
+        .last()
+}
+
+/// 先頭の `(` に対応する `)` の位置
+fn closing_paren(input: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in input.bytes().enumerate() {
+        match c {
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// キャスト先の型をパースする
+fn parse_type(input: &str) -> Result<TypeExpr> {
+    let input = input.trim();
+    for prefix in ["*const ", "*mut ", "&mut ", "&"] {
+        if let Some(pointee) = input.strip_prefix(prefix) {
+            return Ok(TypeExpr::Pointer(Box::new(parse_type(pointee)?)));
+        }
+    }
+    if input.is_empty() {
+        return Err(anyhow::anyhow!("Missing type in cast"));
+    }
+    Ok(TypeExpr::Named(input.to_string()))
+}
+
 /// 式をパースする簡易パーサー
 pub fn parse_expression(input: &str) -> Result<Expression> {
     let input = input.trim();
 
+    if input.is_empty() {
+        return Err(anyhow::anyhow!("Empty expression"));
+    }
+
+    // `a+b` / `a-b` -> Binary（左結合なので最後の演算子で分割）
+    if let Some((pos, op)) = find_binary_operator(input) {
+        let lhs = Box::new(parse_expression(&input[..pos])?);
+        let rhs = Box::new(parse_expression(&input[pos + 1..])?);
+        return Ok(Expression::Binary { op, lhs, rhs });
+    }
+
+    // `expr as Type` -> Cast（左結合なので最後の `as` で分割）
+    if let Some(pos) = find_cast_keyword(input) {
+        let expr = Box::new(parse_expression(&input[..pos])?);
+        return Ok(Expression::Cast { expr, target: parse_type(&input[pos + 4..])? });
+    }
+
+    // `*expr` -> Deref
+    if let Some(inner) = input.strip_prefix('*') {
+        return Ok(Expression::Deref(Box::new(parse_expression(inner)?)));
+    }
+
+    // `$`, `$$`, `$$n`, `$n` -> History、`$name` -> Register（コンビニエンス変数を含む）
+    // `$1.field` や `$buf[0..4]` は後のフィールド・要素アクセスで分ける
+    if let Some(name) = input.strip_prefix('$').filter(|name| !name.contains(['.', '['])) {
+        let number = |n: &str| n.parse::<usize>().map_err(|_| anyhow::anyhow!("Invalid history reference: {}", input));
+        return Ok(match name.strip_prefix('$') {
+            Some("") => Expression::History(HistoryRef::Relative(1)),
+            Some(back) => Expression::History(HistoryRef::Relative(number(back)?)),
+            None if name.is_empty() => Expression::History(HistoryRef::Relative(0)),
+            None if name.starts_with(|c: char| c.is_ascii_digit()) => Expression::History(HistoryRef::Absolute(number(name)?)),
+            None => Expression::Register(name.to_string()),
+        });
+    }
+
+    // `(Type)expr` -> Cast、`(expr)` は括弧を外す（後ろに `.`/`[` が続けばフィールド・要素アクセス）
+    if input.starts_with('(') {
+        let close = closing_paren(input).ok_or_else(|| anyhow::anyhow!("Missing closing parenthesis ')'"))?;
+        let inner = &input[1..close];
+        let rest = input[close + 1..].trim_start();
+        if rest.is_empty() {
+            return parse_expression(inner);
+        }
+        if !rest.starts_with(['.', '[']) {
+            let expr = Box::new(parse_expression(rest)?);
+            return Ok(Expression::Cast { expr, target: parse_type(inner)? });
+        }
+    }
+
+    // 数字で始まる場合 -> Integer
+    if input.starts_with(|c: char| c.is_ascii_digit()) {
+        return crate::parse::parse_address(input).map(Expression::Integer);
+    }
+
     // `.`を含む場合 -> FieldAccess
-    if let Some(dot_pos) = input.find('.') {
+    if let Some((dot_pos, _)) = top_level_bytes(input).find(|&(_, c)| c == b'.') {
         let base_str = &input[..dot_pos];
         let field_str = &input[dot_pos + 1..];
 
@@ -248,7 +985,7 @@ pub fn parse_expression(input: &str) -> Result<Expression> {
     }
 
     // `[`を含む場合 -> IndexAccess
-    if let Some(bracket_pos) = input.find('[') {
+    if let Some((bracket_pos, _)) = top_level_bytes(input).find(|&(_, c)| c == b'[') {
         if !input.ends_with(']') {
             return Err(anyhow::anyhow!("Missing closing bracket ']'"));
         }
@@ -256,6 +993,12 @@ pub fn parse_expression(input: &str) -> Result<Expression> {
         let base_str = &input[..bracket_pos];
         let index_str = &input[bracket_pos + 1..input.len() - 1];
 
+        // `start..end` -> SliceAccess
+        if let Some((start, end)) = parse_range(index_str)? {
+            let base = Box::new(parse_expression(base_str)?);
+            return Ok(Expression::SliceAccess { base, start, end });
+        }
+
         // インデックスをパース
         let index = index_str
             .parse::<usize>()
@@ -305,6 +1048,142 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_address_expressions() {
+        assert_eq!(
+            parse_expression("$pc+0x12").unwrap(),
+            Expression::Binary {
+                op: BinaryOp::Add,
+                lhs: Box::new(Expression::Register("pc".to_string())),
+                rhs: Box::new(Expression::Integer(0x12)),
+            }
+        );
+        assert_eq!(
+            parse_expression("*$rsp").unwrap(),
+            Expression::Deref(Box::new(Expression::Register("rsp".to_string())))
+        );
+        assert_eq!(
+            parse_expression("simple_async::main+0x40").unwrap(),
+            Expression::Binary {
+                op: BinaryOp::Add,
+                lhs: Box::new(Expression::Variable("simple_async::main".to_string())),
+                rhs: Box::new(Expression::Integer(0x40)),
+            }
+        );
+        // 左結合: (a - 1) + 2
+        match parse_expression("a-1+2").unwrap() {
+            Expression::Binary { op: BinaryOp::Add, lhs, .. } => {
+                assert!(matches!(*lhs, Expression::Binary { op: BinaryOp::Sub, .. }));
+            }
+            e => panic!("Unexpected expression: {:?}", e),
+        }
+        // シンボル名中の `<...>` や `->` では分割しない
+        assert_eq!(
+            parse_expression("<fn() -> T>::call").unwrap(),
+            Expression::Variable("<fn() -> T>::call".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_casts() {
+        let var = |name: &str| Box::new(Expression::Variable(name.to_string()));
+        let pointer = |name: &str| TypeExpr::Pointer(Box::new(TypeExpr::Named(name.to_string())));
+
+        assert_eq!(
+            parse_expression("(u32)x").unwrap(),
+            Expression::Cast { expr: var("x"), target: TypeExpr::Named("u32".to_string()) }
+        );
+        assert_eq!(
+            parse_expression("data as *const app::State").unwrap(),
+            Expression::Cast { expr: var("data"), target: pointer("app::State") }
+        );
+        // 左結合: (x as u64) as *mut Foo
+        assert_eq!(
+            parse_expression("x as u64 as *mut Foo").unwrap(),
+            Expression::Cast {
+                expr: Box::new(Expression::Cast { expr: var("x"), target: TypeExpr::Named("u64".to_string()) }),
+                target: pointer("Foo"),
+            }
+        );
+        // ジェネリクス中の `as` では分割しない
+        assert_eq!(
+            parse_expression("<T as Trait>::f").unwrap(),
+            Expression::Variable("<T as Trait>::f".to_string())
+        );
+
+        let cast = Expression::Cast { expr: var("data"), target: pointer("Waker<u8>") };
+        assert_eq!(parse_expression("*(data as &Waker<u8>)").unwrap(), Expression::Deref(Box::new(cast.clone())));
+        assert_eq!(
+            parse_expression("(data as *const Waker<u8>).vtable").unwrap(),
+            Expression::FieldAccess { base: Box::new(cast.clone()), field: "vtable".to_string() }
+        );
+        assert_eq!(
+            parse_expression("(*const Waker<u8>)data[2]").unwrap(),
+            Expression::Cast {
+                expr: Box::new(Expression::IndexAccess { base: var("data"), index: 2 }),
+                target: pointer("Waker<u8>"),
+            }
+        );
+        assert_eq!(
+            parse_expression("(data as *const Waker<u8>)[2]").unwrap(),
+            Expression::IndexAccess { base: Box::new(cast), index: 2 }
+        );
+        assert_eq!(parse_expression("((x))").unwrap(), Expression::Variable("x".to_string()));
+        assert!(parse_expression("x as &").is_err());
+        assert!(parse_expression("(u32 x").is_err());
+
+        assert!(parse_expression("$rdi as *const u8").unwrap().is_address_arithmetic());
+        assert!(!parse_expression("x as u8").unwrap().is_address_arithmetic());
+        assert_eq!(cast_integer(0x1ff, "u8"), Some(0xff));
+        assert_eq!(cast_integer(0xff, "i8"), Some(u64::MAX));
+        assert_eq!(cast_integer(0xffff_ffff, "i64"), Some(0xffff_ffff));
+        assert_eq!(cast_integer(1, "f32"), None);
+    }
+
+    #[test]
+    fn test_parse_history() {
+        assert_eq!(parse_expression("$").unwrap(), Expression::History(HistoryRef::Relative(0)));
+        assert_eq!(parse_expression("$$").unwrap(), Expression::History(HistoryRef::Relative(1)));
+        assert_eq!(parse_expression("$$3").unwrap(), Expression::History(HistoryRef::Relative(3)));
+        assert_eq!(parse_expression("$12").unwrap(), Expression::History(HistoryRef::Absolute(12)));
+        assert_eq!(parse_expression("$base").unwrap(), Expression::Register("base".to_string()));
+        assert_eq!(
+            parse_expression("$1.len").unwrap(),
+            Expression::FieldAccess { base: Box::new(Expression::History(HistoryRef::Absolute(1))), field: "len".to_string() }
+        );
+        assert!(matches!(parse_expression("$+8").unwrap(), Expression::Binary { op: BinaryOp::Add, .. }));
+        assert!(parse_expression("$$x").is_err());
+    }
+
+    #[test]
+    fn test_parse_slices() {
+        let slice = |input: &str| match parse_expression(input).unwrap() {
+            Expression::SliceAccess { start, end, .. } => (start, end),
+            e => panic!("Expected SliceAccess: {:?}", e),
+        };
+        assert_eq!(slice("buf[0..16]"), (0, Some(16)));
+        assert_eq!(slice("buf[4..]"), (4, None));
+        assert_eq!(slice("buf[..8]"), (0, Some(8)));
+        assert_eq!(slice("buf[2..=3]"), (2, Some(4)));
+        assert_eq!(
+            parse_expression("(data as *const u32)[1..3]").unwrap(),
+            Expression::SliceAccess {
+                base: Box::new(Expression::Cast {
+                    expr: Box::new(Expression::Variable("data".to_string())),
+                    target: TypeExpr::Pointer(Box::new(TypeExpr::Named("u32".to_string()))),
+                }),
+                start: 1,
+                end: Some(3),
+            }
+        );
+        assert!(parse_expression("buf[a..4]").is_err());
+
+        assert_eq!(vec_element_type("alloc::vec::Vec<u8, alloc::alloc::Global>"), Some("u8"));
+        assert_eq!(vec_element_type("Vec<(u8, u16)>"), Some("(u8, u16)"));
+        assert_eq!(vec_element_type("Vec<Vec<u8>>"), Some("Vec<u8>"));
+        assert_eq!(vec_element_type("app::MyVec<u8>"), None);
+    }
+
     #[test]
     fn test_parse_nested_field_access() {
         let expr = parse_expression("obj.inner.value").unwrap();
@@ -0,0 +1,213 @@
+//! 任意の関数の出入りのトレース（ftrace）
+//!
+//! async 計装と同じく関数の入口と ret 命令に内部ブレークポイントを置き、当たるたびに引数・戻り値を
+//! 記録して止まらずに続けます。記録はスレッドごとの呼び出しの深さで字下げした呼び出しログになり、
+//! 上限を超えた分は古いものから捨てます。
+
+use crate::BreakpointId;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+/// 呼び出しログに残す件数の既定値
+pub const DEFAULT_FTRACE_CAPACITY: usize = 10_000;
+
+/// トレース中の関数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracedFunction {
+    /// デマングル済みの関数名
+    pub name: String,
+    /// 入口のブレークポイント
+    pub entry: BreakpointId,
+    /// ret 命令のブレークポイント
+    pub exits: Vec<BreakpointId>,
+    /// DWARF 上で戻り値の型があるか（なければ戻り値を記録しない）
+    pub returns_value: bool,
+}
+
+impl TracedFunction {
+    pub fn breakpoints(&self) -> impl Iterator<Item = BreakpointId> + '_ {
+        std::iter::once(self.entry).chain(self.exits.iter().copied())
+    }
+}
+
+/// 呼び出しログの1件の種類
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallEvent {
+    /// 関数に入った（引数の名前と値）
+    Enter { args: Vec<(String, String)> },
+    /// 関数から戻った（戻り値がなければ None）
+    Exit { value: Option<String> },
+}
+
+/// 呼び出しログの1件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallRecord {
+    pub tid: i32,
+    /// そのスレッドでの呼び出しの深さ（0 が最も外側）
+    pub depth: usize,
+    pub function: String,
+    pub event: CallEvent,
+}
+
+impl fmt::Display for CallRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {:indent$}", self.tid, "", indent = self.depth * 2)?;
+        match &self.event {
+            CallEvent::Enter { args } => {
+                let args: Vec<String> = args.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+                write!(f, "-> {}({})", self.function, args.join(", "))
+            }
+            CallEvent::Exit { value: Some(value) } => write!(f, "<- {} = {}", self.function, value),
+            CallEvent::Exit { value: None } => write!(f, "<- {}", self.function),
+        }
+    }
+}
+
+/// 関数トレースの状態
+#[derive(Debug, Clone)]
+pub struct FunctionTrace {
+    functions: Vec<TracedFunction>,
+    depths: HashMap<i32, usize>,
+    records: VecDeque<CallRecord>,
+    capacity: usize,
+    dropped: usize,
+    recorded: u64,
+}
+
+impl FunctionTrace {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_FTRACE_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            functions: Vec::new(),
+            depths: HashMap::new(),
+            records: VecDeque::new(),
+            capacity: capacity.max(1),
+            dropped: 0,
+            recorded: 0,
+        }
+    }
+
+    /// 関数をトレース対象に加える
+    pub fn add(&mut self, function: TracedFunction) {
+        self.functions.push(function);
+    }
+
+    pub fn is_tracing(&self, name: &str) -> bool {
+        self.functions.iter().any(|function| function.name == name)
+    }
+
+    /// `remove` が true の関数をトレースから外す（ブレークポイントの削除は呼び出し側で行う）
+    pub fn remove_where(&mut self, mut remove: impl FnMut(&TracedFunction) -> bool) -> Vec<TracedFunction> {
+        let (removed, kept) = std::mem::take(&mut self.functions).into_iter().partition(|function| remove(function));
+        self.functions = kept;
+        removed
+    }
+
+    pub fn functions(&self) -> &[TracedFunction] {
+        &self.functions
+    }
+
+    /// 入口がこのブレークポイントの関数
+    pub fn entry_of(&self, id: BreakpointId) -> Option<&TracedFunction> {
+        self.functions.iter().find(|function| function.entry == id)
+    }
+
+    /// ret 命令にこのブレークポイントがある関数
+    pub fn exit_of(&self, id: BreakpointId) -> Option<&TracedFunction> {
+        self.functions.iter().find(|function| function.exits.contains(&id))
+    }
+
+    /// 関数に入ったことを記録する
+    pub fn on_enter(&mut self, tid: i32, function: &str, args: Vec<(String, String)>) {
+        self.recorded += 1;
+        let depth = self.depths.entry(tid).or_default();
+        let record = CallRecord { tid, depth: *depth, function: function.to_string(), event: CallEvent::Enter { args } };
+        *depth += 1;
+        self.push(record);
+    }
+
+    /// 関数から戻ったことを記録する
+    pub fn on_exit(&mut self, tid: i32, function: &str, value: Option<String>) {
+        self.recorded += 1;
+        let depth = self.depths.entry(tid).or_default();
+        *depth = depth.saturating_sub(1);
+        let record = CallRecord { tid, depth: *depth, function: function.to_string(), event: CallEvent::Exit { value } };
+        self.push(record);
+    }
+
+    fn push(&mut self, record: CallRecord) {
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+            self.dropped += 1;
+        }
+        self.records.push_back(record);
+    }
+
+    /// 呼び出しログ（古い順）
+    pub fn records(&self) -> impl DoubleEndedIterator<Item = &CallRecord> + ExactSizeIterator {
+        self.records.iter()
+    }
+
+    /// 上限を超えて捨てた件数
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// これまでに記録した件数（捨てた分も含む）
+    pub fn recorded(&self) -> u64 {
+        self.recorded
+    }
+
+    /// 呼び出しログを空にする（トレース対象はそのまま）
+    pub fn clear_log(&mut self) {
+        self.records.clear();
+        self.depths.clear();
+        self.dropped = 0;
+    }
+}
+
+impl Default for FunctionTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_log_indents_by_depth() {
+        let mut trace = FunctionTrace::with_capacity(4);
+        trace.add(TracedFunction { name: "app::fib".to_string(), entry: 1, exits: vec![2, 3], returns_value: true });
+        assert_eq!(trace.entry_of(1).unwrap().name, "app::fib");
+        assert_eq!(trace.exit_of(3).unwrap().name, "app::fib");
+        assert!(trace.entry_of(2).is_none());
+
+        trace.on_enter(7, "app::fib", vec![("n".to_string(), "2".to_string())]);
+        trace.on_enter(7, "app::fib", vec![("n".to_string(), "1".to_string())]);
+        trace.on_exit(7, "app::fib", Some("1".to_string()));
+        trace.on_exit(7, "app::fib", None);
+        let lines: Vec<String> = trace.records().map(|record| record.to_string()).collect();
+        assert_eq!(lines, vec![
+            "[7] -> app::fib(n=2)",
+            "[7]   -> app::fib(n=1)",
+            "[7]   <- app::fib = 1",
+            "[7] <- app::fib",
+        ]);
+
+        // 上限を超えたら古いものから捨てる
+        trace.on_enter(8, "app::fib", Vec::new());
+        assert_eq!(trace.records().len(), 4);
+        assert_eq!(trace.dropped(), 1);
+        assert_eq!(trace.recorded(), 5);
+        assert_eq!(trace.records().next_back().unwrap().depth, 0);
+
+        let removed = trace.remove_where(|function| function.name.starts_with("app::"));
+        assert_eq!(removed.len(), 1);
+        assert!(trace.functions().is_empty());
+    }
+}
@@ -0,0 +1,203 @@
+//! async の表示で使う Future の型名の短縮
+//!
+//! タスクの型名は async fn の状態機械のシンボル名（`app::handler::{{closure}}` など）で、
+//! ハッシュ・ジェネリクス引数・`{{closure}}` / `{async_fn_env#0}` が付いて一覧では読みにくいので、
+//! 表示のときだけこれらを落として元の async fn のパスにします。追跡・絞り込みは生の名前のままです。
+
+use std::fmt;
+
+/// 名前の表示の仕方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NameStyle {
+    /// ハッシュや env の要素を落として async fn のパスにする
+    #[default]
+    Short,
+    /// デマングルした名前をそのまま出す
+    Raw,
+}
+
+impl fmt::Display for NameStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameStyle::Short => write!(f, "short"),
+            NameStyle::Raw => write!(f, "raw"),
+        }
+    }
+}
+
+/// Future の型名の表示設定（set async names）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FutureNames {
+    pub style: NameStyle,
+    /// ジェネリクス引数を `<…>` にまとめる
+    pub collapse_generics: bool,
+    /// 元の async fn の名前を太字にする（端末に出すときだけ CLI が有効にする）
+    pub highlight: bool,
+}
+
+impl FutureNames {
+    pub fn new() -> Self {
+        Self { style: NameStyle::Short, collapse_generics: true, highlight: false }
+    }
+
+    /// デマングル済みの名前を表示用にする
+    pub fn display(&self, name: &str) -> String {
+        if self.style == NameStyle::Raw {
+            return name.to_string();
+        }
+        let name = strip_hashes(name);
+        let name = if self.collapse_generics { collapse_generics(&name) } else { name };
+
+        let mut segments = split_path(&name);
+        // 末尾の env の要素（async fn の状態機械そのもの）を落とし、途中の async ブロックは短く書く
+        let is_env = segments.last().and_then(|last| env_kind(last)).is_some();
+        if is_env {
+            segments.pop();
+        }
+        let mut origin = None;
+        for (i, segment) in segments.iter_mut().enumerate() {
+            match env_kind(segment) {
+                Some(kind) => *segment = kind.to_string(),
+                None if is_env => origin = Some(i),
+                None => {}
+            }
+        }
+        if segments.is_empty() {
+            return name;
+        }
+        if let (Some(i), true) = (origin, self.highlight) {
+            segments[i] = format!("\x1b[1m{}\x1b[22m", segments[i]);
+        }
+        segments.join("::")
+    }
+}
+
+impl Default for FutureNames {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 状態機械を表すパスの要素なら、短く書いたもの
+fn env_kind(segment: &str) -> Option<&'static str> {
+    let inner = segment.strip_prefix('{')?.strip_suffix('}')?;
+    let inner = inner.strip_prefix('{').and_then(|s| s.strip_suffix('}')).unwrap_or(inner);
+    let kind = inner.split('#').next().unwrap_or(inner);
+    match kind {
+        "closure" | "async_fn_env" | "async_closure" => Some("{closure}"),
+        "async_block_env" | "async_block" => Some("{async block}"),
+        _ => None,
+    }
+}
+
+/// legacy マングリングの末尾のハッシュ（`::h` + 16 桁）と v0 のクレートの識別子（`[1a2b3c4d]`）を落とす
+fn strip_hashes(name: &str) -> String {
+    let mut name = name;
+    if let Some((head, hash)) = name.rsplit_once("::h") {
+        if hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            name = head;
+        }
+    }
+    let mut out = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(open) = rest.find('[') {
+        let Some(len) = rest[open + 1..].find(']') else {
+            break;
+        };
+        let inside = &rest[open + 1..open + 1 + len];
+        out.push_str(&rest[..open]);
+        // 識別子の直後に付いたものだけが識別子（`&[Bad]` のようなスライスは残す）
+        let follows_ident = out.ends_with(|c: char| c.is_alphanumeric() || c == '_');
+        if !follows_ident || inside.is_empty() || !inside.bytes().all(|b| b.is_ascii_hexdigit()) {
+            out.push_str(&rest[open..open + len + 2]);
+        }
+        rest = &rest[open + len + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// 型・パスに付いたジェネリクス引数を `<…>` にする
+///
+/// パスの先頭の `<T as Trait>` は引数ではないので残し、その中のジェネリクスだけをまとめます。
+fn collapse_generics(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut skipping = 0usize;
+    let mut prev = None;
+    for c in name.chars() {
+        if skipping > 0 {
+            match c {
+                '<' => skipping += 1,
+                '>' if prev != Some('-') => skipping -= 1,
+                _ => {}
+            }
+            prev = Some(c);
+            continue;
+        }
+        let opens_arguments = prev.is_some_and(|p: char| p.is_alphanumeric() || p == '_' || p == ':');
+        if c == '<' && opens_arguments {
+            out.push_str("<…>");
+            skipping = 1;
+        } else {
+            out.push(c);
+        }
+        prev = Some(c);
+    }
+    out
+}
+
+/// `::` でパスの要素に分ける（`<...>` の中の `::` では分けない）
+fn split_path(name: &str) -> Vec<String> {
+    let mut segments = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let bytes = name.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'<' => depth += 1,
+            b'>' if i == 0 || bytes[i - 1] != b'-' => depth = depth.saturating_sub(1),
+            b':' if depth == 0 && bytes.get(i + 1) == Some(&b':') => {
+                segments.push(name[start..i].to_string());
+                start = i + 2;
+                i += 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    segments.push(name[start..].to_string());
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shorten_future_names() {
+        let names = FutureNames::new();
+        assert_eq!(names.display("simple_async::compute::{{closure}}"), "simple_async::compute");
+        assert_eq!(names.display("app::serve::{{closure}}::h0123456789abcdef"), "app::serve");
+        assert_eq!(names.display("app[5f2a9c1b]::db::query::{async_fn_env#0}"), "app::db::query");
+        assert_eq!(names.display("app::run::{{closure}}::{{closure}}"), "app::run::{closure}");
+        assert_eq!(names.display("app::run::{async_block_env#1}"), "app::run");
+        assert_eq!(
+            names.display("app::fetch<alloc::vec::Vec<u8>, fn() -> u8>::{{closure}}"),
+            "app::fetch<…>"
+        );
+        assert_eq!(
+            names.display("<app::Conn<T> as core::future::Future>::poll"),
+            "<app::Conn<…> as core::future::Future>::poll"
+        );
+        // env の要素がない名前（手書きの Future など）は変えない
+        assert_eq!(names.display("tokio::time::Sleep"), "tokio::time::Sleep");
+
+        let keep = FutureNames { collapse_generics: false, ..FutureNames::new() };
+        assert_eq!(keep.display("app::fetch<u8>::{{closure}}"), "app::fetch<u8>");
+        let bold = FutureNames { highlight: true, ..FutureNames::new() };
+        assert_eq!(bold.display("app::serve::{{closure}}"), "app::\x1b[1mserve\x1b[22m");
+        let raw = FutureNames { style: NameStyle::Raw, ..FutureNames::new() };
+        assert_eq!(raw.display("app::serve::{{closure}}::h0123456789abcdef"), "app::serve::{{closure}}::h0123456789abcdef");
+    }
+}
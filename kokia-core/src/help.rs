@@ -0,0 +1,434 @@
+//! コマンドのヘルプ
+//!
+//! `help` の一覧、`help <command>` の詳細、未知のコマンドへの候補（"did you mean ..."）はすべて
+//! この表から作ります。`Command::help_entry` は variant ごとの網羅的な match なので、コマンドを
+//! 追加すると名前の付け忘れがコンパイル時に分かります。名前が表にない場合はテストで分かります。
+
+use crate::{Command, Result};
+use kokia_target::WatchKind;
+
+/// ヘルプ一覧での分類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HelpCategory {
+    General,
+    Debugging,
+    Thread,
+    Async,
+}
+
+impl HelpCategory {
+    /// 一覧に表示する順
+    pub const ALL: [HelpCategory; 4] = [HelpCategory::General, HelpCategory::Debugging, HelpCategory::Thread, HelpCategory::Async];
+
+    /// 一覧の見出し
+    pub fn title(self) -> &'static str {
+        match self {
+            HelpCategory::General => "Available commands",
+            HelpCategory::Debugging => "Debug commands",
+            HelpCategory::Thread => "Thread commands",
+            HelpCategory::Async => "Async commands",
+        }
+    }
+}
+
+/// 1つのコマンドのヘルプ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandHelp {
+    /// コマンド名（`async tasks` のように複数語のこともある）
+    pub name: &'static str,
+    /// 別名（`c`、`bt` など）
+    pub aliases: &'static [&'static str],
+    /// 名前に続く引数の書式
+    pub args: &'static str,
+    /// 一覧に出す1行の説明
+    pub summary: &'static str,
+    /// `help <command>` で出す詳しい説明（なければ空）
+    pub details: &'static str,
+    pub category: HelpCategory,
+}
+
+impl CommandHelp {
+    /// `continue (c)`、`x [/N] <addr>` のような書式
+    pub fn synopsis(&self) -> String {
+        let mut synopsis = self.name.to_string();
+        if !self.aliases.is_empty() {
+            // `async ls` のような別名はサブコマンドの部分だけを出す
+            let aliases: Vec<&str> = self.aliases.iter().map(|a| a.rsplit(' ').next().unwrap_or(a)).collect();
+            synopsis.push_str(&format!(" ({})", aliases.join(", ")));
+        }
+        if !self.args.is_empty() {
+            synopsis.push(' ');
+            synopsis.push_str(self.args);
+        }
+        synopsis
+    }
+
+    /// 名前か別名が一致するか
+    fn is_named(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+}
+
+const fn entry(
+    category: HelpCategory,
+    name: &'static str,
+    aliases: &'static [&'static str],
+    args: &'static str,
+    summary: &'static str,
+    details: &'static str,
+) -> CommandHelp {
+    CommandHelp { name, aliases, args, summary, details, category }
+}
+
+use HelpCategory::{Async, Debugging, General, Thread};
+
+/// すべてのコマンドのヘルプ（一覧に表示する順）
+pub const COMMANDS: &[CommandHelp] = &[
+    entry(General, "help", &["h", "?"], "[command]", "Show this help, or details of one command", ""),
+    entry(General, "set logging", &[], "on [file]|off", "Record commands, stops and output with timestamps (default kokia.log)",
+        "A file ending in .jsonl gets one JSON object per line; anything else is plain text. Useful for attaching a session to a bug report."),
+    entry(General, "quit", &["exit", "q"], "", "Exit the debugger", "A running debuggee is detached, not killed."),
+    entry(Debugging, "break", &["b"], "<loc> [thread N] [task <task>]", "Set breakpoint at symbol or address",
+        "<loc> is a symbol, an address or an address expression: main, 0x1234, *$rsp, $pc+0x12, main+0x40. 'thread N' (numbered as in 'info threads') and 'task #N' only stop there in that thread, or while that task is being polled. A hit at a function's entry also prints its arguments, read from the argument registers and stack by the calling convention."),
+    entry(Debugging, "logpoint", &[], "<loc> \"<format>\"", "Print a message and keep running whenever <loc> is hit",
+        "Expressions in braces are evaluated at each hit, e.g. logpoint main.rs:42 \"x={x} state={self.__state}\"; write {{ and }} for literal braces."),
+    entry(Debugging, "bpgroup", &[], "create|enable|disable|delete <name>|add <name> <cmd>|list|save|load <file>",
+        "Toggle a named set of breakpoints and async instrumentation at once",
+        "<cmd> is 'break <loc>' or 'async enable --filter <pattern>'; a break location with * or ? (or /regex/) is set on every matching function. Groups remember locations, not breakpoint ids, so they can be enabled again after 'run' or saved as a profile. Enabling fails as a whole if any member cannot be set."),
+    entry(Debugging, "continue", &["c"], "", "Continue execution", ""),
+    entry(Debugging, "step", &["s"], "", "Execute one instruction (step into)",
+        "Functions matching the step filter (see 'set step-filter') are run through until user code is reached."),
+    entry(Debugging, "reverse-stepi", &["rsi"], "[n]", "Undo the last n instructions run with 'step' (registers and memory)",
+        "Only instructions executed with 'step' are recorded; the history is cleared on continue."),
+    entry(Debugging, "trace", &[], "start [--branches] [--limit <n>]|stop|show [n]|export <file>",
+        "Record executed instructions (or only taken branches) between two stops",
+        "While tracing, 'continue' single-steps (slowly) until a user breakpoint, watchpoint, signal or Ctrl-C; 'stop' prints branch counts by function."),
+    entry(Debugging, "trace hw", &[], "start|stop", "Record branches with Intel PT while the program runs normally",
+        "Needs an Intel CPU with PT (not available in most VMs). 'stop' decodes indirect branch and return targets per thread."),
+    entry(Debugging, "ftrace", &[], "<pat>|log [n]|clear|stop [pat]", "Log entry and exit of matching functions without stopping",
+        "Places internal breakpoints after each function's prologue and on its ret instructions. Without arguments lists the traced functions; 'log' shows the call log indented by call depth per thread (arguments on entry, RAX on return)."),
+    entry(Debugging, "profile", &[], "start [hz]|stop|report", "Sample every thread's call stack while running under continue",
+        "Uses perf task-clock sampling (default 99 Hz); 'report' shows a flat profile, the call tree and samples per async fn."),
+    entry(Debugging, "next", &["n"], "", "Execute to next source line (step over)", ""),
+    entry(Debugging, "finish", &["f"], "", "Execute until current function returns (step out)",
+        "Prints the return value, decoded from RAX/RDX, xmm0/xmm1 or the returned-in-memory slot by the function's DWARF return type; a Poll<T> is shown as Poll::Ready(value) or Poll::Pending."),
+    entry(Debugging, "backtrace", &["bt"], "", "Show stack backtrace", ""),
+    entry(Debugging, "backtrace full", &["bt full"], "", "Show stack backtrace with the arguments and locals of every frame", ""),
+    entry(Debugging, "locals", &["l"], "", "Show local variables", "Only variables in scope at the current PC are shown (lexical blocks, DW_AT_start_scope). Function arguments are listed separately by 'info args'."),
+    entry(Debugging, "info args", &[], "", "Show the arguments of the current function", ""),
+    entry(Debugging, "print", &["p"], "<expr>", "Evaluate and print expression (variable, field, array index, slice, cast)",
+        "Examples: print x, print obj.field, print arr[0], print *ptr, print $rsp+8, print (u32)x, print *(data as *const app::State), print (data as *const app::State).field, print (buf as *const u8)[3], print buf[0..16], print v[2..]. Slices work on arrays, Vec, String and pointer casts. Pointer casts read the value as an address; other casts reinterpret the memory in place. Names that are not locals are looked up as statics; a thread_local! (print COUNTER, print tokio::runtime::context::CONTEXT) shows the selected thread's value."),
+    entry(Debugging, "set $var", &[], "= <expr>", "Store a value in a convenience variable for later expressions",
+        "Example: set $base = $rsp+0x40, then print *($base as *const u64). Each print is also kept as $1, $2, ...: $ is the last value, $$ the one before, $$n n values back."),
+    entry(Debugging, "ptype", &[], "<type|expr>", "Show type layout (fields, offsets, sizes, async fn state variants)",
+        "For an async fn (ptype simple_async::compute) the state machine's variants and their fields are shown."),
+    entry(Debugging, "whatis", &[], "<expr>", "Show only the declared type of an expression or type name", ""),
+    entry(Debugging, "x", &[], "[/N] <addr>", "Examine N 8-byte words of memory at address expression",
+        "Example: x/4 $rsp. Format letters after N (x/4x) are accepted and ignored."),
+    entry(Debugging, "display", &[], "<expr>", "Print expression automatically after every stop",
+        "Without an argument the registered expressions are printed now."),
+    entry(Debugging, "undisplay", &[], "[n]", "Remove auto-display expression n (all if omitted)", ""),
+    entry(Debugging, "find", &[], "<pattern>", "Find symbols matching pattern", ""),
+    entry(Debugging, "info proc", &[], "", "Show process metadata (pid, exe, cwd, memory, mappings)", ""),
+    entry(Debugging, "mem protect", &[], "<addr> <len> <rwx>", "Change memory protection (injects mprotect)", ""),
+    entry(Debugging, "patch", &[], "<addr> <bytes>|asm <insns>", "Write raw bytes (patch foo 90 90) or assembled instructions",
+        "Supported instructions: nop, int3, ret, ud2, hlt, jmp <addr>, call <addr>, separated by ';'."),
+    entry(Debugging, "unpatch", &[], "[n]", "Revert patch n (all if omitted)", ""),
+    entry(Debugging, "info patches", &[], "", "List applied patches with original bytes", ""),
+    entry(Debugging, "watch", &[], "<addr> [len]", "Stop when memory is written (hardware, len 1/2/4/8, default 8)",
+        "x86-64 has four debug registers, so at most four watchpoints can be active. The old and new value are shown on a hit."),
+    entry(Debugging, "awatch", &[], "<addr> [len]", "Stop when memory is read or written", ""),
+    entry(Debugging, "unwatch", &[], "[n]", "Delete watchpoint n (all if omitted)", ""),
+    entry(Debugging, "info watchpoints", &[], "", "List watchpoints with their debug register and last value", ""),
+    entry(Debugging, "info async-runtime", &[], "", "Detect the async runtime, its version, scheduler and worker threads", ""),
+    entry(Debugging, "info binary", &[], "", "Show build-id, PIE, rustc version, debuginfo level and symbol counts of the binary",
+        "Optimization and debuginfo level are inferred from the DWARF (rustc does not record its flags). 'kokia doctor <binary>' checks the same things before starting."),
+    entry(Debugging, "info variants", &[], "<type>", "List discriminant values, variant names and suspend-point lines", ""),
+    entry(Debugging, "symbol-file-from-memory", &[], "", "Replace symbols with the running image's .dynsym (after build-id mismatch)", ""),
+    entry(Debugging, "gcore", &["generate-core-file"], "[path]", "Write an ELF core file of the stopped process (default core.<pid>)", ""),
+    entry(Debugging, "attach", &[], "[--wait] <pid|name>", "Attach to a process after the previous one exited or was killed",
+        "With --wait the process list is polled until a match appears (for services restarted by systemd); Ctrl-C stops waiting. A name matches the comm or executable file name; the newest match is chosen."),
+    entry(Debugging, "export-state", &[], "<path>", "Save breakpoints, settings, displays and tracked async tasks to a JSON file",
+        "Attach the file to a bug report. Times are stored relative to the export, breakpoints as file addresses, and the mapped modules are listed."),
+    entry(Debugging, "import-state", &[], "<path>", "Restore a state saved by export-state (replaces tracked async tasks)",
+        "Breakpoints are set again only while a process is running, so import after 'run' against the same binary. A build-id mismatch is reported."),
+    entry(Debugging, "set crash-report", &[], "on|off", "Print registers, disassembly, backtrace and async tree on fatal signals", ""),
+    entry(Debugging, "set step-filter", &[], "<prefix>...|off|default", "Crate prefixes 'step' runs through (default tokio:: core:: std:: ...)",
+        "A function is filtered when its demangled path, or the implementing type of a trait method, starts with a prefix."),
+    entry(Debugging, "catch err", &[], "<function>", "Stop when the function is about to return Err (decoded from its DWARF return type)", ""),
+    entry(Debugging, "catch panic", &[], "", "Stop when a Rust panic starts unwinding (shows message, backtrace and async backtrace)", ""),
+    entry(Thread, "thread apply", &[], "all|<n>|<pattern> <cmd>", "Run command in every thread, thread <n>, or threads whose name matches",
+        "Examples: thread apply all bt, thread apply 2 print x, thread apply tokio-runtime-w* bt."),
+    entry(Thread, "info threads", &[], "[pattern]", "List threads with name, current frame and the async task being polled", ""),
+    entry(Thread, "add-inferior", &[], "[<binary> [args...]]", "Add another debuggee (started from <binary>, or empty for 'attach') and select it",
+        "Each inferior has its own process, symbols, breakpoints and async tasks; settings such as the step filter are shared."),
+    entry(Thread, "inferior", &[], "<n>", "Select inferior n; continue/step only run the selected one while the others stay stopped", ""),
+    entry(Thread, "inferior apply all", &[], "<cmd>", "Run command in every inferior (inferior apply all async tree)", ""),
+    entry(Thread, "info inferiors", &[], "", "List inferiors with process, binary and tracked async task count", ""),
+    entry(Async, "async enable", &[], "[--filter <pat>]", "Enable async tracking (instrument only matching functions with --filter)",
+        "Patterns are globs (app::*) or regular expressions between slashes (/db|cache/). A panic caught by catch_unwind (as runtimes do around each poll) skips the exit breakpoints of the unwound polls; an internal breakpoint on __rust_panic_cleanup pops them from the poll scopes."),
+    entry(Async, "async disable", &[], "[pat|--keep]", "Remove instrumentation from matching functions, or stop async tracking entirely",
+        "Without a pattern, removes every async breakpoint and uprobe so the target runs at full speed again and discards the collected tasks; --keep keeps them as a read-only snapshot until the next 'async enable'."),
+    entry(Async, "async sample", &[], "[<pat> <n>]", "Record only every n-th entry of matching fns (no args: show hit counts)", ""),
+    entry(Async, "async uprobe", &[], "start [--filter <pat>]|collect <secs>|stop", "Track async fns with kernel uprobes instead of INT3",
+        "'collect' runs the debuggee for <secs> and feeds the events into the tracker; 'stop' removes the uprobes."),
+    entry(Async, "async list", &["async ls"], "", "List all async-related symbols", ""),
+    entry(Async, "async bt", &["async backtrace"], "[--all] [--raw]", "Show async backtrace (logical stack); --all for every live root task", ""),
+    entry(Async, "async tasks", &[], "[--raw]", "Show all tracked async tasks",
+        "Future type names are shortened to the originating async fn (see 'set async names'); --raw shows the demangled names as they are, and works the same on async bt, memsize, lifetimes, find and tree. The state read from the discriminant is Unresumed, Suspend<N> (waiting at the N-th .await), Returned, or PANICKED for a task whose poll panicked (polling it again panics; async find --state panicked lists them)."),
+    entry(Async, "async memsize", &[], "[--raw]", "Show the size of each live task's state machine, per root task and per async fn",
+        "Sizes come from the DW_AT_byte_size of each async fn's state machine type; awaited children stored inside their parent are not counted twice."),
+    entry(Async, "async lifetimes", &[], "[--raw]", "Show the distribution of task lifetimes, per-type counts and throughput",
+        "Lifetimes run from the first observed poll to completion; pending tasks count their age so far and evicted tasks are not included."),
+    entry(Async, "async find", &[], "[pat] [--state pending|completed|root|panicked] [--older-than <secs>] [--newer-than <secs>] [--sort handle|age|recent|name] [--raw]",
+        "Find tasks by function name, state or age",
+        "A pattern without * or ? matches any part of the name (async find fetch); /regex/ is also accepted. Age is the time since the task was first seen."),
+    entry(Async, "async assert", &[], "no-stalled [--timeout <5s|500ms|2m>] | max-tasks <n> | no-dropped",
+        "Check the task graph and exit kokia with status 3 on violation",
+        "Meant for CI: kokia --batch -x 'continue' -x 'async assert no-stalled --timeout 5s'. no-stalled fails on a live task not polled within the timeout (time stopped in kokia does not count), max-tasks on more live tasks than n, no-dropped on a task dropped before completing."),
+    entry(Async, "async edges", &[], "[--active] [--root <task>] [--since <secs>]", "Show async task parent-child relationships",
+        "--root takes a task number (#4) or address and limits the output to what that task awaits."),
+    entry(Async, "async tree", &[], "[--active] [--root <task>] [--since <secs>] [--raw]", "Show tasks as a tree (completed subtrees folded)",
+        "--root takes a task number (#4) or address; an explicit root is expanded even if it has completed."),
+    entry(Async, "async clear", &[], "", "Forget all tracked async tasks and edges (instrumentation stays)", ""),
+    entry(Async, "async catch poison", &[], "", "Stop when a panic during poll leaves tasks in the Panicked state",
+        "Needs 'async enable'. The tasks being polled are noted when the panic starts and re-read where catch_unwind (e.g. the runtime's task harness) catches it, before the runtime drops the future; the report shows the poisoned tasks and the await chain that was unwound. A panic caught inside the async fn itself does not stop."),
+    entry(Async, "async export", &[], "webhook|otlp <http://url> [--long-poll <100ms>] [--stall <5s>] | stop",
+        "Stream task created/completed, stalled and long-poll events to a webhook or an OTLP collector",
+        "webhook POSTs {\"source\":\"kokia\",\"events\":[...]} to the URL; otlp sends OTLP/HTTP JSON to <url>/v1/traces (completed tasks and long polls as spans) and <url>/v1/logs (created and stalled tasks). Events are batched and sent from a background thread about once a second; only plain http:// is supported. Poll durations include the breakpoint overhead. Without arguments shows the destination and how many events were sent, failed or dropped."),
+    entry(Async, "async stats", &[], "", "Show task and poll counts, and how long kokia's instrumentation kept the target stopped",
+        "The stopped time is counted from when an async breakpoint, a thread/task-limited breakpoint or 'catch err' that does not stop, a logpoint or an ftrace probe is hit until the target is resumed, and is compared with the time spent in 'continue'. Stops reported to you are not counted. If the share is large, narrow the instrumentation with 'async enable --filter' or 'async sample', or use 'async uprobe'. 'async clear' resets the counts."),
+    entry(Async, "async locals", &["async l"], "[task]", "Show local variables of a task (default: the task being polled)",
+        "A suspended task has no stack frame, so only the variables saved in its Future across .await are shown. A task is given by its number (#4) or its address, both listed by 'async tasks'."),
+    entry(Async, "set async summary", &[], "on|off", "Print a one-line async summary after each stop", ""),
+    entry(Async, "set async trace", &[], "on|off", "Record async events without stopping at async breakpoints", ""),
+    entry(Async, "set async fast-entry", &[], "on|off", "Find the parent of a poll from the poll scope stack instead of a backtrace",
+        "Walking the stack on every async fn entry is the largest cost of 'async enable'. With this on, the task being polled further up the thread is taken as the parent; the stack is only walked when that contradicts what earlier polls saw (the task had another parent, or the task on top already returned). 'async stats' shows how often it fell back."),
+    entry(Async, "set async names", &[], "short|raw | generics on|off", "Shorten future type names in async displays, or show them raw",
+        "short drops symbol hashes, crate disambiguators and the {{closure}} / {async_fn_env#0} of the state machine, leaving the async fn path (async blocks inside it show as {async block}); generics on collapses generic arguments to <…>. On a terminal the async fn name is shown in bold. Patterns (async find, --filter) still match the raw names."),
+    entry(Async, "set async retention", &[], "max-tasks <n>|max-age <secs>|off", "Evict the oldest finished tasks, or tasks <secs> after they complete", ""),
+    entry(Async, "set query-timeout", &[], "<secs>|off", "Abort slow locals/async locals lookups (Ctrl-C also aborts)", ""),
+];
+
+impl Command {
+    /// コマンドのヘルプ（表に載っていなければエラー）
+    pub fn help_entry(&self) -> Result<&'static CommandHelp> {
+        let name = match self {
+            Command::Help(_) => "help",
+            Command::Quit => "quit",
+            Command::Break(..) => "break",
+            Command::Logpoint(..) => "logpoint",
+            Command::BpGroupCreate(_) | Command::BpGroupAdd(..) | Command::BpGroupEnable(_) | Command::BpGroupDisable(_)
+            | Command::BpGroupDelete(_) | Command::BpGroupList | Command::BpGroupSave(_) | Command::BpGroupLoad(_) => "bpgroup",
+            Command::Watch(_, _, WatchKind::Write) => "watch",
+            Command::Watch(..) => "awatch",
+            Command::Unwatch(_) => "unwatch",
+            Command::Continue => "continue",
+            Command::Step => "step",
+            Command::ReverseStepi(_) => "reverse-stepi",
+            Command::TraceStart(..) | Command::TraceStop | Command::TraceShow(_) | Command::TraceExport(_) => "trace",
+            Command::TraceHwStart | Command::TraceHwStop => "trace hw",
+            Command::Ftrace(_) | Command::FtraceList | Command::FtraceLog(_) | Command::FtraceClear | Command::FtraceStop(_) => "ftrace",
+            Command::ProfileStart(_) | Command::ProfileStop | Command::ProfileReport => "profile",
+            Command::Next => "next",
+            Command::Finish => "finish",
+            Command::Backtrace => "backtrace",
+            Command::BacktraceFull => "backtrace full",
+            Command::Locals => "locals",
+            Command::InfoArgs => "info args",
+            Command::Print(_) => "print",
+            Command::SetVariable(..) => "set $var",
+            Command::PrintType(_) => "ptype",
+            Command::WhatIs(_) => "whatis",
+            Command::Examine(..) => "x",
+            Command::Display(_) => "display",
+            Command::Undisplay(_) => "undisplay",
+            Command::AsyncBacktrace | Command::AsyncBacktraceAll => "async bt",
+            Command::AsyncLocals(_) => "async locals",
+            Command::AsyncTasks => "async tasks",
+            Command::AsyncMemsize => "async memsize",
+            Command::AsyncLifetimes => "async lifetimes",
+            Command::AsyncFind(_) => "async find",
+            Command::AsyncAssert(_) => "async assert",
+            Command::AsyncEdges(_) => "async edges",
+            Command::AsyncTree(_) => "async tree",
+            Command::AsyncEnable(_) => "async enable",
+            Command::AsyncDisable(_) | Command::AsyncDisableAll(_) => "async disable",
+            Command::AsyncSample(_) => "async sample",
+            Command::AsyncUprobeStart(_) | Command::AsyncUprobeCollect(_) | Command::AsyncUprobeStop => "async uprobe",
+            Command::AsyncClear => "async clear",
+            Command::AsyncStats => "async stats",
+            Command::AsyncCatchPoison => "async catch poison",
+            Command::AsyncExportStart(_) | Command::AsyncExportStop | Command::AsyncExportStatus => "async export",
+            Command::AsyncRawNames(cmd) => {
+                return Command::parse(cmd)
+                    .ok_or_else(|| anyhow::anyhow!("Cannot parse '{}'", cmd))?
+                    .help_entry();
+            }
+            Command::ThreadApplyAll(_) | Command::ThreadApply(..) | Command::ThreadApplyMatching(..) => "thread apply",
+            Command::InfoThreads(_) => "info threads",
+            Command::AddInferior { .. } => "add-inferior",
+            Command::Inferior(_) => "inferior",
+            Command::InferiorApplyAll(_) => "inferior apply all",
+            Command::InfoInferiors => "info inferiors",
+            Command::InfoProc => "info proc",
+            Command::MemProtect(..) => "mem protect",
+            Command::Patch(..) => "patch",
+            Command::Unpatch(_) => "unpatch",
+            Command::InfoVariants(_) => "info variants",
+            Command::InfoPatches => "info patches",
+            Command::InfoWatchpoints => "info watchpoints",
+            Command::InfoAsyncRuntime => "info async-runtime",
+            Command::InfoBinary => "info binary",
+            Command::SymbolFileFromMemory => "symbol-file-from-memory",
+            Command::SetAsyncSummary(_) => "set async summary",
+            Command::SetAsyncTrace(_) => "set async trace",
+            Command::SetAsyncFastEntry(_) => "set async fast-entry",
+            Command::SetAsyncNames(_) | Command::SetAsyncNameGenerics(_) => "set async names",
+            Command::SetAsyncRetentionMaxTasks(_) | Command::SetAsyncRetentionMaxAge(_) => "set async retention",
+            Command::SetQueryTimeout(_) => "set query-timeout",
+            Command::GenerateCore(_) => "gcore",
+            Command::Attach { .. } => "attach",
+            Command::ExportState(_) => "export-state",
+            Command::ImportState(_) => "import-state",
+            Command::SetCrashReport(_) => "set crash-report",
+            Command::CatchPanic => "catch panic",
+            Command::CatchErr(_) => "catch err",
+            Command::SetStepFilter(_) => "set step-filter",
+            Command::SetLogging(_) => "set logging",
+        };
+        COMMANDS.iter().find(|help| help.name == name)
+            .ok_or_else(|| anyhow::anyhow!("No help entry for '{}'", name))
+    }
+}
+
+/// `help <topic>` の対象を探す
+///
+/// 名前か別名が一致するコマンドがあればそれだけを、なければ名前が `topic` で始まるコマンド
+/// （`help async` なら async のサブコマンド全部）を返します。
+pub fn lookup(topic: &str) -> Vec<&'static CommandHelp> {
+    let topic = topic.split_whitespace().collect::<Vec<_>>().join(" ");
+    if let Some(help) = COMMANDS.iter().find(|help| help.is_named(&topic)) {
+        return vec![help];
+    }
+    let prefix = format!("{} ", topic);
+    COMMANDS.iter().filter(|help| help.name.starts_with(&prefix)).collect()
+}
+
+/// 入力の先頭の語がコマンド名そのものなら、そのコマンドを返す（引数が誤っている場合の書式表示用）
+pub fn find_by_input(input: &str) -> Option<&'static CommandHelp> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    COMMANDS.iter()
+        .filter(|help| {
+            let len = help.name.split(' ').count();
+            words.len() >= len && help.is_named(&words[..len].join(" "))
+        })
+        .max_by_key(|help| help.name.len())
+}
+
+/// 未知のコマンドに近いコマンド名を探す（"did you mean ..."）
+///
+/// 名前と同じ語数だけ入力の先頭を取り、編集距離が名前の長さの 1/3 以下（最低 1）で最も近いものを
+/// 返します。
+pub fn suggest(input: &str) -> Option<&'static str> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    let mut best: Option<(usize, &'static str)> = None;
+    for help in COMMANDS {
+        let len = help.name.split(' ').count();
+        if words.len() < len {
+            continue;
+        }
+        let typed = words[..len].join(" ");
+        for name in std::iter::once(&help.name).chain(help.aliases) {
+            let distance = edit_distance(&typed, name);
+            let limit = (name.len() / 3).max(1);
+            if distance <= limit && best.is_none_or(|(d, _)| distance < d) {
+                best = Some((distance, help.name));
+            }
+        }
+    }
+    best.map(|(_, name)| name)
+}
+
+/// レーベンシュタイン距離
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            row[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(row[j] + 1);
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_help_lookup_and_suggest() {
+        assert_eq!(Command::parse("c").unwrap().help_entry().unwrap().name, "continue");
+        assert_eq!(Command::parse("async uprobe stop").unwrap().help_entry().unwrap().name, "async uprobe");
+        assert_eq!(Command::parse("awatch 0x10").unwrap().help_entry().unwrap().name, "awatch");
+        assert_eq!(lookup("bt")[0].synopsis(), "backtrace (bt)");
+        assert_eq!(lookup("x")[0].synopsis(), "x [/N] <addr>");
+        assert_eq!(lookup("async  ls")[0].synopsis(), "async list (ls)");
+        assert!(lookup("async").len() > 5);
+        assert!(lookup("frobnicate").is_empty());
+
+        assert_eq!(find_by_input("break").map(|h| h.name), Some("break"));
+        assert_eq!(find_by_input("info threads a b").map(|h| h.name), Some("info threads"));
+
+        assert_eq!(suggest("async task"), Some("async tasks"));
+        assert_eq!(suggest("contnue"), Some("continue"));
+        assert_eq!(suggest("set async sumary on"), Some("set async summary"));
+        assert_eq!(suggest("xyzzy"), None);
+
+        // 名前は重複しない
+        for (i, help) in COMMANDS.iter().enumerate() {
+            assert!(COMMANDS[i + 1..].iter().all(|other| other.name != help.name), "{}", help.name);
+        }
+    }
+
+    #[test]
+    fn test_every_command_has_help_entry() {
+        // Command の variant ごとに 1 つ（variant を追加したらここにも加える）
+        let inputs = [
+            "help", "quit", "break main", "logpoint main \"hit\"",
+            "bpgroup create g", "bpgroup add g break main", "bpgroup enable g", "bpgroup disable g", "bpgroup delete g",
+            "bpgroup list", "bpgroup save g.json", "bpgroup load g.json",
+            "watch 0x10", "unwatch", "continue", "step", "reverse-stepi",
+            "trace start", "trace stop", "trace show", "trace export t.json", "trace hw start", "trace hw stop",
+            "ftrace main", "ftrace", "ftrace log", "ftrace clear", "ftrace stop",
+            "profile start", "profile stop", "profile report",
+            "next", "finish", "backtrace", "backtrace full", "locals", "info args",
+            "print x", "set $a = 1", "ptype x", "whatis x", "x 0x10", "display x", "undisplay",
+            "async bt", "async bt --all", "async locals", "async tasks", "async memsize", "async lifetimes",
+            "async find", "async assert max-tasks 3", "async edges", "async tree",
+            "async enable", "async disable main", "async disable", "async sample",
+            "async uprobe start", "async uprobe collect 1", "async uprobe stop", "async clear", "async catch poison",
+            "async export webhook http://localhost", "async export stop", "async export", "async stats",
+            "async tasks --raw",
+            "thread apply all bt", "thread apply 1 bt", "thread apply worker bt", "info threads",
+            "add-inferior", "inferior 1", "inferior apply all bt", "info inferiors", "info proc",
+            "mem protect 0x10 4096 rwx", "patch 0x10 90", "unpatch", "info variants Foo", "info patches",
+            "info watchpoints", "info async-runtime", "info binary", "symbol-file-from-memory",
+            "set async summary on", "set async trace on", "set async fast-entry on", "set async names short",
+            "set async names generics on", "set async retention max-tasks 3", "set async retention max-age 3",
+            "set query-timeout 3", "gcore", "attach 1", "export-state s.json", "import-state s.json",
+            "set crash-report on", "catch panic", "catch err main", "set step-filter off", "set logging off",
+        ];
+        let mut variants = std::collections::HashSet::new();
+        for input in inputs {
+            let command = Command::parse(input).unwrap_or_else(|| panic!("'{}' does not parse", input));
+            if let Err(e) = command.help_entry() {
+                panic!("{:?}: {}", command, e);
+            }
+            assert!(variants.insert(std::mem::discriminant(&command)), "'{}' repeats {:?}", input, command);
+        }
+    }
+}
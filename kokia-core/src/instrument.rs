@@ -0,0 +1,301 @@
+//! async 計装プラン
+//!
+//! `async enable` でどの async 関数に entry/exit ブレークポイントを置いたかを記録し、
+//! パターンで選んだ一部の関数だけを有効化・無効化できるようにします。
+//! タイマーのように頻繁に poll される関数は、N 回に1回だけ記録するよう間引けます。
+
+use crate::{BreakpointId, Result};
+use kokia_async::Tid;
+use kokia_dwarf::Symbol;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+
+/// デマングル名に対するパターン
+///
+/// `/.../` で囲むと正規表現（部分一致）、それ以外は `*` と `?` を使えるグロブ（全体一致）として扱います。
+#[derive(Debug, Clone)]
+pub struct SymbolPattern {
+    source: String,
+    regex: Regex,
+}
+
+impl SymbolPattern {
+    /// パターン文字列をパースする
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let regex = match pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+            Some(re) => Regex::new(re),
+            None => {
+                let mut re = String::from("^");
+                for c in pattern.chars() {
+                    match c {
+                        '*' => re.push_str(".*"),
+                        '?' => re.push('.'),
+                        c => re.push_str(&regex::escape(&c.to_string())),
+                    }
+                }
+                re.push('$');
+                Regex::new(&re)
+            }
+        }
+        .map_err(|e| anyhow::anyhow!("Invalid pattern '{}': {}", pattern, e))?;
+
+        Ok(Self { source: pattern.to_string(), regex })
+    }
+
+    /// デマングル名がパターンにマッチするか
+    pub fn matches(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+
+    /// 元のパターン文字列
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+/// 計装済みの async 関数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArmedFunction {
+    /// デマングル名
+    pub name: String,
+    /// entry ブレークポイント
+    pub entry: BreakpointId,
+    /// exit ブレークポイント（各 ret 命令）
+    pub exits: Vec<BreakpointId>,
+    /// exit ブレークポイントの配置を終えたか
+    pub exits_installed: bool,
+    /// 何回に1回の entry を記録するか（1 なら毎回）
+    pub sample_every: u64,
+    /// entry ブレークポイントのヒット数（間引いた分も含む）
+    pub hits: u64,
+    /// 記録した entry の数
+    pub recorded: u64,
+    /// 間引いた entry の、スレッドごとの entry 時の RSP（内側の poll ほど後ろ）
+    ///
+    /// ret 命令の時点の RSP は entry 時と同じなので、exit はこれと突き合わせて読み飛ばします。
+    /// poll が入れ子になっても、別のスレッドの poll と交互になっても取り違えません。
+    skipped: HashMap<Tid, Vec<u64>>,
+}
+
+impl ArmedFunction {
+    /// この関数のために置いたブレークポイントすべて
+    pub fn breakpoints(&self) -> impl Iterator<Item = BreakpointId> + '_ {
+        std::iter::once(self.entry).chain(self.exits.iter().copied())
+    }
+}
+
+/// async 計装プラン（関数の開始アドレス → 計装状態）
+///
+/// アドレスはシンボルテーブル上の値（PIE ならロードバイアスを含まない）で管理します。
+pub struct InstrumentationPlan {
+    armed: BTreeMap<u64, ArmedFunction>,
+}
+
+impl InstrumentationPlan {
+    /// 空のプランを作成する
+    pub fn new() -> Self {
+        Self { armed: BTreeMap::new() }
+    }
+
+    /// 候補のうち、フィルタにマッチして未計装の関数を選ぶ
+    pub fn select(&self, candidates: Vec<Symbol>, filter: Option<&SymbolPattern>) -> Vec<Symbol> {
+        candidates
+            .into_iter()
+            .filter(|sym| !self.armed.contains_key(&sym.address))
+            .filter(|sym| filter.is_none_or(|f| f.matches(&sym.demangled_name)))
+            .collect()
+    }
+
+    /// 関数を計装済みとして記録する
+    pub fn arm(&mut self, symbol: &Symbol, entry: BreakpointId, exits: Vec<BreakpointId>, exits_installed: bool) {
+        self.armed.insert(
+            symbol.address,
+            ArmedFunction {
+                name: symbol.demangled_name.clone(),
+                entry,
+                exits,
+                exits_installed,
+                sample_every: 1,
+                hits: 0,
+                recorded: 0,
+                skipped: HashMap::new(),
+            },
+        );
+    }
+
+    /// パターンにマッチする関数の間引き間隔を設定する
+    ///
+    /// # Returns
+    /// 設定した関数の数
+    pub fn set_sampling(&mut self, pattern: &SymbolPattern, every: u64) -> usize {
+        let mut count = 0;
+        for function in self.armed.values_mut().filter(|f| pattern.matches(&f.name)) {
+            function.sample_every = every.max(1);
+            count += 1;
+        }
+        count
+    }
+
+    /// entry ヒットを数え、このヒットを記録すべきかを返す
+    ///
+    /// 計装プランにない関数は常に記録します。`sp` は entry で止まったときの RSP です。
+    pub fn on_entry_hit(&mut self, func_start: u64, tid: Tid, sp: u64) -> bool {
+        let Some(function) = self.armed.get_mut(&func_start) else {
+            return true;
+        };
+        function.hits += 1;
+        let record = (function.hits - 1) % function.sample_every == 0;
+        if record {
+            function.recorded += 1;
+        }
+        if !record {
+            function.skipped.entry(tid).or_default().push(sp);
+        }
+        record
+    }
+
+    /// exit ヒットを記録すべきかを返す（同じスレッド・同じ RSP の entry を間引いていたら false）
+    pub fn on_exit_hit(&mut self, func_start: u64, tid: Tid, sp: u64) -> bool {
+        let Some(function) = self.armed.get_mut(&func_start) else {
+            return true;
+        };
+        let Some(skipped) = function.skipped.get_mut(&tid) else {
+            return true;
+        };
+        // より深い RSP の entry は、パニックなどで exit を通らずに抜けている
+        while skipped.last().is_some_and(|&entry_sp| entry_sp < sp) {
+            skipped.pop();
+        }
+        let skip = skipped.last() == Some(&sp);
+        if skip {
+            skipped.pop();
+        }
+        if skipped.is_empty() {
+            function.skipped.remove(&tid);
+        }
+        !skip
+    }
+
+    /// パターンにマッチする計装を取り除き、取り除いた関数を返す
+    ///
+    /// ブレークポイントの無効化は呼び出し側で行います。
+    pub fn disarm(&mut self, pattern: &SymbolPattern) -> Vec<ArmedFunction> {
+        let addrs: Vec<u64> = self
+            .armed
+            .iter()
+            .filter(|(_, f)| pattern.matches(&f.name))
+            .map(|(addr, _)| *addr)
+            .collect();
+        addrs.into_iter().filter_map(|addr| self.armed.remove(&addr)).collect()
+    }
+
+    /// entry ブレークポイントが `entry` の関数の計装を取り除く
+    pub fn disarm_entry(&mut self, entry: BreakpointId) -> Option<ArmedFunction> {
+        let addr = self.armed.iter().find(|(_, f)| f.entry == entry).map(|(addr, _)| *addr)?;
+        self.armed.remove(&addr)
+    }
+
+    /// 計装済みの関数を取得する
+    pub fn get(&self, func_start: u64) -> Option<&ArmedFunction> {
+        self.armed.get(&func_start)
+    }
+
+    /// 後から配置した exit ブレークポイントを記録する
+    pub fn add_exits(&mut self, func_start: u64, exits: Vec<BreakpointId>) {
+        if let Some(function) = self.armed.get_mut(&func_start) {
+            function.exits.extend(exits);
+            function.exits_installed = true;
+        }
+    }
+
+    /// 計装済みの関数を開始アドレス順に取得する
+    pub fn armed(&self) -> impl Iterator<Item = (u64, &ArmedFunction)> {
+        self.armed.iter().map(|(addr, f)| (*addr, f))
+    }
+
+    /// 計装済みの関数の数
+    pub fn len(&self) -> usize {
+        self.armed.len()
+    }
+
+    /// 計装済みの関数がないか
+    pub fn is_empty(&self) -> bool {
+        self.armed.is_empty()
+    }
+
+    /// 記録をすべて破棄する（プロセス終了時）
+    pub fn clear(&mut self) {
+        self.armed.clear();
+    }
+}
+
+impl Default for InstrumentationPlan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol_pattern() {
+        let glob = SymbolPattern::parse("my_crate::api::*").unwrap();
+        assert!(glob.matches("my_crate::api::handler::{{closure}}"));
+        assert!(!glob.matches("other::my_crate::api::handler"));
+
+        let single = SymbolPattern::parse("app::task?::{{closure}}").unwrap();
+        assert!(single.matches("app::task1::{{closure}}"));
+        assert!(!single.matches("app::task10::{{closure}}"));
+
+        let re = SymbolPattern::parse("/timer|sleep/").unwrap();
+        assert!(re.matches("app::timer::tick::{{closure}}"));
+        assert!(SymbolPattern::parse("/(/").is_err());
+    }
+
+    #[test]
+    fn test_plan_select_and_disarm() {
+        let a = Symbol::new("app::api::get::{{closure}}".into(), 0x100, 0x10);
+        let b = Symbol::new("app::db::query::{{closure}}".into(), 0x200, 0x10);
+        let mut plan = InstrumentationPlan::new();
+
+        let api = SymbolPattern::parse("app::api::*").unwrap();
+        let selected = plan.select(vec![a.clone(), b.clone()], Some(&api));
+        assert_eq!(selected.len(), 1);
+        plan.arm(&selected[0], 1, vec![2, 3], true);
+
+        // 計装済みの関数は再び選ばれない
+        assert_eq!(plan.select(vec![a, b], None).len(), 1);
+
+        // 3回に1回だけ記録し、間引いた entry に対応する exit も読み飛ばす
+        assert_eq!(plan.set_sampling(&api, 3), 1);
+        let recorded: Vec<bool> = (0..6)
+            .map(|_| {
+                let entry = plan.on_entry_hit(0x100, Tid(1), 0x7f00);
+                assert_eq!(plan.on_exit_hit(0x100, Tid(1), 0x7f00), entry);
+                entry
+            })
+            .collect();
+        assert_eq!(recorded, vec![true, false, false, true, false, false]);
+        assert_eq!((plan.get(0x100).unwrap().hits, plan.get(0x100).unwrap().recorded), (6, 2));
+
+        // 入れ子の poll（記録した外側の中で間引いた内側）と、別のスレッドの poll が交互に来る
+        assert!(plan.on_entry_hit(0x100, Tid(1), 0x7f00));
+        assert!(!plan.on_entry_hit(0x100, Tid(1), 0x7e00));
+        assert!(!plan.on_entry_hit(0x100, Tid(2), 0x5f00));
+        assert!(plan.on_exit_hit(0x100, Tid(1), 0x6000), "an unrelated deeper exit");
+        assert!(!plan.on_exit_hit(0x100, Tid(1), 0x7e00));
+        assert!(plan.on_exit_hit(0x100, Tid(1), 0x7f00));
+        assert!(!plan.on_exit_hit(0x100, Tid(2), 0x5f00));
+        // 間引いた entry の exit を通らずに抜けたら、外側の exit で捨てる
+        assert!(plan.on_entry_hit(0x100, Tid(1), 0x7f00));
+        assert!(!plan.on_entry_hit(0x100, Tid(1), 0x7e00));
+        assert!(plan.on_exit_hit(0x100, Tid(1), 0x7f00));
+        assert!(plan.get(0x100).unwrap().skipped.is_empty());
+
+        let removed = plan.disarm(&api);
+        assert_eq!(removed[0].breakpoints().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(plan.is_empty());
+    }
+}
@@ -4,22 +4,90 @@
 //! ターゲットプロセスの制御、デバッグ情報の解析、非同期関数のトレースを統合します。
 
 pub mod debugger;
+pub mod async_assert;
+pub mod binary_info;
+pub mod bpgroup;
 pub mod breakpoint;
+pub mod callconv;
 pub mod command;
+pub mod convenience;
 pub mod disasm;
+pub mod display;
+pub mod doctor;
+pub mod err_catch;
 pub mod errors;
+pub mod eval_cache;
 pub mod parse;
+pub mod panic;
+pub mod patch;
+pub mod poison;
+pub mod profile;
 pub mod expr_eval;
+pub mod ftrace;
+pub mod future_name;
+pub mod help;
+pub mod instrument;
+pub mod logpoint;
+pub mod memsize;
+pub mod metrics;
+pub mod reverse;
+pub mod runtime;
+pub mod session;
+pub mod state;
+pub mod step_filter;
+pub mod supervise;
+pub mod symbol_memo;
+pub mod symbolize;
+pub mod stop;
+pub mod task_query;
+pub mod telemetry;
+pub mod trace;
+pub mod unwind;
+pub mod watchpoint;
 
-pub use debugger::{Debugger, StackFrame};
+pub use debugger::{AsyncTeardown, BuildIdCheck, Debugger, InferiorId, InferiorSummary, StackFrame, UprobeCollection, WarningCallback};
+pub use async_assert::{AsyncAssertion, ASSERTION_FAILED_EXIT_CODE};
+pub use binary_info::{BinaryReport, SplitDebugInfo};
+pub use bpgroup::{BreakpointGroup, BreakpointGroups, GroupActivation, GroupMember};
 pub use breakpoint::{Breakpoint, BreakpointId, BreakpointType};
-pub use command::Command;
-pub use expr_eval::{Expression, ExpressionEvaluator, EvaluationResult, parse_expression};
+pub use command::{BreakContext, Command};
+pub use convenience::{ConvenienceVariables, HistoryRef, Value};
+pub use display::{DisplayEntry, DisplayId};
+pub use doctor::{Check, CheckStatus, DoctorReport};
+pub use err_catch::{ErrCatchpoint, ErrReturn};
+pub use eval_cache::EvalCache;
+pub use ftrace::{CallEvent, CallRecord, FunctionTrace, TracedFunction};
+pub use future_name::{FutureNames, NameStyle};
+pub use help::{CommandHelp, HelpCategory};
+pub use panic::{PanicLocation, PanicReport};
+pub use patch::{Patch, PatchId};
+pub use poison::{PoisonCatch, PoisonedTask};
+pub use profile::{FunctionCount, Profile, ProfileLine};
+pub use instrument::{ArmedFunction, InstrumentationPlan, SymbolPattern};
+pub use logpoint::{LogTemplate, LogpointCallback};
+pub use memsize::{FunctionMemory, MemsizeReport, RootMemory, TaskMemory};
+pub use metrics::{BreakpointOverhead, MetricsServer, MetricsSnapshot, OverheadKind, PollTimer, StopTime};
+pub use step_filter::StepFilter;
+pub use symbol_memo::SymbolMemo;
+pub use supervise::{AfterStop, StopAction, StopPlan, Supervision, Trigger};
+pub use symbolize::{format_addr2line, Symbolized, Symbolizer};
+pub use runtime::{RuntimeKind, RuntimeReport};
+pub use session::{BreakLocation, Session, SessionConfig, SessionEvent, SessionTarget};
+pub use state::{DebuggerState, ImportReport, STATE_FORMAT_VERSION};
+pub use stop::StopEvent;
+pub use unwind::CallerFrame;
+pub use task_query::{TaskQuery, TaskSort, TaskState};
+pub use telemetry::{AsyncEvent, AsyncEventKind, EventExporter, ExportConfig, ExportFormat, ExportStats};
+pub use trace::{BranchCount, InstructionTrace, TraceMode, TraceRecord};
+pub use watchpoint::{Watchpoint, WatchpointHit, WatchpointId};
+pub use expr_eval::{BinaryOp, Expression, ExpressionEvaluator, EvaluationResult, TypeExpr, parse_expression};
 
 // 他のクレートから使用するために再エクスポート
 pub use kokia_dwarf::Symbol;
-pub use kokia_target::StopReason;
-pub use kokia_async::{Tid, TaskInfo};
+pub use kokia_target::{MemoryMapping, ProcessInfo, Protection, PtDecoded, PtEvent, PtThreadTrace, Signal, SignalInfo, SpawnOptions, StopReason, WatchKind};
+pub use kokia_target::cleanup::{install_cleanup_handlers, kill_registered_inferiors};
+pub use kokia_target::interrupt::InterruptGuard;
+pub use kokia_async::{Tid, TaskInfo, TaskRef, AsyncStats, LifetimeStats, DEFAULT_STALL_THRESHOLD, RetentionPolicy, EvictionStats, EdgeFilter, TreeLine};
 
 /// デバッガの結果型
 pub type Result<T> = anyhow::Result<T>;
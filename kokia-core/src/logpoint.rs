@@ -0,0 +1,113 @@
+//! ログポイント（logpoint）
+//!
+//! 当たっても止まらず、書式文字列に埋め込んだ式を評価して1行出力してから実行を続ける
+//! ブレークポイントです。`print` を手で繰り返す代わりに、ループや頻繁に poll される
+//! async fn の中で値の移り変わりを追うのに使います。
+//!
+//! 書式は `x={x} state={self.__state}` のように `{式}` を埋め込み、`{{` と `}}` で波括弧そのものを書きます。
+
+use crate::breakpoint::BreakpointId;
+use crate::Result;
+use std::rc::Rc;
+
+/// ログポイントの出力を受け取るコールバック（ブレークポイント ID と、式を埋めた1行）
+pub type LogpointCallback = Rc<dyn Fn(BreakpointId, &str)>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Expression(String),
+}
+
+/// ログポイントの書式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogTemplate {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl LogTemplate {
+    /// 書式をパースする
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.next_if_eq(&'{').is_some() => text.push('{'),
+                '}' if chars.next_if_eq(&'}').is_some() => text.push('}'),
+                '{' => {
+                    let mut expression = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => expression.push(c),
+                            None => return Err(anyhow::anyhow!("Unclosed '{{' in log format: {}", source)),
+                        }
+                    }
+                    let expression = expression.trim();
+                    if expression.is_empty() {
+                        return Err(anyhow::anyhow!("Empty '{{}}' in log format: {}", source));
+                    }
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Expression(expression.to_string()));
+                }
+                '}' => return Err(anyhow::anyhow!("Unmatched '}}' in log format (write '}}}}'): {}", source)),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(Self { source: source.to_string(), segments })
+    }
+
+    /// 書いたままの書式
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// 埋め込んだ式（出てくる順）
+    pub fn expressions(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Expression(expression) => Some(expression.as_str()),
+            Segment::Text(_) => None,
+        })
+    }
+
+    /// 式を `evaluate` の結果で置き換えた1行を作る
+    pub fn render(&self, mut evaluate: impl FnMut(&str) -> String) -> String {
+        let mut line = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => line.push_str(text),
+                Segment::Expression(expression) => line.push_str(&evaluate(expression)),
+            }
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_template() {
+        let template = LogTemplate::parse("x={x} state={ self.__state } {{literal}}").unwrap();
+        assert_eq!(template.expressions().collect::<Vec<_>>(), vec!["x", "self.__state"]);
+        let line = template.render(|expression| match expression {
+            "x" => "42".to_string(),
+            _ => "Suspend0".to_string(),
+        });
+        assert_eq!(line, "x=42 state=Suspend0 {literal}");
+        assert_eq!(template.as_str(), "x={x} state={ self.__state } {{literal}}");
+
+        assert_eq!(LogTemplate::parse("plain").unwrap().render(|_| unreachable!()), "plain");
+        assert!(LogTemplate::parse("x={x").is_err());
+        assert!(LogTemplate::parse("x={}").is_err());
+        assert!(LogTemplate::parse("x}").is_err());
+    }
+}
@@ -0,0 +1,160 @@
+//! async タスクの状態機械のメモリ使用量（async memsize）
+//!
+//! 各タスクの状態機械の型（env 型）の DW_AT_byte_size を、ルートタスクごと・関数ごとに集計します。
+//! `.await` した子の Future は親の状態機械の中に埋め込まれるので、ルートの合計には
+//! 親の状態機械の外にある（Box などで別に確保された）子孫だけを足します。
+
+use kokia_async::TaskId;
+use std::collections::{HashMap, HashSet};
+
+/// 1つのタスクの状態機械
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskMemory {
+    pub id: TaskId,
+    pub handle: u32,
+    pub address: u64,
+    /// 関数名（async fn のパス）
+    pub function: Option<String>,
+    /// 状態機械の型のサイズ（型が分からなければ None）
+    pub size: Option<u64>,
+    /// await している親タスク
+    pub parent: Option<TaskId>,
+}
+
+/// ルートタスクごとの集計
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootMemory {
+    pub root: TaskMemory,
+    /// ルートと、親の外に確保された子孫のサイズの合計
+    pub total: u64,
+    /// ルート以下のタスク数（ルートを含む）
+    pub tasks: usize,
+}
+
+/// 関数ごとの集計
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionMemory {
+    pub function: String,
+    /// 状態機械1つのサイズ
+    pub size: u64,
+    /// その関数のタスク数
+    pub count: usize,
+}
+
+/// タスクのメモリ使用量の集計
+#[derive(Debug, Clone)]
+pub struct MemsizeReport {
+    tasks: Vec<TaskMemory>,
+}
+
+impl MemsizeReport {
+    pub fn new(tasks: Vec<TaskMemory>) -> Self {
+        Self { tasks }
+    }
+
+    pub fn tasks(&self) -> &[TaskMemory] {
+        &self.tasks
+    }
+
+    /// サイズの分からないタスクの数
+    pub fn unknown(&self) -> usize {
+        self.tasks.iter().filter(|task| task.size.is_none()).count()
+    }
+
+    /// サイズの大きい順に `limit` 件
+    pub fn largest(&self, limit: usize) -> Vec<&TaskMemory> {
+        let mut tasks: Vec<&TaskMemory> = self.tasks.iter().filter(|task| task.size.is_some()).collect();
+        tasks.sort_by(|a, b| b.size.cmp(&a.size).then(a.handle.cmp(&b.handle)));
+        tasks.truncate(limit);
+        tasks
+    }
+
+    /// ルートタスクごとの合計（多い順）
+    pub fn by_root(&self) -> Vec<RootMemory> {
+        let by_id: HashMap<TaskId, &TaskMemory> = self.tasks.iter().map(|task| (task.id, task)).collect();
+        let mut roots: HashMap<TaskId, RootMemory> = HashMap::new();
+        for task in &self.tasks {
+            let root = root_of(task, &by_id);
+            let entry = roots.entry(root.id).or_insert_with(|| RootMemory { root: root.clone(), total: 0, tasks: 0 });
+            entry.tasks += 1;
+            let parent = task.parent.and_then(|id| by_id.get(&id));
+            if !parent.is_some_and(|parent| contains(parent, task.address)) {
+                entry.total += task.size.unwrap_or(0);
+            }
+        }
+        let mut roots: Vec<RootMemory> = roots.into_values().collect();
+        roots.sort_by(|a, b| b.total.cmp(&a.total).then(a.root.handle.cmp(&b.root.handle)));
+        roots
+    }
+
+    /// 関数ごとの合計（合計の多い順）
+    pub fn by_function(&self) -> Vec<FunctionMemory> {
+        let mut functions: HashMap<&str, FunctionMemory> = HashMap::new();
+        for task in &self.tasks {
+            let (Some(function), Some(size)) = (task.function.as_deref(), task.size) else {
+                continue;
+            };
+            functions.entry(function)
+                .or_insert_with(|| FunctionMemory { function: function.to_string(), size, count: 0 })
+                .count += 1;
+        }
+        let mut functions: Vec<FunctionMemory> = functions.into_values().collect();
+        functions.sort_by(|a, b| {
+            (b.size * b.count as u64).cmp(&(a.size * a.count as u64)).then_with(|| a.function.cmp(&b.function))
+        });
+        functions
+    }
+}
+
+/// 親をたどって最も外側のタスクを求める（循環していたらそこで止める）
+fn root_of<'a>(task: &'a TaskMemory, by_id: &HashMap<TaskId, &'a TaskMemory>) -> &'a TaskMemory {
+    let mut current = task;
+    let mut seen = HashSet::new();
+    while let Some(parent) = current.parent.and_then(|id| by_id.get(&id)) {
+        if !seen.insert(current.id) {
+            break;
+        }
+        current = parent;
+    }
+    current
+}
+
+/// アドレスがタスクの状態機械の中にあるか
+fn contains(task: &TaskMemory, address: u64) -> bool {
+    task.size.is_some_and(|size| (task.address..task.address + size).contains(&address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: TaskId, function: &str, size: Option<u64>, parent: Option<TaskId>) -> TaskMemory {
+        TaskMemory { id, handle: id as u32, address: id * 0x1000, function: Some(function.to_string()), size, parent }
+    }
+
+    #[test]
+    fn test_memsize_report() {
+        let mut inline_child = task(2, "app::fetch", Some(0x200), Some(1));
+        inline_child.address = 0x1100;
+        let report = MemsizeReport::new(vec![
+            task(1, "app::handle", Some(0x800), None),
+            inline_child,
+            // Box::pin された子は親の外にある
+            task(3, "app::fetch", Some(0x200), Some(1)),
+            task(4, "app::tick", Some(0x40), None),
+            task(5, "app::unknown", None, Some(4)),
+        ]);
+
+        assert_eq!(report.unknown(), 1);
+        assert_eq!(report.largest(2).iter().map(|t| t.id).collect::<Vec<_>>(), vec![1, 2]);
+
+        let roots = report.by_root();
+        assert_eq!(roots.len(), 2);
+        assert_eq!((roots[0].root.id, roots[0].total, roots[0].tasks), (1, 0xa00, 3));
+        assert_eq!((roots[1].root.id, roots[1].total, roots[1].tasks), (4, 0x40, 2));
+
+        let functions = report.by_function();
+        assert_eq!(functions[0], FunctionMemory { function: "app::handle".to_string(), size: 0x800, count: 1 });
+        assert_eq!(functions[1], FunctionMemory { function: "app::fetch".to_string(), size: 0x200, count: 2 });
+    }
+}
@@ -0,0 +1,458 @@
+//! Prometheus 形式のメトリクス（attach --metrics）
+//!
+//! 長時間アタッチしたままのサービスを Grafana などで見られるよう、タスク数・停滞したタスク数・
+//! poll の回数と時間・計装による停止時間を HTTP で公開します。
+//!
+//! Debugger は別スレッドから触れないので、poll のイベントのたびに（間引いて）値を
+//! [`MetricsSnapshot`] に写し、公開用のスレッドはそれを読んで応答します。停滞の判定は
+//! 応答するときの時刻で行うので、デバッグ対象がイベントを出さずに止まっていても数えられます。
+
+use anyhow::Context;
+use crate::BreakpointType;
+use kokia_async::{RunningTime, TaskId};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// スナップショットを更新する最短の間隔
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 応答の読み書きのタイムアウト（遅いクライアントで公開が止まらないように）
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// poll の回数と、entry から exit までの時間の集計
+#[derive(Debug, Clone, Default)]
+pub struct PollTimer {
+    /// poll 中のタスク（スレッド, タスク）→ entry の時刻
+    starts: HashMap<(i32, TaskId), Instant>,
+    polls: u64,
+    timed_polls: u64,
+    poll_time: Duration,
+}
+
+impl PollTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_entry(&mut self, tid: i32, task: TaskId, at: Instant) {
+        self.polls += 1;
+        self.starts.insert((tid, task), at);
+    }
+
+    /// poll の時間（entry を見ていなければ None）
+    pub fn on_exit(&mut self, tid: i32, task: TaskId, at: Instant) -> Option<Duration> {
+        let duration = at.saturating_duration_since(self.starts.remove(&(tid, task))?);
+        self.timed_polls += 1;
+        self.poll_time += duration;
+        Some(duration)
+    }
+
+    /// entry を数えた poll の回数
+    pub fn polls(&self) -> u64 {
+        self.polls
+    }
+
+    /// exit まで見た poll の回数と時間の合計
+    pub fn timed(&self) -> (u64, Duration) {
+        (self.timed_polls, self.poll_time)
+    }
+
+    /// poll の平均時間
+    pub fn mean(&self) -> Option<Duration> {
+        mean_duration(self.poll_time, self.timed_polls)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// kokia が止めて、利用者に報告せずに再開した停止の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverheadKind {
+    /// async fn の poll の entry
+    AsyncEntry,
+    /// async fn の poll の exit（ret 命令）
+    AsyncExit,
+    /// スレッド・タスクの限定や `catch err` の判定で止まらなかったブレークポイント
+    Condition,
+    Logpoint,
+    /// 関数トレース（ftrace）の entry と exit
+    Ftrace,
+    /// パニックの追跡など、その他の内部ブレークポイント
+    Other,
+}
+
+impl OverheadKind {
+    pub const ALL: [OverheadKind; 6] = [
+        OverheadKind::AsyncEntry,
+        OverheadKind::AsyncExit,
+        OverheadKind::Condition,
+        OverheadKind::Logpoint,
+        OverheadKind::Ftrace,
+        OverheadKind::Other,
+    ];
+
+    /// 当たったブレークポイントの種類から（ブレークポイントでない停止は Other）
+    pub fn of(bp_type: Option<BreakpointType>) -> Self {
+        match bp_type {
+            Some(BreakpointType::AsyncEntry) => OverheadKind::AsyncEntry,
+            Some(BreakpointType::AsyncExit) => OverheadKind::AsyncExit,
+            Some(BreakpointType::User | BreakpointType::ErrReturn) => OverheadKind::Condition,
+            Some(BreakpointType::Logpoint) => OverheadKind::Logpoint,
+            Some(BreakpointType::TraceEntry | BreakpointType::TraceExit) => OverheadKind::Ftrace,
+            _ => OverheadKind::Other,
+        }
+    }
+
+    /// メトリクスのラベルの値
+    pub fn name(self) -> &'static str {
+        match self {
+            OverheadKind::AsyncEntry => "async_entry",
+            OverheadKind::AsyncExit => "async_exit",
+            OverheadKind::Condition => "condition",
+            OverheadKind::Logpoint => "logpoint",
+            OverheadKind::Ftrace => "ftrace",
+            OverheadKind::Other => "other",
+        }
+    }
+}
+
+/// 止まった回数と時間
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StopTime {
+    pub stops: u64,
+    pub stopped: Duration,
+}
+
+impl StopTime {
+    /// 1回あたりの時間
+    pub fn mean(&self) -> Option<Duration> {
+        mean_duration(self.stopped, self.stops)
+    }
+}
+
+/// 計装のブレークポイントで止まっていた回数と時間（観測による影響の見積もり）
+///
+/// 止まってから kokia が処理を終えて再開するまでを数えます。利用者に報告する停止は含みません。
+/// `running` は continue で走らせていた時間の合計で、止まっていた時間もこれに含まれます。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BreakpointOverhead {
+    by_kind: [StopTime; OverheadKind::ALL.len()],
+    pub running: Duration,
+}
+
+impl BreakpointOverhead {
+    pub fn record(&mut self, kind: OverheadKind, stopped: Duration) {
+        let time = &mut self.by_kind[kind as usize];
+        time.stops += 1;
+        time.stopped += stopped;
+    }
+
+    pub fn get(&self, kind: OverheadKind) -> StopTime {
+        self.by_kind[kind as usize]
+    }
+
+    pub fn total(&self) -> StopTime {
+        self.by_kind.iter().fold(StopTime::default(), |total, time| StopTime {
+            stops: total.stops + time.stops,
+            stopped: total.stopped + time.stopped,
+        })
+    }
+
+    /// 走らせていた時間のうち止まっていた割合
+    pub fn fraction(&self) -> Option<f64> {
+        (!self.running.is_zero()).then(|| (self.total().stopped.as_secs_f64() / self.running.as_secs_f64()).min(1.0))
+    }
+}
+
+/// 公開する値の写し
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// 未完了のタスクの最終観測時刻（数が生存タスク数、古いものが停滞）
+    pub live_last_seen: Vec<Instant>,
+    /// デバッグ対象が実行していた時間（停滞は実行中の時間だけで判定する）
+    pub running: RunningTime,
+    pub stall_threshold: Duration,
+    pub polls: u64,
+    pub timed_polls: u64,
+    pub poll_time: Duration,
+    pub overhead: BreakpointOverhead,
+}
+
+/// メトリクスを Prometheus のテキスト形式にする
+///
+/// `polls_per_second` は前回の応答からの poll の回数の増え方です（最初は None で 0 を出す）。
+pub fn render(snapshot: &MetricsSnapshot, now: Instant, polls_per_second: Option<f64>) -> String {
+    let stalled = snapshot.live_last_seen.iter()
+        .filter(|seen| snapshot.running.since(**seen, now) >= snapshot.stall_threshold)
+        .count();
+    let mut text = String::new();
+    metric(&mut text, "kokia_tasks_alive", "gauge", "Async tasks seen polled that have not completed", snapshot.live_last_seen.len());
+    let stalled_help = format!("Live async tasks not polled for {}s", snapshot.stall_threshold.as_secs_f64());
+    metric(&mut text, "kokia_tasks_stalled", "gauge", &stalled_help, stalled);
+    metric(&mut text, "kokia_polls_total", "counter", "Polls of instrumented async fns", snapshot.polls);
+    metric(&mut text, "kokia_polls_per_second", "gauge", "Polls per second since the previous scrape", polls_per_second.unwrap_or(0.0));
+    let _ = writeln!(
+        text,
+        "# HELP kokia_poll_duration_seconds Time from poll entry to exit, breakpoint handling included\n\
+         # TYPE kokia_poll_duration_seconds summary\n\
+         kokia_poll_duration_seconds_sum {}\n\
+         kokia_poll_duration_seconds_count {}",
+        snapshot.poll_time.as_secs_f64(),
+        snapshot.timed_polls
+    );
+    let overhead: Vec<(OverheadKind, StopTime)> = OverheadKind::ALL.iter()
+        .map(|kind| (*kind, snapshot.overhead.get(*kind)))
+        .collect();
+    let _ = writeln!(
+        text,
+        "# HELP kokia_breakpoint_overhead_seconds_total Time the target spent stopped at kokia's instrumentation breakpoints\n\
+         # TYPE kokia_breakpoint_overhead_seconds_total counter"
+    );
+    for (kind, time) in &overhead {
+        let _ = writeln!(text, "kokia_breakpoint_overhead_seconds_total{{kind=\"{}\"}} {}", kind.name(), time.stopped.as_secs_f64());
+    }
+    let _ = writeln!(
+        text,
+        "# HELP kokia_breakpoint_stops_total Stops at kokia's instrumentation breakpoints\n\
+         # TYPE kokia_breakpoint_stops_total counter"
+    );
+    for (kind, time) in &overhead {
+        let _ = writeln!(text, "kokia_breakpoint_stops_total{{kind=\"{}\"}} {}", kind.name(), time.stops);
+    }
+    text
+}
+
+/// `count` 回の合計 `total` の平均（u32 に収まらない回数でも切り詰めない）
+fn mean_duration(total: Duration, count: u64) -> Option<Duration> {
+    (count > 0).then(|| Duration::from_nanos((total.as_nanos() / count as u128) as u64))
+}
+
+fn metric(text: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(text, "# HELP {} {}\n# TYPE {} {}\n{} {}", name, help, name, kind, name, value);
+}
+
+/// `/metrics` を公開するスレッド
+pub struct MetricsServer {
+    address: SocketAddr,
+    snapshot: Arc<Mutex<MetricsSnapshot>>,
+    published: Option<Instant>,
+    stop: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// `address` で待ち受けを始める（ポート 0 なら空いているポート）
+    pub fn start(address: SocketAddr, stall_threshold: Duration) -> crate::Result<Self> {
+        let listener = TcpListener::bind(address).with_context(|| format!("Failed to listen on {}", address))?;
+        let address = listener.local_addr()?;
+        let snapshot = Arc::new(Mutex::new(MetricsSnapshot { stall_threshold, ..MetricsSnapshot::default() }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let worker = {
+            let (snapshot, stop) = (Arc::clone(&snapshot), Arc::clone(&stop));
+            std::thread::Builder::new()
+                .name("kokia-metrics".to_string())
+                .spawn(move || serve(&listener, &snapshot, &stop))
+                .context("Failed to start the metrics thread")?
+        };
+        Ok(Self { address, snapshot, published: None, stop, worker: Some(worker) })
+    }
+
+    /// 待ち受けているアドレス
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// 前回の更新から間があれば、`snapshot` で作った値に置き換える
+    pub fn publish_if_due(&mut self, now: Instant, snapshot: impl FnOnce(Duration) -> MetricsSnapshot) {
+        if self.published.is_some_and(|last| now.saturating_duration_since(last) < PUBLISH_INTERVAL) {
+            return;
+        }
+        self.published = Some(now);
+        let Ok(mut current) = self.snapshot.lock() else {
+            return;
+        };
+        *current = snapshot(current.stall_threshold);
+    }
+
+    /// 実行時間の記録だけを置き換える（再開したときに、次の更新を待たずに停滞を数え始めるため）
+    pub fn set_running_time(&self, running: &RunningTime) {
+        if let Ok(mut current) = self.snapshot.lock() {
+            current.running = running.clone();
+        }
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // accept で待っているスレッドを起こす
+        let _ = TcpStream::connect_timeout(&self.address, CLIENT_TIMEOUT);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn serve(listener: &TcpListener, snapshot: &Mutex<MetricsSnapshot>, stop: &AtomicBool) {
+    let mut last_scrape: Option<(Instant, u64)> = None;
+    for stream in listener.incoming() {
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        let _ = stream.set_read_timeout(Some(CLIENT_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(CLIENT_TIMEOUT));
+        let Some(request) = read_request_head(&mut stream) else {
+            continue;
+        };
+        let path = request.split_whitespace().nth(1).unwrap_or_default();
+        let response = if request.starts_with("GET ") && (path == "/metrics" || path == "/") {
+            let now = Instant::now();
+            let Ok(current) = snapshot.lock().map(|snapshot| snapshot.clone()) else {
+                continue;
+            };
+            let rate = last_scrape.map(|(at, polls)| {
+                current.polls.saturating_sub(polls) as f64 / now.saturating_duration_since(at).as_secs_f64().max(1e-3)
+            });
+            last_scrape = Some((now, current.polls));
+            let body = render(&current, now, rate);
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        } else {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        };
+        let _ = stream.write_all(response.as_bytes());
+    }
+}
+
+/// リクエストのヘッダーまでを読む（本文は使わない）
+fn read_request_head(stream: &mut TcpStream) -> Option<String> {
+    let mut head = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") && head.len() < 8192 {
+        let read = stream.read(&mut buffer).ok()?;
+        if read == 0 {
+            break;
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+    Some(String::from_utf8_lossy(&head).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poll_timer_and_render() {
+        let start = Instant::now();
+        let mut timer = PollTimer::new();
+        timer.on_entry(7, 0x1000, start);
+        timer.on_entry(7, 0x2000, start + Duration::from_millis(10));
+        assert_eq!(timer.on_exit(7, 0x2000, start + Duration::from_millis(30)), Some(Duration::from_millis(20)));
+        assert_eq!(timer.on_exit(7, 0x1000, start + Duration::from_millis(40)), Some(Duration::from_millis(40)));
+        assert_eq!(timer.on_exit(8, 0x1000, start), None);
+        assert_eq!(timer.polls(), 2);
+        assert_eq!(timer.mean(), Some(Duration::from_millis(30)));
+
+        let snapshot = MetricsSnapshot {
+            live_last_seen: vec![start, start + Duration::from_secs(9)],
+            running: RunningTime::new(),
+            stall_threshold: Duration::from_secs(5),
+            polls: timer.polls(),
+            timed_polls: 2,
+            poll_time: Duration::from_millis(60),
+            overhead: {
+                let mut overhead = BreakpointOverhead::default();
+                overhead.record(OverheadKind::AsyncEntry, Duration::from_millis(300));
+                overhead.record(OverheadKind::AsyncExit, Duration::from_millis(200));
+                overhead
+            },
+        };
+        let text = render(&snapshot, start + Duration::from_secs(10), Some(2.5));
+        assert!(text.contains("# TYPE kokia_tasks_alive gauge\nkokia_tasks_alive 2\n"));
+        assert!(text.contains("\nkokia_tasks_stalled 1\n"));
+        assert!(text.contains("\nkokia_polls_per_second 2.5\n"));
+        assert!(text.contains("\nkokia_poll_duration_seconds_sum 0.06\nkokia_poll_duration_seconds_count 2\n"));
+        assert!(text.contains("\nkokia_breakpoint_overhead_seconds_total{kind=\"async_entry\"} 0.3\n"));
+        assert!(text.contains("\nkokia_breakpoint_stops_total{kind=\"condition\"} 0\n"));
+
+        // プロンプトで止まっていた時間は停滞に数えない
+        let mut running = RunningTime::new();
+        running.resume(start);
+        running.stop(start + Duration::from_secs(1));
+        let text = render(&MetricsSnapshot { running, ..snapshot }, start + Duration::from_secs(10), None);
+        assert!(text.contains("\nkokia_tasks_stalled 0\n"));
+    }
+
+    #[test]
+    fn test_poll_mean_with_count_beyond_u32() {
+        // u32 に切り詰めると 0 で割ることになる回数
+        let timer = PollTimer { timed_polls: 1 << 32, poll_time: Duration::from_secs(1 << 32), ..PollTimer::default() };
+        assert_eq!(timer.mean(), Some(Duration::from_secs(1)));
+        let timer = PollTimer { timed_polls: 3 << 31, poll_time: Duration::from_millis(3 << 31), ..PollTimer::default() };
+        assert_eq!(timer.mean(), Some(Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn test_stop_mean_with_count_beyond_u32() {
+        let time = StopTime { stops: 1 << 32, stopped: Duration::from_secs(2 << 32) };
+        assert_eq!(time.mean(), Some(Duration::from_secs(2)));
+        assert_eq!(StopTime::default().mean(), None);
+    }
+
+    #[test]
+    fn test_breakpoint_overhead_by_kind() {
+        assert_eq!(OverheadKind::of(Some(BreakpointType::User)), OverheadKind::Condition);
+        assert_eq!(OverheadKind::of(Some(BreakpointType::TraceExit)), OverheadKind::Ftrace);
+        assert_eq!(OverheadKind::of(None), OverheadKind::Other);
+
+        let mut overhead = BreakpointOverhead::default();
+        assert_eq!(overhead.fraction(), None);
+        overhead.record(OverheadKind::AsyncEntry, Duration::from_millis(30));
+        overhead.record(OverheadKind::AsyncEntry, Duration::from_millis(10));
+        overhead.record(OverheadKind::Condition, Duration::from_millis(10));
+        overhead.running = Duration::from_millis(200);
+        assert_eq!(overhead.get(OverheadKind::AsyncEntry).mean(), Some(Duration::from_millis(20)));
+        assert_eq!(overhead.total(), StopTime { stops: 3, stopped: Duration::from_millis(50) });
+        assert_eq!(overhead.fraction(), Some(0.25));
+    }
+
+    #[test]
+    fn test_metrics_server_answers_scrapes() {
+        let mut server = MetricsServer::start("127.0.0.1:0".parse().unwrap(), Duration::from_secs(5)).unwrap();
+        server.publish_if_due(Instant::now(), |stall_threshold| MetricsSnapshot {
+            live_last_seen: vec![Instant::now()],
+            stall_threshold,
+            polls: 3,
+            ..MetricsSnapshot::default()
+        });
+
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(server.address()).unwrap();
+            stream.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\nkokia_polls_total 3\n"));
+        assert!(response.contains("\nkokia_tasks_alive 1\n"));
+        assert!(get("/other").starts_with("HTTP/1.1 404"));
+        drop(server);
+    }
+}
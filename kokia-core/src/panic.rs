@@ -0,0 +1,198 @@
+//! Rust のパニックのキャッチポイント
+//!
+//! パニック位置は、パニックハンドラ（`rust_begin_unwind`）に渡される `PanicInfo` から、
+//! メッセージはアンワインドを始める `rust_panic` に渡されるペイロードから読みます。
+//! `rust_panic` に来た時点でパニックフックは終わっており、フォーマット済みの文字列ができています。
+//! std の型には DWARF の型情報が付いていないことが多いので、今の std のレイアウトを前提にしつつ、
+//! 読んだ値が文字列として妥当かを確かめながら取り出します。
+
+use crate::Result;
+use std::fmt;
+
+/// パニック位置を読むパニックハンドラのシンボル（先に見つかったものを使う）
+pub const PANIC_HANDLER_SYMBOLS: &[&str] = &["rust_begin_unwind", "begin_panic_handler"];
+
+/// キャッチポイントとして止まるシンボル
+pub const PANIC_SYMBOLS: &[&str] = &["rust_panic"];
+
+/// ターゲットから読む文字列の長さの上限
+const MAX_STR_LEN: usize = 64 * 1024;
+
+/// ペイロードとして読む大きさの上限
+const MAX_PAYLOAD_SIZE: usize = 64;
+
+/// パニックが起きたソース上の位置（`core::panic::Location`）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for PanicLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// キャッチしたパニック
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PanicReport {
+    /// パニックメッセージ（文字列でないペイロードなら None）
+    pub message: Option<String>,
+    /// パニック位置（パニックハンドラを通らなかった場合は None）
+    pub location: Option<PanicLocation>,
+}
+
+/// デマングル名がキャッチポイント用のシンボル `name` を指しているか
+///
+/// `__rustc::rust_panic` のようにパスが付いていても一致とみなし、`rust_panic_with_hook` のような
+/// 前方一致は除きます。
+pub fn is_panic_symbol(demangled: &str, name: &str) -> bool {
+    demangled == name || demangled.strip_suffix(name).is_some_and(|prefix| prefix.ends_with("::"))
+}
+
+/// `PanicInfo` のアドレスからパニック位置を読む
+///
+/// `PanicInfo` の先頭2ワードはメッセージと `Location` への参照なので、どちらが位置かは
+/// 指す先が `Location` として読めるかで判断します。
+pub fn read_panic_info_location(
+    read: &mut impl FnMut(u64, usize) -> Result<Vec<u8>>,
+    info: u64,
+) -> Option<PanicLocation> {
+    let words = read_words(read, info, 16)?;
+    words.into_iter().find_map(|address| read_location(read, address))
+}
+
+/// `Location { file: &str, line: u32, col: u32 }` を読む
+pub fn read_location(
+    read: &mut impl FnMut(u64, usize) -> Result<Vec<u8>>,
+    address: u64,
+) -> Option<PanicLocation> {
+    let bytes = read(address, 24).ok()?;
+    let words = to_words(&bytes[..16]);
+    let line = u32::from_le_bytes(bytes[16..20].try_into().ok()?);
+    let column = u32::from_le_bytes(bytes[20..24].try_into().ok()?);
+    if line == 0 {
+        return None;
+    }
+    let file = read_str(read, words[0], words[1]).or_else(|| read_str(read, words[1], words[0]))?;
+    if file.is_empty() {
+        return None;
+    }
+    Some(PanicLocation { file, line, column })
+}
+
+/// `&mut dyn PanicPayload`（データポインタと vtable）からメッセージを読む
+///
+/// vtable の2ワード目にある型の大きさで、`&'static str` を持つペイロードか、フォーマット済みの
+/// `String` を持つペイロードかを見分けます。
+pub fn read_payload_message(
+    read: &mut impl FnMut(u64, usize) -> Result<Vec<u8>>,
+    data: u64,
+    vtable: u64,
+) -> Option<String> {
+    let size = read_words(read, vtable + 8, 8)?[0] as usize;
+    if size == 0 || size > MAX_PAYLOAD_SIZE || !size.is_multiple_of(8) {
+        return None;
+    }
+    let words = read_words(read, data, size)?;
+
+    // &'static str
+    if let [a, b] = words[..] {
+        return read_str(read, a, b).or_else(|| read_str(read, b, a));
+    }
+
+    // Option<String>：連続する3ワードの (cap, ptr, len) を順番違いも含めて探す
+    for triple in words.windows(3) {
+        let (x, y, z) = (triple[0], triple[1], triple[2]);
+        for (cap, ptr, len) in [(x, y, z), (y, x, z), (z, x, y)] {
+            if len > cap {
+                continue;
+            }
+            if let Some(message) = read_str(read, ptr, len) {
+                return Some(message);
+            }
+        }
+    }
+    None
+}
+
+/// ptr と len から UTF-8 文字列を読む
+fn read_str(read: &mut impl FnMut(u64, usize) -> Result<Vec<u8>>, ptr: u64, len: u64) -> Option<String> {
+    let len = usize::try_from(len).ok().filter(|len| *len <= MAX_STR_LEN)?;
+    if ptr == 0 {
+        return None;
+    }
+    if len == 0 {
+        return Some(String::new());
+    }
+    String::from_utf8(read(ptr, len).ok()?).ok()
+}
+
+fn read_words(read: &mut impl FnMut(u64, usize) -> Result<Vec<u8>>, address: u64, size: usize) -> Option<Vec<u64>> {
+    let bytes = read(address, size).ok()?;
+    (bytes.len() == size).then(|| to_words(&bytes))
+}
+
+fn to_words(bytes: &[u8]) -> Vec<u64> {
+    bytes.chunks_exact(8)
+        .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// アドレスごとのバイト列を持つ疑似メモリ
+    struct FakeMemory(BTreeMap<u64, Vec<u8>>);
+
+    impl FakeMemory {
+        fn read(&self, address: u64, len: usize) -> Result<Vec<u8>> {
+            let (base, bytes) = self.0.range(..=address).next_back()
+                .ok_or_else(|| anyhow::anyhow!("unmapped 0x{:x}", address))?;
+            let start = (address - base) as usize;
+            bytes.get(start..start + len)
+                .map(|b| b.to_vec())
+                .ok_or_else(|| anyhow::anyhow!("unmapped 0x{:x}", address))
+        }
+    }
+
+    fn words(values: &[u64]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_read_panic_location_and_message() {
+        let message = "value too large: 5";
+        let mut location = words(&[0x3000, 13]);
+        location.extend(1u32.to_le_bytes());
+        location.extend(30u32.to_le_bytes());
+        let memory = FakeMemory(BTreeMap::from([
+            // PanicInfo { message: &Arguments, location: &Location, .. }
+            (0x1000, words(&[0x5000, 0x2000])),
+            (0x2000, location),
+            (0x3000, b"/tmp/panic.rs".to_vec()),
+            (0x4000, message.as_bytes().to_vec()),
+            // FormatStringPayload { string: Some(String { cap, ptr, len }), inner: &Arguments }
+            (0x6000, words(&[0x22, 0x4000, message.len() as u64, 0x5000])),
+            (0x7000, words(&[0, 32, 8])),
+            // StaticStrPayload(&'static str)
+            (0x8000, words(&[0x4000, 5])),
+            (0x9000, words(&[0, 16, 8])),
+        ]));
+        let mut read = |address, len| memory.read(address, len);
+
+        let location = read_panic_info_location(&mut read, 0x1000).unwrap();
+        assert_eq!(location.to_string(), "/tmp/panic.rs:1:30");
+        assert_eq!(read_payload_message(&mut read, 0x6000, 0x7000).as_deref(), Some(message));
+        assert_eq!(read_payload_message(&mut read, 0x8000, 0x9000).as_deref(), Some("value"));
+
+        assert!(is_panic_symbol("__rustc::rust_panic", "rust_panic"));
+        assert!(is_panic_symbol("rust_panic", "rust_panic"));
+        assert!(!is_panic_symbol("std::panicking::rust_panic_with_hook", "rust_panic"));
+        assert!(!is_panic_symbol("my_rust_panic", "rust_panic"));
+    }
+}
@@ -0,0 +1,218 @@
+//! コードパッチ管理
+//!
+//! 任意のアドレスにバイト列（またはアセンブルした命令）を書き込み、
+//! 元のバイト列を記録して `unpatch` で戻せるようにします。
+
+use crate::Result;
+use iced_x86::code_asm::CodeAssembler;
+use kokia_target::TargetMemory;
+
+/// パッチID
+pub type PatchId = usize;
+
+/// 適用中のパッチ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    pub id: PatchId,
+    pub address: u64,
+    /// 書き込み前のバイト列
+    pub original: Vec<u8>,
+    /// 書き込んだバイト列
+    pub patched: Vec<u8>,
+}
+
+impl Patch {
+    /// パッチが書き換える範囲の終端（排他的）
+    pub fn end(&self) -> u64 {
+        self.address + self.patched.len() as u64
+    }
+
+    /// 指定範囲と重なるか
+    pub fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.address < end && start < self.end()
+    }
+}
+
+/// パッチマネージャ
+///
+/// パッチ同士の重なりは許可しないため、どの順序で戻しても元のバイト列が復元されます。
+pub struct PatchManager {
+    patches: Vec<Patch>,
+    next_id: PatchId,
+}
+
+impl PatchManager {
+    /// 新しいパッチマネージャを作成する
+    pub fn new() -> Self {
+        Self {
+            patches: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// バイト列を書き込み、パッチとして記録する
+    pub fn apply(&mut self, address: u64, bytes: &[u8], memory: &dyn TargetMemory) -> Result<PatchId> {
+        if bytes.is_empty() {
+            return Err(anyhow::anyhow!("Patch must contain at least one byte"));
+        }
+        let end = address + bytes.len() as u64;
+        if let Some(existing) = self.patches.iter().find(|p| p.overlaps(address, end)) {
+            return Err(anyhow::anyhow!(
+                "Patch {} at 0x{:x} overlaps this range; unpatch it first",
+                existing.id,
+                existing.address
+            ));
+        }
+
+        let original = memory.read_memory(address, bytes.len())?;
+        memory.write_memory(address, bytes)?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.patches.push(Patch {
+            id,
+            address,
+            original,
+            patched: bytes.to_vec(),
+        });
+        Ok(id)
+    }
+
+    /// パッチを元に戻して削除する
+    pub fn revert(&mut self, id: PatchId, memory: &dyn TargetMemory) -> Result<Patch> {
+        let pos = self
+            .patches
+            .iter()
+            .position(|p| p.id == id)
+            .ok_or_else(|| anyhow::anyhow!("No patch number {}", id))?;
+        memory.write_memory(self.patches[pos].address, &self.patches[pos].original)?;
+        Ok(self.patches.remove(pos))
+    }
+
+    /// パッチを取得する
+    pub fn get(&self, id: PatchId) -> Option<&Patch> {
+        self.patches.iter().find(|p| p.id == id)
+    }
+
+    /// 適用中のパッチを適用順に取得する
+    pub fn all(&self) -> &[Patch] {
+        &self.patches
+    }
+
+    /// 記録をすべて破棄する（プロセス終了時など、戻す必要がない場合）
+    pub fn clear(&mut self) {
+        self.patches.clear();
+    }
+}
+
+impl Default for PatchManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 16進数のバイト列をパースする
+///
+/// `90 90 cc`、`9090cc`、`0x90 0x90` のいずれの形式も受け付けます。
+pub fn parse_hex_bytes(input: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for token in input.split(|c: char| c.is_whitespace() || c == ',') {
+        let digits = token
+            .strip_prefix("0x")
+            .or_else(|| token.strip_prefix("0X"))
+            .unwrap_or(token);
+        if digits.is_empty() {
+            continue;
+        }
+        if digits.len() % 2 != 0 {
+            return Err(anyhow::anyhow!("Odd number of hex digits in '{}'", token));
+        }
+        for i in (0..digits.len()).step_by(2) {
+            let byte = u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| anyhow::anyhow!("Invalid hex byte in '{}'", token))?;
+            bytes.push(byte);
+        }
+    }
+    if bytes.is_empty() {
+        return Err(anyhow::anyhow!("No bytes to patch"));
+    }
+    Ok(bytes)
+}
+
+/// `;` 区切りの命令列を x86_64 機械語にアセンブルする
+///
+/// 対応する命令は `nop`、`int3`、`ret`、`ud2`、`hlt`、`jmp <addr>`、`call <addr>` です。
+/// 分岐先は `resolve` で絶対アドレスに変換し、`ip` からの相対分岐としてエンコードします。
+pub fn assemble(
+    source: &str,
+    ip: u64,
+    mut resolve: impl FnMut(&str) -> Result<u64>,
+) -> Result<Vec<u8>> {
+    let mut asm = CodeAssembler::new(64).map_err(asm_error)?;
+
+    for insn in source.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+        let (mnemonic, operand) = match insn.split_once(char::is_whitespace) {
+            Some((m, op)) => (m, Some(op.trim())),
+            None => (insn, None),
+        };
+        match (mnemonic.to_ascii_lowercase().as_str(), operand) {
+            ("nop", None) => asm.nop(),
+            ("int3", None) => asm.int3(),
+            ("ret", None) => asm.ret(),
+            ("ud2", None) => asm.ud2(),
+            ("hlt", None) => asm.hlt(),
+            ("jmp", Some(target)) => asm.jmp(resolve(target)?),
+            ("call", Some(target)) => asm.call(resolve(target)?),
+            _ => return Err(anyhow::anyhow!("Unsupported instruction: {}", insn)),
+        }
+        .map_err(asm_error)?;
+    }
+
+    let bytes = asm.assemble(ip).map_err(asm_error)?;
+    if bytes.is_empty() {
+        return Err(anyhow::anyhow!("No instructions to assemble"));
+    }
+    Ok(bytes)
+}
+
+fn asm_error(e: iced_x86::IcedError) -> anyhow::Error {
+    anyhow::anyhow!("Failed to assemble: {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hex_bytes() {
+        assert_eq!(parse_hex_bytes("90 90 cc").unwrap(), vec![0x90, 0x90, 0xcc]);
+        assert_eq!(parse_hex_bytes("9090CC").unwrap(), vec![0x90, 0x90, 0xcc]);
+        assert_eq!(parse_hex_bytes("0x48,0x31").unwrap(), vec![0x48, 0x31]);
+        assert!(parse_hex_bytes("909").is_err());
+        assert!(parse_hex_bytes("zz").is_err());
+        assert!(parse_hex_bytes("").is_err());
+    }
+
+    #[test]
+    fn test_assemble() {
+        let no_symbols = |s: &str| crate::parse::parse_address(s);
+        assert_eq!(assemble("nop; int3; ret", 0x1000, no_symbols).unwrap(), vec![0x90, 0xcc, 0xc3]);
+        // 近い分岐先は rel8 になる: 0x1000 + 2 + 0x0e = 0x1010
+        assert_eq!(assemble("jmp 0x1010", 0x1000, no_symbols).unwrap(), vec![0xeb, 0x0e]);
+        // 遠い分岐先は rel32: 0x1000 + 5 + 0xffb = 0x2000
+        assert_eq!(
+            assemble("call 0x2000", 0x1000, no_symbols).unwrap(),
+            vec![0xe8, 0xfb, 0x0f, 0x00, 0x00]
+        );
+        assert!(assemble("mov rax, 1", 0x1000, no_symbols).is_err());
+        assert!(assemble(" ; ", 0x1000, no_symbols).is_err());
+    }
+
+    #[test]
+    fn test_patch_overlap() {
+        let patch = Patch { id: 1, address: 0x10, original: vec![0; 4], patched: vec![0x90; 4] };
+        assert!(patch.overlaps(0x13, 0x14));
+        assert!(!patch.overlaps(0x14, 0x18));
+        assert!(!patch.overlaps(0x0c, 0x10));
+    }
+}
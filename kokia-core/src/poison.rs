@@ -0,0 +1,92 @@
+//! パニックで使えなくなった async タスクのキャッチポイント（async catch poison）
+//!
+//! poll の途中でパニックすると、巻き戻しの途中で状態機械の discriminant が Panicked になり、
+//! その Future はもう poll できません。パニックハンドラでそのスレッドが poll 中だったタスクの
+//! 並びを控え、catch_unwind がパニックを受け止めた所（`__rust_panic_cleanup`）でそれらの
+//! discriminant を読み直して、Panicked に変わったものがあれば止まります。ランタイムは受け止めた直後に
+//! Future を破棄するので、後の停止で読み直すのでは間に合いません。
+
+use crate::PanicLocation;
+use kokia_async::{Tid, TaskId, PANICKED_DISCRIMINANT};
+use std::collections::HashMap;
+
+/// 巻き戻しの終わりに止まるシンボル
+pub const PANIC_CLEANUP_SYMBOLS: &[&str] = &["__rust_panic_cleanup"];
+
+/// `__rust_panic_cleanup` と、catch_unwind の中で受け止めたパニックを取り出す関数
+/// （古い std では `std::panicking::try::` の下、新しい std では `std::panicking::catch_unwind::` の下）
+pub const PANIC_CATCH_FRAMES: &[&str] = &[
+    "__rust_panic_cleanup",
+    "panic_unwind::",
+    "__rust_try",
+    "std::panicking::try::",
+    "std::panicking::catch_unwind::",
+];
+
+/// パニックで Panicked になったタスク
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoisonedTask {
+    /// Panicked になったうち最も内側（パニックが起きた）のタスク
+    pub task: TaskId,
+    /// Panicked になったタスク（ルートから順）
+    pub poisoned: Vec<TaskId>,
+    /// パニックの時に poll 中だった await チェーン（ルートから順）
+    pub chain: Vec<TaskId>,
+    /// パニックハンドラで読んだパニック位置
+    pub location: Option<PanicLocation>,
+}
+
+/// 巻き戻し中のパニックの記録
+#[derive(Debug, Default)]
+pub struct PoisonCatch {
+    unwinding: HashMap<Tid, (Vec<TaskId>, Option<PanicLocation>)>,
+}
+
+impl PoisonCatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// パニックの開始で、そのスレッドが poll 中だったタスクを控える
+    pub fn on_panic_start(&mut self, tid: Tid, chain: Vec<TaskId>, location: Option<PanicLocation>) {
+        if !chain.is_empty() {
+            self.unwinding.insert(tid, (chain, location));
+        }
+    }
+
+    /// パニックを受け止めた所で、控えたタスクのうち Panicked になったものを調べる
+    ///
+    /// poll の中の catch_unwind で受け止めた場合は状態機械まで巻き戻っていないので None です。
+    pub fn on_cleanup(&mut self, tid: Tid, mut read_discriminant: impl FnMut(TaskId) -> Option<u64>) -> Option<PoisonedTask> {
+        let (chain, location) = self.unwinding.remove(&tid)?;
+        let poisoned: Vec<TaskId> = chain.iter()
+            .copied()
+            .filter(|task| read_discriminant(*task) == Some(PANICKED_DISCRIMINANT))
+            .collect();
+        let task = *poisoned.last()?;
+        Some(PoisonedTask { task, poisoned, chain, location })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poison_found_at_cleanup() {
+        let mut catch = PoisonCatch::new();
+        catch.on_panic_start(Tid(7), vec![0x100, 0x200, 0x300], None);
+        // poll の中で受け止めたパニック: どれも Panicked になっていない
+        assert_eq!(catch.on_cleanup(Tid(7), |_| Some(3)), None);
+        assert_eq!(catch.on_cleanup(Tid(7), |_| Some(2)), None);
+
+        catch.on_panic_start(Tid(7), vec![0x100, 0x200, 0x300], None);
+        catch.on_panic_start(Tid(8), Vec::new(), None);
+        let discriminants = HashMap::from([(0x100, 4), (0x200, 2), (0x300, 2)]);
+        let poisoned = catch.on_cleanup(Tid(7), |task| discriminants.get(&task).copied()).unwrap();
+        assert_eq!(poisoned.task, 0x300);
+        assert_eq!(poisoned.poisoned, vec![0x200, 0x300]);
+        assert_eq!(poisoned.chain, vec![0x100, 0x200, 0x300]);
+        assert_eq!(catch.on_cleanup(Tid(8), |_| Some(2)), None);
+    }
+}
@@ -0,0 +1,185 @@
+//! 統計的プロファイル（profile start / profile report）
+//!
+//! `continue` で走らせている間に perf で周期的に取った PC とコールチェーンを関数名にして集計し、
+//! 関数ごとの自己時間・累積時間（flat）、呼び出し木（tree）、サンプルを含んでいた最も内側の
+//! async fn ごとの内訳を作ります。async fn の内訳で、どのタスクの poll が CPU を使っていたかが分かります。
+
+use std::collections::{HashMap, HashSet};
+
+/// サンプリング周波数の既定値（Hz、タイマーと同期しにくい値）
+pub const DEFAULT_PROFILE_FREQUENCY: u64 = 99;
+
+/// 関数ごとのサンプル数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCount {
+    pub name: String,
+    /// その関数の中で PC がサンプルされた数
+    pub self_samples: u64,
+    /// コールチェーンのどこかにその関数があった数
+    pub total_samples: u64,
+}
+
+/// 呼び出し木の1行（深さ優先、サンプルの多い子から）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileLine {
+    pub depth: usize,
+    pub name: String,
+    pub total_samples: u64,
+    pub self_samples: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Node {
+    name: String,
+    total: u64,
+    self_: u64,
+    children: HashMap<String, usize>,
+}
+
+/// 集計したプロファイル
+#[derive(Debug, Clone)]
+pub struct Profile {
+    samples: u64,
+    functions: HashMap<String, (u64, u64)>,
+    /// 呼び出し木（0 番は根、子は外側の関数から順）
+    nodes: Vec<Node>,
+    by_async_fn: HashMap<Option<String>, u64>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self { samples: 0, functions: HashMap::new(), nodes: vec![Node::default()], by_async_fn: HashMap::new() }
+    }
+
+    /// 1回のサンプルを加える
+    ///
+    /// `frames` は関数名のコールチェーン（サンプルした PC の関数から外側へ）、`async_fn` は
+    /// その中で最も内側の async fn です。
+    pub fn add(&mut self, frames: &[String], async_fn: Option<&str>) {
+        self.samples += 1;
+        *self.by_async_fn.entry(async_fn.map(str::to_string)).or_default() += 1;
+
+        // 再帰していても累積は1サンプルに1回だけ数える
+        let mut seen = HashSet::new();
+        for (i, name) in frames.iter().enumerate() {
+            let entry = self.functions.entry(name.clone()).or_default();
+            if i == 0 {
+                entry.0 += 1;
+            }
+            if seen.insert(name.as_str()) {
+                entry.1 += 1;
+            }
+        }
+
+        let mut node = 0;
+        self.nodes[0].total += 1;
+        for name in frames.iter().rev() {
+            let next = match self.nodes[node].children.get(name) {
+                Some(&child) => child,
+                None => {
+                    self.nodes.push(Node { name: name.clone(), ..Default::default() });
+                    let child = self.nodes.len() - 1;
+                    self.nodes[node].children.insert(name.clone(), child);
+                    child
+                }
+            };
+            node = next;
+            self.nodes[node].total += 1;
+        }
+        self.nodes[node].self_ += 1;
+    }
+
+    /// 集計したサンプル数
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// 関数ごとのサンプル数（自己時間の多い順）
+    pub fn flat(&self) -> Vec<FunctionCount> {
+        let mut flat: Vec<FunctionCount> = self.functions.iter()
+            .map(|(name, &(self_samples, total_samples))| FunctionCount { name: name.clone(), self_samples, total_samples })
+            .collect();
+        flat.sort_by(|a, b| {
+            b.self_samples.cmp(&a.self_samples)
+                .then(b.total_samples.cmp(&a.total_samples))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        flat
+    }
+
+    /// 呼び出し木を深さ優先で並べる（全体の `min_fraction` 未満の部分木は省く）
+    pub fn tree(&self, min_fraction: f64) -> Vec<ProfileLine> {
+        let min_samples = (self.samples as f64 * min_fraction).ceil() as u64;
+        let mut lines = Vec::new();
+        self.push_children(0, 0, min_samples.max(1), &mut lines);
+        lines
+    }
+
+    fn push_children(&self, node: usize, depth: usize, min_samples: u64, lines: &mut Vec<ProfileLine>) {
+        let mut children: Vec<&Node> = self.nodes[node].children.values().map(|&child| &self.nodes[child]).collect();
+        children.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)));
+        for child in children.into_iter().filter(|child| child.total >= min_samples) {
+            lines.push(ProfileLine { depth, name: child.name.clone(), total_samples: child.total, self_samples: child.self_ });
+            let index = self.nodes[node].children[&child.name];
+            self.push_children(index, depth + 1, min_samples, lines);
+        }
+    }
+
+    /// 最も内側の async fn ごとのサンプル数（多い順、async fn の外は None）
+    pub fn by_async_fn(&self) -> Vec<(Option<String>, u64)> {
+        let mut counts: Vec<(Option<String>, u64)> = self.by_async_fn.iter().map(|(name, &n)| (name.clone(), n)).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_profile() {
+        let mut profile = Profile::new();
+        profile.add(&frames(&["app::hash", "app::handle::{{closure}}", "main"]), Some("app::handle::{{closure}}"));
+        profile.add(&frames(&["app::hash", "app::handle::{{closure}}", "main"]), Some("app::handle::{{closure}}"));
+        profile.add(&frames(&["app::fib", "app::fib", "main"]), None);
+        profile.add(&frames(&["main"]), None);
+        assert_eq!(profile.samples(), 4);
+
+        let flat = profile.flat();
+        assert_eq!(flat[0], FunctionCount { name: "app::hash".into(), self_samples: 2, total_samples: 2 });
+        let fib = flat.iter().find(|f| f.name == "app::fib").unwrap();
+        assert_eq!((fib.self_samples, fib.total_samples), (1, 1));
+        let main = flat.iter().find(|f| f.name == "main").unwrap();
+        assert_eq!((main.self_samples, main.total_samples), (1, 4));
+
+        let tree = profile.tree(0.0);
+        let names: Vec<(usize, &str, u64)> = tree.iter().map(|l| (l.depth, l.name.as_str(), l.total_samples)).collect();
+        assert_eq!(
+            names,
+            vec![
+                (0, "main", 4),
+                (1, "app::handle::{{closure}}", 2),
+                (2, "app::hash", 2),
+                (1, "app::fib", 1),
+                (2, "app::fib", 1),
+            ]
+        );
+        assert_eq!(profile.tree(0.5).len(), 3);
+
+        assert_eq!(
+            profile.by_async_fn(),
+            vec![(None, 2), (Some("app::handle::{{closure}}".to_string()), 2)]
+        );
+    }
+}
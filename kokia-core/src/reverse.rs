@@ -0,0 +1,214 @@
+//! 命令単位の逆ステップ（reverse-stepi）
+//!
+//! `step` で1命令進めるたびに、実行前のレジスタと、その命令が書き込むメモリの元の内容を
+//! 小さな undo ログに残します。`reverse-stepi` は新しいものから順にこれを書き戻して数命令だけ
+//! 巻き戻します。書き込み先は命令をデコードして求めるため、システムコール中にカーネルが書いた
+//! メモリや他スレッドによる書き込みは戻せません。
+
+use crate::Result;
+use iced_x86::{Decoder, DecoderOptions, InstructionInfoFactory, OpAccess, Register};
+use kokia_target::UserRegs;
+use std::collections::VecDeque;
+
+/// undo ログに残す命令数の既定値
+pub const DEFAULT_UNDO_CAPACITY: usize = 64;
+
+/// x86_64 の命令長の上限
+pub const MAX_INSTRUCTION_LEN: usize = 15;
+
+/// ステップ実行した1命令の巻き戻しに必要な情報
+pub struct UndoEntry {
+    /// ステップ実行したスレッド
+    pub tid: i32,
+    /// 実行前のレジスタ
+    pub regs: UserRegs,
+    /// 命令が書き込む範囲の、実行前の内容（アドレス, バイト列）
+    pub memory: Vec<(u64, Vec<u8>)>,
+    /// アドレスを求められず、記録できなかった書き込みの数
+    pub unrecorded_writes: usize,
+}
+
+impl UndoEntry {
+    /// 実行前の状態を記録する
+    ///
+    /// `code` は PC から読んだ命令バイト列（INT3 は元のバイトに戻しておく）、`read` は実行前の
+    /// メモリを読む関数です。読めなかった範囲は記録できなかった書き込みとして数えます。
+    pub fn capture(
+        tid: i32,
+        regs: UserRegs,
+        code: &[u8],
+        mut read: impl FnMut(u64, usize) -> Result<Vec<u8>>,
+    ) -> Self {
+        let (ranges, mut unrecorded_writes) = written_ranges(code, &regs);
+        let mut memory = Vec::new();
+        for (address, len) in ranges {
+            match read(address, len) {
+                Ok(bytes) => memory.push((address, bytes)),
+                Err(_) => unrecorded_writes += 1,
+            }
+        }
+        Self { tid, regs, memory, unrecorded_writes }
+    }
+
+    /// 実行前の PC
+    pub fn pc(&self) -> u64 {
+        self.regs.rip
+    }
+}
+
+/// 直近のステップ実行の undo ログ（古いものから溢れる）
+pub struct UndoLog {
+    entries: VecDeque<UndoEntry>,
+    capacity: usize,
+}
+
+impl UndoLog {
+    /// 指定件数まで保持する undo ログを作成する
+    pub fn new(capacity: usize) -> Self {
+        Self { entries: VecDeque::new(), capacity }
+    }
+
+    /// ステップ実行を記録する
+    pub fn push(&mut self, entry: UndoEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// 最も新しい記録を取り出す
+    pub fn pop(&mut self) -> Option<UndoEntry> {
+        self.entries.pop_back()
+    }
+
+    /// 最も新しい記録
+    pub fn last(&self) -> Option<&UndoEntry> {
+        self.entries.back()
+    }
+
+    /// 記録している命令数
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// 記録がないか
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 記録をすべて破棄する（continue などで履歴が途切れたとき）
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl Default for UndoLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_UNDO_CAPACITY)
+    }
+}
+
+/// PC の命令が書き込むメモリ範囲と、アドレスを求められなかった書き込みの数
+///
+/// push / call のような暗黙のスタック書き込みも含みます。
+pub fn written_ranges(code: &[u8], regs: &UserRegs) -> (Vec<(u64, usize)>, usize) {
+    let mut decoder = Decoder::with_ip(64, code, regs.rip, DecoderOptions::NONE);
+    let instruction = decoder.decode();
+    if instruction.is_invalid() {
+        return (Vec::new(), 0);
+    }
+
+    let mut factory = InstructionInfoFactory::new();
+    let mut ranges = Vec::new();
+    let mut unresolved = 0;
+    for used in factory.info(&instruction).used_memory() {
+        let writes = matches!(
+            used.access(),
+            OpAccess::Write | OpAccess::CondWrite | OpAccess::ReadWrite | OpAccess::ReadCondWrite
+        );
+        if !writes {
+            continue;
+        }
+        let size = used.memory_size().size();
+        match used.virtual_address(0, |reg, _, _| register_value(regs, reg)) {
+            Some(address) if size > 0 => ranges.push((address, size)),
+            _ => unresolved += 1,
+        }
+    }
+    (ranges, unresolved)
+}
+
+/// アドレス計算に使うレジスタの値（セグメントレジスタはベースアドレス）
+fn register_value(regs: &UserRegs, reg: Register) -> Option<u64> {
+    let value = match reg {
+        Register::ES | Register::CS | Register::SS | Register::DS => return Some(0),
+        Register::FS => return Some(regs.fs_base),
+        Register::GS => return Some(regs.gs_base),
+        _ => match reg.full_register() {
+            Register::RAX => regs.rax,
+            Register::RBX => regs.rbx,
+            Register::RCX => regs.rcx,
+            Register::RDX => regs.rdx,
+            Register::RSI => regs.rsi,
+            Register::RDI => regs.rdi,
+            Register::RBP => regs.rbp,
+            Register::RSP => regs.rsp,
+            Register::R8 => regs.r8,
+            Register::R9 => regs.r9,
+            Register::R10 => regs.r10,
+            Register::R11 => regs.r11,
+            Register::R12 => regs.r12,
+            Register::R13 => regs.r13,
+            Register::R14 => regs.r14,
+            Register::R15 => regs.r15,
+            Register::RIP => regs.rip,
+            // ベクトルレジスタ（VSIB）などは扱わない
+            _ => return None,
+        },
+    };
+    // 32 ビットのアドレスレジスタ（esi など）は下位だけを使う
+    Some(match reg.size() {
+        4 => value & 0xffff_ffff,
+        2 => value & 0xffff,
+        _ => value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regs_at(rip: u64) -> UserRegs {
+        let mut regs: UserRegs = unsafe { std::mem::zeroed() };
+        regs.rip = rip;
+        regs.rsp = 0x7000;
+        regs.rdi = 0x5000;
+        regs
+    }
+
+    #[test]
+    fn test_written_ranges() {
+        let regs = regs_at(0x1000);
+        // push rbp: rsp-8 に 8 バイト
+        assert_eq!(written_ranges(&[0x55], &regs), (vec![(0x6ff8, 8)], 0));
+        // mov dword [rdi+0x10], eax
+        assert_eq!(written_ranges(&[0x89, 0x47, 0x10], &regs), (vec![(0x5010, 4)], 0));
+        // mov eax, [rdi] は読み取りだけ
+        assert_eq!(written_ranges(&[0x8b, 0x07], &regs), (vec![], 0));
+        // mov byte [rip+0x20], 1（次の命令の先頭からの相対）
+        assert_eq!(written_ranges(&[0xc6, 0x05, 0x20, 0, 0, 0, 0x01], &regs), (vec![(0x1027, 1)], 0));
+
+        let mut log = UndoLog::new(2);
+        for pc in [0x1000, 0x1001, 0x1004] {
+            log.push(UndoEntry::capture(1, regs_at(pc), &[0x55], |_, len| Ok(vec![0; len])));
+        }
+        // 容量を超えた古い記録は捨て、新しいものから取り出す
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.pop().map(|e| e.pc()), Some(0x1004));
+        assert_eq!(log.pop().map(|e| (e.pc(), e.memory.len())), Some((0x1001, 1)));
+        assert!(log.is_empty());
+    }
+}
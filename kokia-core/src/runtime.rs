@@ -0,0 +1,209 @@
+//! async ランタイムの検出（info async-runtime）
+//!
+//! タスクグラフの読み方はランタイムによって変わるので、シンボルからどのランタイムが
+//! リンクされているかを調べ、バイナリに埋め込まれたソースパス（`tokio-1.48.0/src/...`）から
+//! バージョンを、生きているスレッドの名前からワーカースレッド数を求めます。
+
+use std::fmt;
+
+/// 検出したランタイムの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeKind {
+    Tokio,
+    AsyncStd,
+    Smol,
+    /// 既知のランタイムは見つからないが、futures の executor などで poll している
+    Custom,
+}
+
+impl RuntimeKind {
+    /// crates.io 上のクレート名（バージョンの検索に使う）
+    pub fn crate_name(self) -> Option<&'static str> {
+        match self {
+            RuntimeKind::Tokio => Some("tokio"),
+            RuntimeKind::AsyncStd => Some("async-std"),
+            RuntimeKind::Smol => Some("smol"),
+            RuntimeKind::Custom => None,
+        }
+    }
+
+    /// ワーカースレッドの名前の接頭辞（comm は 15 文字で切れる）
+    fn worker_thread_prefixes(self) -> &'static [&'static str] {
+        match self {
+            RuntimeKind::Tokio => &["tokio-runtime-w"],
+            RuntimeKind::AsyncStd => &["async-std/runti", "async-global-ex"],
+            RuntimeKind::Smol => &["smol-", "async-executor"],
+            RuntimeKind::Custom => &[],
+        }
+    }
+}
+
+impl fmt::Display for RuntimeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RuntimeKind::Tokio => "tokio",
+            RuntimeKind::AsyncStd => "async-std",
+            RuntimeKind::Smol => "smol",
+            RuntimeKind::Custom => "custom",
+        };
+        f.write_str(name)
+    }
+}
+
+/// ランタイムの検出結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeReport {
+    pub kind: RuntimeKind,
+    /// バイナリに埋め込まれたソースパスから求めたバージョン
+    pub version: Option<String>,
+    /// バイナリにリンクされているスケジューラ（tokio の current_thread / multi_thread など）
+    pub compiled_schedulers: Vec<&'static str>,
+    /// 名前がワーカースレッドのものだった生きているスレッドの数（プロセスがなければ None）
+    pub worker_threads: Option<usize>,
+    /// 使われているスケジューラの推定
+    pub scheduler: Option<String>,
+}
+
+/// シンボル名からリンクされているランタイムを調べる
+///
+/// `names` はデマングル名です。どのランタイムも見つからなかったときだけ `Custom` を返します
+/// （futures の executor で poll しているか、独自の executor を持っている場合）。
+pub fn detect_runtimes<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<(RuntimeKind, Vec<&'static str>)> {
+    let mut tokio = None::<Vec<&'static str>>;
+    let mut async_std = false;
+    let mut smol = false;
+    let mut custom = false;
+
+    for name in names {
+        let path = name.trim_start_matches('<');
+        if path.starts_with("tokio::runtime::") {
+            let schedulers = tokio.get_or_insert_with(Vec::new);
+            for (marker, scheduler) in TOKIO_SCHEDULERS {
+                if path.contains(marker) && !schedulers.contains(scheduler) {
+                    schedulers.push(scheduler);
+                }
+            }
+        } else if path.starts_with("async_std::") || path.starts_with("async_global_executor::") {
+            async_std = true;
+        } else if path.starts_with("smol::") || path.starts_with("async_executor::") {
+            smol = true;
+        } else if path.starts_with("futures_executor::") || path.contains(" as core::future::future::Future>::poll") {
+            custom = true;
+        }
+    }
+
+    let mut found = Vec::new();
+    if let Some(mut schedulers) = tokio {
+        schedulers.sort_unstable();
+        found.push((RuntimeKind::Tokio, schedulers));
+    }
+    if async_std {
+        found.push((RuntimeKind::AsyncStd, Vec::new()));
+    }
+    // async-std も内部で async-executor を使うので、smol は単独のときだけ報告する
+    if smol && !async_std {
+        found.push((RuntimeKind::Smol, Vec::new()));
+    }
+    if found.is_empty() && custom {
+        found.push((RuntimeKind::Custom, Vec::new()));
+    }
+    found
+}
+
+/// tokio のスケジューラを示すパス（古い版の名前も含む）
+const TOKIO_SCHEDULERS: &[(&str, &str)] = &[
+    ("::scheduler::current_thread::", "current_thread"),
+    ("::basic_scheduler::", "current_thread"),
+    ("::scheduler::multi_thread::", "multi_thread"),
+    ("::thread_pool::", "multi_thread"),
+];
+
+/// `.../tokio-1.48.0/src/...` のようなソースパスからクレートのバージョンを探す
+///
+/// 複数のバージョンが埋め込まれていれば、最も多く現れたものを返します。
+pub fn crate_version(haystack: &[u8], crate_name: &str) -> Option<String> {
+    let needle = format!("/{}-", crate_name);
+    let needle = needle.as_bytes();
+    let mut counts: Vec<(String, usize)> = Vec::new();
+
+    let mut rest = haystack;
+    while let Some(pos) = rest.windows(needle.len()).position(|w| w == needle) {
+        let tail = &rest[pos + needle.len()..];
+        let len = tail.iter().take(32).position(|&b| b == b'/').unwrap_or(0);
+        let candidate = &tail[..len];
+        let is_version = candidate.first().is_some_and(u8::is_ascii_digit)
+            && candidate.iter().all(|&b| b.is_ascii_alphanumeric() || b == b'.' || b == b'-' || b == b'+');
+        if is_version {
+            let version = String::from_utf8_lossy(candidate).into_owned();
+            match counts.iter_mut().find(|(v, _)| *v == version) {
+                Some((_, n)) => *n += 1,
+                None => counts.push((version, 1)),
+            }
+        }
+        rest = &rest[pos + needle.len()..];
+    }
+    counts.into_iter().max_by_key(|(_, n)| *n).map(|(v, _)| v)
+}
+
+/// スレッド名のうち、ランタイムのワーカースレッドのものを数える
+pub fn count_worker_threads<'a>(kind: RuntimeKind, thread_names: impl IntoIterator<Item = &'a str>) -> usize {
+    let prefixes = kind.worker_thread_prefixes();
+    thread_names.into_iter()
+        .filter(|name| prefixes.iter().any(|p| name.starts_with(p)))
+        .count()
+}
+
+/// リンクされているスケジューラとワーカースレッド数から、使われているスケジューラを推定する
+pub fn infer_scheduler(kind: RuntimeKind, compiled: &[&str], worker_threads: Option<usize>) -> Option<String> {
+    if kind != RuntimeKind::Tokio {
+        return None;
+    }
+    let has = |s: &str| compiled.contains(&s);
+    let scheduler = match worker_threads {
+        Some(n) if n > 0 && has("multi_thread") => format!("multi_thread ({} worker thread(s) alive)", n),
+        // current_thread ランタイムは block_on を呼んだスレッドで動き、ワーカーを持たない
+        Some(_) if has("current_thread") && !has("multi_thread") => "current_thread".to_string(),
+        Some(_) if has("current_thread") => "current_thread (no worker threads alive)".to_string(),
+        Some(_) => "multi_thread (no worker threads started yet)".to_string(),
+        None if compiled.len() == 1 => compiled[0].to_string(),
+        None => return None,
+    };
+    Some(scheduler)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_tokio_runtime() {
+        let symbols = [
+            "tokio::runtime::scheduler::multi_thread::worker::run",
+            "tokio::runtime::scheduler::current_thread::CurrentThread::block_on",
+            "<tokio::runtime::task::join::JoinHandle<T> as core::future::future::Future>::poll",
+            "simple_async::main",
+        ];
+        let found = detect_runtimes(symbols);
+        assert_eq!(found, vec![(RuntimeKind::Tokio, vec!["current_thread", "multi_thread"])]);
+
+        let haystack = b"/root/.cargo/registry/src/index.crates.io-1949cf8c6b5b557f/tokio-1.48.0/src/runtime/mod.rs\0\
+                         /root/.cargo/registry/src/index.crates.io-1949cf8c6b5b557f/tokio-macros-2.6.0/src/lib.rs\0\
+                         /x/tokio-1.48.0/src/task.rs";
+        assert_eq!(crate_version(haystack, "tokio").as_deref(), Some("1.48.0"));
+        assert_eq!(crate_version(haystack, "smol"), None);
+
+        let threads = ["simple_async", "tokio-runtime-w", "tokio-runtime-w"];
+        let workers = count_worker_threads(RuntimeKind::Tokio, threads);
+        assert_eq!(workers, 2);
+        let compiled = ["current_thread", "multi_thread"];
+        assert_eq!(
+            infer_scheduler(RuntimeKind::Tokio, &compiled, Some(workers)).as_deref(),
+            Some("multi_thread (2 worker thread(s) alive)")
+        );
+        assert_eq!(infer_scheduler(RuntimeKind::Tokio, &["current_thread"], Some(0)).as_deref(), Some("current_thread"));
+
+        // 既知のランタイムがなく、Future を自前で poll しているだけなら custom
+        let custom = detect_runtimes(["<app::Task as core::future::future::Future>::poll"]);
+        assert_eq!(custom, vec![(RuntimeKind::Custom, vec![])]);
+    }
+}
@@ -0,0 +1,260 @@
+//! 組み込み用の高水準 API（Session）
+//!
+//! CLI を通さずに kokia のデバッグエンジンを使うための入口です。テストハーネスやトレースの
+//! エクスポーターから、プロセスの起動・アタッチ、ブレークポイント、実行の再開、タスクや変数の
+//! 問い合わせを標準出力に何も書かずに行えます。停止・ログポイントの出力・警告はイベントとして溜まり、
+//! [`Session::drain_events`] で取り出します。
+//!
+//! ```no_run
+//! use kokia_core::{Session, SessionConfig, SessionEvent};
+//!
+//! let mut session = Session::launch(
+//!     SessionConfig::launch("./target/debug/app", &[]).with_async_tracking(None),
+//! )?;
+//! session.break_at("app::handle")?;
+//! let stop = session.resume()?;
+//! println!("stopped: {:?}, {} task(s)", stop.reason, session.tasks().len());
+//! for event in session.drain_events() {
+//!     if let SessionEvent::Logpoint { line, .. } = event {
+//!         println!("{}", line);
+//!     }
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! ここにない操作は [`Session::debugger_mut`] で [`Debugger`] を直接使います。
+
+use crate::{BreakpointId, Debugger, Result, StackFrame, StopEvent, SymbolPattern};
+use kokia_async::{AsyncStats, TaskInfo, DEFAULT_STALL_THRESHOLD};
+use kokia_target::{SpawnOptions, StopReason};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// デバッグ対象
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionTarget {
+    /// 実行可能ファイルを起動する
+    Launch { program: PathBuf, args: Vec<String> },
+    /// 動いているプロセスにアタッチする（`binary` はそのプロセスの実行可能ファイル）
+    Attach { binary: PathBuf, pid: i32 },
+}
+
+/// セッションの設定
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    pub target: SessionTarget,
+    pub spawn_options: SpawnOptions,
+    /// async トラッキングを有効にするか（中身は計装する関数のパターン、None ならすべて）
+    pub async_tracking: Option<Option<String>>,
+}
+
+impl SessionConfig {
+    /// 実行可能ファイルを起動する設定
+    pub fn launch(program: impl Into<PathBuf>, args: &[&str]) -> Self {
+        Self::new(SessionTarget::Launch {
+            program: program.into(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        })
+    }
+
+    /// 動いているプロセスにアタッチする設定
+    pub fn attach(binary: impl Into<PathBuf>, pid: i32) -> Self {
+        Self::new(SessionTarget::Attach { binary: binary.into(), pid })
+    }
+
+    fn new(target: SessionTarget) -> Self {
+        Self { target, spawn_options: SpawnOptions::default(), async_tracking: None }
+    }
+
+    /// 開始時に async トラッキングを有効にする（`filter` は計装する async fn のパターン）
+    pub fn with_async_tracking(mut self, filter: Option<&str>) -> Self {
+        self.async_tracking = Some(filter.map(str::to_string));
+        self
+    }
+
+    pub fn with_spawn_options(mut self, options: SpawnOptions) -> Self {
+        self.spawn_options = options;
+        self
+    }
+}
+
+/// セッションで起きたこと（古い順に溜まる）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    /// プロセスが停止した（終了も含む）
+    Stopped(Box<StopEvent>),
+    /// ログポイントが当たった
+    Logpoint { id: BreakpointId, line: String },
+    /// 処理は続けたが知らせておきたい問題（計装の一部の失敗など）
+    Warning(String),
+}
+
+/// ブレークポイントの位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BreakLocation {
+    /// 実行時アドレス（`0x` で始まる）
+    Address(u64),
+    /// `file:line`
+    FileLine(String, u32),
+    /// 関数名
+    Symbol(String),
+}
+
+impl BreakLocation {
+    /// `0x5555...`、`main.rs:30`、`app::handle` の形式をパースする
+    pub fn parse(location: &str) -> Result<Self> {
+        let location = location.trim();
+        if location.starts_with("0x") || location.starts_with("0X") {
+            return crate::parse::parse_address(location).map(BreakLocation::Address);
+        }
+        if let Some((file, line)) = location.rsplit_once(':') {
+            if let Ok(line) = line.parse() {
+                return Ok(BreakLocation::FileLine(file.to_string(), line));
+            }
+        }
+        if location.is_empty() {
+            return Err(anyhow::anyhow!("Empty breakpoint location"));
+        }
+        Ok(BreakLocation::Symbol(location.to_string()))
+    }
+}
+
+/// kokia のデバッグエンジンを包んだセッション
+pub struct Session {
+    debugger: Debugger,
+    events: Rc<RefCell<VecDeque<SessionEvent>>>,
+}
+
+impl Session {
+    /// 設定に従ってデバッグ対象を起動（またはアタッチ）し、停止した状態のセッションを返す
+    pub fn launch(config: SessionConfig) -> Result<Self> {
+        let events: Rc<RefCell<VecDeque<SessionEvent>>> = Rc::default();
+        let mut debugger = Debugger::new();
+        let sink = Rc::clone(&events);
+        debugger.set_logpoint_callback(Some(Rc::new(move |id, line: &str| {
+            sink.borrow_mut().push_back(SessionEvent::Logpoint { id, line: line.to_string() });
+        })));
+        let sink = Rc::clone(&events);
+        debugger.set_warning_callback(Some(Rc::new(move |message: &str| {
+            sink.borrow_mut().push_back(SessionEvent::Warning(message.to_string()));
+        })));
+
+        match &config.target {
+            SessionTarget::Launch { program, args } => {
+                debugger.load_binary(program)?;
+                debugger.spawn_with_options(program, args, &config.spawn_options)?;
+            }
+            SessionTarget::Attach { binary, pid } => {
+                debugger.load_binary(binary)?;
+                debugger.attach(*pid)?;
+            }
+        }
+
+        if let Some(filter) = &config.async_tracking {
+            let pattern = filter.as_deref().map(SymbolPattern::parse).transpose()?;
+            debugger.enable_async_instrumentation(pattern.as_ref())?;
+        }
+
+        Ok(Self { debugger, events })
+    }
+
+    /// ブレークポイントを設定する（位置の書式は [`BreakLocation::parse`]）
+    pub fn break_at(&mut self, location: &str) -> Result<BreakpointId> {
+        match BreakLocation::parse(location)? {
+            BreakLocation::Address(address) => self.debugger.set_breakpoint(address),
+            BreakLocation::FileLine(file, line) => self.debugger.set_breakpoint_by_file_line(&file, line),
+            BreakLocation::Symbol(name) => self.debugger.set_breakpoint_by_symbol(&name),
+        }
+    }
+
+    /// 次に停止するまで実行する（停止はイベントにも積む）
+    pub fn resume(&mut self) -> Result<StopEvent> {
+        let reason = self.debugger.continue_and_wait()?;
+        let event = self.debugger.last_stop().cloned().unwrap_or_else(|| StopEvent::new(reason));
+        self.events.borrow_mut().push_back(SessionEvent::Stopped(Box::new(event.clone())));
+        Ok(event)
+    }
+
+    /// プロセスが終了するまで実行し、終了コードを返す（途中の停止はイベントに積む）
+    pub fn run_to_exit(&mut self) -> Result<i32> {
+        loop {
+            let event = self.resume()?;
+            match event.reason {
+                StopReason::Exited(code) => return Ok(code),
+                StopReason::Killed(signal) => return Err(anyhow::anyhow!("Debuggee was killed by {:?}", signal)),
+                _ => {}
+            }
+            if !self.debugger.is_alive() {
+                return Err(anyhow::anyhow!("Debuggee terminated without an exit code ({:?})", event.reason));
+            }
+        }
+    }
+
+    /// 溜まったイベントを古い順にすべて取り出す
+    pub fn drain_events(&mut self) -> Vec<SessionEvent> {
+        self.events.borrow_mut().drain(..).collect()
+    }
+
+    /// 追跡中の async タスク（表示用の番号順）
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        self.debugger.async_tracker().all_tasks().into_iter().cloned().collect()
+    }
+
+    /// タスクの統計（停滞の判定は [`DEFAULT_STALL_THRESHOLD`]）
+    pub fn async_stats(&self) -> AsyncStats {
+        self.debugger.async_tracker().stats(self.debugger.running_time(), DEFAULT_STALL_THRESHOLD)
+    }
+
+    /// 選択中のスレッドのコールスタック
+    pub fn backtrace(&self) -> Result<Vec<StackFrame>> {
+        self.debugger.backtrace()
+    }
+
+    /// 現在のフレームの引数とローカル変数
+    pub fn locals(&self) -> Result<Vec<kokia_dwarf::Variable>> {
+        self.debugger.get_local_variables()
+    }
+
+    /// 式を評価して `print` と同じ書式の文字列にする
+    pub fn evaluate(&self, expression: &str) -> Result<String> {
+        let expression = crate::parse_expression(expression)?;
+        crate::ExpressionEvaluator::new(&self.debugger).evaluate_to_string(&expression)
+    }
+
+    /// デバッグ対象の終了コード（終了していなければ None）
+    pub fn exit_code(&self) -> Option<i32> {
+        self.debugger.exit_code()
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_break_location() {
+        assert_eq!(BreakLocation::parse("0x5555555551a0").unwrap(), BreakLocation::Address(0x5555555551a0));
+        assert_eq!(
+            BreakLocation::parse("src/main.rs:30").unwrap(),
+            BreakLocation::FileLine("src/main.rs".to_string(), 30)
+        );
+        // `add` は16進数としても読めるが関数名として扱う
+        assert_eq!(BreakLocation::parse("add").unwrap(), BreakLocation::Symbol("add".to_string()));
+        assert_eq!(
+            BreakLocation::parse("app::handle").unwrap(),
+            BreakLocation::Symbol("app::handle".to_string())
+        );
+        assert!(BreakLocation::parse(" ").is_err());
+        assert!(BreakLocation::parse("0xzz").is_err());
+    }
+}
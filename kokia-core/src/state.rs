@@ -0,0 +1,406 @@
+//! デバッガの状態のスナップショット（export-state / import-state）
+//!
+//! ブレークポイント、設定、async トラッカーが集めたタスクとエッジ、読み込まれていたモジュールを
+//! バージョン付きの JSON に書き出します。利用者の環境で起きた問題を添付してもらい、
+//! メンテナが同じバイナリで読み込んで `async tree` やブレークポイントを再現するためのものです。
+//!
+//! [`Instant`] はプロセスの外に持ち出せないので、時刻は書き出した時点からの経過ミリ秒で保存し、
+//! 読み込んだ時点から同じだけ遡った時刻に戻します。ブレークポイントは PIE でも使えるように
+//! ファイル上のアドレスで保存します。
+
+use crate::{BreakpointId, Result};
+use kokia_async::{Callsite, CallsiteId, Edge, RetentionPolicy, TaskId, TaskInfo, Tid};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// スナップショットの形式のバージョン（互換性のない変更をしたら上げる）
+pub const STATE_FORMAT_VERSION: u32 = 1;
+
+/// デバッガの状態のスナップショット
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebuggerState {
+    pub version: u32,
+    /// 書き出した kokia のバージョン
+    pub kokia_version: String,
+    pub binary: Option<BinarySnapshot>,
+    pub config: ConfigSnapshot,
+    pub breakpoints: Vec<BreakpointSnapshot>,
+    /// `display` で登録した式（登録順）
+    pub displays: Vec<String>,
+    pub tasks: Vec<TaskSnapshot>,
+    pub edges: Vec<EdgeSnapshot>,
+    pub callsites: Vec<CallsiteSnapshot>,
+    /// 書き出した時点でマップされていたファイル（プロセスがなければ空）
+    pub modules: Vec<ModuleSnapshot>,
+}
+
+/// 読み込んでいたバイナリ
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BinarySnapshot {
+    pub path: PathBuf,
+    pub build_id: Option<String>,
+}
+
+/// `set` で変えられる設定
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ConfigSnapshot {
+    pub async_summary: bool,
+    pub async_trace: bool,
+    pub crash_report: bool,
+    pub query_timeout_ms: Option<u64>,
+    pub step_filter: Vec<String>,
+    pub retention_max_tasks: Option<usize>,
+    pub retention_max_age_ms: Option<u64>,
+}
+
+impl ConfigSnapshot {
+    pub fn retention(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            max_tasks: self.retention_max_tasks,
+            max_age: self.retention_max_age_ms.map(Duration::from_millis),
+        }
+    }
+}
+
+/// ユーザーのブレークポイント（async 計装などの内部のものは含めない）
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BreakpointSnapshot {
+    pub id: BreakpointId,
+    /// ファイル上のアドレス
+    pub offset: u64,
+    pub thread: Option<i32>,
+    pub task: Option<TaskId>,
+    /// ログポイントの書式
+    pub logpoint: Option<String>,
+}
+
+/// async タスク
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskSnapshot {
+    pub id: TaskId,
+    pub handle: u32,
+    pub address: u64,
+    pub generation: u32,
+    pub type_name: Option<String>,
+    pub first_rip: Option<u64>,
+    pub last_rip: Option<u64>,
+    pub discriminant: Option<u64>,
+    pub is_root: bool,
+    pub completed: bool,
+    pub pre_existing: bool,
+    pub superseded: bool,
+    pub last_tid: Option<i32>,
+    pub migrations: u32,
+    /// 最初に観測してから書き出すまで
+    pub age_ms: u64,
+    /// 最後に観測してから書き出すまで
+    pub idle_ms: u64,
+    /// 終了してから書き出すまで
+    pub finished_ms: Option<u64>,
+}
+
+impl TaskSnapshot {
+    pub fn capture(task: &TaskInfo, now: Instant) -> Self {
+        Self {
+            id: task.id,
+            handle: task.handle,
+            address: task.address,
+            generation: task.generation,
+            type_name: task.type_name.clone(),
+            first_rip: task.first_rip,
+            last_rip: task.last_rip,
+            discriminant: task.current_discriminant,
+            is_root: task.is_root,
+            completed: task.completed,
+            pre_existing: task.pre_existing,
+            superseded: task.superseded,
+            last_tid: task.last_tid.map(|tid| tid.0),
+            migrations: task.migrations,
+            age_ms: millis_before(now, task.first_seen),
+            idle_ms: millis_before(now, task.last_seen),
+            finished_ms: task.finished_at.map(|at| millis_before(now, at)),
+        }
+    }
+
+    /// `now` を書き出した時点とみなしてタスク情報に戻す（論理スタックは空になる）
+    pub fn to_task(&self, now: Instant) -> TaskInfo {
+        let mut task = TaskInfo::with_generation(self.address, self.generation);
+        task.id = self.id;
+        task.handle = self.handle;
+        task.type_name = self.type_name.clone();
+        task.first_rip = self.first_rip;
+        task.last_rip = self.last_rip;
+        task.current_discriminant = self.discriminant;
+        task.is_root = self.is_root;
+        task.completed = self.completed;
+        task.pre_existing = self.pre_existing;
+        task.superseded = self.superseded;
+        task.last_tid = self.last_tid.map(Tid);
+        task.migrations = self.migrations;
+        task.first_seen = instant_before(now, self.age_ms);
+        task.last_seen = instant_before(now, self.idle_ms);
+        task.finished_at = self.finished_ms.map(|ms| instant_before(now, ms));
+        task
+    }
+}
+
+/// 親タスクが子タスクを await している関係
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EdgeSnapshot {
+    pub parent: TaskId,
+    pub child: TaskId,
+    /// 呼び出しサイトの ID（16進数。JSON の数値には収まらない）
+    pub callsite: String,
+    pub completed: bool,
+    pub age_ms: u64,
+    pub idle_ms: u64,
+}
+
+/// await している位置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallsiteSnapshot {
+    /// 書き出したときの ID（エッジの `callsite` と対応する）
+    pub id: String,
+    pub parent: TaskId,
+    pub suspend_idx: Option<u32>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+/// マップされていたファイル
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleSnapshot {
+    pub path: String,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ModuleSnapshot {
+    /// メモリマッピングをファイルごとにまとめる（疑似名と匿名のマッピングは除く）
+    pub fn from_mappings(mappings: &[kokia_target::MemoryMapping]) -> Vec<Self> {
+        let mut modules: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+        for mapping in mappings {
+            let Some(path) = mapping.path.as_deref().filter(|path| path.starts_with('/')) else {
+                continue;
+            };
+            let range = modules.entry(path).or_insert((u64::MAX, 0));
+            range.0 = range.0.min(mapping.start as u64);
+            range.1 = range.1.max(mapping.end as u64);
+        }
+        let mut modules: Vec<Self> = modules.into_iter()
+            .map(|(path, (start, end))| Self { path: path.to_string(), start, end })
+            .collect();
+        modules.sort_by_key(|module| module.start);
+        modules
+    }
+}
+
+impl DebuggerState {
+    /// async トラッカーの中身をスナップショットにする
+    pub fn capture_async<'a>(
+        &mut self,
+        tasks: impl IntoIterator<Item = &'a TaskInfo>,
+        edges: impl IntoIterator<Item = &'a Edge>,
+        callsites: impl IntoIterator<Item = &'a Callsite>,
+        now: Instant,
+    ) {
+        self.tasks = tasks.into_iter().map(|task| TaskSnapshot::capture(task, now)).collect();
+        self.tasks.sort_by_key(|task| task.handle);
+        self.edges = edges.into_iter()
+            .map(|edge| EdgeSnapshot {
+                parent: edge.parent,
+                child: edge.child,
+                callsite: format!("{:x}", edge.callsite.0),
+                completed: edge.completed,
+                age_ms: millis_before(now, edge.first_seen),
+                idle_ms: millis_before(now, edge.last_seen),
+            })
+            .collect();
+        self.edges.sort_by_key(|edge| (edge.parent, edge.child));
+        self.callsites = callsites.into_iter()
+            .map(|callsite| CallsiteSnapshot {
+                id: format!("{:x}", callsite.compute_id().0),
+                parent: callsite.parent,
+                suspend_idx: callsite.suspend_idx,
+                file: callsite.file.clone(),
+                line: callsite.line,
+            })
+            .collect();
+        self.callsites.sort_by(|a, b| (a.parent, &a.file, a.line).cmp(&(b.parent, &b.file, b.line)));
+    }
+
+    /// async トラッカーに戻すタスク・エッジ・呼び出しサイト
+    ///
+    /// 呼び出しサイトの ID は中身のハッシュなので、書き出した kokia とハッシュが違っても
+    /// エッジが同じ呼び出しサイトを指すように付け替えます。
+    pub fn restore_async(&self, now: Instant) -> Result<(Vec<TaskInfo>, Vec<Edge>, Vec<Callsite>)> {
+        let tasks = self.tasks.iter().map(|task| task.to_task(now)).collect();
+
+        let mut callsite_ids = HashMap::new();
+        let mut callsites = Vec::with_capacity(self.callsites.len());
+        for snapshot in &self.callsites {
+            let callsite = Callsite {
+                parent: snapshot.parent,
+                suspend_idx: snapshot.suspend_idx,
+                file: snapshot.file.clone(),
+                line: snapshot.line,
+            };
+            callsite_ids.insert(parse_id(&snapshot.id)?, callsite.compute_id());
+            callsites.push(callsite);
+        }
+
+        let edges = self.edges.iter()
+            .map(|snapshot| {
+                let stored = parse_id(&snapshot.callsite)?;
+                let callsite = callsite_ids.get(&stored).copied().unwrap_or(CallsiteId(stored));
+                let mut edge = Edge::new(snapshot.parent, snapshot.child, callsite);
+                edge.completed = snapshot.completed;
+                edge.first_seen = instant_before(now, snapshot.age_ms);
+                edge.last_seen = instant_before(now, snapshot.idle_ms);
+                Ok(edge)
+            })
+            .collect::<Result<_>>()?;
+        Ok((tasks, edges, callsites))
+    }
+
+    /// 読み込める形式のバージョンか確かめる
+    pub fn check_version(&self) -> Result<()> {
+        if self.version != STATE_FORMAT_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported state format version {} (this kokia reads version {})",
+                self.version, STATE_FORMAT_VERSION
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        // 形式が変わっていても、まずバージョンの違いとして報告する
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        let version = value.get("version").and_then(serde_json::Value::as_u64);
+        if version != Some(STATE_FORMAT_VERSION as u64) {
+            return Err(anyhow::anyhow!(
+                "Unsupported state format version {} (this kokia reads version {})",
+                version.map_or_else(|| "<missing>".to_string(), |v| v.to_string()), STATE_FORMAT_VERSION
+            ));
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// ファイルに書き出す
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_json()? + "\n")
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// ファイルから読み込む
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_json(&json).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))
+    }
+}
+
+/// 状態を読み込んだ結果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// 設定し直したブレークポイント（スナップショットの ID, 新しい ID）
+    pub breakpoints: Vec<(BreakpointId, BreakpointId)>,
+    /// プロセスがないため設定できなかったブレークポイント
+    pub pending_breakpoints: Vec<BreakpointSnapshot>,
+    pub tasks: usize,
+    pub edges: usize,
+    pub displays: usize,
+    /// 再現しきれなかったこと
+    pub warnings: Vec<String>,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Restored {} breakpoint(s), {} display(s), {} task(s), {} edge(s)",
+            self.breakpoints.len(), self.displays, self.tasks, self.edges
+        )?;
+        if !self.pending_breakpoints.is_empty() {
+            write!(f, "; {} breakpoint(s) need a running process (import again after 'run')", self.pending_breakpoints.len())?;
+        }
+        for warning in &self.warnings {
+            write!(f, "\nwarning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+fn millis_before(now: Instant, at: Instant) -> u64 {
+    now.saturating_duration_since(at).as_millis() as u64
+}
+
+fn instant_before(now: Instant, millis: u64) -> Instant {
+    now.checked_sub(Duration::from_millis(millis)).unwrap_or(now)
+}
+
+fn parse_id(id: &str) -> Result<u128> {
+    u128::from_str_radix(id, 16).map_err(|_| anyhow::anyhow!("Invalid callsite id in state: {}", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_round_trip() {
+        let now = Instant::now();
+        let mut parent = TaskInfo::new(0x7000);
+        parent.handle = 1;
+        parent.is_root = true;
+        parent.type_name = Some("app::serve".to_string());
+        parent.first_seen = now - Duration::from_secs(30);
+        parent.last_seen = now - Duration::from_secs(12);
+        let mut child = TaskInfo::with_generation(0x7100, 2);
+        child.handle = 4;
+        child.mark_completed();
+        let mut callsite = Callsite::new(parent.id);
+        callsite.file = Some("src/main.rs".to_string());
+        callsite.line = Some(42);
+        let edge = Edge::new(parent.id, child.id, callsite.compute_id());
+
+        let mut state = DebuggerState {
+            version: STATE_FORMAT_VERSION,
+            kokia_version: "0.1.0".to_string(),
+            binary: Some(BinarySnapshot { path: "/srv/app".into(), build_id: Some("ab12".to_string()) }),
+            config: ConfigSnapshot { async_summary: true, retention_max_age_ms: Some(5000), ..Default::default() },
+            breakpoints: vec![BreakpointSnapshot { id: 3, offset: 0x1a40, thread: None, task: Some(parent.id), logpoint: Some("x={x}".to_string()) }],
+            displays: vec!["self.__state".to_string()],
+            tasks: Vec::new(),
+            edges: Vec::new(),
+            callsites: Vec::new(),
+            modules: Vec::new(),
+        };
+        state.capture_async([&child, &parent], [&edge], [&callsite], now);
+        assert_eq!(state.tasks.iter().map(|t| t.handle).collect::<Vec<_>>(), vec![1, 4]);
+        assert_eq!(state.tasks[0].idle_ms, 12_000);
+
+        let loaded = DebuggerState::from_json(&state.to_json().unwrap()).unwrap();
+        assert_eq!(loaded, state);
+        assert_eq!(loaded.config.retention().max_age, Some(Duration::from_secs(5)));
+
+        let later = now + Duration::from_secs(60);
+        let (tasks, edges, callsites) = loaded.restore_async(later).unwrap();
+        assert_eq!(tasks[0].type_name.as_deref(), Some("app::serve"));
+        assert_eq!(later - tasks[0].last_seen, Duration::from_secs(12));
+        assert_eq!(tasks[1].id, child.id);
+        assert!(tasks[1].completed && tasks[1].finished_at.is_some());
+        assert_eq!(edges[0].callsite, callsites[0].compute_id());
+
+        let newer = DebuggerState::from_json(&state.to_json().unwrap().replacen("\"version\": 1", "\"version\": 2", 1));
+        assert!(newer.unwrap_err().to_string().contains("version 2"));
+    }
+}
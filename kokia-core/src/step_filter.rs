@@ -0,0 +1,95 @@
+//! ステップフィルタ
+//!
+//! `.await` にステップインすると、tokio の park ループや Pin の射影といったランタイム内部に入ってしまいます。
+//! デマングル名がフィルタの接頭辞に当たる関数にステップで入ったときは、戻りアドレスに
+//! テンポラリブレークポイントを置いて、ユーザーのコードに戻るまで実行を続けます。
+
+/// 既定でスキップするクレートの接頭辞
+pub const DEFAULT_STEP_FILTER: &[&str] = &[
+    "tokio::",
+    "core::",
+    "std::",
+    "alloc::",
+    "futures_util::",
+    "futures_core::",
+    "__rustc::",
+];
+
+/// ステップでスキップする関数の接頭辞の一覧
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepFilter {
+    prefixes: Vec<String>,
+}
+
+impl StepFilter {
+    /// 既定の接頭辞を持つフィルタを作成する
+    pub fn new() -> Self {
+        Self::with_prefixes(DEFAULT_STEP_FILTER.iter().map(|p| p.to_string()).collect())
+    }
+
+    /// 指定した接頭辞だけを持つフィルタを作成する（空ならフィルタしない）
+    pub fn with_prefixes(prefixes: Vec<String>) -> Self {
+        Self { prefixes }
+    }
+
+    /// デマングル名がスキップ対象か
+    ///
+    /// `<tokio::sync::Notified as core::future::Future>::poll` のような trait 実装は、実装している
+    /// 型のパスで判定します。ユーザーの型に対する `Future` 実装はスキップしません。`u32` や `str` の
+    /// ようにパスを持たない型の実装は trait 側のパスで判定します。
+    pub fn matches(&self, demangled: &str) -> bool {
+        let Some(inner) = demangled.strip_prefix('<') else {
+            return self.matches_path(demangled);
+        };
+        let (ty, rest) = inner.split_once(" as ").unwrap_or((inner, ""));
+        let ty = ty.trim_start_matches(['&', '*']).trim_start_matches("mut ").trim_start_matches("const ");
+        if ty.contains("::") {
+            self.matches_path(ty)
+        } else {
+            self.matches_path(rest)
+        }
+    }
+
+    fn matches_path(&self, path: &str) -> bool {
+        self.prefixes.iter().any(|prefix| path.starts_with(prefix.as_str()))
+    }
+
+    /// フィルタの接頭辞
+    pub fn prefixes(&self) -> &[String] {
+        &self.prefixes
+    }
+
+    /// フィルタが無効か
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+}
+
+impl Default for StepFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_filter_matches() {
+        let filter = StepFilter::new();
+        assert!(filter.matches("tokio::runtime::park::CachedParkThread::block_on"));
+        assert!(filter.matches("<core::pin::Pin<P> as core::future::future::Future>::poll"));
+        assert!(filter.matches("<&mut std::io::stdio::Stdout as std::io::Write>::write"));
+        assert!(!filter.matches("<simple_async::Timer as core::future::future::Future>::poll"));
+        assert!(!filter.matches("simple_async::compute::{async_fn#0}"));
+        assert!(filter.matches("<u32 as core::fmt::Display>::fmt"));
+        assert!(filter.matches("__rustc::__rust_alloc"));
+        // "tokio_util::" は "tokio::" に当たらない
+        assert!(!filter.matches("tokio_util::codec::Framed::poll_next"));
+
+        let off = StepFilter::with_prefixes(Vec::new());
+        assert!(off.is_empty());
+        assert!(!off.matches("core::ptr::drop_in_place"));
+    }
+}
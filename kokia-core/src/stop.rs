@@ -0,0 +1,80 @@
+//! 停止イベント
+//!
+//! [`StopReason`] は停止の種類だけを表します。フロントエンドやフックが追加の問い合わせなしに
+//! 反応できるよう、停止したスレッド・PC・ヒットしたブレークポイントやウォッチポイント・シグナルの詳細・
+//! async ブレークポイントで対象になったタスク・キャッチしたパニックをまとめて記録します。
+
+use crate::{BreakpointId, BreakpointType, ErrReturn, PanicReport, PoisonedTask, WatchpointHit};
+use kokia_async::TaskId;
+use kokia_target::{SignalInfo, StopReason};
+
+/// 停止イベントの詳細
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StopEvent {
+    /// 停止の種類
+    pub reason: StopReason,
+    /// 停止したスレッド（終了時は None）
+    pub tid: Option<i32>,
+    /// 停止位置の実行時アドレス（ブレークポイントなら INT3 の分を戻した値。終了時は None）
+    pub pc: Option<u64>,
+    /// ヒットしたブレークポイント
+    pub breakpoint: Option<(BreakpointId, BreakpointType)>,
+    /// ヒットしたウォッチポイントと、監視中のメモリの値の変化
+    pub watchpoint: Option<WatchpointHit>,
+    /// シグナルによる停止ならその詳細
+    pub signal: Option<SignalInfo>,
+    /// async ブレークポイントで poll に入った、または poll から戻ったタスク
+    pub task: Option<TaskId>,
+    /// パニックのキャッチポイントで止まったなら、読み取ったメッセージと位置
+    pub panic: Option<PanicReport>,
+    /// `catch err` のキャッチポイントで止まったなら、Err を返そうとしている関数と中身
+    pub err_return: Option<ErrReturn>,
+    /// `async catch poison` で止まったなら、パニックで Panicked になったタスク
+    pub poisoned: Option<PoisonedTask>,
+    /// poll から戻るところ（AsyncExit）や finish で戻った直後なら、DWARF の戻り値の型で読んだ戻り値
+    pub return_value: Option<String>,
+}
+
+impl StopEvent {
+    /// 停止の種類だけを持つイベントを作成する
+    pub fn new(reason: StopReason) -> Self {
+        Self {
+            reason,
+            tid: None,
+            pc: None,
+            breakpoint: None,
+            watchpoint: None,
+            signal: None,
+            task: None,
+            panic: None,
+            err_return: None,
+            poisoned: None,
+            return_value: None,
+        }
+    }
+
+    /// ヒットしたブレークポイントのID
+    pub fn breakpoint_id(&self) -> Option<BreakpointId> {
+        self.breakpoint.map(|(id, _)| id)
+    }
+
+    /// プロセスを終わらせる種類のシグナル（SIGSEGV、SIGABRT など）で止まったか
+    pub fn is_crash(&self) -> bool {
+        use kokia_target::Signal;
+
+        matches!(
+            self.reason,
+            StopReason::Signal(
+                Signal::SIGSEGV | Signal::SIGBUS | Signal::SIGILL | Signal::SIGFPE | Signal::SIGABRT | Signal::SIGSYS
+            )
+        )
+    }
+
+    /// async トラッキング用のブレークポイントで止まったか
+    pub fn is_async_breakpoint(&self) -> bool {
+        matches!(
+            self.breakpoint,
+            Some((_, BreakpointType::AsyncEntry | BreakpointType::AsyncExit))
+        )
+    }
+}
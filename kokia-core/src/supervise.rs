@@ -0,0 +1,193 @@
+//! 起動したプロセスの無人監視（kokia run --on-crash / --on-break）
+//!
+//! 不安定な async テストを kokia の下で何度も回し、落ちた回だけ材料を残すための仕組みです。
+//! 監視中は終了するまで continue を繰り返し、クラッシュ（致命的なシグナルかキャッチしたパニック）や
+//! ユーザーのブレークポイントで止まったら、指定された動作を順に行ってから
+//! 対話用に止まったままにするか、kokia を終了します。
+
+use crate::{BreakpointType, StopEvent};
+use kokia_target::StopReason;
+use std::fmt;
+use std::path::PathBuf;
+
+/// ブレークポイント（またはウォッチポイント・catch err）で止まって終了するときの kokia の終了コード
+pub const BREAK_TRIGGERED_EXIT_CODE: i32 = 4;
+
+/// キャッチしたパニックで終了するときの終了コード（パニックしたプロセスと同じ値）
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+/// 止まったときに行う動作
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopAction {
+    /// バックトレースと async タスクのツリーを表示する（クラッシュならレジスタと逆アセンブルも）
+    Report,
+    /// コアファイルを書き出す（パス省略時は core.<pid>）
+    Core(Option<PathBuf>),
+    /// ファイルに書いたコマンドを1行ずつ実行する
+    Source(PathBuf),
+}
+
+/// 動作を終えた後どうするか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AfterStop {
+    /// kokia を終了する（起動したプロセスも終わらせる）
+    #[default]
+    Exit,
+    /// 止まったまま対話用の REPL に入る
+    Hold,
+}
+
+/// `--on-crash` / `--on-break` に渡した動作の並び
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StopPlan {
+    pub actions: Vec<StopAction>,
+    pub then: AfterStop,
+}
+
+impl StopPlan {
+    /// `report,core=/tmp/core,source=cmds.txt,hold` のようなカンマ区切りの指定をパースする
+    ///
+    /// `hold` か `exit` は最後に1つだけ書けます（省略時は exit）。
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut plan = StopPlan::default();
+        let items: Vec<&str> = spec.split(',').map(str::trim).filter(|item| !item.is_empty()).collect();
+        if items.is_empty() {
+            return Err("expected actions such as 'report,core,exit'".to_string());
+        }
+        for (i, item) in items.iter().enumerate() {
+            let (name, value) = match item.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (*item, None),
+            };
+            let action = match (name, value) {
+                ("report", None) => StopAction::Report,
+                ("core", path) => StopAction::Core(path.map(PathBuf::from)),
+                ("source", Some(path)) if !path.is_empty() => StopAction::Source(PathBuf::from(path)),
+                ("source", _) => return Err("'source' needs a file: source=<path>".to_string()),
+                ("hold" | "exit", None) if i + 1 == items.len() => {
+                    plan.then = if name == "hold" { AfterStop::Hold } else { AfterStop::Exit };
+                    continue;
+                }
+                ("hold" | "exit", None) => return Err(format!("'{}' must be the last action", name)),
+                _ => return Err(format!("unknown action '{}' (expected report, core[=path], source=<path>, hold or exit)", item)),
+            };
+            plan.actions.push(action);
+        }
+        Ok(plan)
+    }
+}
+
+impl fmt::Display for StopPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for action in &self.actions {
+            match action {
+                StopAction::Report => write!(f, "report,")?,
+                StopAction::Core(None) => write!(f, "core,")?,
+                StopAction::Core(Some(path)) => write!(f, "core={},", path.display())?,
+                StopAction::Source(path) => write!(f, "source={},", path.display())?,
+            }
+        }
+        match self.then {
+            AfterStop::Exit => write!(f, "exit"),
+            AfterStop::Hold => write!(f, "hold"),
+        }
+    }
+}
+
+/// 監視中の停止の分類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// 致命的なシグナル、キャッチしたパニック、またはパニックで Panicked になったタスク
+    Crash,
+    /// ユーザーのブレークポイント・ウォッチポイント・catch err
+    Break,
+}
+
+impl Trigger {
+    /// 停止イベントを分類する（async ブレークポイントや無害なシグナルなら None で、そのまま続ける）
+    pub fn classify(event: &StopEvent) -> Option<Self> {
+        if event.is_crash() || event.panic.is_some() || event.poisoned.is_some() {
+            return Some(Trigger::Crash);
+        }
+        if event.watchpoint.is_some() || event.err_return.is_some() {
+            return Some(Trigger::Break);
+        }
+        match event.breakpoint {
+            Some((_, BreakpointType::User)) => Some(Trigger::Break),
+            _ => None,
+        }
+    }
+
+    /// この停止で kokia を終了するときの終了コード
+    ///
+    /// シグナルならシェルと同じく 128 + シグナル番号にします。
+    pub fn exit_code(self, event: &StopEvent) -> i32 {
+        match self {
+            Trigger::Crash => match event.reason {
+                StopReason::Signal(signal) if event.panic.is_none() => 128 + signal as i32,
+                _ => PANIC_EXIT_CODE,
+            },
+            Trigger::Break => BREAK_TRIGGERED_EXIT_CODE,
+        }
+    }
+}
+
+/// 監視の設定
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Supervision {
+    pub on_crash: Option<StopPlan>,
+    pub on_break: Option<StopPlan>,
+}
+
+impl Supervision {
+    pub fn is_enabled(&self) -> bool {
+        self.on_crash.is_some() || self.on_break.is_some()
+    }
+
+    /// 分類した停止に対応する動作（指定がなければ None で、そのまま続ける）
+    pub fn plan_for(&self, trigger: Trigger) -> Option<&StopPlan> {
+        match trigger {
+            Trigger::Crash => self.on_crash.as_ref(),
+            Trigger::Break => self.on_break.as_ref(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kokia_target::Signal;
+
+    #[test]
+    fn test_parse_plans_and_classify_stops() {
+        let plan = StopPlan::parse("report, core=/tmp/c, source=cmds.txt, hold").unwrap();
+        assert_eq!(plan.actions, vec![
+            StopAction::Report,
+            StopAction::Core(Some("/tmp/c".into())),
+            StopAction::Source("cmds.txt".into()),
+        ]);
+        assert_eq!(plan.then, AfterStop::Hold);
+        assert_eq!(plan.to_string(), "report,core=/tmp/c,source=cmds.txt,hold");
+        assert_eq!(StopPlan::parse("core").unwrap().then, AfterStop::Exit);
+        assert!(StopPlan::parse("hold,report").is_err());
+        assert!(StopPlan::parse("source").is_err());
+        assert!(StopPlan::parse("bt").is_err());
+        assert!(StopPlan::parse("").is_err());
+
+        let segv = StopEvent::new(StopReason::Signal(Signal::SIGSEGV));
+        assert_eq!(Trigger::classify(&segv), Some(Trigger::Crash));
+        assert_eq!(Trigger::Crash.exit_code(&segv), 139);
+        assert_eq!(Trigger::classify(&StopEvent::new(StopReason::Signal(Signal::SIGCHLD))), None);
+
+        let mut hit = StopEvent::new(StopReason::Breakpoint);
+        hit.breakpoint = Some((1, BreakpointType::AsyncEntry));
+        assert_eq!(Trigger::classify(&hit), None);
+        hit.breakpoint = Some((2, BreakpointType::User));
+        assert_eq!(Trigger::classify(&hit), Some(Trigger::Break));
+        assert_eq!(Trigger::Break.exit_code(&hit), BREAK_TRIGGERED_EXIT_CODE);
+
+        let supervision = Supervision { on_crash: Some(plan), on_break: None };
+        assert!(supervision.is_enabled());
+        assert!(supervision.plan_for(Trigger::Break).is_none());
+    }
+}
@@ -0,0 +1,102 @@
+//! 停止中のシンボル・行番号の引き直しのメモ
+//!
+//! 1 回の停止の間に、同じ PC が何度もシンボルと行番号に引かれます（停止位置の表示、フック、
+//! async の entry の処理など）。引いた結果をアドレスごとに覚えておき、プロセスを再開したら捨てます。
+//! 再開の判定には [`EvalCache`](crate::EvalCache) と同じ [`StopEpoch`] を使います。
+
+use crate::eval_cache::StopEpoch;
+use kokia_dwarf::Symbol;
+use std::collections::HashMap;
+
+/// アドレスからのシンボル・行番号の引き直しのメモ
+#[derive(Debug, Default)]
+pub struct SymbolMemo {
+    epoch: Option<StopEpoch>,
+    symbols: HashMap<u64, Option<Symbol>>,
+    lines: HashMap<u64, Option<(String, u32)>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl SymbolMemo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 覚えているシンボル（引いていなければ None、引いて見つからなかったなら Some(None)）
+    pub fn symbol(&mut self, epoch: StopEpoch, address: u64) -> Option<Option<Symbol>> {
+        self.sync(epoch);
+        let symbol = self.symbols.get(&address).cloned();
+        self.count(symbol.is_some());
+        symbol
+    }
+
+    pub fn insert_symbol(&mut self, epoch: StopEpoch, address: u64, symbol: Option<Symbol>) {
+        self.sync(epoch);
+        self.symbols.insert(address, symbol);
+    }
+
+    /// 覚えている行番号（[`SymbolMemo::symbol`] と同じく、見つからなかったことも覚える）
+    pub fn line(&mut self, epoch: StopEpoch, address: u64) -> Option<Option<(String, u32)>> {
+        self.sync(epoch);
+        let line = self.lines.get(&address).cloned();
+        self.count(line.is_some());
+        line
+    }
+
+    pub fn insert_line(&mut self, epoch: StopEpoch, address: u64, line: Option<(String, u32)>) {
+        self.sync(epoch);
+        self.lines.insert(address, line);
+    }
+
+    /// すべて捨てる（シンボルや DWARF を読み直したとき）
+    pub fn clear(&mut self) {
+        self.symbols.clear();
+        self.lines.clear();
+    }
+
+    /// メモに当たった回数と外れた回数
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+
+    fn count(&mut self, hit: bool) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+
+    fn sync(&mut self, epoch: StopEpoch) {
+        if self.epoch != Some(epoch) {
+            self.clear();
+            self.epoch = Some(epoch);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbol_memo_cleared_on_resume() {
+        let mut memo = SymbolMemo::new();
+        let address = |symbol: Option<Option<Symbol>>| symbol.map(|symbol| symbol.map(|symbol| symbol.address));
+
+        assert_eq!(address(memo.symbol((1, 0), 0x1010)), None);
+        memo.insert_symbol((1, 0), 0x1010, Some(Symbol::new("main".to_string(), 0x1000, 0x40)));
+        memo.insert_line((1, 0), 0x1010, Some(("main.rs".to_string(), 3)));
+        // 見つからなかったことも覚える
+        memo.insert_symbol((1, 0), 0x9000, None);
+        assert_eq!(address(memo.symbol((1, 0), 0x1010)), Some(Some(0x1000)));
+        assert_eq!(address(memo.symbol((1, 0), 0x9000)), Some(None));
+        assert_eq!(memo.line((1, 0), 0x1010), Some(Some(("main.rs".to_string(), 3))));
+
+        // 再開したら捨てる
+        assert_eq!(address(memo.symbol((1, 1), 0x1010)), None);
+        assert_eq!(memo.line((1, 1), 0x1010), None);
+        assert_eq!(memo.stats(), (3, 3));
+    }
+}
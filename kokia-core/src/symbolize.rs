@@ -0,0 +1,197 @@
+//! アドレスのシンボル化（kokia symbolize）
+//!
+//! パニックのバックトレースや perf の出力にある生のアドレスを、プロセスなしでバイナリだけから
+//! 関数名とソース位置に変換します。PIE の実行時アドレスは、ロードした先頭アドレス（`/proc/<pid>/maps`
+//! の最初の行）を差し引いてから引きます。
+//!
+//! `addr2line -f -C -i -e` と同じ形式でも出力できるので、既存のスクリプトのコマンドを差し替えるだけで
+//! Rust のデマングルとインライン展開の情報が得られます。
+
+use crate::Result;
+use kokia_dwarf::{DwarfLoader, InlineFrame, InlineFrameResolver, LineInfoProvider, SymbolResolver};
+use std::cell::OnceCell;
+use std::fmt;
+use std::path::Path;
+
+/// 1つのアドレスを変換した結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbolized {
+    /// 与えられたアドレス
+    pub address: u64,
+    /// バイナリ内のアドレス（PIE ならロード先を差し引いたもの）
+    pub offset: u64,
+    /// 関数名（デマングル済み）と関数先頭からのオフセット
+    pub function: Option<(String, u64)>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+impl fmt::Display for Symbolized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:x}", self.address)?;
+        match &self.function {
+            Some((name, 0)) => write!(f, " {}", name)?,
+            Some((name, delta)) => write!(f, " {}+0x{:x}", name, delta)?,
+            None => write!(f, " ??")?,
+        }
+        if let (Some(file), Some(line)) = (&self.file, self.line) {
+            write!(f, " at {}:{}", file, line)?;
+        }
+        Ok(())
+    }
+}
+
+/// バイナリを読み込んでアドレスを変換する
+pub struct Symbolizer {
+    loader: DwarfLoader,
+    resolver: SymbolResolver,
+    /// PIE のロード先（実行時アドレスから差し引く）
+    load_bias: u64,
+    /// addr2line 形式で使うときに作る
+    inline_frames: OnceCell<InlineFrameResolver>,
+}
+
+impl Symbolizer {
+    /// バイナリを読み込む
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let loader = DwarfLoader::load(path)?;
+        let resolver = SymbolResolver::new(&loader)?;
+        Ok(Self { loader, resolver, load_bias: 0, inline_frames: OnceCell::new() })
+    }
+
+    /// 与えるアドレスが PIE の実行時アドレスなら、そのロード先を指定する（非 PIE では無視）
+    pub fn with_load_bias(mut self, load_bias: u64) -> Self {
+        self.load_bias = load_bias;
+        self
+    }
+
+    /// アドレスを関数名とソース位置に変換する
+    ///
+    /// ロード先より前のアドレスや、どの関数にも入らないアドレスは `function` が None になります。
+    pub fn symbolize(&self, address: u64) -> Result<Symbolized> {
+        let Some(offset) = to_offset(address, self.resolver.is_pie(), self.load_bias) else {
+            return Ok(Symbolized { address, offset: address, function: None, file: None, line: None });
+        };
+        // reverse_resolve は大きさの外でも直前のシンボルを返すので、ここで範囲を確かめる
+        // （アドレス 0 のものはリンク時に捨てられた関数や未定義のシンボル）
+        let function = self.resolver.reverse_resolve(offset)
+            .filter(|symbol| symbol.address != 0 && (symbol.size == 0 || offset < symbol.address + symbol.size))
+            .map(|symbol| (symbol.demangled_name, offset.saturating_sub(symbol.address)));
+        // 捨てられた関数の行番号表もアドレス 0 から始まるので、関数の外では行を引かない
+        let line_info = match function {
+            Some(_) => LineInfoProvider::new(&self.loader).lookup(offset)?,
+            None => None,
+        };
+        Ok(Symbolized {
+            address,
+            offset,
+            function,
+            file: line_info.as_ref().and_then(|info| info.file.clone()),
+            line: line_info.and_then(|info| info.line).map(|line| line as u32),
+        })
+    }
+
+    /// インライン展開を含むフレームを内側から順に返す（見つからなければ空）
+    ///
+    /// DWARF に関数がなければ、シンボルテーブルの関数名で補います。
+    pub fn inline_frames(&self, address: u64) -> Result<Vec<InlineFrame>> {
+        let Some(offset) = to_offset(address, self.resolver.is_pie(), self.load_bias) else {
+            return Ok(Vec::new());
+        };
+        // リンク時に捨てられた関数もアドレス 0 から始まる DWARF を残すので、コードの外は引かない
+        if !self.loader.is_code_address(offset) {
+            return Ok(Vec::new());
+        }
+        let resolver = match self.inline_frames.get() {
+            Some(resolver) => resolver,
+            None => {
+                let resolver = InlineFrameResolver::new(&self.loader)?;
+                self.inline_frames.get_or_init(|| resolver)
+            }
+        };
+        let mut frames = resolver.frames(offset)?;
+        if frames.iter().all(|frame| frame.function.is_none()) {
+            if let Some((name, _)) = self.symbolize(address)?.function {
+                match frames.last_mut() {
+                    Some(outermost) => outermost.function = Some(name),
+                    None => frames.push(InlineFrame { function: Some(name), file: None, line: None }),
+                }
+            }
+        }
+        Ok(frames)
+    }
+}
+
+/// フレームを `addr2line -f -C -i` の形式（フレームごとに関数名と `file:line` の2行）にする
+///
+/// 何も分からないアドレスは `??` と `??:0`、行番号だけ分からないときは `file:?` になります。
+pub fn format_addr2line(frames: &[InlineFrame]) -> String {
+    if frames.is_empty() {
+        return "??\n??:0\n".to_string();
+    }
+    let mut out = String::new();
+    for frame in frames {
+        out.push_str(frame.function.as_deref().unwrap_or("??"));
+        out.push('\n');
+        out.push_str(frame.file.as_deref().unwrap_or("??"));
+        match frame.line {
+            Some(line) if line != 0 => out.push_str(&format!(":{}\n", line)),
+            _ => out.push_str(":?\n"),
+        }
+    }
+    out
+}
+
+/// 実行時アドレスをバイナリ内のアドレスにする（ロード先より前なら None）
+fn to_offset(address: u64, is_pie: bool, load_bias: u64) -> Option<u64> {
+    if is_pie {
+        address.checked_sub(load_bias)
+    } else {
+        Some(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset_and_display() {
+        assert_eq!(to_offset(0x5555_5555_e010, true, 0x5555_5555_4000), Some(0xa010));
+        assert_eq!(to_offset(0x1000, true, 0x5555_5555_4000), None);
+        assert_eq!(to_offset(0x401000, false, 0x5555_5555_4000), Some(0x401000));
+
+        let mut found = Symbolized {
+            address: 0x5555_5555_e014,
+            offset: 0xa014,
+            function: Some(("app::compute".to_string(), 4)),
+            file: Some("src/main.rs".to_string()),
+            line: Some(26),
+        };
+        assert_eq!(found.to_string(), "0x55555555e014 app::compute+0x4 at src/main.rs:26");
+        found.function = None;
+        found.file = None;
+        assert_eq!(found.to_string(), "0x55555555e014 ??");
+    }
+
+    #[test]
+    fn test_format_addr2line() {
+        assert_eq!(format_addr2line(&[]), "??\n??:0\n");
+        let frames = vec![
+            InlineFrame {
+                function: Some("app::parse".to_string()),
+                file: Some("/src/app/src/parse.rs".to_string()),
+                line: Some(12),
+            },
+            InlineFrame {
+                function: Some("app::compute::{{closure}}".to_string()),
+                file: Some("/src/app/src/main.rs".to_string()),
+                line: None,
+            },
+        ];
+        assert_eq!(
+            format_addr2line(&frames),
+            "app::parse\n/src/app/src/parse.rs:12\napp::compute::{{closure}}\n/src/app/src/main.rs:?\n"
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! async タスクの検索（async find）
+//!
+//! タスク数が多いと `async tasks` の一覧を目で追うのは難しいので、関数名のパターン・状態・
+//! 観測してからの時間で絞り込み、並べ替えて表示できるようにします。
+
+use crate::{Result, SymbolPattern, TaskInfo};
+use std::time::{Duration, Instant};
+
+/// 状態による絞り込み
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    /// 完了も破棄もされていない
+    Pending,
+    Completed,
+    Root,
+    /// poll の途中でパニックした状態機械（discriminant が Panicked）
+    Panicked,
+}
+
+impl TaskState {
+    /// `pending` などの名前をパースする
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "pending" | "live" => Some(TaskState::Pending),
+            "completed" | "done" => Some(TaskState::Completed),
+            "root" => Some(TaskState::Root),
+            "panicked" => Some(TaskState::Panicked),
+            _ => None,
+        }
+    }
+
+    fn accepts(self, task: &TaskInfo) -> bool {
+        match self {
+            TaskState::Pending => task.is_live(),
+            TaskState::Completed => task.completed,
+            TaskState::Root => task.is_root,
+            TaskState::Panicked => task.is_panicked(),
+        }
+    }
+}
+
+/// 並べ替えの順
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TaskSort {
+    /// 表示用の番号順（登録順）
+    #[default]
+    Handle,
+    /// 最初に観測したのが古い順
+    Age,
+    /// 最後に poll されたのが新しい順
+    Recent,
+    /// 関数名順
+    Name,
+}
+
+impl TaskSort {
+    /// `age` などの名前をパースする
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "handle" | "id" => Some(TaskSort::Handle),
+            "age" => Some(TaskSort::Age),
+            "recent" => Some(TaskSort::Recent),
+            "name" => Some(TaskSort::Name),
+            _ => None,
+        }
+    }
+}
+
+/// `async find` の検索条件
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskQuery {
+    /// 関数名のパターン（グロブか `/正規表現/`。`*` も `?` もなければ部分一致）
+    pub pattern: Option<String>,
+    pub state: Option<TaskState>,
+    /// 最初に観測してからこの時間以上経っている
+    pub older_than: Option<Duration>,
+    /// 最初に観測してからこの時間内
+    pub newer_than: Option<Duration>,
+    pub sort: TaskSort,
+}
+
+impl TaskQuery {
+    /// 条件に合うタスクを並べ替えて返す
+    pub fn run<'a>(&self, tasks: impl IntoIterator<Item = &'a TaskInfo>, now: Instant) -> Result<Vec<&'a TaskInfo>> {
+        let pattern = self.pattern.as_deref().map(name_pattern).transpose()?;
+        let mut found: Vec<&TaskInfo> = tasks.into_iter()
+            .filter(|task| match &pattern {
+                Some(pattern) => task.type_name.as_deref().is_some_and(|name| pattern.matches(name)),
+                None => true,
+            })
+            .filter(|task| self.state.is_none_or(|state| state.accepts(task)))
+            .filter(|task| {
+                let age = now.saturating_duration_since(task.first_seen);
+                self.older_than.is_none_or(|min| age >= min) && self.newer_than.is_none_or(|max| age <= max)
+            })
+            .collect();
+
+        match self.sort {
+            TaskSort::Handle => found.sort_by_key(|task| task.handle),
+            TaskSort::Age => found.sort_by_key(|task| (task.first_seen, task.handle)),
+            TaskSort::Recent => found.sort_by_key(|task| (std::cmp::Reverse(task.last_seen), task.handle)),
+            TaskSort::Name => found.sort_by(|a, b| (&a.type_name, a.handle).cmp(&(&b.type_name, b.handle))),
+        }
+        Ok(found)
+    }
+}
+
+/// メタ文字のない語は部分一致にする（`find` と同じ感覚で `async find compute` と書ける）
+fn name_pattern(pattern: &str) -> Result<SymbolPattern> {
+    let is_regex = pattern.len() > 1 && pattern.starts_with('/') && pattern.ends_with('/');
+    if is_regex || pattern.contains(['*', '?']) {
+        SymbolPattern::parse(pattern)
+    } else {
+        SymbolPattern::parse(&format!("*{}*", pattern))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(handle: u32, name: &str, age_secs: u64, now: Instant) -> TaskInfo {
+        let mut task = TaskInfo::new(0x1000 * handle as u64);
+        task.handle = handle;
+        task.type_name = Some(name.to_string());
+        task.first_seen = now - Duration::from_secs(age_secs);
+        task.last_seen = now - Duration::from_secs(age_secs / 2);
+        task
+    }
+
+    #[test]
+    fn test_task_query() {
+        let now = Instant::now() + Duration::from_secs(100);
+        let mut tasks = vec![
+            task(1, "app::main::{{closure}}", 60, now),
+            task(2, "app::fetch::{{closure}}", 5, now),
+            task(3, "app::fetch::{{closure}}", 30, now),
+            task(4, "tokio::time::sleep::{{closure}}", 40, now),
+        ];
+        tasks[0].is_root = true;
+        tasks[2].mark_completed();
+        tasks[3].current_discriminant = Some(2);
+
+        let handles = |query: &TaskQuery| -> Vec<u32> {
+            query.run(&tasks, now).unwrap().iter().map(|t| t.handle).collect()
+        };
+
+        let fetch = TaskQuery { pattern: Some("fetch".into()), ..Default::default() };
+        assert_eq!(handles(&fetch), vec![2, 3]);
+        let pending = TaskQuery { state: Some(TaskState::Pending), ..fetch.clone() };
+        assert_eq!(handles(&pending), vec![2]);
+        let app = TaskQuery { pattern: Some("app::*".into()), sort: TaskSort::Age, ..Default::default() };
+        assert_eq!(handles(&app), vec![1, 3, 2]);
+        let old = TaskQuery { older_than: Some(Duration::from_secs(35)), ..Default::default() };
+        assert_eq!(handles(&old), vec![1, 4]);
+        let recent = TaskQuery { newer_than: Some(Duration::from_secs(35)), sort: TaskSort::Recent, ..Default::default() };
+        assert_eq!(handles(&recent), vec![2, 3]);
+        let roots = TaskQuery { state: Some(TaskState::Root), ..Default::default() };
+        assert_eq!(handles(&roots), vec![1]);
+        let panicked = TaskQuery { state: Some(TaskState::Panicked), ..Default::default() };
+        assert_eq!(handles(&panicked), vec![4]);
+        let by_name = TaskQuery { sort: TaskSort::Name, ..Default::default() };
+        assert_eq!(handles(&by_name), vec![2, 3, 1, 4]);
+        assert!(TaskQuery { pattern: Some("/(/".into()), ..Default::default() }.run(&tasks, now).is_err());
+    }
+}
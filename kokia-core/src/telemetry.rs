@@ -0,0 +1,708 @@
+//! async イベントの外部送出（async export）
+//!
+//! AsyncTracker が見たタスクの生成・完了、停滞、長い poll を、Webhook（JSON を POST）か
+//! OpenTelemetry（OTLP/HTTP の JSON）で送ります。ステージング環境のサービスにアタッチした kokia を
+//! 既存の監視基盤につなぐためのものです。
+//!
+//! 送信は別スレッドでまとめて行い、キューがあふれたら捨てて数えるので、デバッグ対象の実行を
+//! 送信先の遅さで止めることはありません。送れるのは平文の `http://` だけです。
+//! poll の時間はブレークポイントの処理を含むので、計装なしで動かしたときより長めに出ます。
+
+use anyhow::{anyhow, bail, Context};
+use kokia_async::{RunningTime, TaskId, TaskInfo};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// これ以上かかった poll を長い poll として送る既定値
+pub const DEFAULT_LONG_POLL_THRESHOLD: Duration = Duration::from_millis(100);
+
+/// 送信待ちにためておけるイベントの数（超えた分は捨てる）
+const QUEUE_CAPACITY: usize = 4096;
+
+/// 1回の送信にまとめるイベントの上限
+const MAX_BATCH: usize = 256;
+
+/// イベントが少なくても送信する間隔
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 停滞したタスクを探す間隔（全タスクを走査するので毎イベントではやらない）
+const STALL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 接続・送受信のタイムアウト
+const HTTP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 送信の形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// `{"source": "kokia", "events": [...]}` を指定の URL に POST する
+    Webhook,
+    /// OTLP/HTTP の JSON で `<url>/v1/traces`（完了したタスクと長い poll のスパン）と
+    /// `<url>/v1/logs`（タスクの生成と停滞のログ）に送る
+    Otlp,
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExportFormat::Webhook => "webhook",
+            ExportFormat::Otlp => "otlp",
+        })
+    }
+}
+
+/// 送信先（`http://host[:port][/path]`）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpEndpoint {
+    pub host: String,
+    pub port: u16,
+    /// `/` で始まるパス
+    pub path: String,
+}
+
+impl HttpEndpoint {
+    pub fn parse(url: &str) -> crate::Result<Self> {
+        if url.starts_with("https://") {
+            bail!("https is not supported; point kokia at a local collector or proxy over http://");
+        }
+        let rest = url.strip_prefix("http://").ok_or_else(|| anyhow!("Expected an http:// URL: {}", url))?;
+        let (authority, path) = match rest.find('/') {
+            Some(pos) => (&rest[..pos], &rest[pos..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| anyhow!("Invalid port in {}", url))?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            bail!("Missing host in {}", url);
+        }
+        Ok(Self { host: host.to_string(), port, path: path.to_string() })
+    }
+
+    /// パスの後ろに `suffix` をつけた送信先（OTLP の `/v1/traces` など）
+    fn join(&self, suffix: &str) -> Self {
+        Self { path: format!("{}{}", self.path.trim_end_matches('/'), suffix), ..self.clone() }
+    }
+}
+
+impl fmt::Display for HttpEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}:{}{}", self.host, self.port, self.path)
+    }
+}
+
+/// `async export` の設定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportConfig {
+    pub format: ExportFormat,
+    pub endpoint: HttpEndpoint,
+    /// これ以上かかった poll を送る
+    pub long_poll: Duration,
+    /// これ以上 poll されていない未完了のタスクを停滞として送る
+    pub stall: Duration,
+}
+
+impl ExportConfig {
+    /// `async export` の引数をパースする（`webhook|otlp <url> [--long-poll <d>] [--stall <d>]`）
+    pub fn parse(args: &[&str]) -> Option<Self> {
+        let (format, url, mut options) = match args {
+            ["webhook", url, options @ ..] => (ExportFormat::Webhook, url, options),
+            ["otlp", url, options @ ..] => (ExportFormat::Otlp, url, options),
+            _ => return None,
+        };
+        let mut config = Self {
+            format,
+            endpoint: HttpEndpoint::parse(url).ok()?,
+            long_poll: DEFAULT_LONG_POLL_THRESHOLD,
+            stall: kokia_async::DEFAULT_STALL_THRESHOLD,
+        };
+        while let [option, value, rest @ ..] = options {
+            let value = crate::async_assert::parse_duration(value)?;
+            match *option {
+                "--long-poll" => config.long_poll = value,
+                "--stall" => config.stall = value,
+                _ => return None,
+            }
+            options = rest;
+        }
+        options.is_empty().then_some(config)
+    }
+}
+
+impl fmt::Display for ExportConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} (long polls >= {:?}, stalls >= {:?})", self.format, self.endpoint, self.long_poll, self.stall)
+    }
+}
+
+/// 送るイベントの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsyncEventKind {
+    /// 初めて poll された
+    TaskCreated,
+    /// Ready を返した（生成から完了までの時間）
+    TaskCompleted { lifetime: Duration },
+    /// 未完了のまましばらく poll されていない（最後の poll からの時間）
+    TaskStalled { idle: Duration },
+    /// 1回の poll が長くかかった
+    LongPoll { duration: Duration },
+}
+
+impl AsyncEventKind {
+    fn name(&self) -> &'static str {
+        match self {
+            AsyncEventKind::TaskCreated => "task_created",
+            AsyncEventKind::TaskCompleted { .. } => "task_completed",
+            AsyncEventKind::TaskStalled { .. } => "task_stalled",
+            AsyncEventKind::LongPoll { .. } => "long_poll",
+        }
+    }
+
+    /// 完了までの時間や poll の時間など、イベントが表す期間
+    fn duration(&self) -> Option<Duration> {
+        match *self {
+            AsyncEventKind::TaskCreated => None,
+            AsyncEventKind::TaskCompleted { lifetime } => Some(lifetime),
+            AsyncEventKind::TaskStalled { idle } => Some(idle),
+            AsyncEventKind::LongPoll { duration } => Some(duration),
+        }
+    }
+}
+
+/// 送るイベント1件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsyncEvent {
+    pub kind: AsyncEventKind,
+    pub task: TaskId,
+    /// `async tasks` の `#N`
+    pub handle: u32,
+    pub function: Option<String>,
+    pub tid: Option<i32>,
+    /// イベントが起きた時刻（期間のあるイベントはその終わり）
+    pub time: SystemTime,
+}
+
+impl AsyncEvent {
+    fn new(kind: AsyncEventKind, task: &TaskInfo, tid: Option<i32>, at: Instant) -> Self {
+        Self { kind, task: task.id, handle: task.handle, function: task.type_name.clone(), tid, time: wall_clock(at) }
+    }
+
+    fn start_time(&self) -> SystemTime {
+        self.time - self.kind.duration().unwrap_or_default()
+    }
+}
+
+/// `Instant` をほぼ同じ時点の実時刻にする
+fn wall_clock(at: Instant) -> SystemTime {
+    let now = Instant::now();
+    match now.checked_duration_since(at) {
+        Some(ago) => SystemTime::now() - ago,
+        None => SystemTime::now() + at.duration_since(now),
+    }
+}
+
+/// poll の entry / exit から送るイベントを作る
+#[derive(Debug, Clone)]
+pub struct EventDetector {
+    long_poll: Duration,
+    stall: Duration,
+    /// 生成を送った未完了のタスク
+    known: HashSet<TaskId>,
+    /// poll 中のタスク（スレッド, タスク）→ poll を始めた時刻
+    polls: HashMap<(i32, TaskId), Instant>,
+    /// 停滞を送ってから、まだ poll されていないタスク
+    stalled: HashSet<TaskId>,
+    last_stall_check: Option<Instant>,
+}
+
+impl EventDetector {
+    pub fn new(long_poll: Duration, stall: Duration) -> Self {
+        Self {
+            long_poll,
+            stall,
+            known: HashSet::new(),
+            polls: HashMap::new(),
+            stalled: HashSet::new(),
+            last_stall_check: None,
+        }
+    }
+
+    /// タスクの poll が始まった（`task` は on_poll_entry で更新した後のもの）
+    pub fn on_poll_entry(&mut self, task: &TaskInfo, tid: i32, at: Instant) -> Option<AsyncEvent> {
+        self.polls.insert((tid, task.id), at);
+        self.stalled.remove(&task.id);
+        self.known.insert(task.id).then(|| AsyncEvent::new(AsyncEventKind::TaskCreated, task, Some(tid), at))
+    }
+
+    /// タスクの poll が終わった（`task` は on_poll_exit で更新した後のもの）
+    pub fn on_poll_exit(&mut self, task: &TaskInfo, tid: i32, at: Instant) -> Vec<AsyncEvent> {
+        let mut events = Vec::new();
+        if let Some(start) = self.polls.remove(&(tid, task.id)) {
+            let duration = at.saturating_duration_since(start);
+            if duration >= self.long_poll {
+                events.push(AsyncEvent::new(AsyncEventKind::LongPoll { duration }, task, Some(tid), at));
+            }
+        }
+        if task.completed && self.known.remove(&task.id) {
+            let finished = task.finished_at.unwrap_or(at);
+            let lifetime = finished.saturating_duration_since(task.first_seen);
+            events.push(AsyncEvent::new(AsyncEventKind::TaskCompleted { lifetime }, task, Some(tid), finished));
+        }
+        events
+    }
+
+    /// しばらく poll されていない未完了のタスクを探す（前回から間がなければ何もしない）
+    ///
+    /// 停滞は1回の停滞につき1回だけ送り、次に poll されたら再び送れるようにします。
+    pub fn check_stalls<'a>(&mut self, tasks: impl IntoIterator<Item = &'a TaskInfo>, running: &RunningTime, now: Instant) -> Vec<AsyncEvent> {
+        if self.last_stall_check.is_some_and(|last| now.saturating_duration_since(last) < STALL_CHECK_INTERVAL) {
+            return Vec::new();
+        }
+        self.last_stall_check = Some(now);
+
+        let mut live = HashSet::new();
+        let mut events = Vec::new();
+        for task in tasks.into_iter().filter(|task| task.is_live()) {
+            live.insert(task.id);
+            if kokia_async::stats::is_stalled(task, running, now, self.stall) && self.stalled.insert(task.id) {
+                let idle = kokia_async::stats::idle_time(task, running, now);
+                events.push(AsyncEvent::new(AsyncEventKind::TaskStalled { idle }, task, task.last_tid.map(|tid| tid.0), now));
+            }
+        }
+        // 破棄されて完了しないタスクの分を捨てる
+        self.known.retain(|id| live.contains(id));
+        self.stalled.retain(|id| live.contains(id));
+        events
+    }
+}
+
+/// 送信の状況
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportStats {
+    /// 送れたイベントの数
+    pub sent: u64,
+    /// 送信に失敗して捨てたイベントの数
+    pub failed: u64,
+    /// キューがあふれて捨てたイベントの数
+    pub dropped: u64,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct SharedStats {
+    sent: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+/// イベントの検出と、別スレッドでの送信
+pub struct EventExporter {
+    config: ExportConfig,
+    detector: EventDetector,
+    sender: Option<SyncSender<AsyncEvent>>,
+    worker: Option<JoinHandle<()>>,
+    stats: Arc<SharedStats>,
+}
+
+impl EventExporter {
+    /// 送信スレッドを起動する（`pid` はリソースの属性として送る）
+    pub fn start(config: ExportConfig, pid: Option<i32>) -> crate::Result<Self> {
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let stats = Arc::new(SharedStats::default());
+        let worker = {
+            let (config, stats) = (config.clone(), Arc::clone(&stats));
+            std::thread::Builder::new()
+                .name("kokia-export".to_string())
+                .spawn(move || run_worker(&config, pid, &receiver, &stats))
+                .context("Failed to start the export thread")?
+        };
+        Ok(Self {
+            detector: EventDetector::new(config.long_poll, config.stall),
+            config,
+            sender: Some(sender),
+            worker: Some(worker),
+            stats,
+        })
+    }
+
+    pub fn config(&self) -> &ExportConfig {
+        &self.config
+    }
+
+    pub fn on_poll_entry(&mut self, task: &TaskInfo, tid: i32, at: Instant) {
+        if let Some(event) = self.detector.on_poll_entry(task, tid, at) {
+            self.send(event);
+        }
+    }
+
+    pub fn on_poll_exit(&mut self, task: &TaskInfo, tid: i32, at: Instant) {
+        for event in self.detector.on_poll_exit(task, tid, at) {
+            self.send(event);
+        }
+    }
+
+    pub fn check_stalls<'a>(&mut self, tasks: impl IntoIterator<Item = &'a TaskInfo>, running: &RunningTime, now: Instant) {
+        for event in self.detector.check_stalls(tasks, running, now) {
+            self.send(event);
+        }
+    }
+
+    fn send(&self, event: AsyncEvent) {
+        let Some(sender) = self.sender.as_ref() else {
+            return;
+        };
+        if let Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) = sender.try_send(event) {
+            self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> ExportStats {
+        ExportStats {
+            sent: self.stats.sent.load(Ordering::Relaxed),
+            failed: self.stats.failed.load(Ordering::Relaxed),
+            dropped: self.stats.dropped.load(Ordering::Relaxed),
+            last_error: self.stats.last_error.lock().ok().and_then(|error| error.clone()),
+        }
+    }
+
+    /// 残っているイベントを送ってから送信スレッドを止める
+    pub fn stop(mut self) -> ExportStats {
+        self.shutdown();
+        self.stats()
+    }
+
+    fn shutdown(&mut self) {
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for EventExporter {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn run_worker(config: &ExportConfig, pid: Option<i32>, receiver: &mpsc::Receiver<AsyncEvent>, stats: &SharedStats) {
+    let mut batch = Vec::new();
+    let mut deadline = Instant::now() + FLUSH_INTERVAL;
+    loop {
+        let disconnected = match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(event) => {
+                batch.push(event);
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+        if batch.len() >= MAX_BATCH || (!batch.is_empty() && (disconnected || Instant::now() >= deadline)) {
+            let count = batch.len() as u64;
+            match deliver(config, pid, &batch) {
+                Ok(()) => stats.sent.fetch_add(count, Ordering::Relaxed),
+                Err(e) => {
+                    if let Ok(mut last_error) = stats.last_error.lock() {
+                        *last_error = Some(format!("{:#}", e));
+                    }
+                    stats.failed.fetch_add(count, Ordering::Relaxed)
+                }
+            };
+            batch.clear();
+        }
+        if disconnected {
+            return;
+        }
+        if Instant::now() >= deadline {
+            deadline = Instant::now() + FLUSH_INTERVAL;
+        }
+    }
+}
+
+/// まとめたイベントを設定の形式で送る
+fn deliver(config: &ExportConfig, pid: Option<i32>, events: &[AsyncEvent]) -> crate::Result<()> {
+    match config.format {
+        ExportFormat::Webhook => post_json(&config.endpoint, &webhook_payload(pid, events)),
+        ExportFormat::Otlp => {
+            let (spans, logs) = otlp_payloads(pid, events);
+            if let Some(spans) = spans {
+                post_json(&config.endpoint.join("/v1/traces"), &spans)?;
+            }
+            if let Some(logs) = logs {
+                post_json(&config.endpoint.join("/v1/logs"), &logs)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// JSON を POST し、2xx の応答でなければエラーにする
+fn post_json(endpoint: &HttpEndpoint, body: &Value) -> crate::Result<()> {
+    let body = body.to_string();
+    let address = (endpoint.host.as_str(), endpoint.port).to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {}", endpoint.host))?
+        .next()
+        .ok_or_else(|| anyhow!("No address for {}", endpoint.host))?;
+    let mut stream = TcpStream::connect_timeout(&address, HTTP_TIMEOUT)
+        .with_context(|| format!("Failed to connect to {}", endpoint))?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        endpoint.path, endpoint.host, endpoint.port, body.len(), body
+    )?;
+
+    // 状態行だけ読めればよい
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 512];
+    while !response.contains(&b'\n') {
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        response.extend_from_slice(&buffer[..read]);
+    }
+    let status_line = String::from_utf8_lossy(&response);
+    let status_line = status_line.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => bail!("{} answered '{}'", endpoint, status_line),
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or_default()
+}
+
+/// Webhook に送る JSON
+pub fn webhook_payload(pid: Option<i32>, events: &[AsyncEvent]) -> Value {
+    let events: Vec<Value> = events.iter().map(|event| {
+        let mut value = json!({
+            "kind": event.kind.name(),
+            "task": format!("0x{:x}", event.task),
+            "handle": event.handle,
+            "function": event.function,
+            "tid": event.tid,
+            "timestamp_ms": unix_nanos(event.time) / 1_000_000,
+        });
+        if let Some(duration) = event.kind.duration() {
+            value["duration_ms"] = json!(duration.as_secs_f64() * 1000.0);
+        }
+        value
+    }).collect();
+    json!({ "source": "kokia", "pid": pid, "events": events })
+}
+
+/// OTLP で送る JSON（トレースとログ。どちらのイベントもなければ None）
+///
+/// 完了したタスクは生成から完了までのスパン、長い poll はそのタスクのスパンの子スパンにします。
+/// トレース ID はタスクごとに決まるので、同じタスクの poll とタスク自体が1つのトレースにまとまります。
+pub fn otlp_payloads(pid: Option<i32>, events: &[AsyncEvent]) -> (Option<Value>, Option<Value>) {
+    let mut resource = vec![attribute("service.name", json!({ "stringValue": "kokia" }))];
+    if let Some(pid) = pid {
+        resource.push(attribute("process.pid", json!({ "intValue": pid.to_string() })));
+    }
+    let scope = json!({ "name": "kokia", "version": env!("CARGO_PKG_VERSION") });
+
+    let mut spans = Vec::new();
+    let mut logs = Vec::new();
+    for event in events {
+        let function = event.function.as_deref().unwrap_or("<unknown>");
+        let attributes = event_attributes(event);
+        match event.kind {
+            AsyncEventKind::TaskCompleted { .. } | AsyncEventKind::LongPoll { .. } => {
+                let trace_id = format!("{:016x}{:016x}", pid.unwrap_or_default() as u64, event.task);
+                let task_span = format!("{:016x}", mix(event.task));
+                let (name, span_id, parent) = match event.kind {
+                    AsyncEventKind::TaskCompleted { .. } => (format!("task {}", function), task_span, String::new()),
+                    _ => (format!("poll {}", function), format!("{:016x}", mix(event.task ^ unix_nanos(event.time))), task_span),
+                };
+                spans.push(json!({
+                    "traceId": trace_id,
+                    "spanId": span_id,
+                    "parentSpanId": parent,
+                    "name": name,
+                    "kind": 1,
+                    "startTimeUnixNano": unix_nanos(event.start_time()).to_string(),
+                    "endTimeUnixNano": unix_nanos(event.time).to_string(),
+                    "attributes": attributes,
+                }));
+            }
+            AsyncEventKind::TaskCreated | AsyncEventKind::TaskStalled { .. } => {
+                let (severity, text, body) = match event.kind {
+                    AsyncEventKind::TaskStalled { idle } => {
+                        (13, "WARN", format!("task #{} {} not polled for {:?}", event.handle, function, idle))
+                    }
+                    _ => (9, "INFO", format!("task #{} {} created", event.handle, function)),
+                };
+                logs.push(json!({
+                    "timeUnixNano": unix_nanos(event.time).to_string(),
+                    "severityNumber": severity,
+                    "severityText": text,
+                    "body": { "stringValue": body },
+                    "attributes": attributes,
+                }));
+            }
+        }
+    }
+
+    let traces = (!spans.is_empty()).then(|| json!({
+        "resourceSpans": [{ "resource": { "attributes": resource }, "scopeSpans": [{ "scope": scope, "spans": spans }] }]
+    }));
+    let logs = (!logs.is_empty()).then(|| json!({
+        "resourceLogs": [{ "resource": { "attributes": resource }, "scopeLogs": [{ "scope": scope, "logRecords": logs }] }]
+    }));
+    (traces, logs)
+}
+
+fn attribute(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
+
+fn event_attributes(event: &AsyncEvent) -> Vec<Value> {
+    let mut attributes = vec![
+        attribute("kokia.event", json!({ "stringValue": event.kind.name() })),
+        attribute("kokia.task.id", json!({ "stringValue": format!("0x{:x}", event.task) })),
+        attribute("kokia.task.handle", json!({ "intValue": event.handle.to_string() })),
+    ];
+    if let Some(function) = &event.function {
+        attributes.push(attribute("code.function", json!({ "stringValue": function })));
+    }
+    if let Some(tid) = event.tid {
+        attributes.push(attribute("thread.id", json!({ "intValue": tid.to_string() })));
+    }
+    attributes
+}
+
+/// タスク ID から0にならないスパン ID を作る（splitmix64）
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z ^ (z >> 31)).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn task(id: u64, name: &str, first_seen: Instant) -> TaskInfo {
+        let mut task = TaskInfo::new(id);
+        task.handle = 1;
+        task.type_name = Some(name.to_string());
+        task.first_seen = first_seen;
+        task.last_seen = first_seen;
+        task
+    }
+
+    #[test]
+    fn test_parse_export_config() {
+        let config = ExportConfig::parse(&["otlp", "http://localhost:4318", "--long-poll", "20ms"]).unwrap();
+        assert_eq!(config.format, ExportFormat::Otlp);
+        assert_eq!(config.endpoint, HttpEndpoint { host: "localhost".to_string(), port: 4318, path: "/".to_string() });
+        assert_eq!(config.long_poll, Duration::from_millis(20));
+        assert_eq!(config.stall, kokia_async::DEFAULT_STALL_THRESHOLD);
+        assert_eq!(config.endpoint.join("/v1/traces").to_string(), "http://localhost:4318/v1/traces");
+
+        let config = ExportConfig::parse(&["webhook", "http://hooks.local/kokia", "--stall", "30s"]).unwrap();
+        assert_eq!((config.endpoint.port, config.endpoint.path.as_str()), (80, "/kokia"));
+        assert_eq!(config.stall, Duration::from_secs(30));
+
+        assert!(ExportConfig::parse(&["webhook", "https://hooks.local"]).is_none());
+        assert!(ExportConfig::parse(&["webhook", "http://hooks.local", "--stall"]).is_none());
+        assert!(ExportConfig::parse(&["statsd", "http://hooks.local"]).is_none());
+    }
+
+    #[test]
+    fn test_detect_task_events() {
+        let start = Instant::now();
+        let mut detector = EventDetector::new(Duration::from_millis(100), Duration::from_secs(5));
+        let mut serve = task(0x1000, "app::serve", start);
+
+        let created = detector.on_poll_entry(&serve, 7, start).unwrap();
+        assert_eq!(created.kind, AsyncEventKind::TaskCreated);
+        assert!(detector.on_poll_exit(&serve, 7, start + Duration::from_millis(10)).is_empty());
+
+        // 2回目の poll は生成ではなく、長くかかれば長い poll
+        assert!(detector.on_poll_entry(&serve, 7, start + Duration::from_secs(1)).is_none());
+        serve.completed = true;
+        serve.finished_at = Some(start + Duration::from_millis(1300));
+        let events = detector.on_poll_exit(&serve, 7, start + Duration::from_millis(1300));
+        let kinds: Vec<AsyncEventKind> = events.iter().map(|event| event.kind).collect();
+        assert_eq!(kinds, vec![
+            AsyncEventKind::LongPoll { duration: Duration::from_millis(300) },
+            AsyncEventKind::TaskCompleted { lifetime: Duration::from_millis(1300) },
+        ]);
+
+        // 停滞は1回だけ送り、poll されたらまた送れる
+        let idle = task(0x2000, "app::idle", start);
+        detector.on_poll_entry(&idle, 7, start);
+        let later = start + Duration::from_secs(10);
+        assert_eq!(detector.check_stalls([&idle], &RunningTime::new(), later)[0].kind, AsyncEventKind::TaskStalled { idle: Duration::from_secs(10) });
+        assert!(detector.check_stalls([&idle], &RunningTime::new(), later + Duration::from_secs(2)).is_empty());
+        detector.on_poll_entry(&idle, 7, later + Duration::from_secs(3));
+        assert_eq!(detector.check_stalls([&idle], &RunningTime::new(), later + Duration::from_secs(4)).len(), 1);
+    }
+
+    #[test]
+    fn test_otlp_payloads() {
+        let now = Instant::now();
+        let serve = task(0x1000, "app::serve", now);
+        let events = [
+            AsyncEvent::new(AsyncEventKind::TaskCreated, &serve, Some(7), now),
+            AsyncEvent::new(AsyncEventKind::LongPoll { duration: Duration::from_millis(150) }, &serve, Some(7), now),
+            AsyncEvent::new(AsyncEventKind::TaskCompleted { lifetime: Duration::from_secs(1) }, &serve, Some(7), now),
+        ];
+        let (traces, logs) = otlp_payloads(Some(42), &events);
+        let spans = &traces.unwrap()["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(spans[0]["name"], "poll app::serve");
+        assert_eq!(spans[1]["name"], "task app::serve");
+        assert_eq!(spans[0]["traceId"], spans[1]["traceId"]);
+        assert_eq!(spans[0]["parentSpanId"], spans[1]["spanId"]);
+        let logs = logs.unwrap();
+        assert_eq!(logs["resourceLogs"][0]["scopeLogs"][0]["logRecords"][0]["body"]["stringValue"], "task #1 app::serve created");
+        assert_eq!(logs["resourceLogs"][0]["resource"]["attributes"][1]["value"]["intValue"], "42");
+
+        let webhook = webhook_payload(Some(42), &events[1..2]);
+        assert_eq!(webhook["events"][0]["kind"], "long_poll");
+        assert_eq!(webhook["events"][0]["duration_ms"], 150.0);
+    }
+
+    #[test]
+    fn test_exporter_posts_to_webhook() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 4096];
+            while !String::from_utf8_lossy(&request).contains("\"events\"") || !request.ends_with(b"}") {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let url = format!("http://127.0.0.1:{}/hook", port);
+        let mut exporter = EventExporter::start(ExportConfig::parse(&["webhook", &url]).unwrap(), Some(42)).unwrap();
+        exporter.on_poll_entry(&task(0x1000, "app::serve", Instant::now()), 7, Instant::now());
+        let stats = exporter.stop();
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /hook HTTP/1.1\r\n"));
+        assert!(request.contains("\"kind\":\"task_created\""));
+        assert_eq!(stats, ExportStats { sent: 1, ..ExportStats::default() });
+    }
+}
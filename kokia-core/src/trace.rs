@@ -0,0 +1,180 @@
+//! 命令トレース（trace start / trace stop）
+//!
+//! シンボルのないコードで poll がどこを通ったかを大まかに知るために、トレース中の `continue` や
+//! `step` をすべてシングルステップで実行し、実行した命令の PC（または分岐した命令だけ）を
+//! 上限つきのバッファに残します。1命令ずつ止めるので、通常の実行より桁違いに遅くなります。
+//! Intel PT が使える環境では `trace hw start` で、止めずに走らせた区間の分岐を記録できます。
+
+use iced_x86::{Decoder, DecoderOptions};
+use std::collections::{HashMap, VecDeque};
+use std::num::NonZeroU64;
+
+/// トレースのバッファに残す件数の既定値
+pub const DEFAULT_TRACE_CAPACITY: usize = 100_000;
+
+/// 何を記録するか
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceMode {
+    /// 実行したすべての命令
+    Instructions,
+    /// 分岐した命令（jmp / call / ret と、成立した条件分岐）だけ
+    Branches,
+}
+
+/// 実行した1命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: u64,
+    /// 次の命令に進まずに分岐した先
+    pub target: Option<NonZeroU64>,
+}
+
+/// 分岐元と分岐先の関数の組ごとの回数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchCount {
+    pub from: String,
+    pub to: String,
+    pub count: usize,
+}
+
+/// 命令トレースの記録
+#[derive(Debug, Clone)]
+pub struct InstructionTrace {
+    mode: TraceMode,
+    capacity: usize,
+    records: VecDeque<TraceRecord>,
+    /// トレース中に実行した命令の総数
+    executed: u64,
+    /// 成立した分岐の総数
+    branches: u64,
+    /// バッファから溢れて捨てた記録の数
+    dropped: u64,
+}
+
+impl InstructionTrace {
+    /// 指定件数まで記録するトレースを作成する
+    pub fn new(mode: TraceMode, capacity: usize) -> Self {
+        Self { mode, capacity, records: VecDeque::new(), executed: 0, branches: 0, dropped: 0 }
+    }
+
+    pub fn mode(&self) -> TraceMode {
+        self.mode
+    }
+
+    /// 1命令の実行を記録する（`len` は実行前の PC にあった命令の長さ）
+    pub fn record(&mut self, pc: u64, len: usize, next_pc: u64) {
+        self.executed += 1;
+        let target = (next_pc != pc.wrapping_add(len as u64)).then(|| NonZeroU64::new(next_pc)).flatten();
+        if target.is_some() {
+            self.branches += 1;
+        } else if self.mode == TraceMode::Branches {
+            return;
+        }
+        if self.capacity == 0 {
+            self.dropped += 1;
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+            self.dropped += 1;
+        }
+        self.records.push_back(TraceRecord { pc, target });
+    }
+
+    /// 残っている記録（古い順）
+    pub fn records(&self) -> impl DoubleEndedIterator<Item = &TraceRecord> + ExactSizeIterator {
+        self.records.iter()
+    }
+
+    pub fn executed(&self) -> u64 {
+        self.executed
+    }
+
+    pub fn branches(&self) -> u64 {
+        self.branches
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// 残っている分岐を、分岐元と分岐先の関数の組ごとに数える（多い順）
+    ///
+    /// `function_of` はアドレスを関数名にする関数で、分からなければ `??` として数えます。
+    pub fn branch_summary(&self, function_of: impl Fn(u64) -> Option<String>) -> Vec<BranchCount> {
+        let mut counts: HashMap<(String, String), usize> = HashMap::new();
+        for record in &self.records {
+            let Some(target) = record.target else {
+                continue;
+            };
+            let from = function_of(record.pc).unwrap_or_else(|| "??".to_string());
+            let to = function_of(target.get()).unwrap_or_else(|| "??".to_string());
+            *counts.entry((from, to)).or_default() += 1;
+        }
+        let mut summary: Vec<BranchCount> = counts.into_iter()
+            .map(|((from, to), count)| BranchCount { from, to, count })
+            .collect();
+        summary.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| (&a.from, &a.to).cmp(&(&b.from, &b.to))));
+        summary
+    }
+}
+
+/// アドレスを関数ごとに数える（多い順、関数が分からなければ `??`）
+pub fn count_by_function(
+    addresses: impl IntoIterator<Item = u64>,
+    function_of: impl Fn(u64) -> Option<String>,
+) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for address in addresses {
+        *counts.entry(function_of(address).unwrap_or_else(|| "??".to_string())).or_default() += 1;
+    }
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+/// PC にある命令の長さ（デコードできなければ 0）
+pub fn instruction_len(code: &[u8], pc: u64) -> usize {
+    let instruction = Decoder::with_ip(64, code, pc, DecoderOptions::NONE).decode();
+    if instruction.is_invalid() { 0 } else { instruction.len() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instruction_trace() {
+        // push rbp; mov rbp, rsp; call rel32
+        let code = [0x55, 0x48, 0x89, 0xe5, 0xe8, 0, 0, 0, 0];
+        assert_eq!(instruction_len(&code, 0x1000), 1);
+        assert_eq!(instruction_len(&code[1..], 0x1001), 3);
+        assert_eq!(instruction_len(&[0x0f, 0xff], 0x1000), 0);
+
+        let mut trace = InstructionTrace::new(TraceMode::Instructions, 3);
+        trace.record(0x1000, 1, 0x1001);
+        trace.record(0x1001, 3, 0x1004);
+        trace.record(0x1004, 5, 0x2000);
+        trace.record(0x2000, 1, 0x1009);
+        assert_eq!(trace.executed(), 4);
+        assert_eq!(trace.branches(), 2);
+        assert_eq!(trace.dropped(), 1);
+        assert_eq!(trace.records().next().map(|record| record.pc), Some(0x1001));
+
+        let function_of = |addr: u64| (addr < 0x2000).then(|| "app::poll".to_string());
+        let summary = trace.branch_summary(function_of);
+        assert_eq!(summary.len(), 2);
+        assert_eq!((summary[0].from.as_str(), summary[0].to.as_str()), ("??", "app::poll"));
+        assert_eq!((summary[1].from.as_str(), summary[1].to.as_str()), ("app::poll", "??"));
+        assert_eq!(
+            count_by_function([0x1000, 0x3000, 0x1004], function_of),
+            vec![("app::poll".to_string(), 2), ("??".to_string(), 1)]
+        );
+
+        let mut branches = InstructionTrace::new(TraceMode::Branches, 10);
+        branches.record(0x1000, 1, 0x1001);
+        branches.record(0x1004, 5, 0x2000);
+        assert_eq!(branches.executed(), 2);
+        assert_eq!(branches.records().len(), 1);
+    }
+}
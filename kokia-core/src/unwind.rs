@@ -0,0 +1,132 @@
+//! フレームポインタによるスタックの巻き戻し
+//!
+//! RBP のチェーン（`[rbp]` に呼び出し元の RBP、`[rbp + 8]` にリターンアドレス）を辿ります。
+//! シンボル解決はせず、メモリだけを見るので [`TargetMemory`] を実装したものなら何でも渡せます。
+
+use anyhow::Result;
+use kokia_target::TargetMemory;
+
+/// これより小さいアドレスは RBP・リターンアドレスとして扱わない
+const MIN_VALID_ADDRESS: u64 = 0x1000;
+
+/// 呼び出し元のフレーム1つ分
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallerFrame {
+    /// このフレームを読んだときの RBP
+    pub frame_base: u64,
+    /// `[frame_base]` に保存されていた呼び出し元の RBP
+    pub caller_rbp: u64,
+    /// `[frame_base + 8]` のリターンアドレス
+    pub return_address: u64,
+}
+
+/// `rbp` から始めて呼び出し元のフレームを最大 `max_frames` 個辿る
+///
+/// RBP やリターンアドレスが不正な値になった、RBP がマップされていない、
+/// 呼び出し元の RBP が開始時の RBP 以下（スタックが逆向き）になった、のいずれかで打ち切ります。
+pub fn walk_frame_pointers(memory: &dyn TargetMemory, rbp: u64, max_frames: usize) -> Result<Vec<CallerFrame>> {
+    let start_rbp = rbp;
+    let mut frame_base = rbp;
+    let mut frames = Vec::new();
+
+    while frames.len() < max_frames {
+        if frame_base < MIN_VALID_ADDRESS || !memory.is_address_mapped(frame_base)? {
+            break;
+        }
+        let Ok(caller_rbp) = memory.read_word(frame_base) else {
+            break;
+        };
+        let Ok(return_address) = memory.read_word(frame_base + 8) else {
+            break;
+        };
+        if return_address < MIN_VALID_ADDRESS {
+            break;
+        }
+
+        frames.push(CallerFrame { frame_base, caller_rbp, return_address });
+        if caller_rbp <= start_rbp {
+            break;
+        }
+        frame_base = caller_rbp;
+    }
+
+    Ok(frames)
+}
+
+/// スタックフレームから self ポインタ（RDI の保存値）を探索する
+///
+/// async 関数のスタックフレーム内から妥当なポインタ値を探索します。
+/// スタックフレームの範囲（RBP-256 ～ RBP）を8バイトずつスキャンし、
+/// ヒープ領域を指す可能性のあるポインタ値を返します。
+///
+/// # Arguments
+/// * `rbp` - フレームのベースポインタ
+/// * `memory` - メモリアクセス
+///
+/// # Returns
+/// 最初に見つかった妥当なポインタ値、または None
+pub fn scan_stack_for_self_ptr(memory: &dyn TargetMemory, rbp: u64) -> Option<u64> {
+    // スタックフレームのサイズを制限（256バイト程度）
+    const FRAME_SCAN_SIZE: u64 = 256;
+
+    // RBP から下方向にスキャン（ローカル変数領域）
+    // RBP-8, RBP-16, ... とスキャン
+    for offset in (8..=FRAME_SCAN_SIZE).step_by(8) {
+        if rbp < offset {
+            break;
+        }
+
+        let addr = rbp - offset;
+
+        // メモリが読み取り可能かチェック
+        if let Ok(value) = memory.read_word(addr) {
+            // ポインタ値として妥当かチェック
+            // - NULL ではない
+            // - 小さすぎない（0x1000 以上）
+            // - マップされた領域を指している
+            if (MIN_VALID_ADDRESS..0x7fff_ffff_ffff).contains(&value) {
+                // ヒープ領域やスタック領域を指している可能性が高い
+                // 最初に見つかったものを返す（簡易実装）
+                if memory.is_address_mapped(value).unwrap_or(false) {
+                    return Some(value);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kokia_target::FakeTarget;
+
+    #[test]
+    fn test_walk_frame_pointers_on_fake_stack() {
+        let stack = FakeTarget::new();
+        stack.map_zeroed(0x7000, 0x100);
+        stack.map_zeroed(0x9000, 0x10);
+        // 0x7000 → 0x7040 → 0x7080（リターンアドレス 0 で終端）
+        stack.write_memory(0x7000, &0x7040u64.to_le_bytes()).unwrap();
+        stack.write_memory(0x7008, &0x40_1234u64.to_le_bytes()).unwrap();
+        stack.write_memory(0x7040, &0x7080u64.to_le_bytes()).unwrap();
+        stack.write_memory(0x7048, &0x40_5678u64.to_le_bytes()).unwrap();
+        // フレームの中に self ポインタらしき値を置く
+        stack.write_memory(0x7030, &0x9000u64.to_le_bytes()).unwrap();
+
+        let frames = walk_frame_pointers(&stack, 0x7000, 100).unwrap();
+        let returns: Vec<u64> = frames.iter().map(|f| f.return_address).collect();
+        assert_eq!(returns, vec![0x40_1234, 0x40_5678]);
+        assert_eq!(frames[1].frame_base, 0x7040);
+        assert_eq!(walk_frame_pointers(&stack, 0x7000, 1).unwrap().len(), 1);
+        assert!(walk_frame_pointers(&stack, 0x8000, 100).unwrap().is_empty());
+
+        // 呼び出し元の RBP が逆向きなら、そのフレームまでで止まる
+        stack.write_memory(0x7040, &0x6000u64.to_le_bytes()).unwrap();
+        assert_eq!(walk_frame_pointers(&stack, 0x7000, 100).unwrap().len(), 2);
+
+        assert_eq!(scan_stack_for_self_ptr(&stack, 0x7040), Some(0x9000));
+        assert_eq!(scan_stack_for_self_ptr(&stack, 0x7000), None);
+    }
+}
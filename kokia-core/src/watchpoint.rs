@@ -0,0 +1,204 @@
+//! ウォッチポイント管理
+//!
+//! デバッグレジスタ（DR0〜DR3）でメモリへのアクセスを監視します。ヒットしたときは DR6 で
+//! どのスロットが発火したかを調べて論理的なウォッチポイントに対応付け、前回見た値と
+//! 現在の値を報告します。
+
+use crate::Result;
+use kokia_target::{HardwareBreakpoint, Registers, TargetMemory, WatchKind, HW_BREAKPOINT_SLOTS};
+
+/// ウォッチポイントID
+pub type WatchpointId = usize;
+
+/// 設定中のウォッチポイント
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub id: WatchpointId,
+    pub address: u64,
+    pub len: usize,
+    pub kind: WatchKind,
+    /// 使用しているデバッグレジスタ（0〜3）
+    pub slot: usize,
+    /// 最後に観測した値
+    pub value: Vec<u8>,
+    pub hits: u64,
+}
+
+/// ウォッチポイントのヒット
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchpointHit {
+    pub id: WatchpointId,
+    pub address: u64,
+    pub kind: WatchKind,
+    /// 前回観測した値
+    pub old: Vec<u8>,
+    /// ヒット時点の値
+    pub new: Vec<u8>,
+}
+
+impl WatchpointHit {
+    /// 値が変わったか（読み取りで発火した場合は変わらない）
+    pub fn changed(&self) -> bool {
+        self.old != self.new
+    }
+}
+
+/// ウォッチポイントマネージャ
+///
+/// デバッグレジスタはスレッドごとの状態なので、設定・解除は渡されたスレッドすべてに行います。
+/// 後から生まれたスレッドには、[`WatchpointManager::debug_registers`] を
+/// [`kokia_target::Process::set_inherited_debug_regs`] で渡しておくと走り出す前に設定されます。
+pub struct WatchpointManager {
+    watchpoints: Vec<(Watchpoint, HardwareBreakpoint)>,
+    next_id: WatchpointId,
+}
+
+impl WatchpointManager {
+    /// 新しいウォッチポイントマネージャを作成する
+    pub fn new() -> Self {
+        Self {
+            watchpoints: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// 空いているデバッグレジスタにウォッチポイントを設定する
+    pub fn add(
+        &mut self,
+        address: u64,
+        len: usize,
+        kind: WatchKind,
+        threads: &[i32],
+        memory: &dyn TargetMemory,
+    ) -> Result<WatchpointId> {
+        let slot = (0..HW_BREAKPOINT_SLOTS)
+            .find(|slot| self.watchpoints.iter().all(|(w, _)| w.slot != *slot))
+            .ok_or_else(|| anyhow::anyhow!("All {} debug registers are in use", HW_BREAKPOINT_SLOTS))?;
+        let hw = HardwareBreakpoint::watch(address, slot, kind, len)?;
+        let value = memory.read_memory(address, len)?;
+
+        for (i, &tid) in threads.iter().enumerate() {
+            if let Err(e) = hw.enable(&Registers::new(tid)) {
+                // 設定済みのスレッドから外してから失敗を返す
+                for &done in &threads[..i] {
+                    let _ = hw.disable(&Registers::new(done));
+                }
+                return Err(e.context(format!("Failed to set debug register on thread {}", tid)));
+            }
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.watchpoints.push((Watchpoint { id, address, len, kind, slot, value, hits: 0 }, hw));
+        Ok(id)
+    }
+
+    /// ウォッチポイントを解除する
+    pub fn remove(&mut self, id: WatchpointId, threads: &[i32]) -> Result<Watchpoint> {
+        let index = self.watchpoints.iter().position(|(w, _)| w.id == id)
+            .ok_or_else(|| anyhow::anyhow!("No watchpoint number {}", id))?;
+        let (watchpoint, hw) = self.watchpoints.remove(index);
+        for &tid in threads {
+            // 既に終了したスレッドは無視する
+            let _ = hw.disable(&Registers::new(tid));
+        }
+        Ok(watchpoint)
+    }
+
+    /// 発火したスロットのウォッチポイントについて、値を読み直してヒットを記録する
+    pub fn record_hit(&mut self, slot: usize, memory: &dyn TargetMemory) -> Option<WatchpointHit> {
+        let (watchpoint, _) = self.watchpoints.iter_mut().find(|(w, _)| w.slot == slot)?;
+        let new = memory.read_memory(watchpoint.address, watchpoint.len)
+            .unwrap_or_else(|_| watchpoint.value.clone());
+        let old = std::mem::replace(&mut watchpoint.value, new.clone());
+        watchpoint.hits += 1;
+        Some(WatchpointHit {
+            id: watchpoint.id,
+            address: watchpoint.address,
+            kind: watchpoint.kind,
+            old,
+            new,
+        })
+    }
+
+    /// 設定中のウォッチポイントをすべて反映したデバッグレジスタ（番号, 値）
+    ///
+    /// 使っているスロットのアドレスと DR7 です。ウォッチポイントがなければ DR7 を 0 にします。
+    pub fn debug_registers(&self) -> Vec<(usize, u64)> {
+        let mut regs: Vec<(usize, u64)> = self.watchpoints.iter().map(|(w, _)| (w.slot, w.address)).collect();
+        let dr7 = self.watchpoints.iter().fold(0, |dr7, (_, hw)| dr7 | hw.dr7_bits());
+        regs.push((7, dr7));
+        regs
+    }
+
+    /// 全てのウォッチポイントを ID 順に取得する
+    pub fn all(&self) -> impl Iterator<Item = &Watchpoint> {
+        self.watchpoints.iter().map(|(w, _)| w)
+    }
+
+    /// ウォッチポイントがないか
+    pub fn is_empty(&self) -> bool {
+        self.watchpoints.is_empty()
+    }
+
+    /// 記録をすべて破棄する（プロセス終了時）
+    pub fn clear(&mut self) {
+        self.watchpoints.clear();
+    }
+}
+
+impl Default for WatchpointManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchpoint_hit_reports_old_and_new_value() {
+        use nix::libc;
+        use nix::sys::{ptrace, wait::waitpid};
+        use kokia_target::Memory;
+        use nix::unistd::{fork, ForkResult};
+
+        static mut COUNTER: u64 = 0;
+
+        match unsafe { fork() }.unwrap() {
+            ForkResult::Child => unsafe {
+                libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0);
+                libc::raise(libc::SIGSTOP);
+                std::ptr::write_volatile(std::ptr::addr_of_mut!(COUNTER), 42);
+                libc::_exit(0);
+            },
+            ForkResult::Parent { child } => {
+                waitpid(child, None).unwrap();
+                let memory = Memory::new(child.as_raw());
+                let address = std::ptr::addr_of!(COUNTER) as u64;
+
+                let mut manager = WatchpointManager::new();
+                let id = manager.add(address, 8, WatchKind::Write, &[child.as_raw()], &memory).unwrap();
+
+                // 書き込みの直後に SIGTRAP で止まり、DR6 に発火したスロットが立つ
+                ptrace::cont(child, None).unwrap();
+                waitpid(child, None).unwrap();
+                let registers = Registers::new(child.as_raw());
+                let status = kokia_target::DebugStatus(registers.read_debug_reg(6).unwrap());
+                let slot = status.triggered().next().unwrap();
+
+                let hit = manager.record_hit(slot, &memory).unwrap();
+                assert_eq!(hit.id, id);
+                assert_eq!(hit.old, 0u64.to_le_bytes());
+                assert_eq!(hit.new, 42u64.to_le_bytes());
+                assert!(hit.changed());
+
+                manager.remove(id, &[child.as_raw()]).unwrap();
+                assert!(manager.is_empty());
+                let _ = ptrace::kill(child);
+                let _ = waitpid(child, None);
+            }
+        }
+    }
+}
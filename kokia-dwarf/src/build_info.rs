@@ -0,0 +1,168 @@
+//! ビルド設定の推定
+//!
+//! rustc はコンパイルオプションを DWARF に書き残さないので、コンパイルユニットの
+//! DW_AT_producer（`clang LLVM (rustc version 1.95.0 (59807616e 2026-04-14))`）から
+//! コンパイラのバージョンを、変数の位置の表し方と DW_TAG_call_site の有無から最適化の有無を、
+//! 変数の有無からデバッグ情報のレベルを推定します。
+
+use crate::{DwarfLoader, Result};
+use std::collections::BTreeMap;
+
+type R = gimli::EndianSlice<'static, gimli::RunTimeEndian>;
+
+/// DWARF から推定したビルド設定
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// コンパイルユニットの数
+    pub units: usize,
+    /// 標準ライブラリ以外の Rust のユニット数（依存クレートも含む）
+    pub rust_units: usize,
+    /// rustc のバージョンと、それでコンパイルされたユニット数
+    pub rustc_versions: BTreeMap<String, usize>,
+    /// 標準ライブラリ以外のユニットにある、位置を持つ変数と引数の数
+    pub variables: usize,
+    /// そのうち位置リストで表されたもの（最適化されたコードでレジスタやスタックを移り歩く）
+    pub location_list_variables: usize,
+    /// 標準ライブラリ以外のユニットにある DW_TAG_call_site の数（最適化したときだけ出力される）
+    pub call_sites: usize,
+    /// 機械語を持つ関数（DW_AT_low_pc のある DW_TAG_subprogram）の数
+    pub functions: usize,
+    /// 本体が .dwo / .dwp にあるスケルトンユニットの数（split-debuginfo）
+    pub split_units: usize,
+}
+
+impl BuildInfo {
+    /// すべてのコンパイルユニットを走査する
+    pub fn collect(loader: &DwarfLoader) -> Result<Self> {
+        let dwarf = loader.dwarf();
+        let mut info = BuildInfo::default();
+
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            info.units += 1;
+
+            let mut entries = unit.entries();
+            let Some((_, root)) = entries.next_dfs()? else {
+                continue;
+            };
+            if matches!(unit.header.type_(), gimli::UnitType::Skeleton(_))
+                || root.attr_value(gimli::DW_AT_GNU_dwo_name)?.is_some()
+            {
+                info.split_units += 1;
+            }
+            let is_rust = root.attr_value(gimli::DW_AT_language)? == Some(gimli::AttributeValue::Language(gimli::DW_LANG_Rust));
+            if is_rust {
+                if let Some(version) = unit_producer(dwarf, &unit, root)?.as_deref().and_then(rustc_version) {
+                    *info.rustc_versions.entry(version.to_string()).or_default() += 1;
+                }
+            }
+            // 配布されている std はリリースビルドなので、最適化の推定から外す
+            let counted = is_rust && !is_std_unit(&unit);
+            if counted {
+                info.rust_units += 1;
+            }
+
+            while let Some((_, entry)) = entries.next_dfs()? {
+                if entry.tag() == gimli::DW_TAG_subprogram {
+                    if entry.attr_value(gimli::DW_AT_low_pc)?.is_some() {
+                        info.functions += 1;
+                    }
+                    continue;
+                }
+                if !counted {
+                    continue;
+                }
+                match entry.tag() {
+                    gimli::DW_TAG_variable | gimli::DW_TAG_formal_parameter => match entry.attr_value(gimli::DW_AT_location)? {
+                        Some(gimli::AttributeValue::Exprloc(_)) => info.variables += 1,
+                        Some(_) => {
+                            info.variables += 1;
+                            info.location_list_variables += 1;
+                        }
+                        None => {}
+                    },
+                    gimli::DW_TAG_call_site | gimli::DW_TAG_GNU_call_site => info.call_sites += 1,
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(info)
+    }
+
+    /// 最適化してビルドされたように見えるか
+    ///
+    /// opt-level = 0 では変数はフレーム上の固定位置に置かれ、呼び出し位置も出力されません。
+    pub fn looks_optimized(&self) -> bool {
+        self.call_sites > 0 || self.location_list_variables * 5 > self.variables
+    }
+
+    /// 関数と行番号の情報しかない（debug = "line-tables-only" や "limited"）ように見えるか
+    pub fn is_line_tables_only(&self) -> bool {
+        self.rust_units > 0 && self.variables == 0
+    }
+
+    /// デバッグ情報のレベル（`none` / `line-tables-only` / `full`）
+    ///
+    /// スケルトンユニットしかない split-debuginfo のバイナリは、本体を読むまで分からないので `full` とみなします。
+    pub fn debuginfo_level(&self) -> &'static str {
+        if self.units == 0 {
+            "none"
+        } else if self.is_line_tables_only() && self.split_units == 0 {
+            "line-tables-only"
+        } else {
+            "full"
+        }
+    }
+}
+
+/// DW_AT_producer から rustc のバージョン（`1.95.0 (59807616e 2026-04-14)`）を取り出す
+pub fn rustc_version(producer: &str) -> Option<&str> {
+    let start = producer.find("rustc version ")? + "rustc version ".len();
+    let version = &producer[start..];
+    // 末尾の `)` は `clang LLVM (` を閉じるもの
+    Some(version.strip_suffix(')').unwrap_or(version).trim())
+}
+
+fn unit_producer(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+    root: &gimli::DebuggingInformationEntry<R>,
+) -> Result<Option<String>> {
+    let Some(value) = root.attr_value(gimli::DW_AT_producer)? else {
+        return Ok(None);
+    };
+    let producer = dwarf.attr_string(unit, value)?;
+    Ok(Some(producer.to_string_lossy().into_owned()))
+}
+
+/// rustup で配布された std のユニットか（コンパイル時のディレクトリが `/rustc/<commit>`）
+fn is_std_unit(unit: &gimli::Unit<R>) -> bool {
+    unit.comp_dir.is_some_and(|dir| dir.slice().starts_with(b"/rustc/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rustc_version_and_heuristics() {
+        assert_eq!(
+            rustc_version("clang LLVM (rustc version 1.95.0 (59807616e 2026-04-14))"),
+            Some("1.95.0 (59807616e 2026-04-14)")
+        );
+        assert_eq!(rustc_version("GNU C17 11.4.0 -O2"), None);
+
+        let debug = BuildInfo { units: 5, rust_units: 3, variables: 100, location_list_variables: 2, ..Default::default() };
+        assert!(!debug.looks_optimized());
+        assert!(!debug.is_line_tables_only());
+        assert!(BuildInfo { location_list_variables: 40, ..debug.clone() }.looks_optimized());
+        assert!(BuildInfo { call_sites: 1, ..debug.clone() }.looks_optimized());
+        assert_eq!(debug.debuginfo_level(), "full");
+        let line_tables = BuildInfo { units: 5, variables: 0, location_list_variables: 0, ..debug };
+        assert!(line_tables.is_line_tables_only());
+        assert_eq!(line_tables.debuginfo_level(), "line-tables-only");
+        assert_eq!(BuildInfo::default().debuginfo_level(), "none");
+    }
+}
@@ -0,0 +1,107 @@
+//! `.eh_frame` の呼び出しフレーム情報（CFI）
+//!
+//! フレームポインタを使わない関数（最適化されたビルドや、opt-level 0 でも RBP を積まない
+//! ジェネリックのインスタンスなど）は RBP のチェーンでは辿れません。`.eh_frame` には
+//! パニックの巻き戻しのために、各アドレスでの CFA（呼び出し直前のスタックポインタ）の求め方と
+//! 呼び出し元のレジスタの保存場所が入っているので、それで 1 フレームずつ辿ります。
+//! x86-64 で要る RSP・RBP と戻りアドレス（常に CFA - 8）だけを扱います。
+
+use crate::{DwarfLoader, Result};
+use gimli::UnwindSection;
+use object::{Object, ObjectSection};
+
+type R = gimli::EndianSlice<'static, gimli::RunTimeEndian>;
+
+/// x86-64 の DWARF レジスタ番号
+const RBP: u16 = 6;
+const RSP: u16 = 7;
+
+/// あるアドレスでの CFA と RBP の復元規則
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRule {
+    /// CFA の基にするレジスタ（DWARF レジスタ番号）
+    pub cfa_register: u16,
+    /// CFA = レジスタ + `cfa_offset`
+    pub cfa_offset: i64,
+    /// 呼び出し元の RBP が保存されている CFA からのオフセット（None なら RBP はそのまま）
+    pub saved_rbp: Option<i64>,
+}
+
+impl FrameRule {
+    /// RSP と RBP の値から CFA を求める（RSP・RBP 以外を基にする規則なら None）
+    pub fn cfa(&self, rsp: u64, rbp: u64) -> Option<u64> {
+        let base = match self.cfa_register {
+            RSP => rsp,
+            RBP => rbp,
+            _ => return None,
+        };
+        Some(base.wrapping_add_signed(self.cfa_offset))
+    }
+}
+
+/// `.eh_frame` の表
+pub struct CallFrameTable {
+    eh_frame: gimli::EhFrame<R>,
+    bases: gimli::BaseAddresses,
+}
+
+impl CallFrameTable {
+    /// `.eh_frame` を読む（セクションがなければ None）
+    pub fn new(loader: &DwarfLoader) -> Option<Self> {
+        let object = loader.object_file();
+        let section = object.section_by_name(".eh_frame")?;
+        let endian = if object.is_little_endian() { gimli::RunTimeEndian::Little } else { gimli::RunTimeEndian::Big };
+        let mut eh_frame = gimli::EhFrame::new(loader.section_data(".eh_frame")?, endian);
+        eh_frame.set_address_size(8);
+        let mut bases = gimli::BaseAddresses::default().set_eh_frame(section.address());
+        if let Some(text) = object.section_by_name(".text") {
+            bases = bases.set_text(text.address());
+        }
+        Some(Self { eh_frame, bases })
+    }
+
+    /// `address`（DWARF 上のアドレス）での規則（FDE がない、または CFA を式で求めるなら None）
+    ///
+    /// 呼び出し元のフレームを引くときは、戻りアドレスではなく call 命令の中（戻りアドレス - 1）を渡します。
+    /// 戻りアドレスは次の関数の先頭になっていることがあるためです。
+    pub fn rule_at(&self, address: u64) -> Result<Option<FrameRule>> {
+        let fde = match self.eh_frame.fde_for_address(&self.bases, address, gimli::EhFrame::cie_from_offset) {
+            Ok(fde) => fde,
+            Err(gimli::Error::NoUnwindInfoForAddress) => return Ok(None),
+            Err(e) => return Err(anyhow::anyhow!("Failed to read .eh_frame at 0x{:x}: {}", address, e)),
+        };
+        let mut context = gimli::UnwindContext::new();
+        let row = fde.unwind_info_for_address(&self.eh_frame, &self.bases, &mut context, address)
+            .map_err(|e| anyhow::anyhow!("Failed to evaluate CFI at 0x{:x}: {}", address, e))?;
+        let gimli::CfaRule::RegisterAndOffset { register, offset } = *row.cfa() else {
+            return Ok(None);
+        };
+        let saved_rbp = match row.register(gimli::Register(RBP)) {
+            gimli::RegisterRule::Offset(offset) => Some(offset),
+            _ => None,
+        };
+        Ok(Some(FrameRule { cfa_register: register.0, cfa_offset: offset, saved_rbp }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SymbolResolver;
+
+    #[test]
+    fn test_rule_at_function_entry() {
+        let loader = DwarfLoader::load(std::env::current_exe().unwrap()).unwrap();
+        let table = CallFrameTable::new(&loader).unwrap();
+        let resolver = SymbolResolver::new(&loader).unwrap();
+        let symbol = resolver.find_symbols("test_rule_at_function_entry").into_iter()
+            .find(|symbol| symbol.size > 0)
+            .unwrap();
+
+        // 関数の先頭では [rsp] が戻りアドレスなので CFA = rsp + 8、RBP はまだ積んでいない
+        let rule = table.rule_at(symbol.address).unwrap().unwrap();
+        assert_eq!(rule, FrameRule { cfa_register: RSP, cfa_offset: 8, saved_rbp: None });
+        assert_eq!(rule.cfa(0x7000, 0x8000), Some(0x7008));
+        assert_eq!(table.rule_at(0).unwrap(), None);
+    }
+}
@@ -0,0 +1,247 @@
+//! 呼び出し位置（DW_TAG_call_site）の索引
+//!
+//! 最適化されたビルドでは、関数の各 call 命令について DW_TAG_call_site（DWARF 4 では
+//! DW_TAG_GNU_call_site）が出力されます。戻りアドレスから呼び出し命令の位置と呼び出し先を引け、
+//! 子の DW_TAG_call_site_parameter には「呼び出し時に引数レジスタに入っていた値」を呼び出し元の
+//! フレームで求める式が付いています。これで、呼び出された側で上書き済みの引数
+//! （`DW_OP_entry_value(DW_OP_reg5)`）を復元できます。
+
+use crate::{DwarfLoader, Progress, ProgressCallback, Result};
+use std::collections::HashMap;
+
+type R = gimli::EndianSlice<'static, gimli::RunTimeEndian>;
+
+/// 呼び出し位置
+#[derive(Debug, Clone)]
+pub struct CallSite {
+    /// 呼び出しから戻った先のアドレス（DWARF 上のアドレス）
+    pub return_pc: u64,
+    /// call 命令のアドレス（出力されていれば）
+    pub call_pc: Option<u64>,
+    /// 呼び出し先の関数名（リンケージ名があればそれ）
+    pub target: Option<String>,
+    /// 末尾呼び出し（jmp）か
+    pub tail_call: bool,
+    /// 呼び出し時の引数レジスタの値を求める式
+    pub parameters: Vec<CallSiteParameter>,
+}
+
+/// 呼び出し時の引数
+#[derive(Debug, Clone)]
+pub struct CallSiteParameter {
+    /// 値が渡されたレジスタ（DWARF レジスタ番号）
+    pub register: u16,
+    /// 呼び出し元のフレームで値を求める式
+    pub value: gimli::Expression<R>,
+    pub encoding: gimli::Encoding,
+}
+
+impl CallSite {
+    /// 呼び出しの行を調べるためのアドレス
+    ///
+    /// call 命令のアドレスがあればそれを、なければ戻りアドレスの1つ前（call 命令の最後のバイト）を
+    /// 返します。戻りアドレスそのものは次の行に属していることがあります。
+    pub fn line_lookup_pc(&self) -> u64 {
+        self.call_pc.unwrap_or(self.return_pc.saturating_sub(1))
+    }
+
+    /// 呼び出し元のフレームで引数の値を評価し、レジスタ番号 -> 値の対応を返す
+    ///
+    /// 呼び出し元のレジスタが分からない式（`get_reg` がエラーを返すもの）の引数は含めません。
+    pub fn entry_values<F, G>(&self, mut get_reg: F, mut read_mem: G) -> HashMap<u16, u64>
+    where
+        F: FnMut(u16) -> Result<u64>,
+        G: FnMut(u64, usize) -> Result<Vec<u8>>,
+    {
+        let mut values = HashMap::new();
+        for param in &self.parameters {
+            let value = crate::loc_eval::evaluate_value(
+                param.value,
+                param.encoding,
+                None,
+                &mut get_reg,
+                &mut read_mem,
+            );
+            if let Ok(value) = value {
+                values.insert(param.register, value);
+            }
+        }
+        values
+    }
+}
+
+/// 戻りアドレスで引ける呼び出し位置の索引
+pub struct CallSiteIndex {
+    by_return_pc: HashMap<u64, CallSite>,
+}
+
+impl CallSiteIndex {
+    /// すべてのコンパイルユニットから呼び出し位置を集める
+    pub fn build(loader: &DwarfLoader) -> Result<Self> {
+        Self::build_with_progress(loader, None)
+    }
+
+    /// 呼び出し位置を集め、走査したユニット数で進捗を通知する
+    pub fn build_with_progress(loader: &DwarfLoader, progress: Option<&ProgressCallback>) -> Result<Self> {
+        let dwarf = loader.dwarf();
+        let mut by_return_pc = HashMap::new();
+        let mut progress = Progress::start(progress, "Indexing call sites", None);
+
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            progress.advance(1);
+            let unit = dwarf.unit(header)?;
+            let mut entries = unit.entries();
+            while let Some((_, entry)) = entries.next_dfs()? {
+                if !matches!(entry.tag(), gimli::DW_TAG_call_site | gimli::DW_TAG_GNU_call_site) {
+                    continue;
+                }
+                if let Some(site) = read_call_site(dwarf, &unit, entry.offset())? {
+                    by_return_pc.insert(site.return_pc, site);
+                }
+            }
+        }
+
+        progress.finish();
+        Ok(Self { by_return_pc })
+    }
+
+    /// 戻りアドレスから呼び出し位置を引く
+    pub fn find_by_return_pc(&self, return_pc: u64) -> Option<&CallSite> {
+        self.by_return_pc.get(&return_pc)
+    }
+
+    /// 索引した呼び出し位置の数
+    pub fn len(&self) -> usize {
+        self.by_return_pc.len()
+    }
+
+    /// 呼び出し位置が1つもないか（最適化なしのビルドなど）
+    pub fn is_empty(&self) -> bool {
+        self.by_return_pc.is_empty()
+    }
+}
+
+/// DW_TAG_call_site とその子の引数を読む
+fn read_call_site(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+    offset: gimli::UnitOffset,
+) -> Result<Option<CallSite>> {
+    let mut tree = unit.entries_tree(Some(offset))?;
+    let root = tree.root()?;
+    let entry = root.entry();
+
+    // DWARF 5 は DW_AT_call_return_pc、GNU 拡張は DW_AT_low_pc が戻りアドレス
+    let return_pc = [gimli::DW_AT_call_return_pc, gimli::DW_AT_low_pc]
+        .into_iter()
+        .find_map(|name| entry.attr_value(name).ok().flatten())
+        .map(|value| dwarf.attr_address(unit, value))
+        .transpose()?
+        .flatten();
+    let Some(return_pc) = return_pc.filter(|pc| *pc != 0) else {
+        return Ok(None);
+    };
+    let call_pc = match entry.attr_value(gimli::DW_AT_call_pc)? {
+        Some(value) => dwarf.attr_address(unit, value)?,
+        None => None,
+    };
+    let tail_call = entry.attr(gimli::DW_AT_call_tail_call)?.is_some()
+        || entry.attr(gimli::DW_AT_GNU_tail_call)?.is_some();
+    let target = [gimli::DW_AT_call_origin, gimli::DW_AT_abstract_origin]
+        .into_iter()
+        .find_map(|name| match entry.attr_value(name).ok().flatten() {
+            Some(gimli::AttributeValue::UnitRef(origin)) => function_name(dwarf, unit, origin),
+            _ => None,
+        });
+
+    let mut parameters = Vec::new();
+    let mut children = root.children();
+    while let Some(child) = children.next()? {
+        let param = child.entry();
+        if !matches!(param.tag(), gimli::DW_TAG_call_site_parameter | gimli::DW_TAG_GNU_call_site_parameter) {
+            continue;
+        }
+        let register = match param.attr_value(gimli::DW_AT_location)? {
+            Some(gimli::AttributeValue::Exprloc(expr)) => crate::loc_eval::single_register(&expr, unit.encoding()),
+            _ => None,
+        };
+        let value = [gimli::DW_AT_call_value, gimli::DW_AT_GNU_call_site_value]
+            .into_iter()
+            .find_map(|name| match param.attr_value(name).ok().flatten() {
+                Some(gimli::AttributeValue::Exprloc(expr)) => Some(expr),
+                _ => None,
+            });
+        if let (Some(register), Some(value)) = (register, value) {
+            parameters.push(CallSiteParameter { register, value, encoding: unit.encoding() });
+        }
+    }
+
+    Ok(Some(CallSite { return_pc, call_pc, target, tail_call, parameters }))
+}
+
+/// 呼び出し先の DIE から関数名を取る（宣言 DIE の DW_AT_specification も辿る）
+fn function_name(dwarf: &gimli::Dwarf<R>, unit: &gimli::Unit<R>, offset: gimli::UnitOffset) -> Option<String> {
+    let mut offset = offset;
+    for _ in 0..4 {
+        let entry = unit.entry(offset).ok()?;
+        for name in [gimli::DW_AT_linkage_name, gimli::DW_AT_MIPS_linkage_name, gimli::DW_AT_name] {
+            if let Some(value) = entry.attr_value(name).ok().flatten() {
+                if let Ok(s) = dwarf.attr_string(unit, value) {
+                    return Some(s.to_string_lossy().into_owned());
+                }
+            }
+        }
+        offset = match entry.attr_value(gimli::DW_AT_specification).ok().flatten()
+            .or_else(|| entry.attr_value(gimli::DW_AT_abstract_origin).ok().flatten())
+        {
+            Some(gimli::AttributeValue::UnitRef(next)) => next,
+            _ => return None,
+        };
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expression(bytes: &'static [u8]) -> gimli::Expression<R> {
+        gimli::Expression(gimli::EndianSlice::new(bytes, gimli::RunTimeEndian::Little))
+    }
+
+    #[test]
+    fn test_call_site_entry_values() {
+        let encoding = gimli::Encoding { address_size: 8, format: gimli::Format::Dwarf32, version: 5 };
+        let site = CallSite {
+            return_pc: 0x1234,
+            call_pc: None,
+            target: Some("work".to_string()),
+            tail_call: false,
+            parameters: vec![
+                // rdi = 42（DW_OP_lit10 DW_OP_constu 32 DW_OP_plus DW_OP_stack_value）
+                CallSiteParameter { register: 5, value: expression(&[0x3a, 0x10, 0x20, 0x22, 0x9f]), encoding },
+                // rsi = rbp - 8 の位置に入っていた値（DW_OP_breg6 -8 DW_OP_deref DW_OP_stack_value）
+                CallSiteParameter { register: 4, value: expression(&[0x76, 0x78, 0x06, 0x9f]), encoding },
+                // rdx = rbx（呼び出し元の rbx は分からない）
+                CallSiteParameter { register: 1, value: expression(&[0x73, 0x00, 0x9f]), encoding },
+            ],
+        };
+        assert_eq!(site.line_lookup_pc(), 0x1233);
+
+        let get_reg = |reg: u16| match reg {
+            6 => Ok(0x7000),
+            _ => Err(anyhow::anyhow!("unknown register {}", reg)),
+        };
+        let read_mem = |addr: u64, _size: usize| match addr {
+            0x6ff8 => Ok(7u64.to_le_bytes().to_vec()),
+            _ => Err(anyhow::anyhow!("unmapped 0x{:x}", addr)),
+        };
+        let values = site.entry_values(get_reg, read_mem);
+        assert_eq!(values, HashMap::from([(5, 42), (4, 7)]));
+
+        let reg = crate::loc_eval::single_register(&expression(&[0x55]), encoding);
+        assert_eq!(reg, Some(5));
+        assert_eq!(crate::loc_eval::single_register(&expression(&[0x55, 0x9f]), encoding), None);
+    }
+}
@@ -0,0 +1,100 @@
+//! 長時間の DWARF 走査の中断
+//!
+//! 巨大なコンパイルユニットを総なめにする検索は数十秒かかることがあるため、
+//! 走査ループの中で [`CancelToken::check`] を呼び、期限切れや利用者の中断要求で打ち切ります。
+
+use crate::Result;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// 中断の理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    /// 期限を過ぎた
+    Deadline,
+    /// 利用者が中断した（Ctrl-C など）
+    Interrupted,
+}
+
+/// 走査が中断されたことを表すエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("DWARF query cancelled ({})", match .0 { CancelReason::Deadline => "timed out", CancelReason::Interrupted => "interrupted" })]
+pub struct Cancelled(pub CancelReason);
+
+/// 協調的な中断のためのトークン
+///
+/// 既定値は中断されないトークンです。
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    deadline: Option<Instant>,
+    interrupted: Option<Rc<dyn Fn() -> bool>>,
+}
+
+impl CancelToken {
+    /// 中断されないトークンを作成する
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 現在から `timeout` 後を期限にする
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(Instant::now() + timeout);
+        self
+    }
+
+    /// 中断要求を確認するコールバックを設定する（true を返すと中断）
+    pub fn with_interrupt_check(mut self, check: impl Fn() -> bool + 'static) -> Self {
+        self.interrupted = Some(Rc::new(check));
+        self
+    }
+
+    /// 中断すべきなら理由を返す
+    pub fn reason(&self) -> Option<CancelReason> {
+        if self.interrupted.as_ref().is_some_and(|check| check()) {
+            return Some(CancelReason::Interrupted);
+        }
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Some(CancelReason::Deadline);
+        }
+        None
+    }
+
+    /// 中断すべきなら [`Cancelled`] エラーを返す
+    pub fn check(&self) -> Result<()> {
+        match self.reason() {
+            Some(reason) => Err(Cancelled(reason).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// エラーが中断によるものか
+pub fn is_cancelled(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<Cancelled>().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_cancel_token() {
+        assert!(CancelToken::new().check().is_ok());
+
+        let expired = CancelToken::new().with_timeout(Duration::ZERO);
+        let err = expired.check().unwrap_err();
+        assert!(is_cancelled(&err));
+        assert_eq!(err.to_string(), "DWARF query cancelled (timed out)");
+
+        let flag = Rc::new(Cell::new(false));
+        let token = CancelToken::new().with_interrupt_check({
+            let flag = flag.clone();
+            move || flag.get()
+        });
+        assert_eq!(token.reason(), None);
+        flag.set(true);
+        assert_eq!(token.reason(), Some(CancelReason::Interrupted));
+        assert!(!is_cancelled(&anyhow::anyhow!("other error")));
+    }
+}
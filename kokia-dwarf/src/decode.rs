@@ -2,6 +2,9 @@
 //!
 //! メモリから読み取ったバイト列を、型情報に基づいて適切にフォーマットします。
 
+use crate::type_info::{FieldInfo, TypeInfo};
+use crate::value_formatter::MemoryReader;
+
 /// デコード設定
 #[derive(Debug, Clone)]
 pub struct DecodeConfig {
@@ -60,6 +63,8 @@ pub enum DisplayValue {
     },
     /// 利用不可
     Unavailable,
+    /// 最大深さを超えたため省略
+    MaxDepth,
     /// オプション
     Option(Option<Box<DisplayValue>>),
     /// Result
@@ -111,6 +116,7 @@ impl std::fmt::Display for DisplayValue {
                 }
                 write!(f, "]")
             }
+            DisplayValue::Struct { name, fields } if fields.is_empty() => write!(f, "{} {{}}", name),
             DisplayValue::Struct { name, fields } => {
                 write!(f, "{} {{ ", name)?;
                 for (i, (field_name, value)) in fields.iter().enumerate() {
@@ -143,6 +149,7 @@ impl std::fmt::Display for DisplayValue {
                 Ok(())
             }
             DisplayValue::Unavailable => write!(f, "<unavailable>"),
+            DisplayValue::MaxDepth => write!(f, "<max depth reached>"),
             DisplayValue::Option(opt) => match opt {
                 Some(val) => write!(f, "Some({})", val),
                 None => write!(f, "None"),
@@ -163,24 +170,24 @@ pub struct ValueDecoder {
     config: DecodeConfig,
 }
 
+impl Default for ValueDecoder {
+    /// デフォルト設定で値デコーダーを作成する
+    fn default() -> Self {
+        Self::new(DecodeConfig::default())
+    }
+}
+
 impl ValueDecoder {
     /// 新しい値デコーダーを作成する
     pub fn new(config: DecodeConfig) -> Self {
         Self { config }
     }
 
-    /// デフォルト設定で値デコーダーを作成する
-    pub fn default() -> Self {
-        Self {
-            config: DecodeConfig::default(),
-        }
-    }
-
     /// プリミティブ型をデコードする
     pub fn decode_primitive(&self, bytes: &[u8], type_name: &str) -> DisplayValue {
         match type_name {
             "i8" => {
-                if bytes.len() >= 1 {
+                if !bytes.is_empty() {
                     DisplayValue::Int(i8::from_le_bytes([bytes[0]]) as i64)
                 } else {
                     DisplayValue::Unavailable
@@ -211,7 +218,7 @@ impl ValueDecoder {
                 }
             }
             "u8" => {
-                if bytes.len() >= 1 {
+                if !bytes.is_empty() {
                     DisplayValue::Uint(bytes[0] as u64)
                 } else {
                     DisplayValue::Unavailable
@@ -258,7 +265,7 @@ impl ValueDecoder {
                 }
             }
             "bool" => {
-                if bytes.len() >= 1 {
+                if !bytes.is_empty() {
                     DisplayValue::Bool(bytes[0] != 0)
                 } else {
                     DisplayValue::Unavailable
@@ -362,6 +369,90 @@ impl ValueDecoder {
         }
     }
 
+    /// 型情報に従ってメモリ上の値をデコードする
+    ///
+    /// 構造体・列挙型・配列・Union の中身は `max_depth` 段目までたどり、それより深いものは
+    /// [`DisplayValue::MaxDepth`] になります。ポインタと参照は先をたどらずアドレスだけにします。
+    pub fn decode_typed(&self, memory: &dyn MemoryReader, address: u64, type_info: &TypeInfo) -> DisplayValue {
+        self.decode_nested(memory, address, type_info, 0)
+    }
+
+    fn decode_nested(&self, memory: &dyn MemoryReader, address: u64, type_info: &TypeInfo, depth: usize) -> DisplayValue {
+        let composite = matches!(
+            type_info,
+            TypeInfo::Struct { .. } | TypeInfo::Enum { .. } | TypeInfo::Array { .. } | TypeInfo::Union { .. }
+        );
+        if composite && depth >= self.config.max_depth {
+            return DisplayValue::MaxDepth;
+        }
+
+        match type_info {
+            TypeInfo::Primitive { name, size } => match memory.read(address as usize, *size as usize) {
+                Ok(bytes) => self.decode_primitive(&bytes, name),
+                Err(_) => DisplayValue::Unavailable,
+            },
+            TypeInfo::Pointer { .. } | TypeInfo::Reference { .. } => {
+                memory.read_u64(address as usize).map(DisplayValue::Ptr).unwrap_or(DisplayValue::Unavailable)
+            }
+            TypeInfo::Struct { name, fields, .. } | TypeInfo::Union { name, members: fields, .. } => DisplayValue::Struct {
+                name: name.clone(),
+                fields: self.decode_fields(memory, address, fields, depth),
+            },
+            TypeInfo::Enum { name, discriminant, variants, .. } => {
+                // discriminant を読み、一致する variant か、なければ niche のデフォルト variant を選ぶ
+                let value = match discriminant {
+                    Some(layout) => match read_uint(memory, address + layout.offset, layout.size) {
+                        Some(value) => Some(value),
+                        None => return DisplayValue::Unavailable,
+                    },
+                    None => None,
+                };
+                let variant = variants
+                    .iter()
+                    .find(|variant| value.is_some() && variant.discriminant == value)
+                    .or_else(|| variants.iter().find(|variant| variant.discriminant.is_none()));
+                match (variant, value) {
+                    (Some(variant), _) => DisplayValue::Enum {
+                        name: name.clone(),
+                        variant: variant.name.clone(),
+                        fields: self.decode_fields(memory, address, &variant.fields, depth),
+                    },
+                    (None, Some(value)) => DisplayValue::Enum {
+                        name: name.clone(),
+                        variant: format!("<discriminant {}>", value),
+                        fields: Vec::new(),
+                    },
+                    (None, None) => DisplayValue::Unavailable,
+                }
+            }
+            TypeInfo::Array { element_type: Some(element), length: Some(length) } if element.byte_size() > 0 => {
+                let element_size = element.byte_size();
+                let shown = (*length).min(self.config.max_array_elements as u64);
+                let elements = (0..shown)
+                    .map(|i| self.decode_nested(memory, address + i * element_size, element, depth + 1))
+                    .collect();
+                DisplayValue::Array(elements, *length > shown)
+            }
+            TypeInfo::Array { .. } | TypeInfo::Unknown => DisplayValue::Unavailable,
+        }
+    }
+
+    /// フィールドを1段深くデコードする（型の分からないフィールドは Unavailable）
+    fn decode_fields(&self, memory: &dyn MemoryReader, address: u64, fields: &[FieldInfo], depth: usize) -> Vec<(String, DisplayValue)> {
+        fields
+            .iter()
+            .map(|field| {
+                let value = match &field.type_info {
+                    Some(type_info) => self.decode_nested(memory, address + field.offset, type_info, depth + 1),
+                    None => DisplayValue::Unavailable,
+                };
+                // タプルのフィールド（`__0` など）は名前を付けずに表示する
+                let positional = field.name.strip_prefix("__").is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()));
+                (if positional { String::new() } else { field.name.clone() }, value)
+            })
+            .collect()
+    }
+
     /// Boxをデコードする（ポインタのみ）
     ///
     /// # Arguments
@@ -450,6 +541,17 @@ impl ValueDecoder {
     }
 }
 
+/// 1〜8 バイトの符号なし整数を読み取る
+fn read_uint(memory: &dyn MemoryReader, address: u64, size: u64) -> Option<u64> {
+    if !(1..=8).contains(&size) {
+        return None;
+    }
+    let bytes = memory.read(address as usize, size as usize).ok()?;
+    let mut buf = [0u8; 8];
+    buf[..bytes.len()].copy_from_slice(&bytes);
+    Some(u64::from_le_bytes(buf))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -623,4 +725,104 @@ mod tests {
             _ => panic!("Expected Ptr"),
         }
     }
+
+    struct SliceMemory(Vec<u8>);
+
+    impl MemoryReader for SliceMemory {
+        fn read_u8(&self, addr: usize) -> crate::Result<u8> {
+            Ok(self.read(addr, 1)?[0])
+        }
+
+        fn read_u16(&self, addr: usize) -> crate::Result<u16> {
+            Ok(read_uint(self, addr as u64, 2).unwrap() as u16)
+        }
+
+        fn read_u32(&self, addr: usize) -> crate::Result<u32> {
+            Ok(read_uint(self, addr as u64, 4).unwrap() as u32)
+        }
+
+        fn read_u64(&self, addr: usize) -> crate::Result<u64> {
+            read_uint(self, addr as u64, 8).ok_or_else(|| anyhow::anyhow!("unmapped"))
+        }
+
+        fn read(&self, addr: usize, size: usize) -> crate::Result<Vec<u8>> {
+            self.0.get(addr..addr + size).map(|b| b.to_vec()).ok_or_else(|| anyhow::anyhow!("unmapped"))
+        }
+    }
+
+    #[test]
+    fn test_decode_typed_nested() {
+        use crate::generator_layout::DiscriminantLayout;
+        use crate::type_info::VariantInfo;
+
+        let mut data = vec![0u8; 32];
+        data[0..4].copy_from_slice(&5u32.to_le_bytes());
+        data[4] = 2;
+        data[8..10].copy_from_slice(&10u16.to_le_bytes());
+        data[10..12].copy_from_slice(&20u16.to_le_bytes());
+        data[12..14].copy_from_slice(&30u16.to_le_bytes());
+        let memory = SliceMemory(data);
+
+        let field = |name: &str, offset, type_info| FieldInfo { name: name.to_string(), offset, size: 0, type_name: None, type_info: Some(Box::new(type_info)) };
+        let u16_type = TypeInfo::Primitive { name: "u16".to_string(), size: 2 };
+        let state = TypeInfo::Enum {
+            name: "State".to_string(),
+            size: 1,
+            discriminant: Some(DiscriminantLayout { offset: 0, size: 1 }),
+            variants: ["Idle", "Busy", "Done"]
+                .iter()
+                .enumerate()
+                .map(|(i, name)| VariantInfo { name: name.to_string(), discriminant: Some(i as u64), fields: Vec::new() })
+                .collect(),
+        };
+        let inner = TypeInfo::Struct {
+            name: "Inner".to_string(),
+            size: 6,
+            fields: vec![field("values", 0, TypeInfo::Array { element_type: Some(Box::new(u16_type)), length: Some(3) })],
+        };
+        let outer = TypeInfo::Struct {
+            name: "Outer".to_string(),
+            size: 14,
+            fields: vec![
+                field("id", 0, TypeInfo::Primitive { name: "u32".to_string(), size: 4 }),
+                field("state", 4, state),
+                field("inner", 8, inner),
+            ],
+        };
+
+        let decoder = ValueDecoder::default();
+        assert_eq!(
+            decoder.decode_typed(&memory, 0, &outer).to_string(),
+            "Outer { id: 5, state: State::Done, inner: Inner { values: [10, 20, 30] } }"
+        );
+
+        let shallow = ValueDecoder::new(DecodeConfig { max_depth: 2, max_array_elements: 2, ..DecodeConfig::default() });
+        assert_eq!(
+            shallow.decode_typed(&memory, 0, &outer).to_string(),
+            "Outer { id: 5, state: State::Done, inner: Inner { values: <max depth reached> } }"
+        );
+        // niche で表現された Option: discriminant が 0 なら None、それ以外はデフォルト variant の Some
+        let option = TypeInfo::Enum {
+            name: "Option<u16>".to_string(),
+            size: 2,
+            discriminant: Some(DiscriminantLayout { offset: 0, size: 2 }),
+            variants: vec![
+                VariantInfo { name: "None".to_string(), discriminant: Some(0), fields: Vec::new() },
+                VariantInfo {
+                    name: "Some".to_string(),
+                    discriminant: None,
+                    fields: vec![field("__0", 0, TypeInfo::Primitive { name: "u16".to_string(), size: 2 })],
+                },
+            ],
+        };
+        assert_eq!(decoder.decode_typed(&memory, 8, &option).to_string(), "Option<u16>::Some(10)");
+        assert_eq!(decoder.decode_typed(&memory, 16, &option).to_string(), "Option<u16>::None");
+
+        let TypeInfo::Struct { fields, .. } = &outer else { unreachable!() };
+        let narrow = ValueDecoder::new(DecodeConfig { max_array_elements: 2, ..DecodeConfig::default() });
+        assert_eq!(
+            narrow.decode_typed(&memory, 8, fields[2].type_info.as_ref().unwrap()).to_string(),
+            "Inner { values: [10, 20, ...] }"
+        );
+    }
 }
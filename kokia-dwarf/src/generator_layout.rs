@@ -1,6 +1,8 @@
 //! Generator レイアウト解析（discriminant位置の特定）
 
-use crate::Result;
+use crate::type_info::{FieldInfo, VariantInfo};
+use crate::utils::entry_name;
+use crate::{CancelToken, Result};
 use gimli::Reader;
 use tracing::debug;
 
@@ -16,11 +18,18 @@ pub struct DiscriminantLayout {
 /// Generatorレイアウトアナライザー
 pub struct GeneratorLayoutAnalyzer<'a> {
     dwarf: &'a gimli::Dwarf<gimli::EndianSlice<'a, gimli::RunTimeEndian>>,
+    cancel: CancelToken,
 }
 
 impl<'a> GeneratorLayoutAnalyzer<'a> {
     pub fn new(dwarf: &'a gimli::Dwarf<gimli::EndianSlice<'a, gimli::RunTimeEndian>>) -> Self {
-        Self { dwarf }
+        Self { dwarf, cancel: CancelToken::new() }
+    }
+
+    /// 走査を中断するトークンを設定する
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
     }
 
     /// Generator型のdiscriminant情報を取得
@@ -35,6 +44,7 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
         // DWARFからgenerator enum型を検索
         let mut iter = self.dwarf.units();
         while let Some(header) = iter.next()? {
+            self.cancel.check()?;
             let unit = self.dwarf.unit(header)?;
 
             if let Some(layout) = self.find_discriminant_in_unit(&unit, type_name)? {
@@ -66,6 +76,7 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
         let mut sample_names: Vec<String> = Vec::new();
 
         while let Some((_, entry)) = entries.next_dfs()? {
+            self.cancel.check()?;
             // enum型（DW_TAG_structure_type または DW_TAG_enumeration_type）を探す
             if entry.tag() == gimli::DW_TAG_structure_type {
                 structure_types_found += 1;
@@ -77,7 +88,7 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
                 || entry.tag() == gimli::DW_TAG_enumeration_type
             {
                 // 型名をチェック
-                if let Some(name) = self.get_entry_name(entry)? {
+                if let Some(name) = entry_name(self.dwarf, unit, entry) {
                     names_extracted += 1;
                     if sample_names.len() < 10 {
                         sample_names.push(name.clone());
@@ -147,27 +158,6 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
         Ok(None)
     }
 
-    /// entry から名前を取得
-    fn get_entry_name<R: Reader<Offset = usize>>(
-        &self,
-        entry: &gimli::DebuggingInformationEntry<R>,
-    ) -> Result<Option<String>> {
-        match entry.attr_value(gimli::DW_AT_name)? {
-            Some(gimli::AttributeValue::String(s)) => {
-                Ok(Some(s.to_string_lossy()?.into_owned()))
-            }
-            Some(gimli::AttributeValue::DebugStrRef(offset)) => {
-                // DebugStrRefを処理する
-                if let Ok(s) = self.dwarf.string(offset) {
-                    Ok(Some(s.to_string_lossy().into_owned()))
-                } else {
-                    Ok(None)
-                }
-            }
-            _ => Ok(None),
-        }
-    }
-
     /// enum型内でdiscriminantフィールドを探す
     fn find_discriminant_field<R: Reader<Offset = usize>>(
         &self,
@@ -190,7 +180,7 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
                     // Discriminant memberエントリを取得
                     let mut entries = unit.entries_at_offset(discr_offset)?;
                     if let Some((_, discr_entry)) = entries.next_dfs()? {
-                        let name = self.get_entry_name(discr_entry)?.unwrap_or_else(|| "<unnamed>".to_string());
+                        let name = entry_name(self.dwarf, unit, discr_entry).unwrap_or_else(|| "<unnamed>".to_string());
                         let offset = self.get_member_offset(discr_entry)?;
                         let size = self.get_member_size(unit, discr_entry)?;
 
@@ -215,7 +205,7 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
             let entry = child.entry();
 
             if entry.tag() == gimli::DW_TAG_member {
-                if let Some(name) = self.get_entry_name(entry)? {
+                if let Some(name) = entry_name(self.dwarf, unit, entry) {
                     if name == "__0" || name == "discriminant" || name == "__state" {
                         let offset = self.get_member_offset(entry)?;
                         let size = self.get_member_size(unit, entry)?;
@@ -295,6 +285,7 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
         // DWARFからgenerator enum型を検索
         let mut iter = self.dwarf.units();
         while let Some(header) = iter.next()? {
+            self.cancel.check()?;
             let unit = self.dwarf.unit(header)?;
 
             if let Some(variant) = self.find_variant_in_unit(&unit, type_name, discriminant_value)? {
@@ -317,11 +308,12 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
         let mut closure_count = 0;
 
         while let Some((_, entry)) = entries.next_dfs()? {
+            self.cancel.check()?;
             // enum型を探す
             if entry.tag() == gimli::DW_TAG_structure_type
                 || entry.tag() == gimli::DW_TAG_enumeration_type
             {
-                if let Some(name) = self.get_entry_name(entry)? {
+                if let Some(name) = entry_name(self.dwarf, unit, entry) {
                     if name.contains("{closure") || name.contains("{async_block") || name.contains("{async_fn") {
                         closure_count += 1;
 
@@ -419,13 +411,13 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
                             debug!("Variant has discriminant={}, looking for={}", discr_val, discriminant_value);
                             if discr_val == discriminant_value {
                                 // variant名とフィールドを抽出
-                                let name = self.get_entry_name(variant_entry)?
+                                let name = entry_name(self.dwarf, unit, variant_entry)
                                     .unwrap_or_else(|| format!("Variant{}", discriminant_value));
                                 debug!("Found matching variant: {}", name);
                                 let fields = self.extract_variant_fields(unit, variant_child)?;
                                 debug!("Extracted {} fields from variant", fields.len());
 
-                                return Ok(Some(VariantInfo { name, fields }));
+                                return Ok(Some(VariantInfo { name, discriminant: Some(discr_val), fields }));
                             }
                         } else {
                             debug!("Variant has no discriminant value");
@@ -442,13 +434,13 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
                     debug!("Variant has discriminant={}, looking for={}", discr_val, discriminant_value);
                     if discr_val == discriminant_value {
                         // variant名とフィールドを抽出
-                        let name = self.get_entry_name(entry)?
+                        let name = entry_name(self.dwarf, unit, entry)
                             .unwrap_or_else(|| format!("Variant{}", discriminant_value));
                         debug!("Found matching variant: {}", name);
                         let fields = self.extract_variant_fields(unit, child)?;
                         debug!("Extracted {} fields from variant", fields.len());
 
-                        return Ok(Some(VariantInfo { name, fields }));
+                        return Ok(Some(VariantInfo { name, discriminant: Some(discr_val), fields }));
                     }
                 } else {
                     debug!("Variant has no discriminant value");
@@ -460,6 +452,7 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
         debug!("No matching variant found, returning default empty variant");
         Ok(Some(VariantInfo {
             name: format!("State{}", discriminant_value),
+            discriminant: Some(discriminant_value),
             fields: Vec::new(),
         }))
     }
@@ -528,7 +521,7 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
         let mut fields = Vec::new();
 
         // 構造体名を表示
-        if let Some(name) = self.get_entry_name(struct_entry)? {
+        if let Some(name) = entry_name(self.dwarf, unit, struct_entry) {
             debug!("extract_struct_fields: struct name='{}'", name);
         }
 
@@ -567,7 +560,7 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
         unit: &gimli::Unit<R>,
         entry: &gimli::DebuggingInformationEntry<R>,
     ) -> Result<Option<FieldInfo>> {
-        let name = self.get_entry_name(entry)?
+        let name = entry_name(self.dwarf, unit, entry)
             .unwrap_or_else(|| "<unnamed>".to_string());
         let offset = self.get_member_offset(entry)?.unwrap_or(0);
         let size = self.get_member_size(unit, entry)?.unwrap_or(0);
@@ -578,6 +571,7 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
             offset,
             size,
             type_name,
+            type_info: None,
         }))
     }
 
@@ -596,31 +590,9 @@ impl<'a> GeneratorLayoutAnalyzer<'a> {
         // 型DIEを取得
         let mut entries = unit.entries_at_offset(type_offset)?;
         if let Some((_, type_entry)) = entries.next_dfs()? {
-            return self.get_entry_name(type_entry);
+            return Ok(entry_name(self.dwarf, unit, type_entry));
         }
 
         Ok(None)
     }
 }
-
-/// Variant情報
-#[derive(Debug, Clone)]
-pub struct VariantInfo {
-    /// Variant名
-    pub name: String,
-    /// フィールド情報のリスト
-    pub fields: Vec<FieldInfo>,
-}
-
-/// フィールド情報
-#[derive(Debug, Clone)]
-pub struct FieldInfo {
-    /// フィールド名
-    pub name: String,
-    /// オフセット
-    pub offset: u64,
-    /// サイズ
-    pub size: u64,
-    /// 型名
-    pub type_name: Option<String>,
-}
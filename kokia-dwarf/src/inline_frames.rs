@@ -0,0 +1,73 @@
+//! インライン展開を含むソース位置の解決
+//!
+//! 最適化されたバイナリでは、1つのアドレスが複数の関数（インライン展開された関数と
+//! その呼び出し元）に属します。DW_TAG_inlined_subroutine をたどって、最も内側の関数から
+//! 外側の実体を持つ関数までを順に返します（`addr2line -i` と同じ並び）。
+
+use crate::{DwarfLoader, Result};
+use object::Object;
+
+type R = gimli::EndianSlice<'static, gimli::RunTimeEndian>;
+
+/// 1つのフレーム（インライン展開された関数、または実体を持つ関数）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InlineFrame {
+    /// 関数名（デマングル済み）
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+/// アドレスからインライン展開を含むフレームを求める
+pub struct InlineFrameResolver {
+    context: addr2line::Context<R>,
+}
+
+impl InlineFrameResolver {
+    /// 読み込んだバイナリの DWARF から作る
+    pub fn new(loader: &DwarfLoader) -> Result<Self> {
+        let endian = if loader.object_file().is_little_endian() {
+            gimli::RunTimeEndian::Little
+        } else {
+            gimli::RunTimeEndian::Big
+        };
+        let dwarf = gimli::Dwarf::load(|id| -> Result<R> {
+            Ok(gimli::EndianSlice::new(loader.section_data(id.name()).unwrap_or(&[]), endian))
+        })?;
+        let context = addr2line::Context::from_dwarf(dwarf)
+            .map_err(|e| anyhow::anyhow!("Failed to index DWARF for address lookup: {}", e))?;
+        Ok(Self { context })
+    }
+
+    /// アドレス（バイナリ内のアドレス）のフレームを内側から順に返す
+    ///
+    /// どのコンパイルユニットにも入らないアドレスでは空になります。
+    /// split-debuginfo の .dwo / .dwp は読まないので、スケルトンユニットでは行番号表だけが使われます。
+    pub fn frames(&self, address: u64) -> Result<Vec<InlineFrame>> {
+        let mut frames = Vec::new();
+        let mut iter = self.context.find_frames(address).skip_all_loads()?;
+        while let Some(frame) = iter.next()? {
+            let function = frame.function
+                .as_ref()
+                .and_then(|name| name.demangle().ok())
+                .map(|name| name.into_owned());
+            let location = frame.location.as_ref();
+            frames.push(InlineFrame {
+                function,
+                file: location.and_then(|loc| loc.file).map(str::to_string),
+                line: location.and_then(|loc| loc.line),
+            });
+        }
+        // 関数の情報がなく行番号表だけ引けるときは、その位置を1つのフレームとして返す
+        if frames.is_empty() {
+            if let Some(location) = self.context.find_location(address)? {
+                frames.push(InlineFrame {
+                    function: None,
+                    file: location.file.map(str::to_string),
+                    line: location.line,
+                });
+            }
+        }
+        Ok(frames)
+    }
+}
@@ -0,0 +1,109 @@
+//! アドレス範囲の区間木
+//!
+//! 開始アドレス順に並べた区間を、中央の要素を根とする暗黙の平衡二分木とみなし、部分木ごとに
+//! 終わりのアドレスの最大値を持たせます。ある点を含む区間を、重なりがあっても O(log n + k) で
+//! すべて列挙できます。作成後に区間は追加・削除しません。
+
+use std::ops::Range;
+
+/// 作成後に変更しない区間木
+#[derive(Debug, Clone)]
+pub struct IntervalTree<T> {
+    /// 開始アドレス順の区間
+    items: Vec<(Range<u64>, T)>,
+    /// `items[i]` を根とする部分木の終わりのアドレスの最大値
+    max_end: Vec<u64>,
+}
+
+impl<T> IntervalTree<T> {
+    /// 区間木を作成する（空の区間は捨てる）
+    pub fn new(mut items: Vec<(Range<u64>, T)>) -> Self {
+        items.retain(|(range, _)| !range.is_empty());
+        items.sort_by_key(|(range, _)| range.start);
+        let mut tree = Self { max_end: vec![0; items.len()], items };
+        tree.fill_max_end(0, tree.items.len());
+        tree
+    }
+
+    /// `point` を含む区間を開始アドレス順に返す
+    pub fn containing(&self, point: u64) -> Vec<(&Range<u64>, &T)> {
+        let mut found = Vec::new();
+        self.visit(0, self.items.len(), point, &mut found);
+        found
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// 開始アドレス順のすべての区間
+    pub fn iter(&self) -> impl Iterator<Item = (&Range<u64>, &T)> {
+        self.items.iter().map(|(range, value)| (range, value))
+    }
+
+    fn fill_max_end(&mut self, lo: usize, hi: usize) -> u64 {
+        if lo >= hi {
+            return 0;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let end = self.items[mid].0.end
+            .max(self.fill_max_end(lo, mid))
+            .max(self.fill_max_end(mid + 1, hi));
+        self.max_end[mid] = end;
+        end
+    }
+
+    fn visit<'a>(&'a self, lo: usize, hi: usize, point: u64, found: &mut Vec<(&'a Range<u64>, &'a T)>) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        // この部分木のどの区間も point より前で終わっている
+        if self.max_end[mid] <= point {
+            return;
+        }
+        self.visit(lo, mid, point, found);
+        let (range, value) = &self.items[mid];
+        // 右の部分木は range より後に始まるので、range が point より後ならそちらも見なくてよい
+        if range.start <= point {
+            if point < range.end {
+                found.push((range, value));
+            }
+            self.visit(mid + 1, hi, point, found);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_containing_matches_linear_scan() {
+        let ranges = [
+            0x1000..0x1100,
+            0x1040..0x1060,
+            0x1040..0x1080,
+            0x1100..0x1200,
+            0x1180..0x1190,
+            0x2000..0x2000,
+            0x0f00..0x3000,
+            0x2500..0x2600,
+        ];
+        let tree = IntervalTree::new(ranges.iter().cloned().zip(0..).collect());
+        assert_eq!(tree.len(), 7);
+
+        for point in (0x0e00..0x3100).step_by(0x10) {
+            let mut expected: Vec<usize> = (0..ranges.len()).filter(|&i| ranges[i].contains(&point)).collect();
+            let mut found: Vec<usize> = tree.containing(point).into_iter().map(|(_, i)| *i).collect();
+            expected.sort_unstable();
+            found.sort_unstable();
+            assert_eq!(found, expected, "point 0x{:x}", point);
+        }
+        assert!(IntervalTree::<()>::new(Vec::new()).containing(0).is_empty());
+    }
+}
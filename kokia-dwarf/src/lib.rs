@@ -12,20 +12,34 @@ pub mod generator_layout;
 pub mod loc_eval;
 pub mod decode;
 pub mod type_info;
+pub mod type_index;
 pub mod value_formatter;
+pub mod cancel;
+pub mod progress;
+pub mod call_site;
+pub mod build_info;
+pub mod inline_frames;
+pub mod interval_tree;
+pub mod call_frame;
 
 pub use loader::DwarfLoader;
 pub use symbols::{Symbol, SymbolResolver};
 pub use lines::{LineInfo, LineInfoProvider};
-pub use variables::{LocalVariable, Variable, VariableLocator, VariableLocation, VariableValue};
-pub use utils::FunctionFinder;
-pub use generator_layout::{
-    DiscriminantLayout, GeneratorLayoutAnalyzer, VariantInfo, FieldInfo,
-};
+pub use variables::{LocalVariable, StaticLocation, StaticVariable, Variable, VariableKind, VariableLocator, VariableLocation, VariableValue};
+pub use utils::{FunctionDie, FunctionFinder};
+pub use generator_layout::{DiscriminantLayout, GeneratorLayoutAnalyzer};
 pub use loc_eval::{Loc, LocPiece, LocPieceLocation, LocationEvaluator};
 pub use decode::{DisplayValue, ValueDecoder, DecodeConfig};
-pub use type_info::{TypeInfo, TypeInfoExtractor, FieldInfo as TypeFieldInfo, VariantInfo as TypeVariantInfo};
-pub use value_formatter::{ValueFormatter, MemoryReader, FormatOptions};
+pub use type_info::{TypeInfo, TypeInfoExtractor, FieldInfo, VariantInfo};
+pub use type_index::{function_parameters, function_return_type, TypeIndex, TypeKind, TypeLayout, TypeRef, MemberLayout, VariantLayout, IndexedType};
+pub use value_formatter::{ValueFormatter, MemoryReader, FormatOptions, ContainerLimits};
+pub use cancel::{CancelReason, CancelToken, Cancelled};
+pub use progress::{Progress, ProgressCallback, ProgressEvent};
+pub use call_site::{CallSite, CallSiteIndex, CallSiteParameter};
+pub use build_info::BuildInfo;
+pub use inline_frames::{InlineFrame, InlineFrameResolver};
+pub use interval_tree::IntervalTree;
+pub use call_frame::{CallFrameTable, FrameRule};
 
 /// DWARF解析の結果型
 pub type Result<T> = anyhow::Result<T>;
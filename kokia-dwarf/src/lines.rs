@@ -82,6 +82,10 @@ impl<'a> LineInfoProvider<'a> {
     }
 
     /// アドレスからソース行情報を取得する
+    ///
+    /// 行番号プログラムはシーケンス（連続したアドレス範囲）ごとにアドレス順になっているだけなので、
+    /// シーケンスの終わりで前の行を捨てながら全シーケンスを調べます。コールドな部分に分割された
+    /// 関数は、本体とは別のシーケンスになります。
     pub fn lookup(&self, addr: u64) -> Result<Option<LineInfo>> {
         let dwarf = self.loader.dwarf();
         let mut units = dwarf.units();
@@ -96,19 +100,21 @@ impl<'a> LineInfoProvider<'a> {
                 while let Some((_, row)) = rows.next_row()? {
                     let row_addr = row.address();
 
-                    if row_addr > addr {
-                        // アドレスを超えた場合、前の行が該当
-                        if let Some(prev) = prev_row {
-                            return Ok(Some(self.extract_line_info(&unit, &prev)?));
-                        }
-                        break;
+                    // 前の行から次の行（またはシーケンスの終わり）の手前までが前の行の範囲
+                    if let Some(prev) = prev_row.filter(|p| p.address() <= addr && addr < row_addr) {
+                        return Ok(Some(self.extract_line_info(&unit, &prev)?));
+                    }
+
+                    if row.end_sequence() {
+                        prev_row = None;
+                        continue;
                     }
 
                     if row_addr == addr {
-                        return Ok(Some(self.extract_line_info(&unit, &row)?));
+                        return Ok(Some(self.extract_line_info(&unit, row)?));
                     }
 
-                    prev_row = Some(row.clone());
+                    prev_row = Some(*row);
                 }
             }
         }
@@ -193,7 +199,7 @@ impl<'a> LineInfoProvider<'a> {
                     if let Some(line) = row.line() {
                         if line.get() == target_line as u64 {
                             // ファイル名をチェック
-                            if let Some(file_name) = self.get_file_name(&unit, &row) {
+                            if let Some(file_name) = self.get_file_name(&unit, row) {
                                 // 部分一致でファイル名を検索（末尾一致も許容）
                                 if file_name.ends_with(file_pattern) || file_name.contains(file_pattern) {
                                     // is_stmt（ステートメント開始位置）を優先
@@ -268,7 +274,7 @@ impl<'a> LineInfoProvider<'a> {
                         // 現在の行より大きい行番号をチェック
                         if line_num > current_line && row.is_stmt() {
                             // ファイル名が一致するかチェック
-                            if let Some(file_name) = self.get_file_name(&unit, &row) {
+                            if let Some(file_name) = self.get_file_name(&unit, row) {
                                 if file_name == current_file {
                                     return Ok(Some(addr));
                                 }
@@ -1,6 +1,8 @@
 //! ELFとDWARFの読み込み機能
 
-use crate::Result;
+use crate::{FunctionDie, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::path::Path;
 use std::fs;
 use std::rc::Rc;
@@ -12,6 +14,8 @@ pub struct DwarfLoader {
     object_file: Rc<object::File<'static>>,
     /// DWARFコンテキスト
     dwarf: gimli::Dwarf<gimli::EndianSlice<'static, gimli::RunTimeEndian>>,
+    /// PC から引いた関数 DIE（[`FunctionFinder::find_in_loader`](crate::FunctionFinder::find_in_loader) が使う）
+    function_dies: RefCell<HashMap<u64, Option<FunctionDie>>>,
 }
 
 impl DwarfLoader {
@@ -53,6 +57,7 @@ impl DwarfLoader {
         Ok(Self {
             object_file: Rc::new(object_file),
             dwarf,
+            function_dies: RefCell::new(HashMap::new()),
         })
     }
 
@@ -61,11 +66,28 @@ impl DwarfLoader {
         &self.dwarf
     }
 
+    pub(crate) fn function_dies(&self) -> &RefCell<HashMap<u64, Option<FunctionDie>>> {
+        &self.function_dies
+    }
+
     /// オブジェクトファイルへの参照を取得
     pub fn object_file(&self) -> &object::File<'static> {
         &self.object_file
     }
 
+    /// セクションの内容を取得する（存在しない、または中身がファイルにないセクションは None）
+    pub fn section_data(&self, name: &str) -> Option<&'static [u8]> {
+        self.object_file.section_by_name(name)?.data().ok()
+    }
+
+    /// アドレスが機械語のセクション（.text など）に入るか
+    pub fn is_code_address(&self, address: u64) -> bool {
+        self.object_file.sections().any(|section| {
+            section.kind() == object::SectionKind::Text
+                && (section.address()..section.address() + section.size()).contains(&address)
+        })
+    }
+
     /// PIE（Position Independent Executable）かどうかを判定する
     ///
     /// PIE実行ファイルの場合、シンボルアドレスはオフセットであり、
@@ -79,4 +101,9 @@ impl DwarfLoader {
         // ET_EXEC (Executable) = 非PIE実行ファイル
         matches!(self.object_file.kind(), ObjectKind::Dynamic)
     }
+
+    /// `.note.gnu.build-id` のビルドIDを取得する（ノートがなければ None）
+    pub fn build_id(&self) -> Option<&'static [u8]> {
+        self.object_file.build_id().ok().flatten()
+    }
 }
@@ -4,6 +4,7 @@
 
 use crate::Result;
 use gimli::{Reader, Evaluation, EvaluationResult, Location, Piece, Value};
+use std::collections::HashMap;
 
 /// ロケーション評価の結果
 #[derive(Debug, Clone)]
@@ -12,6 +13,8 @@ pub enum Loc {
     Reg { reg: u16 },
     /// メモリアドレス
     Addr { addr: u64, size: usize },
+    /// 値そのもの（DW_OP_stack_value、DW_OP_implicit_value）
+    Value(Vec<u8>),
     /// 複数のピースから構成される（構造体の一部など）
     Pieces(Vec<LocPiece>),
     /// 最適化により削除された
@@ -44,6 +47,8 @@ pub enum LocPieceLocation {
 pub struct LocationEvaluator<'a, R: Reader> {
     eval: Option<Evaluation<R>>,
     frame_base: Option<u64>,
+    /// 関数の入口でのレジスタ値（DW_OP_entry_value の評価に使う）
+    entry_values: HashMap<u16, u64>,
     #[allow(dead_code)]
     encoding: gimli::Encoding,
     _marker: std::marker::PhantomData<&'a ()>,
@@ -61,11 +66,21 @@ impl<'a, R: Reader<Offset = usize>> LocationEvaluator<'a, R> {
         Self {
             eval: Some(eval),
             frame_base,
+            entry_values: HashMap::new(),
             encoding,
             _marker: std::marker::PhantomData,
         }
     }
 
+    /// 関数の入口でのレジスタ値を設定する
+    ///
+    /// 呼び出し元の DW_TAG_call_site_parameter から復元した値で、最適化で上書きされた引数の
+    /// `DW_OP_entry_value(DW_OP_regN)` を評価できるようになります。
+    pub fn with_entry_values(mut self, entry_values: HashMap<u16, u64>) -> Self {
+        self.entry_values = entry_values;
+        self
+    }
+
     /// ロケーション式を評価する
     ///
     /// # Arguments
@@ -82,23 +97,33 @@ impl<'a, R: Reader<Offset = usize>> LocationEvaluator<'a, R> {
     {
         let mut eval = self.eval.take().ok_or_else(|| anyhow::anyhow!("Evaluation already consumed"))?;
 
+        // resume_with_* は評価を再開して次の結果を返すため、evaluate() は最初の一回だけ呼ぶ
+        // （待機中に evaluate() を呼ぶと gimli が panic する）
+        let mut step = eval.evaluate()?;
         loop {
-            match eval.evaluate()? {
+            step = match step {
                 EvaluationResult::Complete => {
                     break;
                 }
                 EvaluationResult::RequiresRegister { register, .. } => {
-                    let reg_num = register.0 as u16;
+                    let reg_num = register.0;
                     let value = get_reg(reg_num)?;
-                    eval.resume_with_register(Value::Generic(value))?;
+                    eval.resume_with_register(Value::Generic(value))?
                 }
                 EvaluationResult::RequiresFrameBase => {
                     if let Some(fb) = self.frame_base {
-                        eval.resume_with_frame_base(fb)?;
+                        eval.resume_with_frame_base(fb)?
                     } else {
                         return Err(anyhow::anyhow!("Frame base required but not provided"));
                     }
                 }
+                EvaluationResult::RequiresEntryValue(expr) => {
+                    let reg = single_register(&expr, self.encoding)
+                        .ok_or_else(|| anyhow::anyhow!("Unsupported entry value expression"))?;
+                    let value = self.entry_values.get(&reg)
+                        .ok_or_else(|| anyhow::anyhow!("Entry value of register {} is unknown", reg))?;
+                    eval.resume_with_entry_value(Value::Generic(*value))?
+                }
                 EvaluationResult::RequiresMemory { address, size, .. } => {
                     let bytes = read_mem(address, size as usize)?;
                     // gimliは結果をu64として期待する場合が多い
@@ -106,12 +131,12 @@ impl<'a, R: Reader<Offset = usize>> LocationEvaluator<'a, R> {
                     let copy_size = bytes.len().min(8);
                     value_bytes[..copy_size].copy_from_slice(&bytes[..copy_size]);
                     let value = u64::from_le_bytes(value_bytes);
-                    eval.resume_with_memory(Value::Generic(value))?;
+                    eval.resume_with_memory(Value::Generic(value))?
                 }
                 other => {
                     return Err(anyhow::anyhow!("Unsupported evaluation result: {:?}", other));
                 }
-            }
+            };
         }
 
         // 評価結果を取得
@@ -140,17 +165,14 @@ impl<'a, R: Reader<Offset = usize>> LocationEvaluator<'a, R> {
         match piece.location {
             Location::Empty => Ok(Loc::Empty),
             Location::Register { register } => Ok(Loc::Reg {
-                reg: register.0 as u16,
+                reg: register.0,
             }),
             Location::Address { address } => {
                 let size = piece.size_in_bits.map(|b| (b / 8) as usize).unwrap_or(8);
                 Ok(Loc::Addr { addr: address, size })
             }
-            Location::Value { value: _ } => {
-                // 値そのものが格納されている場合、アドレスとしては扱えない
-                // Empty として返すか、エラーにする
-                Ok(Loc::Empty)
-            }
+            Location::Value { value } => Ok(Loc::Value(value_bytes(value))),
+            Location::Bytes { ref value } => Ok(Loc::Value(value.to_slice()?.into_owned())),
             _ => Ok(Loc::Empty),
         }
     }
@@ -162,25 +184,9 @@ impl<'a, R: Reader<Offset = usize>> LocationEvaluator<'a, R> {
 
         let location = match piece.location {
             Location::Empty => return Err(anyhow::anyhow!("Empty piece location")),
-            Location::Register { register } => LocPieceLocation::Reg(register.0 as u16),
+            Location::Register { register } => LocPieceLocation::Reg(register.0),
             Location::Address { address } => LocPieceLocation::Addr(address),
-            Location::Value { value } => {
-                // 値を直接取得
-                let bytes = match value {
-                    Value::Generic(v) => v.to_le_bytes().to_vec(),
-                    Value::I8(v) => vec![v as u8],
-                    Value::U8(v) => vec![v],
-                    Value::I16(v) => v.to_le_bytes().to_vec(),
-                    Value::U16(v) => v.to_le_bytes().to_vec(),
-                    Value::I32(v) => v.to_le_bytes().to_vec(),
-                    Value::U32(v) => v.to_le_bytes().to_vec(),
-                    Value::I64(v) => v.to_le_bytes().to_vec(),
-                    Value::U64(v) => v.to_le_bytes().to_vec(),
-                    Value::F32(v) => v.to_le_bytes().to_vec(),
-                    Value::F64(v) => v.to_le_bytes().to_vec(),
-                };
-                LocPieceLocation::Value(bytes)
-            }
+            Location::Value { value } => LocPieceLocation::Value(value_bytes(value)),
             _ => return Err(anyhow::anyhow!("Unsupported piece location")),
         };
 
@@ -192,6 +198,63 @@ impl<'a, R: Reader<Offset = usize>> LocationEvaluator<'a, R> {
     }
 }
 
+/// gimli の値をリトルエンディアンのバイト列にする
+fn value_bytes(value: Value) -> Vec<u8> {
+    match value {
+        Value::Generic(v) => v.to_le_bytes().to_vec(),
+        Value::I8(v) => vec![v as u8],
+        Value::U8(v) => vec![v],
+        Value::I16(v) => v.to_le_bytes().to_vec(),
+        Value::U16(v) => v.to_le_bytes().to_vec(),
+        Value::I32(v) => v.to_le_bytes().to_vec(),
+        Value::U32(v) => v.to_le_bytes().to_vec(),
+        Value::I64(v) => v.to_le_bytes().to_vec(),
+        Value::U64(v) => v.to_le_bytes().to_vec(),
+        Value::F32(v) => v.to_le_bytes().to_vec(),
+        Value::F64(v) => v.to_le_bytes().to_vec(),
+    }
+}
+
+/// 式が1つのレジスタ（DW_OP_regN、DW_OP_regx）だけからなるなら、そのレジスタ番号を返す
+pub fn single_register<R: Reader>(expr: &gimli::Expression<R>, encoding: gimli::Encoding) -> Option<u16> {
+    let mut ops = expr.clone().operations(encoding);
+    let reg = match ops.next().ok()?? {
+        gimli::Operation::Register { register } => register.0,
+        _ => return None,
+    };
+    ops.next().ok()?.is_none().then_some(reg)
+}
+
+/// 値を求める DWARF 式（DW_AT_call_value など）を評価する
+///
+/// 結果がメモリ上の位置を指す形（スタックの先頭）ならその値を、DW_OP_stack_value なら値そのものを、
+/// レジスタなら `get_reg` で読んだ値を返します。
+pub fn evaluate_value<R, F, G>(
+    expr: gimli::Expression<R>,
+    encoding: gimli::Encoding,
+    frame_base: Option<u64>,
+    mut get_reg: F,
+    read_mem: G,
+) -> Result<u64>
+where
+    R: Reader<Offset = usize>,
+    F: FnMut(u16) -> Result<u64>,
+    G: FnMut(u64, usize) -> Result<Vec<u8>>,
+{
+    let mut evaluator = LocationEvaluator::new(expr, frame_base, encoding);
+    match evaluator.evaluate(&mut get_reg, read_mem)? {
+        Loc::Addr { addr, .. } => Ok(addr),
+        Loc::Reg { reg } => get_reg(reg),
+        Loc::Value(bytes) => {
+            let mut word = [0u8; 8];
+            let len = bytes.len().min(8);
+            word[..len].copy_from_slice(&bytes[..len]);
+            Ok(u64::from_le_bytes(word))
+        }
+        Loc::Pieces(_) | Loc::Empty => Err(anyhow::anyhow!("Expression has no single value")),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -214,4 +277,33 @@ mod tests {
             _ => panic!("Expected Addr"),
         }
     }
+
+    #[test]
+    fn test_evaluate_resumes_multi_step_expression() {
+        // DW_OP_breg6 -16; DW_OP_deref（レジスタとメモリの両方を待つ）
+        let bytes = [0x76, 0x70, 0x06];
+        let encoding = gimli::Encoding { format: gimli::Format::Dwarf32, version: 4, address_size: 8 };
+        let expr = gimli::Expression(gimli::EndianSlice::new(&bytes, gimli::LittleEndian));
+        let mut registers = Vec::new();
+        let mut reads = Vec::new();
+        let loc = LocationEvaluator::new(expr, None, encoding)
+            .evaluate(
+                |reg| {
+                    registers.push(reg);
+                    Ok(0x7000)
+                },
+                |address, size| {
+                    reads.push((address, size));
+                    Ok(0x1234u64.to_le_bytes().to_vec())
+                },
+            )
+            .unwrap();
+
+        match loc {
+            Loc::Addr { addr, .. } => assert_eq!(addr, 0x1234),
+            other => panic!("Expected Addr, got {:?}", other),
+        }
+        assert_eq!(registers, [6]);
+        assert_eq!(reads, [(0x7000 - 16, 8)]);
+    }
 }
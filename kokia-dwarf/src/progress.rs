@@ -0,0 +1,122 @@
+//! 長時間処理の進捗通知
+//!
+//! バイナリの読み込みや型索引の構築、大量のブレークポイント設置などの進捗を
+//! [`ProgressEvent`] としてコールバックに渡します。イベントは開始・更新・終了の3種類で、
+//! CLI のスピナー表示や DAP の progressStart/progressUpdate/progressEnd にそのまま対応します。
+
+use std::rc::Rc;
+
+/// 進捗イベント
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// 処理の開始（件数が分かっていれば total に入る）
+    Start { title: String, total: Option<u64> },
+    /// 完了件数の更新
+    Update { done: u64, total: Option<u64> },
+    /// 処理の終了
+    End,
+}
+
+/// 進捗イベントを受け取るコールバック
+pub type ProgressCallback = Rc<dyn Fn(&ProgressEvent)>;
+
+/// 件数が分からない場合に Update を送る間隔
+const UNKNOWN_TOTAL_STEP: u64 = 256;
+
+/// 1つの処理の進捗を数え、適度に間引いてコールバックへ通知する
+///
+/// コールバックが設定されていなければ何もしません。Drop 時に未送信なら End を送ります。
+pub struct Progress {
+    callback: Option<ProgressCallback>,
+    done: u64,
+    total: Option<u64>,
+    /// 最後に通知した値（total ありなら百分率、なしなら件数）
+    last_reported: u64,
+    finished: bool,
+}
+
+impl Progress {
+    /// 処理を開始して Start を通知する
+    pub fn start(callback: Option<&ProgressCallback>, title: impl Into<String>, total: Option<u64>) -> Self {
+        let callback = callback.cloned();
+        if let Some(cb) = &callback {
+            cb(&ProgressEvent::Start { title: title.into(), total });
+        }
+        Self { callback, done: 0, total, last_reported: 0, finished: false }
+    }
+
+    /// 通知しない進捗（コールバック未設定時用）
+    pub fn none() -> Self {
+        Self { callback: None, done: 0, total: None, last_reported: 0, finished: true }
+    }
+
+    /// 完了件数を `n` 進める
+    pub fn advance(&mut self, n: u64) {
+        self.done += n;
+        let Some(cb) = &self.callback else {
+            return;
+        };
+        let report = match self.total {
+            Some(total) if total > 0 => (self.done * 100 / total).min(100),
+            _ => self.done / UNKNOWN_TOTAL_STEP * UNKNOWN_TOTAL_STEP,
+        };
+        if report != self.last_reported {
+            self.last_reported = report;
+            cb(&ProgressEvent::Update { done: self.done, total: self.total });
+        }
+    }
+
+    /// 処理を終えて End を通知する
+    pub fn finish(mut self) {
+        self.send_end();
+    }
+
+    fn send_end(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        if let Some(cb) = &self.callback {
+            cb(&ProgressEvent::End);
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        // エラーで途中終了した場合も表示を閉じる
+        self.send_end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_progress_throttles_updates() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let callback: ProgressCallback = {
+            let events = events.clone();
+            Rc::new(move |e: &ProgressEvent| events.borrow_mut().push(e.clone()))
+        };
+
+        let mut progress = Progress::start(Some(&callback), "Indexing", Some(1000));
+        for _ in 0..1000 {
+            progress.advance(1);
+        }
+        drop(progress);
+
+        let events = events.borrow();
+        assert_eq!(events.first(), Some(&ProgressEvent::Start { title: "Indexing".into(), total: Some(1000) }));
+        // 1% ごとに1回（100回）だけ Update が送られる
+        assert_eq!(events.iter().filter(|e| matches!(e, ProgressEvent::Update { .. })).count(), 100);
+        assert_eq!(events.last(), Some(&ProgressEvent::End));
+
+        // コールバックなしでは何も起きない
+        let mut silent = Progress::start(None, "Silent", None);
+        silent.advance(10);
+        silent.finish();
+    }
+}
@@ -1,8 +1,9 @@
 //! シンボル解決機能
 
-use crate::{DwarfLoader, Result};
+use crate::{DwarfLoader, FunctionFinder, IntervalTree, Progress, ProgressCallback, Result};
 use std::collections::HashMap;
-use object::{Object, ObjectSymbol};
+use std::ops::Range;
+use object::{Object, ObjectSection, ObjectSymbol, SectionKind};
 
 /// シンボル情報
 #[derive(Debug, Clone)]
@@ -45,12 +46,142 @@ fn demangle_symbol(name: &str) -> String {
     name.to_string()
 }
 
+/// DWARF の関数 DIE ごとのアドレス範囲を集める
+fn dwarf_function_ranges(loader: &DwarfLoader) -> Result<Vec<Vec<(u64, u64)>>> {
+    let dwarf = loader.dwarf();
+    let mut functions = Vec::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs()? {
+            if entry.tag() != gimli::DW_TAG_subprogram {
+                continue;
+            }
+            if let Ok(ranges) = FunctionFinder::function_ranges(dwarf, &unit, entry) {
+                if !ranges.is_empty() {
+                    functions.push(ranges);
+                }
+            }
+        }
+    }
+    Ok(functions)
+}
+
+/// シンボルがコールドな部分（`foo.cold`、`foo.cold.1`）を指しているか
+fn is_cold_part(name: &str) -> bool {
+    name.ends_with(".cold") || name.contains(".cold.")
+}
+
+/// 複数の範囲に分割された関数の、先頭以外の範囲
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SplitPart {
+    start: u64,
+    end: u64,
+    /// 関数本体（エントリのある範囲）の開始アドレス
+    parent: u64,
+}
+
+/// 複数の範囲を持つ関数から、本体以外の範囲を取り出す
+///
+/// 本体は、コールドな部分でないシンボルが先頭にある範囲です。見つからなければ最初の範囲を本体とします。
+fn split_parts(functions: &[Vec<(u64, u64)>], symbols: &[Symbol]) -> Vec<SplitPart> {
+    let has_entry_symbol = |address: u64| {
+        let first = symbols.partition_point(|s| s.address < address);
+        symbols[first..].iter()
+            .take_while(|s| s.address == address)
+            .any(|s| !is_cold_part(&s.name))
+    };
+
+    let mut parts = Vec::new();
+    for ranges in functions.iter().filter(|r| r.len() > 1) {
+        let primary = ranges.iter().position(|(start, _)| has_entry_symbol(*start)).unwrap_or(0);
+        let parent = ranges[primary].0;
+        for (i, &(start, end)) in ranges.iter().enumerate() {
+            if i != primary {
+                parts.push(SplitPart { start, end, parent });
+            }
+        }
+    }
+    parts.sort_by_key(|p| p.start);
+    parts.dedup();
+    parts
+}
+
+/// シンボルテーブルにサイズがない（0 の）関数シンボルのサイズを補う
+///
+/// `symbols` はアドレス順に並んでいる必要があります。同じアドレスの別名にサイズがあればそれを、
+/// なければ DWARF の DW_AT_high_pc を、それもなければ同じセクション内の次のシンボルの
+/// アドレス（最後のシンボルはセクションの終わり）までを関数の範囲とみなします。
+/// 実行可能セクションの外にあるシンボル（データなど）は変更しません。
+fn fill_missing_sizes(symbols: &mut [Symbol], text_sections: &[(u64, u64)], dwarf_ends: &HashMap<u64, u64>) {
+    for i in 0..symbols.len() {
+        if symbols[i].size != 0 {
+            continue;
+        }
+        let address = symbols[i].address;
+        let Some(&(_, section_end)) = text_sections.iter().find(|(start, end)| (*start..*end).contains(&address)) else {
+            continue;
+        };
+
+        let first = symbols.partition_point(|s| s.address < address);
+        let alias = symbols[first..].iter()
+            .take_while(|s| s.address == address)
+            .map(|s| s.size)
+            .find(|size| *size > 0);
+        let end = match alias {
+            Some(size) => address + size,
+            None => match dwarf_ends.get(&address) {
+                Some(&end) => end,
+                None => symbols[i + 1..].iter()
+                    .map(|s| s.address)
+                    .find(|a| *a > address)
+                    .map_or(section_end, |next| next.min(section_end)),
+            },
+        };
+        symbols[i].size = end.saturating_sub(address);
+    }
+}
+
+/// 区間木の区間が指すシンボル
+#[derive(Debug, Clone, Copy)]
+struct RangeOwner {
+    /// `symbols_by_address` の添字
+    symbol: usize,
+    /// 分割された関数の本体以外の範囲か（同じ範囲のコールドな部分のシンボルより優先する）
+    split: bool,
+}
+
+/// シンボルの実効的な範囲の区間木を作る
+///
+/// サイズのあるシンボルはその範囲を、分割された関数の本体以外の範囲は本体のシンボルを指します。
+fn build_ranges(symbols: &[Symbol], split_parts: &[SplitPart]) -> IntervalTree<RangeOwner> {
+    let mut ranges: Vec<(Range<u64>, RangeOwner)> = symbols.iter().enumerate()
+        .filter(|(_, s)| s.size > 0)
+        .map(|(symbol, s)| (s.address..s.address + s.size, RangeOwner { symbol, split: false }))
+        .collect();
+    for part in split_parts {
+        let first = symbols.partition_point(|s| s.address < part.parent);
+        let body = (first..symbols.len())
+            .take_while(|&i| symbols[i].address == part.parent)
+            .find(|&i| !is_cold_part(&symbols[i].name));
+        if let Some(symbol) = body {
+            ranges.push((part.start..part.end, RangeOwner { symbol, split: true }));
+        }
+    }
+    IntervalTree::new(ranges)
+}
+
 /// シンボル解決
 pub struct SymbolResolver {
     /// シンボル名 -> シンボル情報のマップ
     symbols_by_name: HashMap<String, Symbol>,
     /// アドレス -> シンボル情報のマップ（ソート済み）
     symbols_by_address: Vec<Symbol>,
+    /// 分割された関数の本体以外の範囲（開始アドレス順）
+    split_parts: Vec<SplitPart>,
+    /// シンボルと分割された関数の範囲の区間木（重なっていてもよい）
+    ranges: IntervalTree<RangeOwner>,
     /// PIE（Position Independent Executable）かどうか
     is_pie: bool,
 }
@@ -58,11 +189,20 @@ pub struct SymbolResolver {
 impl SymbolResolver {
     /// DWARFローダーからシンボル解決を作成する
     pub fn new(loader: &DwarfLoader) -> Result<Self> {
+        Self::new_with_progress(loader, None)
+    }
+
+    /// シンボル解決を作成し、読み込んだシンボル数で進捗を通知する
+    pub fn new_with_progress(loader: &DwarfLoader, progress: Option<&ProgressCallback>) -> Result<Self> {
         let mut symbols_by_name = HashMap::new();
         let mut symbols_by_address = Vec::new();
 
+        let total = loader.object_file().symbols().count() as u64;
+        let mut progress = Progress::start(progress, "Reading symbols", Some(total));
+
         // objectファイルからシンボルテーブルを読み取る
         for symbol in loader.object_file().symbols() {
+            progress.advance(1);
             if let Ok(name) = symbol.name() {
                 if !name.is_empty() {
                     let address = symbol.address();
@@ -79,14 +219,53 @@ impl SymbolResolver {
         // アドレスでソート
         symbols_by_address.sort_by_key(|s| s.address);
 
+        // サイズ 0 の関数シンボルに実効的な範囲を与え、コールドな部分を関数本体に結び付ける
+        // （どちらもなければ DWARF の関数を走査しない）
+        let mut parts = Vec::new();
+        if symbols_by_address.iter().any(|s| s.size == 0 || is_cold_part(&s.name)) {
+            let text_sections: Vec<(u64, u64)> = loader.object_file().sections()
+                .filter(|s| s.kind() == SectionKind::Text)
+                .map(|s| (s.address(), s.address() + s.size()))
+                .collect();
+            let functions = dwarf_function_ranges(loader).unwrap_or_default();
+            let dwarf_ends: HashMap<u64, u64> = functions.iter().flatten().copied().collect();
+            fill_missing_sizes(&mut symbols_by_address, &text_sections, &dwarf_ends);
+            parts = split_parts(&functions, &symbols_by_address);
+            for sym in &symbols_by_address {
+                if let Some(named) = symbols_by_name.get_mut(&sym.name) {
+                    if named.address == sym.address {
+                        named.size = sym.size;
+                    }
+                }
+            }
+        }
+
         // PIE判定
         let is_pie = loader.is_pie();
+        progress.finish();
 
-        Ok(Self {
-            symbols_by_name,
-            symbols_by_address,
-            is_pie,
-        })
+        Ok(Self::build(symbols_by_name, symbols_by_address, parts, is_pie))
+    }
+
+    /// シンボル一覧から直接作成する（ターゲットのメモリから読んだシンボルなど）
+    pub fn from_symbols(symbols: Vec<Symbol>, is_pie: bool) -> Self {
+        let mut symbols_by_address = symbols;
+        symbols_by_address.sort_by_key(|s| s.address);
+        let symbols_by_name = symbols_by_address
+            .iter()
+            .map(|s| (s.name.clone(), s.clone()))
+            .collect();
+        Self::build(symbols_by_name, symbols_by_address, Vec::new(), is_pie)
+    }
+
+    fn build(
+        symbols_by_name: HashMap<String, Symbol>,
+        symbols_by_address: Vec<Symbol>,
+        split_parts: Vec<SplitPart>,
+        is_pie: bool,
+    ) -> Self {
+        let ranges = build_ranges(&symbols_by_address, &split_parts);
+        Self { symbols_by_name, symbols_by_address, split_parts, ranges, is_pie }
     }
 
     /// PIE（Position Independent Executable）かどうかを取得する
@@ -99,28 +278,37 @@ impl SymbolResolver {
         self.symbols_by_name.get(symbol).map(|s| s.address)
     }
 
-    /// アドレスからシンボル名を解決する（最も近いシンボルを返す）
+    /// アドレスからシンボル名を解決する
+    ///
+    /// アドレスを含む範囲のうち最も狭いもの（別名や ifunc で範囲が重なっていれば内側）のシンボルを
+    /// 返します。分割された関数のコールドな部分のアドレスは、関数本体のシンボルに解決します。
+    /// どの範囲にも入らなければ、直前のシンボルがサイズの分からないものである場合に限りそれを返します
+    /// （シンボルの間の隙間は、手前のシンボルのものとはみなさない）。
     pub fn reverse_resolve(&self, addr: u64) -> Option<Symbol> {
-        // バイナリサーチで最も近いシンボルを見つける
-        match self.symbols_by_address.binary_search_by_key(&addr, |s| s.address) {
-            Ok(idx) => Some(self.symbols_by_address[idx].clone()),
-            Err(idx) => {
-                if idx > 0 {
-                    let sym = &self.symbols_by_address[idx - 1];
-                    // シンボルのサイズ範囲内かチェック
-                    if sym.size > 0 && addr < sym.address + sym.size {
-                        Some(sym.clone())
-                    } else if addr >= sym.address {
-                        // サイズ情報がない場合は単純に最も近いシンボルを返す
-                        Some(sym.clone())
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            }
+        let innermost = self.ranges.containing(addr).into_iter()
+            .min_by_key(|(range, owner)| (range.end - range.start, !owner.split, owner.symbol));
+        if let Some((_, owner)) = innermost {
+            return Some(self.symbols_by_address[owner.symbol].clone());
         }
+
+        let idx = self.symbols_by_address.partition_point(|s| s.address <= addr);
+        let sym = self.symbols_by_address[..idx].last()?;
+        (sym.size == 0).then(|| sym.clone())
+    }
+
+    /// 関数のコードがあるアドレス範囲を、本体から順に返す
+    ///
+    /// 本体はシンボルの大きさから、コールドな部分は DWARF の DW_AT_ranges から求めます。
+    /// サイズが分からないシンボルの本体は含めません。
+    pub fn function_ranges(&self, symbol: &Symbol) -> Vec<(u64, u64)> {
+        let mut ranges = Vec::new();
+        if symbol.size > 0 {
+            ranges.push((symbol.address, symbol.address + symbol.size));
+        }
+        ranges.extend(self.split_parts.iter()
+            .filter(|p| p.parent == symbol.address)
+            .map(|p| (p.start, p.end)));
+        ranges
     }
 
     /// すべてのシンボルを取得する
@@ -138,3 +326,70 @@ impl SymbolResolver {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_missing_sizes() {
+        let mut symbols = vec![
+            Symbol::new("alias".to_string(), 0x1000, 0),
+            Symbol::new("sized".to_string(), 0x1000, 0x20),
+            Symbol::new("from_dwarf".to_string(), 0x1040, 0),
+            Symbol::new("until_next".to_string(), 0x1080, 0),
+            Symbol::new("next".to_string(), 0x10c0, 0x10),
+            Symbol::new("last".to_string(), 0x1100, 0),
+            Symbol::new("data".to_string(), 0x3000, 0),
+        ];
+        let text_sections = [(0x1000, 0x1180)];
+        let dwarf_ends = HashMap::from([(0x1040, 0x1070)]);
+        fill_missing_sizes(&mut symbols, &text_sections, &dwarf_ends);
+
+        let sizes: Vec<u64> = symbols.iter().map(|s| s.size).collect();
+        assert_eq!(sizes, vec![0x20, 0x20, 0x30, 0x40, 0x10, 0x80, 0]);
+    }
+
+    #[test]
+    fn test_split_function_parts() {
+        let symbols = vec![
+            Symbol::new("_ZN4demo4work17h0123456789abcdefE".to_string(), 0x1000, 0x40),
+            Symbol::new("_ZN4demo4work17h0123456789abcdefE.cold".to_string(), 0x8000, 0x20),
+        ];
+        // DW_AT_ranges にコールドな部分が先に並んでいても、シンボルのある方を本体とする
+        let functions = vec![vec![(0x8000, 0x8020), (0x1000, 0x1040)], vec![(0x2000, 0x2010)]];
+        let parts = split_parts(&functions, &symbols);
+        assert_eq!(parts, vec![SplitPart { start: 0x8000, end: 0x8020, parent: 0x1000 }]);
+
+        let resolver = SymbolResolver::build(HashMap::new(), symbols, parts, false);
+        let work = resolver.reverse_resolve(0x8010).unwrap();
+        assert_eq!(work.address, 0x1000);
+        assert_eq!(resolver.function_ranges(&work), vec![(0x1000, 0x1040), (0x8000, 0x8020)]);
+        assert!(is_cold_part("_ZN4demo4work17h0123456789abcdefE.cold.1"));
+        assert!(!is_cold_part("demo::coldness"));
+    }
+
+    #[test]
+    fn test_reverse_resolve_overlapping_and_gaps() {
+        let resolver = SymbolResolver::from_symbols(vec![
+            Symbol::new("outer".to_string(), 0x1000, 0x100),
+            // ifunc の実装のように、大きな関数の中に別のシンボルがある
+            Symbol::new("inner".to_string(), 0x1040, 0x20),
+            Symbol::new("inner_alias".to_string(), 0x1040, 0x40),
+            Symbol::new("after_gap".to_string(), 0x1200, 0x10),
+            Symbol::new("unsized".to_string(), 0x2000, 0),
+        ], false);
+        let name = |addr| resolver.reverse_resolve(addr).map(|s| s.name);
+
+        assert_eq!(name(0x1010).as_deref(), Some("outer"));
+        assert_eq!(name(0x1040).as_deref(), Some("inner"));
+        assert_eq!(name(0x1070).as_deref(), Some("inner_alias"));
+        // 内側のシンボルの後ろは外側の関数
+        assert_eq!(name(0x1090).as_deref(), Some("outer"));
+        // シンボルの間の隙間はどれでもない
+        assert_eq!(name(0x1100), None);
+        assert_eq!(name(0x0fff), None);
+        // サイズの分からないシンボルは直前なら使う
+        assert_eq!(name(0x2010).as_deref(), Some("unsized"));
+    }
+}
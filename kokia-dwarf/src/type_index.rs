@@ -0,0 +1,732 @@
+//! 型名による DWARF 型の検索とレイアウト解析
+//!
+//! 全コンパイルユニットの名前付き型 DIE を名前空間付きの完全修飾名で索引し、
+//! `ptype` のようにフィールドのオフセット・サイズ・入れ子の型を含むレイアウトを組み立てます。
+//! async fn の状態機械（`{async_fn_env#0}`）も通常の variant 付き構造体として扱います。
+
+use crate::utils::{array_length, entry_name};
+use crate::{DwarfLoader, Progress, ProgressCallback, Result, TypeInfo, TypeInfoExtractor};
+use std::collections::HashMap;
+use std::fmt;
+
+type R = gimli::EndianSlice<'static, gimli::RunTimeEndian>;
+
+/// 入れ子の型を展開するデフォルトの深さ
+pub const DEFAULT_EXPAND_DEPTH: usize = 2;
+
+/// 索引内の型 DIE の位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeRef {
+    /// 型を含むコンパイルユニットのオフセット
+    pub unit: gimli::DebugInfoOffset<usize>,
+    /// ユニット内の DIE オフセット
+    pub die: gimli::UnitOffset<usize>,
+}
+
+/// 索引された型
+#[derive(Debug, Clone)]
+pub struct IndexedType {
+    /// 名前空間付きの完全修飾名（例: `simple_async::compute::{async_fn_env#0}`）
+    pub qualified_name: String,
+    pub type_ref: TypeRef,
+}
+
+/// 型の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeKind {
+    Struct,
+    Union,
+    Enum,
+    Base,
+    Pointer,
+    Array,
+    Typedef,
+    Other,
+}
+
+impl TypeKind {
+    fn from_tag(tag: gimli::DwTag) -> Self {
+        match tag {
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type => TypeKind::Struct,
+            gimli::DW_TAG_union_type => TypeKind::Union,
+            gimli::DW_TAG_enumeration_type => TypeKind::Enum,
+            gimli::DW_TAG_base_type => TypeKind::Base,
+            gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type => TypeKind::Pointer,
+            gimli::DW_TAG_array_type => TypeKind::Array,
+            gimli::DW_TAG_typedef => TypeKind::Typedef,
+            _ => TypeKind::Other,
+        }
+    }
+
+    fn keyword(self) -> &'static str {
+        match self {
+            TypeKind::Struct => "struct",
+            TypeKind::Union => "union",
+            TypeKind::Enum => "enum",
+            TypeKind::Base => "base",
+            TypeKind::Pointer => "pointer",
+            TypeKind::Array => "array",
+            TypeKind::Typedef => "typedef",
+            TypeKind::Other => "type",
+        }
+    }
+}
+
+/// 型のレイアウト
+#[derive(Debug, Clone)]
+pub struct TypeLayout {
+    pub name: String,
+    pub kind: TypeKind,
+    pub size: Option<u64>,
+    /// フィールド（構造体・共用体）
+    pub members: Vec<MemberLayout>,
+    /// variant を区別する discriminant フィールド（Rust の enum・状態機械）
+    pub discriminant: Option<MemberLayout>,
+    /// variant 一覧（DW_TAG_variant_part）
+    pub variants: Vec<VariantLayout>,
+    /// 列挙子（C 形式の enum）
+    pub enumerators: Vec<(String, i64)>,
+}
+
+/// フィールドのレイアウト
+#[derive(Debug, Clone)]
+pub struct MemberLayout {
+    pub name: String,
+    pub offset: Option<u64>,
+    pub size: Option<u64>,
+    pub type_name: String,
+    /// 入れ子の構造体などを展開したレイアウト（展開深さの範囲内のみ）
+    pub layout: Option<Box<TypeLayout>>,
+}
+
+/// variant のレイアウト
+#[derive(Debug, Clone)]
+pub struct VariantLayout {
+    /// discriminant 値（DW_AT_discr_value がない場合はデフォルト variant）
+    pub discriminant: Option<u64>,
+    pub name: String,
+    /// variant 内のフィールド
+    pub members: Vec<MemberLayout>,
+    /// 宣言位置（状態機械の場合は suspend point の行）
+    pub decl_file: Option<String>,
+    pub decl_line: Option<u64>,
+}
+
+/// 名前付き型の索引
+pub struct TypeIndex {
+    types: Vec<IndexedType>,
+    by_qualified: HashMap<String, Vec<usize>>,
+    by_short: HashMap<String, Vec<usize>>,
+}
+
+impl TypeIndex {
+    /// 全コンパイルユニットを走査して索引を構築する
+    pub fn build(loader: &DwarfLoader) -> Result<Self> {
+        Self::build_with_progress(loader, None)
+    }
+
+    /// 索引を構築し、コンパイルユニット単位で進捗を通知する
+    pub fn build_with_progress(loader: &DwarfLoader, progress: Option<&ProgressCallback>) -> Result<Self> {
+        let dwarf = loader.dwarf();
+        let mut progress = match progress {
+            Some(_) => {
+                let mut count = 0u64;
+                let mut headers = dwarf.units();
+                while headers.next()?.is_some() {
+                    count += 1;
+                }
+                Progress::start(progress, "Indexing types", Some(count))
+            }
+            None => Progress::none(),
+        };
+        let mut index = Self {
+            types: Vec::new(),
+            by_qualified: HashMap::new(),
+            by_short: HashMap::new(),
+        };
+
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            progress.advance(1);
+            let Some(unit_offset) = header.offset().as_debug_info_offset() else {
+                continue;
+            };
+            let unit = dwarf.unit(header)?;
+
+            // (深さ, 名前) のスタックで名前空間・外側の型のパスを追跡する
+            let mut scope: Vec<(isize, String)> = Vec::new();
+            let mut depth = 0isize;
+            let mut entries = unit.entries();
+            while let Some((delta, entry)) = entries.next_dfs()? {
+                depth += delta;
+                while scope.last().is_some_and(|(d, _)| *d >= depth) {
+                    scope.pop();
+                }
+
+                let tag = entry.tag();
+                let is_type = matches!(
+                    tag,
+                    gimli::DW_TAG_structure_type
+                        | gimli::DW_TAG_class_type
+                        | gimli::DW_TAG_union_type
+                        | gimli::DW_TAG_enumeration_type
+                        | gimli::DW_TAG_base_type
+                        | gimli::DW_TAG_typedef
+                        | gimli::DW_TAG_pointer_type
+                );
+                if tag != gimli::DW_TAG_namespace && !is_type {
+                    continue;
+                }
+                let Some(name) = entry_name(dwarf, &unit, entry) else {
+                    continue;
+                };
+
+                if is_type && !is_declaration(entry) {
+                    let qualified_name = scope
+                        .iter()
+                        .map(|(_, s)| s.as_str())
+                        .chain(std::iter::once(name.as_str()))
+                        .collect::<Vec<_>>()
+                        .join("::");
+                    index.insert(
+                        qualified_name,
+                        name.clone(),
+                        TypeRef { unit: unit_offset, die: entry.offset() },
+                    );
+                }
+                if entry.has_children() {
+                    scope.push((depth, name));
+                }
+            }
+        }
+
+        Ok(index)
+    }
+
+    fn insert(&mut self, qualified_name: String, short_name: String, type_ref: TypeRef) {
+        let idx = self.types.len();
+        self.by_qualified.entry(qualified_name.clone()).or_default().push(idx);
+        self.by_short.entry(short_name).or_default().push(idx);
+        self.types.push(IndexedType { qualified_name, type_ref });
+    }
+
+    /// 索引された型の数
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    /// 索引が空か
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    /// 名前で型を検索する
+    ///
+    /// 完全修飾名で一致するものを優先し、なければ修飾なしの名前で探します。
+    /// どちらもなければ async fn のパスとみなし、その状態機械の型を探します。
+    /// 同じ型が複数のユニットに含まれることがあるため、候補はすべて返します。
+    pub fn find(&self, name: &str) -> Vec<&IndexedType> {
+        let name = name.trim();
+        let indices = self
+            .by_qualified
+            .get(name)
+            .or_else(|| self.by_short.get(name))
+            .or_else(|| self.by_qualified.get(&format!("{}::{{async_fn_env#0}}", name)));
+        indices
+            .map(|v| v.iter().map(|&i| &self.types[i]).collect())
+            .unwrap_or_default()
+    }
+
+    /// 名前で型を検索し、最初の候補を返す
+    pub fn lookup(&self, name: &str) -> Option<&IndexedType> {
+        self.find(name).into_iter().next()
+    }
+
+    /// 型のレイアウトを組み立てる
+    ///
+    /// # Arguments
+    /// * `loader` - 索引を構築した DWARF ローダー
+    /// * `type_ref` - 対象の型
+    /// * `expand_depth` - 入れ子の構造体・共用体・enum を展開する深さ
+    pub fn layout(&self, loader: &DwarfLoader, type_ref: TypeRef, expand_depth: usize) -> Result<TypeLayout> {
+        let dwarf = loader.dwarf();
+        let header = dwarf.debug_info.header_from_offset(type_ref.unit)?;
+        let unit = dwarf.unit(header)?;
+        LayoutBuilder { dwarf, unit: &unit }.layout(type_ref.die, expand_depth)
+    }
+
+    /// 型の TypeInfo を抽出する（式のキャストで値を読むときに使う）
+    pub fn type_info(&self, loader: &DwarfLoader, type_ref: TypeRef) -> Result<TypeInfo> {
+        let dwarf = loader.dwarf();
+        let header = dwarf.debug_info.header_from_offset(type_ref.unit)?;
+        let unit = dwarf.unit(header)?;
+        TypeInfoExtractor::new(dwarf).extract_type_info(&unit, type_ref.die)
+    }
+}
+
+/// pc を含む関数の戻り値の型（`()` を返す関数なら None）
+///
+/// `pc` は DWARF 上のアドレスです。具象 DIE に型がなければ DW_AT_specification や
+/// DW_AT_abstract_origin の先から取ります。
+pub fn function_return_type(loader: &DwarfLoader, pc: u64) -> Result<Option<TypeRef>> {
+    let Some((unit, function)) = function_unit_at_pc(loader, pc)? else {
+        return Ok(None);
+    };
+
+    let mut offset = function.die;
+    for _ in 0..4 {
+        let entry = unit.entry(offset)?;
+        if let Some(die) = type_ref(&entry) {
+            return Ok(Some(TypeRef { unit: function.unit, die }));
+        }
+        let origin = [gimli::DW_AT_specification, gimli::DW_AT_abstract_origin]
+            .into_iter()
+            .find_map(|attr| match entry.attr_value(attr).ok()? {
+                Some(gimli::AttributeValue::UnitRef(origin)) => Some(origin),
+                _ => None,
+            });
+        match origin {
+            Some(origin) => offset = origin,
+            None => break,
+        }
+    }
+    Ok(None)
+}
+
+/// pc を含む関数 DIE とそのユニット
+fn function_unit_at_pc(loader: &DwarfLoader, pc: u64) -> Result<Option<(gimli::Unit<R>, crate::FunctionDie)>> {
+    let Some(function) = crate::FunctionFinder::find_in_loader(loader, pc, &crate::CancelToken::new())? else {
+        return Ok(None);
+    };
+    let dwarf = loader.dwarf();
+    let unit = dwarf.unit(dwarf.debug_info.header_from_offset(function.unit)?)?;
+    Ok(Some((unit, function)))
+}
+
+/// pc を含む関数の仮引数（DW_TAG_formal_parameter）の名前と型を宣言順に返す
+///
+/// 名前や型のない仮引数は DW_AT_abstract_origin の先から取ります。どちらも分からないものは飛ばします。
+pub fn function_parameters(loader: &DwarfLoader, pc: u64) -> Result<Vec<(String, TypeRef)>> {
+    let Some((unit, function)) = function_unit_at_pc(loader, pc)? else {
+        return Ok(Vec::new());
+    };
+
+    let dwarf = loader.dwarf();
+    let mut parameters = Vec::new();
+    let mut tree = unit.entries_tree(Some(function.die))?;
+    let mut children = tree.root()?.children();
+    while let Some(child) = children.next()? {
+        let entry = child.entry();
+        if entry.tag() != gimli::DW_TAG_formal_parameter {
+            continue;
+        }
+        let origin = match entry.attr_value(gimli::DW_AT_abstract_origin)? {
+            Some(gimli::AttributeValue::UnitRef(origin)) => Some(unit.entry(origin)?),
+            _ => None,
+        };
+        let name = entry_name(dwarf, &unit, entry)
+            .or_else(|| origin.as_ref().and_then(|origin| entry_name(dwarf, &unit, origin)));
+        let die = type_ref(entry).or_else(|| origin.as_ref().and_then(type_ref));
+        if let (Some(name), Some(die)) = (name, die) {
+            parameters.push((name, TypeRef { unit: function.unit, die }));
+        }
+    }
+    Ok(parameters)
+}
+
+/// 1 つのユニット内でレイアウトを組み立てる
+struct LayoutBuilder<'a> {
+    dwarf: &'a gimli::Dwarf<R>,
+    unit: &'a gimli::Unit<R>,
+}
+
+impl LayoutBuilder<'_> {
+    fn layout(&self, offset: gimli::UnitOffset<usize>, expand_depth: usize) -> Result<TypeLayout> {
+        // typedef や const/volatile は実体の型までたどる
+        let offset = self.strip_aliases(offset)?;
+        let mut tree = self.unit.entries_tree(Some(offset))?;
+        let root = tree.root()?;
+        let entry = root.entry();
+
+        let mut layout = TypeLayout {
+            name: self.type_name(offset)?,
+            kind: TypeKind::from_tag(entry.tag()),
+            size: self.type_size(entry)?,
+            members: Vec::new(),
+            discriminant: None,
+            variants: Vec::new(),
+            enumerators: Vec::new(),
+        };
+
+        let mut children = root.children();
+        while let Some(child) = children.next()? {
+            let child_entry = child.entry();
+            match child_entry.tag() {
+                gimli::DW_TAG_member => layout.members.push(self.member(child_entry, expand_depth)?),
+                gimli::DW_TAG_enumerator => {
+                    let name = entry_name(self.dwarf, self.unit, child_entry).unwrap_or_default();
+                    let value = match child_entry.attr_value(gimli::DW_AT_const_value)? {
+                        Some(gimli::AttributeValue::Sdata(v)) => v,
+                        Some(v) => v.udata_value().unwrap_or(0) as i64,
+                        None => 0,
+                    };
+                    layout.enumerators.push((name, value));
+                }
+                gimli::DW_TAG_variant_part => self.variant_part(child, &mut layout, expand_depth)?,
+                _ => {}
+            }
+        }
+
+        Ok(layout)
+    }
+
+    /// DW_TAG_variant_part から discriminant と variant 一覧を取り出す
+    fn variant_part(
+        &self,
+        node: gimli::EntriesTreeNode<R>,
+        layout: &mut TypeLayout,
+        expand_depth: usize,
+    ) -> Result<()> {
+        let discr_offset = match node.entry().attr_value(gimli::DW_AT_discr)? {
+            Some(gimli::AttributeValue::UnitRef(offset)) => Some(offset),
+            _ => None,
+        };
+
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            match entry.tag() {
+                gimli::DW_TAG_member if Some(entry.offset()) == discr_offset => {
+                    layout.discriminant = Some(self.member(entry, 0)?);
+                }
+                gimli::DW_TAG_variant => {
+                    let discriminant = attr_udata(entry, gimli::DW_AT_discr_value);
+                    let mut members = child.children();
+                    // variant は「variant 名の型を持つ 1 つのメンバー」として表現される
+                    if let Some(member_node) = members.next()? {
+                        let member_entry = member_node.entry();
+                        let decl_file = self.decl_file(member_entry);
+                        let decl_line = attr_udata(member_entry, gimli::DW_AT_decl_line);
+                        let member = self.member(member_entry, expand_depth + 1)?;
+                        let name = variant_name(&member);
+                        let members = member.layout.map(|l| l.members).unwrap_or_default();
+                        layout.variants.push(VariantLayout { discriminant, name, members, decl_file, decl_line });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn member(&self, entry: &gimli::DebuggingInformationEntry<R>, expand_depth: usize) -> Result<MemberLayout> {
+        let name = entry_name(self.dwarf, self.unit, entry).unwrap_or_else(|| "<unnamed>".to_string());
+        let offset = attr_udata(entry, gimli::DW_AT_data_member_location);
+
+        let Some(type_offset) = type_ref(entry) else {
+            return Ok(MemberLayout { name, offset, size: None, type_name: "()".to_string(), layout: None });
+        };
+
+        let type_name = self.type_name(type_offset)?;
+        let resolved = self.strip_aliases(type_offset)?;
+        let mut entries = self.unit.entries_at_offset(resolved)?;
+        let (size, expandable) = match entries.next_dfs()? {
+            Some((_, type_entry)) => (
+                self.type_size(type_entry)?,
+                matches!(
+                    TypeKind::from_tag(type_entry.tag()),
+                    TypeKind::Struct | TypeKind::Union | TypeKind::Enum
+                ) && type_entry.has_children(),
+            ),
+            None => (None, false),
+        };
+
+        let layout = if expandable && expand_depth > 0 {
+            Some(Box::new(self.layout(resolved, expand_depth - 1)?))
+        } else {
+            None
+        };
+
+        Ok(MemberLayout { name, offset, size, type_name, layout })
+    }
+
+    /// 型の大きさ（配列は DW_AT_byte_size を持たないことが多いので、要素の大きさ × 要素数で求める）
+    fn type_size(&self, entry: &gimli::DebuggingInformationEntry<R>) -> Result<Option<u64>> {
+        if let Some(size) = attr_udata(entry, gimli::DW_AT_byte_size) {
+            return Ok(Some(size));
+        }
+        let Some(element) = type_ref(entry).filter(|_| entry.tag() == gimli::DW_TAG_array_type) else {
+            return Ok(None);
+        };
+        let mut entries = self.unit.entries_at_offset(self.strip_aliases(element)?)?;
+        let element_size = match entries.next_dfs()? {
+            Some((_, element_entry)) => self.type_size(element_entry)?,
+            None => None,
+        };
+        let length = array_length(self.unit, entry)?;
+        Ok(element_size.zip(length).map(|(size, length)| size * length))
+    }
+
+    /// DW_AT_decl_file を行番号プログラムのファイルテーブルからパスに解決する
+    fn decl_file(&self, entry: &gimli::DebuggingInformationEntry<R>) -> Option<String> {
+        let index = match entry.attr_value(gimli::DW_AT_decl_file).ok()?? {
+            gimli::AttributeValue::FileIndex(index) => index,
+            value => value.udata_value()?,
+        };
+        let header = self.unit.line_program.as_ref()?.header();
+        let file = header.file(index)?;
+
+        let mut path = std::path::PathBuf::new();
+        if let Some(dir) = file.directory(header) {
+            if let Ok(dir) = self.dwarf.attr_string(self.unit, dir) {
+                path.push(dir.to_string_lossy().as_ref());
+            }
+        }
+        let name = self.dwarf.attr_string(self.unit, file.path_name()).ok()?;
+        path.push(name.to_string_lossy().as_ref());
+        Some(path.to_string_lossy().into_owned())
+    }
+
+    /// typedef・const・volatile を外した型のオフセット
+    fn strip_aliases(&self, mut offset: gimli::UnitOffset<usize>) -> Result<gimli::UnitOffset<usize>> {
+        // 循環した DWARF で無限ループしないよう上限を設ける
+        for _ in 0..16 {
+            let mut entries = self.unit.entries_at_offset(offset)?;
+            let Some((_, entry)) = entries.next_dfs()? else {
+                break;
+            };
+            if !matches!(
+                entry.tag(),
+                gimli::DW_TAG_typedef | gimli::DW_TAG_const_type | gimli::DW_TAG_volatile_type
+            ) {
+                break;
+            }
+            match type_ref(entry) {
+                Some(next) => offset = next,
+                None => break,
+            }
+        }
+        Ok(offset)
+    }
+
+    /// 型の表示名を組み立てる（名前のない C のポインタ・配列などにも対応）
+    fn type_name(&self, offset: gimli::UnitOffset<usize>) -> Result<String> {
+        self.type_name_bounded(offset, 8)
+    }
+
+    fn type_name_bounded(&self, offset: gimli::UnitOffset<usize>, budget: usize) -> Result<String> {
+        let mut entries = self.unit.entries_at_offset(offset)?;
+        let Some((_, entry)) = entries.next_dfs()? else {
+            return Ok("<unknown>".to_string());
+        };
+        if let Some(name) = entry_name(self.dwarf, self.unit, entry) {
+            return Ok(name);
+        }
+        if budget == 0 {
+            return Ok("...".to_string());
+        }
+
+        let inner = match type_ref(entry) {
+            Some(inner) => self.type_name_bounded(inner, budget - 1)?,
+            None => "void".to_string(),
+        };
+        Ok(match entry.tag() {
+            gimli::DW_TAG_pointer_type => format!("{} *", inner),
+            gimli::DW_TAG_reference_type => format!("{} &", inner),
+            gimli::DW_TAG_const_type => format!("const {}", inner),
+            gimli::DW_TAG_volatile_type => format!("volatile {}", inner),
+            gimli::DW_TAG_array_type => match array_length(self.unit, entry)? {
+                Some(len) => format!("[{}; {}]", inner, len),
+                None => format!("[{}]", inner),
+            },
+            gimli::DW_TAG_subroutine_type => "fn(..)".to_string(),
+            _ => "<anonymous>".to_string(),
+        })
+    }
+}
+
+/// variant 名を決める
+///
+/// rustc は状態機械の variant メンバーを `0`, `1`, ... と名付けるため、
+/// その場合は型名の最後の要素（`Unresumed`, `Suspend0` など）を使う。
+fn variant_name(member: &MemberLayout) -> String {
+    if member.name.chars().all(|c| c.is_ascii_digit()) {
+        member
+            .type_name
+            .rsplit("::")
+            .next()
+            .unwrap_or(&member.type_name)
+            .to_string()
+    } else {
+        member.name.clone()
+    }
+}
+
+fn attr_udata(entry: &gimli::DebuggingInformationEntry<R>, name: gimli::DwAt) -> Option<u64> {
+    entry.attr_value(name).ok()??.udata_value()
+}
+
+fn type_ref(entry: &gimli::DebuggingInformationEntry<R>) -> Option<gimli::UnitOffset<usize>> {
+    match entry.attr_value(gimli::DW_AT_type).ok()?? {
+        gimli::AttributeValue::UnitRef(offset) => Some(offset),
+        _ => None,
+    }
+}
+
+fn is_declaration(entry: &gimli::DebuggingInformationEntry<R>) -> bool {
+    matches!(
+        entry.attr_value(gimli::DW_AT_declaration),
+        Ok(Some(gimli::AttributeValue::Flag(true)))
+    )
+}
+
+impl fmt::Display for TypeLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+impl TypeLayout {
+    /// 値のバイト列から、discriminant が指している variant を選ぶ
+    ///
+    /// niche で表現された enum では、どの discriminant 値にも当たらないときに
+    /// DW_AT_discr_value のないデフォルト variant になります。
+    pub fn active_variant(&self, bytes: &[u8]) -> Option<&VariantLayout> {
+        let Some(discr) = &self.discriminant else {
+            // variant が1つだけなら discriminant は省略される
+            return match self.variants.as_slice() {
+                [only] => Some(only),
+                _ => None,
+            };
+        };
+        let offset = discr.offset? as usize;
+        let size = discr.size? as usize;
+        let raw = bytes.get(offset..offset + size).filter(|_| size <= 8)?;
+        let mut value = [0u8; 8];
+        value[..size].copy_from_slice(raw);
+        let value = u64::from_le_bytes(value);
+
+        self.variants.iter()
+            .find(|v| v.discriminant == Some(value))
+            .or_else(|| self.variants.iter().find(|v| v.discriminant.is_none()))
+    }
+
+    fn write_indented(&self, f: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        let pad = "    ".repeat(indent);
+        write!(f, "{} {}", self.kind.keyword(), self.name)?;
+        if let Some(size) = self.size {
+            write!(f, " /* size {} */", size)?;
+        }
+        if self.members.is_empty() && self.variants.is_empty() && self.enumerators.is_empty() {
+            return Ok(());
+        }
+        writeln!(f, " {{")?;
+
+        for member in &self.members {
+            member.write_indented(f, indent + 1)?;
+        }
+        for (name, value) in &self.enumerators {
+            writeln!(f, "{}    {} = {},", pad, name, value)?;
+        }
+        if !self.variants.is_empty() {
+            match &self.discriminant {
+                Some(discr) => writeln!(
+                    f,
+                    "{}    /* variants, discriminant {}: {} at offset {} */",
+                    pad,
+                    discr.name,
+                    discr.type_name,
+                    discr.offset.map_or("?".to_string(), |o| format!("0x{:x}", o))
+                )?,
+                None => writeln!(f, "{}    /* variants */", pad)?,
+            }
+            for variant in &self.variants {
+                let discr = variant.discriminant.map_or("_".to_string(), |d| d.to_string());
+                if variant.members.is_empty() {
+                    writeln!(f, "{}    {} => {},", pad, discr, variant.name)?;
+                } else {
+                    writeln!(f, "{}    {} => {} {{", pad, discr, variant.name)?;
+                    for member in &variant.members {
+                        member.write_indented(f, indent + 2)?;
+                    }
+                    writeln!(f, "{}    }},", pad)?;
+                }
+            }
+        }
+        write!(f, "{}}}", pad)
+    }
+}
+
+impl MemberLayout {
+    fn write_indented(&self, f: &mut fmt::Formatter, indent: usize) -> fmt::Result {
+        let pad = "    ".repeat(indent);
+        let offset = self.offset.map_or("?".to_string(), |o| format!("0x{:x}", o));
+        let size = self.size.map_or("?".to_string(), |s| s.to_string());
+        write!(f, "{}/* {:>6} {:>4} */ {}: ", pad, offset, size, self.name)?;
+        match &self.layout {
+            Some(layout) => layout.write_indented(f, indent)?,
+            None => write!(f, "{}", self.type_name)?,
+        }
+        writeln!(f, ",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(name: &str, type_name: &str) -> MemberLayout {
+        MemberLayout { name: name.to_string(), offset: Some(0), size: Some(1), type_name: type_name.to_string(), layout: None }
+    }
+
+    #[test]
+    fn test_variant_name() {
+        assert_eq!(variant_name(&member("0", "simple_async::heavy::{async_fn_env#0}::Unresumed")), "Unresumed");
+        assert_eq!(variant_name(&member("Some", "core::option::Option<i32>::Some")), "Some");
+    }
+
+    #[test]
+    fn test_active_variant() {
+        let variant = |discriminant, name: &str| VariantLayout {
+            discriminant,
+            name: name.to_string(),
+            members: Vec::new(),
+            decl_file: None,
+            decl_line: None,
+        };
+        // Result<u32, String> のように、String の容量の niche に Ok が入る enum
+        let niche = TypeLayout {
+            name: "Result<u32, String>".to_string(),
+            kind: TypeKind::Struct,
+            size: Some(24),
+            members: Vec::new(),
+            discriminant: Some(MemberLayout { offset: Some(0), size: Some(8), ..member("__0", "u64") }),
+            variants: vec![variant(Some(0x8000_0000_0000_0000), "Ok"), variant(None, "Err")],
+            enumerators: Vec::new(),
+        };
+        let mut bytes = vec![0u8; 24];
+        bytes[..8].copy_from_slice(&0x8000_0000_0000_0000u64.to_le_bytes());
+        assert_eq!(niche.active_variant(&bytes).map(|v| v.name.as_str()), Some("Ok"));
+        bytes[..8].copy_from_slice(&5u64.to_le_bytes());
+        assert_eq!(niche.active_variant(&bytes).map(|v| v.name.as_str()), Some("Err"));
+        // discriminant の範囲を読めなければ判定しない
+        assert!(niche.active_variant(&bytes[..4]).is_none());
+    }
+
+    #[test]
+    fn test_display_layout() {
+        let layout = TypeLayout {
+            name: "Point".to_string(),
+            kind: TypeKind::Struct,
+            size: Some(8),
+            members: vec![member("x", "i32")],
+            discriminant: None,
+            variants: Vec::new(),
+            enumerators: Vec::new(),
+        };
+        let text = layout.to_string();
+        assert!(text.starts_with("struct Point /* size 8 */ {"));
+        assert!(text.contains("x: i32,"));
+        assert!(text.ends_with('}'));
+    }
+}
@@ -2,8 +2,17 @@
 //!
 //! DWARF DIEから型情報（構造体フィールド、列挙型variant等）を抽出します。
 
+use crate::generator_layout::DiscriminantLayout;
+use crate::utils::{array_length, entry_name};
 use crate::Result;
 use gimli::Reader;
+use std::cell::Cell;
+
+/// ポインタ・参照の先を抽出する深さの上限
+///
+/// 連結リストのノードのように自分自身を指す型で再帰が止まらなくなるのを防ぎます。
+/// これより深いポインタは指す先の型を持ちません。
+const MAX_POINTEE_DEPTH: usize = 2;
 
 /// 型情報
 #[derive(Debug, Clone)]
@@ -34,10 +43,12 @@ pub enum TypeInfo {
         size: u64,
         fields: Vec<FieldInfo>,
     },
-    /// 列挙型
+    /// 列挙型（C 風の列挙型と、DW_TAG_variant_part を持つ Rust の enum）
     Enum {
         name: String,
         size: u64,
+        /// discriminant の位置（variant が1つだけなら None）
+        discriminant: Option<DiscriminantLayout>,
         variants: Vec<VariantInfo>,
     },
     /// Union型
@@ -50,7 +61,26 @@ pub enum TypeInfo {
     Unknown,
 }
 
+impl TypeInfo {
+    /// 型のサイズ（バイト、配列は要素のサイズ × 長さ、分からなければ 0）
+    pub fn byte_size(&self) -> u64 {
+        match self {
+            TypeInfo::Primitive { size, .. }
+            | TypeInfo::Pointer { size, .. }
+            | TypeInfo::Reference { size, .. }
+            | TypeInfo::Struct { size, .. }
+            | TypeInfo::Enum { size, .. }
+            | TypeInfo::Union { size, .. } => *size,
+            TypeInfo::Array { element_type: Some(element), length: Some(length) } => element.byte_size() * length,
+            TypeInfo::Array { .. } | TypeInfo::Unknown => 0,
+        }
+    }
+}
+
 /// フィールド情報
+///
+/// TypeInfoExtractor と GeneratorLayoutAnalyzer の両方が返します。GeneratorLayoutAnalyzer は
+/// 型名だけを埋め、`type_info` は None のままにします。
 #[derive(Debug, Clone)]
 pub struct FieldInfo {
     /// フィールド名
@@ -59,6 +89,8 @@ pub struct FieldInfo {
     pub offset: u64,
     /// サイズ（バイト）
     pub size: u64,
+    /// 型名（DW_AT_type の指す DIE の名前）
+    pub type_name: Option<String>,
     /// 型情報
     pub type_info: Option<Box<TypeInfo>>,
 }
@@ -68,7 +100,7 @@ pub struct FieldInfo {
 pub struct VariantInfo {
     /// Variant名
     pub name: String,
-    /// Discriminant値
+    /// Discriminant値（None は niche のデフォルト variant）
     pub discriminant: Option<u64>,
     /// フィールド
     pub fields: Vec<FieldInfo>,
@@ -76,14 +108,15 @@ pub struct VariantInfo {
 
 /// 型情報抽出器
 pub struct TypeInfoExtractor<'a, R: Reader> {
-    #[allow(dead_code)]
     dwarf: &'a gimli::Dwarf<R>,
+    /// いまたどっているポインタの深さ
+    pointee_depth: Cell<usize>,
 }
 
 impl<'a, R: Reader<Offset = usize>> TypeInfoExtractor<'a, R> {
     /// 新しい型情報抽出器を作成する
     pub fn new(dwarf: &'a gimli::Dwarf<R>) -> Self {
-        Self { dwarf }
+        Self { dwarf, pointee_depth: Cell::new(0) }
     }
 
     /// 型DIEから型情報を抽出する
@@ -108,7 +141,7 @@ impl<'a, R: Reader<Offset = usize>> TypeInfoExtractor<'a, R> {
         entry: &gimli::DebuggingInformationEntry<R>,
     ) -> Result<TypeInfo> {
         match entry.tag() {
-            gimli::DW_TAG_base_type => self.extract_base_type(entry),
+            gimli::DW_TAG_base_type => self.extract_base_type(unit, entry),
             gimli::DW_TAG_pointer_type => self.extract_pointer_type(unit, entry),
             gimli::DW_TAG_reference_type => self.extract_reference_type(unit, entry),
             gimli::DW_TAG_array_type => self.extract_array_type(unit, entry),
@@ -120,8 +153,12 @@ impl<'a, R: Reader<Offset = usize>> TypeInfoExtractor<'a, R> {
     }
 
     /// 基本型を抽出する
-    fn extract_base_type(&self, entry: &gimli::DebuggingInformationEntry<R>) -> Result<TypeInfo> {
-        let name = self.get_name(entry).unwrap_or_else(|| "<unknown>".to_string());
+    fn extract_base_type(
+        &self,
+        unit: &gimli::Unit<R>,
+        entry: &gimli::DebuggingInformationEntry<R>,
+    ) -> Result<TypeInfo> {
+        let name = self.get_name(unit, entry).unwrap_or_else(|| "<unknown>".to_string());
         let size = self.get_byte_size(entry).unwrap_or(0);
 
         Ok(TypeInfo::Primitive { name, size })
@@ -136,14 +173,7 @@ impl<'a, R: Reader<Offset = usize>> TypeInfoExtractor<'a, R> {
         let size = self.get_byte_size(entry).unwrap_or(8); // 64bit = 8 bytes
 
         // 参照先の型を取得
-        let pointee_type = if let Some(type_offset) = self.get_type(entry) {
-            match self.extract_type_info(unit, type_offset) {
-                Ok(info) => Some(Box::new(info)),
-                Err(_) => None,
-            }
-        } else {
-            None
-        };
+        let pointee_type = self.extract_pointee(unit, entry);
 
         Ok(TypeInfo::Pointer {
             pointee_type,
@@ -159,14 +189,7 @@ impl<'a, R: Reader<Offset = usize>> TypeInfoExtractor<'a, R> {
     ) -> Result<TypeInfo> {
         let size = self.get_byte_size(entry).unwrap_or(8);
 
-        let referent_type = if let Some(type_offset) = self.get_type(entry) {
-            match self.extract_type_info(unit, type_offset) {
-                Ok(info) => Some(Box::new(info)),
-                Err(_) => None,
-            }
-        } else {
-            None
-        };
+        let referent_type = self.extract_pointee(unit, entry);
 
         Ok(TypeInfo::Reference {
             referent_type,
@@ -174,6 +197,23 @@ impl<'a, R: Reader<Offset = usize>> TypeInfoExtractor<'a, R> {
         })
     }
 
+    /// ポインタ・参照の指す先の型を抽出する（深さの上限を超えたら None）
+    fn extract_pointee(
+        &self,
+        unit: &gimli::Unit<R>,
+        entry: &gimli::DebuggingInformationEntry<R>,
+    ) -> Option<Box<TypeInfo>> {
+        let type_offset = self.get_type(entry)?;
+        let depth = self.pointee_depth.get();
+        if depth >= MAX_POINTEE_DEPTH {
+            return None;
+        }
+        self.pointee_depth.set(depth + 1);
+        let info = self.extract_type_info(unit, type_offset).ok();
+        self.pointee_depth.set(depth);
+        info.map(Box::new)
+    }
+
     /// 配列型を抽出する
     fn extract_array_type(
         &self,
@@ -189,9 +229,7 @@ impl<'a, R: Reader<Offset = usize>> TypeInfoExtractor<'a, R> {
             None
         };
 
-        // 配列長を取得（子DIEから）
-        // TODO: DW_TAG_subrange_typeから長さを取得
-        let length = None;
+        let length = array_length(unit, entry)?;
 
         Ok(TypeInfo::Array {
             element_type,
@@ -199,15 +237,25 @@ impl<'a, R: Reader<Offset = usize>> TypeInfoExtractor<'a, R> {
         })
     }
 
-    /// 構造体型を抽出する
+    /// 構造体型を抽出する（DW_TAG_variant_part を持つものは Rust の enum として扱う）
     fn extract_struct_type(
         &self,
         unit: &gimli::Unit<R>,
         entry: &gimli::DebuggingInformationEntry<R>,
     ) -> Result<TypeInfo> {
-        let name = self.get_name(entry).unwrap_or_else(|| "<anonymous>".to_string());
+        let name = self.get_name(unit, entry).unwrap_or_else(|| "<anonymous>".to_string());
         let size = self.get_byte_size(entry).unwrap_or(0);
 
+        let mut tree = unit.entries_tree(Some(entry.offset()))?;
+        let root = tree.root()?;
+        let mut children = root.children();
+        while let Some(child) = children.next()? {
+            if child.entry().tag() == gimli::DW_TAG_variant_part {
+                let (discriminant, variants) = self.extract_variant_part(unit, child)?;
+                return Ok(TypeInfo::Enum { name, size, discriminant, variants });
+            }
+        }
+
         // フィールドを列挙
         let fields = self.extract_fields(unit, entry)?;
 
@@ -220,15 +268,15 @@ impl<'a, R: Reader<Offset = usize>> TypeInfoExtractor<'a, R> {
         unit: &gimli::Unit<R>,
         entry: &gimli::DebuggingInformationEntry<R>,
     ) -> Result<TypeInfo> {
-        let name = self.get_name(entry).unwrap_or_else(|| "<anonymous>".to_string());
+        let name = self.get_name(unit, entry).unwrap_or_else(|| "<anonymous>".to_string());
         let size = self.get_byte_size(entry).unwrap_or(0);
 
-        // Variantを列挙
-        let variants = self.extract_variants(unit, entry)?;
+        let variants = self.extract_enumerators(unit, entry, size)?;
 
         Ok(TypeInfo::Enum {
             name,
             size,
+            discriminant: Some(DiscriminantLayout { offset: 0, size }),
             variants,
         })
     }
@@ -239,7 +287,7 @@ impl<'a, R: Reader<Offset = usize>> TypeInfoExtractor<'a, R> {
         unit: &gimli::Unit<R>,
         entry: &gimli::DebuggingInformationEntry<R>,
     ) -> Result<TypeInfo> {
-        let name = self.get_name(entry).unwrap_or_else(|| "<anonymous>".to_string());
+        let name = self.get_name(unit, entry).unwrap_or_else(|| "<anonymous>".to_string());
         let size = self.get_byte_size(entry).unwrap_or(0);
 
         // メンバを列挙
@@ -282,47 +330,136 @@ impl<'a, R: Reader<Offset = usize>> TypeInfoExtractor<'a, R> {
         unit: &gimli::Unit<R>,
         entry: &gimli::DebuggingInformationEntry<R>,
     ) -> Result<Option<FieldInfo>> {
-        let name = self.get_name(entry).unwrap_or_else(|| "<unnamed>".to_string());
+        let name = self.get_name(unit, entry).unwrap_or_else(|| "<unnamed>".to_string());
         let offset = self.get_data_member_location(entry).unwrap_or(0);
 
         // 型情報を取得
-        let (type_info, size) = if let Some(type_offset) = self.get_type(entry) {
+        let (type_name, type_info, size) = if let Some(type_offset) = self.get_type(entry) {
+            let type_name = unit.entry(type_offset).ok().and_then(|type_entry| self.get_name(unit, &type_entry));
             match self.extract_type_info(unit, type_offset) {
                 Ok(info) => {
-                    let size = self.get_type_size(&info);
-                    (Some(Box::new(info)), size)
+                    let size = info.byte_size();
+                    (type_name, Some(Box::new(info)), size)
                 }
-                Err(_) => (None, 0),
+                Err(_) => (type_name, None, 0),
             }
         } else {
-            (None, 0)
+            (None, None, 0)
         };
 
         Ok(Some(FieldInfo {
             name,
             offset,
             size,
+            type_name,
             type_info,
         }))
     }
 
-    /// Variantを抽出する（簡易実装）
-    fn extract_variants(
+    /// C 風の列挙型の DW_TAG_enumerator を variant として抽出する
+    fn extract_enumerators(
         &self,
-        _unit: &gimli::Unit<R>,
-        _parent_entry: &gimli::DebuggingInformationEntry<R>,
+        unit: &gimli::Unit<R>,
+        parent_entry: &gimli::DebuggingInformationEntry<R>,
+        size: u64,
     ) -> Result<Vec<VariantInfo>> {
-        // TODO: 列挙型のvariantを抽出する完全な実装
-        Ok(Vec::new())
+        // 負の値はメモリ上のバイト列と比べられるように size バイトに切り詰める
+        let mask = if (1..8).contains(&size) { (1u64 << (size * 8)) - 1 } else { u64::MAX };
+        let mut variants = Vec::new();
+        let mut tree = unit.entries_tree(Some(parent_entry.offset()))?;
+        let root = tree.root()?;
+        let mut children = root.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            if entry.tag() != gimli::DW_TAG_enumerator {
+                continue;
+            }
+            let value = match entry.attr_value(gimli::DW_AT_const_value)? {
+                Some(gimli::AttributeValue::Sdata(v)) => Some(v as u64 & mask),
+                Some(v) => v.udata_value(),
+                None => None,
+            };
+            variants.push(VariantInfo {
+                name: self.get_name(unit, entry).unwrap_or_default(),
+                discriminant: value,
+                fields: Vec::new(),
+            });
+        }
+        Ok(variants)
     }
 
-    /// 名前を取得する
-    fn get_name(&self, entry: &gimli::DebuggingInformationEntry<R>) -> Option<String> {
-        let attr = entry.attr_value(gimli::DW_AT_name).ok()??;
-        match attr {
-            gimli::AttributeValue::String(s) => s.to_string_lossy().ok().map(|s| s.into_owned()),
+    /// DW_TAG_variant_part から discriminant の位置と variant 一覧を抽出する
+    ///
+    /// 各 variant は「variant 名の構造体を型に持つ1つのメンバー」として表現されるので、
+    /// その構造体のフィールドを enum の先頭からのオフセットに直して variant のフィールドにする。
+    fn extract_variant_part(
+        &self,
+        unit: &gimli::Unit<R>,
+        node: gimli::EntriesTreeNode<R>,
+    ) -> Result<(Option<DiscriminantLayout>, Vec<VariantInfo>)> {
+        let discr_offset = match node.entry().attr_value(gimli::DW_AT_discr)? {
+            Some(gimli::AttributeValue::UnitRef(offset)) => Some(offset),
             _ => None,
+        };
+
+        let mut discriminant = None;
+        let mut variants = Vec::new();
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            match entry.tag() {
+                gimli::DW_TAG_member if Some(entry.offset()) == discr_offset => {
+                    let size = self.get_type(entry)
+                        .and_then(|type_offset| self.extract_type_info(unit, type_offset).ok())
+                        .map_or(0, |info| info.byte_size());
+                    let offset = self.get_data_member_location(entry).unwrap_or(0);
+                    discriminant = Some(DiscriminantLayout { offset, size });
+                }
+                gimli::DW_TAG_variant => {
+                    let value = attr_udata(entry, gimli::DW_AT_discr_value);
+                    let mut members = child.children();
+                    if let Some(member) = members.next()? {
+                        variants.push(self.extract_variant(unit, member.entry(), value)?);
+                    }
+                }
+                _ => {}
+            }
         }
+        Ok((discriminant, variants))
+    }
+
+    /// variant を表すメンバーから variant 名とフィールドを抽出する
+    fn extract_variant(
+        &self,
+        unit: &gimli::Unit<R>,
+        member: &gimli::DebuggingInformationEntry<R>,
+        discriminant: Option<u64>,
+    ) -> Result<VariantInfo> {
+        let Some(field) = self.extract_field(unit, member)? else {
+            return Ok(VariantInfo { name: "<unnamed>".to_string(), discriminant, fields: Vec::new() });
+        };
+        // 状態機械の variant メンバーは `0`, `1`, ... と名付けられるので、型名（Unresumed など）を使う
+        let name = match &field.type_name {
+            Some(type_name) if field.name.chars().all(|c| c.is_ascii_digit()) => {
+                type_name.rsplit("::").next().unwrap_or(type_name).to_string()
+            }
+            _ => field.name.clone(),
+        };
+        let fields = match field.type_info.map(|info| *info) {
+            Some(TypeInfo::Struct { fields, .. }) => fields
+                .into_iter()
+                .map(|inner| FieldInfo { offset: field.offset + inner.offset, ..inner })
+                .collect(),
+            _ => Vec::new(),
+        };
+        Ok(VariantInfo { name, discriminant, fields })
+    }
+
+    /// 名前を取得する
+    ///
+    /// 名前は DIE に直接書かれているほか、.debug_str などの文字列表にあることが多い。
+    fn get_name(&self, unit: &gimli::Unit<R>, entry: &gimli::DebuggingInformationEntry<R>) -> Option<String> {
+        entry_name(self.dwarf, unit, entry)
     }
 
     /// バイトサイズを取得する
@@ -357,17 +494,8 @@ impl<'a, R: Reader<Offset = usize>> TypeInfoExtractor<'a, R> {
             _ => None,
         }
     }
+}
 
-    /// 型情報からサイズを取得する
-    fn get_type_size(&self, type_info: &TypeInfo) -> u64 {
-        match type_info {
-            TypeInfo::Primitive { size, .. } => *size,
-            TypeInfo::Pointer { size, .. } => *size,
-            TypeInfo::Reference { size, .. } => *size,
-            TypeInfo::Struct { size, .. } => *size,
-            TypeInfo::Enum { size, .. } => *size,
-            TypeInfo::Union { size, .. } => *size,
-            _ => 0,
-        }
-    }
+fn attr_udata<R: Reader>(entry: &gimli::DebuggingInformationEntry<R>, name: gimli::DwAt) -> Option<u64> {
+    entry.attr_value(name).ok()??.udata_value()
 }
@@ -1,76 +1,204 @@
 //! DWARF解析のユーティリティ関数
 
-use crate::Result;
+use crate::{CancelToken, DwarfLoader, Result};
 use gimli::Reader;
 
 /// 関数DIE検索ユーティリティ
 pub struct FunctionFinder;
 
+/// PC を含む関数 DIE の位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FunctionDie {
+    /// 関数を含むコンパイルユニットのオフセット
+    pub unit: gimli::DebugInfoOffset<usize>,
+    /// ユニット内の DIE オフセット
+    pub die: gimli::UnitOffset<usize>,
+}
+
+/// PC を含む関数 DIE の候補の優先度（小さいほど優先）
+///
+/// DW_AT_low_pc から始まる本体の範囲で PC を含む具象 DIE を、DW_AT_ranges のコールドな部分で
+/// 含むものより優先し、同じなら範囲の狭い（内側の）ものを選びます。
+type Preference = (bool, u64);
+
 impl FunctionFinder {
     /// PCを含む関数DIEを検索
     ///
     /// # Arguments
+    /// * `dwarf` - DWARF セクション（DW_AT_ranges の解決に使う）
     /// * `unit` - DWARFコンパイルユニット
     /// * `pc` - プログラムカウンタ
     ///
     /// # Returns
     /// 関数DIEのオフセット、見つからない場合はNone
     pub fn find_at_pc<R: Reader>(
+        dwarf: &gimli::Dwarf<R>,
         unit: &gimli::Unit<R>,
         pc: u64,
     ) -> Result<Option<gimli::UnitOffset<R::Offset>>> {
-        let mut entries = unit.entries();
+        Self::find_at_pc_cancellable(dwarf, unit, pc, &CancelToken::new())
+    }
 
-        while let Some((_, entry)) = entries.next_dfs()? {
-            if entry.tag() == gimli::DW_TAG_subprogram {
-                if let Some(offset) = Self::check_pc_in_function(entry, pc)? {
-                    return Ok(Some(offset));
+    /// PCを含む関数DIEを検索（DIE ごとに `cancel` を確認する）
+    ///
+    /// 複数の DIE が PC を含むときは、DW_AT_low_pc の範囲で含む具象 DIE のうち最も内側のものを返します。
+    /// 抽象 DIE（DW_AT_inline）や宣言は返しません。
+    pub fn find_at_pc_cancellable<R: Reader>(
+        dwarf: &gimli::Dwarf<R>,
+        unit: &gimli::Unit<R>,
+        pc: u64,
+        cancel: &CancelToken,
+    ) -> Result<Option<gimli::UnitOffset<R::Offset>>> {
+        Ok(Self::best_in_unit(dwarf, unit, pc, cancel)?.map(|(offset, _)| offset))
+    }
+
+    /// すべてのユニットから PC を含む関数DIEを検索する
+    ///
+    /// 同じ関数が複数のユニットにあれば [`FunctionFinder::find_at_pc_cancellable`] と同じ優先度で
+    /// 1 つを選びます。結果（見つからなかったことも含む）は `loader` に PC ごとに覚えます。
+    pub fn find_in_loader(loader: &DwarfLoader, pc: u64, cancel: &CancelToken) -> Result<Option<FunctionDie>> {
+        if let Some(found) = loader.function_dies().borrow().get(&pc) {
+            return Ok(*found);
+        }
+
+        let dwarf = loader.dwarf();
+        let mut best: Option<(FunctionDie, Preference)> = None;
+        let mut headers = dwarf.units();
+        while let Some(header) = headers.next()? {
+            cancel.check()?;
+            let Some(unit_offset) = header.offset().as_debug_info_offset() else {
+                continue;
+            };
+            let unit = dwarf.unit(header)?;
+            // ユニットの範囲が分かっていて PC を含まなければ、DIE を走査しない
+            let mut unit_ranges = dwarf.unit_ranges(&unit)?;
+            let mut has_ranges = false;
+            let mut covers = false;
+            while let Some(range) = unit_ranges.next()? {
+                has_ranges = true;
+                covers |= (range.begin..range.end).contains(&pc);
+            }
+            if has_ranges && !covers {
+                continue;
+            }
+            if let Some((die, preference)) = Self::best_in_unit(dwarf, &unit, pc, cancel)? {
+                if best.is_none_or(|(_, known)| preference < known) {
+                    best = Some((FunctionDie { unit: unit_offset, die }, preference));
                 }
             }
         }
-        Ok(None)
+
+        let found = best.map(|(function, _)| function);
+        loader.function_dies().borrow_mut().insert(pc, found);
+        Ok(found)
     }
 
-    /// 関数DIEの範囲チェック
-    fn check_pc_in_function<R: Reader>(
-        entry: &gimli::DebuggingInformationEntry<R>,
+    /// ユニット内で PC を含む関数DIEのうち最も優先するものと、その優先度
+    fn best_in_unit<R: Reader>(
+        dwarf: &gimli::Dwarf<R>,
+        unit: &gimli::Unit<R>,
         pc: u64,
-    ) -> Result<Option<gimli::UnitOffset<R::Offset>>> {
-        let (start_addr, end_addr) = match Self::get_function_range(entry) {
-            Ok(range) => range,
-            Err(_) => return Ok(None),
-        };
-
-        if pc >= start_addr && pc < end_addr {
-            Ok(Some(entry.offset()))
-        } else {
-            Ok(None)
+        cancel: &CancelToken,
+    ) -> Result<Option<(gimli::UnitOffset<R::Offset>, Preference)>> {
+        let mut best = None;
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs()? {
+            cancel.check()?;
+            if entry.tag() != gimli::DW_TAG_subprogram
+                || entry.attr_value(gimli::DW_AT_inline)?.is_some()
+                || entry.attr_value(gimli::DW_AT_declaration)?.is_some()
+            {
+                continue;
+            }
+            let ranges = Self::function_ranges(dwarf, unit, entry).unwrap_or_default();
+            let Some(&(start, end)) = ranges.iter().find(|(start, end)| (*start..*end).contains(&pc)) else {
+                continue;
+            };
+            let low_pc = match entry.attr_value(gimli::DW_AT_low_pc)? {
+                Some(value) => dwarf.attr_address(unit, value)?,
+                None => None,
+            };
+            let preference = (low_pc != Some(start), end - start);
+            if best.as_ref().is_none_or(|(_, known)| preference < *known) {
+                best = Some((entry.offset(), preference));
+            }
         }
+        Ok(best)
     }
 
     /// 関数のアドレス範囲を取得
-    fn get_function_range<R: Reader>(
+    ///
+    /// DW_AT_low_pc/DW_AT_high_pc の1範囲だけでなく、ホットな部分とコールドな部分
+    /// （`.text.unlikely` など）に分割された関数の DW_AT_ranges も返します。
+    /// リンク時に捨てられた関数の範囲（開始アドレス 0）は含めません。
+    pub fn function_ranges<R: Reader>(
+        dwarf: &gimli::Dwarf<R>,
+        unit: &gimli::Unit<R>,
         entry: &gimli::DebuggingInformationEntry<R>,
-    ) -> Result<(u64, u64)> {
-        let low_pc = entry.attr_value(gimli::DW_AT_low_pc)?;
-        let high_pc = entry.attr_value(gimli::DW_AT_high_pc)?;
-
-        let (low_pc_val, high_pc_val) = match (low_pc, high_pc) {
-            (Some(l), Some(h)) => (l, h),
-            _ => return Err(anyhow::anyhow!("Missing address attributes")),
-        };
+    ) -> Result<Vec<(u64, u64)>> {
+        let mut ranges = Vec::new();
+        let mut iter = dwarf.die_ranges(unit, entry)?;
+        while let Some(range) = iter.next()? {
+            if range.begin != 0 && range.end > range.begin {
+                ranges.push((range.begin, range.end));
+            }
+        }
+        Ok(ranges)
+    }
+}
 
-        let start_addr = match low_pc_val {
-            gimli::AttributeValue::Addr(addr) => addr,
-            _ => return Err(anyhow::anyhow!("Invalid low_pc")),
-        };
+/// 文字列属性の値を文字列にする
+///
+/// rustc は名前の大半を .debug_str への参照（DW_FORM_strp）で出力し、DWARF 5 では
+/// .debug_line_str（DW_FORM_line_strp）や .debug_str_offsets（DW_FORM_strx）経由の参照も使うため、
+/// それらをすべて解決する。`dwarf` と `unit` の Reader が違っていてもよい（オフセットだけを使う）。
+pub fn attr_string<R, S>(dwarf: &gimli::Dwarf<S>, unit: &gimli::Unit<R>, value: gimli::AttributeValue<R>) -> Option<String>
+where
+    R: Reader<Offset = usize>,
+    S: Reader<Offset = usize>,
+{
+    let string = match value {
+        gimli::AttributeValue::String(s) => return s.to_string_lossy().ok().map(|s| s.into_owned()),
+        gimli::AttributeValue::DebugStrRef(offset) => dwarf.string(offset).ok()?,
+        gimli::AttributeValue::DebugLineStrRef(offset) => dwarf.line_string(offset).ok()?,
+        gimli::AttributeValue::DebugStrOffsetsIndex(index) => {
+            let offset = dwarf.debug_str_offsets
+                .get_str_offset(unit.encoding().format, unit.str_offsets_base, index)
+                .ok()?;
+            dwarf.string(offset).ok()?
+        }
+        _ => return None,
+    };
+    string.to_string_lossy().ok().map(|s| s.into_owned())
+}
 
-        let end_addr = match high_pc_val {
-            gimli::AttributeValue::Addr(addr) => addr,
-            gimli::AttributeValue::Udata(offset) => start_addr + offset,
-            _ => return Err(anyhow::anyhow!("Invalid high_pc")),
-        };
+/// DIE の名前（DW_AT_name）を取得する
+pub fn entry_name<R, S>(dwarf: &gimli::Dwarf<S>, unit: &gimli::Unit<R>, entry: &gimli::DebuggingInformationEntry<R>) -> Option<String>
+where
+    R: Reader<Offset = usize>,
+    S: Reader<Offset = usize>,
+{
+    attr_string(dwarf, unit, entry.attr_value(gimli::DW_AT_name).ok()??)
+}
 
-        Ok((start_addr, end_addr))
+/// 配列型の要素数（最初の DW_TAG_subrange_type の DW_AT_count か DW_AT_upper_bound から）
+pub fn array_length<R: Reader<Offset = usize>>(
+    unit: &gimli::Unit<R>,
+    entry: &gimli::DebuggingInformationEntry<R>,
+) -> Result<Option<u64>> {
+    let udata = |entry: &gimli::DebuggingInformationEntry<R>, name| entry.attr_value(name).ok().flatten()?.udata_value();
+    let mut tree = unit.entries_tree(Some(entry.offset()))?;
+    let root = tree.root()?;
+    let mut children = root.children();
+    while let Some(child) = children.next()? {
+        let subrange = child.entry();
+        if subrange.tag() == gimli::DW_TAG_subrange_type {
+            if let Some(count) = udata(subrange, gimli::DW_AT_count) {
+                return Ok(Some(count));
+            }
+            let lower = udata(subrange, gimli::DW_AT_lower_bound).unwrap_or(0);
+            return Ok(udata(subrange, gimli::DW_AT_upper_bound).map(|upper| (upper + 1).saturating_sub(lower)));
+        }
     }
+    Ok(None)
 }
@@ -2,7 +2,8 @@
 //!
 //! 型情報に基づいて変数の値を人間が読みやすい形式でフォーマットします。
 
-use crate::type_info::{TypeInfo, FieldInfo as TypeFieldInfo};
+use crate::decode::{DecodeConfig, DisplayValue, ValueDecoder};
+use crate::type_info::{FieldInfo, TypeInfo};
 use crate::Result;
 use std::collections::HashSet;
 
@@ -11,6 +12,7 @@ use std::collections::HashSet;
 /// メモリアドレスと型情報から値を読み取り、フォーマットします。
 pub struct ValueFormatter<'a> {
     memory: &'a dyn MemoryReader,
+    limits: ContainerLimits,
 }
 
 /// 型名から基本型を判定
@@ -130,8 +132,62 @@ pub trait MemoryReader {
     fn read_u32(&self, addr: usize) -> Result<u32>;
     fn read_u64(&self, addr: usize) -> Result<u64>;
     fn read(&self, addr: usize, size: usize) -> Result<Vec<u8>>;
+
+    /// アドレスがマップされているか（判定できない場合は true）
+    fn is_mapped(&self, _addr: usize) -> bool {
+        true
+    }
+
+    /// 先頭から読めるところまで読み取る
+    ///
+    /// デフォルト実装はページ単位で読み取り、最初に失敗した時点で打ち切ります。
+    fn read_prefix(&self, addr: usize, size: usize) -> Vec<u8> {
+        if let Ok(bytes) = self.read(addr, size) {
+            return bytes;
+        }
+
+        let mut bytes = Vec::with_capacity(size);
+        let end = addr + size;
+        let mut pos = addr;
+        while pos < end {
+            let chunk_len = (READ_CHUNK - pos % READ_CHUNK).min(end - pos);
+            match self.read(pos, chunk_len) {
+                Ok(chunk) => bytes.extend_from_slice(&chunk),
+                Err(_) => break,
+            }
+            pos += chunk_len;
+        }
+        bytes
+    }
 }
 
+/// String/Vec を読み取る際の上限
+///
+/// 破損した len/capacity をそのまま信じると巨大な読み取りやエラーの連鎖になるため、
+/// これらの上限を超える値は読み取らずに診断メッセージを表示します。
+#[derive(Debug, Clone)]
+pub struct ContainerLimits {
+    /// 表示する最大文字列長（バイト）
+    pub max_string_len: usize,
+    /// 表示する最大要素数
+    pub max_elements: usize,
+    /// 妥当とみなす確保サイズ（capacity × 要素サイズ）の上限（バイト）
+    pub max_capacity_bytes: u64,
+}
+
+impl Default for ContainerLimits {
+    fn default() -> Self {
+        Self {
+            max_string_len: 1024,
+            max_elements: 20,
+            max_capacity_bytes: 1 << 32,
+        }
+    }
+}
+
+/// 読み取りを分割する単位（ページサイズ）
+const READ_CHUNK: usize = 4096;
+
 /// フォーマット制御オプション
 #[derive(Debug, Clone)]
 pub struct FormatOptions {
@@ -156,7 +212,12 @@ impl Default for FormatOptions {
 impl<'a> ValueFormatter<'a> {
     /// 新しいフォーマッターを作成する
     pub fn new(memory: &'a dyn MemoryReader) -> Self {
-        Self { memory }
+        Self::with_limits(memory, ContainerLimits::default())
+    }
+
+    /// 上限を指定してフォーマッターを作成する
+    pub fn with_limits(memory: &'a dyn MemoryReader, limits: ContainerLimits) -> Self {
+        Self { memory, limits }
     }
 
     /// TypeInfo を使って値をフォーマットする（高度版）
@@ -171,16 +232,16 @@ impl<'a> ValueFormatter<'a> {
             return Ok("<max depth reached>".to_string());
         }
 
-        // 循環参照チェック
-        if options.visited.contains(&address) {
-            return Ok("<circular reference>".to_string());
-        }
-
-        options.visited.insert(address);
-
         match type_info {
-            TypeInfo::Primitive { name, .. } => {
-                // プリミティブ型は型名から判断
+            TypeInfo::Primitive { name, size } => {
+                // 数値・bool・char はバイト列をデコードし、それ以外は型名から判断
+                if (1..=16).contains(size) {
+                    let bytes = self.memory.read(address as usize, *size as usize)?;
+                    match ValueDecoder::new(DecodeConfig::default()).decode_primitive(&bytes, name) {
+                        DisplayValue::Unavailable => {}
+                        value => return Ok(value.to_string()),
+                    }
+                }
                 self.format_by_type(address, name)
             }
             TypeInfo::Pointer { pointee_type, .. } => {
@@ -193,8 +254,11 @@ impl<'a> ValueFormatter<'a> {
                 }
             }
             TypeInfo::Reference { referent_type, .. } => {
-                // 参照の先を読み取る
+                // 参照の先を読み取る（構造体と先頭のフィールドは同じアドレスなので、循環はたどる先で調べる）
                 let ref_addr = self.memory.read_u64(address as usize)?;
+                if !options.visited.insert(ref_addr) {
+                    return Ok("<circular reference>".to_string());
+                }
                 if let Some(referent) = referent_type {
                     self.format_with_type_info(ref_addr, referent, options)
                 } else {
@@ -204,9 +268,14 @@ impl<'a> ValueFormatter<'a> {
             TypeInfo::Struct { name, fields, .. } => {
                 self.format_struct(address, name, fields, options)
             }
-            TypeInfo::Enum { name, variants, .. } => {
-                // Enumは簡易フォーマット
-                Ok(format!("<{} enum with {} variants>", name, variants.len()))
+            TypeInfo::Enum { .. } | TypeInfo::Union { .. } => {
+                // 残りの深さを引き継いで ValueDecoder でデコードする
+                let decoder = ValueDecoder::new(DecodeConfig {
+                    max_depth: options.max_depth - options.indent / 2,
+                    max_array_elements: self.limits.max_elements,
+                    ..DecodeConfig::default()
+                });
+                Ok(decoder.decode_typed(self.memory, address, type_info).to_string())
             }
             TypeInfo::Array { element_type, length } => {
                 if let Some(elem_type) = element_type {
@@ -215,9 +284,6 @@ impl<'a> ValueFormatter<'a> {
                     Ok("[<unknown array>]".to_string())
                 }
             }
-            TypeInfo::Union { name, .. } => {
-                Ok(format!("<{} union>", name))
-            }
             TypeInfo::Unknown => Ok("<unknown type>".to_string()),
         }
     }
@@ -241,7 +307,7 @@ impl<'a> ValueFormatter<'a> {
         &self,
         address: u64,
         name: &str,
-        fields: &[TypeFieldInfo],
+        fields: &[FieldInfo],
         options: FormatOptions,
     ) -> Result<String> {
         if fields.is_empty() {
@@ -261,7 +327,7 @@ impl<'a> ValueFormatter<'a> {
                 self.format_with_type_info(field_addr, type_info, field_options)
                     .unwrap_or_else(|_| "<error>".to_string())
             } else {
-                format!("<no type info>")
+                "<no type info>".to_string()
             };
 
             result.push_str(&format!("{}  {}: {},\n", indent_str, field.name, field_value));
@@ -280,11 +346,10 @@ impl<'a> ValueFormatter<'a> {
         options: FormatOptions,
     ) -> Result<String> {
         let len = length.unwrap_or(0) as usize;
-        const MAX_ELEMENTS: usize = 20;
-        let display_len = len.min(MAX_ELEMENTS);
+        let display_len = len.min(self.limits.max_elements);
 
         // 要素のサイズを取得
-        let element_size = self.get_type_size(element_type);
+        let element_size = element_type.byte_size();
         if element_size == 0 {
             return Ok(format!("[<unknown element size>, len: {}]", len));
         }
@@ -298,33 +363,13 @@ impl<'a> ValueFormatter<'a> {
         }
 
         let elements_str = elements.join(", ");
-        if len > MAX_ELEMENTS {
+        if len > display_len {
             Ok(format!("[{}, ...] (len: {})", elements_str, len))
         } else {
             Ok(format!("[{}]", elements_str))
         }
     }
 
-    /// 型のサイズを取得する
-    fn get_type_size(&self, type_info: &TypeInfo) -> u64 {
-        match type_info {
-            TypeInfo::Primitive { size, .. } => *size,
-            TypeInfo::Pointer { size, .. } => *size,
-            TypeInfo::Reference { size, .. } => *size,
-            TypeInfo::Struct { size, .. } => *size,
-            TypeInfo::Enum { size, .. } => *size,
-            TypeInfo::Union { size, .. } => *size,
-            TypeInfo::Array { element_type, length } => {
-                if let (Some(elem_type), Some(len)) = (element_type, length) {
-                    self.get_type_size(elem_type) * len
-                } else {
-                    0
-                }
-            }
-            TypeInfo::Unknown => 0,
-        }
-    }
-
     /// 型名に基づいて値をフォーマットする
     pub fn format_by_type(&self, address: u64, type_name: &str) -> Result<String> {
         let basic_type = BasicType::from_type_name(type_name);
@@ -398,32 +443,36 @@ impl<'a> ValueFormatter<'a> {
     ///
     /// String の内部表現: { ptr: *const u8, len: usize, capacity: usize }
     pub fn format_string(&self, address: u64) -> Result<String> {
-        // ptr (8 bytes)
-        let ptr = self.memory.read_u64(address as usize)?;
-
-        // len (8 bytes)
-        let len = self.memory.read_u64((address + 8) as usize)? as usize;
-
-        // capacity (8 bytes)
-        let capacity = self.memory.read_u64((address + 16) as usize)? as usize;
-
-        // 最大長を制限（安全性のため）
-        const MAX_STR_LEN: usize = 1024;
-        let actual_len = len.min(MAX_STR_LEN);
-
-        // 文字列データを読み取る
-        let bytes = self.memory.read(ptr as usize, actual_len)?;
-
-        // UTF-8としてデコード
-        match std::str::from_utf8(&bytes) {
-            Ok(s) => {
-                if len > MAX_STR_LEN {
-                    Ok(format!("\"{}...\" (len: {}, cap: {})", s, len, capacity))
-                } else {
-                    Ok(format!("\"{}\" (cap: {})", s, capacity))
-                }
-            }
-            Err(_) => Ok(format!("<invalid UTF-8, len: {}, cap: {}>", len, capacity)),
+        let (ptr, len, capacity) = self.read_raw_vec(address)?;
+        if let Some(reason) = self.check_container(ptr, len, capacity, 1) {
+            return Ok(corrupted("String", &reason, ptr, len, capacity));
+        }
+
+        let actual_len = len.min(self.limits.max_string_len as u64) as usize;
+        let bytes = self.memory.read_prefix(ptr as usize, actual_len);
+        if bytes.is_empty() && actual_len > 0 {
+            return Ok(corrupted("String", "data is unreadable", ptr, len, capacity));
+        }
+
+        // 途中までしか読めなかった場合や途中で切れた場合も、有効な UTF-8 の部分は表示する
+        let (text, valid) = match std::str::from_utf8(&bytes) {
+            Ok(s) => (s, true),
+            Err(e) if e.error_len().is_none() => (std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or(""), true),
+            Err(_) => ("", false),
+        };
+        if !valid {
+            return Ok(format!("<invalid UTF-8, len: {}, cap: {}>", len, capacity));
+        }
+
+        if bytes.len() < actual_len {
+            Ok(format!(
+                "\"{}...\" (only {} of {} bytes readable, cap: {})",
+                text, bytes.len(), len, capacity
+            ))
+        } else if len as usize > actual_len {
+            Ok(format!("\"{}...\" (len: {}, cap: {})", text, len, capacity))
+        } else {
+            Ok(format!("\"{}\" (cap: {})", text, capacity))
         }
     }
 
@@ -431,43 +480,76 @@ impl<'a> ValueFormatter<'a> {
     ///
     /// Vec の内部表現: { ptr: *mut T, len: usize, capacity: usize }
     pub fn format_vec_primitive(&self, address: u64, element_size: usize) -> Result<String> {
-        // ptr (8 bytes)
-        let ptr = self.memory.read_u64(address as usize)?;
-
-        // len (8 bytes)
-        let len = self.memory.read_u64((address + 8) as usize)? as usize;
-
-        // capacity (8 bytes)
-        let capacity = self.memory.read_u64((address + 16) as usize)? as usize;
+        let (ptr, len, capacity) = self.read_raw_vec(address)?;
+        if let Some(reason) = self.check_container(ptr, len, capacity, element_size as u64) {
+            return Ok(corrupted("Vec", &reason, ptr, len, capacity));
+        }
 
-        // 要素数を制限（安全性のため）
-        const MAX_ELEMENTS: usize = 20;
-        let display_len = len.min(MAX_ELEMENTS);
+        let display_len = len.min(self.limits.max_elements as u64);
 
         let mut elements = Vec::new();
         for i in 0..display_len {
-            let elem_addr = ptr + (i * element_size) as u64;
+            let elem_addr = ptr + i * element_size as u64;
 
             // 要素のサイズに応じて読み取り
             let value = match element_size {
-                1 => self.memory.read_u8(elem_addr as usize)? as u64,
-                2 => self.memory.read_u16(elem_addr as usize)? as u64,
-                4 => self.memory.read_u32(elem_addr as usize)? as u64,
-                8 => self.memory.read_u64(elem_addr as usize)?,
+                1 => self.memory.read_u8(elem_addr as usize).map(u64::from),
+                2 => self.memory.read_u16(elem_addr as usize).map(u64::from),
+                4 => self.memory.read_u32(elem_addr as usize).map(u64::from),
+                8 => self.memory.read_u64(elem_addr as usize),
                 _ => return Ok(format!("[...] (len: {}, cap: {})", len, capacity)),
             };
 
-            elements.push(value.to_string());
+            match value {
+                Ok(value) => elements.push(value.to_string()),
+                Err(_) => {
+                    // 読めたところまでを表示する
+                    elements.push(format!("<unreadable at 0x{:x}>", elem_addr));
+                    break;
+                }
+            }
         }
 
         let elements_str = elements.join(", ");
-        if len > MAX_ELEMENTS {
+        if len > display_len {
             Ok(format!("[{}, ...] (len: {}, cap: {})", elements_str, len, capacity))
         } else {
             Ok(format!("[{}] (len: {}, cap: {})", elements_str, len, capacity))
         }
     }
 
+    /// Vec・String の { ptr, len, capacity } の3ワードを読み取る
+    pub fn read_raw_vec(&self, address: u64) -> Result<(u64, u64, u64)> {
+        let ptr = self.memory.read_u64(address as usize)?;
+        let len = self.memory.read_u64((address + 8) as usize)?;
+        let capacity = self.memory.read_u64((address + 16) as usize)?;
+        Ok((ptr, len, capacity))
+    }
+
+    /// len/capacity/ptr の整合性を検査し、破損していればその理由を返す
+    fn check_container(&self, ptr: u64, len: u64, capacity: u64, element_size: u64) -> Option<String> {
+        // ゼロサイズ型の Vec は capacity が usize::MAX になるため検査しない
+        if element_size == 0 {
+            return None;
+        }
+        if len > capacity {
+            return Some(format!("len {} exceeds cap {}", len, capacity));
+        }
+        match capacity.checked_mul(element_size) {
+            Some(bytes) if bytes <= self.limits.max_capacity_bytes => {}
+            _ => {
+                return Some(format!(
+                    "cap {} exceeds the {}-byte limit",
+                    capacity, self.limits.max_capacity_bytes
+                ))
+            }
+        }
+        if len > 0 && (ptr == 0 || !self.memory.is_mapped(ptr as usize)) {
+            return Some(format!("ptr 0x{:x} is not mapped", ptr));
+        }
+        None
+    }
+
     /// Option<T> をフォーマットする（簡易版）
     ///
     /// Option のレイアウト:
@@ -502,6 +584,11 @@ impl<'a> ValueFormatter<'a> {
     }
 }
 
+/// 破損したコンテナの診断メッセージ
+fn corrupted(kind: &str, reason: &str, ptr: u64, len: u64, capacity: u64) -> String {
+    format!("<corrupted {}: {} (ptr: 0x{:x}, len: {}, cap: {})>", kind, reason, ptr, len, capacity)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -512,21 +599,21 @@ mod tests {
 
     impl MemoryReader for MockMemory {
         fn read_u8(&self, addr: usize) -> Result<u8> {
-            Ok(self.data[addr])
+            Ok(self.read(addr, 1)?[0])
         }
 
         fn read_u16(&self, addr: usize) -> Result<u16> {
-            let bytes = &self.data[addr..addr+2];
+            let bytes = self.read(addr, 2)?;
             Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
         }
 
         fn read_u32(&self, addr: usize) -> Result<u32> {
-            let bytes = &self.data[addr..addr+4];
+            let bytes = self.read(addr, 4)?;
             Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
         }
 
         fn read_u64(&self, addr: usize) -> Result<u64> {
-            let bytes = &self.data[addr..addr+8];
+            let bytes = self.read(addr, 8)?;
             Ok(u64::from_le_bytes([
                 bytes[0], bytes[1], bytes[2], bytes[3],
                 bytes[4], bytes[5], bytes[6], bytes[7],
@@ -534,10 +621,97 @@ mod tests {
         }
 
         fn read(&self, addr: usize, size: usize) -> Result<Vec<u8>> {
-            Ok(self.data[addr..addr+size].to_vec())
+            self.data
+                .get(addr..addr + size)
+                .map(|b| b.to_vec())
+                .ok_or_else(|| anyhow::anyhow!("unmapped"))
+        }
+
+        fn is_mapped(&self, addr: usize) -> bool {
+            addr < self.data.len()
         }
     }
 
+    /// { ptr, len, cap } を書き込む
+    fn write_raw_vec(data: &mut [u8], at: usize, ptr: u64, len: u64, cap: u64) {
+        data[at..at + 8].copy_from_slice(&ptr.to_le_bytes());
+        data[at + 8..at + 16].copy_from_slice(&len.to_le_bytes());
+        data[at + 16..at + 24].copy_from_slice(&cap.to_le_bytes());
+    }
+
+    #[test]
+    fn test_format_string_validation() {
+        let mut data = vec![0u8; 0x2000];
+        data[0x80..0x85].copy_from_slice(b"Hello");
+        write_raw_vec(&mut data, 0x00, 0x80, 5, 8);
+        write_raw_vec(&mut data, 0x20, 0x80, 50, 8);
+        write_raw_vec(&mut data, 0x40, 0x80, 5, u64::MAX / 2);
+        write_raw_vec(&mut data, 0x60, 0x1_0000, 5, 8);
+        // ページ末尾の 6 バイトだけが読める
+        data[0x1ffa..0x2000].copy_from_slice(b"abcdef");
+        write_raw_vec(&mut data, 0xc0, 0x1ffa, 10, 16);
+
+        let memory = MockMemory { data };
+        let formatter = ValueFormatter::new(&memory);
+
+        assert_eq!(formatter.format_string(0x00).unwrap(), "\"Hello\" (cap: 8)");
+        assert!(formatter.format_string(0x20).unwrap().contains("len 50 exceeds cap 8"));
+        assert!(formatter.format_string(0x40).unwrap().contains("byte limit"));
+        assert!(formatter.format_string(0x60).unwrap().contains("not mapped"));
+        assert_eq!(
+            formatter.format_string(0xc0).unwrap(),
+            "\"abcdef...\" (only 6 of 10 bytes readable, cap: 16)"
+        );
+    }
+
+    #[test]
+    fn test_format_vec_partial() {
+        let mut data = vec![0u8; 64];
+        data[0x20..0x24].copy_from_slice(&7u32.to_le_bytes());
+        // 2 要素目以降はメモリ外
+        write_raw_vec(&mut data, 0, 0x3c, 3, 4);
+        data[0x3c..0x40].copy_from_slice(&9u32.to_le_bytes());
+        let memory = MockMemory { data };
+        let formatter = ValueFormatter::new(&memory);
+
+        assert_eq!(
+            formatter.format_vec_primitive(0, 4).unwrap(),
+            "[9, <unreadable at 0x40>] (len: 3, cap: 4)"
+        );
+    }
+
+    #[test]
+    fn test_format_with_type_info() {
+        let mut data = vec![0u8; 64];
+        data[0..4].copy_from_slice(&(-7i32).to_le_bytes());
+        data[4] = 1;
+        // 自分自身を指す参照
+        data[8..16].copy_from_slice(&8u64.to_le_bytes());
+        let memory = MockMemory { data };
+        let formatter = ValueFormatter::new(&memory);
+
+        let primitive = |name: &str, size| Some(Box::new(TypeInfo::Primitive { name: name.to_string(), size }));
+        let field = |name: &str, offset, type_info| FieldInfo { name: name.to_string(), offset, size: 0, type_name: None, type_info };
+        let pair = TypeInfo::Struct {
+            name: "Pair".to_string(),
+            size: 8,
+            fields: vec![field("a", 0, primitive("i32", 4)), field("b", 4, primitive("bool", 1))],
+        };
+        assert_eq!(
+            formatter.format_with_type_info(0, &pair, FormatOptions::default()).unwrap(),
+            "Pair {\n  a: -7,\n  b: true,\n}"
+        );
+
+        let self_ref = TypeInfo::Reference {
+            referent_type: Some(Box::new(TypeInfo::Reference { referent_type: None, size: 8 })),
+            size: 8,
+        };
+        assert_eq!(
+            formatter.format_with_type_info(8, &self_ref, FormatOptions::default()).unwrap(),
+            "<circular reference>"
+        );
+    }
+
     #[test]
     fn test_format_str() {
         // メモリレイアウト:
@@ -1,8 +1,13 @@
 //! 変数ロケーション評価
 
-use crate::{DwarfLoader, Result};
+use crate::utils::{array_length, entry_name};
+use crate::{CancelToken, DwarfLoader, Result, TypeRef};
 use crate::{LocationEvaluator, Loc, ValueDecoder, DecodeConfig, DisplayValue};
 use gimli::Reader;
+use std::collections::HashMap;
+
+/// 名前のない型をたどって型名を組み立てる深さの上限（循環した DWARF で止まらなくなるのを防ぐ）
+const MAX_TYPE_NAME_DEPTH: usize = 8;
 
 /// 変数の値
 #[derive(Debug, Clone)]
@@ -51,6 +56,26 @@ pub struct Variable {
     pub type_name: String,
     pub value: Option<VariableValue>,
     pub location: VariableLocation,
+    pub kind: VariableKind,
+}
+
+/// 変数の種類（DIE のタグで区別する）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableKind {
+    /// ローカル変数（DW_TAG_variable）
+    Local,
+    /// 関数の引数（DW_TAG_formal_parameter）
+    Argument,
+}
+
+impl VariableKind {
+    fn of<R: Reader>(entry: &gimli::DebuggingInformationEntry<R>) -> Self {
+        if entry.tag() == gimli::DW_TAG_formal_parameter {
+            VariableKind::Argument
+        } else {
+            VariableKind::Local
+        }
+    }
 }
 
 /// 変数のロケーション
@@ -62,6 +87,8 @@ pub enum VariableLocation {
     Register(u16),
     /// 静的アドレス
     Address(u64),
+    /// 計算された値（DW_OP_stack_value など。メモリ上にもレジスタにも実体がない）
+    Computed,
     /// 最適化により削除された
     OptimizedOut,
     /// 不明
@@ -79,35 +106,83 @@ pub struct LocalVariable {
     pub type_name: Option<String>,
 }
 
+/// `thread_local!` が実体の変数に付ける名前（std の版によって異なる）
+const THREAD_LOCAL_STORAGE_NAMES: &[&str] = &["__RUST_STD_INTERNAL_VAL", "VAL", "__KEY"];
+
+/// 関数の外で定義された変数（static と thread_local!）の置き場所
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaticLocation {
+    /// ファイル上のアドレス（DW_OP_addr）
+    Address(u64),
+    /// モジュールの TLS ブロック内のオフセット（DW_OP_GNU_push_tls_address / DW_OP_form_tls_address）
+    ThreadLocal(u64),
+}
+
+/// 関数の外で定義された変数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticVariable {
+    /// 名前空間付きの名前（`thread_local!` なら実体ではなく宣言した名前: `tokio::runtime::context::CONTEXT`）
+    pub path: String,
+    pub type_name: String,
+    pub location: StaticLocation,
+    pub linkage_name: Option<String>,
+    /// 型 DIE（値の読み取りに使う型情報はここから作る）
+    pub type_ref: Option<TypeRef>,
+}
+
+impl StaticVariable {
+    /// `name` がこの変数を指すか（完全な名前か、`::` 区切りの末尾が一致すればよい）
+    pub fn matches(&self, name: &str) -> bool {
+        self.path == name || self.path.strip_suffix(name).is_some_and(|prefix| prefix.ends_with("::"))
+    }
+}
+
+/// `thread_local!` の実体の名前空間付きの名前から、宣言した名前を取り出す
+///
+/// `app::COUNTER::{constant#0}::{closure#1}::__RUST_STD_INTERNAL_VAL` は `app::COUNTER` になります。
+fn thread_local_key(path: &[String]) -> Option<String> {
+    let (last, scopes) = path.split_last()?;
+    if !THREAD_LOCAL_STORAGE_NAMES.contains(&last.as_str()) {
+        return None;
+    }
+    let key_len = scopes.iter().rposition(|scope| !scope.starts_with('{') && scope != "__getit")? + 1;
+    Some(scopes[..key_len].join("::"))
+}
+
 /// 変数ロケーター
 pub struct VariableLocator<'a> {
     loader: &'a DwarfLoader,
+    cancel: CancelToken,
+    /// 関数の入口でのレジスタ値（呼び出し元の call site から復元したもの）
+    entry_values: HashMap<u16, u64>,
 }
 
 impl<'a> VariableLocator<'a> {
     /// 変数ロケーターを作成する
     pub fn new(loader: &'a DwarfLoader) -> Self {
-        Self { loader }
+        Self { loader, cancel: CancelToken::new(), entry_values: HashMap::new() }
     }
 
-    /// 関数のローカル変数を取得する
-    pub fn get_locals(&self, pc: u64) -> Result<Vec<Variable>> {
-        let dwarf = self.loader.dwarf();
-        let mut variables = Vec::new();
-
-        // 各コンパイルユニットを走査
-        let mut iter = dwarf.units();
-        while let Some(header) = iter.next()? {
-            let unit = dwarf.unit(header)?;
+    /// 走査を中断するトークンを設定する
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
 
-            // PCを含む関数DIEを探す
-            if let Some(function_die_offset) = self.find_function_at_pc(&unit, pc)? {
-                // 関数DIEの子（ローカル変数）を列挙
-                variables.extend(self.enumerate_local_variables(&unit, function_die_offset)?);
-            }
-        }
+    /// 関数の入口でのレジスタ値を設定する（DW_OP_entry_value の評価に使う）
+    pub fn with_entry_values(mut self, entry_values: HashMap<u16, u64>) -> Self {
+        self.entry_values = entry_values;
+        self
+    }
 
-        Ok(variables)
+    /// 関数のローカル変数を取得する
+    pub fn get_locals(&self, pc: u64) -> Result<Vec<Variable>> {
+        // PCを含む関数DIEを探す（同じ関数が複数のユニットにあっても 1 つだけ使う）
+        let Some((unit, function_die_offset)) = self.find_function_at_pc(pc)? else {
+            return Ok(Vec::new());
+        };
+        // 関数DIEの子（ローカル変数）のうち PC で有効なものを列挙
+        self.enumerate_local_variables(&unit, function_die_offset, pc)
     }
 
     /// 関数のローカル変数を値付きで取得する（DWARF完全評価版）
@@ -128,53 +203,162 @@ impl<'a> VariableLocator<'a> {
         F: FnMut(u16) -> Result<u64>,
         G: FnMut(u64, usize) -> Result<Vec<u8>>,
     {
-        let dwarf = self.loader.dwarf();
-        let mut variables = Vec::new();
         let decoder = ValueDecoder::new(DecodeConfig::default());
 
-        // 各コンパイルユニットを走査
+        // PCを含む関数DIEを探す
+        let Some((unit, function_die_offset)) = self.find_function_at_pc(pc)? else {
+            return Ok(Vec::new());
+        };
+        // 関数DIEの子（ローカル変数）を列挙して値を読み取る
+        self.enumerate_local_variables_with_values(
+            self.loader.dwarf(),
+            &unit,
+            function_die_offset,
+            pc,
+            frame_base,
+            &mut get_reg,
+            &mut read_mem,
+            &decoder,
+        )
+    }
+
+    /// 名前が `name` に一致する static と `thread_local!` を探す
+    ///
+    /// 関数の中は探しません。`thread_local!` は宣言ごとに使われない実体も DWARF に残るので、
+    /// シンボルテーブルに TLS シンボルとして残っているものだけを返します。
+    pub fn find_statics(&self, name: &str) -> Result<Vec<StaticVariable>> {
+        use object::{Object, ObjectSymbol, SymbolKind};
+
+        let dwarf = self.loader.dwarf();
+        let mut found = Vec::new();
         let mut iter = dwarf.units();
         while let Some(header) = iter.next()? {
+            self.cancel.check()?;
             let unit = dwarf.unit(header)?;
+            let mut tree = unit.entries_tree(None)?;
+            let mut path = Vec::new();
+            self.collect_statics(&mut found, tree.root()?, &unit, &mut path, name)?;
+        }
 
-            // PCを含む関数DIEを探す
-            if let Some(function_die_offset) = self.find_function_at_pc(&unit, pc)? {
-                // 関数DIEの子（ローカル変数）を列挙して値を読み取る
-                variables.extend(self.enumerate_local_variables_with_values(
-                    &unit,
-                    function_die_offset,
-                    frame_base,
-                    &mut get_reg,
-                    &mut read_mem,
-                    &decoder,
-                )?);
-            }
+        let tls_symbols: std::collections::HashSet<&str> = self.loader.object_file().symbols()
+            .filter(|sym| sym.kind() == SymbolKind::Tls)
+            .filter_map(|sym| sym.name().ok())
+            .collect();
+        if !tls_symbols.is_empty() {
+            found.retain(|var| match (var.location, &var.linkage_name) {
+                (StaticLocation::ThreadLocal(_), Some(linkage)) => tls_symbols.contains(linkage.as_str()),
+                _ => true,
+            });
         }
+        Ok(found)
+    }
 
-        Ok(variables)
+    /// 名前空間をたどって static を集める
+    fn collect_statics<R: Reader<Offset = usize>>(
+        &self,
+        found: &mut Vec<StaticVariable>,
+        node: gimli::EntriesTreeNode<R>,
+        unit: &gimli::Unit<R>,
+        path: &mut Vec<String>,
+        name: &str,
+    ) -> Result<()> {
+        let dwarf = self.loader.dwarf();
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            match entry.tag() {
+                gimli::DW_TAG_namespace => {
+                    path.push(entry_name(dwarf, unit, entry).unwrap_or_default());
+                    self.collect_statics(found, child, unit, path, name)?;
+                    path.pop();
+                }
+                gimli::DW_TAG_variable => {
+                    let Some(var_name) = entry_name(dwarf, unit, entry) else {
+                        continue;
+                    };
+                    let Some(location) = static_location(dwarf, unit, entry)? else {
+                        continue;
+                    };
+                    path.push(var_name);
+                    let key = match location {
+                        StaticLocation::ThreadLocal(_) => thread_local_key(path),
+                        StaticLocation::Address(_) => None,
+                    };
+                    let var_path = key.unwrap_or_else(|| path.join("::"));
+                    path.pop();
+
+                    let mut var = StaticVariable {
+                        path: var_path,
+                        type_name: String::new(),
+                        location,
+                        linkage_name: entry.attr_value(gimli::DW_AT_linkage_name)?
+                            .and_then(|value| crate::utils::attr_string(dwarf, unit, value)),
+                        type_ref: None,
+                    };
+                    if var.matches(name) {
+                        var.type_name = self.get_type_name(unit, entry)?.unwrap_or_else(|| "<unknown>".to_string());
+                        if let (Some(gimli::AttributeValue::UnitRef(die)), Some(unit_offset)) =
+                            (entry.attr_value(gimli::DW_AT_type)?, unit.header.offset().as_debug_info_offset())
+                        {
+                            var.type_ref = Some(TypeRef { unit: unit_offset, die });
+                        }
+                        found.push(var);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
     }
 
     /// PCを含む関数DIEを探す
-    fn find_function_at_pc<R: Reader<Offset = usize>>(
+    fn find_function_at_pc(
         &self,
-        unit: &gimli::Unit<R>,
         pc: u64,
-    ) -> Result<Option<gimli::UnitOffset<R::Offset>>> {
-        crate::utils::FunctionFinder::find_at_pc(unit, pc)
+    ) -> Result<Option<(gimli::Unit<gimli::EndianSlice<'static, gimli::RunTimeEndian>>, gimli::UnitOffset)>> {
+        let Some(function) = crate::utils::FunctionFinder::find_in_loader(self.loader, pc, &self.cancel)? else {
+            return Ok(None);
+        };
+        let dwarf = self.loader.dwarf();
+        let unit = dwarf.unit(dwarf.debug_info.header_from_offset(function.unit)?)?;
+        Ok(Some((unit, function.die)))
     }
 
-    /// ローカル変数を列挙する（PCは使用しないバージョン）
-    fn enumerate_local_variables<R: Reader<Offset = usize>>(
+    /// 変数の名前と型名（具象 DIE になければ DW_AT_abstract_origin の先から取る）
+    fn variable_name_and_type<R: Reader<Offset = usize>>(
         &self,
         unit: &gimli::Unit<R>,
-        function_offset: gimli::UnitOffset<R::Offset>,
+        entry: &gimli::DebuggingInformationEntry<R>,
+    ) -> Result<Option<(String, String)>> {
+        let origin = match entry.attr_value(gimli::DW_AT_abstract_origin)? {
+            Some(gimli::AttributeValue::UnitRef(origin)) => Some(unit.entry(origin)?),
+            _ => None,
+        };
+        let name = entry_name(self.loader.dwarf(), unit, entry)
+            .or_else(|| origin.as_ref().and_then(|origin| entry_name(self.loader.dwarf(), unit, origin)));
+        let Some(name) = name else {
+            return Ok(None);
+        };
+        let mut type_name = self.get_type_name(unit, entry)?;
+        if let (None, Some(origin)) = (&type_name, &origin) {
+            type_name = self.get_type_name(unit, origin)?;
+        }
+        Ok(Some((name, type_name.unwrap_or_else(|| "<unknown>".to_string()))))
+    }
+
+    /// ローカル変数を列挙する
+    fn enumerate_local_variables(
+        &self,
+        unit: &gimli::Unit<gimli::EndianSlice<'static, gimli::RunTimeEndian>>,
+        function_offset: gimli::UnitOffset,
+        pc: u64,
     ) -> Result<Vec<Variable>> {
         let mut variables = Vec::new();
         let mut tree = unit.entries_tree(Some(function_offset))?;
         let root = tree.root()?;
 
         // 関数DIEの子を再帰的に走査
-        self.collect_variables_recursive(&mut variables, root, unit)?;
+        self.collect_variables_recursive(&mut variables, root, self.loader.dwarf(), unit, pc)?;
 
         Ok(variables)
     }
@@ -184,8 +368,11 @@ impl<'a> VariableLocator<'a> {
         &self,
         variables: &mut Vec<Variable>,
         node: gimli::EntriesTreeNode<R>,
+        dwarf: &gimli::Dwarf<R>,
         unit: &gimli::Unit<R>,
+        pc: u64,
     ) -> Result<()> {
+        self.cancel.check()?;
         let entry = node.entry();
 
         // DW_TAG_variable または DW_TAG_formal_parameter (引数)
@@ -197,10 +384,14 @@ impl<'a> VariableLocator<'a> {
             }
         }
 
-        // 子ノードを再帰的に走査（lexical_blockなど）
+        // 子ノードを再帰的に走査（lexical_blockなど）。PC で有効でないスコープと変数、
+        // インライン展開された関数（その変数は展開先の関数のものではない）は飛ばす
+        let scope_start = scope_start(dwarf, unit, entry)?;
         let mut children = node.children();
         while let Some(child) = children.next()? {
-            self.collect_variables_recursive(variables, child, unit)?;
+            if child.entry().tag() != gimli::DW_TAG_inlined_subroutine && live_at(dwarf, unit, child.entry(), scope_start, pc)? {
+                self.collect_variables_recursive(variables, child, dwarf, unit, pc)?;
+            }
         }
 
         Ok(())
@@ -212,26 +403,11 @@ impl<'a> VariableLocator<'a> {
         unit: &gimli::Unit<R>,
         entry: &gimli::DebuggingInformationEntry<R>,
     ) -> Result<Option<Variable>> {
-        // 変数名を取得
-        let name = match entry.attr_value(gimli::DW_AT_name)? {
-            Some(gimli::AttributeValue::String(s)) => {
-                s.to_string_lossy()?.into_owned()
-            }
-            Some(gimli::AttributeValue::DebugStrRef(offset)) => {
-                // DebugStrRef を適切に処理
-                let dwarf = self.loader.dwarf();
-                match dwarf.string(offset) {
-                    Ok(s) => s.to_string_lossy().into_owned(),
-                    Err(_) => return Ok(None),
-                }
-            }
-            _ => return Ok(None),
+        // 変数名と型名を取得
+        let Some((name, type_name)) = self.variable_name_and_type(unit, entry)? else {
+            return Ok(None);
         };
 
-        // 型名を取得（簡易実装）
-        let type_name = self.get_type_name(unit, entry)?
-            .unwrap_or_else(|| "<unknown>".to_string());
-
         // ロケーションを取得
         let location = self.get_variable_location(unit, entry)?;
 
@@ -240,6 +416,7 @@ impl<'a> VariableLocator<'a> {
             type_name,
             value: None, // 値の読み取りは後で実装
             location,
+            kind: VariableKind::of(entry),
         }))
     }
 
@@ -271,7 +448,7 @@ impl<'a> VariableLocator<'a> {
                             Ok(VariableLocation::Address(addr))
                         }
                         // DW_OP_regN: レジスタ
-                        op if op >= gimli::constants::DW_OP_reg0.0 && op <= gimli::constants::DW_OP_reg31.0 => {
+                        op if (gimli::constants::DW_OP_reg0.0..=gimli::constants::DW_OP_reg31.0).contains(&op) => {
                             let reg = op - gimli::constants::DW_OP_reg0.0;
                             Ok(VariableLocation::Register(reg as u16))
                         }
@@ -297,31 +474,71 @@ impl<'a> VariableLocator<'a> {
             _ => return Ok(None),
         };
 
-        // 型DIEを取得
-        let mut entries = unit.entries_at_offset(type_attr)?;
-        if let Some((_, type_entry)) = entries.next_dfs()? {
-            // 型名を取得
-            match type_entry.attr_value(gimli::DW_AT_name)? {
-                Some(gimli::AttributeValue::String(s)) => {
-                    return Ok(Some(s.to_string_lossy()?.into_owned()));
-                }
-                Some(gimli::AttributeValue::DebugStrRef(offset)) => {
-                    // DebugStrRef を適切に処理
-                    let dwarf = self.loader.dwarf();
-                    if let Ok(s) = dwarf.string(offset) {
-                        return Ok(Some(s.to_string_lossy().into_owned()));
-                    }
-                }
-                _ => {}
-            }
+        self.type_name_at(unit, type_attr, MAX_TYPE_NAME_DEPTH)
+    }
 
-            // 基本型の場合、エンコーディングから名前を推測
-            if type_entry.tag() == gimli::DW_TAG_base_type {
-                return Ok(Some(self.infer_base_type_name(&type_entry)?));
+    /// 型DIEの表示名を求める
+    ///
+    /// typedef・const・volatile は実体の型の名前にし、名前のないポインタ・参照・配列は
+    /// 指す先の型から Rust 風の名前（`*const Foo`, `&i32`, `[u8; 4]`）を組み立てる。
+    fn type_name_at<R: Reader<Offset = usize>>(
+        &self,
+        unit: &gimli::Unit<R>,
+        offset: gimli::UnitOffset<R::Offset>,
+        budget: usize,
+    ) -> Result<Option<String>> {
+        let entry = unit.entry(offset)?;
+        let tag = entry.tag();
+        let qualifier = matches!(
+            tag,
+            gimli::DW_TAG_typedef
+                | gimli::DW_TAG_const_type
+                | gimli::DW_TAG_volatile_type
+                | gimli::DW_TAG_restrict_type
+                | gimli::DW_TAG_atomic_type
+        );
+        if !qualifier {
+            if let Some(name) = entry_name(self.loader.dwarf(), unit, &entry) {
+                return Ok(Some(name));
             }
         }
+        // 基本型の場合、エンコーディングから名前を推測
+        if tag == gimli::DW_TAG_base_type {
+            return Ok(Some(self.infer_base_type_name(&entry)?));
+        }
+        if budget == 0 {
+            return Ok(None);
+        }
 
-        Ok(None)
+        let target = match entry.attr_value(gimli::DW_AT_type)? {
+            Some(gimli::AttributeValue::UnitRef(target)) => Some(target),
+            _ => None,
+        };
+        // DW_AT_type のないポインタ・修飾子は void を指す
+        let inner = match target {
+            Some(target) => match self.type_name_at(unit, target, budget - 1)? {
+                Some(name) => name,
+                None => return Ok(None),
+            },
+            None => "()".to_string(),
+        };
+        let points_to_const = target
+            .and_then(|target| unit.entry(target).ok())
+            .is_some_and(|target| target.tag() == gimli::DW_TAG_const_type);
+
+        Ok(match tag {
+            _ if qualifier => Some(inner),
+            gimli::DW_TAG_pointer_type if points_to_const => Some(format!("*const {}", inner)),
+            gimli::DW_TAG_pointer_type => Some(format!("*mut {}", inner)),
+            gimli::DW_TAG_reference_type if points_to_const => Some(format!("&{}", inner)),
+            gimli::DW_TAG_reference_type | gimli::DW_TAG_rvalue_reference_type => Some(format!("&mut {}", inner)),
+            gimli::DW_TAG_array_type => Some(match array_length(unit, &entry)? {
+                Some(length) => format!("[{}; {}]", inner, length),
+                None => format!("[{}]", inner),
+            }),
+            gimli::DW_TAG_subroutine_type => Some("fn(..)".to_string()),
+            _ => None,
+        })
     }
 
     /// 基本型の名前を推測
@@ -365,10 +582,13 @@ impl<'a> VariableLocator<'a> {
     }
 
     /// ローカル変数を値付きで列挙する
+    #[allow(clippy::too_many_arguments)]
     fn enumerate_local_variables_with_values<R: Reader<Offset = usize>, F, G>(
         &self,
+        dwarf: &gimli::Dwarf<R>,
         unit: &gimli::Unit<R>,
         function_offset: gimli::UnitOffset<R::Offset>,
+        pc: u64,
         frame_base: Option<u64>,
         get_reg: &mut F,
         read_mem: &mut G,
@@ -386,7 +606,9 @@ impl<'a> VariableLocator<'a> {
         self.collect_variables_with_values_recursive(
             &mut variables,
             root,
+            dwarf,
             unit,
+            pc,
             frame_base,
             get_reg,
             read_mem,
@@ -397,11 +619,14 @@ impl<'a> VariableLocator<'a> {
     }
 
     /// 変数を再帰的に収集する（値付き）
+    #[allow(clippy::too_many_arguments)]
     fn collect_variables_with_values_recursive<R: Reader<Offset = usize>, F, G>(
         &self,
         variables: &mut Vec<Variable>,
         node: gimli::EntriesTreeNode<R>,
+        dwarf: &gimli::Dwarf<R>,
         unit: &gimli::Unit<R>,
+        pc: u64,
         frame_base: Option<u64>,
         get_reg: &mut F,
         read_mem: &mut G,
@@ -411,6 +636,7 @@ impl<'a> VariableLocator<'a> {
         F: FnMut(u16) -> Result<u64>,
         G: FnMut(u64, usize) -> Result<Vec<u8>>,
     {
+        self.cancel.check()?;
         let entry = node.entry();
 
         // DW_TAG_variable または DW_TAG_formal_parameter (引数)
@@ -420,6 +646,7 @@ impl<'a> VariableLocator<'a> {
             if let Some(var) = self.extract_variable_with_value(
                 unit,
                 entry,
+                pc,
                 frame_base,
                 get_reg,
                 read_mem,
@@ -429,13 +656,20 @@ impl<'a> VariableLocator<'a> {
             }
         }
 
-        // 子ノードを再帰的に走査（lexical_blockなど）
+        // 子ノードを再帰的に走査（lexical_blockなど）。PC で有効でないスコープと変数、
+        // インライン展開された関数は飛ばす
+        let scope_start = scope_start(dwarf, unit, entry)?;
         let mut children = node.children();
         while let Some(child) = children.next()? {
+            if child.entry().tag() == gimli::DW_TAG_inlined_subroutine || !live_at(dwarf, unit, child.entry(), scope_start, pc)? {
+                continue;
+            }
             self.collect_variables_with_values_recursive(
                 variables,
                 child,
+                dwarf,
                 unit,
+                pc,
                 frame_base,
                 get_reg,
                 read_mem,
@@ -447,10 +681,12 @@ impl<'a> VariableLocator<'a> {
     }
 
     /// 変数情報を値付きで抽出する
+    #[allow(clippy::too_many_arguments)]
     fn extract_variable_with_value<R: Reader<Offset = usize>, F, G>(
         &self,
         unit: &gimli::Unit<R>,
         entry: &gimli::DebuggingInformationEntry<R>,
+        pc: u64,
         frame_base: Option<u64>,
         get_reg: &mut F,
         read_mem: &mut G,
@@ -460,30 +696,16 @@ impl<'a> VariableLocator<'a> {
         F: FnMut(u16) -> Result<u64>,
         G: FnMut(u64, usize) -> Result<Vec<u8>>,
     {
-        // 変数名を取得
-        let name = match entry.attr_value(gimli::DW_AT_name)? {
-            Some(gimli::AttributeValue::String(s)) => {
-                s.to_string_lossy()?.into_owned()
-            }
-            Some(gimli::AttributeValue::DebugStrRef(offset)) => {
-                // DebugStrRef を適切に処理
-                let dwarf = self.loader.dwarf();
-                match dwarf.string(offset) {
-                    Ok(s) => s.to_string_lossy().into_owned(),
-                    Err(_) => return Ok(None),
-                }
-            }
-            _ => return Ok(None),
+        // 変数名と型名を取得
+        let Some((name, type_name)) = self.variable_name_and_type(unit, entry)? else {
+            return Ok(None);
         };
 
-        // 型名を取得
-        let type_name = self.get_type_name(unit, entry)?
-            .unwrap_or_else(|| "<unknown>".to_string());
-
         // ロケーションを評価
         let (location, value) = self.evaluate_location_and_read_value(
             unit,
             entry,
+            pc,
             frame_base,
             &type_name,
             get_reg,
@@ -496,14 +718,17 @@ impl<'a> VariableLocator<'a> {
             type_name,
             value,
             location,
+            kind: VariableKind::of(entry),
         }))
     }
 
     /// ロケーションを評価して値を読み取る
+    #[allow(clippy::too_many_arguments)]
     fn evaluate_location_and_read_value<R: Reader<Offset = usize>, F, G>(
         &self,
         unit: &gimli::Unit<R>,
         entry: &gimli::DebuggingInformationEntry<R>,
+        pc: u64,
         frame_base: Option<u64>,
         type_name: &str,
         get_reg: &mut F,
@@ -520,23 +745,19 @@ impl<'a> VariableLocator<'a> {
             None => return Ok((VariableLocation::OptimizedOut, Some(VariableValue::Unavailable))),
         };
 
-        // Exprloc の場合のみ評価
-        let expr = match location_attr {
-            gimli::AttributeValue::Exprloc(expr) => expr,
-            _ => return Ok((VariableLocation::Unknown, None)),
-        };
-
-        // LocationEvaluator でロケーションを評価
-        let mut evaluator = LocationEvaluator::new(expr, frame_base, unit.encoding());
-
-        // クロージャをラッピングして、evaluate後も使用可能にする
-        let loc = {
-            let mut get_reg_wrapper = |reg: u16| get_reg(reg);
-            let mut read_mem_wrapper = |addr: u64, size: usize| read_mem(addr, size);
-            match evaluator.evaluate(&mut get_reg_wrapper, &mut read_mem_wrapper) {
-                Ok(l) => l,
-                Err(_) => return Ok((VariableLocation::Unknown, None)),
+        // Exprloc はそのまま、ロケーションリストは PC を含むエントリの式を評価する
+        let evaluated = match location_attr {
+            gimli::AttributeValue::Exprloc(expr) => {
+                self.evaluate_expression(expr, unit.encoding(), frame_base, get_reg, read_mem)
             }
+            attr => match self.location_list_expression(unit, attr, pc)? {
+                Some(expr) => self.evaluate_expression(expr, unit.encoding(), frame_base, get_reg, read_mem),
+                None => return Ok((VariableLocation::OptimizedOut, Some(VariableValue::Unavailable))),
+            },
+        };
+        let loc = match evaluated {
+            Ok(l) => l,
+            Err(_) => return Ok((VariableLocation::Unknown, None)),
         };
 
         // 評価結果に基づいて値を読み取る
@@ -554,6 +775,11 @@ impl<'a> VariableLocator<'a> {
                 let value = self.convert_display_value_to_variable_value(display_value);
                 Ok((VariableLocation::Address(addr), Some(value)))
             }
+            Loc::Value(bytes) => {
+                let display_value = decoder.decode_primitive(&bytes, type_name);
+                let value = self.convert_display_value_to_variable_value(display_value);
+                Ok((VariableLocation::Computed, Some(value)))
+            }
             Loc::Pieces(_pieces) => {
                 // 複数ピースの場合は未対応
                 Ok((VariableLocation::Unknown, Some(VariableValue::Unavailable)))
@@ -564,6 +790,62 @@ impl<'a> VariableLocator<'a> {
         }
     }
 
+    /// LocationEvaluator でロケーション式を評価する
+    fn evaluate_expression<R: Reader<Offset = usize>, F, G>(
+        &self,
+        expr: gimli::Expression<R>,
+        encoding: gimli::Encoding,
+        frame_base: Option<u64>,
+        get_reg: &mut F,
+        read_mem: &mut G,
+    ) -> Result<Loc>
+    where
+        F: FnMut(u16) -> Result<u64>,
+        G: FnMut(u64, usize) -> Result<Vec<u8>>,
+    {
+        let mut evaluator = LocationEvaluator::new(expr, frame_base, encoding)
+            .with_entry_values(self.entry_values.clone());
+
+        // クロージャをラッピングして、evaluate後も使用可能にする
+        let mut get_reg_wrapper = |reg: u16| get_reg(reg);
+        let mut read_mem_wrapper = |addr: u64, size: usize| read_mem(addr, size);
+        evaluator.evaluate(&mut get_reg_wrapper, &mut read_mem_wrapper)
+    }
+
+    /// ロケーションリストから PC を含むエントリの式を取り出す
+    ///
+    /// ロケーションリストでない属性や、PC を含むエントリがない（その位置では最適化で消えている）
+    /// ときは None を返します。
+    fn location_list_expression<R: Reader<Offset = usize>>(
+        &self,
+        unit: &gimli::Unit<R>,
+        attr: gimli::AttributeValue<R>,
+        pc: u64,
+    ) -> Result<Option<gimli::Expression<gimli::EndianSlice<'static, gimli::RunTimeEndian>>>> {
+        let dwarf = self.loader.dwarf();
+        let offset = match attr {
+            gimli::AttributeValue::LocationListsRef(offset) => offset,
+            gimli::AttributeValue::SecOffset(offset) => gimli::LocationListsOffset(offset),
+            gimli::AttributeValue::DebugLocListsIndex(index) => {
+                dwarf.locations.get_offset(unit.encoding(), unit.loclists_base, index)?
+            }
+            _ => return Ok(None),
+        };
+        let mut locations = dwarf.locations.locations(
+            offset,
+            unit.encoding(),
+            unit.low_pc,
+            &dwarf.debug_addr,
+            unit.addr_base,
+        )?;
+        while let Some(entry) = locations.next()? {
+            if (entry.range.begin..entry.range.end).contains(&pc) {
+                return Ok(Some(entry.data));
+            }
+        }
+        Ok(None)
+    }
+
     /// レジスタ値をデコード
     fn decode_register_value(&self, reg_value: u64, type_name: &str, decoder: &ValueDecoder) -> VariableValue {
         let bytes = reg_value.to_le_bytes();
@@ -585,3 +867,82 @@ impl<'a> VariableLocator<'a> {
         }
     }
 }
+
+/// static の DW_AT_location を読む（アドレスか TLS のオフセットだけからなる式でなければ None）
+fn static_location<R: Reader<Offset = usize>>(
+    dwarf: &gimli::Dwarf<gimli::EndianSlice<'static, gimli::RunTimeEndian>>,
+    unit: &gimli::Unit<R>,
+    entry: &gimli::DebuggingInformationEntry<R>,
+) -> Result<Option<StaticLocation>> {
+    let Some(gimli::AttributeValue::Exprloc(expr)) = entry.attr_value(gimli::DW_AT_location)? else {
+        return Ok(None);
+    };
+    let mut ops = expr.operations(unit.encoding());
+    let mut stack = Vec::new();
+    let mut tls = false;
+    while let Some(op) = ops.next()? {
+        match op {
+            gimli::Operation::Address { address } => stack.push(address),
+            gimli::Operation::AddressIndex { index } => {
+                stack.push(dwarf.debug_addr.get_address(unit.encoding().address_size, unit.addr_base, gimli::DebugAddrIndex(index.0))?)
+            }
+            gimli::Operation::UnsignedConstant { value } => stack.push(value),
+            gimli::Operation::TLS if !tls => tls = true,
+            _ => return Ok(None),
+        }
+    }
+    Ok(match (stack.as_slice(), tls) {
+        ([value], true) => Some(StaticLocation::ThreadLocal(*value)),
+        ([address], false) => Some(StaticLocation::Address(*address)),
+        _ => None,
+    })
+}
+
+/// 関数・lexical block の先頭アドレス（DW_AT_start_scope の基準）
+fn scope_start<R: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+    entry: &gimli::DebuggingInformationEntry<R>,
+) -> Result<Option<u64>> {
+    if entry.tag() != gimli::DW_TAG_subprogram && entry.tag() != gimli::DW_TAG_lexical_block {
+        return Ok(None);
+    }
+    let ranges = crate::utils::FunctionFinder::function_ranges(dwarf, unit, entry)?;
+    Ok(ranges.iter().map(|(start, _)| *start).min())
+}
+
+/// DIE が `pc` で有効か
+///
+/// lexical block はアドレス範囲に `pc` を含むときだけ、変数は DW_AT_start_scope があれば
+/// その位置に達してからだけ有効とします（宣言より前の未初期化の値を表示しないため）。
+/// 範囲の情報がない DIE は有効とみなします。
+fn live_at<R: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+    entry: &gimli::DebuggingInformationEntry<R>,
+    scope_start: Option<u64>,
+    pc: u64,
+) -> Result<bool> {
+    let contains = |ranges: &[(u64, u64)]| ranges.is_empty() || ranges.iter().any(|(start, end)| (*start..*end).contains(&pc));
+
+    if entry.tag() == gimli::DW_TAG_lexical_block {
+        return Ok(contains(&crate::utils::FunctionFinder::function_ranges(dwarf, unit, entry)?));
+    }
+
+    let Some(value) = entry.attr_value(gimli::DW_AT_start_scope)? else {
+        return Ok(true);
+    };
+    // DWARF 4 までは定数（スコープ先頭からのオフセット）、DWARF 5 は範囲リスト
+    if let Some(offset) = value.udata_value() {
+        return Ok(scope_start.is_none_or(|start| pc >= start.wrapping_add(offset)));
+    }
+    let Some(offset) = dwarf.attr_ranges_offset(unit, value)? else {
+        return Ok(true);
+    };
+    let mut ranges = Vec::new();
+    let mut iter = dwarf.ranges(unit, offset)?;
+    while let Some(range) = iter.next()? {
+        ranges.push((range.begin, range.end));
+    }
+    Ok(contains(&ranges))
+}
@@ -1,6 +1,6 @@
 //! Async locals（DWARF location evaluation）の統合テスト
 
-use kokia_dwarf::{DwarfLoader, VariableLocator, ValueDecoder, DecodeConfig};
+use kokia_dwarf::{DwarfLoader, LineInfoProvider, VariableLocator, ValueDecoder, DecodeConfig};
 
 #[test]
 fn test_variable_locator_creation() {
@@ -148,3 +148,57 @@ fn test_decode_config() {
 
     println!("✓ Custom DecodeConfig works correctly");
 }
+
+#[test]
+fn test_locals_filtered_by_lexical_block() {
+    let loader = DwarfLoader::load("../target/debug/simple_async")
+        .expect("Failed to load DWARF from simple_async binary");
+    let lines = LineInfoProvider::new(&loader);
+    let locator = VariableLocator::new(&loader);
+    let names_at = |line: u32| -> Vec<String> {
+        let pc = lines.find_address_by_file_line("simple_async/src/main.rs", line)
+            .unwrap()
+            .expect("line not found");
+        locator.get_locals(pc).unwrap().into_iter().map(|var| var.name).collect()
+    };
+
+    // test_variables_sync の `let message` より前では message はまだ有効でない
+    assert!(!names_at(58).contains(&"message".to_string()));
+
+    let names = names_at(65);
+    assert!(names.contains(&"message".to_string()));
+    assert!(names.contains(&"result_value".to_string()));
+}
+
+#[test]
+fn test_function_lookup_prefers_concrete_die() {
+    use kokia_dwarf::{CancelToken, FunctionFinder};
+
+    let loader = DwarfLoader::load("../target/debug/simple_async")
+        .expect("Failed to load DWARF from simple_async binary");
+    let pc = LineInfoProvider::new(&loader).find_address_by_file_line("simple_async/src/main.rs", 65)
+        .unwrap()
+        .expect("line not found");
+
+    let function = FunctionFinder::find_in_loader(&loader, pc, &CancelToken::new())
+        .unwrap()
+        .expect("no function at the line");
+    // 2 回目は覚えた結果を返す
+    assert_eq!(FunctionFinder::find_in_loader(&loader, pc, &CancelToken::new()).unwrap(), Some(function));
+
+    // 抽象 DIE ではなく、DW_AT_low_pc が PC を含む具象 DIE を選ぶ
+    let dwarf = loader.dwarf();
+    let unit = dwarf.unit(dwarf.debug_info.header_from_offset(function.unit).unwrap()).unwrap();
+    let entry = unit.entry(function.die).unwrap();
+    assert_eq!(entry.tag(), gimli::DW_TAG_subprogram);
+    assert!(entry.attr_value(gimli::DW_AT_inline).unwrap().is_none());
+    let low_pc = dwarf.attr_address(&unit, entry.attr_value(gimli::DW_AT_low_pc).unwrap().unwrap()).unwrap().unwrap();
+    assert!(low_pc <= pc);
+
+    // 同じ関数の変数を複数のユニットから重ねて返さない
+    let mut names: Vec<String> = VariableLocator::new(&loader).get_locals(pc).unwrap().into_iter().map(|var| var.name).collect();
+    let count = names.len();
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), count);
+}
@@ -50,6 +50,26 @@ fn test_load_simple_async() {
     }
 }
 
+#[test]
+fn test_reverse_resolve_stays_inside_symbols() {
+    let loader = DwarfLoader::load("../target/debug/simple_async")
+        .expect("Failed to load DWARF from simple_async binary");
+    let resolver = SymbolResolver::new(&loader)
+        .expect("Failed to create symbol resolver");
+
+    // 関数の先頭・末尾を引くと、そのアドレスを含み、その関数より広くない（内側が優先される）シンボルになる
+    let symbols: Vec<_> = resolver.find_symbols("simple_async").into_iter().filter(|s| s.size > 0).collect();
+    assert!(!symbols.is_empty());
+    for symbol in &symbols {
+        for addr in [symbol.address, symbol.address + symbol.size - 1] {
+            let resolved = resolver.reverse_resolve(addr)
+                .unwrap_or_else(|| panic!("0x{:x} in {} did not resolve", addr, symbol.name));
+            assert!(resolved.address <= addr && addr < resolved.address + resolved.size.max(1), "{} at 0x{:x}", resolved.name, addr);
+            assert!(resolved.size <= symbol.size, "{} is wider than {}", resolved.name, symbol.name);
+        }
+    }
+}
+
 #[test]
 fn test_find_poll_functions() {
     let binary_path = "../target/debug/simple_async";
@@ -70,3 +90,23 @@ fn test_find_poll_functions() {
 
     assert!(!poll_symbols.is_empty(), "Should find poll-related symbols");
 }
+
+#[test]
+fn test_find_thread_local_statics() {
+    use kokia_dwarf::{StaticLocation, VariableLocator};
+
+    let loader = DwarfLoader::load("../target/debug/simple_async")
+        .expect("Failed to load DWARF from simple_async binary");
+    let locator = VariableLocator::new(&loader);
+
+    // thread_local! は実体（__RUST_STD_INTERNAL_VAL）ではなく宣言した名前で引け、
+    // 使われていない実体はシンボルテーブルで除かれる
+    let found = locator.find_statics("runtime::context::CONTEXT").unwrap();
+    assert_eq!(found.len(), 1, "{:?}", found);
+    assert_eq!(found[0].path, "tokio::runtime::context::CONTEXT");
+    assert!(matches!(found[0].location, StaticLocation::ThreadLocal(_)));
+    assert!(found[0].type_ref.is_some());
+
+    // `::` の区切りの途中からの一致は数えない
+    assert!(locator.find_statics("TEXT").unwrap().is_empty());
+}
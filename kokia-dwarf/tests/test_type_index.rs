@@ -0,0 +1,72 @@
+//! 型索引と ptype レイアウトの統合テスト
+
+use kokia_dwarf::{DwarfLoader, TypeIndex, TypeInfo, TypeKind};
+
+#[test]
+fn test_lookup_async_fn_env() {
+    let loader = DwarfLoader::load("../target/debug/simple_async")
+        .expect("Failed to load DWARF from simple_async binary");
+    let index = TypeIndex::build(&loader).expect("Failed to build type index");
+    assert!(!index.is_empty());
+
+    // async fn のパスで状態機械の型を引ける
+    let env = index.lookup("simple_async::compute").expect("compute env type not found");
+    assert_eq!(env.qualified_name, "simple_async::compute::{async_fn_env#0}");
+
+    let layout = index.layout(&loader, env.type_ref, 1).unwrap();
+    println!("{}", layout);
+    assert_eq!(layout.kind, TypeKind::Struct);
+    assert!(layout.discriminant.is_some());
+
+    let names: Vec<&str> = layout.variants.iter().map(|v| v.name.as_str()).collect();
+    assert!(names.contains(&"Unresumed"));
+    assert!(names.contains(&"Suspend0"));
+
+    // suspend point の行は async fn の本体内を指す
+    let suspend = layout.variants.iter().find(|v| v.name == "Suspend0").unwrap();
+    assert!(suspend.decl_file.as_deref().is_some_and(|f| f.ends_with("main.rs")));
+    assert!(suspend.decl_line.is_some());
+
+    // 基本型は修飾なしで引ける
+    let i32_type = index.lookup("i32").expect("i32 not found");
+    let layout = index.layout(&loader, i32_type.type_ref, 0).unwrap();
+    assert_eq!(layout.kind, TypeKind::Base);
+    assert_eq!(layout.size, Some(4));
+}
+
+#[test]
+fn test_type_info_of_async_fn_env() {
+    let loader = DwarfLoader::load("../target/debug/simple_async")
+        .expect("Failed to load DWARF from simple_async binary");
+    let index = TypeIndex::build(&loader).expect("Failed to build type index");
+    let env = index.lookup("simple_async::compute").expect("compute env type not found");
+
+    // 状態機械は DW_TAG_variant_part を持つので enum として抽出される
+    let info = index.type_info(&loader, env.type_ref).unwrap();
+    let TypeInfo::Enum { discriminant, variants, .. } = info else {
+        panic!("expected an enum, got {:?}", info);
+    };
+    assert!(discriminant.is_some_and(|d| d.size > 0));
+    let unresumed = variants.iter().find(|v| v.name == "Unresumed").expect("Unresumed not found");
+    assert!(unresumed.discriminant.is_some());
+    assert!(variants.iter().any(|v| v.name == "Suspend0"));
+}
+
+#[test]
+fn test_function_parameters() {
+    let loader = DwarfLoader::load("../target/debug/simple_async")
+        .expect("Failed to load DWARF from simple_async binary");
+    let resolver = kokia_dwarf::SymbolResolver::new(&loader).expect("Failed to create symbol resolver");
+    let index = TypeIndex::build(&loader).expect("Failed to build type index");
+
+    // async fn compute(x: i32, y: i32) の Future を作る側の関数
+    let symbol = resolver.find_symbols("simple_async::compute").into_iter()
+        .find(|s| s.demangled_name.ends_with("simple_async::compute"))
+        .expect("compute not found");
+    let parameters = kokia_dwarf::function_parameters(&loader, symbol.address).unwrap();
+    let names: Vec<&str> = parameters.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, vec!["x", "y"]);
+    let layout = index.layout(&loader, parameters[0].1, 0).unwrap();
+    assert_eq!(layout.kind, TypeKind::Base);
+    assert_eq!(layout.size, Some(4));
+}
@@ -0,0 +1,128 @@
+//! デバッグ対象へのアクセスの抽象化
+//!
+//! ブレークポイントの管理やフレームポインタの巻き戻し、async の poll の出入りの追跡は、
+//! メモリと汎用レジスタの読み書きと実行の再開さえできれば動きます。それらを
+//! [`TargetMemory`] / [`TargetBackend`] 越しに使うようにしておくと、ptrace の代わりに
+//! [`crate::fake::FakeTarget`] を渡してプロセスを起動せずに単体テストできます。
+
+use crate::{Memory, Process, Registers, Result, StopReason, UserRegs};
+use std::rc::Rc;
+
+/// デバッグ対象のメモリ
+pub trait TargetMemory {
+    fn read_memory(&self, addr: u64, len: usize) -> Result<Vec<u8>>;
+
+    fn write_memory(&self, addr: u64, data: &[u8]) -> Result<()>;
+
+    /// アドレスがいずれかのマッピングに含まれるか
+    fn is_address_mapped(&self, addr: u64) -> Result<bool>;
+
+    /// リトルエンディアンの 8 バイトを読む
+    fn read_word(&self, addr: u64) -> Result<u64> {
+        let bytes = self.read_memory(addr, 8)?;
+        let bytes: [u8; 8] = bytes.as_slice().try_into()
+            .map_err(|_| anyhow::anyhow!("Short read at 0x{:x}", addr))?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// リトルエンディアンの `size` バイト（8 バイトまで）の符号なし整数を読む
+    fn read_uint(&self, addr: u64, size: usize) -> Result<u64> {
+        let bytes = self.read_memory(addr, size.min(8))?;
+        Ok(bytes.iter().rev().fold(0, |value, &byte| value << 8 | u64::from(byte)))
+    }
+}
+
+/// メモリに加えてレジスタと実行制御も扱えるデバッグ対象（選択中のスレッドについて）
+pub trait TargetBackend: TargetMemory {
+    /// 選択中のスレッド（停止の直後なら停止を報告したスレッド）
+    fn thread_id(&self) -> i32;
+
+    fn registers(&self) -> Result<UserRegs>;
+
+    fn set_registers(&self, regs: &UserRegs) -> Result<()>;
+
+    /// 実行を再開し、次に止まるまで待つ
+    fn resume(&self) -> Result<StopReason>;
+
+    /// 1命令だけ実行する
+    fn step(&self) -> Result<StopReason>;
+
+    fn pc(&self) -> Result<u64> {
+        Ok(self.registers()?.rip)
+    }
+
+    fn set_pc(&self, pc: u64) -> Result<()> {
+        let mut regs = self.registers()?;
+        regs.rip = pc;
+        self.set_registers(&regs)
+    }
+}
+
+impl TargetMemory for Memory {
+    fn read_memory(&self, addr: u64, len: usize) -> Result<Vec<u8>> {
+        self.read(addr as usize, len)
+    }
+
+    fn write_memory(&self, addr: u64, data: &[u8]) -> Result<()> {
+        self.write(addr as usize, data)
+    }
+
+    fn is_address_mapped(&self, addr: u64) -> Result<bool> {
+        self.is_mapped(addr as usize)
+    }
+}
+
+/// ptrace で制御しているプロセスの [`TargetBackend`]
+///
+/// プロセスはデバッガと共有し、レジスタと 1 命令の実行はプロセスの選択中のスレッドについて行います。
+pub struct PtraceBackend {
+    process: Rc<Process>,
+    memory: Memory,
+}
+
+impl PtraceBackend {
+    pub fn new(process: Rc<Process>) -> Self {
+        let memory = Memory::new(process.pid());
+        Self { process, memory }
+    }
+
+    fn current_registers(&self) -> Registers {
+        Registers::new(self.process.current_thread())
+    }
+}
+
+impl TargetMemory for PtraceBackend {
+    fn read_memory(&self, addr: u64, len: usize) -> Result<Vec<u8>> {
+        self.memory.read_memory(addr, len)
+    }
+
+    fn write_memory(&self, addr: u64, data: &[u8]) -> Result<()> {
+        self.memory.write_memory(addr, data)
+    }
+
+    fn is_address_mapped(&self, addr: u64) -> Result<bool> {
+        self.memory.is_address_mapped(addr)
+    }
+}
+
+impl TargetBackend for PtraceBackend {
+    fn thread_id(&self) -> i32 {
+        self.process.current_thread()
+    }
+
+    fn registers(&self) -> Result<UserRegs> {
+        self.current_registers().read()
+    }
+
+    fn set_registers(&self, regs: &UserRegs) -> Result<()> {
+        self.current_registers().write(*regs)
+    }
+
+    fn resume(&self) -> Result<StopReason> {
+        self.process.continue_and_wait()
+    }
+
+    fn step(&self) -> Result<StopReason> {
+        self.process.step()
+    }
+}
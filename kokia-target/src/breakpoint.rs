@@ -1,6 +1,6 @@
 //! ブレークポイント機能
 
-use crate::Result;
+use crate::{Result, TargetMemory};
 
 /// INT3命令のオペコード
 const INT3_OPCODE: u8 = 0xCC;
@@ -40,25 +40,25 @@ impl SoftwareBreakpoint {
     /// ブレークポイントを設定する
     ///
     /// 指定されたアドレスの命令を0xCC（INT3）で置き換えます。
-    pub fn enable(&mut self, memory: &crate::Memory) -> Result<()> {
+    pub fn enable(&mut self, memory: &dyn TargetMemory) -> Result<()> {
         if self.enabled {
             return Ok(());
         }
 
         // アドレスが有効なメモリマッピング内にあるか確認
-        if !memory.is_mapped(self.address as usize)? {
+        if !memory.is_address_mapped(self.address)? {
             return Err(anyhow::anyhow!(
                 "Cannot set breakpoint at 0x{:x}: address is not in a valid memory mapping",
                 self.address
             ));
         }
 
-        // 元のバイトを保存（PTRACE_PEEKDATAを使用）
-        let bytes = memory.read_via_ptrace(self.address as usize, 1)?;
+        // 元のバイトを保存
+        let bytes = memory.read_memory(self.address, 1)?;
         self.original_byte = bytes[0];
 
         // INT3命令で置き換え
-        memory.write_u8(self.address as usize, INT3_OPCODE)?;
+        memory.write_memory(self.address, &[INT3_OPCODE])?;
 
         self.enabled = true;
         Ok(())
@@ -67,29 +67,99 @@ impl SoftwareBreakpoint {
     /// ブレークポイントを解除する
     ///
     /// INT3命令を元のバイトで置き換えます。
-    pub fn disable(&mut self, memory: &crate::Memory) -> Result<()> {
+    pub fn disable(&mut self, memory: &dyn TargetMemory) -> Result<()> {
         if !self.enabled {
             return Ok(());
         }
 
         // 元のバイトで置き換え
-        memory.write_u8(self.address as usize, self.original_byte)?;
+        memory.write_memory(self.address, &[self.original_byte])?;
 
         self.enabled = false;
         Ok(())
     }
 }
 
-/// ハードウェアブレークポイント
+/// デバッグレジスタのアドレススロット数（DR0〜DR3）
+pub const HW_BREAKPOINT_SLOTS: usize = 4;
+
+/// DR6 の B0〜B3（どのスロットが発火したか）
+const DR6_TRAP_MASK: u64 = 0xf;
+
+/// DR6 の BS（シングルステップによるトラップ）
+const DR6_SINGLE_STEP: u64 = 1 << 14;
+
+/// ハードウェアブレークポイントが反応するアクセス
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    /// 命令の実行
+    Execute,
+    /// 書き込み
+    Write,
+    /// 読み取りまたは書き込み（x86 は読み取りだけの監視を持たない）
+    ReadWrite,
+}
+
+impl WatchKind {
+    /// DR7 の R/W フィールドの値
+    fn rw_bits(self) -> u64 {
+        match self {
+            WatchKind::Execute => 0b00,
+            WatchKind::Write => 0b01,
+            WatchKind::ReadWrite => 0b11,
+        }
+    }
+}
+
+/// DR6（デバッグステータス）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebugStatus(pub u64);
+
+impl DebugStatus {
+    /// 発火したスロット（DR0〜DR3 の番号）
+    pub fn triggered(self) -> impl Iterator<Item = usize> {
+        (0..HW_BREAKPOINT_SLOTS).filter(move |slot| self.0 & DR6_TRAP_MASK & (1 << slot) != 0)
+    }
+
+    /// シングルステップによるトラップか
+    pub fn single_step(&self) -> bool {
+        self.0 & DR6_SINGLE_STEP != 0
+    }
+}
+
+/// ハードウェアブレークポイント（デバッグレジスタ DR0〜DR3 の1スロット）
+///
+/// デバッグレジスタはスレッドごとの状態なので、有効化・無効化はスレッドごとに行います。
 pub struct HardwareBreakpoint {
     address: u64,
     index: usize,
+    kind: WatchKind,
+    len: usize,
 }
 
 impl HardwareBreakpoint {
-    /// ハードウェアブレークポイントを作成する
+    /// 命令実行で発火するハードウェアブレークポイントを作成する
     pub fn new(address: u64, index: usize) -> Self {
-        Self { address, index }
+        Self { address, index, kind: WatchKind::Execute, len: 1 }
+    }
+
+    /// メモリアクセスで発火するハードウェアブレークポイント（ウォッチポイント）を作成する
+    ///
+    /// 長さは 1/2/4/8 バイトで、アドレスは長さに揃っている必要があります。
+    pub fn watch(address: u64, index: usize, kind: WatchKind, len: usize) -> Result<Self> {
+        if index >= HW_BREAKPOINT_SLOTS {
+            return Err(anyhow::anyhow!("Invalid debug register slot: {}", index));
+        }
+        if !matches!(len, 1 | 2 | 4 | 8) {
+            return Err(anyhow::anyhow!("Watch length must be 1, 2, 4 or 8 bytes (got {})", len));
+        }
+        if !address.is_multiple_of(len as u64) {
+            return Err(anyhow::anyhow!("Address 0x{:x} is not aligned to the watch length {}", address, len));
+        }
+        if kind == WatchKind::Execute && len != 1 {
+            return Err(anyhow::anyhow!("Execution breakpoints must have length 1"));
+        }
+        Ok(Self { address, index, kind, len })
     }
 
     /// ブレークポイントのアドレスを取得する
@@ -101,4 +171,67 @@ impl HardwareBreakpoint {
     pub fn index(&self) -> usize {
         self.index
     }
+
+    /// 反応するアクセス
+    pub fn kind(&self) -> WatchKind {
+        self.kind
+    }
+
+    /// 監視する長さ（バイト）
+    pub fn size(&self) -> usize {
+        self.len
+    }
+
+    /// DR7 のうち、このスロットが使うビット
+    fn dr7_mask(&self) -> u64 {
+        (0b11 << (self.index * 2)) | (0b1111 << (16 + self.index * 4))
+    }
+
+    /// DR7 のうち、このスロットを有効にする値
+    pub fn dr7_bits(&self) -> u64 {
+        // LEN は 1→00, 2→01, 8→10, 4→11
+        let len_bits = match self.len {
+            1 => 0b00,
+            2 => 0b01,
+            8 => 0b10,
+            _ => 0b11,
+        };
+        let control = self.kind.rw_bits() | (len_bits << 2);
+        (1 << (self.index * 2)) | (control << (16 + self.index * 4))
+    }
+
+    /// スレッドのデバッグレジスタに設定する
+    pub fn enable(&self, registers: &crate::Registers) -> Result<()> {
+        registers.write_debug_reg(self.index, self.address)?;
+        let dr7 = registers.read_debug_reg(7)?;
+        registers.write_debug_reg(7, (dr7 & !self.dr7_mask()) | self.dr7_bits())
+    }
+
+    /// スレッドのデバッグレジスタから外す
+    pub fn disable(&self, registers: &crate::Registers) -> Result<()> {
+        let dr7 = registers.read_debug_reg(7)?;
+        registers.write_debug_reg(7, dr7 & !self.dr7_mask())?;
+        registers.write_debug_reg(self.index, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hardware_watch_dr7_encoding() {
+        let bp = HardwareBreakpoint::watch(0x1000, 1, WatchKind::Write, 8).unwrap();
+        // L1 と、スロット1の R/W=01, LEN=10
+        assert_eq!(bp.dr7_bits(), (1 << 2) | (0b1001 << 20));
+        assert_eq!(bp.dr7_mask(), (0b11 << 2) | (0b1111 << 20));
+
+        assert!(HardwareBreakpoint::watch(0x1004, 0, WatchKind::Write, 8).is_err());
+        assert!(HardwareBreakpoint::watch(0x1000, 0, WatchKind::Write, 3).is_err());
+        assert!(HardwareBreakpoint::watch(0x1000, 4, WatchKind::ReadWrite, 4).is_err());
+
+        let status = DebugStatus(0xffff0ff2 | DR6_SINGLE_STEP);
+        assert_eq!(status.triggered().collect::<Vec<_>>(), vec![1]);
+        assert!(status.single_step());
+    }
 }
@@ -0,0 +1,82 @@
+//! 孤児プロセス対策
+//!
+//! kokia 自身が panic や SIGTERM で終了した場合に、起動したデバッグ対象プロセスが
+//! INT3 を埋め込まれたまま放置されないよう、登録済みのプロセスを強制終了する。
+//!
+//! シグナルハンドラから参照するため、登録表はロックを使わない固定長のアトミック配列で持つ。
+
+use nix::libc;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+/// 同時に登録できるデバッグ対象プロセスの最大数
+const MAX_INFERIORS: usize = 64;
+
+/// 登録済みプロセスのPID（0 は空きスロット）
+static INFERIORS: [AtomicI32; MAX_INFERIORS] = [const { AtomicI32::new(0) }; MAX_INFERIORS];
+
+/// ハンドラ設置済みフラグ
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// 終了時に強制終了するプロセスとして登録する
+///
+/// 登録表が満杯の場合は何もしない。
+pub fn register_inferior(pid: i32) {
+    for slot in INFERIORS.iter() {
+        if slot.compare_exchange(0, pid, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+            return;
+        }
+    }
+}
+
+/// 登録を解除する
+pub fn unregister_inferior(pid: i32) {
+    for slot in INFERIORS.iter() {
+        let _ = slot.compare_exchange(pid, 0, Ordering::SeqCst, Ordering::SeqCst);
+    }
+}
+
+/// 登録済みのプロセスをすべて SIGKILL で終了させる
+///
+/// シグナルハンドラからも呼ばれるため、async-signal-safe な処理のみを行う。
+pub fn kill_registered_inferiors() {
+    for slot in INFERIORS.iter() {
+        let pid = slot.swap(0, Ordering::SeqCst);
+        if pid > 0 {
+            unsafe {
+                libc::kill(pid, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+/// SIGTERM/SIGHUP 受信時のハンドラ
+extern "C" fn on_terminate(sig: libc::c_int) {
+    kill_registered_inferiors();
+    // デフォルト動作に戻して再送し、本来の終了ステータスで終了する
+    unsafe {
+        libc::signal(sig, libc::SIG_DFL);
+        libc::raise(sig);
+    }
+}
+
+/// panic フックとシグナルハンドラを設置する
+///
+/// 何度呼んでも設置は一度だけ行われる。既存の panic フックは保持され、
+/// プロセスの後始末の後に呼び出される。
+pub fn install_cleanup_handlers() {
+    if INSTALLED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        kill_registered_inferiors();
+        previous(info);
+    }));
+
+    let handler = on_terminate as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGHUP, handler);
+    }
+}
@@ -0,0 +1,339 @@
+//! コアファイルの書き出し（gcore 相当）
+//!
+//! 停止中のプロセスのメモリマッピングとスレッドのレジスタから、gdb などで読める ELF コア
+//! ファイルを組み立てます。ノートには PRSTATUS / FPREGSET（スレッドごと）、PRPSINFO、AUXV、
+//! FILE を入れ、読めるマッピングはすべて PT_LOAD として内容ごと書き出します。
+
+use crate::{Memory, MemoryMapping, Registers, Result, UserRegs};
+use nix::libc;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const PAGE_SIZE: u64 = 4096;
+
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_FPREGSET: u32 = 2;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_FILE: u32 = 0x4649_4c45;
+
+/// `struct elf_prstatus` 内の pr_reg のオフセットと構造体のサイズ（x86_64）
+const PRSTATUS_REG_OFFSET: usize = 112;
+const PRSTATUS_SIZE: usize = 336;
+
+/// `struct elf_prpsinfo` のサイズ（x86_64）
+const PRPSINFO_SIZE: usize = 136;
+
+/// 内容の読み出しを分割する単位
+const COPY_CHUNK: usize = 1 << 20;
+
+/// 書き出したコアファイルの概要
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoreSummary {
+    /// レジスタを書き出したスレッドの数
+    pub threads: usize,
+    /// PT_LOAD セグメントの数
+    pub segments: usize,
+    /// ファイルサイズ（バイト）
+    pub bytes: u64,
+    /// 読めずに 0 で埋めたバイト数
+    pub unreadable: u64,
+}
+
+/// コアファイルに書き出すスレッドの状態
+struct ThreadState {
+    tid: i32,
+    regs: UserRegs,
+    fpregs: Option<libc::user_fpregs_struct>,
+}
+
+/// 停止中のプロセスのコアファイルを書き出す
+///
+/// `threads` の先頭のスレッドを、シグナル `signal` を受けたスレッドとして記録します。
+/// レジスタを読めないスレッド（ptrace で止めていないスレッドなど）は省きます。
+pub fn write_core(
+    path: &Path,
+    pid: i32,
+    memory: &Memory,
+    threads: &[i32],
+    signal: Option<i32>,
+) -> Result<CoreSummary> {
+    let states: Vec<ThreadState> = threads
+        .iter()
+        .filter_map(|&tid| {
+            let registers = Registers::new(tid);
+            let regs = registers.read().ok()?;
+            Some(ThreadState { tid, regs, fpregs: registers.read_fpregs().ok() })
+        })
+        .collect();
+    if states.is_empty() {
+        return Err(anyhow::anyhow!("Could not read the registers of any thread"));
+    }
+
+    // [vvar] はカーネルの内部ページで、/proc/pid/mem からも読めない
+    let mappings: Vec<MemoryMapping> = memory
+        .get_mappings()?
+        .into_iter()
+        .filter(|m| !matches!(m.path.as_deref(), Some("[vvar]" | "[vvar_vclock]" | "[vsyscall]")))
+        .collect();
+
+    let notes = build_notes(pid, &states, &mappings, signal);
+    let phnum = mappings.len() + 1;
+    let notes_offset = ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE * phnum;
+    let data_offset = align_up((notes_offset + notes.len()) as u64, PAGE_SIZE);
+
+    let file = File::create(path)
+        .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path.display(), e))?;
+    let mut out = BufWriter::new(file);
+
+    out.write_all(&elf_header(phnum as u16))?;
+    out.write_all(&program_header(PT_NOTE, 0, notes_offset as u64, 0, notes.len() as u64, 0, 1))?;
+    let mut offset = data_offset;
+    for mapping in &mappings {
+        let size = (mapping.end - mapping.start) as u64;
+        let file_size = if mapping.readable { size } else { 0 };
+        let mut flags = 0;
+        if mapping.readable { flags |= PF_R; }
+        if mapping.writable { flags |= PF_W; }
+        if mapping.executable { flags |= PF_X; }
+        out.write_all(&program_header(PT_LOAD, flags, offset, mapping.start as u64, file_size, size, PAGE_SIZE))?;
+        offset += file_size;
+    }
+    out.write_all(&notes)?;
+
+    out.seek(SeekFrom::Start(data_offset))?;
+    let mut unreadable = 0u64;
+    for mapping in mappings.iter().filter(|m| m.readable) {
+        let mut addr = mapping.start;
+        while addr < mapping.end {
+            let len = (mapping.end - addr).min(COPY_CHUNK);
+            match memory.read_partial(addr, len) {
+                Ok(chunk) => {
+                    unreadable += chunk.holes.iter().map(|h| h.len() as u64).sum::<u64>();
+                    out.write_all(&chunk.data)?;
+                }
+                Err(_) => {
+                    unreadable += len as u64;
+                    out.write_all(&vec![0u8; len])?;
+                }
+            }
+            addr += len;
+        }
+    }
+    out.flush()?;
+
+    Ok(CoreSummary {
+        threads: states.len(),
+        segments: mappings.len(),
+        bytes: offset,
+        unreadable,
+    })
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    value.div_ceil(align) * align
+}
+
+fn elf_header(phnum: u16) -> Vec<u8> {
+    let mut h = Vec::with_capacity(ELF_HEADER_SIZE);
+    h.extend_from_slice(b"\x7fELF");
+    h.extend_from_slice(&[2, 1, 1, 0]); // ELFCLASS64, little endian, EV_CURRENT, SYSV
+    h.extend_from_slice(&[0; 8]);
+    h.extend_from_slice(&ET_CORE.to_le_bytes());
+    h.extend_from_slice(&EM_X86_64.to_le_bytes());
+    h.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    h.extend_from_slice(&0u64.to_le_bytes()); // e_entry
+    h.extend_from_slice(&(ELF_HEADER_SIZE as u64).to_le_bytes()); // e_phoff
+    h.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    h.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    h.extend_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes());
+    h.extend_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+    h.extend_from_slice(&phnum.to_le_bytes());
+    h.extend_from_slice(&[0; 6]); // e_shentsize, e_shnum, e_shstrndx
+    h
+}
+
+fn program_header(p_type: u32, flags: u32, offset: u64, vaddr: u64, filesz: u64, memsz: u64, align: u64) -> Vec<u8> {
+    let mut h = Vec::with_capacity(PROGRAM_HEADER_SIZE);
+    h.extend_from_slice(&p_type.to_le_bytes());
+    h.extend_from_slice(&flags.to_le_bytes());
+    for value in [offset, vaddr, 0, filesz, memsz, align] {
+        h.extend_from_slice(&value.to_le_bytes());
+    }
+    h
+}
+
+/// ノート1つ（名前 "CORE"、4 バイト境界に揃える）を追加する
+fn push_note(notes: &mut Vec<u8>, n_type: u32, desc: &[u8]) {
+    const NAME: &[u8] = b"CORE\0";
+    notes.extend_from_slice(&(NAME.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&n_type.to_le_bytes());
+    notes.extend_from_slice(NAME);
+    notes.resize(align_up(notes.len() as u64, 4) as usize, 0);
+    notes.extend_from_slice(desc);
+    notes.resize(align_up(notes.len() as u64, 4) as usize, 0);
+}
+
+fn build_notes(pid: i32, states: &[ThreadState], mappings: &[MemoryMapping], signal: Option<i32>) -> Vec<u8> {
+    let mut notes = Vec::new();
+    for (i, state) in states.iter().enumerate() {
+        // シグナルは先頭（停止の原因になった）スレッドにだけ付ける
+        let signal = if i == 0 { signal.unwrap_or(0) } else { 0 };
+        push_note(&mut notes, NT_PRSTATUS, &prstatus(pid, state, signal));
+        if i == 0 {
+            push_note(&mut notes, NT_PRPSINFO, &prpsinfo(pid));
+            if let Ok(auxv) = std::fs::read(format!("/proc/{}/auxv", pid)) {
+                push_note(&mut notes, NT_AUXV, &auxv);
+            }
+            push_note(&mut notes, NT_FILE, &file_note(mappings));
+        }
+        if let Some(fpregs) = &state.fpregs {
+            // user_fpregs_struct は整数の配列だけからなる repr(C) 構造体
+            let bytes = unsafe {
+                std::slice::from_raw_parts(
+                    (fpregs as *const libc::user_fpregs_struct).cast::<u8>(),
+                    std::mem::size_of::<libc::user_fpregs_struct>(),
+                )
+            };
+            push_note(&mut notes, NT_FPREGSET, bytes);
+        }
+    }
+    notes
+}
+
+/// `struct elf_prstatus`
+fn prstatus(pid: i32, state: &ThreadState, signal: i32) -> Vec<u8> {
+    let mut desc = vec![0u8; PRSTATUS_SIZE];
+    desc[0..4].copy_from_slice(&signal.to_le_bytes()); // pr_info.si_signo
+    desc[12..14].copy_from_slice(&(signal as i16).to_le_bytes()); // pr_cursig
+    desc[32..36].copy_from_slice(&state.tid.to_le_bytes()); // pr_pid
+    desc[40..44].copy_from_slice(&pid.to_le_bytes()); // pr_pgrp（プロセスの代表として pid を入れる）
+
+    let r = &state.regs;
+    let gregs = [
+        r.r15, r.r14, r.r13, r.r12, r.rbp, r.rbx, r.r11, r.r10, r.r9, r.r8, r.rax, r.rcx, r.rdx, r.rsi, r.rdi,
+        r.orig_rax, r.rip, r.cs, r.eflags, r.rsp, r.ss, r.fs_base, r.gs_base, r.ds, r.es, r.fs, r.gs,
+    ];
+    for (i, value) in gregs.iter().enumerate() {
+        let at = PRSTATUS_REG_OFFSET + i * 8;
+        desc[at..at + 8].copy_from_slice(&value.to_le_bytes());
+    }
+    let fpvalid = PRSTATUS_REG_OFFSET + gregs.len() * 8;
+    desc[fpvalid..fpvalid + 4].copy_from_slice(&(state.fpregs.is_some() as i32).to_le_bytes());
+    desc
+}
+
+/// `struct elf_prpsinfo`（実行ファイル名とコマンドライン）
+fn prpsinfo(pid: i32) -> Vec<u8> {
+    let mut desc = vec![0u8; PRPSINFO_SIZE];
+    desc[0] = b't'; // pr_state: tracing stop
+    desc[1] = b't';
+    desc[24..28].copy_from_slice(&pid.to_le_bytes());
+
+    let comm = std::fs::read_to_string(format!("/proc/{}/comm", pid)).unwrap_or_default();
+    let comm = comm.trim_end().as_bytes();
+    let len = comm.len().min(15);
+    desc[40..40 + len].copy_from_slice(&comm[..len]);
+
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+    let args: Vec<u8> = cmdline.iter().map(|&b| if b == 0 { b' ' } else { b }).collect();
+    let args = args.trim_ascii_end();
+    let len = args.len().min(79);
+    desc[56..56 + len].copy_from_slice(&args[..len]);
+    desc
+}
+
+/// NT_FILE（ファイルをマップした範囲と、ファイル内のページオフセット）
+fn file_note(mappings: &[MemoryMapping]) -> Vec<u8> {
+    let files: Vec<&MemoryMapping> = mappings
+        .iter()
+        .filter(|m| m.path.as_deref().is_some_and(|p| p.starts_with('/')))
+        .collect();
+
+    let mut desc = Vec::new();
+    desc.extend_from_slice(&(files.len() as u64).to_le_bytes());
+    desc.extend_from_slice(&PAGE_SIZE.to_le_bytes());
+    for m in &files {
+        for value in [m.start as u64, m.end as u64, m.offset as u64 / PAGE_SIZE] {
+            desc.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    for m in &files {
+        desc.extend_from_slice(m.path.as_deref().unwrap_or_default().as_bytes());
+        desc.push(0);
+    }
+    desc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(data: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(data[at..at + 2].try_into().unwrap())
+    }
+
+    fn u64_at(data: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(data[at..at + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_write_core_of_stopped_child() {
+        use nix::sys::{ptrace, wait::waitpid};
+        use nix::unistd::{fork, ForkResult};
+
+        static MARKER: [u8; 16] = *b"kokia-core-test!";
+
+        match unsafe { fork() }.unwrap() {
+            ForkResult::Child => unsafe {
+                libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0);
+                libc::raise(libc::SIGSTOP);
+                libc::_exit(0);
+            },
+            ForkResult::Parent { child } => {
+                waitpid(child, None).unwrap();
+                let pid = child.as_raw();
+                let path = std::env::temp_dir().join(format!("kokia-core-test.{}", pid));
+
+                let summary = write_core(&path, pid, &Memory::new(pid), &[pid], Some(libc::SIGSTOP)).unwrap();
+                let _ = ptrace::kill(child);
+                let _ = waitpid(child, None);
+                let data = std::fs::read(&path).unwrap();
+                let _ = std::fs::remove_file(&path);
+
+                assert_eq!(summary.threads, 1);
+                assert_eq!(data.len() as u64, summary.bytes);
+                assert_eq!(&data[..4], b"\x7fELF");
+                assert_eq!(u16_at(&data, 16), ET_CORE);
+                let phnum = u16_at(&data, 56) as usize;
+                assert_eq!(phnum, summary.segments + 1);
+
+                // MARKER を含む PT_LOAD から元のバイト列が読める
+                let marker = MARKER.as_ptr() as u64;
+                let found = (1..phnum).any(|i| {
+                    let ph = ELF_HEADER_SIZE + i * PROGRAM_HEADER_SIZE;
+                    let (offset, vaddr, filesz) = (u64_at(&data, ph + 8), u64_at(&data, ph + 16), u64_at(&data, ph + 32));
+                    if vaddr <= marker && marker + 16 <= vaddr + filesz {
+                        let at = (offset + marker - vaddr) as usize;
+                        &data[at..at + 16] == MARKER.as_slice()
+                    } else {
+                        false
+                    }
+                });
+                assert!(found);
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! 実行環境の診断
+//!
+//! ptrace が権限エラーで失敗したときに、Yama LSM の設定やケーパビリティを調べて
+//! 利用者が次に何をすればよいかを示すメッセージを組み立てます。
+
+use std::fmt;
+
+/// Yama の ptrace_scope 設定ファイル
+const PTRACE_SCOPE_PATH: &str = "/proc/sys/kernel/yama/ptrace_scope";
+
+/// CAP_SYS_PTRACE のビット番号
+const CAP_SYS_PTRACE: u32 = 19;
+
+/// Yama LSM の ptrace 制限レベル
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PtraceScope {
+    /// 0: 同一 uid のプロセスにアタッチ可能
+    Classic,
+    /// 1: 子孫プロセス（または PR_SET_PTRACER で許可された相手）のみ
+    Restricted,
+    /// 2: CAP_SYS_PTRACE を持つプロセスのみ
+    AdminOnly,
+    /// 3: アタッチ不可（再起動まで変更できない）
+    NoAttach,
+}
+
+impl PtraceScope {
+    /// ptrace_scope の値をパースする
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "0" => Some(Self::Classic),
+            "1" => Some(Self::Restricted),
+            "2" => Some(Self::AdminOnly),
+            "3" => Some(Self::NoAttach),
+            _ => None,
+        }
+    }
+
+    /// 現在の設定を読み取る（Yama が無効なら None）
+    pub fn current() -> Option<Self> {
+        std::fs::read_to_string(PTRACE_SCOPE_PATH)
+            .ok()
+            .and_then(|s| Self::parse(&s))
+    }
+
+    fn level(self) -> u8 {
+        match self {
+            Self::Classic => 0,
+            Self::Restricted => 1,
+            Self::AdminOnly => 2,
+            Self::NoAttach => 3,
+        }
+    }
+}
+
+/// ptrace に関わる実行環境の状態
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PtraceEnvironment {
+    /// Yama の設定（Yama が無効、または読めない場合は None）
+    pub scope: Option<PtraceScope>,
+    /// 自プロセスが CAP_SYS_PTRACE を持っているか（判定できない場合は None）
+    pub has_cap_sys_ptrace: Option<bool>,
+    /// 実効 uid が root か
+    pub is_root: bool,
+}
+
+impl PtraceEnvironment {
+    /// 自プロセスの環境を調べる
+    pub fn detect() -> Self {
+        let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+        Self {
+            scope: PtraceScope::current(),
+            has_cap_sys_ptrace: parse_cap_eff(&status).map(|caps| caps & (1 << CAP_SYS_PTRACE) != 0),
+            is_root: parse_euid(&status) == Some(0),
+        }
+    }
+
+    /// アタッチ失敗の原因と対処法を列挙する
+    pub fn attach_hints(&self) -> Vec<String> {
+        let privileged = self.is_root || self.has_cap_sys_ptrace == Some(true);
+        let mut hints = Vec::new();
+
+        match self.scope {
+            Some(PtraceScope::NoAttach) => {
+                hints.push(
+                    "kernel.yama.ptrace_scope is 3: ptrace attach is disabled until reboot".to_string(),
+                );
+                return hints;
+            }
+            Some(PtraceScope::AdminOnly) if !privileged => hints.push(
+                "kernel.yama.ptrace_scope is 2: only processes with CAP_SYS_PTRACE may attach".to_string(),
+            ),
+            Some(PtraceScope::Restricted) if !privileged => hints.push(
+                "kernel.yama.ptrace_scope is 1: only descendants of the debugger may be attached".to_string(),
+            ),
+            _ => {}
+        }
+
+        if !privileged {
+            hints.push("run kokia with sudo, or grant the capability: sudo setcap cap_sys_ptrace=eip $(which kokia)".to_string());
+            if matches!(self.scope, Some(PtraceScope::Restricted)) {
+                hints.push("or relax Yama: echo 0 | sudo tee /proc/sys/kernel/yama/ptrace_scope".to_string());
+            }
+        } else {
+            hints.push(
+                "the target may already be traced (check TracerPid in /proc/<pid>/status) or be a kernel thread"
+                    .to_string(),
+            );
+        }
+        hints
+    }
+}
+
+impl fmt::Display for PtraceEnvironment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.scope {
+            Some(scope) => write!(f, "ptrace_scope={}", scope.level())?,
+            None => write!(f, "ptrace_scope=n/a")?,
+        }
+        match self.has_cap_sys_ptrace {
+            Some(cap) => write!(f, ", CAP_SYS_PTRACE={}", if cap { "yes" } else { "no" })?,
+            None => write!(f, ", CAP_SYS_PTRACE=unknown")?,
+        }
+        write!(f, ", euid={}", if self.is_root { "root" } else { "non-root" })
+    }
+}
+
+/// ptrace のアタッチ失敗を説明するエラーを作る
+///
+/// EPERM の場合だけ環境を調べ、それ以外のエラーはそのまま返します。
+pub fn explain_attach_error(pid: i32, errno: nix::errno::Errno) -> anyhow::Error {
+    if errno != nix::errno::Errno::EPERM {
+        return anyhow::anyhow!("Failed to attach to process {}: {}", pid, errno);
+    }
+    let env = PtraceEnvironment::detect();
+    let mut message = format!("Permission denied attaching to process {} ({})", pid, env);
+    for hint in env.attach_hints() {
+        message.push_str("\n  hint: ");
+        message.push_str(&hint);
+    }
+    anyhow::anyhow!(message)
+}
+
+/// fork した子プロセスを PTRACE_TRACEME で停止させ、ptrace がこの環境で使えるか確かめる
+///
+/// コンテナの seccomp プロファイルなどで ptrace システムコール自体が禁止されていると、
+/// Yama の設定に関係なく失敗します。
+pub fn probe_ptrace() -> crate::Result<()> {
+    use nix::sys::signal::{kill, raise, Signal};
+    use nix::sys::wait::{waitpid, WaitStatus};
+    use nix::unistd::{fork, ForkResult};
+
+    match unsafe { fork()? } {
+        ForkResult::Child => {
+            // fork 後の子では exec するまで最低限のことしかしない
+            let code = match nix::sys::ptrace::traceme() {
+                Ok(()) => raise(Signal::SIGSTOP).map_or(2, |_| 0),
+                Err(_) => 1,
+            };
+            unsafe { nix::libc::_exit(code) }
+        }
+        ForkResult::Parent { child } => {
+            let result = match waitpid(child, None)? {
+                WaitStatus::Stopped(_, Signal::SIGSTOP) => nix::sys::ptrace::getregs(child)
+                    .map(|_| ())
+                    .map_err(|errno| anyhow::anyhow!("PTRACE_GETREGS on a traced child failed: {}", errno)),
+                WaitStatus::Exited(_, 1) => Err(anyhow::anyhow!(
+                    "PTRACE_TRACEME failed (ptrace may be blocked by seccomp, e.g. a container's default profile)"
+                )),
+                status => Err(anyhow::anyhow!("Unexpected wait status of the test child: {:?}", status)),
+            };
+            let _ = kill(child, Signal::SIGKILL);
+            let _ = waitpid(child, None);
+            result
+        }
+    }
+}
+
+/// /proc/<pid>/status の CapEff 行をパースする
+fn parse_cap_eff(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|value| u64::from_str_radix(value.trim(), 16).ok())
+}
+
+/// /proc/<pid>/status の Uid 行から実効 uid を取り出す
+fn parse_euid(status: &str) -> Option<u32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))
+        .and_then(|value| value.split_whitespace().nth(1)?.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scope_and_caps() {
+        assert_eq!(PtraceScope::parse("1\n"), Some(PtraceScope::Restricted));
+        assert_eq!(PtraceScope::parse("7"), None);
+        let status = "Name:\tkokia\nUid:\t1000\t0\t1000\t1000\nCapEff:\t0000000000080000\n";
+        assert_eq!(parse_cap_eff(status), Some(1 << CAP_SYS_PTRACE));
+        assert_eq!(parse_euid(status), Some(0));
+        assert_eq!(parse_cap_eff("Name:\tkokia\n"), None);
+    }
+
+    #[test]
+    fn test_attach_hints() {
+        let unprivileged = PtraceEnvironment {
+            scope: Some(PtraceScope::Restricted),
+            has_cap_sys_ptrace: Some(false),
+            is_root: false,
+        };
+        let hints = unprivileged.attach_hints();
+        assert!(hints.iter().any(|h| h.contains("sudo setcap")));
+        assert!(hints.iter().any(|h| h.contains("ptrace_scope")));
+
+        let locked = PtraceEnvironment { scope: Some(PtraceScope::NoAttach), is_root: true, ..unprivileged };
+        assert_eq!(locked.attach_hints().len(), 1);
+        assert_eq!(locked.to_string(), "ptrace_scope=3, CAP_SYS_PTRACE=no, euid=root");
+    }
+}
@@ -0,0 +1,317 @@
+//! デバッグ対象のメモリ上にマップされた ELF イメージの解析
+//!
+//! ディスク上のバイナリが実行中のものと一致しない（再デプロイ後など）場合に備え、
+//! ELF ヘッダ・プログラムヘッダ・ノート・動的シンボルテーブルをターゲットのメモリから直接読み取ります。
+//! セクションヘッダや `.symtab` は通常マップされないため、読めるのは `.dynsym` までです。
+
+use crate::{Memory, Result};
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_NOTE: u32 = 4;
+
+const ET_DYN: u16 = 3;
+
+const DT_NULL: u64 = 0;
+const DT_HASH: u64 = 4;
+const DT_STRTAB: u64 = 5;
+const DT_SYMTAB: u64 = 6;
+const DT_STRSZ: u64 = 10;
+const DT_GNU_HASH: u64 = 0x6fff_fef5;
+
+const NT_GNU_BUILD_ID: u32 = 3;
+
+const ELF64_EHDR_SIZE: usize = 64;
+const ELF64_PHDR_SIZE: usize = 56;
+const ELF64_SYM_SIZE: usize = 24;
+
+/// 動的シンボルテーブルから読む最大シンボル数（壊れたハッシュテーブル対策）
+const MAX_DYNAMIC_SYMBOLS: usize = 1 << 20;
+
+/// プログラムヘッダ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramHeader {
+    pub p_type: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+    pub align: u64,
+}
+
+/// メモリ上から読み取った動的シンボル
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageSymbol {
+    pub name: String,
+    /// ELF 上の仮想アドレス（ロードバイアスを含まない）
+    pub address: u64,
+    pub size: u64,
+}
+
+/// メモリ上にマップされた ELF イメージ
+#[derive(Debug, Clone)]
+pub struct MappedImage {
+    /// ファイル先頭がマップされたアドレス
+    base: u64,
+    /// ELF タイプ（e_type）
+    elf_type: u16,
+    program_headers: Vec<ProgramHeader>,
+}
+
+impl MappedImage {
+    /// `base`（ファイル先頭がマップされたアドレス）から ELF ヘッダとプログラムヘッダを読み取る
+    pub fn read(memory: &Memory, base: u64) -> Result<Self> {
+        let ehdr = memory.read(base as usize, ELF64_EHDR_SIZE)?;
+        if &ehdr[..4] != b"\x7fELF" {
+            return Err(anyhow::anyhow!("No ELF header at 0x{:x}", base));
+        }
+        // ELFCLASS64 / ELFDATA2LSB のみ対応
+        if ehdr[4] != 2 || ehdr[5] != 1 {
+            return Err(anyhow::anyhow!("Unsupported ELF class/encoding at 0x{:x}", base));
+        }
+
+        let elf_type = u16_at(&ehdr, 16);
+        let phoff = u64_at(&ehdr, 32);
+        let phentsize = u16_at(&ehdr, 54) as usize;
+        let phnum = u16_at(&ehdr, 56) as usize;
+        if phentsize != ELF64_PHDR_SIZE {
+            return Err(anyhow::anyhow!("Unexpected program header size {}", phentsize));
+        }
+
+        let table = memory.read((base + phoff) as usize, phnum * ELF64_PHDR_SIZE)?;
+        let program_headers = table.chunks_exact(ELF64_PHDR_SIZE).map(parse_phdr).collect();
+
+        Ok(Self { base, elf_type, program_headers })
+    }
+
+    /// プログラムヘッダ一覧
+    pub fn program_headers(&self) -> &[ProgramHeader] {
+        &self.program_headers
+    }
+
+    /// 位置独立（ET_DYN）イメージか
+    pub fn is_pie(&self) -> bool {
+        self.elf_type == ET_DYN
+    }
+
+    /// ロードバイアス（ELF の仮想アドレスに加算すると実行時アドレスになる値）
+    pub fn load_bias(&self) -> u64 {
+        let first_load_page = self
+            .program_headers
+            .iter()
+            .find(|ph| ph.p_type == PT_LOAD)
+            .map_or(0, |ph| ph.vaddr & !ph.align.saturating_sub(1));
+        self.base.wrapping_sub(first_load_page)
+    }
+
+    /// PT_NOTE から GNU ビルドIDを読み取る
+    pub fn build_id(&self, memory: &Memory) -> Result<Option<Vec<u8>>> {
+        for ph in self.program_headers.iter().filter(|ph| ph.p_type == PT_NOTE) {
+            let notes = memory.read(self.runtime_addr(ph.vaddr) as usize, ph.filesz as usize)?;
+            if let Some(id) = find_build_id(&notes) {
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+
+    /// PT_DYNAMIC が指す動的シンボルテーブル（`.dynsym`）を読み取る
+    ///
+    /// シンボル数は DT_HASH の nchain、なければ DT_GNU_HASH のチェーンから求めます。
+    pub fn dynamic_symbols(&self, memory: &Memory) -> Result<Vec<ImageSymbol>> {
+        let Some(dynamic) = self.program_headers.iter().find(|ph| ph.p_type == PT_DYNAMIC) else {
+            return Ok(Vec::new());
+        };
+        let entries = memory.read(self.runtime_addr(dynamic.vaddr) as usize, dynamic.filesz as usize)?;
+
+        let (mut symtab, mut strtab, mut strsz, mut hash, mut gnu_hash) = (None, None, None, None, None);
+        for entry in entries.chunks_exact(16) {
+            let (tag, value) = (u64_at(entry, 0), u64_at(entry, 8));
+            match tag {
+                DT_NULL => break,
+                DT_SYMTAB => symtab = Some(self.dynamic_ptr(value)),
+                DT_STRTAB => strtab = Some(self.dynamic_ptr(value)),
+                DT_STRSZ => strsz = Some(value),
+                DT_HASH => hash = Some(self.dynamic_ptr(value)),
+                DT_GNU_HASH => gnu_hash = Some(self.dynamic_ptr(value)),
+                _ => {}
+            }
+        }
+        let (Some(symtab), Some(strtab), Some(strsz)) = (symtab, strtab, strsz) else {
+            return Ok(Vec::new());
+        };
+
+        let count = match (hash, gnu_hash) {
+            (Some(hash), _) => {
+                let header = memory.read(hash as usize, 8)?;
+                u32_at(&header, 4) as usize
+            }
+            (None, Some(gnu_hash)) => gnu_hash_symbol_count(memory, gnu_hash)?,
+            (None, None) => return Ok(Vec::new()),
+        }
+        .min(MAX_DYNAMIC_SYMBOLS);
+
+        let strings = memory.read(strtab as usize, strsz as usize)?;
+        let table = memory.read(symtab as usize, count * ELF64_SYM_SIZE)?;
+        let symbols = table
+            .chunks_exact(ELF64_SYM_SIZE)
+            .filter_map(|sym| {
+                let name_off = u32_at(sym, 0) as usize;
+                let (address, size) = (u64_at(sym, 8), u64_at(sym, 16));
+                // st_shndx == SHN_UNDEF は他のオブジェクトへの参照
+                if u16_at(sym, 6) == 0 || address == 0 {
+                    return None;
+                }
+                let name = c_str_at(&strings, name_off)?;
+                Some(ImageSymbol { name, address, size })
+            })
+            .collect();
+        Ok(symbols)
+    }
+
+    /// ELF の仮想アドレスを実行時アドレスに変換する
+    fn runtime_addr(&self, vaddr: u64) -> u64 {
+        self.load_bias().wrapping_add(vaddr)
+    }
+
+    /// 動的セクションのポインタ値を実行時アドレスにする
+    ///
+    /// 動的リンカが .dynamic を書き換えて再配置済みの値を入れている場合があるため、
+    /// バイアス以上の値はすでに実行時アドレスとみなします。
+    fn dynamic_ptr(&self, value: u64) -> u64 {
+        let bias = self.load_bias();
+        if bias != 0 && value >= bias {
+            value
+        } else {
+            self.runtime_addr(value)
+        }
+    }
+}
+
+/// DT_GNU_HASH テーブルから動的シンボル数を求める
+///
+/// 最大のバケット値からチェーンを辿り、終端ビットが立ったエントリの次がシンボル数になります。
+fn gnu_hash_symbol_count(memory: &Memory, addr: u64) -> Result<usize> {
+    let header = memory.read(addr as usize, 16)?;
+    let nbuckets = u32_at(&header, 0) as usize;
+    let symoffset = u32_at(&header, 4) as usize;
+    let bloom_size = u32_at(&header, 8) as usize;
+
+    let buckets_addr = addr + 16 + bloom_size as u64 * 8;
+    let buckets = memory.read(buckets_addr as usize, nbuckets * 4)?;
+    let last_bucket = buckets.chunks_exact(4).map(|b| u32_at(b, 0) as usize).max().unwrap_or(0);
+    if last_bucket < symoffset {
+        return Ok(symoffset);
+    }
+
+    let chain_addr = buckets_addr + nbuckets as u64 * 4;
+    let mut index = last_bucket;
+    while index < MAX_DYNAMIC_SYMBOLS {
+        let value = memory.read((chain_addr + (index - symoffset) as u64 * 4) as usize, 4)?;
+        index += 1;
+        if u32_at(&value, 0) & 1 != 0 {
+            break;
+        }
+    }
+    Ok(index)
+}
+
+/// ノートセグメントの内容から NT_GNU_BUILD_ID を探す
+fn find_build_id(notes: &[u8]) -> Option<Vec<u8>> {
+    let align4 = |n: usize| (n + 3) & !3;
+    let mut pos = 0;
+    while pos + 12 <= notes.len() {
+        let namesz = u32_at(notes, pos) as usize;
+        let descsz = u32_at(notes, pos + 4) as usize;
+        let note_type = u32_at(notes, pos + 8);
+        let name_start = pos + 12;
+        let desc_start = name_start + align4(namesz);
+        let desc_end = desc_start + descsz;
+        if desc_end > notes.len() {
+            return None;
+        }
+        if note_type == NT_GNU_BUILD_ID && &notes[name_start..name_start + namesz] == b"GNU\0" {
+            return Some(notes[desc_start..desc_end].to_vec());
+        }
+        pos = desc_start + align4(descsz);
+    }
+    None
+}
+
+fn parse_phdr(bytes: &[u8]) -> ProgramHeader {
+    ProgramHeader {
+        p_type: u32_at(bytes, 0),
+        flags: u32_at(bytes, 4),
+        offset: u64_at(bytes, 8),
+        vaddr: u64_at(bytes, 16),
+        filesz: u64_at(bytes, 32),
+        memsz: u64_at(bytes, 40),
+        align: u64_at(bytes, 48),
+    }
+}
+
+fn c_str_at(bytes: &[u8], offset: usize) -> Option<String> {
+    let rest = bytes.get(offset..)?;
+    let len = rest.iter().position(|&b| b == 0)?;
+    (len > 0).then(|| String::from_utf8_lossy(&rest[..len]).into_owned())
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// ビルドIDを16進文字列にする
+pub fn format_build_id(id: &[u8]) -> String {
+    id.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_build_id() {
+        let mut notes = Vec::new();
+        // NT_GNU_ABI_TAG（読み飛ばされる）
+        notes.extend_from_slice(&4u32.to_le_bytes());
+        notes.extend_from_slice(&16u32.to_le_bytes());
+        notes.extend_from_slice(&1u32.to_le_bytes());
+        notes.extend_from_slice(b"GNU\0");
+        notes.extend_from_slice(&[0; 16]);
+        // NT_GNU_BUILD_ID（desc が4バイト境界に揃っていない）
+        notes.extend_from_slice(&4u32.to_le_bytes());
+        notes.extend_from_slice(&3u32.to_le_bytes());
+        notes.extend_from_slice(&NT_GNU_BUILD_ID.to_le_bytes());
+        notes.extend_from_slice(b"GNU\0");
+        notes.extend_from_slice(&[0xde, 0xad, 0xbe, 0]);
+
+        assert_eq!(find_build_id(&notes), Some(vec![0xde, 0xad, 0xbe]));
+        assert_eq!(find_build_id(&notes[..28]), None);
+        assert_eq!(format_build_id(&[0x0a, 0xff]), "0aff");
+    }
+
+    #[test]
+    fn test_read_own_image() {
+        let memory = Memory::new(std::process::id() as i32);
+        let base = memory.get_base_address().unwrap() as u64;
+        let image = MappedImage::read(&memory, base).unwrap();
+        assert!(image.program_headers().iter().any(|ph| ph.p_type == PT_LOAD));
+
+        // 自分自身の関数のアドレスがイメージ内の PT_LOAD に収まる
+        let runtime = test_read_own_image as fn() as usize as u64;
+        let vaddr = runtime.wrapping_sub(image.load_bias());
+        assert!(image
+            .program_headers()
+            .iter()
+            .any(|ph| ph.p_type == PT_LOAD && (ph.vaddr..ph.vaddr + ph.memsz).contains(&vaddr)));
+    }
+}
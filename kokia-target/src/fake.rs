@@ -0,0 +1,173 @@
+//! メモリ上だけで動く偽のデバッグ対象（単体テスト用）
+//!
+//! 領域ごとのバイト列とレジスタを持ち、実行は「次にどのアドレスを実行するか」の
+//! 筋書きで表します。再開すると筋書きのアドレスを順に実行したことにし、そこに INT3 が
+//! 書かれていれば本物と同じく PC を INT3 の次に進めてブレークポイントで止まります。
+//! 筋書きが尽きたら終了コード 0 で終了したことにします。
+
+use crate::backend::{TargetBackend, TargetMemory};
+use crate::{Result, StopReason, UserRegs};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
+
+const INT3_OPCODE: u8 = 0xCC;
+
+/// 偽のデバッグ対象
+pub struct FakeTarget {
+    /// 領域の先頭アドレス → 中身
+    regions: RefCell<BTreeMap<u64, Vec<u8>>>,
+    regs: RefCell<UserRegs>,
+    /// 停止を報告するスレッド
+    tid: Cell<i32>,
+    /// これから実行するアドレス
+    script: RefCell<VecDeque<u64>>,
+    /// `step` で進める命令長
+    step_len: u64,
+    steps: Cell<u64>,
+    resumes: Cell<u64>,
+}
+
+impl FakeTarget {
+    pub fn new() -> Self {
+        // SAFETY: user_regs_struct は整数だけの構造体なので、すべて 0 は有効な値
+        let regs: UserRegs = unsafe { std::mem::zeroed() };
+        Self {
+            regions: RefCell::new(BTreeMap::new()),
+            regs: RefCell::new(regs),
+            tid: Cell::new(1),
+            script: RefCell::new(VecDeque::new()),
+            step_len: 1,
+            steps: Cell::new(0),
+            resumes: Cell::new(0),
+        }
+    }
+
+    /// `addr` から `bytes` を置いた領域を作る
+    pub fn map(&self, addr: u64, bytes: Vec<u8>) {
+        self.regions.borrow_mut().insert(addr, bytes);
+    }
+
+    /// `addr` から `len` バイトの 0 で埋めた領域を作る
+    pub fn map_zeroed(&self, addr: u64, len: usize) {
+        self.map(addr, vec![0; len]);
+    }
+
+    /// 再開したときに実行するアドレスを筋書きの最後に加える
+    pub fn will_execute(&self, addr: u64) {
+        self.script.borrow_mut().push_back(addr);
+    }
+
+    /// レジスタを書き換える
+    pub fn with_regs(&self, update: impl FnOnce(&mut UserRegs)) {
+        update(&mut self.regs.borrow_mut());
+    }
+
+    /// 以後の停止を報告するスレッドを変える（最初は 1）
+    pub fn set_thread(&self, tid: i32) {
+        self.tid.set(tid);
+    }
+
+    /// `step` を呼ばれた回数
+    pub fn steps(&self) -> u64 {
+        self.steps.get()
+    }
+
+    /// `resume` を呼ばれた回数
+    pub fn resumes(&self) -> u64 {
+        self.resumes.get()
+    }
+
+    /// `[addr, addr + len)` を含む領域とその中の位置
+    fn locate(&self, addr: u64, len: usize) -> Option<(u64, usize)> {
+        let regions = self.regions.borrow();
+        let (&start, bytes) = regions.range(..=addr).next_back()?;
+        let offset = (addr - start) as usize;
+        (offset + len <= bytes.len()).then_some((start, offset))
+    }
+}
+
+impl Default for FakeTarget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TargetMemory for FakeTarget {
+    fn read_memory(&self, addr: u64, len: usize) -> Result<Vec<u8>> {
+        let (start, offset) = self.locate(addr, len)
+            .ok_or_else(|| anyhow::anyhow!("Unmapped read of {} bytes at 0x{:x}", len, addr))?;
+        Ok(self.regions.borrow()[&start][offset..offset + len].to_vec())
+    }
+
+    fn write_memory(&self, addr: u64, data: &[u8]) -> Result<()> {
+        let (start, offset) = self.locate(addr, data.len())
+            .ok_or_else(|| anyhow::anyhow!("Unmapped write of {} bytes at 0x{:x}", data.len(), addr))?;
+        self.regions.borrow_mut().get_mut(&start).expect("located region")[offset..offset + data.len()]
+            .copy_from_slice(data);
+        Ok(())
+    }
+
+    fn is_address_mapped(&self, addr: u64) -> Result<bool> {
+        Ok(self.locate(addr, 1).is_some())
+    }
+}
+
+impl TargetBackend for FakeTarget {
+    fn thread_id(&self) -> i32 {
+        self.tid.get()
+    }
+
+    fn registers(&self) -> Result<UserRegs> {
+        Ok(*self.regs.borrow())
+    }
+
+    fn set_registers(&self, regs: &UserRegs) -> Result<()> {
+        *self.regs.borrow_mut() = *regs;
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<StopReason> {
+        self.resumes.set(self.resumes.get() + 1);
+        loop {
+            let Some(addr) = self.script.borrow_mut().pop_front() else {
+                return Ok(StopReason::Exited(0));
+            };
+            if self.read_memory(addr, 1).ok() == Some(vec![INT3_OPCODE]) {
+                self.regs.borrow_mut().rip = addr + 1;
+                return Ok(StopReason::Breakpoint);
+            }
+            self.regs.borrow_mut().rip = addr;
+        }
+    }
+
+    fn step(&self) -> Result<StopReason> {
+        self.steps.set(self.steps.get() + 1);
+        self.regs.borrow_mut().rip += self.step_len;
+        Ok(StopReason::Step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fake_target_stops_on_int3() {
+        let target = FakeTarget::new();
+        target.map(0x1000, vec![0x90; 0x20]);
+        assert_eq!(target.read_word(0x1000).unwrap(), 0x9090_9090_9090_9090);
+        assert!(target.read_memory(0x101c, 8).is_err());
+        assert!(!target.is_address_mapped(0x2000).unwrap());
+
+        target.write_memory(0x1010, &[INT3_OPCODE]).unwrap();
+        target.will_execute(0x1008);
+        target.will_execute(0x1010);
+        target.will_execute(0x1018);
+        assert_eq!(target.resume().unwrap(), StopReason::Breakpoint);
+        assert_eq!(target.pc().unwrap(), 0x1011);
+        assert_eq!(target.step().unwrap(), StopReason::Step);
+        assert_eq!(target.pc().unwrap(), 0x1012);
+        assert_eq!(target.resume().unwrap(), StopReason::Exited(0));
+        assert_eq!((target.resumes(), target.steps()), (2, 1));
+    }
+}
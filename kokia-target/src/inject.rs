@@ -0,0 +1,70 @@
+//! デバッグ対象プロセスへのシステムコール注入
+//!
+//! 停止中のスレッドの現在の PC に `syscall` 命令を一時的に書き込み、
+//! 1命令だけ実行させて戻り値を回収する。実行後は命令バイトとレジスタを元に戻す。
+
+use crate::{Memory, Registers, Result};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+
+/// x86_64 の `syscall` 命令
+const SYSCALL_INSN: [u8; 2] = [0x0f, 0x05];
+
+/// システムコール引数に使われるレジスタの数（rdi, rsi, rdx, r10, r8, r9）
+const MAX_SYSCALL_ARGS: usize = 6;
+
+/// 停止中のスレッドでシステムコールを実行させる
+///
+/// # Arguments
+/// * `tid` - 停止中（ptrace-stop）のスレッドID
+/// * `nr` - システムコール番号
+/// * `args` - 引数（最大6個）
+///
+/// # Returns
+/// システムコールの戻り値（失敗時は `-errno`）
+pub fn inject_syscall(tid: i32, nr: i64, args: &[u64]) -> Result<i64> {
+    if args.len() > MAX_SYSCALL_ARGS {
+        return Err(anyhow::anyhow!("Too many syscall arguments: {}", args.len()));
+    }
+
+    let registers = Registers::new(tid);
+    let memory = Memory::new(tid);
+
+    let saved_regs = registers.read()?;
+    let pc = saved_regs.rip as usize;
+    let saved_code = memory.read(pc, SYSCALL_INSN.len())?;
+
+    let mut regs = saved_regs;
+    regs.rax = nr as u64;
+    // 中断されたシステムコールの再開処理が走らないようにする
+    regs.orig_rax = u64::MAX;
+    let slots = [&mut regs.rdi, &mut regs.rsi, &mut regs.rdx, &mut regs.r10, &mut regs.r8, &mut regs.r9];
+    for (slot, &arg) in slots.into_iter().zip(args) {
+        *slot = arg;
+    }
+
+    memory.write(pc, &SYSCALL_INSN)?;
+    let result = registers.write(regs)
+        .and_then(|_| step_and_wait(tid))
+        .and_then(|_| registers.read())
+        .map(|after| after.rax as i64);
+
+    // 成否に関わらず元の状態に戻す
+    let restore_code = memory.write(pc, &saved_code);
+    let restore_regs = registers.write(saved_regs);
+    restore_code?;
+    restore_regs?;
+
+    result
+}
+
+/// 1命令ステップ実行して停止を待つ
+fn step_and_wait(tid: i32) -> Result<()> {
+    let pid = Pid::from_raw(tid);
+    nix::sys::ptrace::step(pid, None)?;
+    // メイン以外のスレッドは clone の子なので __WALL が要る
+    match waitpid(pid, Some(WaitPidFlag::__WALL))? {
+        WaitStatus::Stopped(_, _) => Ok(()),
+        status => Err(anyhow::anyhow!("Unexpected wait status during syscall injection: {:?}", status)),
+    }
+}
@@ -0,0 +1,412 @@
+//! Intel PT（Processor Trace）によるハードウェア分岐トレース
+//!
+//! `trace start` のシングルステップと違い、CPU が分岐の結果を AUX バッファに書き出すので、
+//! 止めずに走らせた区間（continue から次の停止まで）に何が実行されたかをほぼ等速で記録できます。
+//! スレッドごとに perf_event_open でイベントを作り、止めるときに AUX バッファの中身を取り出します。
+//!
+//! デコーダは最小限で、命令列を辿って実行経路を復元することはしません。間接分岐・戻り先（TIP）、
+//! 割り込みなどの非同期イベントの位置（FUP）、トレースの開始・終了を取り出し、条件分岐（TNT）は
+//! 成立・不成立の回数だけを数えます。ret の圧縮を切って（noretcomp）記録するので、関数からの復帰も
+//! すべて TIP として現れます。
+//!
+//! 開始時に存在したスレッドだけを記録し、AUX バッファ（4 MiB）が一杯になるとそれ以降は捨てられます。
+
+use crate::uprobe::{ioctl, parse_config_bit, perf_event_open, PerfEventAttr, ATTR_FLAG_DISABLED, PERF_ATTR_SIZE, PERF_EVENT_IOC_ENABLE};
+use crate::Result;
+use nix::libc;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::sync::atomic::{fence, Ordering};
+
+const INTEL_PT_PMU_DIR: &str = "/sys/bus/event_source/devices/intel_pt";
+
+const ATTR_FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+const ATTR_FLAG_EXCLUDE_HV: u64 = 1 << 6;
+
+const PERF_EVENT_IOC_DISABLE: libc::c_ulong = 0x2401;
+
+/// perf_event_mmap_page 内の AUX 領域のフィールドのオフセット
+const MMAP_AUX_HEAD: usize = 1056;
+const MMAP_AUX_OFFSET: usize = 1072;
+const MMAP_AUX_SIZE: usize = 1080;
+
+/// サイドバンド用のリングバッファのデータ部のページ数（2 の冪）
+const DATA_PAGES: usize = 8;
+/// AUX バッファのページ数（2 の冪）
+const AUX_PAGES: usize = 1024;
+
+/// PSB（同期点）のパケット: `02 82` の8回繰り返し
+const PSB: [u8; 16] = [0x02, 0x82, 0x02, 0x82, 0x02, 0x82, 0x02, 0x82, 0x02, 0x82, 0x02, 0x82, 0x02, 0x82, 0x02, 0x82];
+
+/// 1スレッド分の生のトレース
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PtThreadTrace {
+    pub tid: i32,
+    /// AUX バッファに書かれたパケット列
+    pub data: Vec<u8>,
+    /// バッファが一杯になり、途中から記録されていない
+    pub truncated: bool,
+}
+
+/// 1スレッド分のイベントと AUX バッファ
+struct ThreadBuffer {
+    tid: i32,
+    fd: OwnedFd,
+    base: *mut u8,
+    aux: *mut u8,
+    page_size: usize,
+}
+
+impl ThreadBuffer {
+    fn open(pmu_type: u32, config: u64, tid: i32) -> Result<Self> {
+        let mut attr = PerfEventAttr {
+            type_: pmu_type,
+            size: PERF_ATTR_SIZE,
+            config,
+            flags: ATTR_FLAG_DISABLED | ATTR_FLAG_EXCLUDE_KERNEL | ATTR_FLAG_EXCLUDE_HV,
+            ..Default::default()
+        };
+        let fd = perf_event_open(&mut attr, tid, -1).map_err(|e| explain_open_error(e, tid))?;
+
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let base = mmap(&fd, page_size * (DATA_PAGES + 1), 0)?;
+        // AUX 領域の位置と大きさを書いてから、同じ fd のその位置を mmap する
+        let aux_offset = page_size * (DATA_PAGES + 1);
+        unsafe {
+            std::ptr::write_volatile(base.add(MMAP_AUX_OFFSET) as *mut u64, aux_offset as u64);
+            std::ptr::write_volatile(base.add(MMAP_AUX_SIZE) as *mut u64, (page_size * AUX_PAGES) as u64);
+        }
+        let aux = match mmap(&fd, page_size * AUX_PAGES, aux_offset as libc::off_t) {
+            Ok(aux) => aux,
+            Err(e) => {
+                unsafe { libc::munmap(base as *mut libc::c_void, page_size * (DATA_PAGES + 1)) };
+                return Err(e);
+            }
+        };
+        Ok(Self { tid, fd, base, aux, page_size })
+    }
+
+    fn aux_size(&self) -> usize {
+        self.page_size * AUX_PAGES
+    }
+
+    /// 記録を止めて AUX バッファの中身を取り出す
+    fn take(&self) -> Result<PtThreadTrace> {
+        ioctl(&self.fd, PERF_EVENT_IOC_DISABLE, 0)?;
+        let head = unsafe { std::ptr::read_volatile(self.base.add(MMAP_AUX_HEAD) as *const u64) } as usize;
+        fence(Ordering::Acquire);
+        // aux_tail を進めないので、カーネルはバッファの末尾で書き込みをやめる
+        let len = head.min(self.aux_size());
+        let data = unsafe { std::slice::from_raw_parts(self.aux, len) }.to_vec();
+        Ok(PtThreadTrace { tid: self.tid, data, truncated: head >= self.aux_size() })
+    }
+}
+
+impl Drop for ThreadBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.aux as *mut libc::c_void, self.aux_size());
+            libc::munmap(self.base as *mut libc::c_void, self.page_size * (DATA_PAGES + 1));
+        }
+    }
+}
+
+fn mmap(fd: &OwnedFd, len: usize, offset: libc::off_t) -> Result<*mut u8> {
+    let addr = unsafe {
+        libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, fd.as_raw_fd(), offset)
+    };
+    if addr == libc::MAP_FAILED {
+        return Err(anyhow::anyhow!("Failed to mmap Intel PT buffer: {}", std::io::Error::last_os_error()));
+    }
+    Ok(addr as *mut u8)
+}
+
+/// Intel PT によるトレースの記録
+pub struct PtSession {
+    buffers: Vec<ThreadBuffer>,
+}
+
+impl PtSession {
+    /// Intel PT の PMU があるか（CPU が対応していない、または VM の中では false）
+    pub fn is_supported() -> bool {
+        pt_pmu_type().is_ok()
+    }
+
+    /// 指定したスレッドのユーザー空間の分岐を記録し始める
+    pub fn start(tids: &[i32]) -> Result<Self> {
+        let pmu_type = pt_pmu_type()?;
+        // ret も TIP として出す（format/noretcomp が `config:11` なら bit 11）
+        let config = std::fs::read_to_string(format!("{}/format/noretcomp", INTEL_PT_PMU_DIR))
+            .ok()
+            .and_then(|format| parse_config_bit(&format))
+            .map(|bit| 1u64 << bit)
+            .unwrap_or(0);
+
+        let buffers = tids.iter()
+            .map(|&tid| ThreadBuffer::open(pmu_type, config, tid))
+            .collect::<Result<Vec<_>>>()?;
+        for buffer in &buffers {
+            ioctl(&buffer.fd, PERF_EVENT_IOC_ENABLE, 0)?;
+        }
+        Ok(Self { buffers })
+    }
+
+    /// 記録しているスレッドの数
+    pub fn thread_count(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// 記録を止めて、スレッドごとのパケット列を取り出す
+    pub fn stop(self) -> Result<Vec<PtThreadTrace>> {
+        self.buffers.iter().map(ThreadBuffer::take).collect()
+    }
+}
+
+fn pt_pmu_type() -> Result<u32> {
+    let path = format!("{}/type", INTEL_PT_PMU_DIR);
+    let value = std::fs::read_to_string(&path).map_err(|_| {
+        anyhow::anyhow!("Intel PT is not available ({} not found): the CPU lacks it, or it is not passed through to this VM", path)
+    })?;
+    Ok(value.trim().parse()?)
+}
+
+fn explain_open_error(err: std::io::Error, tid: i32) -> anyhow::Error {
+    match err.raw_os_error() {
+        Some(libc::EACCES) | Some(libc::EPERM) => anyhow::anyhow!(
+            "Permission denied enabling Intel PT for thread {}\n  hint: run as root, grant CAP_PERFMON, or set kernel.perf_event_paranoid to 1 or lower",
+            tid
+        ),
+        Some(libc::EBUSY) => anyhow::anyhow!("Intel PT is already in use (by perf or another tracer): {}", err),
+        _ => anyhow::anyhow!("Failed to enable Intel PT for thread {}: {}", tid, err),
+    }
+}
+
+/// デコードしたイベント
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PtEvent {
+    /// 間接分岐・ret・far 分岐の行き先（TIP）
+    Branch(u64),
+    /// トレースが（再び）有効になった位置（TIP.PGE）
+    TraceOn(u64),
+    /// トレースが無効になった（TIP.PGD、カーネルへの遷移など。位置は分かれば）
+    TraceOff(Option<u64>),
+    /// 割り込み・例外などの非同期イベントが起きた位置（FUP）
+    Async(u64),
+    /// CPU 内部のバッファが溢れてパケットが失われた（OVF）
+    Overflow,
+}
+
+/// パケット列のデコード結果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PtDecoded {
+    pub events: Vec<PtEvent>,
+    /// 成立した条件分岐の数
+    pub taken: u64,
+    /// 成立しなかった条件分岐の数
+    pub not_taken: u64,
+    /// 読めずに次の PSB まで読み飛ばした回数
+    pub desyncs: usize,
+}
+
+impl PtDecoded {
+    /// 行き先が分かっている分岐（Branch と TraceOn）のアドレス
+    pub fn branch_targets(&self) -> impl Iterator<Item = u64> + '_ {
+        self.events.iter().filter_map(|event| match event {
+            PtEvent::Branch(ip) | PtEvent::TraceOn(ip) => Some(*ip),
+            _ => None,
+        })
+    }
+}
+
+/// Intel PT のパケット列を読む
+pub fn decode(data: &[u8]) -> PtDecoded {
+    let mut decoded = PtDecoded::default();
+    // 最初の PSB までは文脈がないので読まない
+    let Some(mut pos) = find_psb(data, 0) else {
+        return decoded;
+    };
+    let mut last_ip = 0u64;
+
+    while pos < data.len() {
+        match decode_packet(&data[pos..], &mut last_ip, &mut decoded) {
+            Some(len) => pos += len,
+            None => {
+                decoded.desyncs += 1;
+                match find_psb(data, pos + 1) {
+                    Some(next) => pos = next,
+                    None => break,
+                }
+            }
+        }
+    }
+    decoded
+}
+
+fn find_psb(data: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?.windows(PSB.len()).position(|window| window == PSB).map(|i| from + i)
+}
+
+/// 1パケットを読み、その長さを返す（読めなければ None）
+fn decode_packet(packet: &[u8], last_ip: &mut u64, decoded: &mut PtDecoded) -> Option<usize> {
+    let header = *packet.first()?;
+    let need = |len: usize| (packet.len() >= len).then_some(len);
+    match header {
+        0x00 => Some(1),
+        0x02 => decode_extended(packet, decoded, last_ip),
+        // 短い TNT: 最上位の 1 が終端で、その下の bit 1 以上が分岐の結果
+        b if b & 1 == 0 => {
+            count_tnt(b as u64 >> 1, decoded);
+            Some(1)
+        }
+        b if matches!(b & 0x1f, 0x0d | 0x11 | 0x01 | 0x1d) => {
+            let (ip, len) = decode_ip(packet, *last_ip)?;
+            if let Some(ip) = ip {
+                *last_ip = ip;
+            }
+            // IP が省略された TIP / FUP は位置が分からないので数えない
+            let event = match (b & 0x1f, ip) {
+                (0x01, ip) => Some(PtEvent::TraceOff(ip)),
+                (_, None) => None,
+                (0x0d, Some(ip)) => Some(PtEvent::Branch(ip)),
+                (0x11, Some(ip)) => Some(PtEvent::TraceOn(ip)),
+                (_, Some(ip)) => Some(PtEvent::Async(ip)),
+            };
+            decoded.events.extend(event);
+            Some(len)
+        }
+        // MODE / TSC / MTC
+        0x99 => need(2),
+        0x19 => need(8),
+        0x59 => need(2),
+        // CYC: bit 2 が立っていれば、最下位ビットが 1 のバイトが続く
+        b if b & 3 == 3 => {
+            if b & 4 == 0 {
+                return Some(1);
+            }
+            let extra = packet[1..].iter().position(|byte| byte & 1 == 0)?;
+            Some(extra + 2)
+        }
+        _ => None,
+    }
+}
+
+/// `02` で始まる2バイト目以降で種類が決まるパケット
+fn decode_extended(packet: &[u8], decoded: &mut PtDecoded, last_ip: &mut u64) -> Option<usize> {
+    let need = |len: usize| (packet.len() >= len).then_some(len);
+    match *packet.get(1)? {
+        0x82 => {
+            // PSB の後は IP の圧縮がリセットされる
+            *last_ip = 0;
+            (packet.get(..PSB.len())? == PSB).then_some(PSB.len())
+        }
+        0xa3 => {
+            let payload = packet.get(2..8)?;
+            let mut bits = [0u8; 8];
+            bits[..6].copy_from_slice(payload);
+            count_tnt(u64::from_le_bytes(bits), decoded);
+            Some(8)
+        }
+        0xf3 => {
+            decoded.events.push(PtEvent::Overflow);
+            Some(2)
+        }
+        // PSBEND / TraceStop / EXSTOP / BEP
+        0x23 | 0x83 | 0x62 | 0xe2 | 0x33 | 0xb3 => need(2),
+        // BBP / CBR / PWRE / CFE
+        0x63 => need(3),
+        0x03 | 0x22 | 0x13 => need(4),
+        // VMCS / TMA / PWRX / PIP
+        0xc8 => need(7),
+        0x73 | 0xa2 => need(7),
+        0x43 => need(8),
+        // MWAIT / MNT / EVD
+        0xc2 => need(10),
+        0xc3 | 0x53 => need(11),
+        // PTW: bit 5-6 が 0 なら4バイト、1 なら8バイトのペイロード
+        b if b & 0x1f == 0x12 => match (b >> 5) & 3 {
+            0 => need(6),
+            1 => need(10),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// TNT のペイロード（最上位の 1 が終端）の分岐結果を数える
+fn count_tnt(payload: u64, decoded: &mut PtDecoded) {
+    if payload == 0 {
+        return;
+    }
+    let stop = 63 - payload.leading_zeros();
+    let results = payload & ((1u64 << stop) - 1);
+    decoded.taken += results.count_ones() as u64;
+    decoded.not_taken += (stop - results.count_ones()) as u64;
+}
+
+/// TIP 系のパケットの IP を読む（IP が省略されていれば None）とパケット長
+fn decode_ip(packet: &[u8], last_ip: u64) -> Option<(Option<u64>, usize)> {
+    let bytes = match packet[0] >> 5 {
+        0 => return Some((None, 1)),
+        1 => 2,
+        2 => 4,
+        3 | 4 => 6,
+        6 => 8,
+        _ => return None,
+    };
+    let payload = packet.get(1..1 + bytes)?;
+    let mut raw = [0u8; 8];
+    raw[..bytes].copy_from_slice(payload);
+    let value = u64::from_le_bytes(raw);
+    let ip = match packet[0] >> 5 {
+        1 => (last_ip & !0xffff) | value,
+        2 => (last_ip & !0xffff_ffff) | value,
+        // 48 ビットを符号拡張する
+        3 => ((value << 16) as i64 >> 16) as u64,
+        4 => (last_ip & !0xffff_ffff_ffff) | value,
+        _ => value,
+    };
+    Some((Some(ip), 1 + bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_packets() {
+        let mut data = vec![0x55, 0x00];
+        data.extend_from_slice(&PSB);
+        data.extend_from_slice(&[0x02, 0x23]);
+        // TIP.PGE 0x5555_5555_e010（6バイト、符号拡張）
+        data.extend_from_slice(&[0x71, 0x10, 0xe0, 0x55, 0x55, 0x55, 0x55]);
+        // 短い TNT: 終端 + taken, not taken, taken
+        data.push(0b0001_1010);
+        // TIP 下位2バイトだけ: 0x5555_5555_f000
+        data.extend_from_slice(&[0x2d, 0x00, 0xf0]);
+        // TSC, CYC（2バイト）, MODE
+        data.extend_from_slice(&[0x19, 1, 2, 3, 4, 5, 6, 7, 0x07, 0x02, 0x99, 0x01]);
+        data.extend_from_slice(&[0x02, 0xf3]);
+        // TIP.PGD（IP 省略）
+        data.push(0x01);
+        // 読めないパケットは次の PSB まで飛ばす
+        data.extend_from_slice(&[0x02, 0xff, 0x0d]);
+        data.extend_from_slice(&PSB);
+        // FUP 8バイト
+        data.extend_from_slice(&[0xdd, 0x00, 0x10, 0x40, 0, 0, 0, 0, 0]);
+
+        let decoded = decode(&data);
+        assert_eq!(
+            decoded.events,
+            vec![
+                PtEvent::TraceOn(0x5555_5555_e010),
+                PtEvent::Branch(0x5555_5555_f000),
+                PtEvent::Overflow,
+                PtEvent::TraceOff(None),
+                PtEvent::Async(0x401000),
+            ]
+        );
+        assert_eq!((decoded.taken, decoded.not_taken), (2, 1));
+        assert_eq!(decoded.desyncs, 1);
+        assert_eq!(decoded.branch_targets().collect::<Vec<_>>(), vec![0x5555_5555_e010, 0x5555_5555_f000]);
+        assert_eq!(decode(&[0x0d, 0x00]), PtDecoded::default());
+    }
+}
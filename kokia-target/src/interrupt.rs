@@ -0,0 +1,60 @@
+//! 長い処理中の Ctrl-C 検出
+//!
+//! REPL のコマンド実行中に SIGINT を受けても kokia 自体は終了させず、フラグを立てるだけにする。
+//! 処理側はフラグを定期的に確認して自発的に打ち切る。
+
+use nix::libc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// ガード設置中に SIGINT を受けたか
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_sig: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// SIGINT をフラグに変換するガード
+///
+/// 生存中は SIGINT で終了せず [`interrupted`] が true になる。Drop で元のハンドラに戻す。
+pub struct InterruptGuard {
+    previous: libc::sighandler_t,
+}
+
+impl InterruptGuard {
+    /// フラグをクリアしてハンドラを設置する
+    pub fn install() -> Self {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        let handler = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        let previous = unsafe { libc::signal(libc::SIGINT, handler) };
+        Self { previous }
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        unsafe {
+            libc::signal(libc::SIGINT, self.previous);
+        }
+    }
+}
+
+/// ガード設置後に SIGINT を受けたか
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interrupt_guard() {
+        let guard = InterruptGuard::install();
+        assert!(!interrupted());
+        unsafe {
+            libc::raise(libc::SIGINT);
+        }
+        assert!(interrupted());
+        drop(guard);
+    }
+}
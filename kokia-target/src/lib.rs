@@ -8,12 +8,38 @@ pub mod thread;
 pub mod memory;
 pub mod registers;
 pub mod breakpoint;
+pub mod cleanup;
+pub mod procfs;
+pub mod inject;
+pub mod diagnostics;
+pub mod namespace;
+pub mod elf_image;
+pub mod interrupt;
+pub mod uprobe;
+pub mod intel_pt;
+pub mod sampler;
+pub mod coredump;
+pub mod backend;
+pub mod fake;
+pub mod tls;
 
-pub use process::{Process, StopReason};
+pub use process::{Process, SignalInfo, SpawnOptions, StopReason};
 pub use thread::{Thread, ThreadId};
-pub use memory::{Memory, MemoryMapping, MemoryReadable};
-pub use registers::Registers;
-pub use breakpoint::{SoftwareBreakpoint, HardwareBreakpoint};
+pub use memory::{Memory, MemoryMapping, MemoryReadable, PartialRead, Protection};
+pub use registers::{Registers, UserRegs, XState};
+pub use procfs::{find_processes, thread_name, ProcessInfo};
+pub use diagnostics::{probe_ptrace, PtraceEnvironment};
+pub use namespace::TargetNamespace;
+pub use elf_image::{ImageSymbol, MappedImage, ProgramHeader};
+pub use uprobe::{ProbeKind, ProbeSpec, UprobeEvent, UprobeSession};
+pub use intel_pt::{PtDecoded, PtEvent, PtSession, PtThreadTrace};
+pub use sampler::{PcSample, PerfSampler};
+pub use coredump::{write_core, CoreSummary};
+pub use backend::{PtraceBackend, TargetBackend, TargetMemory};
+pub use fake::FakeTarget;
+pub use tls::{tls_block_address, EXECUTABLE_TLS_MODULE};
+pub use nix::sys::signal::Signal;
+pub use breakpoint::{SoftwareBreakpoint, HardwareBreakpoint, DebugStatus, WatchKind, HW_BREAKPOINT_SLOTS};
 
 /// ターゲット制御の結果型
 pub type Result<T> = anyhow::Result<T>;
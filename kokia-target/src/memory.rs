@@ -1,6 +1,6 @@
 //! メモリアクセス機能
 
-use crate::Result;
+use crate::{Result, TargetNamespace};
 use nix::unistd::Pid;
 use std::fs::{File, OpenOptions};
 use std::io::{BufRead, BufReader, Read as _, Seek, SeekFrom, Write as _};
@@ -82,11 +82,94 @@ pub struct MemoryMapping {
     pub readable: bool,
     pub writable: bool,
     pub executable: bool,
+    /// マップされたファイル内のオフセット
+    pub offset: usize,
+    /// マップされたファイルのパス（`[heap]` などの疑似名を含む。匿名マッピングは None）
+    pub path: Option<String>,
+}
+
+/// メモリ保護属性（mprotect の PROT_* に対応）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Protection {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Protection {
+    /// `rwx` / `r-x` / `rw` 形式の文字列をパースする
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut prot = Self::default();
+        for c in s.chars() {
+            match c {
+                'r' => prot.read = true,
+                'w' => prot.write = true,
+                'x' => prot.execute = true,
+                '-' => {}
+                _ => return Err(anyhow::anyhow!("Invalid protection '{}': expected combination of r, w, x", s)),
+            }
+        }
+        Ok(prot)
+    }
+
+    /// PROT_* フラグに変換する
+    pub fn to_prot_flags(self) -> i32 {
+        let mut flags = nix::libc::PROT_NONE;
+        if self.read {
+            flags |= nix::libc::PROT_READ;
+        }
+        if self.write {
+            flags |= nix::libc::PROT_WRITE;
+        }
+        if self.execute {
+            flags |= nix::libc::PROT_EXEC;
+        }
+        flags
+    }
+}
+
+impl std::fmt::Display for Protection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            if self.read { 'r' } else { '-' },
+            if self.write { 'w' } else { '-' },
+            if self.execute { 'x' } else { '-' }
+        )
+    }
+}
+
+/// 分割読み取り（`Memory::read_partial`）の単位
+const PAGE_SIZE: usize = 4096;
+
+/// 一部が読めなかった可能性のある読み取り結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialRead {
+    /// 読み取ったデータ（要求サイズ分。読めなかった範囲は 0 埋め）
+    pub data: Vec<u8>,
+    /// 読めなかった範囲（要求先頭からのオフセット、昇順・非重複）
+    pub holes: Vec<std::ops::Range<usize>>,
+}
+
+impl PartialRead {
+    /// 全範囲を読めたか
+    pub fn is_complete(&self) -> bool {
+        self.holes.is_empty()
+    }
+
+    /// 先頭から連続して読めた部分
+    pub fn prefix(&self) -> &[u8] {
+        let len = self.holes.first().map_or(self.data.len(), |hole| hole.start);
+        &self.data[..len]
+    }
 }
 
 /// メモリアクセス
 pub struct Memory {
     pid: Pid,
+    /// マッピングのパス名を解釈する名前空間（コンテナ内のプロセスの場合）
+    namespace: Option<TargetNamespace>,
 }
 
 impl Memory {
@@ -94,6 +177,17 @@ impl Memory {
     pub fn new(pid: i32) -> Self {
         Self {
             pid: Pid::from_raw(pid),
+            namespace: None,
+        }
+    }
+
+    /// 別のマウント名前空間で動くプロセスのメモリアクセスを作成する
+    ///
+    /// `get_mappings` が返すパス名は、ホスト側から開けるパスに変換されます。
+    pub fn with_namespace(pid: i32, namespace: TargetNamespace) -> Self {
+        Self {
+            pid: Pid::from_raw(pid),
+            namespace: Some(namespace),
         }
     }
 
@@ -141,23 +235,97 @@ impl Memory {
         Ok(buffer)
     }
 
+    /// ページ単位に分割して読み取り、読めなかった範囲を穴として記録する
+    ///
+    /// 未マップのページをまたぐ範囲でも全体を失敗にせず、読めた部分を返します。
+    /// ヒープの走査や大きな構造体の表示に使用します。
+    pub fn read_partial(&self, addr: usize, size: usize) -> Result<PartialRead> {
+        let mem_path = self.mem_path();
+        let mut file = File::open(&mem_path)
+            .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", mem_path, e))?;
+
+        let mut data = vec![0u8; size];
+        let mut holes: Vec<std::ops::Range<usize>> = Vec::new();
+        let mut pos = 0;
+        while pos < size {
+            // ページ境界で区切る
+            let chunk_addr = addr + pos;
+            let chunk_len = (PAGE_SIZE - chunk_addr % PAGE_SIZE).min(size - pos);
+            let chunk = &mut data[pos..pos + chunk_len];
+
+            let ok = file.seek(SeekFrom::Start(chunk_addr as u64)).is_ok() && file.read_exact(chunk).is_ok();
+            if !ok {
+                chunk.fill(0);
+                match holes.last_mut() {
+                    Some(hole) if hole.end == pos => hole.end = pos + chunk_len,
+                    _ => holes.push(pos..pos + chunk_len),
+                }
+            }
+            pos += chunk_len;
+        }
+
+        Ok(PartialRead { data, holes })
+    }
+
     /// メモリにデータを書き込む
     ///
     /// /proc/pid/memを使用してターゲットプロセスのメモリに書き込みます。
+    /// コンテナの hidepid 設定などで /proc/pid/mem に書き込めない場合（EACCES/EPERM）、
+    /// PTRACE_POKEDATAにフォールバックします。
     pub fn write(&self, addr: usize, data: &[u8]) -> Result<()> {
-        let mem_path = self.mem_path();
-        let mut file = OpenOptions::new()
-            .write(true)
-            .open(&mem_path)
-            .map_err(|e| anyhow::anyhow!("Failed to open {} for writing: {}", mem_path, e))?;
-
-        // 指定されたアドレスにシーク
-        file.seek(SeekFrom::Start(addr as u64))
-            .map_err(|e| anyhow::anyhow!("Failed to seek to address 0x{:x}: {}", addr, e))?;
+        match self.write_via_proc_mem(addr, data) {
+            Ok(()) => Ok(()),
+            Err(e) if matches!(e.raw_os_error(), Some(nix::libc::EACCES) | Some(nix::libc::EPERM)) => {
+                self.write_via_ptrace(addr, data)
+            }
+            Err(e) => Err(anyhow::anyhow!(
+                "Failed to write {} bytes to 0x{:x} via {}: {}",
+                data.len(),
+                addr,
+                self.mem_path(),
+                e
+            )),
+        }
+    }
 
-        // データを書き込む
+    /// /proc/pid/mem経由でメモリに書き込む（内部実装）
+    fn write_via_proc_mem(&self, addr: usize, data: &[u8]) -> std::io::Result<()> {
+        let mut file = OpenOptions::new().write(true).open(self.mem_path())?;
+        file.seek(SeekFrom::Start(addr as u64))?;
         file.write_all(data)
-            .map_err(|e| anyhow::anyhow!("Failed to write {} bytes to 0x{:x}: {}", data.len(), addr, e))?;
+    }
+
+    /// PTRACE_POKEDATAを使用してメモリにデータを書き込む
+    ///
+    /// /proc/pid/memに書き込めない場合のフォールバック。
+    /// word単位で書き込むため、端の部分的なwordは読み取ってから書き戻します。
+    pub fn write_via_ptrace(&self, addr: usize, data: &[u8]) -> Result<()> {
+        use nix::sys::ptrace;
+
+        let word_size = std::mem::size_of::<usize>();
+        let first_word = addr - addr % word_size;
+        let end = addr + data.len();
+
+        let mut word_addr = first_word;
+        while word_addr < end {
+            let ptr = word_addr as *mut std::ffi::c_void;
+            let start = addr.saturating_sub(word_addr);
+            let stop = (end - word_addr).min(word_size);
+
+            let mut bytes = if start == 0 && stop == word_size {
+                [0u8; 8]
+            } else {
+                ptrace::read(self.pid, ptr)
+                    .map_err(|e| anyhow::anyhow!("Failed to read via ptrace at 0x{:x}: {}", word_addr, e))?
+                    .to_ne_bytes()
+            };
+            let src = word_addr + start - addr;
+            bytes[start..stop].copy_from_slice(&data[src..src + (stop - start)]);
+
+            ptrace::write(self.pid, ptr, i64::from_ne_bytes(bytes))
+                .map_err(|e| anyhow::anyhow!("Failed to write via ptrace at 0x{:x}: {}", word_addr, e))?;
+            word_addr += word_size;
+        }
 
         Ok(())
     }
@@ -219,6 +387,32 @@ impl Memory {
         self.write_typed(addr, &value)
     }
 
+    /// メモリ領域の保護属性を変更する
+    ///
+    /// デバッグ対象に mprotect システムコールを注入して実行します（プロセスは停止中である必要があります）。
+    /// 範囲はページ境界に揃えられます。
+    ///
+    /// # Returns
+    /// 実際に変更した範囲（ページ境界に揃えた開始アドレスと長さ）
+    pub fn set_protection(&self, addr: usize, len: usize, prot: Protection) -> Result<(usize, usize)> {
+        let page_size = unsafe { nix::libc::sysconf(nix::libc::_SC_PAGESIZE) } as usize;
+        let start = addr & !(page_size - 1);
+        let end = (addr + len.max(1)).div_ceil(page_size) * page_size;
+        let aligned_len = end - start;
+
+        let ret = crate::inject::inject_syscall(
+            self.pid.as_raw(),
+            nix::libc::SYS_mprotect,
+            &[start as u64, aligned_len as u64, prot.to_prot_flags() as u64],
+        )?;
+        if ret < 0 {
+            let errno = nix::errno::Errno::from_raw(-ret as i32);
+            return Err(anyhow::anyhow!("mprotect(0x{:x}, {}, {}) failed: {}", start, aligned_len, prot, errno));
+        }
+
+        Ok((start, aligned_len))
+    }
+
     /// /proc/pid/maps を解析してメモリマッピング情報を取得する
     pub fn get_mappings(&self) -> Result<Vec<MemoryMapping>> {
         let maps_path = format!("/proc/{}/maps", self.pid);
@@ -250,9 +444,25 @@ impl Memory {
 
             // パーミッションをパース
             let perms = parts[1];
-            let readable = perms.chars().next() == Some('r');
+            let readable = perms.starts_with('r');
             let writable = perms.chars().nth(1) == Some('w');
             let executable = perms.chars().nth(2) == Some('x');
+            let offset = parts
+                .get(2)
+                .and_then(|o| usize::from_str_radix(o, 16).ok())
+                .unwrap_or(0);
+
+            // パス名（空白を含む場合があるので6番目以降を連結）
+            let path = if parts.len() > 5 {
+                Some(parts[5..].join(" "))
+            } else {
+                None
+            };
+            // "[heap]" などの疑似パスはそのまま残す
+            let path = match (&self.namespace, path) {
+                (Some(ns), Some(p)) if p.starts_with('/') => Some(ns.host_path(&p).to_string_lossy().into_owned()),
+                (_, path) => path,
+            };
 
             mappings.push(MemoryMapping {
                 start,
@@ -260,6 +470,8 @@ impl Memory {
                 readable,
                 writable,
                 executable,
+                offset,
+                path,
             });
         }
 
@@ -275,45 +487,25 @@ impl Memory {
     /// 実行可能ファイルのベースアドレスを取得する
     ///
     /// PIE（Position Independent Executable）の場合、実行時にランダムなアドレスにロードされます。
-    /// このメソッドは、実行可能ファイルの最初の実行可能セグメントのベースアドレスを返します。
+    /// 最初の実行可能セグメントを持つファイルについて、最も低いアドレスのマッピング
+    /// （ファイル先頭を含む最初の PT_LOAD）の開始アドレスからオフセットを引いた値を返します。
+    ///
+    /// 実行可能セグメント自体の `start - offset` は、lld のように仮想アドレスと
+    /// ファイルオフセットのずれがセグメントごとに異なるレイアウトでは誤った値になります。
     pub fn get_base_address(&self) -> Result<usize> {
-        let maps_path = format!("/proc/{}/maps", self.pid);
-        let file = File::open(&maps_path)
-            .map_err(|e| anyhow::anyhow!("Failed to open {}: {}", maps_path, e))?;
-        let reader = BufReader::new(file);
-
-        // 最初の実行可能セグメントを見つけて、ファイルオフセットを引く
-        for line in reader.lines() {
-            let line = line?;
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() < 6 {
-                continue;
-            }
-
-            // アドレス範囲、パーミッション、オフセットをパース
-            let addr_parts: Vec<&str> = parts[0].split('-').collect();
-            if addr_parts.len() != 2 {
-                continue;
-            }
-
-            let perms = parts[1];
-            let offset_str = parts[2];
-
-            // 実行可能（x）フラグがあるセグメントを探す
-            if perms.chars().nth(2) == Some('x') {
-                let start = usize::from_str_radix(addr_parts[0], 16)
-                    .map_err(|e| anyhow::anyhow!("Failed to parse base address: {}", e))?;
-                let offset = usize::from_str_radix(offset_str, 16)
-                    .map_err(|e| anyhow::anyhow!("Failed to parse segment offset: {}", e))?;
-
-                // PIEの場合、シンボルオフセットはファイル内のオフセットなので、
-                // セグメントのファイルオフセットを引いた値を返す
-                let base = start - offset;
-                return Ok(base);
-            }
-        }
+        let mappings = self.get_mappings()?;
+        let exe_path = mappings
+            .iter()
+            .find(|m| m.executable && m.path.as_deref().is_some_and(|p| !p.starts_with('[')))
+            .and_then(|m| m.path.as_deref())
+            .ok_or_else(|| anyhow::anyhow!("Could not find executable segment in memory mappings"))?;
 
-        Err(anyhow::anyhow!("Could not find executable segment in memory mappings"))
+        mappings
+            .iter()
+            .filter(|m| m.path.as_deref() == Some(exe_path))
+            .min_by_key(|m| m.start)
+            .map(|m| m.start - m.offset)
+            .ok_or_else(|| anyhow::anyhow!("Could not find executable segment in memory mappings"))
     }
 
     /// PTRACE_PEEKDATAを使用してメモリからデータを読み取る
@@ -366,4 +558,102 @@ impl kokia_dwarf::MemoryReader for Memory {
     fn read(&self, addr: usize, size: usize) -> Result<Vec<u8>> {
         self.read(addr, size)
     }
+
+    fn is_mapped(&self, addr: usize) -> bool {
+        // /proc/pid/maps が読めない場合は読み取りを試みさせる
+        self.is_mapped(addr).unwrap_or(true)
+    }
+
+    fn read_prefix(&self, addr: usize, size: usize) -> Vec<u8> {
+        match self.read_partial(addr, size) {
+            Ok(partial) => partial.prefix().to_vec(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_protection() {
+        let prot = Protection::parse("r-x").unwrap();
+        assert_eq!(prot, Protection { read: true, write: false, execute: true });
+        assert_eq!(prot.to_prot_flags(), nix::libc::PROT_READ | nix::libc::PROT_EXEC);
+        assert_eq!(prot.to_string(), "r-x");
+        assert_eq!(Protection::parse("").unwrap().to_prot_flags(), nix::libc::PROT_NONE);
+        assert!(Protection::parse("rwz").is_err());
+    }
+
+    #[test]
+    fn test_write_via_ptrace_unaligned() {
+        use nix::libc;
+        use nix::sys::{ptrace, wait::waitpid};
+        use nix::unistd::{fork, ForkResult};
+
+        static BUFFER: [u8; 32] = [0x11; 32];
+
+        match unsafe { fork() }.unwrap() {
+            ForkResult::Child => unsafe {
+                libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0);
+                libc::raise(libc::SIGSTOP);
+                libc::_exit(0);
+            },
+            ForkResult::Parent { child } => {
+                waitpid(child, None).unwrap();
+                let memory = Memory::new(child.as_raw());
+                let addr = BUFFER.as_ptr() as usize;
+
+                // word 境界をまたぐ 10 バイトを書き込み、前後のバイトが保たれることを確認する
+                memory.write_via_ptrace(addr + 3, &[0xaa; 10]).unwrap();
+                let bytes = memory.read_via_ptrace(addr, 16).unwrap();
+                let mut expected = [0x11u8; 16];
+                expected[3..13].fill(0xaa);
+                assert_eq!(bytes, expected);
+
+                ptrace::kill(child).ok();
+                waitpid(child, None).ok();
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_partial_with_hole() {
+        use nix::libc;
+
+        // 3 ページ確保して中央のページを解放する
+        let len = PAGE_SIZE * 3;
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(base, libc::MAP_FAILED);
+        let base = base as usize;
+        unsafe {
+            std::ptr::write_bytes(base as *mut u8, 0xab, len);
+            libc::munmap((base + PAGE_SIZE) as *mut libc::c_void, PAGE_SIZE);
+        }
+
+        let memory = Memory::new(std::process::id() as i32);
+        // 先頭ページの途中から末尾ページの途中まで
+        let start = base + PAGE_SIZE - 16;
+        let result = memory.read_partial(start, PAGE_SIZE + 32).unwrap();
+
+        assert_eq!(result.holes, vec![16..16 + PAGE_SIZE]);
+        assert_eq!(result.prefix(), &[0xab; 16]);
+        assert!(result.data[16 + PAGE_SIZE..].iter().all(|&b| b == 0xab));
+        assert!(!result.is_complete());
+
+        unsafe {
+            libc::munmap(base as *mut libc::c_void, PAGE_SIZE);
+            libc::munmap((base + 2 * PAGE_SIZE) as *mut libc::c_void, PAGE_SIZE);
+        }
+    }
 }
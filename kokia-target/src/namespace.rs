@@ -0,0 +1,114 @@
+//! コンテナ内プロセスの名前空間解決
+//!
+//! コンテナ内のプロセスが見ているファイルパスや PID は、ホスト側からは
+//! `/proc/<pid>/root` 経由や `NSpid` の対応表を通してしか辿れません。
+//! kokia 自身は setns せずホストの名前空間に留まり、パスと PID の変換だけを行います。
+
+use crate::Result;
+use std::path::{Path, PathBuf};
+
+/// デバッグ対象が属する名前空間
+///
+/// 名前空間内の任意のプロセス（ホスト側の PID）を基準にします。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetNamespace {
+    /// 基準にするプロセスのホスト側 PID
+    pid: i32,
+    /// マウント名前空間のルート（/proc/<pid>/root）
+    root: PathBuf,
+}
+
+impl TargetNamespace {
+    /// ホスト側 PID `pid` のプロセスが属する名前空間を基準にする
+    pub fn new(pid: i32) -> Result<Self> {
+        let root = PathBuf::from(format!("/proc/{}/root", pid));
+        std::fs::read_dir(&root)
+            .map_err(|e| anyhow::anyhow!("Cannot access root filesystem of process {} ({:?}): {}", pid, root, e))?;
+        Ok(Self { pid, root })
+    }
+
+    /// 基準プロセスのホスト側 PID
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// マウント名前空間のルートのホスト側パス
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// 名前空間内のパスをホスト側から開けるパスに変換する
+    ///
+    /// 相対パスは基準プロセスのカレントディレクトリ（/proc/<pid>/cwd）からの相対として扱います。
+    pub fn host_path(&self, path: impl AsRef<Path>) -> PathBuf {
+        let path = path.as_ref();
+        match path.strip_prefix("/") {
+            Ok(relative) => self.root.join(relative),
+            Err(_) => PathBuf::from(format!("/proc/{}/cwd", self.pid)).join(path),
+        }
+    }
+
+    /// 名前空間内の PID をホスト側の PID に変換する
+    ///
+    /// ホストの /proc を走査し、同じ PID 名前空間に属して `NSpid` の末尾が
+    /// `ns_pid` に一致するプロセスを探します。
+    pub fn host_pid(&self, ns_pid: i32) -> Result<i32> {
+        let pid_ns = std::fs::read_link(format!("/proc/{}/ns/pid", self.pid))
+            .map_err(|e| anyhow::anyhow!("Cannot read pid namespace of process {}: {}", self.pid, e))?;
+
+        for entry in std::fs::read_dir("/proc")?.filter_map(|entry| entry.ok()) {
+            let Some(host_pid) = entry.file_name().to_str().and_then(|s| s.parse::<i32>().ok()) else {
+                continue;
+            };
+            let same_ns = std::fs::read_link(entry.path().join("ns/pid")).is_ok_and(|ns| ns == pid_ns);
+            if !same_ns {
+                continue;
+            }
+            let Ok(status) = std::fs::read_to_string(entry.path().join("status")) else {
+                continue;
+            };
+            if parse_nspid(&status).last() == Some(&ns_pid) {
+                return Ok(host_pid);
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "No process with pid {} in the pid namespace of process {}",
+            ns_pid,
+            self.pid
+        ))
+    }
+}
+
+/// /proc/<pid>/status の NSpid 行（外側の名前空間から順に並ぶ PID）をパースする
+fn parse_nspid(status: &str) -> Vec<i32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("NSpid:"))
+        .map(|value| value.split_whitespace().filter_map(|pid| pid.parse().ok()).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nspid() {
+        assert_eq!(parse_nspid("Name:\tapp\nNSpid:\t4242\t17\t1\n"), vec![4242, 17, 1]);
+        assert!(parse_nspid("Name:\tapp\n").is_empty());
+    }
+
+    #[test]
+    fn test_own_namespace() {
+        let pid = std::process::id() as i32;
+        let ns = TargetNamespace::new(pid).unwrap();
+        assert_eq!(ns.host_path("/usr/bin/env"), PathBuf::from(format!("/proc/{}/root/usr/bin/env", pid)));
+        assert_eq!(ns.host_path("src/main.rs"), PathBuf::from(format!("/proc/{}/cwd/src/main.rs", pid)));
+        assert!(ns.host_path("/proc/self/exe").exists());
+
+        // 同じ名前空間なので、名前空間内の PID もそのままホストの PID に一致する
+        let own_ns_pid = *parse_nspid(&std::fs::read_to_string("/proc/self/status").unwrap()).last().unwrap();
+        assert_eq!(ns.host_pid(own_ns_pid).unwrap(), pid);
+    }
+}
@@ -1,7 +1,13 @@
 //! プロセス制御機能
 
-use crate::Result;
+use crate::{Result, Thread};
+use nix::errno::Errno;
+use nix::sys::ptrace;
 use nix::sys::signal::Signal;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::ffi::CString;
 use std::path::Path;
 
@@ -12,17 +18,158 @@ pub enum StopReason {
     Breakpoint,
     /// ステップ実行完了（SIGTRAP）
     Step,
+    /// ウォッチポイントのヒット（デバッグレジスタによる SIGTRAP）
+    Watchpoint,
     /// シグナル受信
     Signal(Signal),
     /// プロセス終了
     Exited(i32),
+    /// シグナルによるプロセス終了
+    Killed(Signal),
     /// その他の停止
     Other,
 }
 
+impl StopReason {
+    /// プロセスが終了した（終了コードかシグナルで）か
+    pub fn is_terminated(&self) -> bool {
+        matches!(self, StopReason::Exited(_) | StopReason::Killed(_))
+    }
+}
+
+/// 停止させたシグナルの詳細（PTRACE_GETSIGINFO）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignalInfo {
+    /// シグナル番号（nix が知らない番号なら None）
+    pub signal: Option<Signal>,
+    /// si_signo の値
+    pub signo: i32,
+    /// si_code（SEGV_MAPERR や SI_USER など）
+    pub code: i32,
+    pub errno: i32,
+    /// 原因となったアドレス（SIGSEGV / SIGBUS / SIGILL / SIGFPE / SIGTRAP のみ）
+    pub fault_addr: Option<u64>,
+    /// 送信元のプロセスID（kill などユーザー空間から送られた場合のみ）
+    pub sender_pid: Option<i32>,
+}
+
+/// si_code: ハードウェアブレークポイント・ウォッチポイントによる SIGTRAP
+const TRAP_HWBKPT: i32 = 4;
+
+impl SignalInfo {
+    /// デバッグレジスタ（ハードウェアブレークポイント・ウォッチポイント）によるトラップか
+    pub fn is_hardware_trap(&self) -> bool {
+        self.signal == Some(Signal::SIGTRAP) && self.code == TRAP_HWBKPT
+    }
+
+    fn from_siginfo(info: &nix::libc::siginfo_t) -> Self {
+        use nix::libc;
+
+        let fault_addr = match info.si_signo {
+            libc::SIGSEGV | libc::SIGBUS | libc::SIGILL | libc::SIGFPE | libc::SIGTRAP => {
+                Some(unsafe { info.si_addr() } as u64)
+            }
+            _ => None,
+        };
+        // si_code が 0 以下ならユーザー空間から送られたシグナル（SI_USER, SI_QUEUE, SI_TKILL など）
+        let sender_pid = (info.si_code <= 0).then(|| unsafe { info.si_pid() });
+        Self {
+            signal: Signal::try_from(info.si_signo).ok(),
+            signo: info.si_signo,
+            code: info.si_code,
+            errno: info.si_errno,
+            fault_addr,
+            sender_pid,
+        }
+    }
+}
+
+/// プロセス起動オプション
+#[derive(Debug, Clone, Default)]
+pub struct SpawnOptions {
+    /// 子プロセスを独立したプロセスグループに配置する
+    ///
+    /// 端末からの SIGINT（Ctrl-C）がデバッグ対象に直接届かなくなる。
+    pub new_process_group: bool,
+}
+
+/// ptrace しているスレッド（LWP）の状態
+#[derive(Debug, Default)]
+struct Lwps {
+    /// ptrace しているスレッド
+    traced: BTreeSet<i32>,
+    /// こちらが止めるために送った SIGSTOP がまだ届いていないスレッド（届いたら黙って再開する）
+    sigstops: HashSet<i32>,
+    /// 他のスレッドを止める途中で受け取った停止（次に再開する代わりに報告する）
+    pending: VecDeque<WaitStatus>,
+    /// clone で生まれ、最初の停止がまだ届いていないスレッド
+    unstarted: HashSet<i32>,
+    /// シグナルで止まったスレッドと、再開するときに届けるそのシグナル
+    signals: HashMap<i32, Signal>,
+    /// 新しいスレッドが走り出す前に書き込むデバッグレジスタ（番号, 値）
+    debug_regs: Vec<(usize, u64)>,
+}
+
 /// デバッグ対象のプロセス
+///
+/// すべてのスレッドを ptrace し（clone で増えたスレッドも PTRACE_O_TRACECLONE で追います）、
+/// どれかのスレッドが止まったら残りも止めます。再開はすべてのスレッドをまとめて行い、
+/// ステップ実行は選択中のスレッドだけを進めます。
 pub struct Process {
-    pid: nix::unistd::Pid,
+    pid: Pid,
+    /// kokia が起動したプロセスか（アタッチの場合は false）
+    spawned: bool,
+    /// 実行を再開した回数
+    resumes: Cell<u64>,
+    lwps: RefCell<Lwps>,
+    /// 直前に停止を報告したスレッド（ステップ実行の対象）
+    current: Cell<i32>,
+}
+
+/// スレッドは clone の子なので __WALL が要る。__WNOTHREAD で、kokia の別のスレッドが
+/// ptrace しているプロセス（並列に走るテストなど）の停止を横取りしないようにする
+fn wait_flags(nohang: bool) -> WaitPidFlag {
+    let flags = WaitPidFlag::__WALL | WaitPidFlag::__WNOTHREAD;
+    if nohang {
+        flags | WaitPidFlag::WNOHANG
+    } else {
+        flags
+    }
+}
+
+thread_local! {
+    /// waitpid(-1) で受け取った、このスレッドが ptrace している別のプロセス（他の inferior）宛ての停止・終了
+    ///
+    /// 選択していない inferior のスレッドの停止を捨てたり、別の [`Process`] に取り込んだりしないよう、
+    /// 持ち主のプロセスが次に wait するまで取っておきます。
+    static STRAY_STATUSES: RefCell<Vec<WaitStatus>> = const { RefCell::new(Vec::new()) };
+}
+
+fn is_clone_event(event: i32) -> bool {
+    event == ptrace::Event::PTRACE_EVENT_CLONE as i32
+}
+
+/// fork した子が exec までに失敗したときの終了コード
+const CHILD_SETUP_FAILED: i32 = 127;
+
+/// fork した子で exec までの準備が失敗したら、理由を標準エラーに書いて終了する
+///
+/// fork 後の子ではメモリ確保やロックを避け、固定の文字列だけを write(2) で書きます。
+fn exit_child(step: &str, errno: Errno) -> ! {
+    for part in ["kokia: ", step, " failed in the child process: ", errno.desc(), "\n"] {
+        // SAFETY: 有効なバッファを書くだけ。失敗しても終了するので無視する
+        unsafe { nix::libc::write(nix::libc::STDERR_FILENO, part.as_ptr().cast(), part.len()) };
+    }
+    // SAFETY: 親から引き継いだ atexit やバッファを実行しないように _exit で終える
+    unsafe { nix::libc::_exit(CHILD_SETUP_FAILED) }
+}
+
+/// スレッドを指定してシグナルを送る
+fn tgkill(pid: i32, tid: i32, signal: Signal) -> Result<()> {
+    // SAFETY: 引数は整数だけのシステムコール
+    let ret = unsafe { nix::libc::syscall(nix::libc::SYS_tgkill, pid, tid, signal as i32) };
+    Errno::result(ret)?;
+    Ok(())
 }
 
 impl Process {
@@ -33,8 +180,18 @@ impl Process {
     /// プロセスは最初の命令で停止状態で返されます。
     /// これにより、メモリマッピングが完全に初期化され、ブレークポイントを安全に設定できます。
     pub fn spawn<P: AsRef<Path>>(program: P, args: &[String]) -> Result<Self> {
-        use nix::sys::ptrace;
-        use nix::sys::wait::{waitpid, WaitStatus};
+        Self::spawn_with_options(program, args, &SpawnOptions::default())
+    }
+
+    /// オプションを指定してデバッグ対象プロセスを起動する
+    ///
+    /// 起動したプロセスは孤児プロセス対策の登録表に追加され、kokia が異常終了した際に
+    /// 強制終了されます（[`crate::cleanup::install_cleanup_handlers`] を参照）。
+    pub fn spawn_with_options<P: AsRef<Path>>(
+        program: P,
+        args: &[String],
+        options: &SpawnOptions,
+    ) -> Result<Self> {
         use nix::unistd::{execve, fork, ForkResult};
 
         // プログラムパスをCStringに変換
@@ -67,7 +224,9 @@ impl Process {
                         match waitpid(child, None)? {
                             WaitStatus::Stopped(_, _) => {
                                 // メモリマッピングが初期化された
-                                Ok(Self { pid: child })
+                                crate::cleanup::register_inferior(child.as_raw());
+                                ptrace::setoptions(child, ptrace::Options::PTRACE_O_TRACECLONE)?;
+                                Ok(Self::traced(child, true))
                             }
                             status => {
                                 Err(anyhow::anyhow!(
@@ -77,29 +236,87 @@ impl Process {
                             }
                         }
                     }
+                    WaitStatus::Exited(_, CHILD_SETUP_FAILED) => {
+                        Err(anyhow::anyhow!("Failed to start {} (see the error above)", program_path))
+                    }
                     status => {
                         Err(anyhow::anyhow!("Unexpected wait status after execve: {:?}", status))
                     }
                 }
             }
             ForkResult::Child => {
+                // 子プロセスは失敗しても `?` で親のコードに戻らず、その場で終了する
+                // （戻るとデバッガの複製として走り続け、終了時の後始末で PID を kill してしまう）
+                if options.new_process_group {
+                    if let Err(errno) = nix::unistd::setpgid(Pid::from_raw(0), Pid::from_raw(0)) {
+                        exit_child("setpgid", errno);
+                    }
+                }
+
                 // 子プロセス: PTRACE_TRACEMEを設定してexecve
-                ptrace::traceme()?;
+                if let Err(errno) = ptrace::traceme() {
+                    exit_child("PTRACE_TRACEME", errno);
+                }
 
                 // execveを実行（成功すると戻ってこない）
-                execve(&program_cstring, &cstring_args, &env)?;
-
-                // execveが失敗した場合はここに到達
-                unreachable!("execve failed");
+                let Err(errno) = execve(&program_cstring, &cstring_args, &env);
+                exit_child("execve", errno);
             }
         }
     }
 
+    fn traced(pid: Pid, spawned: bool) -> Self {
+        let lwps = Lwps { traced: BTreeSet::from([pid.as_raw()]), ..Lwps::default() };
+        Self { pid, spawned, resumes: Cell::new(0), lwps: RefCell::new(lwps), current: Cell::new(pid.as_raw()) }
+    }
+
     /// 既存のプロセスにアタッチする
+    ///
+    /// メインスレッドに続けて `/proc/<pid>/task` のすべてのスレッドにアタッチし、止まるまで待ちます。
+    /// 列挙している間に増えたスレッドも拾うため、新しいスレッドが見つからなくなるまで繰り返します。
     pub fn attach(pid: i32) -> Result<Self> {
-        let pid = nix::unistd::Pid::from_raw(pid);
-        nix::sys::ptrace::attach(pid)?;
-        Ok(Self { pid })
+        let leader = Pid::from_raw(pid);
+        ptrace::attach(leader)
+            .map_err(|errno| crate::diagnostics::explain_attach_error(pid, errno))?;
+        let process = Self::traced(leader, false);
+        process.wait_attached(pid)?;
+
+        loop {
+            let new: Vec<i32> = process.threads()?.iter()
+                .map(|thread| thread.tid())
+                .filter(|tid| !process.is_traced(*tid))
+                .collect();
+            if new.is_empty() {
+                break;
+            }
+            for tid in new {
+                match ptrace::attach(Pid::from_raw(tid)) {
+                    Ok(()) => process.wait_attached(tid)?,
+                    // 列挙した後に終了した
+                    Err(Errno::ESRCH) => {}
+                    Err(errno) => return Err(errno.into()),
+                }
+            }
+        }
+        Ok(process)
+    }
+
+    /// アタッチしたスレッドが止まるのを待ち、追跡に加える
+    fn wait_attached(&self, tid: i32) -> Result<()> {
+        let lwp = Pid::from_raw(tid);
+        match self.wait_lwp(tid)? {
+            WaitStatus::Stopped(_, Signal::SIGSTOP) => {}
+            // アタッチの SIGSTOP より先に別のシグナルで止まった（SIGSTOP は後で届く）
+            WaitStatus::Stopped(_, _) => {
+                self.lwps.borrow_mut().sigstops.insert(tid);
+            }
+            // 待っている間に終了した
+            WaitStatus::Exited(..) | WaitStatus::Signaled(..) if tid != self.pid() => return Ok(()),
+            status => return Err(anyhow::anyhow!("Unexpected wait status after attach: {:?}", status)),
+        }
+        ptrace::setoptions(lwp, ptrace::Options::PTRACE_O_TRACECLONE)?;
+        self.lwps.borrow_mut().traced.insert(tid);
+        Ok(())
     }
 
     /// プロセスIDを取得する
@@ -107,83 +324,505 @@ impl Process {
         self.pid.as_raw()
     }
 
+    /// プロセス内のスレッド一覧を取得する
+    ///
+    /// `/proc/<pid>/task` を列挙し、スレッドID昇順（通常はメインスレッドが先頭）で返します。
+    pub fn threads(&self) -> Result<Vec<Thread>> {
+        let task_dir = format!("/proc/{}/task", self.pid);
+        let mut tids: Vec<i32> = std::fs::read_dir(&task_dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str().and_then(|s| s.parse().ok()))
+            .collect();
+        tids.sort_unstable();
+        Ok(tids.into_iter().map(Thread::new).collect())
+    }
+
+    /// ptrace しているスレッドID（昇順）
+    pub fn traced_threads(&self) -> Vec<i32> {
+        self.lwps.borrow().traced.iter().copied().collect()
+    }
+
+    fn is_traced(&self, tid: i32) -> bool {
+        self.lwps.borrow().traced.contains(&tid)
+    }
+
+    /// 直前に停止を報告したスレッド（[`Process::select_thread`] で選び直したらそのスレッド）
+    pub fn current_thread(&self) -> i32 {
+        self.current.get()
+    }
+
+    /// ステップ実行とシグナル情報の読み取りの対象にするスレッドを選ぶ
+    pub fn select_thread(&self, tid: i32) -> Result<()> {
+        if !self.is_traced(tid) {
+            return Err(anyhow::anyhow!("Thread {} is not traced", tid));
+        }
+        self.current.set(tid);
+        Ok(())
+    }
+
+    /// clone で生まれたスレッドに、走り出す前に書き込むデバッグレジスタを設定する
+    ///
+    /// デバッグレジスタはスレッドごとの状態で、新しいスレッドには引き継がれません。
+    /// ウォッチポイントを後から生まれたスレッドでも効かせるために使います。
+    pub fn set_inherited_debug_regs(&self, regs: Vec<(usize, u64)>) {
+        self.lwps.borrow_mut().debug_regs = regs;
+    }
+
+    /// 実行を再開した回数
+    ///
+    /// 停止中に読んだメモリやレジスタから求めた値が、まだ使えるかの判定に使います。
+    pub fn resume_count(&self) -> u64 {
+        self.resumes.get()
+    }
+
+    fn resumed(&self) {
+        self.resumes.set(self.resumes.get() + 1);
+    }
+
     /// プロセスを実行継続する
+    ///
+    /// 前の停止で他のスレッドの停止も受け取っていたら、どのスレッドも再開せずに
+    /// [`Process::try_wait`] でその停止を報告します。
     pub fn continue_execution(&self) -> Result<()> {
-        nix::sys::ptrace::cont(self.pid, None)?;
+        self.resumed();
+        if self.lwps.borrow().pending.is_empty() {
+            self.resume_all()?;
+        }
         Ok(())
     }
 
     /// プロセスを実行継続して停止イベントを待機する
     ///
     /// プロセスを実行継続し、次の停止イベント（ブレークポイント、シグナル、終了など）まで待機します。
+    /// 前の停止で他のスレッドの停止も受け取っていたら、再開せずにそれを返します。
     pub fn continue_and_wait(&self) -> Result<StopReason> {
-        use nix::sys::ptrace;
-        use nix::sys::wait::{waitpid, WaitStatus};
+        self.resumed();
+        if let Some(stop) = self.take_pending() {
+            return Ok(stop);
+        }
+        self.resume_all()?;
+        Ok(self.wait_stop(false)?.unwrap_or(StopReason::Other))
+    }
+
+    /// 1命令だけ実行して停止する（ステップ実行）
+    ///
+    /// 選択中のスレッドの1命令だけを実行し（他のスレッドは止めたまま）、次の停止イベントまで待機します。
+    /// 関数呼び出しの中にも入ります（ステップイン）。
+    pub fn step(&self) -> Result<StopReason> {
+        self.resumed();
+        let tid = self.current.get();
+        let lwp = Pid::from_raw(tid);
+        loop {
+            ptrace::step(lwp, self.take_signal(tid))?;
+            let status = self.wait_lwp(tid)?;
+            match status {
+                // 前に送った SIGSTOP が届いた（命令はまだ実行していない）
+                WaitStatus::Stopped(_, Signal::SIGSTOP) if self.take_sigstop(tid) => {}
+                // clone の途中で止まった。新しいスレッドは止めたままにしてステップを続ける
+                WaitStatus::PtraceEvent(_, _, event) if is_clone_event(event) => self.adopt_clone(tid, true)?,
+                // ステップ中のスレッドが終了した。以降はメインスレッドを選ぶ
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) if tid != self.pid() => {
+                    self.forget(tid);
+                    self.current.set(self.pid());
+                    return Ok(StopReason::Other);
+                }
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    self.lwps.replace(Lwps::default());
+                    return Ok(stop_reason_from(status));
+                }
+                // SIGTRAPはステップ実行完了
+                // （ブレークポイントヒットの場合は、呼び出し元で判定する）
+                WaitStatus::Stopped(_, Signal::SIGTRAP) => return Ok(StopReason::Step),
+                status => return Ok(self.report(status)),
+            }
+        }
+    }
 
-        // プロセスを実行継続
-        ptrace::cont(self.pid, None)?;
+    /// プロセスを停止する（メインスレッドに SIGSTOP を送る）
+    pub fn stop(&self) -> Result<()> {
+        tgkill(self.pid(), self.pid(), Signal::SIGSTOP)
+    }
 
-        // 停止イベントを待機
-        let status = waitpid(self.pid, None)?;
+    /// 実行中のプロセスが停止・終了していれば、待たずにその理由を返す
+    pub fn try_wait(&self) -> Result<Option<StopReason>> {
+        if let Some(stop) = self.take_pending() {
+            return Ok(Some(stop));
+        }
+        self.wait_stop(true)
+    }
 
-        match status {
-            WaitStatus::Stopped(_, signal) => {
-                // SIGTRAPはブレークポイントヒット
-                if signal == Signal::SIGTRAP {
-                    Ok(StopReason::Breakpoint)
-                } else {
-                    Ok(StopReason::Signal(signal))
-                }
+    /// プロセスがもう存在しないか（終了した・ゾンビになった）
+    ///
+    /// waitpid で状態を見ると停止イベントを消費してしまうので /proc を見ます。
+    /// ゾンビになっていたら、ここで回収します。
+    pub fn is_gone(&self) -> bool {
+        match crate::procfs::process_state(self.pid()) {
+            None => true,
+            Some(state) if crate::procfs::is_dead_state(state) => {
+                let _ = waitpid(self.pid, Some(WaitPidFlag::WNOHANG));
+                true
             }
-            WaitStatus::Exited(_, code) => Ok(StopReason::Exited(code)),
-            WaitStatus::Signaled(_, signal, _) => {
-                Ok(StopReason::Signal(signal))
+            Some(_) => false,
+        }
+    }
+
+    /// 直前の停止を引き起こしたシグナルの詳細を取得する（選択中のスレッドについて）
+    pub fn signal_info(&self) -> Result<SignalInfo> {
+        let info = ptrace::getsiginfo(Pid::from_raw(self.current.get()))?;
+        Ok(SignalInfo::from_siginfo(&info))
+    }
+
+    /// 実行中のプロセスのすべてのスレッドを SIGSTOP で止め、停止するまで待機する
+    ///
+    /// 止める途中で別の停止（ブレークポイントなど）を受け取った場合はその理由を返します。
+    pub fn interrupt_and_wait(&self) -> Result<StopReason> {
+        if let Some(stop) = self.try_wait()? {
+            return Ok(stop);
+        }
+        self.stop_others(None)?;
+        if let Some(stop) = self.take_pending() {
+            return Ok(stop);
+        }
+        let first = self.lwps.borrow().traced.first().copied().unwrap_or(self.pid());
+        self.current.set(first);
+        Ok(StopReason::Signal(Signal::SIGSTOP))
+    }
+
+    /// 止まっているすべてのスレッドを再開する
+    fn resume_all(&self) -> Result<()> {
+        let current = self.current.get();
+        for tid in self.traced_threads() {
+            match ptrace::cont(Pid::from_raw(tid), self.take_signal(tid)) {
+                Ok(()) => {}
+                // 止まっている間に終了したスレッド（終了は wait で受け取る）
+                Err(Errno::ESRCH) if tid != current => {}
+                Err(errno) => return Err(errno.into()),
             }
-            _ => Ok(StopReason::Other),
         }
+        Ok(())
     }
 
-    /// 1命令だけ実行して停止する（ステップ実行）
+    /// どれかのスレッドが報告すべき停止をするまで待つ（`nohang` なら待たない）
     ///
-    /// プロセスの1命令だけを実行し、次の停止イベントまで待機します。
-    /// 関数呼び出しの中にも入ります（ステップイン）。
-    pub fn step(&self) -> Result<StopReason> {
-        use nix::sys::ptrace;
-        use nix::sys::wait::{waitpid, WaitStatus};
+    /// clone の通知、こちらが送った SIGSTOP、メイン以外のスレッドの終了はここで片付けて
+    /// 待ち続けます。報告する停止を受け取ったら、残りのスレッドも止めます。
+    /// 別のプロセスのスレッドの停止・終了は [`STRAY_STATUSES`] に取っておきます。
+    fn wait_stop(&self, nohang: bool) -> Result<Option<StopReason>> {
+        loop {
+            let status = match self.take_stray(|_| true) {
+                Some(status) => status,
+                None => waitpid(Pid::from_raw(-1), Some(wait_flags(nohang)))?,
+            };
+            let Some(tid) = status.pid().map(Pid::as_raw) else {
+                return Ok(None);
+            };
+            if !self.owns(&status) {
+                STRAY_STATUSES.with_borrow_mut(|strays| strays.push(status));
+                continue;
+            }
+            match status {
+                WaitStatus::PtraceEvent(_, _, event) if is_clone_event(event) => {
+                    self.adopt_clone(tid, false)?;
+                    self.cont_lwp(tid)?;
+                }
+                WaitStatus::Stopped(_, Signal::SIGSTOP) if self.take_sigstop(tid) => self.cont_lwp(tid)?,
+                WaitStatus::Stopped(_, Signal::SIGSTOP) if self.lwps.borrow_mut().unstarted.remove(&tid) => {
+                    self.start_thread(tid)?;
+                    self.cont_lwp(tid)?;
+                }
+                // clone の通知より先に届いた、新しいスレッドの最初の停止（スレッドグループは確かめてある）
+                WaitStatus::Stopped(_, Signal::SIGSTOP) if !self.is_traced(tid) => {
+                    self.lwps.borrow_mut().traced.insert(tid);
+                    self.start_thread(tid)?;
+                    self.cont_lwp(tid)?;
+                }
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) if tid != self.pid() => self.forget(tid),
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    self.lwps.replace(Lwps::default());
+                    return Ok(Some(stop_reason_from(status)));
+                }
+                status => {
+                    self.current.set(tid);
+                    self.stop_others(Some(tid))?;
+                    return Ok(Some(self.report(status)));
+                }
+            }
+        }
+    }
 
-        // 1命令だけ実行
-        ptrace::step(self.pid, None)?;
+    /// `except` 以外のスレッドを SIGSTOP で止める
+    ///
+    /// 止める前に別の理由で止まったスレッドの停止は、[`Lwps::pending`] に取っておきます。
+    fn stop_others(&self, except: Option<i32>) -> Result<()> {
+        for tid in self.traced_threads().into_iter().filter(|&tid| Some(tid) != except) {
+            // 前に送った SIGSTOP か、新しいスレッドの最初の停止がまだ届いていなければ、それで止まるのを待つ
+            // （止まったまま wait していないだけのこともあるので、重ねて送ると2回止まる）
+            let unstarted = self.lwps.borrow_mut().unstarted.remove(&tid);
+            if !unstarted && !self.take_sigstop(tid) && tgkill(self.pid(), tid, Signal::SIGSTOP).is_err() {
+                self.forget(tid);
+                continue;
+            }
+            match self.wait_lwp(tid)? {
+                WaitStatus::Stopped(_, Signal::SIGSTOP) if unstarted => self.start_thread(tid)?,
+                WaitStatus::Stopped(_, Signal::SIGSTOP) => {}
+                WaitStatus::PtraceEvent(_, _, event) if is_clone_event(event) => {
+                    self.adopt_clone(tid, true)?;
+                    self.lwps.borrow_mut().sigstops.insert(tid);
+                }
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) if tid != self.pid() => self.forget(tid),
+                status => {
+                    let mut lwps = self.lwps.borrow_mut();
+                    lwps.pending.push_back(status);
+                    lwps.sigstops.insert(tid);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// clone で増えたスレッドを追跡に加える
+    ///
+    /// 新しいスレッドは最初に SIGSTOP で止まります。`keep_stopped` ならその停止をここで受け取って
+    /// 止めたままにし、そうでなければ届いたときに再開します。
+    fn adopt_clone(&self, parent: i32, keep_stopped: bool) -> Result<()> {
+        let tid = ptrace::getevent(Pid::from_raw(parent))? as i32;
+        // 最初の停止がもう届いていて、追跡に加えてある
+        if !self.lwps.borrow_mut().traced.insert(tid) {
+            return Ok(());
+        }
+        if keep_stopped {
+            self.wait_lwp(tid)?;
+            self.start_thread(tid)?;
+        } else {
+            self.lwps.borrow_mut().unstarted.insert(tid);
+        }
+        Ok(())
+    }
 
-        // 停止イベントを待機
-        let status = waitpid(self.pid, None)?;
+    /// 最初の停止で止まっている新しいスレッドに、引き継ぐ状態を書き込む
+    fn start_thread(&self, tid: i32) -> Result<()> {
+        let registers = crate::Registers::new(tid);
+        for &(index, value) in &self.lwps.borrow().debug_regs {
+            registers.write_debug_reg(index, value)?;
+        }
+        Ok(())
+    }
 
+    /// 取っておいた停止を報告する（報告したスレッドを選ぶ）
+    fn take_pending(&self) -> Option<StopReason> {
+        let status = self.lwps.borrow_mut().pending.pop_front()?;
         match status {
-            WaitStatus::Stopped(_, signal) => {
-                // SIGTRAPはステップ実行完了
-                // （ブレークポイントヒットの場合は、呼び出し元で判定する）
-                if signal == Signal::SIGTRAP {
-                    Ok(StopReason::Step)
-                } else {
-                    Ok(StopReason::Signal(signal))
+            WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                self.lwps.replace(Lwps::default());
+            }
+            _ => {
+                if let Some(tid) = status.pid() {
+                    self.current.set(tid.as_raw());
                 }
             }
-            WaitStatus::Exited(_, code) => Ok(StopReason::Exited(code)),
-            WaitStatus::Signaled(_, signal, _) => {
-                Ok(StopReason::Signal(signal))
+        }
+        Some(self.report(status))
+    }
+
+    /// スレッドの停止を報告する
+    ///
+    /// SIGTRAP とこちらの SIGSTOP 以外のシグナルで止まったら、次にそのスレッドを再開するときに
+    /// シグナルを届けます（握りつぶすと、フォールトした命令をもう一度実行して止まり続ける）。
+    fn report(&self, status: WaitStatus) -> StopReason {
+        if let WaitStatus::Stopped(tid, signal) = status {
+            if signal != Signal::SIGTRAP && signal != Signal::SIGSTOP {
+                self.lwps.borrow_mut().signals.insert(tid.as_raw(), signal);
             }
-            _ => Ok(StopReason::Other),
         }
+        stop_reason_from(status)
     }
 
-    /// プロセスを停止する（シグナルを送信）
-    pub fn stop(&self) -> Result<()> {
-        nix::sys::signal::kill(self.pid, nix::sys::signal::Signal::SIGSTOP)?;
-        Ok(())
+    /// 再開するときに届けるシグナルを取り出す
+    fn take_signal(&self, tid: i32) -> Option<Signal> {
+        self.lwps.borrow_mut().signals.remove(&tid)
+    }
+
+    /// スレッド `tid` の次の停止・終了を待つ（他のプロセス宛てとして取っておいたものがあればそれを返す）
+    fn wait_lwp(&self, tid: i32) -> Result<WaitStatus> {
+        if let Some(status) = self.take_stray(|status| status.pid() == Some(Pid::from_raw(tid))) {
+            return Ok(status);
+        }
+        Ok(waitpid(Pid::from_raw(tid), Some(wait_flags(false)))?)
+    }
+
+    /// 取っておいた停止・終了のうち、このプロセスのもので `filter` に合う最初のものを取り出す
+    fn take_stray(&self, filter: impl Fn(&WaitStatus) -> bool) -> Option<WaitStatus> {
+        STRAY_STATUSES.with_borrow_mut(|strays| {
+            let index = strays.iter().position(|status| filter(status) && self.owns(status))?;
+            Some(strays.remove(index))
+        })
+    }
+
+    /// waitpid(-1) で受け取った停止・終了がこのプロセスのスレッドのものか
+    ///
+    /// clone の通知より先に届いた新しいスレッドの最初の停止は、まだ追跡していないので
+    /// /proc のスレッドグループで見分けます。
+    fn owns(&self, status: &WaitStatus) -> bool {
+        let Some(tid) = status.pid().map(Pid::as_raw) else {
+            return true;
+        };
+        if self.is_traced(tid) || self.lwps.borrow().unstarted.contains(&tid) {
+            return true;
+        }
+        matches!(status, WaitStatus::Stopped(_, Signal::SIGSTOP))
+            && crate::procfs::thread_group(tid) == Some(self.pid())
+    }
+
+    fn take_sigstop(&self, tid: i32) -> bool {
+        self.lwps.borrow_mut().sigstops.remove(&tid)
+    }
+
+    fn cont_lwp(&self, tid: i32) -> Result<()> {
+        match ptrace::cont(Pid::from_raw(tid), None) {
+            Ok(()) | Err(Errno::ESRCH) => Ok(()),
+            Err(errno) => Err(errno.into()),
+        }
+    }
+
+    /// 終了したスレッドを追跡から外す
+    fn forget(&self, tid: i32) {
+        let mut lwps = self.lwps.borrow_mut();
+        lwps.traced.remove(&tid);
+        lwps.sigstops.remove(&tid);
+        lwps.unstarted.remove(&tid);
+        lwps.signals.remove(&tid);
+    }
+}
+
+/// waitpid の結果を停止理由に変換する
+fn stop_reason_from(status: WaitStatus) -> StopReason {
+    match status {
+        WaitStatus::Stopped(_, Signal::SIGTRAP) => StopReason::Breakpoint,
+        WaitStatus::Stopped(_, signal) => StopReason::Signal(signal),
+        WaitStatus::Exited(_, code) => StopReason::Exited(code),
+        WaitStatus::Signaled(_, signal, _) => StopReason::Killed(signal),
+        _ => StopReason::Other,
     }
 }
 
 impl Drop for Process {
     fn drop(&mut self) {
-        let _ = nix::sys::ptrace::detach(self.pid, None);
+        if self.spawned {
+            crate::cleanup::unregister_inferior(self.pid.as_raw());
+        }
+        for tid in self.traced_threads() {
+            let _ = ptrace::detach(Pid::from_raw(tid), None);
+        }
+        let _ = STRAY_STATUSES.try_with(|strays| strays.borrow_mut().retain(|status| !self.owns(status)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static WORKER_DONE: AtomicBool = AtomicBool::new(false);
+    static mut WORKER_STACK: [u8; 64 * 1024] = [0; 64 * 1024];
+
+    extern "C" fn worker(_: *mut nix::libc::c_void) -> nix::libc::c_int {
+        unsafe {
+            std::arch::asm!("int3");
+            WORKER_DONE.store(true, Ordering::SeqCst);
+            nix::libc::syscall(nix::libc::SYS_exit, 0);
+        }
+        0
+    }
+
+    #[test]
+    fn test_spawn_failure_does_not_return_in_child() {
+        // exec に失敗した子は親のコードに戻らず、決まった終了コードで終わる
+        let options = SpawnOptions { new_process_group: true };
+        let error = Process::spawn_with_options("/nonexistent/kokia-fixture", &[], &options).err().unwrap();
+        assert!(error.to_string().contains("Failed to start"), "{}", error);
+    }
+
+    #[test]
+    fn test_wait_keeps_other_inferiors_exit() {
+        let sleeper = Process::spawn("/bin/sleep", &["0.3".to_string()]).unwrap();
+        let quick = Process::spawn("/bin/false", &[]).unwrap();
+        quick.continue_execution().unwrap();
+        // sleeper を待つ間に届いた quick の終了は、quick が wait するまで取っておく
+        assert_eq!(sleeper.continue_and_wait().unwrap(), StopReason::Exited(0));
+        assert_eq!(quick.try_wait().unwrap(), Some(StopReason::Exited(1)));
+    }
+
+    #[test]
+    fn test_breakpoint_on_cloned_thread() {
+        use nix::libc;
+        use nix::unistd::{fork, ForkResult};
+
+        match unsafe { fork() }.unwrap() {
+            ForkResult::Child => unsafe {
+                libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0);
+                libc::raise(libc::SIGSTOP);
+                // fork した子ではメモリを確保せずにスレッドを作る
+                let stack = std::ptr::addr_of_mut!(WORKER_STACK) as *mut u8;
+                let flags = libc::CLONE_VM | libc::CLONE_FS | libc::CLONE_FILES | libc::CLONE_SIGHAND | libc::CLONE_THREAD;
+                libc::clone(worker, stack.add(64 * 1024) as *mut libc::c_void, flags, std::ptr::null_mut());
+                while !WORKER_DONE.load(Ordering::SeqCst) {
+                    libc::sched_yield();
+                }
+                libc::_exit(0);
+            },
+            ForkResult::Parent { child } => {
+                waitpid(child, None).unwrap();
+                ptrace::setoptions(child, ptrace::Options::PTRACE_O_TRACECLONE).unwrap();
+                let process = Process::traced(child, false);
+
+                // 新しいスレッドの INT3 は、そのスレッドの停止として報告される
+                assert_eq!(process.continue_and_wait().unwrap(), StopReason::Breakpoint);
+                let worker = process.current_thread();
+                assert_ne!(worker, child.as_raw());
+                assert_eq!(process.traced_threads().len(), 2);
+                assert!(process.traced_threads().contains(&worker));
+                assert_eq!(process.signal_info().unwrap().signal, Some(Signal::SIGTRAP));
+
+                // ワーカーの終了は報告せず、プロセスの終了まで進む
+                assert_eq!(process.continue_and_wait().unwrap(), StopReason::Exited(0));
+                assert!(process.traced_threads().is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_signal_info_from_siginfo() {
+        use nix::libc;
+        use nix::sys::{ptrace, wait::waitpid};
+        use nix::unistd::{fork, ForkResult};
+
+        match unsafe { fork() }.unwrap() {
+            ForkResult::Child => unsafe {
+                libc::ptrace(libc::PTRACE_TRACEME, 0, 0, 0);
+                libc::raise(libc::SIGSTOP);
+                std::ptr::write_volatile(0x10 as *mut u8, 0);
+                libc::_exit(0);
+            },
+            ForkResult::Parent { child } => {
+                // raise() による SIGSTOP はユーザー空間から送られたので送信元がわかる
+                waitpid(child, None).unwrap();
+                let stop = SignalInfo::from_siginfo(&ptrace::getsiginfo(child).unwrap());
+                assert_eq!(stop.signal, Some(Signal::SIGSTOP));
+                assert_eq!(stop.sender_pid, Some(child.as_raw()));
+                assert_eq!(stop.fault_addr, None);
+
+                // 不正なアドレスへの書き込みはカーネルが送るので、原因のアドレスが入る
+                ptrace::cont(child, None).unwrap();
+                waitpid(child, None).unwrap();
+                let segv = SignalInfo::from_siginfo(&ptrace::getsiginfo(child).unwrap());
+                assert_eq!(segv.signal, Some(Signal::SIGSEGV));
+                assert_eq!(segv.code, 1); // SEGV_MAPERR
+                assert_eq!(segv.fault_addr, Some(0x10));
+                assert_eq!(segv.sender_pid, None);
+
+                let _ = ptrace::kill(child);
+                let _ = waitpid(child, None);
+            }
+        }
     }
 }
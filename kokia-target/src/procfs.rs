@@ -0,0 +1,221 @@
+//! /proc からのプロセス情報取得
+
+use crate::Result;
+use std::path::PathBuf;
+
+/// デバッグ対象プロセスのメタデータ
+#[derive(Debug, Clone)]
+pub struct ProcessInfo {
+    /// プロセスID
+    pub pid: i32,
+    /// 実行ファイルのパス（/proc/pid/exe）
+    pub exe: Option<PathBuf>,
+    /// カレントディレクトリ（/proc/pid/cwd）
+    pub cwd: Option<PathBuf>,
+    /// コマンドライン引数（/proc/pid/cmdline）
+    pub cmdline: Vec<String>,
+    /// プロセス状態（例: "t (tracing stop)"）
+    pub state: Option<String>,
+    /// スレッド数
+    pub threads: Option<u64>,
+    /// 仮想メモリサイズ（KiB）
+    pub vm_size_kb: Option<u64>,
+    /// 常駐メモリサイズ（KiB）
+    pub vm_rss_kb: Option<u64>,
+}
+
+impl ProcessInfo {
+    /// /proc/<pid> からプロセス情報を読み取る
+    ///
+    /// 個々の項目が読めない場合（権限不足など）は None のまま返します。
+    pub fn read(pid: i32) -> Result<Self> {
+        let base = PathBuf::from(format!("/proc/{}", pid));
+        if !base.exists() {
+            return Err(anyhow::anyhow!("Process {} not found in /proc", pid));
+        }
+
+        let cmdline = std::fs::read(base.join("cmdline"))
+            .map(|bytes| parse_cmdline(&bytes))
+            .unwrap_or_default();
+
+        let mut info = Self {
+            pid,
+            exe: std::fs::read_link(base.join("exe")).ok(),
+            cwd: std::fs::read_link(base.join("cwd")).ok(),
+            cmdline,
+            state: None,
+            threads: None,
+            vm_size_kb: None,
+            vm_rss_kb: None,
+        };
+
+        if let Ok(status) = std::fs::read_to_string(base.join("status")) {
+            for line in status.lines() {
+                let Some((key, value)) = line.split_once(':') else {
+                    continue;
+                };
+                let value = value.trim();
+                match key {
+                    "State" => info.state = Some(value.to_string()),
+                    "Threads" => info.threads = value.parse().ok(),
+                    "VmSize" => info.vm_size_kb = parse_kb(value),
+                    "VmRSS" => info.vm_rss_kb = parse_kb(value),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(info)
+    }
+}
+
+/// スレッド名（/proc/pid/task/tid/comm、15 文字で切れる）を読み取る
+pub fn thread_name(pid: i32, tid: i32) -> Option<String> {
+    let comm = std::fs::read_to_string(format!("/proc/{}/task/{}/comm", pid, tid)).ok()?;
+    Some(comm.trim_end_matches('\n').to_string())
+}
+
+/// `target`（PID またはプロセス名）に当てはまる生存中のプロセスを、新しく起動した順に探す
+///
+/// 名前は comm（15 文字で切れる）、実行ファイル名、argv[0] のファイル名のいずれかと比べます。
+/// kokia 自身とゾンビは除きます。
+pub fn find_processes(target: &str) -> Vec<i32> {
+    let own_pid = std::process::id() as i32;
+    let candidates: Vec<i32> = match target.parse::<i32>() {
+        Ok(pid) => vec![pid],
+        Err(_) => std::fs::read_dir("/proc")
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+                    .filter(|&pid| process_has_name(pid, target))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+
+    let mut found: Vec<(u64, i32)> = candidates
+        .into_iter()
+        .filter(|&pid| pid != own_pid)
+        .filter_map(|pid| {
+            let (state, start_time) = read_stat(pid)?;
+            (!is_dead_state(state)).then_some((start_time, pid))
+        })
+        .collect();
+    found.sort_unstable_by(|a, b| b.cmp(a));
+    found.into_iter().map(|(_, pid)| pid).collect()
+}
+
+/// プロセスの状態（/proc/pid/stat の 3 番目の欄）を読む。プロセスがなければ None
+pub fn process_state(pid: i32) -> Option<char> {
+    read_stat(pid).map(|(state, _)| state)
+}
+
+/// スレッドが属するプロセス（/proc/tid/status の Tgid）。スレッドがなければ None
+pub fn thread_group(tid: i32) -> Option<i32> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", tid)).ok()?;
+    status.lines().find_map(|line| line.strip_prefix("Tgid:"))?.trim().parse().ok()
+}
+
+/// 終了していて、もう実行されない状態（ゾンビ・消滅中）か
+pub fn is_dead_state(state: char) -> bool {
+    matches!(state, 'Z' | 'X' | 'x')
+}
+
+fn process_has_name(pid: i32, name: &str) -> bool {
+    let base = PathBuf::from(format!("/proc/{}", pid));
+    let file_name_is = |path: &std::path::Path| path.file_name().is_some_and(|file| file == name);
+    if let Ok(comm) = std::fs::read_to_string(base.join("comm")) {
+        if comm.trim_end_matches('\n') == name {
+            return true;
+        }
+    }
+    if std::fs::read_link(base.join("exe")).is_ok_and(|exe| file_name_is(&exe)) {
+        return true;
+    }
+    std::fs::read(base.join("cmdline"))
+        .ok()
+        .and_then(|bytes| parse_cmdline(&bytes).into_iter().next())
+        .is_some_and(|argv0| file_name_is(std::path::Path::new(&argv0)))
+}
+
+/// /proc/pid/stat から (状態, 起動時刻) を読む
+fn read_stat(pid: i32) -> Option<(char, u64)> {
+    parse_stat(&std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?)
+}
+
+/// "pid (comm) state ppid ..." から状態と起動時刻（22 番目の欄）を取り出す
+///
+/// comm には空白や括弧が入りうるので、最後の ')' より後ろを欄に分ける。
+fn parse_stat(stat: &str) -> Option<(char, u64)> {
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    let state = fields.first()?.chars().next()?;
+    let start_time = fields.get(19)?.parse().ok()?;
+    Some((state, start_time))
+}
+
+/// NUL 区切りのコマンドラインを分割する
+fn parse_cmdline(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect()
+}
+
+/// "1234 kB" 形式の値をパースする
+fn parse_kb(value: &str) -> Option<u64> {
+    value.split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status_fields() {
+        assert_eq!(parse_cmdline(b"./prog\0--flag\0a b\0"), vec!["./prog", "--flag", "a b"]);
+        assert_eq!(parse_kb("  2048 kB"), Some(2048));
+        assert_eq!(parse_kb(""), None);
+    }
+
+    #[test]
+    fn test_find_processes_by_name_and_pid() {
+        let stat = "42 (my (odd) prog) S 1 42 42 0 -1 4194560 100 0 0 0 1 2 0 0 20 0 1 0 98765 1000 10";
+        assert_eq!(parse_stat(stat), Some(('S', 98765)));
+
+        let mut child = std::process::Command::new("sleep").arg("5").spawn().unwrap();
+        let pid = child.id() as i32;
+        assert!(find_processes("sleep").contains(&pid));
+        assert_eq!(find_processes(&pid.to_string()), vec![pid]);
+        assert!(find_processes(&std::process::id().to_string()).is_empty());
+
+        child.kill().unwrap();
+        // 回収するまではゾンビとして残るが、候補には入らない
+        while !process_state(pid).is_some_and(is_dead_state) {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(find_processes(&pid.to_string()).is_empty());
+        child.wait().unwrap();
+        assert_eq!(process_state(pid), None);
+    }
+
+    #[test]
+    fn test_thread_group_of_own_thread() {
+        let pid = std::process::id() as i32;
+        let tid = std::thread::scope(|scope| scope.spawn(|| {
+            let tid = nix::unistd::gettid().as_raw();
+            assert_eq!(thread_group(tid), Some(pid));
+            tid
+        }).join().unwrap());
+        assert_ne!(tid, pid);
+        assert_eq!(thread_group(pid), Some(pid));
+    }
+
+    #[test]
+    fn test_read_self() {
+        let info = ProcessInfo::read(std::process::id() as i32).unwrap();
+        assert!(info.exe.is_some());
+        assert!(!info.cmdline.is_empty());
+        assert!(info.threads.unwrap_or(0) >= 1);
+    }
+}
@@ -1,8 +1,96 @@
 //! レジスタアクセス機能
 
 use crate::Result;
+use nix::libc;
+use nix::sys::ptrace::{self, regset};
 use nix::unistd::Pid;
 
+/// x86 拡張状態（XSAVE 領域）の regset 番号
+const NT_X86_XSTATE: libc::c_int = 0x202;
+
+/// XSAVE 領域の読み取りバッファサイズ（AMX タイルを含めても収まる大きさ）
+const XSTATE_BUF_SIZE: usize = 16 * 1024;
+
+/// XSAVE 領域内の XMM0 のオフセット（legacy 領域）
+const XSAVE_XMM_OFFSET: usize = 160;
+
+/// XSAVE ヘッダ内の XSTATE_BV のオフセット
+const XSAVE_XSTATE_BV_OFFSET: usize = 512;
+
+/// XSAVE 領域内の YMM 上位 128 ビット（YMM_Hi128）のオフセット
+const XSAVE_YMM_HI_OFFSET: usize = 576;
+
+/// XSTATE_BV の AVX ビット
+const XSTATE_BV_AVX: u64 = 1 << 2;
+
+/// x86 拡張レジスタ状態（NT_X86_XSTATE の生データ）
+///
+/// カーネルは非圧縮（standard）形式の XSAVE 領域を返すため、各コンポーネントは
+/// 固定オフセットに配置されている。
+#[derive(Debug, Clone)]
+pub struct XState {
+    data: Vec<u8>,
+}
+
+impl XState {
+    /// 生データから作成する
+    pub fn from_bytes(data: Vec<u8>) -> Self {
+        Self { data }
+    }
+
+    /// 生データを取得する
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// XSTATE_BV（有効なコンポーネントのビットマップ）を取得する
+    pub fn xstate_bv(&self) -> u64 {
+        self.data
+            .get(XSAVE_XSTATE_BV_OFFSET..XSAVE_XSTATE_BV_OFFSET + 8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+            .unwrap_or(0)
+    }
+
+    /// XMMn（0〜15）を取得する
+    pub fn xmm(&self, n: usize) -> Option<[u8; 16]> {
+        if n >= 16 {
+            return None;
+        }
+        let off = XSAVE_XMM_OFFSET + n * 16;
+        self.data.get(off..off + 16).map(|b| b.try_into().unwrap())
+    }
+
+    /// XMMn（0〜15）を書き換える
+    pub fn set_xmm(&mut self, n: usize, value: [u8; 16]) -> Result<()> {
+        if n >= 16 {
+            return Err(anyhow::anyhow!("Invalid XMM register: {}", n));
+        }
+        let off = XSAVE_XMM_OFFSET + n * 16;
+        let dst = self.data.get_mut(off..off + 16)
+            .ok_or_else(|| anyhow::anyhow!("XSAVE area too small"))?;
+        dst.copy_from_slice(&value);
+        Ok(())
+    }
+
+    /// YMMn（0〜15）を取得する（AVX 状態が保存されていない場合は None）
+    pub fn ymm(&self, n: usize) -> Option<[u8; 32]> {
+        if self.xstate_bv() & XSTATE_BV_AVX == 0 {
+            return None;
+        }
+        let low = self.xmm(n)?;
+        let off = XSAVE_YMM_HI_OFFSET + n * 16;
+        let high = self.data.get(off..off + 16)?;
+
+        let mut ymm = [0u8; 32];
+        ymm[..16].copy_from_slice(&low);
+        ymm[16..].copy_from_slice(high);
+        Some(ymm)
+    }
+}
+
+/// 汎用レジスタ一式（`struct user_regs_struct`）
+pub type UserRegs = libc::user_regs_struct;
+
 /// レジスタ情報
 pub struct Registers {
     pid: Pid,
@@ -28,6 +116,86 @@ impl Registers {
         Ok(())
     }
 
+    /// 汎用レジスタ一式（NT_PRSTATUS）を PTRACE_GETREGSET で読み取る
+    pub fn read_prstatus(&self) -> Result<libc::user_regs_struct> {
+        Ok(ptrace::getregset::<regset::NT_PRSTATUS>(self.pid)?)
+    }
+
+    /// 汎用レジスタ一式（NT_PRSTATUS）を PTRACE_SETREGSET で書き込む
+    pub fn write_prstatus(&self, regs: libc::user_regs_struct) -> Result<()> {
+        ptrace::setregset::<regset::NT_PRSTATUS>(self.pid, regs)?;
+        Ok(())
+    }
+
+    /// x87/SSE レジスタ（NT_PRFPREG）を読み取る
+    pub fn read_fpregs(&self) -> Result<libc::user_fpregs_struct> {
+        Ok(ptrace::getregset::<regset::NT_PRFPREG>(self.pid)?)
+    }
+
+    /// x87/SSE レジスタ（NT_PRFPREG）を書き込む
+    pub fn write_fpregs(&self, fpregs: libc::user_fpregs_struct) -> Result<()> {
+        ptrace::setregset::<regset::NT_PRFPREG>(self.pid, fpregs)?;
+        Ok(())
+    }
+
+    /// 拡張レジスタ状態（NT_X86_XSTATE）を読み取る
+    pub fn read_xstate(&self) -> Result<XState> {
+        let mut buf = vec![0u8; XSTATE_BUF_SIZE];
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let ret = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETREGSET,
+                self.pid.as_raw(),
+                NT_X86_XSTATE as usize as *mut libc::c_void,
+                &mut iov as *mut libc::iovec as *mut libc::c_void,
+            )
+        };
+        if ret < 0 {
+            return Err(nix::errno::Errno::last().into());
+        }
+
+        // カーネルが実際に書き込んだサイズに切り詰める
+        buf.truncate(iov.iov_len);
+        Ok(XState::from_bytes(buf))
+    }
+
+    /// 拡張レジスタ状態（NT_X86_XSTATE）を書き込む
+    pub fn write_xstate(&self, xstate: &XState) -> Result<()> {
+        let mut buf = xstate.as_bytes().to_vec();
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        let ret = unsafe {
+            libc::ptrace(
+                libc::PTRACE_SETREGSET,
+                self.pid.as_raw(),
+                NT_X86_XSTATE as usize as *mut libc::c_void,
+                &mut iov as *mut libc::iovec as *mut libc::c_void,
+            )
+        };
+        if ret < 0 {
+            return Err(nix::errno::Errno::last().into());
+        }
+        Ok(())
+    }
+
+    /// XMMn（0〜15）を取得する
+    pub fn get_xmm(&self, n: usize) -> Result<[u8; 16]> {
+        if n >= 16 {
+            return Err(anyhow::anyhow!("Invalid XMM register: {}", n));
+        }
+        let fpregs = self.read_fpregs()?;
+        let mut xmm = [0u8; 16];
+        for (i, word) in fpregs.xmm_space[n * 4..n * 4 + 4].iter().enumerate() {
+            xmm[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        Ok(xmm)
+    }
+
     /// プログラムカウンタ（RIP）を取得する
     pub fn get_pc(&self) -> Result<u64> {
         let regs = self.read()?;
@@ -64,4 +232,52 @@ impl Registers {
         let regs = self.read()?;
         Ok(regs.rax)
     }
+
+    /// デバッグレジスタ DRn（0〜7）を読み取る
+    pub fn read_debug_reg(&self, n: usize) -> Result<u64> {
+        let offset = debug_reg_offset(n)?;
+        Ok(ptrace::read_user(self.pid, offset as ptrace::AddressType)? as u64)
+    }
+
+    /// デバッグレジスタ DRn（0〜7）に書き込む
+    ///
+    /// DR4/DR5 は予約済みで、カーネルは書き込みを拒否します。
+    pub fn write_debug_reg(&self, n: usize, value: u64) -> Result<()> {
+        let offset = debug_reg_offset(n)?;
+        ptrace::write_user(self.pid, offset as ptrace::AddressType, value as libc::c_long)?;
+        Ok(())
+    }
+}
+
+/// `struct user` 内の `u_debugreg[n]` のオフセット
+fn debug_reg_offset(n: usize) -> Result<usize> {
+    if n >= 8 {
+        return Err(anyhow::anyhow!("Invalid debug register: DR{}", n));
+    }
+    Ok(std::mem::offset_of!(libc::user, u_debugreg) + n * std::mem::size_of::<u64>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xstate_xmm_and_ymm() {
+        let mut xstate = XState::from_bytes(vec![0u8; 1024]);
+        xstate.set_xmm(1, [0x11; 16]).unwrap();
+        assert_eq!(xstate.xmm(1), Some([0x11; 16]));
+        assert!(xstate.set_xmm(16, [0; 16]).is_err());
+
+        // AVX ビットが立っていなければ YMM は取得できない
+        assert_eq!(xstate.ymm(1), None);
+
+        let mut data = xstate.as_bytes().to_vec();
+        data[XSAVE_XSTATE_BV_OFFSET] = XSTATE_BV_AVX as u8;
+        data[XSAVE_YMM_HI_OFFSET + 16..XSAVE_YMM_HI_OFFSET + 32].fill(0x22);
+        let xstate = XState::from_bytes(data);
+
+        let ymm = xstate.ymm(1).unwrap();
+        assert_eq!(&ymm[..16], &[0x11; 16]);
+        assert_eq!(&ymm[16..], &[0x22; 16]);
+    }
 }
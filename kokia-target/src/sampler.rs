@@ -0,0 +1,189 @@
+//! perf のタスククロックによる PC とコールチェーンのサンプリング（profile start）
+//!
+//! スレッドごとに PERF_COUNT_SW_TASK_CLOCK のイベントを指定した周波数で設置し、スレッドが
+//! CPU を使っている間だけ、ユーザー空間の PC とフレームポインタでたどったコールチェーンを
+//! カーネルに記録させます。ターゲットを止めないので、`continue` で走らせている間の負荷を測れます。
+//!
+//! サンプルはスレッドごとのリングバッファに溜まり、停止したときに [`PerfSampler::drain`] で
+//! 読み出します。停止の間隔が長すぎてバッファが一杯になった分は失われ、[`PerfSampler::lost`] に数えます。
+
+use crate::uprobe::{
+    ioctl, perf_event_open, PerfEventAttr, RingBuffer, ATTR_FLAG_DISABLED, PERF_ATTR_SIZE, PERF_EVENT_IOC_ENABLE,
+    PERF_RECORD_LOST, PERF_RECORD_SAMPLE, PERF_SAMPLE_IP, PERF_SAMPLE_TID, PERF_SAMPLE_TIME,
+};
+use crate::Result;
+use nix::libc;
+use std::collections::HashMap;
+
+const PERF_TYPE_SOFTWARE: u32 = 1;
+const PERF_COUNT_SW_TASK_CLOCK: u64 = 1;
+
+const PERF_SAMPLE_CALLCHAIN: u64 = 1 << 5;
+
+/// sample_period の代わりに sample_freq（Hz）を使う
+const ATTR_FLAG_FREQ: u64 = 1 << 10;
+const ATTR_FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+const ATTR_FLAG_EXCLUDE_HV: u64 = 1 << 6;
+const ATTR_FLAG_EXCLUDE_CALLCHAIN_KERNEL: u64 = 1 << 21;
+
+/// コールチェーン中の文脈の区切り（PERF_CONTEXT_USER など）はこの値以上
+const PERF_CONTEXT_MAX: u64 = -4095i64 as u64;
+
+/// 記録するコールチェーンの深さの上限
+const MAX_STACK: u16 = 64;
+
+/// スレッドごとのリングバッファのデータ部のページ数（2 の冪、99 Hz で1分ほど溜められる）
+const SAMPLE_PAGES: usize = 512;
+
+/// 1回のサンプル
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcSample {
+    pub tid: i32,
+    /// サンプルした時点の PC
+    pub ip: u64,
+    /// 呼び出し元への戻りアドレス（内側から順、`ip` は含まない）
+    pub callers: Vec<u64>,
+}
+
+/// 周期的な PC のサンプリング
+pub struct PerfSampler {
+    frequency: u64,
+    buffers: HashMap<i32, RingBuffer>,
+    lost: u64,
+}
+
+impl PerfSampler {
+    /// 指定したスレッドを `frequency` Hz でサンプリングし始める
+    pub fn start(tids: &[i32], frequency: u64) -> Result<Self> {
+        let mut sampler = Self { frequency, buffers: HashMap::new(), lost: 0 };
+        sampler.add_threads(tids)?;
+        if sampler.buffers.is_empty() {
+            return Err(anyhow::anyhow!("No thread to sample"));
+        }
+        Ok(sampler)
+    }
+
+    /// まだサンプリングしていないスレッドを加える（終了したスレッドなどで開けなければ飛ばす）
+    pub fn add_threads(&mut self, tids: &[i32]) -> Result<()> {
+        for &tid in tids {
+            if self.buffers.contains_key(&tid) {
+                continue;
+            }
+            match self.open(tid) {
+                Ok(buffer) => {
+                    self.buffers.insert(tid, buffer);
+                }
+                // 最初のスレッドで失敗するのは権限などの問題なので、そのまま伝える
+                Err(e) if self.buffers.is_empty() => return Err(e),
+                // 途中のスレッドはその間に終了していることがある
+                Err(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn open(&self, tid: i32) -> Result<RingBuffer> {
+        let mut attr = PerfEventAttr {
+            type_: PERF_TYPE_SOFTWARE,
+            size: PERF_ATTR_SIZE,
+            config: PERF_COUNT_SW_TASK_CLOCK,
+            sample_period: self.frequency,
+            sample_type: PERF_SAMPLE_IP | PERF_SAMPLE_TID | PERF_SAMPLE_TIME | PERF_SAMPLE_CALLCHAIN,
+            flags: ATTR_FLAG_DISABLED
+                | ATTR_FLAG_FREQ
+                | ATTR_FLAG_EXCLUDE_KERNEL
+                | ATTR_FLAG_EXCLUDE_HV
+                | ATTR_FLAG_EXCLUDE_CALLCHAIN_KERNEL,
+            sample_max_stack: MAX_STACK,
+            ..Default::default()
+        };
+        let fd = perf_event_open(&mut attr, tid, -1).map_err(|e| match e.raw_os_error() {
+            Some(libc::EACCES) | Some(libc::EPERM) => anyhow::anyhow!(
+                "Permission denied sampling thread {}\n  hint: set kernel.perf_event_paranoid to 2 or lower, or grant CAP_PERFMON",
+                tid
+            ),
+            _ => anyhow::anyhow!("Failed to start sampling thread {}: {}", tid, e),
+        })?;
+        let buffer = RingBuffer::map_pages(fd, SAMPLE_PAGES)?;
+        ioctl(&buffer.fd, PERF_EVENT_IOC_ENABLE, 0)?;
+        Ok(buffer)
+    }
+
+    /// サンプリングしているスレッドの数
+    pub fn thread_count(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// バッファ溢れで失われたサンプルの累計
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    /// 溜まっているサンプルをすべて読み出す
+    pub fn drain(&mut self) -> Vec<PcSample> {
+        let mut samples = Vec::new();
+        let mut lost = 0;
+        for buffer in self.buffers.values_mut() {
+            buffer.drain(|record| match parse_sample(record) {
+                Some(Ok(sample)) => samples.push(sample),
+                Some(Err(n)) => lost += n,
+                None => {}
+            });
+        }
+        self.lost += lost;
+        samples
+    }
+}
+
+/// サンプルのレコードを読む（PERF_RECORD_LOST なら失われた数を Err で返す）
+fn parse_sample(record: &[u8]) -> Option<std::result::Result<PcSample, u64>> {
+    let u32_at = |off: usize| record.get(off..off + 4).map(|b| u32::from_ne_bytes(b.try_into().unwrap()));
+    let u64_at = |off: usize| record.get(off..off + 8).map(|b| u64::from_ne_bytes(b.try_into().unwrap()));
+
+    match u32_at(0)? {
+        PERF_RECORD_SAMPLE => {
+            // ip, pid/tid, time, nr, ips[nr]
+            let ip = u64_at(8)?;
+            let tid = u32_at(20)? as i32;
+            let nr = u64_at(32)? as usize;
+            let chain: Vec<u64> = (0..nr).map(|i| u64_at(40 + i * 8)).collect::<Option<_>>()?;
+            // 先頭は PERF_CONTEXT_USER とサンプル時の PC なので、その後ろが呼び出し元
+            let mut frames = chain.into_iter().filter(|&addr| addr < PERF_CONTEXT_MAX).peekable();
+            frames.next_if_eq(&ip);
+            Some(Ok(PcSample { tid, ip, callers: frames.filter(|&addr| addr != 0).collect() }))
+        }
+        PERF_RECORD_LOST => Some(Err(u64_at(16)?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sample() {
+        let mut record = Vec::new();
+        record.extend_from_slice(&PERF_RECORD_SAMPLE.to_ne_bytes());
+        record.extend_from_slice(&0u16.to_ne_bytes());
+        record.extend_from_slice(&72u16.to_ne_bytes());
+        record.extend_from_slice(&0x401010u64.to_ne_bytes());
+        record.extend_from_slice(&100u32.to_ne_bytes());
+        record.extend_from_slice(&101u32.to_ne_bytes());
+        for value in [5000u64, 4, -512i64 as u64, 0x401010, 0x402020, 0x403030] {
+            record.extend_from_slice(&value.to_ne_bytes());
+        }
+
+        let Some(Ok(sample)) = parse_sample(&record) else {
+            panic!("expected a sample");
+        };
+        assert_eq!(sample, PcSample { tid: 101, ip: 0x401010, callers: vec![0x402020, 0x403030] });
+
+        let mut lost = Vec::new();
+        lost.extend_from_slice(&PERF_RECORD_LOST.to_ne_bytes());
+        lost.extend_from_slice(&[0, 0, 24, 0]);
+        lost.extend_from_slice(&7u64.to_ne_bytes());
+        lost.extend_from_slice(&3u64.to_ne_bytes());
+        assert_eq!(parse_sample(&lost), Some(Err(3)));
+    }
+}
@@ -0,0 +1,63 @@
+//! スレッドローカル領域（TLS）のアドレス計算
+//!
+//! x86-64 の glibc では FS レジスタがスレッドの TCB（`tcbhead_t`）を指し、その 8 バイト目に
+//! DTV（dynamic thread vector）へのポインタがあります。DTV はモジュール番号ごとに
+//! 16 バイトの要素を持ち、先頭 8 バイトがそのスレッドでのモジュールの TLS ブロックの先頭です。
+//! `__tls_get_addr` を呼ぶ代わりに、これをメモリから読んで同じ計算をします。
+
+use crate::backend::TargetMemory;
+use crate::Result;
+
+/// 実行ファイル自身のモジュール番号
+pub const EXECUTABLE_TLS_MODULE: u64 = 1;
+
+/// `tcbhead_t` の中の DTV へのポインタの位置
+const TCB_DTV_OFFSET: u64 = 8;
+
+/// DTV の1要素の大きさ（`dtv_t`: ブロックの先頭と解放用のポインタ）
+const DTV_ENTRY_SIZE: u64 = 16;
+
+/// まだ割り当てられていない TLS ブロック（`TLS_DTV_UNALLOCATED`）
+const DTV_UNALLOCATED: u64 = u64::MAX;
+
+/// `fs_base` のスレッドで、モジュール `module` の TLS ブロックの先頭アドレスを求める
+pub fn tls_block_address(memory: &dyn TargetMemory, fs_base: u64, module: u64) -> Result<u64> {
+    if fs_base == 0 {
+        return Err(anyhow::anyhow!("Thread pointer (fs_base) is not set up yet"));
+    }
+    let dtv = memory.read_word(fs_base + TCB_DTV_OFFSET)
+        .map_err(|e| anyhow::anyhow!("Cannot read the thread's DTV pointer: {}", e))?;
+    // DTV の先頭2要素は長さと世代番号で、モジュール番号は 1 から
+    let length = memory.read_word(dtv.wrapping_sub(DTV_ENTRY_SIZE))?;
+    if module == 0 || module > length {
+        return Err(anyhow::anyhow!("TLS module {} is not loaded in this thread", module));
+    }
+    let block = memory.read_word(dtv + module * DTV_ENTRY_SIZE)?;
+    if block == DTV_UNALLOCATED || block == 0 {
+        return Err(anyhow::anyhow!("TLS block of module {} is not allocated in this thread yet", module));
+    }
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FakeTarget;
+
+    #[test]
+    fn test_tls_block_from_dtv() {
+        let target = FakeTarget::new();
+        // TCB（fs_base = 0x7000）と、-1 番目が長さの DTV（0x9010 から）
+        target.map_zeroed(0x7000, 0x20);
+        target.map_zeroed(0x9000, 0x40);
+        target.write_memory(0x7008, &0x9010u64.to_le_bytes()).unwrap();
+        target.write_memory(0x9000, &2u64.to_le_bytes()).unwrap();
+        target.write_memory(0x9020, &0x6f80u64.to_le_bytes()).unwrap();
+        target.write_memory(0x9030, &DTV_UNALLOCATED.to_le_bytes()).unwrap();
+
+        assert_eq!(tls_block_address(&target, 0x7000, EXECUTABLE_TLS_MODULE).unwrap(), 0x6f80);
+        assert!(tls_block_address(&target, 0x7000, 2).is_err());
+        assert!(tls_block_address(&target, 0x7000, 3).is_err());
+        assert!(tls_block_address(&target, 0, 1).is_err());
+    }
+}
@@ -0,0 +1,538 @@
+//! perf_event_open による uprobe イベント収集
+//!
+//! INT3 ブレークポイントと違い、uprobe はカーネル内でイベントを記録してそのまま実行を続けるため、
+//! ターゲットを止めずに async 関数の entry/exit を集められます。イベントは CPU ごとの
+//! perf リングバッファに書き込まれ、[`UprobeSession::poll`] で読み出します。
+//!
+//! 継承（inherit）付きのスレッド単位イベントは mmap できないため、CPU 単位で設置して
+//! 対象プロセス以外のサンプルを捨てています。後から生成されたスレッドも拾える代わりに、
+//! CPU 単位のイベントを作れる権限（root、CAP_PERFMON、または perf_event_paranoid <= 0）が必要です。
+//!
+//! 同じアドレスに INT3 ブレークポイントが書き込まれていると uprobe の登録に失敗するので、
+//! INT3 による async 計装とは併用できません。
+
+use crate::Result;
+use nix::libc;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::PathBuf;
+use std::sync::atomic::{fence, Ordering};
+use std::time::Duration;
+
+const UPROBE_PMU_DIR: &str = "/sys/bus/event_source/devices/uprobe";
+
+/// perf_event_attr のサイズ（PERF_ATTR_SIZE_VER5）
+pub(crate) const PERF_ATTR_SIZE: u32 = 112;
+
+pub(crate) const PERF_SAMPLE_IP: u64 = 1 << 0;
+pub(crate) const PERF_SAMPLE_TID: u64 = 1 << 1;
+pub(crate) const PERF_SAMPLE_TIME: u64 = 1 << 2;
+const PERF_SAMPLE_REGS_USER: u64 = 1 << 12;
+const PERF_SAMPLE_STACK_USER: u64 = 1 << 13;
+const PERF_SAMPLE_IDENTIFIER: u64 = 1 << 16;
+
+pub(crate) const ATTR_FLAG_DISABLED: u64 = 1 << 0;
+/// fork/exit のサイドバンドレコードを出す
+const ATTR_FLAG_TASK: u64 = 1 << 13;
+
+pub(crate) const PERF_RECORD_LOST: u32 = 2;
+const PERF_RECORD_EXIT: u32 = 4;
+pub(crate) const PERF_RECORD_SAMPLE: u32 = 9;
+
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+pub(crate) const PERF_EVENT_IOC_ENABLE: libc::c_ulong = 0x2400;
+const PERF_EVENT_IOC_SET_OUTPUT: libc::c_ulong = 0x2405;
+const PERF_EVENT_IOC_ID: libc::c_ulong = 0x8008_2407;
+
+/// x86_64 の perf レジスタ番号（arch/x86/include/uapi/asm/perf_regs.h）
+const PERF_REG_X86_AX: u32 = 0;
+const PERF_REG_X86_DI: u32 = 5;
+
+/// サンプルに含めるユーザースタックのバイト数（Entry 時点の戻りアドレス1つ分）
+const SAMPLE_STACK_BYTES: u32 = 8;
+
+/// perf_event_mmap_page 内の data_head / data_tail のオフセット
+const MMAP_DATA_HEAD: usize = 1024;
+const MMAP_DATA_TAIL: usize = 1032;
+
+/// リングバッファのデータ部のページ数（2 の冪）
+const RING_PAGES: usize = 64;
+
+/// perf_event_attr（VER5 までのフィールド）
+#[repr(C)]
+#[derive(Default)]
+pub(crate) struct PerfEventAttr {
+    pub(crate) type_: u32,
+    pub(crate) size: u32,
+    pub(crate) config: u64,
+    pub(crate) sample_period: u64,
+    pub(crate) sample_type: u64,
+    pub(crate) read_format: u64,
+    pub(crate) flags: u64,
+    pub(crate) wakeup_events: u32,
+    pub(crate) bp_type: u32,
+    pub(crate) config1: u64,
+    pub(crate) config2: u64,
+    pub(crate) branch_sample_type: u64,
+    pub(crate) sample_regs_user: u64,
+    pub(crate) sample_stack_user: u32,
+    pub(crate) clockid: i32,
+    pub(crate) sample_regs_intr: u64,
+    pub(crate) aux_watermark: u32,
+    pub(crate) sample_max_stack: u16,
+    pub(crate) reserved_2: u16,
+}
+
+/// プローブの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeKind {
+    /// 関数の先頭（uprobe）
+    Entry,
+    /// 関数からの復帰（uretprobe）
+    Return,
+}
+
+/// 設置するプローブ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeSpec {
+    /// プローブを置く ELF ファイルのパス（デバッガから見えるパス）
+    pub path: PathBuf,
+    /// ファイル先頭からのオフセット
+    pub file_offset: u64,
+    pub kind: ProbeKind,
+}
+
+/// uprobe が記録したイベント
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UprobeEvent {
+    /// [`UprobeSession::open`] に渡したプローブのインデックス
+    pub probe: usize,
+    pub tid: i32,
+    /// カーネルのタイムスタンプ（ナノ秒、CLOCK_MONOTONIC 相当）
+    pub time: u64,
+    /// 記録時の命令ポインタ（Return では戻り先）
+    pub ip: u64,
+    /// 第1引数（Entry で有効）
+    pub rdi: u64,
+    /// 戻り値（Return で有効）
+    pub rax: u64,
+    /// スタック先頭の値（Entry では呼び出し元への戻りアドレス。取れなければ 0）
+    pub return_address: u64,
+}
+
+/// 1 CPU 分のリングバッファ
+pub(crate) struct RingBuffer {
+    pub(crate) fd: OwnedFd,
+    base: *mut u8,
+    page_size: usize,
+    /// データ部のページ数
+    pages: usize,
+}
+
+impl RingBuffer {
+    fn map(fd: OwnedFd) -> Result<Self> {
+        Self::map_pages(fd, RING_PAGES)
+    }
+
+    /// データ部が `pages` ページ（2 の冪）のリングバッファを mmap する
+    pub(crate) fn map_pages(fd: OwnedFd, pages: usize) -> Result<Self> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let len = page_size * (pages + 1);
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(anyhow::anyhow!("Failed to mmap perf ring buffer: {}", std::io::Error::last_os_error()));
+        }
+        Ok(Self { fd, base: base as *mut u8, page_size, pages })
+    }
+
+    fn data_size(&self) -> usize {
+        self.page_size * self.pages
+    }
+
+    /// 溜まっているレコードをすべて取り出す
+    pub(crate) fn drain(&mut self, mut handle: impl FnMut(&[u8])) {
+        let head_ptr = unsafe { self.base.add(MMAP_DATA_HEAD) } as *const u64;
+        let tail_ptr = unsafe { self.base.add(MMAP_DATA_TAIL) } as *mut u64;
+        let head = unsafe { std::ptr::read_volatile(head_ptr) };
+        fence(Ordering::Acquire);
+        let mut tail = unsafe { std::ptr::read_volatile(tail_ptr) };
+
+        let data = unsafe { std::slice::from_raw_parts(self.base.add(self.page_size), self.data_size()) };
+        let mut record = Vec::new();
+        while tail < head {
+            let size = {
+                let header = copy_wrapped(data, tail, 8);
+                u16::from_ne_bytes([header[6], header[7]]) as u64
+            };
+            if size < 8 {
+                break;
+            }
+            record.clear();
+            record.extend_from_slice(&copy_wrapped(data, tail, size as usize));
+            handle(&record);
+            tail += size;
+        }
+
+        fence(Ordering::Release);
+        unsafe { std::ptr::write_volatile(tail_ptr, tail) };
+    }
+}
+
+impl Drop for RingBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.base as *mut libc::c_void, self.page_size * (self.pages + 1));
+        }
+    }
+}
+
+/// リングバッファの `pos` から `len` バイトを、末尾での折り返しを考慮してコピーする
+fn copy_wrapped(data: &[u8], pos: u64, len: usize) -> Vec<u8> {
+    let start = (pos % data.len() as u64) as usize;
+    let first = len.min(data.len() - start);
+    let mut bytes = data[start..start + first].to_vec();
+    bytes.extend_from_slice(&data[..len - first]);
+    bytes
+}
+
+/// uprobe によるイベント収集
+pub struct UprobeSession {
+    pid: i32,
+    probes: Vec<ProbeSpec>,
+    /// perf イベントID → プローブのインデックス
+    ids: HashMap<u64, usize>,
+    buffers: Vec<RingBuffer>,
+    /// SET_OUTPUT でバッファを共有しているイベント（閉じるとプローブが外れる）
+    events: Vec<OwnedFd>,
+    lost: u64,
+    /// 終了を観測したスレッド（[`UprobeSession::take_exited_threads`] で取り出す）
+    exited_threads: Vec<i32>,
+}
+
+impl UprobeSession {
+    /// uprobe PMU が使えるか
+    pub fn is_supported() -> bool {
+        uprobe_pmu_type().is_ok()
+    }
+
+    /// すべての CPU にプローブを設置し、`pid` のプロセスのイベント収集を開始する
+    pub fn open(pid: i32, probes: Vec<ProbeSpec>) -> Result<Self> {
+        if probes.is_empty() {
+            return Err(anyhow::anyhow!("No probes to install"));
+        }
+        let pmu_type = uprobe_pmu_type()?;
+        let retprobe_bit = retprobe_config_bit()?;
+        let paths: Vec<CString> = probes
+            .iter()
+            .map(|p| CString::new(p.path.as_os_str().as_encoded_bytes()))
+            .collect::<std::result::Result<_, _>>()?;
+
+        let mut session = Self {
+            pid,
+            probes,
+            ids: HashMap::new(),
+            buffers: Vec::new(),
+            events: Vec::new(),
+            lost: 0,
+            exited_threads: Vec::new(),
+        };
+
+        for cpu in online_cpus()? {
+            let mut leader: Option<RingBuffer> = None;
+            for (index, probe) in session.probes.iter().enumerate() {
+                let mut attr = PerfEventAttr {
+                    type_: pmu_type,
+                    size: PERF_ATTR_SIZE,
+                    config: match probe.kind {
+                        ProbeKind::Entry => 0,
+                        ProbeKind::Return => 1 << retprobe_bit,
+                    },
+                    sample_period: 1,
+                    sample_type: PERF_SAMPLE_IDENTIFIER
+                        | PERF_SAMPLE_IP
+                        | PERF_SAMPLE_TID
+                        | PERF_SAMPLE_TIME
+                        | PERF_SAMPLE_REGS_USER
+                        | PERF_SAMPLE_STACK_USER,
+                    // スレッドの終了は CPU ごとに1つのイベントから受け取れば足りる
+                    flags: if leader.is_none() { ATTR_FLAG_DISABLED | ATTR_FLAG_TASK } else { ATTR_FLAG_DISABLED },
+                    wakeup_events: 1,
+                    config1: paths[index].as_ptr() as u64,
+                    config2: probe.file_offset,
+                    sample_regs_user: (1 << PERF_REG_X86_AX) | (1 << PERF_REG_X86_DI),
+                    sample_stack_user: SAMPLE_STACK_BYTES,
+                    ..Default::default()
+                };
+                let fd = perf_event_open(&mut attr, -1, cpu)
+                    .map_err(|e| explain_open_error(e, probe))?;
+                session.ids.insert(event_id(&fd)?, index);
+
+                match &leader {
+                    Some(buffer) => {
+                        ioctl(&fd, PERF_EVENT_IOC_SET_OUTPUT, buffer.fd.as_raw_fd() as libc::c_ulong)?;
+                        session.events.push(fd);
+                    }
+                    None => leader = Some(RingBuffer::map(fd)?),
+                }
+            }
+            session.buffers.extend(leader);
+        }
+
+        for fd in session.buffers.iter().map(|b| &b.fd).chain(&session.events) {
+            ioctl(fd, PERF_EVENT_IOC_ENABLE, 0)?;
+        }
+        Ok(session)
+    }
+
+    /// 設置したプローブ
+    pub fn probe(&self, index: usize) -> &ProbeSpec {
+        &self.probes[index]
+    }
+
+    /// 設置したプローブの数
+    pub fn probe_count(&self) -> usize {
+        self.probes.len()
+    }
+
+    /// バッファ溢れで失われたイベントの累計
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    /// これまでに終了したスレッドを取り出す
+    ///
+    /// 終了したスレッドのイベントはすべてそれ以前に読み出されているので、
+    /// 同じ poll で得たイベントを処理したあとに呼べば順序が崩れません。
+    pub fn take_exited_threads(&mut self) -> Vec<i32> {
+        std::mem::take(&mut self.exited_threads)
+    }
+
+    /// 最大 `timeout` 待ってイベントを読み出す（時刻順）
+    pub fn poll(&mut self, timeout: Duration) -> Result<Vec<UprobeEvent>> {
+        let mut fds: Vec<libc::pollfd> = self
+            .buffers
+            .iter()
+            .map(|b| libc::pollfd { fd: b.fd.as_raw_fd(), events: libc::POLLIN, revents: 0 })
+            .collect();
+        let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+        let ret = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms) };
+        if ret < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() != std::io::ErrorKind::Interrupted {
+                return Err(err.into());
+            }
+        }
+        Ok(self.drain())
+    }
+
+    /// 溜まっているイベントをすべて読み出す（時刻順）
+    pub fn drain(&mut self) -> Vec<UprobeEvent> {
+        let mut events = Vec::new();
+        let mut lost = 0;
+        for buffer in &mut self.buffers {
+            buffer.drain(|record| match parse_record(record) {
+                Some(Record::Sample { id, pid, event }) if pid == self.pid => events.push((id, event)),
+                Some(Record::Sample { .. }) => {}
+                Some(Record::Lost(n)) => lost += n,
+                Some(Record::Exit { pid, tid }) if pid == self.pid => self.exited_threads.push(tid),
+                Some(Record::Exit { .. }) | None => {}
+            });
+        }
+        self.lost += lost;
+
+        let mut events: Vec<UprobeEvent> = events
+            .into_iter()
+            .filter_map(|(id, event)| self.ids.get(&id).map(|&probe| UprobeEvent { probe, ..event }))
+            .collect();
+        events.sort_by_key(|e| e.time);
+        events
+    }
+}
+
+/// リングバッファのレコード
+enum Record {
+    Sample { id: u64, pid: i32, event: UprobeEvent },
+    Lost(u64),
+    Exit { pid: i32, tid: i32 },
+}
+
+/// レコードを読み取る（sample_type は [`UprobeSession::open`] の設定に対応）
+fn parse_record(record: &[u8]) -> Option<Record> {
+    let u32_at = |off: usize| record.get(off..off + 4).map(|b| u32::from_ne_bytes(b.try_into().unwrap()));
+    let u64_at = |off: usize| record.get(off..off + 8).map(|b| u64::from_ne_bytes(b.try_into().unwrap()));
+
+    match u32_at(0)? {
+        PERF_RECORD_SAMPLE => {
+            let id = u64_at(8)?;
+            let ip = u64_at(16)?;
+            let pid = u32_at(24)? as i32;
+            let tid = u32_at(28)? as i32;
+            let time = u64_at(32)?;
+            let abi = u64_at(40)?;
+            // abi が 0（PERF_SAMPLE_REGS_ABI_NONE）ならレジスタは含まれない
+            let (rax, rdi, stack) = if abi != 0 { (u64_at(48)?, u64_at(56)?, 64) } else { (0, 0, 48) };
+            // スタックは (size, data[size], dyn_size) の順。dyn_size が実際に読めたバイト数
+            let return_address = match u64_at(stack) {
+                Some(size) if size >= 8 && u64_at(stack + 8 + size as usize).is_some_and(|dyn_size| dyn_size >= 8) => {
+                    u64_at(stack + 8)?
+                }
+                _ => 0,
+            };
+            let event = UprobeEvent { probe: 0, tid, time, ip, rdi, rax, return_address };
+            Some(Record::Sample { id, pid, event })
+        }
+        // PERF_RECORD_LOST の id は SET_OUTPUT 先のイベントのものなので使わない
+        PERF_RECORD_LOST => Some(Record::Lost(u64_at(16)?)),
+        PERF_RECORD_EXIT => Some(Record::Exit { pid: u32_at(8)? as i32, tid: u32_at(16)? as i32 }),
+        _ => None,
+    }
+}
+
+fn uprobe_pmu_type() -> Result<u32> {
+    let path = format!("{}/type", UPROBE_PMU_DIR);
+    let value = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("uprobe PMU is not available ({}: {}); the kernel needs CONFIG_UPROBE_EVENTS", path, e))?;
+    Ok(value.trim().parse()?)
+}
+
+/// `format/retprobe` の `config:N` から uretprobe を示すビット番号を得る
+fn retprobe_config_bit() -> Result<u32> {
+    let path = format!("{}/format/retprobe", UPROBE_PMU_DIR);
+    let value = std::fs::read_to_string(&path)?;
+    parse_config_bit(&value).ok_or_else(|| anyhow::anyhow!("Unexpected format in {}: {}", path, value.trim()))
+}
+
+pub(crate) fn parse_config_bit(format: &str) -> Option<u32> {
+    format.trim().strip_prefix("config:")?.parse().ok()
+}
+
+fn online_cpus() -> Result<Vec<i32>> {
+    let list = std::fs::read_to_string("/sys/devices/system/cpu/online")?;
+    parse_cpu_list(&list).ok_or_else(|| anyhow::anyhow!("Unexpected CPU list: {}", list.trim()))
+}
+
+/// `0-3,6` 形式の CPU リストをパースする
+fn parse_cpu_list(list: &str) -> Option<Vec<i32>> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|r| !r.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<i32>().ok()?..=last.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// `pid` が -1 なら CPU 単位、`cpu` が -1 ならスレッド単位のイベントを作る
+pub(crate) fn perf_event_open(attr: &mut PerfEventAttr, pid: i32, cpu: i32) -> std::io::Result<OwnedFd> {
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_perf_event_open,
+            attr as *mut PerfEventAttr,
+            pid,
+            cpu,
+            -1 as libc::c_int,
+            PERF_FLAG_FD_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as i32) })
+}
+
+fn event_id(fd: &OwnedFd) -> Result<u64> {
+    let mut id = 0u64;
+    ioctl(fd, PERF_EVENT_IOC_ID, &mut id as *mut u64 as libc::c_ulong)?;
+    Ok(id)
+}
+
+pub(crate) fn ioctl(fd: &OwnedFd, request: libc::c_ulong, arg: libc::c_ulong) -> Result<()> {
+    if unsafe { libc::ioctl(fd.as_raw_fd(), request, arg) } < 0 {
+        return Err(anyhow::anyhow!("perf ioctl 0x{:x} failed: {}", request, std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+fn explain_open_error(err: std::io::Error, probe: &ProbeSpec) -> anyhow::Error {
+    let location = format!("{}+0x{:x}", probe.path.display(), probe.file_offset);
+    match err.raw_os_error() {
+        Some(libc::EACCES) | Some(libc::EPERM) => {
+            let paranoid = std::fs::read_to_string("/proc/sys/kernel/perf_event_paranoid")
+                .map(|s| s.trim().to_string())
+                .unwrap_or_else(|_| "?".to_string());
+            anyhow::anyhow!(
+                "Permission denied installing uprobe at {} (perf_event_paranoid={})\n  hint: run as root, grant CAP_PERFMON, or set kernel.perf_event_paranoid to 0 or lower (CPU-wide events are required)",
+                location,
+                paranoid
+            )
+        }
+        Some(libc::EEXIST) | Some(libc::EINVAL) => anyhow::anyhow!(
+            "Failed to install uprobe at {}: {} (is an INT3 breakpoint already set there?)",
+            location,
+            err
+        ),
+        _ => anyhow::anyhow!("Failed to install uprobe at {}: {}", location, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_helpers() {
+        assert_eq!(parse_config_bit("config:0\n"), Some(0));
+        assert_eq!(parse_config_bit("config1:0-63"), None);
+        assert_eq!(parse_cpu_list("0-3,6\n"), Some(vec![0, 1, 2, 3, 6]));
+
+        let data = [0u8, 1, 2, 3, 4, 5, 6, 7];
+        assert_eq!(copy_wrapped(&data, 6, 4), vec![6, 7, 0, 1]);
+        assert_eq!(copy_wrapped(&data, 10, 3), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_parse_sample_record() {
+        let mut record = Vec::new();
+        record.extend_from_slice(&PERF_RECORD_SAMPLE.to_ne_bytes());
+        record.extend_from_slice(&0u16.to_ne_bytes());
+        record.extend_from_slice(&88u16.to_ne_bytes());
+        for value in [42u64, 0x401000] {
+            record.extend_from_slice(&value.to_ne_bytes());
+        }
+        record.extend_from_slice(&100u32.to_ne_bytes());
+        record.extend_from_slice(&101u32.to_ne_bytes());
+        for value in [5000u64, 2, 1, 0x7fff_0000, 8, 0x401234, 8] {
+            record.extend_from_slice(&value.to_ne_bytes());
+        }
+
+        let Some(Record::Sample { id, pid, event }) = parse_record(&record) else {
+            panic!("expected a sample record");
+        };
+        assert_eq!((id, pid), (42, 100));
+        assert_eq!((event.ip, event.tid, event.time), (0x401000, 101, 5000));
+        assert_eq!((event.rax, event.rdi), (1, 0x7fff_0000));
+        assert_eq!(event.return_address, 0x401234);
+
+        let mut exit = Vec::new();
+        exit.extend_from_slice(&PERF_RECORD_EXIT.to_ne_bytes());
+        exit.extend_from_slice(&0u16.to_ne_bytes());
+        exit.extend_from_slice(&32u16.to_ne_bytes());
+        for value in [100u32, 1, 102, 100] {
+            exit.extend_from_slice(&value.to_ne_bytes());
+        }
+        exit.extend_from_slice(&6000u64.to_ne_bytes());
+        assert!(matches!(parse_record(&exit), Some(Record::Exit { pid: 100, tid: 102 })));
+    }
+}
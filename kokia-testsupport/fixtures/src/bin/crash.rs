@@ -0,0 +1,20 @@
+//! checkpoint の後にクラッシュする（既定は SIGSEGV、引数 `abort` なら SIGABRT）
+
+#[inline(never)]
+fn checkpoint(stage: u64) {
+    std::hint::black_box(stage);
+}
+
+#[inline(never)]
+fn fault() -> u64 {
+    // 何も割り当てられていないアドレスを読む
+    unsafe { std::ptr::read_volatile(std::hint::black_box(0x10usize) as *const u64) }
+}
+
+fn main() {
+    checkpoint(1);
+    if std::env::args().nth(1).as_deref() == Some("abort") {
+        std::process::abort();
+    }
+    println!("{}", fault());
+}
@@ -0,0 +1,22 @@
+//! 親が子を2回続けて await する（current_thread ランタイム）
+
+#[inline(never)]
+fn checkpoint(total: u64) {
+    std::hint::black_box(total);
+}
+
+async fn child(x: u64) -> u64 {
+    tokio::task::yield_now().await;
+    x
+}
+
+async fn parent() -> u64 {
+    child(20).await + child(22).await
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let total = parent().await;
+    checkpoint(total);
+    println!("total = {}", total);
+}
@@ -0,0 +1,29 @@
+//! join! で2つの子を同時に await する
+
+#[inline(never)]
+fn checkpoint(total: u64) {
+    std::hint::black_box(total);
+}
+
+async fn left() -> u64 {
+    tokio::task::yield_now().await;
+    3
+}
+
+async fn right() -> u64 {
+    tokio::task::yield_now().await;
+    tokio::task::yield_now().await;
+    4
+}
+
+async fn both() -> u64 {
+    let (a, b) = tokio::join!(left(), right());
+    a + b
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let total = both().await;
+    checkpoint(total);
+    println!("total = {}", total);
+}
@@ -0,0 +1,26 @@
+//! ワーカースレッド上で spawn したタスクがそれぞれ子を await する（multi_thread ランタイム）
+
+#[inline(never)]
+fn checkpoint(total: u64) {
+    std::hint::black_box(total);
+}
+
+async fn step(i: u64) -> u64 {
+    tokio::task::yield_now().await;
+    i * 2
+}
+
+async fn worker(i: u64) -> u64 {
+    step(i).await + 1
+}
+
+#[tokio::main(flavor = "multi_thread", worker_threads = 2)]
+async fn main() {
+    let handles: Vec<_> = (0..4).map(|i| tokio::spawn(worker(i))).collect();
+    let mut total = 0;
+    for handle in handles {
+        total += handle.await.unwrap();
+    }
+    checkpoint(total);
+    println!("total = {}", total);
+}
@@ -0,0 +1,25 @@
+//! spawn したタスクが poll の途中でパニックし、ランタイムの catch_unwind が受け止める
+
+#[inline(never)]
+fn checkpoint(total: u64) {
+    std::hint::black_box(total);
+}
+
+async fn boom() -> u64 {
+    tokio::task::yield_now().await;
+    panic!("boom");
+}
+
+async fn after() -> u64 {
+    tokio::task::yield_now().await;
+    7
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    std::panic::set_hook(Box::new(|_| {}));
+    let failed = tokio::spawn(boom()).await.is_err();
+    let total = after().await + u64::from(failed);
+    checkpoint(total);
+    println!("total = {}", total);
+}
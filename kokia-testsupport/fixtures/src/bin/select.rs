@@ -0,0 +1,33 @@
+//! select! で先に終わった側だけが完了し、もう一方は完了せずに捨てられる
+
+use std::time::Duration;
+
+#[inline(never)]
+fn checkpoint(winner: u64) {
+    std::hint::black_box(winner);
+}
+
+async fn fast() -> u64 {
+    tokio::task::yield_now().await;
+    1
+}
+
+async fn slow() -> u64 {
+    tokio::time::sleep(Duration::from_secs(30)).await;
+    2
+}
+
+async fn race() -> u64 {
+    tokio::select! {
+        biased;
+        value = fast() => value,
+        value = slow() => value,
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let winner = race().await;
+    checkpoint(winner);
+    println!("winner = {}", winner);
+}
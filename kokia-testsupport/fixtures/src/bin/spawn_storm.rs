@@ -0,0 +1,24 @@
+//! 短命なタスクを大量に spawn する（Future のアドレスが再利用される）
+
+/// spawn するタスクの数（テスト側と合わせる）
+const TASKS: u64 = 64;
+
+#[inline(never)]
+fn checkpoint(total: u64) {
+    std::hint::black_box(total);
+}
+
+async fn leaf(i: u64) -> u64 {
+    tokio::task::yield_now().await;
+    i
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let mut total = 0;
+    for i in 0..TASKS {
+        total += tokio::spawn(leaf(i)).await.unwrap();
+    }
+    checkpoint(total);
+    println!("total = {}", total);
+}
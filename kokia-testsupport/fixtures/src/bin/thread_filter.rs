@@ -0,0 +1,20 @@
+//! 同じ関数をメインスレッドと spawn_blocking のスレッドが順に呼ぶ（スレッドを限定したブレークポイント用）
+
+#[inline(never)]
+fn hit(which: u64) -> u64 {
+    std::hint::black_box(which)
+}
+
+#[inline(never)]
+fn checkpoint(total: u64) {
+    std::hint::black_box(total);
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let first = hit(1);
+    let second = tokio::task::spawn_blocking(|| hit(2)).await.unwrap();
+    let third = hit(3);
+    checkpoint(first + second + third);
+    println!("total = {}", first + second + third);
+}
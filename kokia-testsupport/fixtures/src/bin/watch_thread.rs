@@ -0,0 +1,18 @@
+//! ウォッチポイントを置いた後に生まれたスレッド（spawn_blocking のスレッド）が static に書き込む
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[inline(never)]
+fn checkpoint(total: u64) {
+    std::hint::black_box(total);
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    tokio::task::spawn_blocking(|| COUNTER.store(42, Ordering::SeqCst)).await.unwrap();
+    let total = COUNTER.load(Ordering::SeqCst);
+    checkpoint(total);
+    println!("counter = {}", total);
+}
@@ -0,0 +1,198 @@
+//! kokia の結合テスト用の支援クレート
+//!
+//! `fixtures/` にある小さな async プログラム（current_thread、multi_thread、select!、join!、spawn_blocking、
+//! spawn の連続）を opt-level ごとにビルドし、[`Session`] で起動して端から端まで動かします。
+//! どのプログラムも最後に `checkpoint` 関数を呼ぶので、そこまで走らせてから
+//! async トラッカーが組み立てたタスクグラフを [`TaskGraph`] で検査します。
+//!
+//! ```no_run
+//! use kokia_testsupport::{Fixture, OptLevel};
+//!
+//! let mut fixture = Fixture::launch("current_thread", OptLevel::O0)?;
+//! let graph = fixture.run_to_checkpoint()?;
+//! assert!(graph.awaits("parent", "child"));
+//! assert_eq!(fixture.run_to_exit()?, 0);
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use anyhow::{anyhow, Result};
+use kokia_core::{Session, SessionConfig, StopReason, TaskInfo};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+/// フィクスチャをビルドする最適化レベル（`fixtures/Cargo.toml` のプロファイル）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+}
+
+impl OptLevel {
+    pub const ALL: [OptLevel; 3] = [OptLevel::O0, OptLevel::O1, OptLevel::O2];
+
+    /// `cargo build --profile` に渡す名前
+    pub fn profile(self) -> &'static str {
+        match self {
+            OptLevel::O0 => "dev",
+            OptLevel::O1 => "o1",
+            OptLevel::O2 => "o2",
+        }
+    }
+
+    /// ビルド結果が入るディレクトリ名
+    fn output_dir(self) -> &'static str {
+        match self {
+            OptLevel::O0 => "debug",
+            other => other.profile(),
+        }
+    }
+}
+
+/// ビルド済みのプロファイル（テストは並列に走るので、ビルドは1つずつ行う）
+static BUILT: Mutex<Vec<OptLevel>> = Mutex::new(Vec::new());
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures")
+}
+
+fn fixtures_target_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../target/fixtures")
+}
+
+/// フィクスチャのバイナリのパス（そのプロファイルを初めて使うときにまとめてビルドする）
+pub fn fixture_binary(name: &str, opt: OptLevel) -> Result<PathBuf> {
+    let mut built = BUILT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if !built.contains(&opt) {
+        let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+        let output = Command::new(cargo)
+            .arg("build")
+            .arg("--quiet")
+            .arg("--bins")
+            .args(["--profile", opt.profile()])
+            .arg("--manifest-path")
+            .arg(fixtures_dir().join("Cargo.toml"))
+            .arg("--target-dir")
+            .arg(fixtures_target_dir())
+            .output()
+            .map_err(|e| anyhow!("Failed to run cargo: {}", e))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Building fixtures ({}) failed:\n{}",
+                opt.profile(),
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+        built.push(opt);
+    }
+
+    let path = fixtures_target_dir().join(opt.output_dir()).join(name);
+    if !path.exists() {
+        return Err(anyhow!("No fixture named '{}' (looked for {})", name, path.display()));
+    }
+    Ok(path)
+}
+
+/// デバッガの下で動かしているフィクスチャ
+pub struct Fixture {
+    name: String,
+    opt: OptLevel,
+    session: Session,
+}
+
+impl Fixture {
+    /// フィクスチャを起動し、そのクレートの async fn を計装して `checkpoint` にブレークポイントを置く
+    ///
+    /// async ブレークポイントでは止まらないようにトレースモードにします。
+    pub fn launch(name: &str, opt: OptLevel) -> Result<Self> {
+        let binary = fixture_binary(name, opt)?;
+        let filter = format!("{}::*", name);
+        let mut session = Session::launch(SessionConfig::launch(binary, &[]).with_async_tracking(Some(&filter)))?;
+        session.debugger_mut().set_async_trace(true)?;
+        session.break_at(&format!("{}::checkpoint", name))?;
+        Ok(Self { name: name.to_string(), opt, session })
+    }
+
+    /// `checkpoint` で止まるまで走らせ、その時点のタスクグラフを返す
+    pub fn run_to_checkpoint(&mut self) -> Result<TaskGraph> {
+        let stop = self.session.resume()?;
+        match stop.reason {
+            StopReason::Breakpoint => Ok(self.graph()),
+            other => Err(anyhow!("{} ({:?}) stopped before checkpoint: {:?}", self.name, self.opt, other)),
+        }
+    }
+
+    /// 終了するまで走らせ、終了コードを返す
+    pub fn run_to_exit(&mut self) -> Result<i32> {
+        self.session.run_to_exit()
+    }
+
+    /// 現在のタスクグラフ
+    pub fn graph(&self) -> TaskGraph {
+        let tracker = self.session.debugger().async_tracker();
+        TaskGraph {
+            crate_name: self.name.clone(),
+            tasks: self.session.tasks(),
+            edges: tracker.all_edges().iter().map(|edge| (edge.parent, edge.child, edge.completed)).collect(),
+        }
+    }
+
+    pub fn opt_level(&self) -> OptLevel {
+        self.opt
+    }
+
+    pub fn session_mut(&mut self) -> &mut Session {
+        &mut self.session
+    }
+}
+
+/// async トラッカーのタスクとエッジ
+///
+/// タスクは関数名で指定します。`parent` は `<crate>::parent::{{closure}}` のタスク、
+/// `both::{{closure}}` は join! などが作る `both` の中のクロージャのタスクです。
+#[derive(Debug, Clone)]
+pub struct TaskGraph {
+    crate_name: String,
+    pub tasks: Vec<TaskInfo>,
+    /// (親, 子, 完了したか)
+    pub edges: Vec<(u64, u64, bool)>,
+}
+
+impl TaskGraph {
+    /// タスクの関数名（クレート名と最後の `::{{closure}}` を除いたもの）
+    pub fn function<'a>(&self, task: &'a TaskInfo) -> Option<&'a str> {
+        let name = task.type_name.as_deref()?;
+        let name = name.strip_suffix("::{{closure}}").unwrap_or(name);
+        Some(name.strip_prefix(&self.crate_name).and_then(|n| n.strip_prefix("::")).unwrap_or(name))
+    }
+
+    /// 関数 `function` のタスク（アドレスの再利用による世代違いも含む）
+    pub fn tasks_of(&self, function: &str) -> Vec<&TaskInfo> {
+        self.tasks.iter().filter(|task| self.function(task) == Some(function)).collect()
+    }
+
+    pub fn count(&self, function: &str) -> usize {
+        self.tasks_of(function).len()
+    }
+
+    /// `parent` のタスクが `child` のタスクを await したエッジがあるか
+    pub fn awaits(&self, parent: &str, child: &str) -> bool {
+        self.awaits_where(parent, child, |_| true)
+    }
+
+    /// `parent` が `child` を await し、そのエッジが完了（Ready を観測）しているか
+    pub fn awaited_to_completion(&self, parent: &str, child: &str) -> bool {
+        self.awaits_where(parent, child, |completed| completed)
+    }
+
+    fn awaits_where(&self, parent: &str, child: &str, keep: impl Fn(bool) -> bool) -> bool {
+        let functions: HashMap<u64, &str> = self.tasks.iter()
+            .filter_map(|task| Some((task.id, self.function(task)?)))
+            .collect();
+        self.edges.iter().any(|(p, c, completed)| {
+            functions.get(p) == Some(&parent) && functions.get(c) == Some(&child) && keep(*completed)
+        })
+    }
+}
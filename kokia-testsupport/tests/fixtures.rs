@@ -0,0 +1,251 @@
+//! フィクスチャを各 opt-level でデバッグし、タスクグラフの形を確かめる
+//!
+//! opt-level 1 以上では子の Future の poll が親にインライン化されるため、
+//! 計装で見えるのは main のタスクだけになる。エッジの形は opt-level 0 でだけ確かめる。
+
+use kokia_core::{parse_expression, ExpressionEvaluator, Session, SessionConfig, Signal, StopReason, Tid, WatchKind};
+use kokia_testsupport::{fixture_binary, Fixture, OptLevel, TaskGraph};
+
+/// 全 opt-level で checkpoint まで走らせ、opt-level 0 のグラフを `check` で確かめる
+fn check_fixture(name: &str, check: impl Fn(&TaskGraph)) {
+    for opt in OptLevel::ALL {
+        let mut fixture = Fixture::launch(name, opt).unwrap_or_else(|e| panic!("{} ({:?}): {:#}", name, opt, e));
+        let graph = fixture.run_to_checkpoint().unwrap();
+        assert!(graph.count("main") >= 1, "{} ({:?}): main task not tracked: {:?}", name, opt, graph.tasks);
+        if opt == OptLevel::O0 {
+            check(&graph);
+        }
+        assert_eq!(fixture.run_to_exit().unwrap(), 0, "{} ({:?})", name, opt);
+    }
+}
+
+#[test]
+fn test_current_thread_await_chain() {
+    check_fixture("current_thread", |graph| {
+        assert!(graph.awaits("main", "parent"), "{:?}", graph);
+        assert!(graph.awaits("parent", "child"), "{:?}", graph);
+        assert!(!graph.awaits("main", "child"), "{:?}", graph);
+    });
+}
+
+#[test]
+fn test_join_polls_both_children() {
+    check_fixture("join", |graph| {
+        assert!(graph.awaits("both", "both::{{closure}}"), "{:?}", graph);
+        assert!(graph.awaited_to_completion("both::{{closure}}", "left"), "{:?}", graph);
+        assert!(graph.awaits("both::{{closure}}", "right"), "{:?}", graph);
+    });
+}
+
+#[test]
+fn test_select_completes_only_the_winner() {
+    check_fixture("select", |graph| {
+        assert!(graph.awaited_to_completion("race::{{closure}}", "fast"), "{:?}", graph);
+        assert!(graph.awaits("race::{{closure}}", "slow"), "{:?}", graph);
+        assert!(!graph.awaited_to_completion("race::{{closure}}", "slow"), "{:?}", graph);
+        assert!(graph.tasks_of("slow").iter().all(|task| !task.completed), "{:?}", graph);
+    });
+}
+
+#[test]
+fn test_spawn_storm_tracks_every_task() {
+    check_fixture("spawn_storm", |graph| {
+        let leaves = graph.tasks_of("leaf");
+        assert!(leaves.len() >= 64, "only {} leaf task(s) tracked", leaves.len());
+        assert!(leaves.iter().all(|task| task.is_root), "{:?}", graph);
+    });
+}
+
+/// multi_thread ランタイムでは opt-level 1 以上で main の poll が block_on にインライン化されるので、
+/// main のタスクは opt-level 0 でだけ確かめる（ワーカー上のタスクはどの opt-level でも見える）
+#[test]
+fn test_multi_thread_workers() {
+    for opt in OptLevel::ALL {
+        let mut fixture = Fixture::launch("multi_thread", opt).unwrap_or_else(|e| panic!("multi_thread ({:?}): {:#}", opt, e));
+        let graph = fixture.run_to_checkpoint().unwrap();
+        assert_eq!(graph.count("worker"), 4, "({:?}) {:?}", opt, graph);
+        if opt == OptLevel::O0 {
+            assert!(graph.count("main") >= 1, "{:?}", graph);
+            assert!(graph.awaits("worker", "step"), "{:?}", graph);
+        }
+        assert_eq!(fixture.run_to_exit().unwrap(), 0, "multi_thread ({:?})", opt);
+    }
+}
+
+/// 既存タスクのシードは全スレッドのスタックを読み、選択中のスレッドとレジスタを元に戻す
+#[test]
+fn test_seeding_restores_selected_thread() {
+    let mut fixture = Fixture::launch("multi_thread", OptLevel::O0).unwrap();
+    fixture.run_to_checkpoint().unwrap();
+    let debugger = fixture.session_mut().debugger_mut();
+    let (thread, pc) = (debugger.current_thread(), debugger.get_pc().unwrap());
+
+    let (_, threads) = debugger.seed_async_tasks_from_threads().unwrap();
+    assert_eq!(threads, debugger.threads().unwrap().len());
+    assert_eq!(debugger.current_thread(), thread);
+    assert_eq!(debugger.get_pc().unwrap(), pc);
+    let top = debugger.backtrace().unwrap().into_iter().next().and_then(|frame| frame.function_name);
+    assert_eq!(top.as_deref(), Some("multi_thread::checkpoint"));
+    assert_eq!(fixture.run_to_exit().unwrap(), 0);
+}
+
+/// ウォッチポイントは、置いた後に clone で生まれたスレッドの書き込みでも止まる
+#[test]
+fn test_watchpoint_fires_on_thread_spawned_later() {
+    let binary = fixture_binary("watch_thread", OptLevel::O0).unwrap();
+    let mut session = Session::launch(SessionConfig::launch(binary, &[])).unwrap();
+    session.break_at("watch_thread::main").unwrap();
+    assert_eq!(session.resume().unwrap().reason, StopReason::Breakpoint);
+
+    let debugger = session.debugger_mut();
+    let address = ExpressionEvaluator::new(debugger)
+        .evaluate_address(&parse_expression("watch_thread::COUNTER").unwrap())
+        .unwrap();
+    debugger.watch(address, 8, WatchKind::Write).unwrap();
+    let main_thread = debugger.current_thread();
+
+    let stop = session.resume().unwrap();
+    assert_eq!(stop.reason, StopReason::Watchpoint);
+    assert_ne!(stop.tid, main_thread);
+    let hit = stop.watchpoint.unwrap();
+    assert_eq!(hit.new, 42u64.to_le_bytes());
+    assert_eq!(session.run_to_exit().unwrap(), 0);
+}
+
+/// 値の履歴は記録した時点の値を残し、再開してメモリが変わっても `$1` は変わらない
+#[test]
+fn test_history_keeps_recorded_value() {
+    let binary = fixture_binary("watch_thread", OptLevel::O0).unwrap();
+    let mut session = Session::launch(SessionConfig::launch(binary, &[])).unwrap();
+    session.break_at("watch_thread::main").unwrap();
+    assert_eq!(session.resume().unwrap().reason, StopReason::Breakpoint);
+
+    let counter = parse_expression("watch_thread::COUNTER").unwrap();
+    let evaluator = ExpressionEvaluator::new(session.debugger());
+    let value = evaluator.evaluate_to_value(&counter).unwrap();
+    let recorded = evaluator.format_value(&value).unwrap();
+    assert_eq!(session.debugger_mut().record_value(value), 1);
+
+    session.break_at("watch_thread::checkpoint").unwrap();
+    assert_eq!(session.resume().unwrap().reason, StopReason::Breakpoint);
+    let evaluator = ExpressionEvaluator::new(session.debugger());
+    assert_eq!(evaluator.evaluate_to_string(&parse_expression("$1").unwrap()).unwrap(), recorded);
+    assert_ne!(evaluator.evaluate_to_string(&counter).unwrap(), recorded);
+    assert_eq!(session.run_to_exit().unwrap(), 0);
+}
+
+/// パッチでメモリを書き換えたら、同じ停止の間でもポインタをたどった評価結果を使い回さない
+#[test]
+fn test_patch_invalidates_cached_pointer_evaluation() {
+    let binary = fixture_binary("watch_thread", OptLevel::O0).unwrap();
+    let mut session = Session::launch(SessionConfig::launch(binary, &[])).unwrap();
+    session.break_at("watch_thread::checkpoint").unwrap();
+    assert_eq!(session.resume().unwrap().reason, StopReason::Breakpoint);
+
+    let debugger = session.debugger_mut();
+    let counter = ExpressionEvaluator::new(debugger)
+        .evaluate_address(&parse_expression("watch_thread::COUNTER").unwrap())
+        .unwrap();
+    // COUNTER の値をポインタとみなしてたどった先
+    let pointee = parse_expression(&format!("**(0x{:x} as *const *const u64)", counter)).unwrap();
+    let evaluate = |debugger: &kokia_core::Debugger| ExpressionEvaluator::new(debugger).evaluate(&pointee).unwrap().address;
+    assert_eq!(evaluate(debugger), 42);
+
+    let id = debugger.patch(counter, &counter.to_le_bytes()).unwrap();
+    assert_eq!(evaluate(debugger), counter);
+    debugger.unpatch(id).unwrap();
+    assert_eq!(evaluate(debugger), 42);
+    assert_eq!(session.run_to_exit().unwrap(), 0);
+}
+
+/// ステップフィルタに当たる関数へのステップは、呼び出し元に戻ったところまで一度に進む
+#[test]
+fn test_step_runs_through_filtered_call() {
+    let binary = fixture_binary("watch_thread", OptLevel::O0).unwrap();
+    let mut session = Session::launch(SessionConfig::launch(binary, &[])).unwrap();
+    let id = session.break_at("watch_thread::checkpoint").unwrap();
+    assert_eq!(session.resume().unwrap().reason, StopReason::Breakpoint);
+
+    let debugger = session.debugger_mut();
+    // 1 バイトの push の直後を、ブレークポイントを踏んだ停止と取り違えないように消しておく
+    debugger.remove_breakpoint(id).unwrap();
+    let function = |debugger: &kokia_core::Debugger| {
+        debugger.get_pc().ok().and_then(|pc| debugger.reverse_resolve(pc)).map(|symbol| symbol.demangled_name)
+    };
+    let mut filtered = 0;
+    while function(debugger).as_deref() == Some("watch_thread::checkpoint") {
+        assert_eq!(debugger.step().unwrap(), StopReason::Step);
+        filtered += debugger.filtered_calls();
+    }
+    // core::hint::black_box の中では止まらず、checkpoint の ret で呼び出し元に戻る
+    assert_eq!(filtered, 1);
+    assert!(function(debugger).is_some_and(|name| name.starts_with("watch_thread::main")));
+    assert_eq!(session.run_to_exit().unwrap(), 0);
+}
+
+/// スレッドを限定したブレークポイントは、別のスレッドが踏んでも止まらずに続ける
+#[test]
+fn test_thread_filtered_breakpoint_skips_other_threads() {
+    let binary = fixture_binary("thread_filter", OptLevel::O0).unwrap();
+    let mut session = Session::launch(SessionConfig::launch(binary, &[])).unwrap();
+    let id = session.break_at("thread_filter::hit").unwrap();
+    let first = session.resume().unwrap();
+    assert_eq!(first.reason, StopReason::Breakpoint);
+    // O0 の hit はプロローグの後も第 1 引数を rdi に残している
+    assert_eq!(session.debugger().read_register("rdi").unwrap(), 1);
+
+    let main_thread = session.debugger().current_thread();
+    session.debugger_mut().restrict_breakpoint(id, main_thread, None).unwrap();
+
+    // spawn_blocking のスレッドの hit(2) は飛ばし、メインスレッドの hit(3) で止まる
+    let second = session.resume().unwrap();
+    assert_eq!(second.reason, StopReason::Breakpoint);
+    assert_eq!(second.tid, main_thread);
+    assert_eq!(session.debugger().read_register("rdi").unwrap(), 3);
+    assert_eq!(session.run_to_exit().unwrap(), 0);
+}
+
+/// ランタイムが poll の途中のパニックを受け止めたら、巻き戻された poll はスコープから外れる
+#[test]
+fn test_panicking_poll_leaves_scope() {
+    let mut fixture = Fixture::launch("panic_poll", OptLevel::O0).unwrap();
+    let graph = fixture.run_to_checkpoint().unwrap();
+    assert!(graph.awaits("main", "after"), "{:?}", graph);
+    assert!(!graph.awaits("boom", "after"), "{:?}", graph);
+    assert!(!graph.awaits("boom", "main"), "{:?}", graph);
+
+    let debugger = fixture.session_mut().debugger();
+    let tid = Tid(debugger.current_thread().unwrap());
+    let scope: Vec<_> = debugger.async_tracker().async_backtrace(tid).iter()
+        .filter_map(|&task| graph.tasks.iter().find(|info| info.id == task))
+        .filter_map(|info| graph.function(info))
+        .collect();
+    assert_eq!(scope, ["main"], "{:?}", graph);
+    assert_eq!(fixture.run_to_exit().unwrap(), 0);
+}
+
+/// フォールトしたシグナルは再開時に届けるので、同じ命令で止まり続けずにプロセスが終わる
+#[test]
+fn test_resume_delivers_crash_signal() {
+    let binary = fixture_binary("crash", OptLevel::O0).unwrap();
+    let mut session = Session::launch(SessionConfig::launch(binary, &[])).unwrap();
+    let stop = session.resume().unwrap();
+    assert_eq!(stop.reason, StopReason::Signal(Signal::SIGSEGV));
+    // 届けた SIGSEGV でプロセスが終わる（終了コードは無い）
+    assert!(session.run_to_exit().is_err());
+}
+
+/// シグナルで終わったプロセスは終了として扱い、終了ステータスは 128+シグナル番号になる
+#[test]
+fn test_process_killed_by_signal_is_terminated() {
+    let binary = fixture_binary("crash", OptLevel::O0).unwrap();
+    let mut session = Session::launch(SessionConfig::launch(binary, &["abort"])).unwrap();
+    assert_eq!(session.resume().unwrap().reason, StopReason::Signal(Signal::SIGABRT));
+    assert_eq!(session.resume().unwrap().reason, StopReason::Killed(Signal::SIGABRT));
+
+    let debugger = session.debugger();
+    assert!(!debugger.is_alive());
+    assert_eq!(debugger.exit_code(), None);
+    assert_eq!(debugger.exit_signal(), Some(Signal::SIGABRT));
+    assert_eq!(debugger.exit_status(), Some(128 + Signal::SIGABRT as i32));
+}